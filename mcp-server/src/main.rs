@@ -1190,30 +1190,11 @@ impl Tool for ProofpatchTreeSearchNearestTool {
                     )
                     .await
                     {
-                        // Apply preset filters (any/all), consistent with `research-auto`.
-                        let must_any_l: Vec<String> = preset
-                            .must_include_any
-                            .iter()
-                            .map(|s| s.to_lowercase())
-                            .collect();
-                        let must_all_l: Vec<String> = preset
-                            .must_include_all
-                            .iter()
-                            .map(|s| s.to_lowercase())
-                            .collect();
-                        if !must_any_l.is_empty() || !must_all_l.is_empty() {
-                            papers = papers
-                                .into_iter()
-                                .filter(|p| {
-                                    let hay =
-                                        format!("{}\n{}", p.title, p.abstract_text).to_lowercase();
-                                    let ok_any = must_any_l.is_empty()
-                                        || must_any_l.iter().any(|tok| hay.contains(tok));
-                                    let ok_all = must_all_l.is_empty()
-                                        || must_all_l.iter().all(|tok| hay.contains(tok));
-                                    ok_any && ok_all
-                                })
-                                .collect();
+                        // Apply preset filters, consistent with `research-auto`.
+                        if let Ok(filter) =
+                            plc::research::filter::ResultFilter::from_preset(&preset)
+                        {
+                            papers.retain(|p| filter.keeps_text(&p.title, &p.abstract_text));
                         }
                         let ctx = json!({
                             "preset": preset_name,
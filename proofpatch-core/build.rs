@@ -41,7 +41,112 @@ fn resolve_tool(env_key: &str, fallback_basename: &str) -> PathBuf {
     PathBuf::from(fallback_basename)
 }
 
+#[cfg(any(feature = "gguf", feature = "onnx"))]
+fn env_path(key: &str) -> Option<PathBuf> {
+    std::env::var(key)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Compile `src/llm/gguf_shim.c` against llama.cpp and link `libllama`.
+///
+/// `LLAMA_CPP_DIR` is an install prefix (`include/llama.h`, `lib/libllama.*`);
+/// `LLAMA_CPP_INCLUDE_DIR` / `LLAMA_CPP_LIB_DIR` override either half (e.g. a source checkout
+/// with `include/` and `build/bin/`).
+#[cfg(feature = "gguf")]
+fn build_gguf_shim() -> Result<(), String> {
+    for k in [
+        "LLAMA_CPP_DIR",
+        "LLAMA_CPP_INCLUDE_DIR",
+        "LLAMA_CPP_LIB_DIR",
+    ] {
+        println!("cargo:rerun-if-env-changed={k}");
+    }
+    println!("cargo:rerun-if-changed=src/llm/gguf_shim.c");
+    let prefix = env_path("LLAMA_CPP_DIR");
+    let include = env_path("LLAMA_CPP_INCLUDE_DIR")
+        .or_else(|| prefix.as_ref().map(|p| p.join("include")))
+        .ok_or(
+            "feature `gguf` needs LLAMA_CPP_DIR (or LLAMA_CPP_INCLUDE_DIR and LLAMA_CPP_LIB_DIR)",
+        )?;
+    let lib = env_path("LLAMA_CPP_LIB_DIR")
+        .or_else(|| prefix.as_ref().map(|p| p.join("lib")))
+        .ok_or("feature `gguf` needs LLAMA_CPP_DIR (or LLAMA_CPP_LIB_DIR)")?;
+    if !include.join("llama.h").exists() {
+        return Err(format!("llama.h not found in {}", include.display()));
+    }
+    cc::Build::new()
+        .file("src/llm/gguf_shim.c")
+        .include(&include)
+        .warnings(false)
+        .compile("proofpatch_gguf_shim");
+    println!("cargo:rustc-link-search=native={}", lib.display());
+    println!("cargo:rustc-link-lib=dylib=llama");
+    if std::env::var("CARGO_CFG_TARGET_OS").ok().as_deref() != Some("windows") {
+        println!("cargo:rustc-link-arg=-Wl,-rpath,{}", lib.display());
+    }
+    Ok(())
+}
+
+/// Compile `src/rerank/onnx_shim.c` against ONNX Runtime and link `libonnxruntime`.
+///
+/// `ONNXRUNTIME_DIR` is a release or install prefix (`include/onnxruntime_c_api.h`, possibly
+/// under `include/onnxruntime/`, and `lib/libonnxruntime.*`); `ONNXRUNTIME_INCLUDE_DIR` /
+/// `ONNXRUNTIME_LIB_DIR` override either half.
+#[cfg(feature = "onnx")]
+fn build_onnx_shim() -> Result<(), String> {
+    for k in [
+        "ONNXRUNTIME_DIR",
+        "ONNXRUNTIME_INCLUDE_DIR",
+        "ONNXRUNTIME_LIB_DIR",
+    ] {
+        println!("cargo:rerun-if-env-changed={k}");
+    }
+    println!("cargo:rerun-if-changed=src/rerank/onnx_shim.c");
+    let prefix = env_path("ONNXRUNTIME_DIR");
+    let include = env_path("ONNXRUNTIME_INCLUDE_DIR")
+        .or_else(|| {
+            let inc = prefix.as_ref()?.join("include");
+            let nested = inc.join("onnxruntime");
+            Some(if nested.join("onnxruntime_c_api.h").exists() {
+                nested
+            } else {
+                inc
+            })
+        })
+        .ok_or(
+            "feature `onnx` needs ONNXRUNTIME_DIR (or ONNXRUNTIME_INCLUDE_DIR and ONNXRUNTIME_LIB_DIR)",
+        )?;
+    let lib = env_path("ONNXRUNTIME_LIB_DIR")
+        .or_else(|| prefix.as_ref().map(|p| p.join("lib")))
+        .ok_or("feature `onnx` needs ONNXRUNTIME_DIR (or ONNXRUNTIME_LIB_DIR)")?;
+    if !include.join("onnxruntime_c_api.h").exists() {
+        return Err(format!(
+            "onnxruntime_c_api.h not found in {}",
+            include.display()
+        ));
+    }
+    cc::Build::new()
+        .file("src/rerank/onnx_shim.c")
+        .include(&include)
+        .warnings(false)
+        .compile("proofpatch_onnx_shim");
+    println!("cargo:rustc-link-search=native={}", lib.display());
+    println!("cargo:rustc-link-lib=dylib=onnxruntime");
+    if std::env::var("CARGO_CFG_TARGET_OS").ok().as_deref() != Some("windows") {
+        println!("cargo:rustc-link-arg=-Wl,-rpath,{}", lib.display());
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "gguf")]
+    build_gguf_shim()?;
+    #[cfg(feature = "onnx")]
+    build_onnx_shim()?;
+
     // Only needed when the embed feature is enabled.
     if std::env::var("CARGO_FEATURE_LEAN_EMBED").ok().is_none() {
         return Ok(());
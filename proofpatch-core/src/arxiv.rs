@@ -15,6 +15,9 @@ pub struct ArxivPaper {
     pub authors: Vec<String>,
     #[serde(default)]
     pub abstract_text: String,
+    /// arXiv categories (`math.CO`, `cs.LO`, …), primary first.
+    #[serde(default)]
+    pub categories: Vec<String>,
 }
 
 fn normalize_ws(s: &str) -> String {
@@ -61,6 +64,23 @@ fn extract_authors(entry: &str) -> Vec<String> {
     out
 }
 
+fn extract_categories(entry: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for tag in ["<arxiv:primary_category", "<category"] {
+        for chunk in entry.split(tag).skip(1) {
+            let Some(attrs) = chunk.split('>').next() else {
+                continue;
+            };
+            if let Some(term) = extract_between(attrs, "term=\"", "\"") {
+                if !term.is_empty() && !out.contains(&term) {
+                    out.push(term);
+                }
+            }
+        }
+    }
+    out
+}
+
 fn extract_pdf_url(entry: &str) -> Option<String> {
     // Atom feed has multiple <link ...> elements; try to find one that looks like a PDF.
     for chunk in entry.split("<link").skip(1) {
@@ -97,6 +117,7 @@ pub fn parse_arxiv_atom(xml: &str, max_results: usize) -> Vec<ArxivPaper> {
         let abstract_text = extract_first_tag_text(entry_block0, "summary").unwrap_or_default();
         let authors = extract_authors(entry_block0);
         let pdf_url = extract_pdf_url(entry_block0);
+        let categories = extract_categories(entry_block0);
         out.push(ArxivPaper {
             title,
             link,
@@ -105,6 +126,7 @@ pub fn parse_arxiv_atom(xml: &str, max_results: usize) -> Vec<ArxivPaper> {
             updated,
             authors,
             abstract_text,
+            categories,
         });
         if out.len() >= max_results {
             break;
@@ -117,6 +139,15 @@ pub async fn arxiv_search(
     query: &str,
     max_results: usize,
     timeout: Duration,
+) -> Result<Vec<ArxivPaper>, String> {
+    arxiv_search_query(&format!("all:{query}"), max_results, timeout).await
+}
+
+/// Search with a raw arXiv `search_query` (`ti:…`, `abs:…`, `cat:…`, `AND`/`OR`).
+pub async fn arxiv_search_query(
+    search_query: &str,
+    max_results: usize,
+    timeout: Duration,
 ) -> Result<Vec<ArxivPaper>, String> {
     let max_results = max_results.clamp(1, 50);
     // arXiv strongly prefers clients identify themselves. Also, `export.arxiv.org` can rate-limit;
@@ -130,7 +161,7 @@ pub async fn arxiv_search(
     let mut url = reqwest::Url::parse("https://export.arxiv.org/api/query")
         .map_err(|e| format!("parse arxiv url: {e}"))?;
     url.query_pairs_mut()
-        .append_pair("search_query", &format!("all:{query}"))
+        .append_pair("search_query", search_query)
         .append_pair("start", "0")
         .append_pair("max_results", &max_results.to_string());
 
@@ -0,0 +1,731 @@
+//! Beam search over tactic sequences, as an alternative to generating a whole proof at once.
+//!
+//! Starting from the proof state at a placeholder, every state in the beam is extended by each
+//! proposed next tactic (`step_tactics`, or the caller's proposer), run in the REPL sandbox
+//! (`repl::ReplSession`, through `Transitions`). Tactics that fail, and states already seen (same
+//! goals, so no progress), are dropped; the survivors are scored (`state_score`: fewer and
+//! smaller goals, shallower first) and the best `width` form the next beam. The search stops at
+//! the first state without goals, or when `max_depth`, `max_nodes` (tactic runs), or `time_budget`
+//! is spent; the script leading to the state with the fewest goals is then reported instead.
+//!
+//! The beam after each depth (`BeamFrontier`) is saved to `BeamOptions::checkpoint`, so a search
+//! stopped by its budgets or interrupted resumes at the last completed depth (`max_depth` counts
+//! across runs, `max_nodes` and `time_budget` per run). As with MCTS, REPL proof-state ids do not
+//! survive the session: a resumed search replays the tactics leading to each state of the beam.
+//!
+//! `beam_search_decl` runs it on a declaration's placeholder through a `warm::WarmVerifier` and
+//! confirms a solution with a full compile (`verify_lean_text`), like the repair loop's warm
+//! checks.
+
+use crate::patching::{apply_patch, PatchTarget};
+use crate::repl::{ReplSession, TacticResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+pub type StepFuture<'a> = Pin<Box<dyn Future<Output = Result<TacticResponse, String>> + Send + 'a>>;
+
+/// Runs a tactic on a proof state. A tactic that does not apply is an `Err` or a response with
+/// error messages; the search stops when the sandbox is no longer `alive`.
+pub trait Transitions: Send {
+    fn step<'a>(&'a mut self, proof_state: u64, tactic: &'a str) -> StepFuture<'a>;
+
+    fn alive(&self) -> bool {
+        true
+    }
+}
+
+impl Transitions for ReplSession {
+    fn step<'a>(&'a mut self, proof_state: u64, tactic: &'a str) -> StepFuture<'a> {
+        Box::pin(self.tactic(tactic, proof_state))
+    }
+
+    fn alive(&self) -> bool {
+        !self.is_closed()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BeamOptions {
+    /// States kept per depth.
+    pub width: usize,
+    /// Tactics in a script at most.
+    pub max_depth: usize,
+    /// Tactic runs in total.
+    pub max_nodes: usize,
+    /// Next tactics tried per state.
+    pub expansions: usize,
+    pub time_budget: Duration,
+    /// Per tactic run; a tactic that takes longer kills the REPL, which ends the search.
+    pub step_timeout: Duration,
+    /// Confirm a solution with `verify_lean_text` (`beam_search_decl`).
+    pub confirm: bool,
+    /// Save the frontier here after every depth, and resume from it (`beam_search_decl`).
+    pub checkpoint: Option<PathBuf>,
+}
+
+impl Default for BeamOptions {
+    fn default() -> Self {
+        Self {
+            width: 4,
+            max_depth: 6,
+            max_nodes: 200,
+            expansions: 12,
+            time_budget: Duration::from_secs(300),
+            step_timeout: Duration::from_secs(20),
+            confirm: true,
+            checkpoint: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BeamOutcome {
+    pub solved: bool,
+    /// The solving script, or (unsolved) the one reaching the fewest goals.
+    pub steps: Vec<String>,
+    /// Goals left after `steps`.
+    pub goals: Vec<String>,
+    /// Tactic runs.
+    pub nodes: usize,
+    /// Deepest level expanded.
+    pub depth: usize,
+    /// "solved", "exhausted" (no state left to extend), "max_depth", "max_nodes",
+    /// "time_budget", or "repl_error".
+    pub stop_reason: String,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Whether the run continued a checkpointed frontier.
+    #[serde(default)]
+    pub resumed: bool,
+    pub elapsed_ms: u64,
+}
+
+impl BeamOutcome {
+    /// `steps` as a replacement for the placeholder (`by` and one tactic per line).
+    pub fn script(&self) -> String {
+        render_script(&self.steps)
+    }
+}
+
+pub fn render_script(steps: &[String]) -> String {
+    let mut out = "by".to_string();
+    for s in steps {
+        for line in s.lines() {
+            out.push_str("\n  ");
+            out.push_str(line);
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    state: u64,
+    goals: Vec<String>,
+    steps: Vec<String>,
+    score: f64,
+}
+
+/// A state of a saved beam: the tactics reaching it from the root and the goals they leave.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrontierNode {
+    pub steps: Vec<String>,
+    pub goals: Vec<String>,
+    pub score: f64,
+}
+
+/// A beam search after `depth` completed depths. Saved and loaded as JSON for checkpoints.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BeamFrontier {
+    pub version: u32,
+    pub root_goals: Vec<String>,
+    pub depth: usize,
+    pub beam: Vec<FrontierNode>,
+    /// `goals_key` of every state reached so far.
+    pub seen: Vec<String>,
+    /// The script reaching the fewest goals so far (the solution, once solved), and its goals.
+    pub best_steps: Vec<String>,
+    pub best_goals: Vec<String>,
+    /// Tactic runs and time across all runs on this frontier.
+    pub nodes: usize,
+    pub elapsed_ms: u64,
+    /// "solved" or "exhausted" once the search has ended for good.
+    #[serde(default)]
+    pub finished: Option<String>,
+}
+
+impl BeamFrontier {
+    pub fn new(goals: Vec<String>) -> Self {
+        Self {
+            version: crate::checkpoint::CHECKPOINT_VERSION,
+            seen: vec![goals_key(&goals)],
+            beam: vec![FrontierNode {
+                steps: Vec::new(),
+                score: state_score(&goals, 0),
+                goals: goals.clone(),
+            }],
+            best_steps: Vec::new(),
+            best_goals: goals.clone(),
+            root_goals: goals,
+            depth: 0,
+            nodes: 0,
+            elapsed_ms: 0,
+            finished: None,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        crate::checkpoint::save_json(path, self)
+    }
+
+    /// The frontier saved at `path` for a search from `goals`, if there is one.
+    pub fn load(path: &Path, goals: &[String]) -> Result<Option<Self>, String> {
+        let Some(f) = crate::checkpoint::load_json::<Self>(path)? else {
+            return Ok(None);
+        };
+        if f.version != crate::checkpoint::CHECKPOINT_VERSION {
+            return Err(format!(
+                "checkpoint {}: unsupported version {}",
+                path.display(),
+                f.version
+            ));
+        }
+        if goals_key(&f.root_goals) != goals_key(goals) {
+            return Err(format!(
+                "checkpoint {} is for a different goal",
+                path.display()
+            ));
+        }
+        Ok(Some(f))
+    }
+}
+
+/// Run `steps` from `root`: the state they reach, or `None` when one of them no longer applies.
+async fn replay(
+    env: &mut dyn Transitions,
+    root: u64,
+    steps: &[String],
+    runs: &mut usize,
+) -> Result<Option<u64>, String> {
+    let mut state = root;
+    for tactic in steps {
+        *runs += 1;
+        let r = match env.step(state, tactic).await {
+            Ok(r) => r,
+            Err(_) if env.alive() => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        match r.proof_state {
+            Some(s) if !r.messages.iter().any(|m| m.severity == "error") => state = s,
+            _ => return Ok(None),
+        }
+    }
+    Ok(Some(state))
+}
+
+/// Higher is better: fewer goals, then less to prove, then shorter scripts.
+pub fn state_score(goals: &[String], depth: usize) -> f64 {
+    let size: usize = goals.iter().map(|g| g.chars().count()).sum();
+    -(goals.len() as f64 * 10.0) - size as f64 / 100.0 - depth as f64 * 0.5
+}
+
+/// Goals up to hypothesis order and whitespace, for spotting states already seen.
+pub fn goals_key(goals: &[String]) -> String {
+    let mut gs: Vec<String> = goals
+        .iter()
+        .map(|g| {
+            let mut lines: Vec<String> = g
+                .lines()
+                .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+                .collect();
+            lines.sort();
+            lines.join("\n")
+        })
+        .collect();
+    gs.sort();
+    gs.join("\n\n")
+}
+
+/// The target (`⊢ …`) of the first goal.
+fn first_target(goals: &[String]) -> &str {
+    goals
+        .first()
+        .and_then(|g| g.lines().find_map(|l| l.trim_start().strip_prefix("⊢")))
+        .map(str::trim)
+        .unwrap_or("")
+}
+
+/// Next tactics for a state: structural steps suggested by the first goal's shape, then
+/// closers and normalizers.
+pub fn step_tactics(goals: &[String]) -> Vec<String> {
+    let target = first_target(goals);
+    let mut out: Vec<&str> = Vec::new();
+    if target.starts_with('∀') || target.starts_with("¬") || target.contains(" → ") {
+        out.push("intro");
+        out.push("intros");
+    }
+    if target.contains(" ∧ ") || target.contains(" ↔ ") {
+        out.push("constructor");
+    }
+    if target.starts_with('∃') {
+        out.push("refine ⟨?_, ?_⟩");
+    }
+    if target.contains(" ∨ ") {
+        out.extend(["left", "right"]);
+    }
+    if target.contains("if ") {
+        out.push("split_ifs");
+    }
+    out.extend([
+        "rfl",
+        "omega",
+        "simp",
+        "norm_num",
+        "linarith",
+        "ring",
+        "positivity",
+        "decide",
+        "simp_all",
+        "aesop",
+        "nlinarith",
+        "ring_nf",
+        "push_neg",
+        "exact?",
+    ]);
+    let mut seen = HashSet::new();
+    out.into_iter()
+        .filter(|t| seen.insert(*t))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Beam search from `root` (a proof state with `goals`), extending states with `propose`.
+pub async fn beam_search(
+    env: &mut dyn Transitions,
+    root: u64,
+    goals: Vec<String>,
+    opts: &BeamOptions,
+    propose: &(dyn Fn(&[String]) -> Vec<String> + Sync),
+) -> BeamOutcome {
+    let mut frontier = BeamFrontier::new(goals);
+    beam_search_from(env, root, &mut frontier, opts, propose).await
+}
+
+/// Continue the beam search saved in `frontier` (fresh from `BeamFrontier::new`, or loaded from
+/// a checkpoint) from `root`, the REPL state of its root goals in `env`.
+pub async fn beam_search_from(
+    env: &mut dyn Transitions,
+    root: u64,
+    frontier: &mut BeamFrontier,
+    opts: &BeamOptions,
+    propose: &(dyn Fn(&[String]) -> Vec<String> + Sync),
+) -> BeamOutcome {
+    let t0 = Instant::now();
+    let width = opts.width.max(1);
+    let mut out = BeamOutcome {
+        solved: frontier.finished.as_deref() == Some("solved"),
+        steps: frontier.best_steps.clone(),
+        goals: frontier.best_goals.clone(),
+        nodes: 0,
+        depth: frontier.depth,
+        stop_reason: "max_depth".to_string(),
+        error: None,
+        resumed: frontier.depth > 0 || frontier.finished.is_some(),
+        elapsed_ms: 0,
+    };
+    if let Some(reason) = &frontier.finished {
+        out.stop_reason = reason.clone();
+        return out;
+    }
+    let (nodes_before, elapsed_before) = (frontier.nodes, frontier.elapsed_ms);
+    let save = |frontier: &mut BeamFrontier, out: &BeamOutcome| {
+        frontier.nodes = nodes_before + out.nodes;
+        frontier.elapsed_ms = elapsed_before + t0.elapsed().as_millis() as u64;
+        if let Some(p) = &opts.checkpoint {
+            // A failed save loses progress, not the search.
+            let _ = frontier.save(p);
+        }
+    };
+    let mut seen: HashSet<String> = frontier.seen.iter().cloned().collect();
+    let mut beam: Vec<Node> = Vec::new();
+    for n in &frontier.beam {
+        match replay(env, root, &n.steps, &mut out.nodes).await {
+            Ok(Some(state)) => beam.push(Node {
+                state,
+                goals: n.goals.clone(),
+                steps: n.steps.clone(),
+                score: n.score,
+            }),
+            Ok(None) => {}
+            Err(e) => {
+                out.stop_reason = "repl_error".to_string();
+                out.error = Some(e);
+                break;
+            }
+        }
+    }
+    if out.error.is_none() && beam.is_empty() {
+        out.stop_reason = "exhausted".to_string();
+        frontier.finished = Some(out.stop_reason.clone());
+    }
+    'search: for depth in frontier.depth + 1..=opts.max_depth {
+        if out.error.is_some() || beam.is_empty() {
+            break;
+        }
+        out.depth = depth;
+        let mut next: Vec<Node> = Vec::new();
+        for node in &beam {
+            for tactic in propose(&node.goals).into_iter().take(opts.expansions) {
+                if out.nodes >= opts.max_nodes {
+                    out.stop_reason = "max_nodes".to_string();
+                    break 'search;
+                }
+                if t0.elapsed() >= opts.time_budget {
+                    out.stop_reason = "time_budget".to_string();
+                    break 'search;
+                }
+                out.nodes += 1;
+                let r = match env.step(node.state, &tactic).await {
+                    Ok(r) => r,
+                    Err(_) if env.alive() => continue,
+                    Err(e) => {
+                        out.stop_reason = "repl_error".to_string();
+                        out.error = Some(e);
+                        break 'search;
+                    }
+                };
+                if r.messages.iter().any(|m| m.severity == "error") {
+                    continue;
+                }
+                let mut steps = node.steps.clone();
+                steps.push(tactic);
+                if r.solved() {
+                    out.solved = true;
+                    out.steps = steps;
+                    out.goals = Vec::new();
+                    out.stop_reason = "solved".to_string();
+                    frontier.finished = Some(out.stop_reason.clone());
+                    frontier.best_steps = out.steps.clone();
+                    frontier.best_goals = Vec::new();
+                    break 'search;
+                }
+                let Some(state) = r.proof_state else {
+                    continue;
+                };
+                if !seen.insert(goals_key(&r.goals)) {
+                    continue;
+                }
+                next.push(Node {
+                    state,
+                    score: state_score(&r.goals, depth),
+                    goals: r.goals,
+                    steps,
+                });
+            }
+        }
+        if next.is_empty() {
+            out.stop_reason = "exhausted".to_string();
+            frontier.finished = Some(out.stop_reason.clone());
+            break;
+        }
+        next.sort_by(|a, b| b.score.total_cmp(&a.score));
+        next.truncate(width);
+        if next[0].goals.len() < out.goals.len() || out.steps.is_empty() {
+            out.steps = next[0].steps.clone();
+            out.goals = next[0].goals.clone();
+        }
+        beam = next;
+        // Only completed depths are saved: a depth cut short is redone on resume.
+        frontier.depth = depth;
+        frontier.beam = beam
+            .iter()
+            .map(|n| FrontierNode {
+                steps: n.steps.clone(),
+                goals: n.goals.clone(),
+                score: n.score,
+            })
+            .collect();
+        frontier.seen = seen.iter().cloned().collect();
+        frontier.best_steps = out.steps.clone();
+        frontier.best_goals = out.goals.clone();
+        save(frontier, &out);
+    }
+    save(frontier, &out);
+    out.elapsed_ms = t0.elapsed().as_millis() as u64;
+    out
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BeamReport {
+    pub file: String,
+    pub decl: String,
+    pub outcome: BeamOutcome,
+    /// With `confirm`: whether the solved file compiles.
+    #[serde(default)]
+    pub verified: Option<bool>,
+    /// The file with the solution in place, when solved (and confirmed, with `confirm`).
+    #[serde(skip)]
+    pub patched_text: Option<String>,
+}
+
+/// A REPL session at the proof state of a declaration's placeholder (`open_placeholder`).
+pub struct Placeholder {
+    pub verifier: crate::warm::WarmVerifier,
+    pub root: u64,
+    pub goals: Vec<String>,
+    /// Line of the placeholder in the file (1-based).
+    pub hole_line: usize,
+}
+
+/// Start a warm REPL on `file_rel` and elaborate `decl_name` with `sorry` at its placeholder.
+pub async fn open_placeholder(
+    repo_root: &Path,
+    file_rel: &str,
+    text: &str,
+    decl_name: &str,
+    setup: Duration,
+) -> Result<Placeholder, String> {
+    let target = PatchTarget::DeclPlaceholder(decl_name.to_string());
+    let (hole_start, _) = crate::patching::resolve_target(text, &target)?;
+    let hole_line = text[..hole_start].matches('\n').count() + 1;
+    let (sorried, _) = apply_patch(text, &target, "sorry")?;
+    let (s, e) = crate::patching::decl_byte_range(&sorried, decl_name)?;
+
+    let mut w =
+        crate::warm::WarmVerifier::start(repo_root, file_rel, text, decl_name, setup).await?;
+    let r = w.elaborate(&sorried[s..e]).await?;
+    // `sorries` positions are relative to the declaration.
+    let rel_line = hole_line + 1 - w.decl_line();
+    let hole = r
+        .sorries
+        .iter()
+        .find(|x| x.pos.line == rel_line)
+        .or(r.sorries.first())
+        .ok_or_else(|| format!("no proof state at the placeholder of {decl_name}"))?;
+    let root = hole.proof_state.ok_or("the REPL returned no proof state")?;
+    let goals = vec![hole.goal.clone()];
+    Ok(Placeholder {
+        verifier: w,
+        root,
+        goals,
+        hole_line,
+    })
+}
+
+/// `text` with `script` at the placeholder of `decl_name`, and (with `confirm`) whether it
+/// compiles with the declaration no longer admitted and passes the safety gate (`safety`).
+pub async fn patch_placeholder(
+    repo_root: &Path,
+    text: &str,
+    decl_name: &str,
+    script: &str,
+    hole_line: usize,
+    confirm: Option<Duration>,
+) -> Result<(String, Option<bool>), String> {
+    let target = PatchTarget::DeclPlaceholder(decl_name.to_string());
+    let (patched, _) = apply_patch(text, &target, script)?;
+    let Some(timeout) = confirm else {
+        return Ok((patched, None));
+    };
+    let policy = crate::safety::SafetyPolicy::configured(repo_root);
+    if !crate::safety::check_candidate(script, &policy).is_empty() {
+        return Ok((patched, Some(false)));
+    }
+    let (probe, line) = crate::safety::instrument(&patched, decl_name);
+    let vr = crate::verify_lean_text(repo_root, &probe, timeout).await?;
+    let mut ok = vr.ok && !crate::repair::decl_admitted_in_output(&vr.stdout, hole_line);
+    if ok {
+        let output = format!("{}\n{}", vr.stdout, vr.stderr);
+        let check = crate::safety::check_axioms(
+            repo_root, text, &patched, decl_name, &output, line, &policy, timeout,
+        )
+        .await;
+        ok = check.violation.is_none();
+    }
+    Ok((patched, Some(ok)))
+}
+
+/// Beam search for the placeholder of `decl_name` in `text` (the contents of `file_rel`),
+/// resuming from `opts.checkpoint` when it exists.
+pub async fn beam_search_decl(
+    repo_root: &Path,
+    file_rel: &str,
+    text: &str,
+    decl_name: &str,
+    opts: &BeamOptions,
+) -> Result<BeamReport, String> {
+    let setup = opts.time_budget.max(opts.step_timeout);
+    let Placeholder {
+        verifier: mut w,
+        root,
+        goals,
+        hole_line,
+    } = open_placeholder(repo_root, file_rel, text, decl_name, setup).await?;
+    let saved = match opts.checkpoint.as_deref() {
+        Some(p) => BeamFrontier::load(p, &goals),
+        None => Ok(None),
+    };
+    let mut frontier = match saved {
+        Ok(f) => f.unwrap_or_else(|| BeamFrontier::new(goals)),
+        Err(e) => {
+            w.close().await;
+            return Err(e);
+        }
+    };
+    w.session().set_timeout(opts.step_timeout);
+    let outcome = beam_search_from(w.session(), root, &mut frontier, opts, &step_tactics).await;
+    w.close().await;
+
+    let mut report = BeamReport {
+        file: file_rel.to_string(),
+        decl: decl_name.to_string(),
+        outcome,
+        verified: None,
+        patched_text: None,
+    };
+    if report.outcome.solved {
+        let confirm = opts.confirm.then_some(opts.step_timeout * 6);
+        let (patched, verified) = patch_placeholder(
+            repo_root,
+            text,
+            decl_name,
+            &report.outcome.script(),
+            hole_line,
+            confirm,
+        )
+        .await?;
+        report.verified = verified;
+        report.patched_text = (verified != Some(false)).then_some(patched);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repl::ReplMessage;
+    use std::collections::HashMap;
+
+    /// States are goal strings; a tactic maps a goal to new goals, or fails.
+    struct Toy {
+        states: Vec<Vec<String>>,
+        rules: HashMap<(&'static str, &'static str), Vec<&'static str>>,
+        runs: usize,
+    }
+
+    impl Transitions for Toy {
+        fn step<'a>(&'a mut self, proof_state: u64, tactic: &'a str) -> StepFuture<'a> {
+            Box::pin(async move {
+                self.runs += 1;
+                let goals = &self.states[proof_state as usize];
+                let Some(new) = goals
+                    .first()
+                    .and_then(|g| self.rules.get(&(g.as_str(), tactic)))
+                else {
+                    return Ok(TacticResponse {
+                        messages: vec![ReplMessage {
+                            severity: "error".into(),
+                            pos: Default::default(),
+                            end_pos: None,
+                            data: "failed".into(),
+                        }],
+                        ..Default::default()
+                    });
+                };
+                let mut goals: Vec<String> = new.iter().map(|s| s.to_string()).collect();
+                goals.extend(self.states[proof_state as usize][1..].iter().cloned());
+                self.states.push(goals.clone());
+                Ok(TacticResponse {
+                    proof_state: Some(self.states.len() as u64 - 1),
+                    goals,
+                    messages: Vec::new(),
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn finds_a_multi_step_script_and_respects_budgets() {
+        let toy = || Toy {
+            states: vec![vec!["⊢ ∀ n, P n ∧ Q n".to_string()]],
+            rules: HashMap::from([
+                (("⊢ ∀ n, P n ∧ Q n", "intro"), vec!["n : ℕ\n⊢ P n ∧ Q n"]),
+                // A detour that goes nowhere new.
+                (("⊢ ∀ n, P n ∧ Q n", "simp"), vec!["⊢ ∀ n, P n ∧ Q n"]),
+                (
+                    ("n : ℕ\n⊢ P n ∧ Q n", "constructor"),
+                    vec!["n : ℕ\n⊢ P n", "n : ℕ\n⊢ Q n"],
+                ),
+                (("n : ℕ\n⊢ P n", "simp"), vec![]),
+                (("n : ℕ\n⊢ Q n", "omega"), vec![]),
+            ]),
+            runs: 0,
+        };
+        let goals = vec!["⊢ ∀ n, P n ∧ Q n".to_string()];
+        let opts = BeamOptions::default();
+        let mut env = toy();
+        let out = beam_search(&mut env, 0, goals.clone(), &opts, &step_tactics).await;
+        assert!(out.solved, "{out:?}");
+        assert_eq!(out.steps, ["intro", "constructor", "simp", "omega"]);
+        assert_eq!(out.script(), "by\n  intro\n  constructor\n  simp\n  omega");
+        assert_eq!(out.nodes, env.runs);
+
+        let tight = BeamOptions {
+            max_nodes: 20,
+            ..Default::default()
+        };
+        let out = beam_search(&mut toy(), 0, goals, &tight, &step_tactics).await;
+        assert!(!out.solved);
+        assert_eq!(out.stop_reason, "max_nodes");
+        assert_eq!(out.nodes, 20);
+        assert_eq!(out.steps, ["intro"]);
+    }
+
+    #[tokio::test]
+    async fn resumes_from_the_last_completed_depth() {
+        let toy = || Toy {
+            states: vec![vec!["⊢ P ∧ Q".to_string()]],
+            rules: HashMap::from([
+                (("⊢ P ∧ Q", "constructor"), vec!["⊢ P", "⊢ Q"]),
+                (("⊢ P", "simp"), vec![]),
+                (("⊢ Q", "omega"), vec![]),
+            ]),
+            runs: 0,
+        };
+        let goals = vec!["⊢ P ∧ Q".to_string()];
+        let ckpt = std::env::temp_dir().join(format!("pp-beam-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&ckpt);
+        // Stop during depth 2; the checkpoint holds the beam after depth 1.
+        let paused = BeamOptions {
+            max_nodes: 14,
+            checkpoint: Some(ckpt.clone()),
+            ..Default::default()
+        };
+        let out = beam_search(&mut toy(), 0, goals.clone(), &paused, &step_tactics).await;
+        assert_eq!(
+            (out.stop_reason.as_str(), out.resumed),
+            ("max_nodes", false)
+        );
+        let mut frontier = BeamFrontier::load(&ckpt, &goals).unwrap().unwrap();
+        assert_eq!(frontier.depth, 1);
+        assert_eq!(frontier.beam[0].steps, ["constructor"]);
+        assert_eq!(frontier.nodes, 14);
+        assert!(BeamFrontier::load(&ckpt, &["⊢ Q".to_string()]).is_err());
+
+        // A new session: the beam's states are replayed, then the search goes on.
+        let resume = BeamOptions {
+            checkpoint: Some(ckpt.clone()),
+            ..Default::default()
+        };
+        let out = beam_search_from(&mut toy(), 0, &mut frontier, &resume, &step_tactics).await;
+        assert!(out.solved && out.resumed, "{out:?}");
+        assert_eq!(out.steps, ["constructor", "simp", "omega"]);
+        let done = BeamFrontier::load(&ckpt, &goals).unwrap().unwrap();
+        assert_eq!(done.finished.as_deref(), Some("solved"));
+        let _ = std::fs::remove_file(&ckpt);
+    }
+}
@@ -0,0 +1,174 @@
+//! Checkpoints for long searches, so an interrupted run resumes where it stopped.
+//!
+//! Each search driver saves its state as JSON while it runs and picks it up again when started
+//! with the same checkpoint path on the same input:
+//! - MCTS saves its tree (`mcts::SearchTree`) every `checkpoint_every` iterations;
+//! - beam search saves its frontier (`beam::BeamFrontier`) after every depth;
+//! - the repair loop saves `RepairCheckpoint` after every round: the candidates tried, the
+//!   attempts and budgets spent, the feedback for the next prompt;
+//! - whole-file repair saves `FileCheckpoint` after every declaration (with the working copy as
+//!   patched so far), and gives the declaration in progress its own repair checkpoint
+//!   (`decl_checkpoint_path`).
+//!
+//! Budgets count across runs where they are totals (the repair loop's rounds and
+//! verifications, the beam's depth), and per run otherwise (tactic runs, wall time). A
+//! checkpoint for other input (a different goal, or a file that changed since) is an error
+//! rather than silently restarting. Writes go through a temporary file and a rename, so a run
+//! killed mid-save leaves the previous checkpoint intact.
+
+use crate::patching::EditRecord;
+use crate::repair::RepairOutcome;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// Write `value` to `path` as JSON, replacing the previous checkpoint atomically.
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| format!("create {}: {e}", dir.display()))?;
+    }
+    let s = serde_json::to_string(value).map_err(|e| format!("serialize checkpoint: {e}"))?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, s).map_err(|e| format!("write {}: {e}", tmp.display()))?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("write {}: {e}", path.display()))
+}
+
+/// Read a checkpoint written by `save_json`; `None` when there is none at `path`.
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let s = std::fs::read_to_string(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+    serde_json::from_str(&s)
+        .map(Some)
+        .map_err(|e| format!("parse checkpoint {}: {e}", path.display()))
+}
+
+fn check_version(path: &Path, version: u32) -> Result<(), String> {
+    if version == CHECKPOINT_VERSION {
+        Ok(())
+    } else {
+        Err(format!(
+            "checkpoint {}: unsupported version {version}",
+            path.display()
+        ))
+    }
+}
+
+/// The repair loop's state after a round (`RepairOptions::checkpoint`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairCheckpoint {
+    pub version: u32,
+    /// `tree_search::hash_text` of the text being repaired.
+    pub text_hash: u64,
+    /// The outcome so far; `rounds` is the number of rounds completed.
+    pub outcome: RepairOutcome,
+    /// `goal_ast::candidate_hash` of every candidate tried or queued.
+    pub tried: Vec<u64>,
+    /// Errors fed back to the next prompt.
+    pub feedback: Vec<String>,
+    /// Renamed candidates waiting for the next round.
+    pub renamed: Vec<(String, String)>,
+    /// Local variants of failed candidates (`mutate`) waiting for the next round.
+    #[serde(default)]
+    pub mutants: Vec<(String, String)>,
+    /// Names Lean reported unknown.
+    pub unknown_names: Vec<String>,
+}
+
+impl RepairCheckpoint {
+    /// The checkpoint at `path` for `decl_name` in `text`, if there is one.
+    pub fn load(path: &Path, text: &str, decl_name: &str) -> Result<Option<Self>, String> {
+        let Some(c) = load_json::<Self>(path)? else {
+            return Ok(None);
+        };
+        check_version(path, c.version)?;
+        if c.outcome.decl != decl_name || c.text_hash != crate::tree_search::hash_text(text) {
+            return Err(format!(
+                "checkpoint {} is for a different declaration or text",
+                path.display()
+            ));
+        }
+        Ok(Some(c))
+    }
+}
+
+/// Whole-file repair's state after a declaration (`file_repair::FileRepairOptions::checkpoint`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileCheckpoint {
+    pub version: u32,
+    pub file: String,
+    /// `tree_search::hash_text` of the file as it was before the run.
+    pub original_hash: u64,
+    /// Declarations done (repaired or given up on), by position in the repair order.
+    pub done: usize,
+    /// The broken declarations, with lines shifted by the edits so far.
+    pub pending: Vec<crate::file_repair::BrokenDecl>,
+    /// Names repaired so far, in order.
+    pub repaired: Vec<String>,
+    pub outcomes: Vec<RepairOutcome>,
+    pub edits: Vec<EditRecord>,
+    pub patched_text: String,
+}
+
+impl FileCheckpoint {
+    /// The checkpoint at `path` for `file_rel` with contents `original`, if there is one.
+    pub fn load(path: &Path, file_rel: &str, original: &str) -> Result<Option<Self>, String> {
+        let Some(c) = load_json::<Self>(path)? else {
+            return Ok(None);
+        };
+        check_version(path, c.version)?;
+        if c.file != file_rel || c.original_hash != crate::tree_search::hash_text(original) {
+            return Err(format!(
+                "checkpoint {} is for a different file, or {file_rel} changed since",
+                path.display()
+            ));
+        }
+        Ok(Some(c))
+    }
+}
+
+/// Where whole-file repair keeps the checkpoint of the declaration in progress.
+pub fn decl_checkpoint_path(file_checkpoint: &Path) -> PathBuf {
+    let mut p = file_checkpoint.as_os_str().to_owned();
+    p.push(".decl");
+    PathBuf::from(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_atomically_and_rejects_other_input() {
+        let dir = std::env::temp_dir().join(format!("pp-ckpt-{}", std::process::id()));
+        let path = dir.join("run.json");
+        assert!(load_json::<FileCheckpoint>(&path).unwrap().is_none());
+        let c = FileCheckpoint {
+            version: CHECKPOINT_VERSION,
+            file: "A.lean".into(),
+            original_hash: crate::tree_search::hash_text("theorem a : True := sorry\n"),
+            done: 1,
+            pending: Vec::new(),
+            repaired: vec!["a".into()],
+            outcomes: Vec::new(),
+            edits: Vec::new(),
+            patched_text: "theorem a : True := trivial\n".into(),
+        };
+        save_json(&path, &c).unwrap();
+        assert!(!decl_checkpoint_path(&path).exists());
+        assert!(!dir.join("run.json.tmp").exists());
+
+        let back = FileCheckpoint::load(&path, "A.lean", "theorem a : True := sorry\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!((back.done, back.repaired), (1, vec!["a".to_string()]));
+        assert!(FileCheckpoint::load(&path, "A.lean", "theorem a : False := sorry\n").is_err());
+        assert!(FileCheckpoint::load(&path, "B.lean", "theorem a : True := sorry\n").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,324 @@
+//! Long-running JSON-RPC server for editor integrations (VS Code, Neovim).
+//!
+//! Messages are JSON-RPC 2.0 with LSP framing (`Content-Length` headers), the same transport the
+//! Lean server client uses, so an extension can drive it with its existing LSP plumbing. Methods:
+//! - `initialize` → `{ "name", "version", "methods" }`;
+//! - `proofpatch/repairAt` (`RepairAtParams`) → `RepairAtResult`: repair the placeholder nearest
+//!   to `file:line` with `repair::repair_decl`. While it runs, every verified candidate is sent as a
+//!   `proofpatch/progress` notification (`{ "id": <request id>, "attempt": RepairAttempt }`);
+//!   the result carries the patch as an LSP `WorkspaceEdit`, which the editor applies (nothing is
+//!   written to disk);
+//! - `shutdown` → `null`, then `exit` ends the loop, abandoning repairs still running (at end of
+//!   input the loop ends too, after they have answered).
+//!
+//! Repairs run concurrently, one thread each; responses and notifications go through one writer task.
+
+use crate::patching::{EditRecord, GoalRef};
+use crate::repair::{RepairAttempt, RepairOptions};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+pub const METHODS: &[&str] = &["initialize", "proofpatch/repairAt", "shutdown", "exit"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairAtParams {
+    pub repo_root: String,
+    /// Repo-relative (or absolute) path of the file.
+    pub file: String,
+    /// 1-based line; the nearest placeholder to it is repaired.
+    pub line: usize,
+    #[serde(default)]
+    pub use_llm: bool,
+    #[serde(default)]
+    pub max_verifications: Option<usize>,
+    #[serde(default)]
+    pub timeout_s: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairAtResult {
+    pub ok: bool,
+    pub decl: String,
+    pub stop_reason: String,
+    pub solution: Option<String>,
+    /// LSP `WorkspaceEdit` (`{"changes": {uri: [TextEdit]}}`), when solved.
+    pub edit: Option<Value>,
+    pub attempts: usize,
+}
+
+/// One framed message.
+pub fn encode_message(v: &Value) -> Vec<u8> {
+    let body = v.to_string();
+    let mut out = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+    out.extend_from_slice(body.as_bytes());
+    out
+}
+
+/// Read one framed message (`Ok(None)` at end of input).
+pub async fn read_message<R: AsyncBufRead + Unpin>(r: &mut R) -> Result<Option<Value>, String> {
+    let mut len: Option<usize> = None;
+    loop {
+        let mut line = String::new();
+        let n = r
+            .read_line(&mut line)
+            .await
+            .map_err(|e| format!("read failed: {e}"))?;
+        if n == 0 {
+            return Ok(None);
+        }
+        let t = line.trim();
+        if t.is_empty() {
+            if len.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(v) = t.strip_prefix("Content-Length:") {
+            len = Some(
+                v.trim()
+                    .parse()
+                    .map_err(|e| format!("bad Content-Length: {e}"))?,
+            );
+        }
+    }
+    let mut buf = vec![0u8; len.unwrap_or(0)];
+    r.read_exact(&mut buf)
+        .await
+        .map_err(|e| format!("read failed: {e}"))?;
+    serde_json::from_slice(&buf)
+        .map(Some)
+        .map_err(|e| format!("invalid message: {e}"))
+}
+
+/// LSP position (0-based line, UTF-16 column) of byte offset `byte`.
+pub fn lsp_position(text: &str, byte: usize) -> Value {
+    let before = &text[..byte.min(text.len())];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].chars().map(char::len_utf16).sum::<usize>(),
+    })
+}
+
+fn file_uri(path: &Path) -> String {
+    let mut out = String::from("file://");
+    for b in path.display().to_string().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+/// `edit` (applied to `original`) as a `WorkspaceEdit` for `file_abs`.
+pub fn workspace_edit(file_abs: &Path, original: &str, edit: &EditRecord) -> Value {
+    let end = edit.byte_start + edit.old_text.len();
+    json!({
+        "changes": {
+            file_uri(file_abs): [{
+                "range": {
+                    "start": lsp_position(original, edit.byte_start),
+                    "end": lsp_position(original, end),
+                },
+                "newText": edit.new_text,
+            }]
+        }
+    })
+}
+
+/// Handle `proofpatch/repairAt`, sending attempts to `progress` as they complete.
+pub async fn repair_at(
+    p: &RepairAtParams,
+    progress: Option<UnboundedSender<RepairAttempt>>,
+) -> Result<RepairAtResult, String> {
+    let repo_root = crate::find_lean_repo_root(Path::new(&p.repo_root))?;
+    let file_rel = Path::new(&p.file)
+        .strip_prefix(&repo_root)
+        .map(|r| r.display().to_string())
+        .unwrap_or_else(|_| p.file.clone());
+    let abs = repo_root.join(&file_rel);
+    let text = std::fs::read_to_string(&abs).map_err(|e| format!("read {}: {e}", abs.display()))?;
+    let hit = crate::library_search::placeholder_near_line(&text, p.line)
+        .ok_or_else(|| "No `sorry`/`admit` tokens found in file.".to_string())?;
+    let goal = GoalRef::at_byte(&text, hit.byte_start)
+        .ok_or_else(|| format!("no declaration encloses line {}", hit.line))?;
+    let mut opts = RepairOptions {
+        use_llm: p.use_llm,
+        sorry_index: goal.sorry_index,
+        progress,
+        ..Default::default()
+    };
+    if let Some(n) = p.max_verifications {
+        opts.max_verifications = n;
+    }
+    if let Some(t) = p.timeout_s {
+        opts.verify_timeout = Duration::from_secs(t);
+    }
+    let out =
+        crate::repair::repair_decl_in_text(&repo_root, &file_rel, &text, &goal.decl, &opts).await?;
+    Ok(RepairAtResult {
+        ok: out.ok,
+        decl: out.decl.clone(),
+        stop_reason: out.stop_reason.clone(),
+        solution: out.solution.clone(),
+        edit: out.edit.as_ref().map(|e| workspace_edit(&abs, &text, e)),
+        attempts: out.attempts.len(),
+    })
+}
+
+fn error_response(id: &Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+async fn handle_repair_at(id: Value, params: Value, out: UnboundedSender<Value>) {
+    let p: RepairAtParams = match serde_json::from_value(params) {
+        Ok(p) => p,
+        Err(e) => {
+            let _ = out.send(error_response(&id, -32602, &format!("invalid params: {e}")));
+            return;
+        }
+    };
+    let (tx, mut rx) = unbounded_channel::<RepairAttempt>();
+    let (note_out, note_id) = (out.clone(), id.clone());
+    let notes = tokio::spawn(async move {
+        while let Some(a) = rx.recv().await {
+            let _ = note_out.send(json!({
+                "jsonrpc": "2.0",
+                "method": "proofpatch/progress",
+                "params": { "id": note_id, "attempt": a },
+            }));
+        }
+    });
+    let res = repair_at(&p, Some(tx)).await;
+    // The sender went away with the repair options; flush the remaining notifications first.
+    let _ = notes.await;
+    let _ = out.send(match res {
+        Ok(r) => json!({ "jsonrpc": "2.0", "id": id, "result": r }),
+        Err(e) => error_response(&id, -32000, &e),
+    });
+}
+
+/// Serve requests from `reader` until `exit` or end of input.
+pub async fn serve<R, W>(mut reader: R, mut writer: W) -> Result<(), String>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (out, mut out_rx) = unbounded_channel::<Value>();
+    let writer_task = tokio::spawn(async move {
+        while let Some(v) = out_rx.recv().await {
+            if writer.write_all(&encode_message(&v)).await.is_err() {
+                break;
+            }
+            let _ = writer.flush().await;
+        }
+    });
+    let mut exited = false;
+    while let Some(msg) = read_message(&mut reader).await? {
+        let method = msg.get("method").and_then(Value::as_str).unwrap_or("");
+        let id = msg.get("id").cloned();
+        let params = msg.get("params").cloned().unwrap_or(Value::Null);
+        match (method, id) {
+            ("exit", _) => {
+                exited = true;
+                break;
+            }
+            ("initialize", Some(id)) => {
+                let _ = out.send(json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": {
+                        "name": "proofpatch",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "methods": METHODS,
+                    },
+                }));
+            }
+            ("shutdown", Some(id)) => {
+                let _ = out.send(json!({ "jsonrpc": "2.0", "id": id, "result": null }));
+            }
+            ("proofpatch/repairAt", Some(id)) => {
+                // The repair future is not `Send`; each request gets its own thread and runtime.
+                let out = out.clone();
+                std::thread::spawn(move || {
+                    match tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                    {
+                        Ok(rt) => rt.block_on(handle_repair_at(id, params, out)),
+                        Err(e) => {
+                            let _ = out.send(error_response(&id, -32000, &e.to_string()));
+                        }
+                    }
+                });
+            }
+            (_, Some(id)) => {
+                let _ = out.send(error_response(
+                    &id,
+                    -32601,
+                    &format!("method not found: {method}"),
+                ));
+            }
+            // Unknown notifications are ignored.
+            (_, None) => {}
+        }
+    }
+    drop(out);
+    if exited {
+        // Repairs still running are abandoned; at end of input their results are still sent.
+        writer_task.abort();
+    }
+    let _ = writer_task.await;
+    Ok(())
+}
+
+/// `serve` on stdin/stdout.
+pub async fn serve_stdio() -> Result<(), String> {
+    serve(
+        tokio::io::BufReader::new(tokio::io::stdin()),
+        tokio::io::stdout(),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_framed_requests_and_maps_edits_to_lsp_ranges() {
+        let mut input = encode_message(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize"}));
+        input.extend(encode_message(
+            &json!({"jsonrpc": "2.0", "id": 2, "method": "nope"}),
+        ));
+        let (client, server_out) = tokio::io::duplex(1 << 16);
+        serve(&input[..], server_out).await.unwrap();
+        let mut r = tokio::io::BufReader::new(client);
+        let a = read_message(&mut r).await.unwrap().unwrap();
+        let b = read_message(&mut r).await.unwrap().unwrap();
+        assert_eq!(a["result"]["methods"][1], "proofpatch/repairAt");
+        assert_eq!(b["error"]["code"], -32601);
+
+        let text = "theorem t : (α → β) := by\n  sorry\n";
+        let start = text.find("sorry").unwrap();
+        let rec = EditRecord {
+            byte_start: start,
+            old_text: "sorry".to_string(),
+            new_text: "exact id".to_string(),
+            line: 2,
+        };
+        let w = workspace_edit(Path::new("/r/My File.lean"), text, &rec);
+        let e = &w["changes"]["file:///r/My%20File.lean"][0];
+        assert_eq!(e["range"]["start"], json!({"line": 1, "character": 2}));
+        assert_eq!(e["range"]["end"], json!({"line": 1, "character": 7}));
+        assert_eq!(lsp_position(text, 16)["character"], 15);
+    }
+}
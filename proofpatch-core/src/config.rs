@@ -1,14 +1,442 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(deny_unknown_fields)]
 pub struct ProofpatchConfig {
     #[serde(default)]
     pub research: ResearchConfig,
     #[serde(default)]
     pub hints: HintsConfig,
+    #[serde(default)]
+    pub verify: VerifyConfig,
+    #[serde(default)]
+    pub llm: LlmConfig,
+    #[serde(default)]
+    pub prompts: PromptsConfig,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    #[serde(default)]
+    pub embeddings: EmbeddingsConfig,
+    #[serde(default)]
+    pub ranking: RankingConfig,
+    #[serde(default)]
+    pub repair: RepairConfig,
+}
+
+/// `[repair]`: named settings for the repair loop.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RepairConfig {
+    /// `[repair.profiles.<name>]`, picked with `--profile <name>`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, RepairProfile>,
+    /// Profile used when a command is given no `--profile`.
+    #[serde(default)]
+    pub default_profile: Option<String>,
+}
+
+impl RepairConfig {
+    pub fn profile(&self, name: &str) -> Result<&RepairProfile, String> {
+        self.profiles.get(name).ok_or_else(|| {
+            let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            format!(
+                "unknown repair profile: {name} (available: {})",
+                names.join(", ")
+            )
+        })
+    }
+
+    /// The profile named `name`, else `default_profile`, else none.
+    pub fn selected(&self, name: Option<&str>) -> Result<Option<&RepairProfile>, String> {
+        name.or(self.default_profile.as_deref())
+            .map(|n| self.profile(n))
+            .transpose()
+    }
+}
+
+/// One repair profile (`repair::RepairOptions::apply_profile`). Unset fields keep the
+/// command's own defaults.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RepairProfile {
+    #[serde(default)]
+    pub max_rounds: Option<usize>,
+    #[serde(default)]
+    pub candidates_per_round: Option<usize>,
+    /// Total compiles across all rounds.
+    #[serde(default)]
+    pub max_verifications: Option<usize>,
+    #[serde(default)]
+    pub verify_timeout_s: Option<u64>,
+    /// Ask the configured LLM for candidates.
+    #[serde(default)]
+    pub use_llm: Option<bool>,
+    /// Solved goals shown to the LLM (`fewshot`).
+    #[serde(default)]
+    pub few_shot: Option<usize>,
+    /// Research preset whose results are retrieved into the LLM prompt (`research::rag`).
+    #[serde(default)]
+    pub research: Option<String>,
+    /// Research chunks retrieved per prompt (default 4).
+    #[serde(default)]
+    pub research_k: Option<usize>,
+    /// Estimated tokens per research chunk (default 200).
+    #[serde(default)]
+    pub research_chunk_tokens: Option<usize>,
+}
+
+/// `[ranking]`: weights of the signals in a candidate's score (`ranking::Weights`; unset ones
+/// keep their defaults). 0 turns a signal off.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RankingConfig {
+    /// SMT entailment of the goal, for arithmetic closers (default 3).
+    #[serde(default)]
+    pub smt: Option<f64>,
+    /// Well-formed text (default 4).
+    #[serde(default)]
+    pub validity: Option<f64>,
+    /// Predicted compile success (default 3).
+    #[serde(default)]
+    pub compile: Option<f64>,
+    /// Shorter proofs (default 0.25).
+    #[serde(default)]
+    pub length: Option<f64>,
+    /// Fewer style issues (default 0.25).
+    #[serde(default)]
+    pub lint: Option<f64>,
+    /// The model's confidence (default 1).
+    #[serde(default)]
+    pub confidence: Option<f64>,
+    /// The reranker's score, when `rerank_model` is set (default 1).
+    #[serde(default)]
+    pub rerank: Option<f64>,
+    /// Penalty for choosing a candidate similar to one already chosen (same head tactic, shared
+    /// premises) when a round keeps the best few (default 1.5).
+    #[serde(default)]
+    pub diversity: Option<f64>,
+    /// ONNX reranker model (`rerank`), relative to the repo root; needs the `onnx` cargo feature.
+    #[serde(default)]
+    pub rerank_model: Option<String>,
+    /// Rhai script adjusting candidate scores (`score_hook`), relative to the repo root; needs
+    /// the `scripting` cargo feature.
+    #[serde(default)]
+    pub score_script: Option<String>,
+}
+
+/// `[embeddings]`: the text embedding backend for retrieval (`llm::embeddings::from_config`).
+/// Without `provider`, the offline hashing embedder is used.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EmbeddingsConfig {
+    /// `hashing` (default), `openai` (any OpenAI-compatible `/embeddings` endpoint), or `ollama`.
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Env var holding the API key (default `OPENAI_API_KEY`).
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// Vector size: for `hashing`, the number of buckets; for `openai`, shortened vectors.
+    #[serde(default)]
+    pub dimensions: Option<usize>,
+    /// Texts per request (default 64).
+    #[serde(default)]
+    pub batch_size: Option<usize>,
+    /// Keep vectors under `.generated/proofpatch-cache/embeddings/` (default true).
+    #[serde(default)]
+    pub cache: Option<bool>,
+    #[serde(default)]
+    pub timeout_s: Option<u64>,
+}
+
+/// `[budget]`: caps on LLM use per run (one process), checked before each request
+/// (`llm::cost::check_budget`). Unset caps are unlimited.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetConfig {
+    #[serde(default)]
+    pub max_requests: Option<u64>,
+    /// Input plus output tokens.
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    /// Estimated spend in USD (requests with no known price count as free).
+    #[serde(default)]
+    pub max_cost_usd: Option<f64>,
+    /// Prices by model name fragment (longest match wins), overriding the built-in list, e.g.
+    /// `[budget.prices."gpt-4o"] input_per_mtok = 2.5` with `output_per_mtok = 10.0`.
+    #[serde(default)]
+    pub prices: HashMap<String, ModelPrice>,
+}
+
+/// USD per million tokens.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPrice {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+/// `[prompts]`: where to look for prompt template overrides (`prompts::PromptTemplate::load`),
+/// and per-task system prompts (`prompts::system_prompt`).
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PromptsConfig {
+    /// Directories holding `<name>.toml` templates, relative to the repo root or absolute.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Put before the system prompt of every task, e.g. project conventions.
+    #[serde(default)]
+    pub persona: Option<String>,
+    /// By task (`patch`, `summarize`, `triage`, `research_summary`, `review`), e.g.
+    /// `[prompts.system.review] append = "Flag any new axioms."`.
+    #[serde(default)]
+    pub system: BTreeMap<crate::prompts::Task, TaskSystemPrompt>,
+}
+
+/// `[prompts.system.<task>]`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TaskSystemPrompt {
+    /// Used instead of the task's built-in (or template) system prompt. Tasks that parse JSON
+    /// replies describe the reply shape there, so prefer `append` for those.
+    #[serde(default)]
+    pub replace: Option<String>,
+    /// Put after the task's system prompt.
+    #[serde(default)]
+    pub append: Option<String>,
+}
+
+/// `[llm]`: which chat backend to use. Without `provider`, the env-based selection in `llm`
+/// (`PROOFPATCH_PROVIDER_ORDER`, `OPENAI_API_KEY`, …) applies.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LlmConfig {
+    /// `openai` (any OpenAI-compatible endpoint), `azure` (Azure OpenAI), `anthropic`, `gemini`,
+    /// `bedrock` (AWS), `ollama`, `gguf` (in-process llama.cpp; cargo feature `gguf`), or `mock`
+    /// (replies from fixture files, for tests).
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Model name; for `azure`, the deployment name; for `gguf`, the path of the `.gguf` file.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Override the API base URL (e.g. a proxy or a self-hosted OpenAI-compatible server).
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Env var holding the API key (default: `OPENAI_API_KEY` / `ANTHROPIC_API_KEY` /
+    /// `GEMINI_API_KEY`). Keys are never read from the config file itself.
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// `azure` only: REST `api-version` (default `2024-10-21`, or `OPENAI_API_VERSION`).
+    #[serde(default)]
+    pub api_version: Option<String>,
+    /// `azure` only: env var with an Azure AD access token, used when no API key is set
+    /// (default `AZURE_OPENAI_AD_TOKEN`).
+    #[serde(default)]
+    pub azure_ad_token_env: Option<String>,
+    /// `bedrock` only: AWS region (default `AWS_REGION` / `AWS_DEFAULT_REGION` / the profile's).
+    #[serde(default)]
+    pub region: Option<String>,
+    /// `bedrock` only: AWS profile for `~/.aws/credentials` (default `AWS_PROFILE`, `default`).
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// `gguf` only: context window in tokens (default 4096).
+    #[serde(default)]
+    pub context_size: Option<u32>,
+    /// The model's context window in tokens, for prompt budgeting (`llm::window`; default: the
+    /// built-in table by model name).
+    #[serde(default)]
+    pub context_window: Option<u32>,
+    /// `gguf` only: layers to offload to the GPU (default 0, CPU only).
+    #[serde(default)]
+    pub gpu_layers: Option<u32>,
+    /// `mock` only: scenario file or directory of them (`llm::mock`).
+    #[serde(default)]
+    pub fixtures: Option<String>,
+    /// `gguf` only: sampler seed when `temperature` > 0.
+    #[serde(default)]
+    pub seed: Option<u32>,
+    /// Reuse cached replies to identical requests (`llm::cache`; default true).
+    #[serde(default)]
+    pub cache: Option<bool>,
+    /// Age after which a cached reply is ignored (default 604800, a week).
+    #[serde(default)]
+    pub cache_ttl_s: Option<u64>,
+    /// `anthropic` only: mark the system prompt, earlier turns, and the stable part of the
+    /// prompt as cacheable, so repeated context is billed as cache reads (default true).
+    #[serde(default)]
+    pub prompt_cache: Option<bool>,
+    /// Cheap-first model tiers for the repair loop (`llm::routing`).
+    #[serde(default)]
+    pub routing: Option<RoutingConfig>,
+    /// Failover chain, e.g. `["anthropic", "openai", "ollama"]` (`llm::failover`). Without
+    /// `provider`, the first entry is the primary.
+    #[serde(default)]
+    pub providers: Vec<String>,
+    /// Settings for the fallback providers, by name, e.g. `[llm.failover.ollama]
+    /// model = "qwen2.5-coder:7b"`; each overrides the `[llm]` fields it sets.
+    #[serde(default)]
+    pub failover: HashMap<String, ModelTier>,
+    /// How long a provider that failed over is skipped (default 60).
+    #[serde(default)]
+    pub failover_cooldown_s: Option<u64>,
+    /// Request limits by provider name, e.g. `[llm.limits.anthropic] max_concurrent = 4`
+    /// (`llm::scheduler`; default: none).
+    #[serde(default)]
+    pub limits: HashMap<String, ProviderLimits>,
+    /// Prompt/response transcripts of each run (`llm::transcripts`).
+    #[serde(default)]
+    pub transcripts: TranscriptsConfig,
+    /// Speculative parallel sampling in the repair loop (`llm::speculative`; default: off).
+    #[serde(default)]
+    pub speculative: Option<SpeculativeConfig>,
+}
+
+/// `[llm.speculative]`: send `requests` samples of each repair prompt at once and cancel the
+/// ones still running once `min_candidates` distinct candidates have arrived. Lower latency for
+/// more requests (and the partial output of cancelled ones, which providers still bill).
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SpeculativeConfig {
+    /// Requests sent at once; the mode is off below 2.
+    #[serde(default)]
+    pub requests: Option<usize>,
+    /// Distinct candidates to wait for (default: wait for every request).
+    #[serde(default)]
+    pub min_candidates: Option<usize>,
+    /// Sampling temperature (default 0.8).
+    #[serde(default)]
+    pub temperature: Option<f32>,
+}
+
+/// `[llm.limits.<provider>]`: process-wide caps on requests to one provider.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderLimits {
+    /// Requests in flight at once.
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+    /// Requests started in any 60-second window.
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+}
+
+/// `[llm.transcripts]`: where and for how long request transcripts are kept.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TranscriptsConfig {
+    /// Record transcripts (default true; `PROOFPATCH_TRANSCRIPTS=0` turns them off).
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    /// Directory holding one subdirectory per run, relative to the repo root
+    /// (default `.generated/proofpatch-runs`).
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// Runs to keep; older ones are deleted when a new run starts (default 20, 0: keep all).
+    #[serde(default)]
+    pub keep_runs: Option<usize>,
+    /// Also delete runs older than this many days.
+    #[serde(default)]
+    pub max_age_days: Option<u64>,
+    /// Extra regexes whose matches are redacted, on top of API keys and known token formats.
+    #[serde(default)]
+    pub redact: Vec<String>,
+}
+
+/// `[llm.routing]`: try the first tier, escalate to later ones for goals it failed on or that
+/// look hard, e.g. `[[llm.routing.tiers]] model = "gpt-4o-mini"`, then one with
+/// `provider = "anthropic"` and `model = "claude-sonnet-4-20250514"`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RoutingConfig {
+    /// Cheapest first. Each tier overrides the `[llm]` fields it sets.
+    #[serde(default)]
+    pub tiers: Vec<ModelTier>,
+    /// LLM rounds without a verified candidate before moving to the next tier (default 1).
+    #[serde(default)]
+    pub escalate_after: Option<usize>,
+    /// Start at the last tier when the goal is longer than this many estimated tokens.
+    #[serde(default)]
+    pub hard_goal_tokens: Option<usize>,
+    /// Start at the last tier when the goal has more hypothesis lines than this.
+    #[serde(default)]
+    pub hard_hypotheses: Option<usize>,
+    /// Start at the last tier when repairing one of these error classes (`diagnostics`
+    /// names, e.g. `deterministic_timeout`).
+    #[serde(default)]
+    pub hard_error_classes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ModelTier {
+    /// Shown in repair outcomes (default: the model name).
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub context_window: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyConfig {
+    /// Run `lake exe cache get` before verification when Mathlib `.olean` files are missing
+    /// (default: true; `PROOFPATCH_AUTO_CACHE` overrides).
+    #[serde(default)]
+    pub auto_cache: Option<bool>,
+    /// Extra toolchain/Mathlib versions accepted patches are checked against (`matrix`), e.g.
+    /// `[[verify.matrix]] name = "nightly"` with `toolchain = "leanprover/lean4:nightly-…"`.
+    #[serde(default)]
+    pub matrix: Vec<MatrixEntry>,
+    /// Compile this many of a repair round's top-ranked candidates at once, cancelling the rest
+    /// when one succeeds (`parallel_verify`; default 1: one at a time).
+    #[serde(default)]
+    pub parallel: Option<usize>,
+    /// With `parallel`, let the batch finish after the first success and keep the shortest
+    /// proof (default false).
+    #[serde(default)]
+    pub keep_searching: Option<bool>,
+    /// Accept candidates using `native_decide`, and the compiler axioms it adds (`safety`;
+    /// default false).
+    #[serde(default)]
+    pub allow_native_decide: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct MatrixEntry {
+    pub name: String,
+    /// An existing checkout of the project pinned to this version (relative to the repo root or
+    /// absolute). Without it, a scratch copy under `.generated/proofpatch-matrix/<name>` is pinned
+    /// to `toolchain` / `mathlib_rev`.
+    #[serde(default)]
+    pub root: Option<String>,
+    /// `lean-toolchain` contents, e.g. `leanprover/lean4:v4.15.0`.
+    #[serde(default)]
+    pub toolchain: Option<String>,
+    /// Mathlib revision (tag, branch, or commit) for the `require mathlib` line.
+    #[serde(default)]
+    pub mathlib_rev: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -61,11 +489,105 @@ pub struct ResearchConfig {
     pub defaults: ResearchDefaults,
     #[serde(default)]
     pub presets: HashMap<String, ResearchPreset>,
+    /// Endpoints and credentials of the search backends that need them.
+    #[serde(default)]
+    pub backends: SearchBackendsConfig,
+}
+
+/// `[research.backends.<name>]`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SearchBackendsConfig {
+    #[serde(default)]
+    pub arxiv: Option<ArxivConfig>,
+    #[serde(default)]
+    pub searxng: Option<SearxngConfig>,
+    #[serde(default)]
+    pub brave: Option<BraveConfig>,
+    #[serde(default)]
+    pub mathlib_docs: Option<MathlibDocsConfig>,
+    #[serde(default)]
+    pub zulip: Option<ZulipConfig>,
+}
+
+/// The arXiv API.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ArxivConfig {
+    /// Only papers in one of these categories (e.g. `math.CO`, `cs.LO`).
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Where query words must occur: `title_abstract` (default), `title`, `abstract`, or `all`.
+    #[serde(default)]
+    pub fields: Option<String>,
+}
+
+/// A SearxNG instance (self-hosted; its `search.formats` must include `json`).
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SearxngConfig {
+    /// e.g. `http://localhost:8888` (default: `PROOFPATCH_SEARXNG_URL`).
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Engines to restrict the search to (default: the instance's).
+    #[serde(default)]
+    pub engines: Vec<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+/// Mathlib declarations and docstrings.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MathlibDocsConfig {
+    /// `leansearch` (default: the hosted semantic search, `PROOFPATCH_LEANSEARCH_URL`) or
+    /// `local` (a doc-gen4 `declaration-data.bmp`, see `index_path`).
+    #[serde(default)]
+    pub source: Option<String>,
+    /// The doc-gen4 declaration index for `local` (default
+    /// `.lake/build/doc/declarations/declaration-data.bmp`, relative to the repo).
+    #[serde(default)]
+    pub index_path: Option<String>,
+    /// Where result links point (default: the hosted mathlib4 docs).
+    #[serde(default)]
+    pub docs_url: Option<String>,
+}
+
+/// A Zulip organization (default: the Lean community's).
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ZulipConfig {
+    #[serde(default)]
+    pub realm: Option<String>,
+    /// Env vars holding the account email and API key (default `ZULIP_EMAIL`, `ZULIP_API_KEY`).
+    /// Without them only web-public channels are searched.
+    #[serde(default)]
+    pub email_env: Option<String>,
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+}
+
+/// The Brave Search API.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BraveConfig {
+    /// Env var holding the subscription token (default `BRAVE_API_KEY`). Keys are never read
+    /// from the config file itself.
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Two-letter country code results are localized to.
+    #[serde(default)]
+    pub country: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(deny_unknown_fields)]
 pub struct ResearchDefaults {
+    /// Search backends presets fan out to (`research::backend`), unless a preset names its own.
+    #[serde(default)]
+    pub backends: Option<Vec<String>>,
     #[serde(default)]
     pub max_results: Option<usize>,
     #[serde(default)]
@@ -82,6 +604,15 @@ pub struct ResearchDefaults {
     pub llm_max_list_items: Option<usize>,
     #[serde(default)]
     pub llm_max_str_chars: Option<usize>,
+    #[serde(default)]
+    pub llm_chunk_tokens: Option<usize>,
+    /// Reuse backend answers cached under `.generated/proofpatch-cache/research/`
+    /// (`research::cache`; default true).
+    #[serde(default)]
+    pub cache: Option<bool>,
+    /// Age after which a cached answer is ignored (default 86400, a day).
+    #[serde(default)]
+    pub cache_ttl_s: Option<u64>,
     /// Optional defaults for proof search behavior (consumed by `proofpatch-cli tree-search-nearest`).
     #[serde(default)]
     pub tree_search: Option<TreeSearchPolicy>,
@@ -120,11 +651,26 @@ pub struct TreeSearchPolicy {
 #[serde(deny_unknown_fields)]
 pub struct ResearchPreset {
     pub query: String,
+    /// Post-filter: one of these tokens must appear in (title + snippet), lowercased substring
+    /// match (`research::filter`).
     #[serde(default)]
     pub must_include_any: Vec<String>,
     /// Post-filter: all of these tokens must appear in (title + abstract), lowercased substring match.
     #[serde(default)]
     pub must_include_all: Vec<String>,
+    /// Post-filter: none of these tokens may appear in (title + snippet).
+    #[serde(default)]
+    pub must_exclude: Vec<String>,
+    /// Post-filter: (title + snippet) must match one of these regexes (case-insensitive).
+    #[serde(default)]
+    pub include_regex: Vec<String>,
+    /// Post-filter: (title + snippet) must match none of these regexes (case-insensitive).
+    #[serde(default)]
+    pub exclude_regex: Vec<String>,
+    /// Search backends to query (`research::backend`); defaults to `[research.defaults] backends`,
+    /// then `["arxiv"]`.
+    #[serde(default)]
+    pub backends: Option<Vec<String>>,
     #[serde(default)]
     pub max_results: Option<usize>,
     #[serde(default)]
@@ -146,11 +692,24 @@ pub struct ResearchPreset {
     /// Cap: max characters per emitted string item (Unicode scalar values).
     #[serde(default)]
     pub llm_max_str_chars: Option<usize>,
+    /// Cap: estimated tokens of sources per summarization request; more sources are summarized
+    /// in several chunks and then merged (`research_summary`).
+    #[serde(default)]
+    pub llm_chunk_tokens: Option<usize>,
+    /// See `ResearchDefaults::cache`.
+    #[serde(default)]
+    pub cache: Option<bool>,
+    #[serde(default)]
+    pub cache_ttl_s: Option<u64>,
     /// Optional per-preset proof search policy.
     #[serde(default)]
     pub tree_search: Option<TreeSearchPolicy>,
 }
 
+fn default_backends() -> Vec<String> {
+    vec!["arxiv".to_string()]
+}
+
 fn default_max_results() -> usize {
     8
 }
@@ -175,12 +734,24 @@ fn default_llm_max_str_chars() -> usize {
     160
 }
 
+fn default_llm_chunk_tokens() -> usize {
+    6_000
+}
+
+fn default_research_cache_ttl_s() -> u64 {
+    24 * 3600
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ResearchPresetResolved {
     pub query: String,
     pub must_include_any: Vec<String>,
     pub must_include_all: Vec<String>,
+    pub must_exclude: Vec<String>,
+    pub include_regex: Vec<String>,
+    pub exclude_regex: Vec<String>,
+    pub backends: Vec<String>,
     pub max_results: usize,
     pub timeout_ms: u64,
     pub llm_summary: bool,
@@ -189,6 +760,9 @@ pub struct ResearchPresetResolved {
     pub llm_max_top: usize,
     pub llm_max_list_items: usize,
     pub llm_max_str_chars: usize,
+    pub llm_chunk_tokens: usize,
+    pub cache: bool,
+    pub cache_ttl_s: u64,
     pub tree_search: Option<TreeSearchPolicy>,
 }
 
@@ -196,47 +770,27 @@ impl ResearchConfig {
     pub fn resolve_preset(&self, name: &str) -> Option<ResearchPresetResolved> {
         let p = self.presets.get(name)?.clone();
         let d = &self.defaults;
-        let tree_search = {
-            let mut out: TreeSearchPolicy = TreeSearchPolicy::default();
-            let mut any = false;
-            if let Some(td) = d.tree_search.clone() {
-                out = td;
-                any = true;
-            }
-            if let Some(tp) = p.tree_search.clone() {
-                if tp.goal_first_k.is_some() {
-                    out.goal_first_k = tp.goal_first_k;
-                }
-                if tp.smt_depth.is_some() {
-                    out.smt_depth = tp.smt_depth;
-                }
-                if tp.hint_packs.is_some() {
-                    out.hint_packs = tp.hint_packs;
-                }
-                if tp.smt_solver.is_some() {
-                    out.smt_solver = tp.smt_solver;
-                }
-                if tp.smt_timeout_ms.is_some() {
-                    out.smt_timeout_ms = tp.smt_timeout_ms;
+        let tree_search = match (d.tree_search.clone(), p.tree_search.as_ref()) {
+            (None, None) => None,
+            (base, over) => {
+                let mut out = base.unwrap_or_default();
+                if let Some(tp) = over {
+                    out.merge(tp);
                 }
-                if tp.smt_explain.is_some() {
-                    out.smt_explain = tp.smt_explain;
-                }
-                if tp.smt_explain_max_hyps.is_some() {
-                    out.smt_explain_max_hyps = tp.smt_explain_max_hyps;
-                }
-                any = true;
-            }
-            if any {
                 Some(out)
-            } else {
-                None
             }
         };
         Some(ResearchPresetResolved {
             query: p.query,
             must_include_any: p.must_include_any,
             must_include_all: p.must_include_all,
+            must_exclude: p.must_exclude,
+            include_regex: p.include_regex,
+            exclude_regex: p.exclude_regex,
+            backends: p
+                .backends
+                .or_else(|| d.backends.clone())
+                .unwrap_or_else(default_backends),
             max_results: p
                 .max_results
                 .or(d.max_results)
@@ -263,11 +817,326 @@ impl ResearchConfig {
                 .llm_max_str_chars
                 .or(d.llm_max_str_chars)
                 .unwrap_or_else(default_llm_max_str_chars),
+            llm_chunk_tokens: p
+                .llm_chunk_tokens
+                .or(d.llm_chunk_tokens)
+                .unwrap_or_else(default_llm_chunk_tokens),
+            cache: p.cache.or(d.cache).unwrap_or(true),
+            cache_ttl_s: p
+                .cache_ttl_s
+                .or(d.cache_ttl_s)
+                .unwrap_or_else(default_research_cache_ttl_s),
             tree_search,
         })
     }
 }
 
+fn merge_opt<T: Clone>(dst: &mut Option<T>, src: &Option<T>) {
+    if src.is_some() {
+        *dst = src.clone();
+    }
+}
+
+impl TreeSearchPolicy {
+    /// Overlay `other` onto `self`: every field set in `other` wins.
+    pub fn merge(&mut self, other: &TreeSearchPolicy) {
+        merge_opt(&mut self.goal_first_k, &other.goal_first_k);
+        merge_opt(&mut self.smt_depth, &other.smt_depth);
+        merge_opt(&mut self.hint_packs, &other.hint_packs);
+        merge_opt(&mut self.smt_solver, &other.smt_solver);
+        merge_opt(&mut self.smt_timeout_ms, &other.smt_timeout_ms);
+        merge_opt(&mut self.smt_explain, &other.smt_explain);
+        merge_opt(&mut self.smt_explain_max_hyps, &other.smt_explain_max_hyps);
+    }
+}
+
+impl ResearchDefaults {
+    /// Overlay `other` onto `self` (same field-wise rule as preset resolution).
+    pub fn merge(&mut self, other: &ResearchDefaults) {
+        merge_opt(&mut self.backends, &other.backends);
+        merge_opt(&mut self.max_results, &other.max_results);
+        merge_opt(&mut self.timeout_ms, &other.timeout_ms);
+        merge_opt(&mut self.llm_summary, &other.llm_summary);
+        merge_opt(&mut self.llm_timeout_s, &other.llm_timeout_s);
+        merge_opt(&mut self.llm_summary_kind, &other.llm_summary_kind);
+        merge_opt(&mut self.llm_max_top, &other.llm_max_top);
+        merge_opt(&mut self.llm_max_list_items, &other.llm_max_list_items);
+        merge_opt(&mut self.llm_max_str_chars, &other.llm_max_str_chars);
+        merge_opt(&mut self.llm_chunk_tokens, &other.llm_chunk_tokens);
+        merge_opt(&mut self.cache, &other.cache);
+        merge_opt(&mut self.cache_ttl_s, &other.cache_ttl_s);
+        match (self.tree_search.as_mut(), other.tree_search.as_ref()) {
+            (Some(dst), Some(src)) => dst.merge(src),
+            (None, Some(src)) => self.tree_search = Some(src.clone()),
+            _ => {}
+        }
+    }
+}
+
+impl RankingConfig {
+    /// Overlay `other` onto `self`: every field set in `other` wins.
+    pub fn merge(&mut self, other: &RankingConfig) {
+        merge_opt(&mut self.smt, &other.smt);
+        merge_opt(&mut self.validity, &other.validity);
+        merge_opt(&mut self.compile, &other.compile);
+        merge_opt(&mut self.length, &other.length);
+        merge_opt(&mut self.lint, &other.lint);
+        merge_opt(&mut self.confidence, &other.confidence);
+        merge_opt(&mut self.rerank, &other.rerank);
+        merge_opt(&mut self.diversity, &other.diversity);
+        merge_opt(&mut self.rerank_model, &other.rerank_model);
+        merge_opt(&mut self.score_script, &other.score_script);
+    }
+}
+
+impl LlmConfig {
+    /// Overlay `other` onto `self`: every field set in `other` wins; `providers` is replaced when
+    /// `other` lists any, `failover` and `limits` entries by provider name, `routing` and
+    /// `speculative` as units.
+    pub fn merge(&mut self, other: &LlmConfig) {
+        merge_opt(&mut self.provider, &other.provider);
+        merge_opt(&mut self.model, &other.model);
+        merge_opt(&mut self.base_url, &other.base_url);
+        merge_opt(&mut self.api_key_env, &other.api_key_env);
+        merge_opt(&mut self.api_version, &other.api_version);
+        merge_opt(&mut self.azure_ad_token_env, &other.azure_ad_token_env);
+        merge_opt(&mut self.region, &other.region);
+        merge_opt(&mut self.profile, &other.profile);
+        merge_opt(&mut self.temperature, &other.temperature);
+        merge_opt(&mut self.max_tokens, &other.max_tokens);
+        merge_opt(&mut self.context_size, &other.context_size);
+        merge_opt(&mut self.context_window, &other.context_window);
+        merge_opt(&mut self.gpu_layers, &other.gpu_layers);
+        merge_opt(&mut self.fixtures, &other.fixtures);
+        merge_opt(&mut self.seed, &other.seed);
+        merge_opt(&mut self.cache, &other.cache);
+        merge_opt(&mut self.cache_ttl_s, &other.cache_ttl_s);
+        merge_opt(&mut self.prompt_cache, &other.prompt_cache);
+        merge_opt(&mut self.routing, &other.routing);
+        if !other.providers.is_empty() {
+            self.providers = other.providers.clone();
+        }
+        self.failover.extend(other.failover.clone());
+        merge_opt(&mut self.failover_cooldown_s, &other.failover_cooldown_s);
+        self.limits.extend(other.limits.clone());
+        self.transcripts.merge(&other.transcripts);
+        merge_opt(&mut self.speculative, &other.speculative);
+    }
+}
+
+impl TranscriptsConfig {
+    /// Overlay `other` onto `self`: every field set in `other` wins; `redact` is replaced when
+    /// `other` lists any patterns.
+    pub fn merge(&mut self, other: &TranscriptsConfig) {
+        merge_opt(&mut self.enabled, &other.enabled);
+        merge_opt(&mut self.dir, &other.dir);
+        merge_opt(&mut self.keep_runs, &other.keep_runs);
+        merge_opt(&mut self.max_age_days, &other.max_age_days);
+        if !other.redact.is_empty() {
+            self.redact = other.redact.clone();
+        }
+    }
+}
+
+impl EmbeddingsConfig {
+    /// Overlay `other` onto `self`: every field set in `other` wins.
+    pub fn merge(&mut self, other: &EmbeddingsConfig) {
+        merge_opt(&mut self.provider, &other.provider);
+        merge_opt(&mut self.model, &other.model);
+        merge_opt(&mut self.base_url, &other.base_url);
+        merge_opt(&mut self.api_key_env, &other.api_key_env);
+        merge_opt(&mut self.dimensions, &other.dimensions);
+        merge_opt(&mut self.batch_size, &other.batch_size);
+        merge_opt(&mut self.cache, &other.cache);
+        merge_opt(&mut self.timeout_s, &other.timeout_s);
+    }
+}
+
+impl ProofpatchConfig {
+    pub fn builder() -> ProofpatchConfigBuilder {
+        ProofpatchConfigBuilder::default()
+    }
+
+    /// Layer `other` on top of `self`.
+    ///
+    /// Semantics (the later layer wins):
+    /// - scalar defaults, `llm`, `ranking`, `embeddings`: field-wise, only fields that are set in
+    ///   `other` override (`LlmConfig::merge`)
+    /// - `hints.defaults.enabled_packs`: replaced when `other` lists any packs
+    /// - presets / hint packs / search backends / repair profiles: replaced wholesale by name (a
+    ///   preset is one unit)
+    /// - `verify.matrix`: replaced when `other` lists any entries
+    pub fn merge(&mut self, other: ProofpatchConfig) {
+        self.research.defaults.merge(&other.research.defaults);
+        self.research.presets.extend(other.research.presets);
+        let b = other.research.backends;
+        merge_opt(&mut self.research.backends.arxiv, &b.arxiv);
+        merge_opt(&mut self.research.backends.searxng, &b.searxng);
+        merge_opt(&mut self.research.backends.brave, &b.brave);
+        merge_opt(&mut self.research.backends.mathlib_docs, &b.mathlib_docs);
+        merge_opt(&mut self.research.backends.zulip, &b.zulip);
+        if !other.hints.defaults.enabled_packs.is_empty() {
+            self.hints.defaults.enabled_packs = other.hints.defaults.enabled_packs;
+        }
+        self.hints.packs.extend(other.hints.packs);
+        if other.verify.auto_cache.is_some() {
+            self.verify.auto_cache = other.verify.auto_cache;
+        }
+        if !other.verify.matrix.is_empty() {
+            self.verify.matrix = other.verify.matrix;
+        }
+        if other.verify.parallel.is_some() {
+            self.verify.parallel = other.verify.parallel;
+        }
+        if other.verify.keep_searching.is_some() {
+            self.verify.keep_searching = other.verify.keep_searching;
+        }
+        if other.verify.allow_native_decide.is_some() {
+            self.verify.allow_native_decide = other.verify.allow_native_decide;
+        }
+        self.llm.merge(&other.llm);
+        self.ranking.merge(&other.ranking);
+        self.embeddings.merge(&other.embeddings);
+        if !other.prompts.paths.is_empty() {
+            self.prompts.paths = other.prompts.paths;
+        }
+        if other.prompts.persona.is_some() {
+            self.prompts.persona = other.prompts.persona;
+        }
+        self.prompts.system.extend(other.prompts.system);
+        if other.budget != BudgetConfig::default() {
+            self.budget = other.budget;
+        }
+        self.repair.profiles.extend(other.repair.profiles);
+        if other.repair.default_profile.is_some() {
+            self.repair.default_profile = other.repair.default_profile;
+        }
+    }
+}
+
+/// Build a `ProofpatchConfig` in code (tests, embedding) without going through TOML.
+///
+/// ```
+/// use proofpatch_core::config::{ProofpatchConfig, ResearchPreset};
+///
+/// let cfg = ProofpatchConfig::builder()
+///     .max_results(5)
+///     .preset("demo", ResearchPreset::new("LLL swap count"))
+///     .build();
+/// assert_eq!(cfg.research.resolve_preset("demo").unwrap().max_results, 5);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ProofpatchConfigBuilder {
+    cfg: ProofpatchConfig,
+}
+
+impl ProofpatchConfigBuilder {
+    pub fn max_results(mut self, n: usize) -> Self {
+        self.cfg.research.defaults.max_results = Some(n);
+        self
+    }
+
+    pub fn timeout_ms(mut self, ms: u64) -> Self {
+        self.cfg.research.defaults.timeout_ms = Some(ms);
+        self
+    }
+
+    pub fn llm_summary(mut self, on: bool) -> Self {
+        self.cfg.research.defaults.llm_summary = Some(on);
+        self
+    }
+
+    pub fn llm_timeout_s(mut self, s: u64) -> Self {
+        self.cfg.research.defaults.llm_timeout_s = Some(s);
+        self
+    }
+
+    pub fn research_defaults(mut self, d: ResearchDefaults) -> Self {
+        self.cfg.research.defaults = d;
+        self
+    }
+
+    pub fn tree_search(mut self, policy: TreeSearchPolicy) -> Self {
+        self.cfg.research.defaults.tree_search = Some(policy);
+        self
+    }
+
+    pub fn preset(mut self, name: impl Into<String>, preset: ResearchPreset) -> Self {
+        self.cfg.research.presets.insert(name.into(), preset);
+        self
+    }
+
+    pub fn llm(mut self, llm: LlmConfig) -> Self {
+        self.cfg.llm = llm;
+        self
+    }
+
+    pub fn budget(mut self, budget: BudgetConfig) -> Self {
+        self.cfg.budget = budget;
+        self
+    }
+
+    pub fn embeddings(mut self, embeddings: EmbeddingsConfig) -> Self {
+        self.cfg.embeddings = embeddings;
+        self
+    }
+
+    pub fn prompt_path(mut self, dir: impl Into<String>) -> Self {
+        self.cfg.prompts.paths.push(dir.into());
+        self
+    }
+
+    pub fn hint_pack(mut self, name: impl Into<String>, pack: HintPack) -> Self {
+        self.cfg.hints.packs.insert(name.into(), pack);
+        self
+    }
+
+    pub fn enable_hint_pack(mut self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        if !self.cfg.hints.defaults.enabled_packs.contains(&name) {
+            self.cfg.hints.defaults.enabled_packs.push(name);
+        }
+        self
+    }
+
+    /// Overlay a whole config (e.g. one loaded from disk) using `ProofpatchConfig::merge`.
+    pub fn merge(mut self, other: ProofpatchConfig) -> Self {
+        self.cfg.merge(other);
+        self
+    }
+
+    pub fn build(self) -> ProofpatchConfig {
+        self.cfg
+    }
+}
+
+impl ResearchPreset {
+    /// A preset with only a query set; every other knob falls back to defaults.
+    pub fn new(query: impl Into<String>) -> Self {
+        Self {
+            query: query.into(),
+            must_include_any: Vec::new(),
+            must_include_all: Vec::new(),
+            must_exclude: Vec::new(),
+            include_regex: Vec::new(),
+            exclude_regex: Vec::new(),
+            backends: None,
+            max_results: None,
+            timeout_ms: None,
+            llm_summary: None,
+            llm_timeout_s: None,
+            llm_summary_kind: None,
+            llm_max_top: None,
+            llm_max_list_items: None,
+            llm_max_str_chars: None,
+            llm_chunk_tokens: None,
+            cache: None,
+            cache_ttl_s: None,
+            tree_search: None,
+        }
+    }
+}
+
 pub fn config_path(repo_root: &Path) -> PathBuf {
     repo_root.join("proofpatch.toml")
 }
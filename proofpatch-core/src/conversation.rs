@@ -0,0 +1,133 @@
+//! Multi-turn repair conversations.
+//!
+//! Instead of re-rendering the `repair` prompt from scratch every round, the repair loop (with
+//! `RepairOptions::multi_turn`) keeps one conversation per declaration: the first turn is the
+//! rendered prompt, and each later turn reports how the model's previous candidates fared (the
+//! first compile error and its class, and for a multi-step script the failing step with the goals
+//! before it, from `replay`) and asks for a revision. The model's replies stay in the transcript,
+//! so it revises its own attempts rather than starting over.
+//!
+//! Older (reply, feedback) pairs are dropped first when the transcript outgrows its token budget;
+//! the first turn, which carries the declaration context, is always kept.
+
+use crate::diagnostics::ErrorClass;
+use crate::llm::provider::ChatMessage;
+use crate::prompt_context::estimate_tokens;
+
+/// A candidate that did not verify, as reported back to the model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedCandidate {
+    pub candidate: String,
+    pub error: Option<String>,
+    pub error_class: Option<ErrorClass>,
+    /// The failing step and the goals before it (`replay::ReplayReport::feedback`).
+    pub step: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RepairConversation {
+    pub system: String,
+    /// Completed turns: user, assistant, user, assistant, …
+    pub turns: Vec<ChatMessage>,
+    /// Token budget for `turns` as sent (`history`).
+    pub max_tokens: usize,
+}
+
+impl RepairConversation {
+    pub fn new(system: &str, max_tokens: usize) -> Self {
+        Self {
+            system: system.to_string(),
+            turns: Vec::new(),
+            max_tokens,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    /// Append a finished exchange.
+    pub fn record(&mut self, user: &str, reply: &str) {
+        self.turns.push(ChatMessage::user(user));
+        self.turns.push(ChatMessage::assistant(reply));
+    }
+
+    /// The turns to send before the next user message: the first exchange, then as many of the
+    /// most recent exchanges as fit in `max_tokens`.
+    pub fn history(&self) -> Vec<ChatMessage> {
+        let pairs: Vec<&[ChatMessage]> = self.turns.chunks(2).collect();
+        let Some((first, rest)) = pairs.split_first() else {
+            return Vec::new();
+        };
+        let cost = |p: &[ChatMessage]| p.iter().map(|m| estimate_tokens(&m.content)).sum::<usize>();
+        let mut budget = self.max_tokens.saturating_sub(cost(first));
+        let mut kept: Vec<&[ChatMessage]> = Vec::new();
+        for p in rest.iter().rev() {
+            let c = cost(p);
+            if c > budget {
+                break;
+            }
+            budget -= c;
+            kept.push(p);
+        }
+        first
+            .iter()
+            .chain(kept.into_iter().rev().flatten())
+            .cloned()
+            .collect()
+    }
+}
+
+/// The follow-up turn after `failed` candidates did not verify.
+pub fn feedback_message(failed: &[FailedCandidate]) -> String {
+    let mut out = String::from("None of those candidates verified. Lean reported:\n");
+    for (i, f) in failed.iter().enumerate() {
+        out.push_str(&format!(
+            "\n{}. ```lean\n{}\n```\n",
+            i + 1,
+            f.candidate.trim()
+        ));
+        let class = f
+            .error_class
+            .map(|c| format!(" [{}]", c.as_str()))
+            .unwrap_or_default();
+        match &f.error {
+            Some(e) => out.push_str(&format!("   error{class}: {}\n", e.trim())),
+            None => out.push_str("   failed without an error message\n"),
+        }
+        if let Some(step) = &f.step {
+            out.push_str(&format!("   failing step: {step}\n"));
+        }
+    }
+    out.push_str(
+        "\nRevise your proof to address these errors. Reply in the same format as before, with new candidates only.",
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_first_exchange_and_latest_that_fit() {
+        let mut c = RepairConversation::new("sys", 60);
+        c.record(&"context ".repeat(20), "by simp");
+        c.record(&"x".repeat(200), "by ring");
+        c.record("second feedback", "by omega");
+        let h = c.history();
+        assert_eq!(h.len(), 4);
+        assert_eq!(h[1].content, "by simp");
+        assert_eq!(h[3].content, "by omega");
+
+        let msg = feedback_message(&[FailedCandidate {
+            candidate: "by omega".to_string(),
+            error: Some("omega could not prove the goal".to_string()),
+            error_class: Some(ErrorClass::TacticFailed),
+            step: Some("step 1 `omega` failed; goals before it: ⊢ x * y = y * x".to_string()),
+        }]);
+        assert!(msg.contains("1. ```lean\nby omega\n```"));
+        assert!(msg.contains("error [tactic_failed]: omega could not prove"));
+        assert!(msg.contains("failing step: step 1"));
+    }
+}
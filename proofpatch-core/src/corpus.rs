@@ -0,0 +1,553 @@
+//! Declaration corpus: names, statements, and docstrings of a project's declarations.
+//!
+//! Two extractors feed the same record type:
+//! - `extract_from_environment` elaborates a small Lean meta-script (`meta_script`) that imports
+//!   the built project, walks `env.constants`, and writes one JSON line per declaration
+//!   (pretty-printed type, docstring, defining module). This needs the `.olean`s to exist.
+//! - `extract_from_source` parses `.lean` files directly (header, statement, `/-- … -/` docstring,
+//!   body). It works on unbuilt trees and is the only source of file/line/body.
+//!
+//! `merge` joins both by (module, name). The store is a JSONL file
+//! (`.generated/proofpatch-corpus/decls.jsonl`) that `Corpus::query`, `Corpus::stats`, and
+//! `export_finetune` read back.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeclOrigin {
+    /// From the elaborated environment (meta-script).
+    Environment,
+    /// From parsing `.lean` source.
+    Source,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorpusDecl {
+    pub name: String,
+    /// `theorem`, `def`, `instance`, `structure`, `inductive`, … (environment records use the
+    /// constant kind: `theorem`, `def`, `axiom`, `inductive`, `constructor`, `opaque`, …).
+    pub kind: String,
+    /// Binders and type (source), or the pretty-printed type (environment).
+    pub statement: String,
+    #[serde(default)]
+    pub doc: Option<String>,
+    #[serde(default)]
+    pub module: Option<String>,
+    #[serde(default)]
+    pub file: Option<String>,
+    /// 1-based line of the declaration header.
+    #[serde(default)]
+    pub line: Option<usize>,
+    /// Source text after `:=` (or from `where`), when extracted from source.
+    #[serde(default)]
+    pub body: Option<String>,
+    pub origin: DeclOrigin,
+}
+
+fn header_regex() -> Result<regex::Regex, String> {
+    regex::Regex::new(
+        r"^(?:@\[[^\]]*\]\s*)*(?:(?:private|protected|noncomputable|unsafe|partial|nonrec)\s+)*(theorem|lemma|def|abbrev|instance|structure|inductive|class|axiom|opaque)\s+([^\s:({\[]+)",
+    )
+    .map_err(|e| format!("invalid corpus header regex: {e}"))
+}
+
+/// The `/-- … -/` docstring that ends right above line `i` (attribute-only lines may intervene).
+fn docstring_above(lines: &[&str], i: usize) -> Option<String> {
+    let mut j = i;
+    while j > 0 && lines[j - 1].trim_start().starts_with("@[") {
+        j -= 1;
+    }
+    if j == 0 || !lines[j - 1].trim_end().ends_with("-/") {
+        return None;
+    }
+    let end = j - 1;
+    let start = (0..=end)
+        .rev()
+        .take(200)
+        .find(|&k| lines[k].trim_start().starts_with("/--"))?;
+    let raw = lines[start..=end].join("\n");
+    let raw = raw.trim();
+    let inner = raw.strip_prefix("/--")?.strip_suffix("-/")?;
+    let doc = inner
+        .lines()
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string();
+    (!doc.is_empty()).then_some(doc)
+}
+
+/// Extract declarations from one file's text.
+pub fn extract_from_source(
+    text: &str,
+    module: Option<&str>,
+    file: Option<&str>,
+) -> Vec<CorpusDecl> {
+    let Ok(header) = header_regex() else {
+        return Vec::new();
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out = Vec::new();
+    let mut i = 0usize;
+    while i < lines.len() {
+        let Some(caps) = header.captures(lines[i]) else {
+            i += 1;
+            continue;
+        };
+        let kind = caps.get(1).map(|m| m.as_str()).unwrap_or("").to_string();
+        let name = caps.get(2).map(|m| m.as_str()).unwrap_or("").to_string();
+        let header_end = caps.get(0).map(|m| m.end()).unwrap_or(0);
+
+        // The header owns every following line until the next non-indented line.
+        let mut end = i + 1;
+        while end < lines.len() {
+            let l = lines[end];
+            if !l.trim().is_empty() && !l.starts_with([' ', '\t']) && !l.starts_with('|') {
+                break;
+            }
+            end += 1;
+        }
+        let decl_text = {
+            let mut s = lines[i][header_end..].to_string();
+            for l in &lines[i + 1..end] {
+                s.push('\n');
+                s.push_str(l);
+            }
+            s
+        };
+        let (stmt, body) = match crate::find_top_level_colon_eq(&decl_text) {
+            Some(k) => (&decl_text[..k], Some(decl_text[k + 2..].trim())),
+            None => match decl_text.find(" where").or_else(|| decl_text.find("\n  |")) {
+                Some(k) => (&decl_text[..k], Some(decl_text[k..].trim())),
+                None => (decl_text.as_str(), None),
+            },
+        };
+        let statement = stmt.split_whitespace().collect::<Vec<_>>().join(" ");
+        out.push(CorpusDecl {
+            name,
+            kind: if kind == "lemma" {
+                "theorem".to_string()
+            } else {
+                kind
+            },
+            statement,
+            doc: docstring_above(&lines, i),
+            module: module.map(str::to_string),
+            file: file.map(str::to_string),
+            line: Some(i + 1),
+            body: body.filter(|b| !b.is_empty()).map(str::to_string),
+            origin: DeclOrigin::Source,
+        });
+        i = end;
+    }
+    out
+}
+
+/// Extract declarations from every `.lean` file under `root` (module names relative to `root`).
+pub fn extract_from_source_tree(root: &Path) -> Vec<CorpusDecl> {
+    let mut out = Vec::new();
+    for rel in crate::scan::list_lean_files(root) {
+        if rel.starts_with("lakefile") {
+            continue;
+        }
+        let Ok(text) = std::fs::read_to_string(root.join(&rel)) else {
+            continue;
+        };
+        let module = crate::module_name_from_file_rel(&rel);
+        out.extend(extract_from_source(&text, module.as_deref(), Some(&rel)));
+    }
+    out
+}
+
+/// Root modules of the project: top-level `Foo.lean` files (excluding the lakefile).
+pub fn default_roots(repo_root: &Path) -> Vec<String> {
+    let Ok(rd) = std::fs::read_dir(repo_root) else {
+        return Vec::new();
+    };
+    let mut roots: Vec<String> = rd
+        .flatten()
+        .filter_map(|e| e.file_name().to_str().map(str::to_string))
+        .filter_map(|n| n.strip_suffix(".lean").map(str::to_string))
+        .filter(|n| n != "lakefile" && !n.is_empty())
+        .collect();
+    roots.sort();
+    roots
+}
+
+/// Lean meta-script that writes one JSON object per declaration under `roots` to `out_path`.
+pub fn meta_script(roots: &[String], out_path: &Path) -> String {
+    let imports: String = roots.iter().map(|r| format!("import {r}\n")).collect();
+    let names = roots
+        .iter()
+        .map(|r| format!("`{r}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let path = serde_json::to_string(&out_path.display().to_string())
+        .unwrap_or_else(|_| "\"decls.jsonl\"".to_string());
+    format!(
+        r#"import Lean
+{imports}
+open Lean Elab Command Meta
+
+#eval show CommandElabM Unit from do
+  let env ← getEnv
+  let roots : Array Name := #[{names}]
+  let h ← IO.FS.Handle.mk {path} IO.FS.Mode.write
+  for (n, ci) in env.constants.map₁.toList do
+    if n.isInternal then continue
+    let some idx := env.getModuleIdxFor? n | continue
+    let mod := env.header.moduleNames[idx.toNat]!
+    unless roots.any (·.isPrefixOf mod) do continue
+    let kind := match ci with
+      | .thmInfo _ => "theorem" | .defnInfo _ => "def" | .axiomInfo _ => "axiom"
+      | .inductInfo _ => "inductive" | .ctorInfo _ => "constructor"
+      | .recInfo _ => "recursor" | .opaqueInfo _ => "opaque" | .quotInfo _ => "quot"
+    let ty ← liftTermElabM <| ppExpr ci.type
+    let doc ← findDocString? env n
+    let j := Json.mkObj [("name", toJson n.toString), ("kind", toJson kind),
+      ("statement", toJson (toString ty)), ("doc", toJson doc),
+      ("module", toJson mod.toString), ("origin", toJson "environment")]
+    h.putStrLn j.compress
+"#
+    )
+}
+
+/// Parse the meta-script's JSONL output (malformed lines are skipped).
+pub fn parse_meta_output(text: &str) -> Vec<CorpusDecl> {
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|l| serde_json::from_str::<CorpusDecl>(l).ok())
+        .map(|mut d| {
+            d.statement = d.statement.split_whitespace().collect::<Vec<_>>().join(" ");
+            d
+        })
+        .collect()
+}
+
+/// Run the meta-script against the built project (modules under `roots`).
+pub async fn extract_from_environment(
+    repo_root: &Path,
+    roots: &[String],
+    timeout: Duration,
+) -> Result<Vec<CorpusDecl>, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let roots: Vec<String> = if roots.is_empty() {
+        default_roots(&repo_root)
+    } else {
+        roots.to_vec()
+    };
+    if roots.is_empty() {
+        return Err("no root modules found (pass --root <Module>)".to_string());
+    }
+    let td = tempfile::tempdir().map_err(|e| format!("failed to create temp dir: {e}"))?;
+    let out_path = td.path().join("decls.jsonl");
+    let v = crate::verify_lean_text(&repo_root, &meta_script(&roots, &out_path), timeout).await?;
+    let text = std::fs::read_to_string(&out_path).unwrap_or_default();
+    if !v.ok && text.is_empty() {
+        let diags = crate::diagnostics::parse_diagnostics(&format!("{}\n{}", v.stdout, v.stderr));
+        let msg = crate::diagnostics::first_error(&diags)
+            .map(|d| d.headline().to_string())
+            .unwrap_or_else(|| "meta-script failed".to_string());
+        return Err(format!("environment extraction failed: {msg}"));
+    }
+    Ok(parse_meta_output(&text))
+}
+
+/// Join environment and source records by (module, name).
+///
+/// Environment records win for `statement`/`kind`; source fills `file`, `line`, `body`, and a
+/// missing `doc`. Source names are unqualified, so an environment name `A.B.c` also matches a
+/// source `B.c` or `c` in the same module. Source records with no match are kept.
+pub fn merge(env: Vec<CorpusDecl>, source: Vec<CorpusDecl>) -> Vec<CorpusDecl> {
+    let mut by_key: HashMap<(Option<String>, String), usize> = HashMap::new();
+    for (i, d) in source.iter().enumerate() {
+        by_key
+            .entry((d.module.clone(), d.name.clone()))
+            .or_insert(i);
+    }
+    let mut used = vec![false; source.len()];
+    let mut out: Vec<CorpusDecl> = env
+        .into_iter()
+        .map(|mut d| {
+            let parts: Vec<&str> = d.name.split('.').collect();
+            let hit = (0..parts.len())
+                .map(|k| parts[k..].join("."))
+                .find_map(|suffix| by_key.get(&(d.module.clone(), suffix)).copied());
+            if let Some(i) = hit.filter(|&i| !used[i]) {
+                used[i] = true;
+                let s = &source[i];
+                d.file = s.file.clone();
+                d.line = s.line;
+                d.body = s.body.clone();
+                if d.doc.is_none() {
+                    d.doc = s.doc.clone();
+                }
+            }
+            d
+        })
+        .collect();
+    out.extend(
+        source
+            .into_iter()
+            .zip(used)
+            .filter(|(_, u)| !u)
+            .map(|(d, _)| d),
+    );
+    out
+}
+
+/// Default corpus location for a repo.
+pub fn default_corpus_path(repo_root: &Path) -> PathBuf {
+    repo_root
+        .join(".generated")
+        .join("proofpatch-corpus")
+        .join("decls.jsonl")
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CorpusQuery {
+    /// Case-insensitive substring of the name.
+    pub name: Option<String>,
+    /// All whitespace-separated terms must occur in name, statement, or doc (case-insensitive).
+    pub text: Option<String>,
+    pub kind: Option<String>,
+    /// Module prefix (`Mathlib.Data`).
+    pub module: Option<String>,
+    pub with_doc: bool,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorpusStats {
+    pub total: usize,
+    pub with_doc: usize,
+    pub with_body: usize,
+    pub by_kind: BTreeMap<String, usize>,
+    pub by_origin: BTreeMap<String, usize>,
+    /// Largest modules first (top 20).
+    pub top_modules: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Corpus {
+    pub decls: Vec<CorpusDecl>,
+}
+
+impl Corpus {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+        }
+        let f = std::fs::File::create(path)
+            .map_err(|e| format!("failed to create {}: {e}", path.display()))?;
+        let mut w = std::io::BufWriter::new(f);
+        for d in &self.decls {
+            let line = serde_json::to_string(d).map_err(|e| format!("serialize: {e}"))?;
+            writeln!(w, "{line}").map_err(|e| format!("write {}: {e}", path.display()))?;
+        }
+        w.flush()
+            .map_err(|e| format!("write {}: {e}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let f = std::fs::File::open(path)
+            .map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+        let mut decls = Vec::new();
+        for (i, line) in BufReader::new(f).lines().enumerate() {
+            let line = line.map_err(|e| format!("read {}: {e}", path.display()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            decls.push(
+                serde_json::from_str(&line)
+                    .map_err(|e| format!("{}:{}: invalid record: {e}", path.display(), i + 1))?,
+            );
+        }
+        Ok(Self { decls })
+    }
+
+    pub fn query(&self, q: &CorpusQuery) -> Vec<&CorpusDecl> {
+        let name = q.name.as_ref().map(|s| s.to_lowercase());
+        let terms: Vec<String> = q
+            .text
+            .as_deref()
+            .unwrap_or("")
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect();
+        let mut hits: Vec<&CorpusDecl> = self
+            .decls
+            .iter()
+            .filter(|d| q.kind.as_ref().is_none_or(|k| &d.kind == k))
+            .filter(|d| {
+                q.module.as_ref().is_none_or(|m| {
+                    d.module
+                        .as_deref()
+                        .is_some_and(|dm| dm == m || dm.starts_with(&format!("{m}.")))
+                })
+            })
+            .filter(|d| !q.with_doc || d.doc.is_some())
+            .filter(|d| {
+                name.as_ref()
+                    .is_none_or(|n| d.name.to_lowercase().contains(n))
+            })
+            .filter(|d| {
+                if terms.is_empty() {
+                    return true;
+                }
+                let hay = format!(
+                    "{} {} {}",
+                    d.name,
+                    d.statement,
+                    d.doc.as_deref().unwrap_or("")
+                )
+                .to_lowercase();
+                terms.iter().all(|t| hay.contains(t))
+            })
+            .collect();
+        // Shorter names first: the closest match to a name query is usually the least qualified.
+        hits.sort_by(|a, b| a.name.len().cmp(&b.name.len()).then(a.name.cmp(&b.name)));
+        hits.truncate(q.limit.unwrap_or(50));
+        hits
+    }
+
+    pub fn stats(&self) -> CorpusStats {
+        let mut by_kind = BTreeMap::new();
+        let mut by_origin = BTreeMap::new();
+        let mut by_module: HashMap<String, usize> = HashMap::new();
+        for d in &self.decls {
+            *by_kind.entry(d.kind.clone()).or_insert(0) += 1;
+            let o = match d.origin {
+                DeclOrigin::Environment => "environment",
+                DeclOrigin::Source => "source",
+            };
+            *by_origin.entry(o.to_string()).or_insert(0) += 1;
+            *by_module
+                .entry(d.module.clone().unwrap_or_default())
+                .or_insert(0) += 1;
+        }
+        let mut top_modules: Vec<(String, usize)> = by_module.into_iter().collect();
+        top_modules.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        top_modules.truncate(20);
+        CorpusStats {
+            total: self.decls.len(),
+            with_doc: self.decls.iter().filter(|d| d.doc.is_some()).count(),
+            with_body: self.decls.iter().filter(|d| d.body.is_some()).count(),
+            by_kind,
+            by_origin,
+            top_modules,
+        }
+    }
+}
+
+/// Fine-tuning records (`{"prompt", "completion", "name"}`).
+///
+/// - `"statement"`: docstring → declaration header (autoformalization pairs; needs a doc).
+/// - `"proof"`: theorem header → body (needs a source body).
+pub fn export_finetune(
+    decls: &[CorpusDecl],
+    format: &str,
+) -> Result<Vec<serde_json::Value>, String> {
+    let header = |d: &CorpusDecl| {
+        let sep = if d.statement.starts_with(':') {
+            ""
+        } else {
+            " "
+        };
+        format!("{} {}{sep}{}", d.kind, d.name, d.statement)
+    };
+    match format {
+        "statement" => Ok(decls
+            .iter()
+            .filter_map(|d| {
+                let doc = d.doc.as_ref()?;
+                Some(serde_json::json!({
+                    "name": d.name,
+                    "prompt": doc,
+                    "completion": header(d),
+                }))
+            })
+            .collect()),
+        "proof" => Ok(decls
+            .iter()
+            .filter(|d| d.kind == "theorem")
+            .filter_map(|d| {
+                let body = d.body.as_ref()?;
+                Some(serde_json::json!({
+                    "name": d.name,
+                    "prompt": format!("{} :=", header(d)),
+                    "completion": body,
+                }))
+            })
+            .collect()),
+        other => Err(format!(
+            "unknown export format: {other} (expected statement|proof)"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_source_decls_and_merges_with_environment() {
+        let text = "\
+import Mathlib
+
+namespace Foo
+
+/-- Adding zero
+does nothing. -/
+@[simp]
+theorem add_zero' (n : Nat) :
+    n + 0 = n := by
+  simp
+
+def double (n : Nat) : Nat := 2 * n
+
+structure Pt where
+  x : Nat
+
+end Foo
+";
+        let ds = extract_from_source(text, Some("Foo.Basic"), Some("Foo/Basic.lean"));
+        let names: Vec<&str> = ds.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, vec!["add_zero'", "double", "Pt"]);
+        assert_eq!(ds[0].statement, "(n : Nat) : n + 0 = n");
+        assert_eq!(ds[0].doc.as_deref(), Some("Adding zero\ndoes nothing."));
+        assert_eq!(ds[0].body.as_deref(), Some("by\n  simp"));
+        assert_eq!(ds[0].line, Some(8));
+        assert_eq!(ds[2].kind, "structure");
+
+        let env = parse_meta_output(
+            r#"{"name":"Foo.add_zero'","kind":"theorem","statement":"∀ (n : ℕ), n + 0 = n","doc":null,"module":"Foo.Basic","origin":"environment"}
+not json
+"#,
+        );
+        let merged = merge(env, ds);
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[0].name, "Foo.add_zero'");
+        assert_eq!(merged[0].line, Some(8));
+        assert_eq!(merged[0].doc.as_deref(), Some("Adding zero\ndoes nothing."));
+
+        let c = Corpus { decls: merged };
+        let q = CorpusQuery {
+            text: Some("n + 0".into()),
+            with_doc: true,
+            ..Default::default()
+        };
+        assert_eq!(c.query(&q).len(), 1);
+        assert_eq!(c.stats().by_kind.get("theorem"), Some(&1));
+        let rows = export_finetune(&c.decls, "proof").unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["completion"], "by\n  simp");
+    }
+}
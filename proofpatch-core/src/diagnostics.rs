@@ -0,0 +1,394 @@
+//! Structured diagnostics from `lake build` / `lake env lean` output.
+//!
+//! Accepts the three shapes we see in practice:
+//! - plain Lean: `File.lean:3:2: error: unknown identifier 'x'` (message continues on the
+//!   following lines, e.g. the goals of an `unsolved goals` error);
+//! - Lake-prefixed: `error: File.lean:3:2: unknown identifier 'x'`;
+//! - `lean --json`: one JSON object per line with `pos`/`endPos` (the only source of ranges).
+//!
+//! Each diagnostic carries an `ErrorClass` so callers (the repair loop, failure clustering) can
+//! react to the kind of failure instead of matching on message text themselves.
+//! `parse_first_error_loc` remains the cheap "where is the first error" helper.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Severity {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "error" => Some(Self::Error),
+            "warning" => Some(Self::Warning),
+            "info" | "information" => Some(Self::Info),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    UnknownIdentifier,
+    UnknownConstant,
+    UnknownTactic,
+    TypeMismatch,
+    UnsolvedGoals,
+    DeterministicTimeout,
+    MaxRecursion,
+    FailedToSynthesize,
+    UniverseLevel,
+    ParseError,
+    NoProgress,
+    TacticFailed,
+    DeclarationUsesSorry,
+    Linter,
+    Other,
+}
+
+impl ErrorClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::UnknownIdentifier => "unknown_identifier",
+            Self::UnknownConstant => "unknown_constant",
+            Self::UnknownTactic => "unknown_tactic",
+            Self::TypeMismatch => "type_mismatch",
+            Self::UnsolvedGoals => "unsolved_goals",
+            Self::DeterministicTimeout => "deterministic_timeout",
+            Self::MaxRecursion => "max_recursion",
+            Self::FailedToSynthesize => "failed_to_synthesize",
+            Self::UniverseLevel => "universe_level",
+            Self::ParseError => "parse_error",
+            Self::NoProgress => "no_progress",
+            Self::TacticFailed => "tactic_failed",
+            Self::DeclarationUsesSorry => "declaration_uses_sorry",
+            Self::Linter => "linter",
+            Self::Other => "other",
+        }
+    }
+
+    /// Classify a message (first line is what matters; linter notes sit at the end).
+    pub fn classify(message: &str) -> Self {
+        let m = message.to_lowercase();
+        let head = m.lines().next().unwrap_or("");
+        if head.contains("unknown identifier") {
+            Self::UnknownIdentifier
+        } else if head.contains("unknown constant") || head.contains("unknown declaration") {
+            Self::UnknownConstant
+        } else if head.contains("unknown tactic") {
+            Self::UnknownTactic
+        } else if head.contains("deterministic") && head.contains("timeout")
+            || head.contains("maximum number of heartbeats")
+        {
+            Self::DeterministicTimeout
+        } else if head.contains("maximum recursion depth") {
+            Self::MaxRecursion
+        } else if head.contains("declaration uses 'sorry'")
+            || head.contains("declaration uses `sorry`")
+        {
+            Self::DeclarationUsesSorry
+        } else if head.contains("type mismatch")
+            || head.contains("application type mismatch")
+            || head.contains("has type") && m.contains("but is expected to have type")
+        {
+            Self::TypeMismatch
+        } else if head.contains("unsolved goals") {
+            Self::UnsolvedGoals
+        } else if head.contains("failed to synthesize") {
+            Self::FailedToSynthesize
+        } else if head.contains("universe") {
+            Self::UniverseLevel
+        } else if head.starts_with("unexpected ") || head.starts_with("expected ") {
+            Self::ParseError
+        } else if head.contains("made no progress") {
+            Self::NoProgress
+        } else if head.contains("failed")
+            || head.contains("could not prove")
+            || head.contains("could not close")
+        {
+            Self::TacticFailed
+        } else if m.contains("set_option linter.") || m.contains("linter.") && m.contains("false") {
+            Self::Linter
+        } else {
+            Self::Other
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub file: String,
+    /// 1-based line.
+    pub line: usize,
+    /// Column as reported by Lean (0-based).
+    pub col: usize,
+    /// End of the range (only available from `--json` output).
+    #[serde(default)]
+    pub end_line: Option<usize>,
+    #[serde(default)]
+    pub end_col: Option<usize>,
+    pub severity: Severity,
+    pub class: ErrorClass,
+    /// Full message, including continuation lines (goals, expected/actual types).
+    pub message: String,
+    /// The offending name for unknown identifier/constant/tactic errors.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn headline(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+
+    fn new(file: String, line: usize, col: usize, severity: Severity, message: String) -> Self {
+        Self {
+            file,
+            line,
+            col,
+            end_line: None,
+            end_col: None,
+            severity,
+            class: ErrorClass::Other,
+            message,
+            name: None,
+        }
+    }
+
+    fn finish(mut self) -> Self {
+        self.message = self.message.trim_end().to_string();
+        self.class = ErrorClass::classify(&self.message);
+        self.name = match self.class {
+            ErrorClass::UnknownIdentifier
+            | ErrorClass::UnknownConstant
+            | ErrorClass::UnknownTactic => quoted_name(self.headline()),
+            _ => None,
+        };
+        self
+    }
+}
+
+/// First `'name'` or `` `name` `` in a message line.
+fn quoted_name(s: &str) -> Option<String> {
+    let i = s.find(['\'', '`'])?;
+    let q = s[i..].chars().next()?;
+    let rest = &s[i + 1..];
+    let j = rest.find(q)?;
+    let name = rest[..j].trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// `<path>:<line>:<col>: <rest>`, split at the first `:<digits>:<digits>:` so Windows drive
+/// letters (`C:\...`) stay in the path.
+fn split_loc(s: &str) -> Option<(String, usize, usize, &str)> {
+    let bytes = s.as_bytes();
+    let mut i = 0usize;
+    while let Some(off) = s[i..].find(':') {
+        let a = i + off;
+        let digits1 = s[a + 1..].bytes().take_while(u8::is_ascii_digit).count();
+        let b = a + 1 + digits1;
+        if digits1 > 0 && bytes.get(b) == Some(&b':') {
+            let digits2 = s[b + 1..].bytes().take_while(u8::is_ascii_digit).count();
+            let c = b + 1 + digits2;
+            if digits2 > 0 && bytes.get(c) == Some(&b':') && a > 0 {
+                let line = s[a + 1..b].parse().ok()?;
+                let col = s[b + 1..c].parse().ok()?;
+                return Some((s[..a].to_string(), line, col, s[c + 1..].trim_start()));
+            }
+        }
+        i = a + 1;
+    }
+    None
+}
+
+fn strip_severity(s: &str) -> (Option<Severity>, &str) {
+    for (p, sev) in [
+        ("error:", Severity::Error),
+        ("warning:", Severity::Warning),
+        ("info:", Severity::Info),
+    ] {
+        if let Some(rest) = s.strip_prefix(p) {
+            return (Some(sev), rest.trim_start());
+        }
+    }
+    (None, s)
+}
+
+/// A diagnostic header line, in plain or Lake-prefixed form.
+fn parse_header(line: &str) -> Option<Diagnostic> {
+    let (lake_sev, rest) = strip_severity(line.trim_start());
+    let (file, l, c, msg) = split_loc(rest)?;
+    // A Lake-prefixed path is never empty and never starts with a space.
+    if file.trim().is_empty() || file.starts_with(' ') {
+        return None;
+    }
+    let (sev, msg) = match lake_sev {
+        Some(s) => (s, msg),
+        None => {
+            let (s, m) = strip_severity(msg);
+            // Plain `lean` prints infos without a severity tag.
+            (s.unwrap_or(Severity::Info), m)
+        }
+    };
+    Some(Diagnostic::new(file, l, c, sev, msg.to_string()))
+}
+
+fn parse_json_line(line: &str) -> Option<Diagnostic> {
+    let t = line.trim();
+    if !t.starts_with('{') {
+        return None;
+    }
+    let v: Value = serde_json::from_str(t).ok()?;
+    let pos = v.get("pos")?;
+    let sev = Severity::parse(v.get("severity")?.as_str()?)?;
+    let num = |p: Option<&Value>, k: &str| {
+        p.and_then(|p| p.get(k))
+            .and_then(|x| x.as_u64())
+            .map(|x| x as usize)
+    };
+    let mut d = Diagnostic::new(
+        v.get("fileName")
+            .and_then(|x| x.as_str())
+            .unwrap_or("")
+            .to_string(),
+        num(Some(pos), "line")?,
+        num(Some(pos), "column").unwrap_or(0),
+        sev,
+        v.get("data")
+            .and_then(|x| x.as_str())
+            .unwrap_or("")
+            .to_string(),
+    );
+    d.end_line = num(v.get("endPos"), "line");
+    d.end_col = num(v.get("endPos"), "column");
+    Some(d.finish())
+}
+
+/// Lake progress/status lines that end a multi-line Lean message.
+fn is_lake_status(line: &str) -> bool {
+    let t = line.trim_start();
+    t.starts_with(['✖', '⚠', '✔', 'ℹ'])
+        || t.starts_with("trace:")
+        || t.starts_with("Some required builds logged failures")
+        || t.starts_with("Build completed")
+        || (t.starts_with("error: ") || t.starts_with("warning: ")) && split_loc(t).is_none()
+}
+
+/// Parse all diagnostics from one output stream, in order of appearance.
+pub fn parse_diagnostics(output: &str) -> Vec<Diagnostic> {
+    let mut out: Vec<Diagnostic> = Vec::new();
+    let mut cur: Option<Diagnostic> = None;
+    for raw in output.lines() {
+        if let Some(d) = parse_json_line(raw) {
+            out.extend(cur.take().map(Diagnostic::finish));
+            out.push(d);
+        } else if let Some(d) = parse_header(raw) {
+            out.extend(cur.take().map(Diagnostic::finish));
+            cur = Some(d);
+        } else if is_lake_status(raw) {
+            out.extend(cur.take().map(Diagnostic::finish));
+        } else if let Some(d) = cur.as_mut() {
+            d.message.push('\n');
+            d.message.push_str(raw);
+        }
+    }
+    out.extend(cur.map(Diagnostic::finish));
+    out
+}
+
+/// `parse_diagnostics` over stdout then stderr.
+pub fn parse_diagnostics_from(stdout: &str, stderr: &str) -> Vec<Diagnostic> {
+    let mut out = parse_diagnostics(stdout);
+    out.extend(parse_diagnostics(stderr));
+    out
+}
+
+pub fn first_error(diags: &[Diagnostic]) -> Option<&Diagnostic> {
+    diags.iter().find(|d| d.severity == Severity::Error)
+}
+
+/// Error counts per class (`"type_mismatch" -> 2`, …); warnings are not counted.
+pub fn error_class_counts(diags: &[Diagnostic]) -> BTreeMap<String, usize> {
+    let mut out = BTreeMap::new();
+    for d in diags.iter().filter(|d| d.severity == Severity::Error) {
+        *out.entry(d.class.as_str().to_string()).or_insert(0) += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_and_lake_shapes_with_continuations() {
+        let out = "\
+✖ [3/4] Building Foo.Bar
+error: ././Foo/Bar.lean:3:2: unknown identifier 'Nat.foo'
+error: ././Foo/Bar.lean:7:4: unsolved goals
+n : ℕ
+⊢ n + 0 = n
+Foo/Bar.lean:9:0: warning: declaration uses 'sorry'
+C:\\proj\\Foo.lean:12:8: error: (deterministic) timeout at `whnf`, maximum number of heartbeats (200000) has been reached
+Foo.lean:14:0: Try this: exact h
+error: Lean exited with code 1
+";
+        let d = parse_diagnostics(out);
+        assert_eq!(d.len(), 5);
+        assert_eq!(d[0].file, "././Foo/Bar.lean");
+        assert_eq!((d[0].line, d[0].col), (3, 2));
+        assert_eq!(d[0].class, ErrorClass::UnknownIdentifier);
+        assert_eq!(d[0].name.as_deref(), Some("Nat.foo"));
+        assert_eq!(d[1].class, ErrorClass::UnsolvedGoals);
+        assert!(d[1].message.ends_with("⊢ n + 0 = n"));
+        assert_eq!(d[2].severity, Severity::Warning);
+        assert_eq!(d[2].class, ErrorClass::DeclarationUsesSorry);
+        assert_eq!(d[3].file, "C:\\proj\\Foo.lean");
+        assert_eq!(d[3].class, ErrorClass::DeterministicTimeout);
+        assert_eq!(d[4].severity, Severity::Info);
+        assert_eq!(first_error(&d).map(|e| e.line), Some(3));
+        assert_eq!(error_class_counts(&d).get("unsolved_goals"), Some(&1));
+    }
+
+    #[test]
+    fn parses_json_ranges_and_classifies() {
+        let out = r#"{"severity":"error","pos":{"line":5,"column":10},"endPos":{"line":5,"column":14},"fileName":"A.lean","data":"type mismatch\n  h\nhas type\n  a < b : Prop\nbut is expected to have type\n  a ≤ b : Prop"}"#;
+        let d = parse_diagnostics(out);
+        assert_eq!(d.len(), 1);
+        assert_eq!((d[0].end_line, d[0].end_col), (Some(5), Some(14)));
+        assert_eq!(d[0].class, ErrorClass::TypeMismatch);
+
+        for (msg, class) in [
+            (
+                "failed to synthesize\n  Decidable p",
+                ErrorClass::FailedToSynthesize,
+            ),
+            (
+                "maximum recursion depth has been reached",
+                ErrorClass::MaxRecursion,
+            ),
+            (
+                "linarith failed to find a contradiction",
+                ErrorClass::TacticFailed,
+            ),
+            ("simp made no progress", ErrorClass::NoProgress),
+            (
+                "unexpected token 'at'; expected term",
+                ErrorClass::ParseError,
+            ),
+            (
+                "stuck at solving universe constraint",
+                ErrorClass::UniverseLevel,
+            ),
+        ] {
+            assert_eq!(ErrorClass::classify(msg), class, "{msg}");
+        }
+    }
+}
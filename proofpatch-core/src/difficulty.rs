@@ -0,0 +1,274 @@
+//! Goal difficulty estimates, for ordering repo-wide repair queues so quick wins come first.
+//!
+//! `Features` are read from the declaration's statement (hypotheses, operator mix, size) and,
+//! when the run history (`history`) has earlier runs on the declaration, from those: how often
+//! it was attempted and failed, and whether SMT found its goal LIA-entailed (an `omega` away) or
+//! refuted. `estimate` combines them into one score, higher for harder. `scan::ScanOptions::
+//! by_difficulty` orders a scan's targets by it, easiest first; the order is stable, so targets
+//! of equal difficulty keep their file (or topological) order.
+
+use crate::history::RunRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Features {
+    /// Hypotheses: binders of the statement, or the hypotheses of the last recorded goal.
+    pub hypotheses: usize,
+    /// Statement length in characters.
+    pub size: usize,
+    /// `∀`, `∃`.
+    pub quantifiers: usize,
+    /// `+ - * / % ^` and order relations.
+    pub arithmetic: usize,
+    /// `∑ ∏ ∫`, limits, derivatives, filters.
+    pub analysis: usize,
+    /// Membership, inclusion, unions and intersections.
+    pub sets: usize,
+    /// `↔ ∧ ∨ ¬` and implications.
+    pub logic: usize,
+    /// SMT outcome of the last recorded run (`Some(true)`: LIA-entailed).
+    #[serde(default)]
+    pub smt_entails: Option<bool>,
+    /// Recorded runs on the declaration, and how many of them failed.
+    pub attempts: usize,
+    pub failures: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Difficulty {
+    /// `estimate(&features)`.
+    pub score: f64,
+    pub features: Features,
+}
+
+const ANALYSIS: &[&str] = &[
+    "∑",
+    "∏",
+    "∫",
+    "Tendsto",
+    "deriv",
+    "HasDerivAt",
+    "Continuous",
+    "Differentiable",
+    "lim",
+    "𝓝",
+    "Filter",
+    "atTop",
+    "Measurable",
+];
+const SETS: &[&str] = &["∈", "∉", "⊆", "⊂", "∪", "∩", "ᶜ", "Set.", "Finset."];
+const LOGIC: &[&str] = &["↔", "∧", "∨", "¬", "→"];
+const ARITHMETIC: &[&str] = &["+", "-", "*", "/", "%", "^", "≤", "<", "≥", ">", "∣"];
+
+/// The statement of a declaration: its text up to the `:=` (or `|`, `where`) starting the proof.
+pub fn statement(decl_text: &str) -> &str {
+    let mut depth = 0i32;
+    for (i, c) in decl_text.char_indices() {
+        match c {
+            '(' | '[' | '{' | '⦃' => depth += 1,
+            ')' | ']' | '}' | '⦄' => depth -= 1,
+            _ if depth > 0 => {}
+            ':' if decl_text[i..].starts_with(":=") => return &decl_text[..i],
+            '|' if decl_text[..i].trim_end_matches(' ').ends_with('\n') => {
+                return &decl_text[..i];
+            }
+            'w' if decl_text[i..].starts_with("where") && decl_text[..i].ends_with([' ', '\n']) => {
+                return &decl_text[..i];
+            }
+            _ => {}
+        }
+    }
+    decl_text
+}
+
+/// Names bound in the statement's binder groups before its type, e.g. 3 for
+/// `theorem t (a b : ℕ) (h : a < b) : …`; instance binders count once.
+pub fn binder_count(statement: &str) -> usize {
+    let mut n = 0;
+    let mut depth = 0i32;
+    let mut group = String::new();
+    for c in statement.chars() {
+        match c {
+            '(' | '[' | '{' | '⦃' => {
+                if depth == 0 {
+                    group.clear();
+                }
+                depth += 1;
+            }
+            ')' | ']' | '}' | '⦄' => {
+                depth -= 1;
+                if depth == 0 {
+                    n += match group.split_once(':') {
+                        Some((names, _)) if c != ']' => names.split_whitespace().count().max(1),
+                        _ => 1,
+                    };
+                }
+            }
+            ':' if depth == 0 => break,
+            _ if depth > 0 => group.push(c),
+            _ => {}
+        }
+    }
+    n
+}
+
+/// Hypotheses of a pretty-printed goal: its lines before `⊢`, counting each name.
+fn goal_hypotheses(goal: &str) -> usize {
+    goal.lines()
+        .take_while(|l| !l.trim_start().starts_with('⊢'))
+        .filter_map(|l| l.split_once(" : "))
+        .map(|(names, _)| names.split_whitespace().count())
+        .sum()
+}
+
+fn count_any(text: &str, needles: &[&str]) -> usize {
+    needles.iter().map(|n| text.matches(n).count()).sum()
+}
+
+impl Features {
+    /// From the statement of a declaration.
+    pub fn from_statement(statement: &str) -> Self {
+        // ASCII arrows are implications, not the `-` and `>` of arithmetic.
+        let arith_text = statement.replace("->", " ").replace("=>", " ");
+        Self {
+            hypotheses: binder_count(statement),
+            size: statement
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .chars()
+                .count(),
+            quantifiers: count_any(statement, &["∀", "∃"]),
+            arithmetic: count_any(&arith_text, ARITHMETIC),
+            analysis: count_any(statement, ANALYSIS),
+            sets: count_any(statement, SETS),
+            logic: count_any(statement, LOGIC) + statement.matches("->").count(),
+            ..Default::default()
+        }
+    }
+
+    /// Add what the recorded runs on this declaration tell (oldest first).
+    pub fn with_history(mut self, runs: &[&RunRecord]) -> Self {
+        self.attempts = runs.len();
+        self.failures = runs.iter().filter(|r| !r.ok).count();
+        if let Some(last) = runs.last() {
+            self.smt_entails = last.smt_entails;
+            if let Some(g) = &last.goal {
+                self.hypotheses = self.hypotheses.max(goal_hypotheses(g));
+            }
+        }
+        self
+    }
+}
+
+/// Difficulty of a goal with `f`, higher for harder (0 at the easiest).
+///
+/// Hypotheses and size count logarithmically; quantifiers, analysis and set operations per
+/// occurrence. An LIA-entailed goal is cheap, a refuted one (maybe false as stated) and one
+/// that failed before are not.
+pub fn estimate(f: &Features) -> f64 {
+    let mut d = (1.0 + f.hypotheses as f64).ln()
+        + 0.5 * (1.0 + f.size as f64 / 40.0).ln()
+        + 0.6 * f.quantifiers as f64
+        + 0.05 * f.arithmetic.min(20) as f64
+        + 1.0 * f.analysis as f64
+        + 0.3 * f.sets as f64
+        + 0.2 * f.logic as f64
+        + 1.0 * f.failures as f64;
+    match f.smt_entails {
+        Some(true) => d -= 2.0,
+        Some(false) => d += 1.0,
+        None => {}
+    }
+    d.max(0.0)
+}
+
+/// Runs in `records` by `(file, decl)`, oldest first.
+pub fn runs_by_decl(records: &[RunRecord]) -> HashMap<(String, String), Vec<&RunRecord>> {
+    let mut out: HashMap<(String, String), Vec<&RunRecord>> = HashMap::new();
+    for r in records {
+        out.entry((r.file.clone(), r.decl.clone()))
+            .or_default()
+            .push(r);
+    }
+    for runs in out.values_mut() {
+        runs.sort_by_key(|r| r.added_at);
+    }
+    out
+}
+
+/// Difficulty of `decl_name` in `text` (the contents of `file_rel`), given the recorded runs.
+pub fn assess(
+    text: &str,
+    file_rel: &str,
+    decl_name: &str,
+    runs: &HashMap<(String, String), Vec<&RunRecord>>,
+) -> Option<Difficulty> {
+    let (s, e) = crate::patching::decl_byte_range(text, decl_name).ok()?;
+    let mut features = Features::from_statement(statement(&text[s..e]));
+    if let Some(rs) = runs.get(&(file_rel.to_string(), decl_name.to_string())) {
+        features = features.with_history(rs);
+    }
+    Some(Difficulty {
+        score: estimate(&features),
+        features,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features_and_ordering() {
+        let easy = "theorem e (n : ℕ) (h : n < 3) : n + 1 ≤ 3 := by\n  sorry";
+        let hard = "theorem h {f : ℕ → ℝ} (hf : ∀ ε > 0, ∃ N, ∀ n ≥ N, |f n| < ε) :\n    \
+                    Tendsto f atTop (𝓝 0) := by\n  sorry";
+        assert_eq!(
+            statement(easy),
+            "theorem e (n : ℕ) (h : n < 3) : n + 1 ≤ 3 "
+        );
+        assert_eq!(
+            binder_count("theorem t (a b : ℕ) [Fintype α] (h : a < b) : a ≤ b"),
+            4
+        );
+
+        let fe = Features::from_statement(statement(easy));
+        let fh = Features::from_statement(statement(hard));
+        assert_eq!((fe.hypotheses, fe.quantifiers, fe.analysis), (2, 0, 0));
+        assert_eq!((fh.hypotheses, fh.quantifiers), (2, 3));
+        assert!(fh.analysis >= 3);
+        assert!(estimate(&fe) < estimate(&fh));
+
+        // History: an entailed goal gets cheaper, repeated failures make it harder.
+        let run = |ok: bool, smt: Option<bool>, at: u64| RunRecord {
+            file: "A.lean".into(),
+            decl: "e".into(),
+            goal: Some("n : ℕ\nh : n < 3\n⊢ n + 1 ≤ 3".into()),
+            context: String::new(),
+            prompt: None,
+            patch: None,
+            patch_source: None,
+            ok,
+            stop_reason: "max_rounds".into(),
+            verifications: 3,
+            added_at: at,
+            attempts: Vec::new(),
+            smt_entails: smt,
+        };
+        let records = vec![run(false, None, 2), run(false, Some(true), 1)];
+        let runs = runs_by_decl(&records);
+        let d = assess(easy, "A.lean", "e", &runs).unwrap();
+        assert_eq!((d.features.attempts, d.features.failures), (2, 2));
+        // The latest run (no SMT outcome) decides.
+        assert_eq!(d.features.smt_entails, None);
+        assert!(d.score > estimate(&fe));
+        let entailed = Features {
+            smt_entails: Some(true),
+            ..fe.clone()
+        };
+        assert!(estimate(&entailed) < estimate(&fe));
+        assert!(assess(easy, "A.lean", "missing", &runs).is_none());
+    }
+}
@@ -0,0 +1,357 @@
+//! Strategy ensemble: a bandit that splits each goal's verification budget among strategies.
+//!
+//! Instead of one repair loop mixing every candidate source in a fixed order, the controller
+//! runs one strategy (`Arm`) at a time on the goal, each a repair loop restricted to that
+//! strategy's sources (`RepairOptions::sources`) with a slice of the budget:
+//! - `portfolio`: the fixed heuristics, goal-derived and strategy seeds, renames, reuse;
+//! - `library_search`: `exact?`, `rw?`, `apply?` at the hole (`RepairOptions::library_search`);
+//! - `smt`: SMT-guided `calc` chains and `omega` stepping stones;
+//! - `llm_single`: one LLM round;
+//! - `llm_iterative`: LLM rounds with the errors fed back in one conversation.
+//!
+//! The next arm is the one with the highest upper confidence bound on successes per
+//! verification (`Bandit::index`): its success rate, shrunk towards a built-in prior as in
+//! `priors`, plus an exploration bonus that shrinks with the pulls it has had, divided by its
+//! mean cost. `Bandit::from_history` counts pulls from the run history (`history`), each
+//! recorded run contributing a pull to every arm that had attempts in it; the controller updates
+//! the counts as it goes, so a `repair-file` run learns across its declarations. Each arm runs at
+//! most once per goal, and the LLM arms only with `use_llm`.
+
+use crate::history::RunRecord;
+use crate::repair::{RepairOptions, RepairOutcome};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Arm {
+    Portfolio,
+    LibrarySearch,
+    Smt,
+    LlmSingle,
+    LlmIterative,
+}
+
+impl Arm {
+    /// Cheapest first (the order ties are broken in).
+    pub const ALL: [Arm; 5] = [
+        Arm::Portfolio,
+        Arm::LibrarySearch,
+        Arm::Smt,
+        Arm::LlmSingle,
+        Arm::LlmIterative,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Portfolio => "portfolio",
+            Self::LibrarySearch => "library_search",
+            Self::Smt => "smt",
+            Self::LlmSingle => "llm_single",
+            Self::LlmIterative => "llm_iterative",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.as_str() == s.trim())
+    }
+
+    pub fn uses_llm(self) -> bool {
+        matches!(self, Self::LlmSingle | Self::LlmIterative)
+    }
+
+    /// Verifications one pull gets at most.
+    pub fn slice(self) -> usize {
+        match self {
+            Self::Portfolio | Self::LlmSingle => 8,
+            Self::LibrarySearch | Self::Smt => 4,
+            Self::LlmIterative => 16,
+        }
+    }
+
+    /// Success rate per pull assumed before any is recorded.
+    pub fn prior(self) -> f64 {
+        match self {
+            Self::Portfolio => 0.3,
+            Self::LibrarySearch => 0.35,
+            Self::Smt => 0.25,
+            Self::LlmSingle => 0.45,
+            Self::LlmIterative => 0.55,
+        }
+    }
+
+    /// The arm a recorded attempt belongs to (`RepairAttempt::source` and round).
+    pub fn of_attempt(source: &str, round: usize) -> Self {
+        match source {
+            "llm" if round == 0 => Self::LlmSingle,
+            "llm" => Self::LlmIterative,
+            "library" => Self::LibrarySearch,
+            "calc" | "stepping_stone" => Self::Smt,
+            _ => Self::Portfolio,
+        }
+    }
+
+    /// `base` restricted to this arm, with `budget` verifications.
+    pub fn options(self, base: &RepairOptions, budget: usize) -> RepairOptions {
+        let sources: &[&str] = match self {
+            Self::Portfolio => &[
+                "heuristic",
+                "goal",
+                "strategy",
+                "rename",
+                "reuse",
+                "mutation",
+            ],
+            Self::LibrarySearch => &["library"],
+            Self::Smt => &["calc", "stepping_stone"],
+            Self::LlmSingle => &["llm"],
+            Self::LlmIterative => &["llm", "rename", "mutation"],
+        };
+        let mut o = base.clone();
+        o.max_verifications = budget;
+        o.sources = Some(sources.iter().map(|s| s.to_string()).collect());
+        o.use_llm = base.use_llm && self.uses_llm();
+        o.library_search = self == Self::LibrarySearch;
+        // Each arm starts afresh; a checkpoint would carry one arm's state into the next.
+        o.checkpoint = None;
+        match self {
+            Self::LlmSingle => {
+                o.max_rounds = 1;
+                o.multi_turn = false;
+            }
+            Self::LlmIterative => {
+                o.max_rounds = base.max_rounds.max(2);
+                o.multi_turn = true;
+            }
+            _ => o.max_rounds = 1,
+        }
+        o
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ArmStats {
+    pub pulls: u64,
+    pub successes: u64,
+    /// Verifications spent over all pulls.
+    pub cost: u64,
+}
+
+/// One arm's run on a goal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pull {
+    pub arm: Arm,
+    /// The bandit index it was chosen with.
+    pub index: f64,
+    pub budget: usize,
+    pub verifications: usize,
+    pub ok: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bandit {
+    pub arms: BTreeMap<Arm, ArmStats>,
+    /// Weight of the exploration bonus.
+    pub exploration: f64,
+}
+
+impl Default for Bandit {
+    fn default() -> Self {
+        Self {
+            arms: BTreeMap::new(),
+            exploration: 0.5,
+        }
+    }
+}
+
+impl Bandit {
+    /// Counts from recorded runs (see the module docs).
+    pub fn from_history(records: &[RunRecord]) -> Self {
+        let mut b = Self::default();
+        for r in records {
+            let mut per_arm: BTreeMap<Arm, (u64, bool)> = BTreeMap::new();
+            for a in &r.attempts {
+                let e = per_arm
+                    .entry(Arm::of_attempt(&a.source, a.round))
+                    .or_default();
+                e.0 += 1;
+                e.1 |= a.ok;
+            }
+            for (arm, (cost, ok)) in per_arm {
+                b.update(arm, cost as usize, ok);
+            }
+        }
+        b
+    }
+
+    /// The repo's recorded runs, counted.
+    pub fn load(repo_root: &Path) -> Self {
+        Self::from_history(&crate::history::load(repo_root))
+    }
+
+    pub fn update(&mut self, arm: Arm, verifications: usize, ok: bool) {
+        let s = self.arms.entry(arm).or_default();
+        s.pulls += 1;
+        s.successes += ok as u64;
+        s.cost += verifications as u64;
+    }
+
+    /// Upper confidence bound on `arm`'s successes per verification.
+    pub fn index(&self, arm: Arm) -> f64 {
+        let s = self.arms.get(&arm).copied().unwrap_or_default();
+        let total: u64 = self.arms.values().map(|s| s.pulls).sum();
+        let strength = crate::priors::STRENGTH;
+        let rate = (s.successes as f64 + strength * arm.prior()) / (s.pulls as f64 + strength);
+        let bonus = self.exploration * ((1.0 + total as f64).ln() / (1.0 + s.pulls as f64)).sqrt();
+        let cost = (s.cost as f64 + strength * arm.slice() as f64) / (s.pulls as f64 + strength);
+        (rate + bonus) / cost.max(1.0)
+    }
+
+    /// The best of `arms` by `index` (the first of equals), if any.
+    pub fn choose(&self, arms: &[Arm]) -> Option<(Arm, f64)> {
+        arms.iter()
+            .map(|&a| (a, self.index(a)))
+            .fold(None, |best: Option<(Arm, f64)>, (a, i)| match best {
+                Some((_, bi)) if bi >= i => best,
+                _ => Some((a, i)),
+            })
+    }
+}
+
+/// Repair `decl_name` in `text` by running arms chosen by `bandit` until one solves it or
+/// `opts.max_verifications` is spent; `bandit` is updated with every pull.
+///
+/// The outcome is the last arm's (the solving one, if any), with the attempts and verifications
+/// of all arms and the pulls in `arms`.
+pub async fn repair_decl_in_text(
+    repo_root: &Path,
+    file_rel: &str,
+    text: &str,
+    decl_name: &str,
+    opts: &RepairOptions,
+    bandit: &mut Bandit,
+) -> Result<RepairOutcome, String> {
+    let mut remaining: Vec<Arm> = Arm::ALL
+        .into_iter()
+        .filter(|a| opts.use_llm || !a.uses_llm())
+        .collect();
+    let mut budget = opts.max_verifications;
+    let mut attempts = Vec::new();
+    let mut spent = 0;
+    let mut pulls: Vec<Pull> = Vec::new();
+    let mut last: Option<RepairOutcome> = None;
+    while budget > 0 {
+        let Some((arm, index)) = bandit.choose(&remaining) else {
+            break;
+        };
+        remaining.retain(|a| *a != arm);
+        let slice = arm.slice().min(budget);
+        let o = crate::repair::repair_decl_in_text(
+            repo_root,
+            file_rel,
+            text,
+            decl_name,
+            &arm.options(opts, slice),
+        )
+        .await?;
+        bandit.update(arm, o.verifications, o.ok);
+        budget = budget.saturating_sub(o.verifications.max(1));
+        spent += o.verifications;
+        attempts.extend(o.attempts.iter().cloned());
+        pulls.push(Pull {
+            arm,
+            index,
+            budget: slice,
+            verifications: o.verifications,
+            ok: o.ok,
+        });
+        let ok = o.ok;
+        last = Some(o);
+        if ok {
+            break;
+        }
+    }
+    let Some(mut out) = last else {
+        return Err("ensemble: no strategy to run (zero budget)".to_string());
+    };
+    out.attempts = attempts;
+    out.verifications = spent;
+    out.arms = pulls;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::AttemptRecord;
+
+    fn run(attempts: &[(&str, usize, bool)]) -> RunRecord {
+        RunRecord {
+            file: "A.lean".into(),
+            decl: "t".into(),
+            goal: None,
+            context: String::new(),
+            prompt: None,
+            patch: None,
+            patch_source: None,
+            ok: attempts.iter().any(|a| a.2),
+            stop_reason: String::new(),
+            verifications: attempts.len(),
+            added_at: 0,
+            attempts: attempts
+                .iter()
+                .map(|&(source, round, ok)| AttemptRecord {
+                    source: source.into(),
+                    family: "omega".into(),
+                    model: None,
+                    ok,
+                    round,
+                })
+                .collect(),
+            smt_entails: None,
+        }
+    }
+
+    #[test]
+    fn learns_from_history_and_explores() {
+        // Without history, cheap arms with decent priors go first.
+        let fresh = Bandit::default();
+        assert_eq!(fresh.choose(&Arm::ALL).unwrap().0, Arm::LibrarySearch);
+        assert!(fresh.choose(&[]).is_none());
+
+        // A repo where SMT-guided stones always close the goal and the portfolio never does.
+        let mut records = Vec::new();
+        for _ in 0..20 {
+            records.push(run(&[
+                ("heuristic", 0, false),
+                ("heuristic", 0, false),
+                ("stepping_stone", 0, true),
+            ]));
+        }
+        records.push(run(&[("llm", 0, false), ("llm", 1, true)]));
+        let mut b = Bandit::from_history(&records);
+        assert_eq!(b.arms[&Arm::Smt].successes, 20);
+        assert_eq!(b.arms[&Arm::Portfolio].cost, 40);
+        assert_eq!(b.arms[&Arm::LlmIterative].pulls, 1);
+        assert_eq!(b.choose(&Arm::ALL).unwrap().0, Arm::Smt);
+        assert!(b.index(Arm::Portfolio) < b.index(Arm::LibrarySearch));
+
+        // Failures wear an arm's index down.
+        let before = b.index(Arm::Smt);
+        for _ in 0..30 {
+            b.update(Arm::Smt, 4, false);
+        }
+        assert!(b.index(Arm::Smt) < before);
+        assert_ne!(b.choose(&Arm::ALL).unwrap().0, Arm::Smt);
+
+        let o = Arm::LlmSingle.options(&RepairOptions::default(), 5);
+        assert_eq!(
+            (o.max_rounds, o.max_verifications, o.use_llm),
+            (1, 5, false)
+        );
+        assert_eq!(o.sources, Some(vec!["llm".to_string()]));
+        assert!(Arm::LibrarySearch.options(&o, 4).library_search);
+        assert_eq!(Arm::parse("llm_iterative"), Some(Arm::LlmIterative));
+    }
+}
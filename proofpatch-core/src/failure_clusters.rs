@@ -0,0 +1,322 @@
+//! Failure clustering: many verification failures summarized as a few kinds.
+//!
+//! After a toolchain or mathlib bump a run can produce hundreds of failures that are mostly the
+//! same few problems. Failures (the original errors of broken declarations, and failed repair
+//! candidates) are grouped first by `ErrorClass`, then within a class by the similarity of their
+//! messages: each message is reduced to a template (position prefix dropped, quoted names and
+//! numbers masked), embedded (`llm::embeddings`, the hashing embedder unless `[embeddings]` says
+//! otherwise), and joins the first cluster whose leader it matches at `threshold` cosine
+//! similarity. A cluster counts failures and distinct declarations, keeps the names its messages
+//! quote (the missing lemma, the bad instance), and carries the repair strategy for its class
+//! (`strategy::strategy_for`), so a report can read "37 goals failed with unknown identifier"
+//! and a run can pick strategies per cluster.
+
+use crate::diagnostics::ErrorClass;
+use crate::file_repair::FileRepairReport;
+use crate::llm::embeddings::Embeddings;
+use crate::strategy::RepairStrategy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Failure {
+    pub file: String,
+    pub decl: String,
+    pub class: ErrorClass,
+    pub message: String,
+    /// The candidate that failed (`None` for a declaration's original error).
+    #[serde(default)]
+    pub candidate: Option<String>,
+}
+
+/// The original errors of `report`'s broken declarations and its failed candidates.
+pub fn failures_from_report(report: &FileRepairReport) -> Vec<Failure> {
+    let mut out: Vec<Failure> = report
+        .broken
+        .iter()
+        .filter_map(|b| {
+            Some(Failure {
+                file: report.file.clone(),
+                decl: b.name.clone(),
+                class: b.first_error_class?,
+                message: b.first_error.clone()?,
+                candidate: None,
+            })
+        })
+        .collect();
+    for o in &report.outcomes {
+        out.extend(o.attempts.iter().filter(|a| !a.ok).filter_map(|a| {
+            let message = a.first_error.clone()?;
+            Some(Failure {
+                file: o.file.clone(),
+                decl: o.decl.clone(),
+                class: a
+                    .error_class
+                    .unwrap_or_else(|| ErrorClass::classify(&message)),
+                message,
+                candidate: Some(a.candidate.clone()),
+            })
+        }));
+    }
+    out
+}
+
+/// The first line of `message` without its `file:line:col: error:` prefix, with quoted names
+/// masked as `_` and numbers as `N`; and the names that were quoted.
+pub fn message_template(message: &str) -> (String, Vec<String>) {
+    let line = message.lines().next().unwrap_or("").trim();
+    let line = line
+        .split_once(": error: ")
+        .map(|(_, m)| m)
+        .unwrap_or(line)
+        .trim_start_matches("error: ");
+    let mut out = String::new();
+    let mut names = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let close = match c {
+            '\'' if out.is_empty() || out.ends_with(' ') => '\'',
+            '`' => '`',
+            '«' => '»',
+            _ => {
+                if c.is_ascii_digit() {
+                    while chars.peek().is_some_and(|d| d.is_ascii_digit()) {
+                        chars.next();
+                    }
+                    out.push('N');
+                } else {
+                    out.push(c);
+                }
+                continue;
+            }
+        };
+        let name: String = chars.by_ref().take_while(|d| *d != close).collect();
+        if !name.is_empty() {
+            names.push(name);
+        }
+        out.push(c);
+        out.push('_');
+        out.push(close);
+    }
+    (out, names)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailureCluster {
+    pub class: ErrorClass,
+    /// Message template of the cluster's first failure.
+    pub template: String,
+    pub failures: usize,
+    /// Distinct declarations (`file:decl`) with a failure in the cluster.
+    pub goals: usize,
+    /// Of those, the first `ClusterOptions::max_listed`.
+    pub decls: Vec<String>,
+    /// Names quoted in the messages, most frequent first (at most `max_listed`).
+    pub names: Vec<(String, usize)>,
+    /// A few of the messages, as reported.
+    pub examples: Vec<String>,
+    pub strategy: RepairStrategy,
+}
+
+impl FailureCluster {
+    /// E.g. `37 goals failed with unknown identifier (Nat.foo ×12, …); strategy: rename`.
+    pub fn summary(&self) -> String {
+        let mut s = format!(
+            "{} goal{} failed with {}",
+            self.goals,
+            if self.goals == 1 { "" } else { "s" },
+            self.class.as_str().replace('_', " ")
+        );
+        if self.failures != self.goals {
+            s.push_str(&format!(" ({} failures)", self.failures));
+        }
+        if !self.names.is_empty() {
+            let names: Vec<String> = self
+                .names
+                .iter()
+                .take(3)
+                .map(|(n, k)| format!("{n} ×{k}"))
+                .collect();
+            let more = if self.names.len() > 3 { ", …" } else { "" };
+            s.push_str(&format!(": {}{more}", names.join(", ")));
+        }
+        s.push_str(&format!("; strategy: {}", self.strategy.as_str()));
+        s
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClusterOptions {
+    /// Cosine similarity of message templates needed to join a cluster.
+    pub threshold: f32,
+    pub max_listed: usize,
+    pub max_examples: usize,
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            max_listed: 10,
+            max_examples: 3,
+        }
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Cluster `failures`, largest first (by declarations, then failures).
+pub async fn cluster_failures(
+    failures: &[Failure],
+    emb: &Embeddings,
+    opts: &ClusterOptions,
+) -> Result<Vec<FailureCluster>, String> {
+    let templates: Vec<(String, Vec<String>)> = failures
+        .iter()
+        .map(|f| message_template(&f.message))
+        .collect();
+    let texts: Vec<String> = templates.iter().map(|(t, _)| t.clone()).collect();
+    let vectors = emb.embed_all(&texts).await?;
+
+    struct Acc {
+        leader: usize,
+        members: Vec<usize>,
+    }
+    let mut accs: Vec<Acc> = Vec::new();
+    for (i, f) in failures.iter().enumerate() {
+        let home = accs.iter_mut().find(|a| {
+            failures[a.leader].class == f.class
+                && (texts[a.leader] == texts[i]
+                    || dot(&vectors[a.leader], &vectors[i]) >= opts.threshold)
+        });
+        match home {
+            Some(a) => a.members.push(i),
+            None => accs.push(Acc {
+                leader: i,
+                members: vec![i],
+            }),
+        }
+    }
+
+    let mut out: Vec<FailureCluster> = accs
+        .into_iter()
+        .map(|a| {
+            let decls: BTreeSet<String> = a
+                .members
+                .iter()
+                .map(|&i| format!("{}:{}", failures[i].file, failures[i].decl))
+                .collect();
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for &i in &a.members {
+                for n in &templates[i].1 {
+                    *counts.entry(n.as_str()).or_default() += 1;
+                }
+            }
+            let mut names: Vec<(String, usize)> = counts
+                .into_iter()
+                .map(|(n, k)| (n.to_string(), k))
+                .collect();
+            names.sort_by(|x, y| y.1.cmp(&x.1).then_with(|| x.0.cmp(&y.0)));
+            names.truncate(opts.max_listed);
+            let mut examples: Vec<String> = Vec::new();
+            for &i in &a.members {
+                let m = failures[i].message.lines().next().unwrap_or("").to_string();
+                if examples.len() < opts.max_examples && !examples.contains(&m) {
+                    examples.push(m);
+                }
+            }
+            let class = failures[a.leader].class;
+            FailureCluster {
+                class,
+                template: texts[a.leader].clone(),
+                failures: a.members.len(),
+                goals: decls.len(),
+                decls: decls.into_iter().take(opts.max_listed).collect(),
+                names,
+                examples,
+                strategy: crate::strategy::strategy_for(class),
+            }
+        })
+        .collect();
+    out.sort_by(|a, b| b.goals.cmp(&a.goals).then(b.failures.cmp(&a.failures)));
+    Ok(out)
+}
+
+/// Clusters as a markdown table.
+pub fn render_table(clusters: &[FailureCluster]) -> String {
+    let mut out = String::from(
+        "| goals | failures | class | message | names | strategy |\n|---|---|---|---|---|---|\n",
+    );
+    for c in clusters {
+        let names: Vec<String> = c
+            .names
+            .iter()
+            .take(5)
+            .map(|(n, k)| format!("{n} ×{k}"))
+            .collect();
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} |\n",
+            c.goals,
+            c.failures,
+            c.class.as_str(),
+            c.template.replace('|', "\\|"),
+            names.join(", ").replace('|', "\\|"),
+            c.strategy.as_str()
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn groups_failures_by_class_and_message() {
+        let f = |decl: &str, message: &str| Failure {
+            file: "F.lean".to_string(),
+            decl: decl.to_string(),
+            class: ErrorClass::classify(message),
+            message: message.to_string(),
+            candidate: None,
+        };
+        let failures = vec![
+            f("a", "F.lean:3:8: error: unknown identifier 'Nat.foo'"),
+            f("b", "F.lean:9:2: error: unknown identifier 'Nat.bar'"),
+            f("b", "F.lean:9:2: error: unknown identifier 'Nat.foo'"),
+            f("c", "F.lean:14:4: error: type mismatch"),
+            f("d", "F.lean:20:4: error: unknown identifier 'List.baz'"),
+        ];
+        assert_eq!(
+            message_template(&failures[0].message),
+            (
+                "unknown identifier '_'".to_string(),
+                vec!["Nat.foo".to_string()]
+            )
+        );
+        let clusters = cluster_failures(
+            &failures,
+            &Embeddings::hashing(256),
+            &ClusterOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(clusters.len(), 2, "{clusters:?}");
+        let c = &clusters[0];
+        assert_eq!(
+            (c.class, c.goals, c.failures),
+            (ErrorClass::UnknownIdentifier, 3, 4)
+        );
+        assert_eq!(c.names[0], ("Nat.foo".to_string(), 2));
+        assert_eq!(c.strategy, RepairStrategy::Rename);
+        assert_eq!(
+            c.summary(),
+            "3 goals failed with unknown identifier (4 failures): Nat.foo ×2, List.baz ×1, \
+             Nat.bar ×1; strategy: rename"
+        );
+        assert_eq!(clusters[1].class, ErrorClass::TypeMismatch);
+        assert!(render_table(&clusters).contains("| 3 | 4 | unknown_identifier |"));
+    }
+}
@@ -0,0 +1,202 @@
+//! Few-shot exemplars: (goal, accepted proof) pairs from this repo's own successful repairs.
+//!
+//! With `RepairOptions::record_exemplars`, every solved goal is appended to
+//! `.generated/proofpatch-fewshot/exemplars.jsonl`. With `RepairOptions::few_shot > 0`, the
+//! exemplars whose goals are most similar to the one being repaired are retrieved
+//! (`ExemplarStore::top_k`, cosine similarity over `premise::HashingEmbedder` vectors of the
+//! binder-normalized goal, or `top_k_with` and the `[embeddings]` backend) and rendered into the `{{examples}}` section of the `repair` prompt.
+//! The store is append-only and small (one line per solved goal), so vectors are computed per
+//! query rather than indexed.
+
+use crate::premise::{Embedder, HashingEmbedder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exemplar {
+    /// Pretty-printed goal at the placeholder.
+    pub goal: String,
+    /// The proof that closed it (as accepted, after minimization).
+    pub proof: String,
+    #[serde(default)]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub decl: Option<String>,
+    /// Unix seconds.
+    #[serde(default)]
+    pub added_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExemplarHit {
+    pub score: f32,
+    #[serde(flatten)]
+    pub exemplar: Exemplar,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExemplarStore {
+    pub exemplars: Vec<Exemplar>,
+}
+
+fn key(goal: &str, proof: &str) -> (String, String) {
+    (
+        crate::goal_ast::normalize_statement(goal),
+        proof.split_whitespace().collect::<Vec<_>>().join(" "),
+    )
+}
+
+impl ExemplarStore {
+    pub fn path(repo_root: &Path) -> PathBuf {
+        repo_root
+            .join(".generated")
+            .join("proofpatch-fewshot")
+            .join("exemplars.jsonl")
+    }
+
+    /// The repo's store (empty when there is none yet). Unparseable lines are skipped, and
+    /// repeated (goal, proof) pairs count once.
+    pub fn load(repo_root: &Path) -> Self {
+        let txt = std::fs::read_to_string(Self::path(repo_root)).unwrap_or_default();
+        let mut seen = HashSet::new();
+        Self {
+            exemplars: txt
+                .lines()
+                .filter_map(|l| serde_json::from_str::<Exemplar>(l).ok())
+                .filter(|e| seen.insert(key(&e.goal, &e.proof)))
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.exemplars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exemplars.is_empty()
+    }
+
+    /// Append `ex` to the repo's store unless the same (normalized) goal and proof is already
+    /// there. Returns whether a line was written.
+    pub fn record(repo_root: &Path, ex: &Exemplar) -> Result<bool, String> {
+        if ex.goal.trim().is_empty() || ex.proof.trim().is_empty() {
+            return Ok(false);
+        }
+        let k = key(&ex.goal, &ex.proof);
+        if Self::load(repo_root)
+            .exemplars
+            .iter()
+            .any(|e| key(&e.goal, &e.proof) == k)
+        {
+            return Ok(false);
+        }
+        let p = Self::path(repo_root);
+        if let Some(dir) = p.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("mkdir {}: {e}", dir.display()))?;
+        }
+        let line = serde_json::to_string(ex).map_err(|e| e.to_string())?;
+        let mut f = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&p)
+            .map_err(|e| format!("open {}: {e}", p.display()))?;
+        writeln!(f, "{line}").map_err(|e| format!("write {}: {e}", p.display()))?;
+        Ok(true)
+    }
+
+    /// The `k` exemplars with the most similar goals (score > 0), best first.
+    pub fn top_k(&self, goal: &str, k: usize) -> Vec<ExemplarHit> {
+        let emb = HashingEmbedder::default();
+        let q = emb.embed(&crate::goal_ast::normalize_statement(goal));
+        let vs = self
+            .exemplars
+            .iter()
+            .map(|e| emb.embed(&crate::goal_ast::normalize_statement(&e.goal)))
+            .collect();
+        self.rank(&q, vs, k)
+    }
+
+    /// `top_k` with an embeddings backend (`llm::embeddings`); exemplar vectors come from its
+    /// cache after the first query.
+    pub async fn top_k_with(
+        &self,
+        goal: &str,
+        k: usize,
+        emb: &crate::llm::embeddings::Embeddings,
+    ) -> Result<Vec<ExemplarHit>, String> {
+        let mut texts: Vec<String> = self
+            .exemplars
+            .iter()
+            .map(|e| crate::goal_ast::normalize_statement(&e.goal))
+            .collect();
+        texts.push(crate::goal_ast::normalize_statement(goal));
+        let mut vs = emb.embed_all(&texts).await?;
+        let q = vs.pop().unwrap_or_default();
+        Ok(self.rank(&q, vs, k))
+    }
+
+    fn rank(&self, q: &[f32], vectors: Vec<Vec<f32>>, k: usize) -> Vec<ExemplarHit> {
+        let mut hits: Vec<ExemplarHit> = self
+            .exemplars
+            .iter()
+            .zip(vectors)
+            .map(|(e, v)| ExemplarHit {
+                score: q.iter().zip(&v).map(|(a, b)| a * b).sum(),
+                exemplar: e.clone(),
+            })
+            .filter(|h| h.score > 0.0)
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(k);
+        hits
+    }
+}
+
+/// Render hits as worked examples for a prompt.
+pub fn examples_prompt_block(hits: &[ExemplarHit]) -> String {
+    hits.iter()
+        .map(|h| {
+            format!(
+                "Goal:\n{}\nProof:\n{}\n",
+                h.exemplar.goal.trim(),
+                h.exemplar.proof.trim()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_once_and_retrieves_by_goal_similarity() {
+        let td = tempfile::tempdir().unwrap();
+        let ex = |goal: &str, proof: &str| Exemplar {
+            goal: goal.to_string(),
+            proof: proof.to_string(),
+            file: None,
+            decl: None,
+            added_at: 0,
+        };
+        let list = ex(
+            "xs ys : List α\n⊢ (xs ++ ys).length = xs.length + ys.length",
+            "by simp",
+        );
+        assert!(ExemplarStore::record(td.path(), &list).unwrap());
+        assert!(!ExemplarStore::record(td.path(), &ex(&list.goal, "by  simp")).unwrap());
+        ExemplarStore::record(td.path(), &ex("a b : ℕ\n⊢ a * b = b * a", "by ring")).unwrap();
+
+        let store = ExemplarStore::load(td.path());
+        assert_eq!(store.len(), 2);
+        let hits = store.top_k(
+            "as bs : List β\n⊢ (as ++ bs).length = bs.length + as.length",
+            1,
+        );
+        assert_eq!(hits[0].exemplar.proof, "by simp");
+        assert!(examples_prompt_block(&hits).starts_with("Goal:\nxs ys : List α\n"));
+    }
+}
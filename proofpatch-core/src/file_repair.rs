@@ -0,0 +1,467 @@
+//! Whole-file repair: fix every broken declaration of one file in a single working copy.
+//!
+//! After a toolchain or mathlib bump a file often has several broken proofs at once. `repair_file`
+//! compiles the file once, collects the declarations with errors (and, optionally, placeholders;
+//! with `decls`, only those declarations), and orders them so that a declaration comes after the
+//! broken declarations it mentions (`repair_order`; source order otherwise). It then runs
+//! `repair::repair_decl_in_text` on each in turn, against the working copy as patched so far:
+//! - an error inside a tactic block is repaired from the failing step on (`from_line`), keeping
+//!   the steps before it; any other failing proof is first replaced by `sorry`, and that stub is
+//!   dropped again if the repair fails;
+//! - candidates are judged only by errors inside the declaration (`scope_errors_to_decl`), since
+//!   later declarations may still be broken;
+//! - declarations repaired earlier are passed to the LLM as context (`extra_context`), the ones
+//!   the current declaration mentions first.
+//!
+//! Nothing is written to disk; the report carries the patched text and the edits in order, and
+//! the failures met on the way, clustered (`failure_clusters`). With `checkpoint`, progress is
+//! saved after every declaration (and every round within one), and a run on the same file
+//! resumes after the declarations already done (`checkpoint::FileCheckpoint`).
+
+use crate::diagnostics::{Diagnostic, ErrorClass, Severity};
+use crate::patching::{apply_patch, EditRecord, PatchTarget};
+use crate::repair::{RepairOptions, RepairOutcome};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct FileRepairOptions {
+    /// Per-declaration repair settings (`scope_errors_to_decl` is always on).
+    pub repair: RepairOptions,
+    /// Also repair declarations that only contain `sorry`/`admit` placeholders.
+    pub include_placeholders: bool,
+    /// Stop after this many declarations.
+    pub max_decls: usize,
+    /// Only repair these declarations (all broken ones when empty).
+    pub decls: Vec<String>,
+    /// Earlier repairs passed as context to each later one.
+    pub max_context_decls: usize,
+    /// Save progress here and resume from it (see the module docs).
+    pub checkpoint: Option<PathBuf>,
+    /// Split each declaration's budget among strategies with the `ensemble` bandit (learning
+    /// across the file's declarations) instead of running one repair loop over all sources.
+    pub ensemble: bool,
+}
+
+impl Default for FileRepairOptions {
+    fn default() -> Self {
+        Self {
+            repair: RepairOptions {
+                // Shrinking and the warm REPL both assume the rest of the file compiles.
+                minimize: false,
+                warm: false,
+                ..Default::default()
+            },
+            include_placeholders: false,
+            max_decls: 50,
+            decls: Vec::new(),
+            max_context_decls: 4,
+            checkpoint: None,
+            ensemble: false,
+        }
+    }
+}
+
+/// A declaration that needs repair.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BrokenDecl {
+    pub name: String,
+    /// 1-based header line.
+    pub line: usize,
+    /// 1-based line of its first error.
+    pub first_error_line: Option<usize>,
+    pub first_error: Option<String>,
+    #[serde(default)]
+    pub first_error_class: Option<ErrorClass>,
+    pub has_placeholder: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileRepairReport {
+    pub file: String,
+    /// Errors in the file before and after the run (`None`: not re-checked).
+    pub errors_before: usize,
+    pub errors_after: Option<usize>,
+    pub broken: Vec<BrokenDecl>,
+    /// Declaration names in repair order.
+    pub order: Vec<String>,
+    pub outcomes: Vec<RepairOutcome>,
+    pub fixed: usize,
+    /// Edits that turn the original text into `patched_text`, in order (`patching::revert_all`).
+    pub edits: Vec<EditRecord>,
+    /// LLM usage summed over `outcomes`.
+    #[serde(default)]
+    pub llm_usage: crate::llm::cost::UsageTotals,
+    /// The original errors and failed candidates, clustered (`failure_clusters`).
+    #[serde(default)]
+    pub failure_clusters: Vec<crate::failure_clusters::FailureCluster>,
+    #[serde(skip)]
+    pub patched_text: String,
+}
+
+/// Declarations with errors in `diags` (and with placeholders, if asked), in source order.
+pub fn broken_decls(
+    text: &str,
+    diags: &[Diagnostic],
+    include_placeholders: bool,
+) -> Vec<BrokenDecl> {
+    let mut out: Vec<BrokenDecl> = Vec::new();
+    let mut add = |line_1: usize, err: Option<&Diagnostic>, placeholder: bool| {
+        let Some(d) = crate::nearest_decl_header_in_text(text, line_1, 20_000) else {
+            return;
+        };
+        let i = match out.iter().position(|b| b.name == d.name) {
+            Some(i) => i,
+            None => {
+                out.push(BrokenDecl {
+                    name: d.name,
+                    line: d.line,
+                    first_error_line: None,
+                    first_error: None,
+                    first_error_class: None,
+                    has_placeholder: false,
+                });
+                out.len() - 1
+            }
+        };
+        let b = &mut out[i];
+        b.has_placeholder |= placeholder;
+        if let Some(e) = err {
+            if b.first_error_line.is_none_or(|l| e.line < l) {
+                b.first_error_line = Some(e.line);
+                b.first_error = Some(e.headline().to_string());
+                b.first_error_class = Some(e.class);
+            }
+        }
+    };
+    for d in diags.iter().filter(|d| d.severity == Severity::Error) {
+        add(d.line, Some(d), false);
+    }
+    if include_placeholders {
+        let tokens: Vec<String> = crate::scan::PLACEHOLDER_TOKENS
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        for h in crate::scan::find_placeholders(text, &tokens) {
+            add(h.line, None, true);
+        }
+    }
+    out.sort_by_key(|b| b.line);
+    out
+}
+
+/// Indices into `broken`: a declaration after the broken declarations it mentions, source order
+/// otherwise (and for cycles).
+pub fn repair_order(text: &str, broken: &[BrokenDecl]) -> Vec<usize> {
+    let short = |n: &str| n.rsplit_once('.').map(|(_, s)| s.to_string());
+    let deps: Vec<BTreeSet<usize>> = broken
+        .iter()
+        .map(|b| {
+            let Ok((s, e)) = crate::patching::decl_byte_range(text, &b.name) else {
+                return BTreeSet::new();
+            };
+            let names = crate::prompt_context::mentioned_names(&text[s..e]);
+            broken
+                .iter()
+                .enumerate()
+                .filter(|(_, o)| o.name != b.name)
+                .filter(|(_, o)| {
+                    names.contains(&o.name) || short(&o.name).is_some_and(|n| names.contains(&n))
+                })
+                .map(|(j, _)| j)
+                .collect()
+        })
+        .collect();
+    let mut done: Vec<bool> = vec![false; broken.len()];
+    let mut out = Vec::new();
+    while out.len() < broken.len() {
+        let next = (0..broken.len())
+            .find(|&i| !done[i] && deps[i].iter().all(|&j| done[j]))
+            .or_else(|| (0..broken.len()).find(|&i| !done[i]));
+        let Some(i) = next else { break };
+        done[i] = true;
+        out.push(i);
+    }
+    out
+}
+
+/// Lines added by `edit` (negative: removed).
+fn line_delta(edit: &EditRecord) -> isize {
+    edit.new_text.matches('\n').count() as isize - edit.old_text.matches('\n').count() as isize
+}
+
+fn shift_line(line: usize, edit: &EditRecord) -> usize {
+    if line > edit.line {
+        (line as isize + line_delta(edit)).max(1) as usize
+    } else {
+        line
+    }
+}
+
+/// Keep an anonymous declaration's name (`example@<line>`) addressable after `edit` moved the
+/// lines below it; named declarations keep theirs.
+pub(crate) fn shift_name_after_edit(name: &mut String, edit: &EditRecord) {
+    if let Some((kind, line)) = crate::parse_anonymous_decl_name(name) {
+        *name = crate::anonymous_decl_name(kind, shift_line(line, edit));
+    }
+}
+
+/// Keep a not-yet-repaired declaration addressable after `edit` moved the lines below it.
+fn shift_after_edit(b: &mut BrokenDecl, edit: &EditRecord) {
+    shift_name_after_edit(&mut b.name, edit);
+    b.line = shift_line(b.line, edit);
+    b.first_error_line = b.first_error_line.map(|l| shift_line(l, edit));
+}
+
+/// Repair every broken declaration of `file_rel` (see the module docs).
+pub async fn repair_file(
+    repo_root: &Path,
+    file_rel: &str,
+    opts: &FileRepairOptions,
+) -> Result<FileRepairReport, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let abs = repo_root.join(file_rel);
+    let original =
+        std::fs::read_to_string(&abs).map_err(|e| format!("read {}: {e}", abs.display()))?;
+    let vr = crate::verify_lean_text(&repo_root, &original, opts.repair.verify_timeout).await?;
+    let diags = crate::diagnostics::parse_diagnostics_from(&vr.stdout, &vr.stderr);
+    let errors_before = diags
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    let mut broken = broken_decls(&original, &diags, opts.include_placeholders);
+    if !opts.decls.is_empty() {
+        broken.retain(|b| opts.decls.contains(&b.name));
+    }
+    let order = repair_order(&original, &broken);
+
+    let mut report = FileRepairReport {
+        file: file_rel.to_string(),
+        errors_before,
+        errors_after: None,
+        broken: broken.clone(),
+        order: order.iter().map(|&i| broken[i].name.clone()).collect(),
+        outcomes: Vec::new(),
+        fixed: 0,
+        edits: Vec::new(),
+        llm_usage: Default::default(),
+        failure_clusters: Vec::new(),
+        patched_text: original.clone(),
+    };
+    let mut pending = broken;
+    let mut repaired: Vec<String> = Vec::new();
+    let mut done = 0;
+    let resumed = match &opts.checkpoint {
+        Some(p) => crate::checkpoint::FileCheckpoint::load(p, file_rel, &original)?,
+        None => None,
+    };
+    if let Some(c) = resumed {
+        done = c.done;
+        pending = c.pending;
+        report.fixed = c.repaired.len();
+        repaired = c.repaired;
+        for o in &c.outcomes {
+            report.llm_usage.merge(&o.llm_usage);
+        }
+        report.outcomes = c.outcomes;
+        report.edits = c.edits;
+        report.patched_text = c.patched_text;
+    }
+    let decl_checkpoint = opts
+        .checkpoint
+        .as_deref()
+        .map(crate::checkpoint::decl_checkpoint_path);
+    let mut bandit = opts
+        .ensemble
+        .then(|| crate::ensemble::Bandit::load(&repo_root));
+    for (k, &i) in order.iter().enumerate().take(opts.max_decls).skip(done) {
+        let b = pending[i].clone();
+        let mut text = report.patched_text.clone();
+        let mut edits: Vec<EditRecord> = Vec::new();
+        let mut ropts = opts.repair.clone();
+        ropts.scope_errors_to_decl = true;
+        ropts.from_line = None;
+        ropts.error_class = b.first_error_class;
+        if let Some(line) = b.first_error_line {
+            let tail = PatchTarget::DeclTail {
+                decl: b.name.clone(),
+                line,
+            };
+            if crate::patching::resolve_target(&text, &tail).is_ok() {
+                ropts.from_line = Some(line);
+            } else {
+                let Ok((stub, e)) =
+                    apply_patch(&text, &PatchTarget::DeclProof(b.name.clone()), "sorry")
+                else {
+                    continue;
+                };
+                (text, edits) = (stub, vec![e]);
+            }
+        }
+        ropts.extra_context = context_for(&text, &b.name, &repaired, opts.max_context_decls);
+        ropts.checkpoint = decl_checkpoint.clone();
+
+        let outcome = match bandit.as_mut() {
+            Some(bandit) => {
+                crate::ensemble::repair_decl_in_text(
+                    &repo_root, file_rel, &text, &b.name, &ropts, bandit,
+                )
+                .await?
+            }
+            None => {
+                crate::repair::repair_decl_in_text(&repo_root, file_rel, &text, &b.name, &ropts)
+                    .await?
+            }
+        };
+        if let (true, Some(p), Some(e)) = (
+            outcome.ok,
+            outcome.patched_text.clone(),
+            outcome.edit.clone(),
+        ) {
+            edits.push(e);
+            for e in &edits {
+                for (j, other) in pending.iter_mut().enumerate() {
+                    if j != i {
+                        shift_after_edit(other, e);
+                    }
+                }
+                // `context_for` looks earlier repairs up by name in the patched text.
+                for name in &mut repaired {
+                    shift_name_after_edit(name, e);
+                }
+            }
+            report.patched_text = p;
+            report.edits.extend(edits);
+            report.fixed += 1;
+            repaired.push(b.name.clone());
+        }
+        report.llm_usage.merge(&outcome.llm_usage);
+        report.outcomes.push(outcome);
+        if let (Some(p), Some(dp)) = (&opts.checkpoint, &decl_checkpoint) {
+            let _ = std::fs::remove_file(dp);
+            let c = crate::checkpoint::FileCheckpoint {
+                version: crate::checkpoint::CHECKPOINT_VERSION,
+                file: file_rel.to_string(),
+                original_hash: crate::tree_search::hash_text(&original),
+                done: k + 1,
+                pending: pending.clone(),
+                repaired: repaired.clone(),
+                outcomes: report.outcomes.clone(),
+                edits: report.edits.clone(),
+                patched_text: report.patched_text.clone(),
+            };
+            // A failed save loses progress, not the run.
+            let _ = crate::checkpoint::save_json(p, &c);
+        }
+    }
+    if report.fixed > 0 {
+        let vr =
+            crate::verify_lean_text(&repo_root, &report.patched_text, opts.repair.verify_timeout)
+                .await?;
+        let diags = crate::diagnostics::parse_diagnostics_from(&vr.stdout, &vr.stderr);
+        report.errors_after = Some(
+            diags
+                .iter()
+                .filter(|d| d.severity == Severity::Error)
+                .count(),
+        );
+    }
+    let failures = crate::failure_clusters::failures_from_report(&report);
+    if !failures.is_empty() {
+        let opts = crate::failure_clusters::ClusterOptions::default();
+        let emb = crate::llm::embeddings::from_repo(&repo_root)
+            .unwrap_or_else(|_| crate::llm::embeddings::Embeddings::hashing(512));
+        report.failure_clusters =
+            match crate::failure_clusters::cluster_failures(&failures, &emb, &opts).await {
+                Ok(c) => c,
+                // A remote embedder that fails should not cost the run its report.
+                Err(_) => crate::failure_clusters::cluster_failures(
+                    &failures,
+                    &crate::llm::embeddings::Embeddings::hashing(512),
+                    &opts,
+                )
+                .await
+                .unwrap_or_default(),
+            };
+    }
+    Ok(report)
+}
+
+/// Declarations repaired earlier, as text: those `decl_name` mentions first, then the most recent.
+fn context_for(text: &str, decl_name: &str, repaired: &[String], max: usize) -> Vec<String> {
+    let mentioned = crate::patching::decl_byte_range(text, decl_name)
+        .map(|(s, e)| crate::prompt_context::mentioned_names(&text[s..e]))
+        .unwrap_or_default();
+    let (mut first, rest): (Vec<&String>, Vec<&String>) =
+        repaired.iter().partition(|n| mentioned.contains(*n));
+    first.extend(rest.into_iter().rev());
+    first
+        .into_iter()
+        .filter_map(|n| {
+            let (s, e) = crate::patching::decl_byte_range(text, n).ok()?;
+            Some(text[s..e].to_string())
+        })
+        .take(max)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_broken_decls_after_the_broken_lemmas_they_use() {
+        let text = "theorem b (n : ℕ) : n + 0 = n := by\n  simpa using a n\n\n\
+                    theorem a (n : ℕ) : 0 + n = n := by\n  simp [Nat.zero_add']\n\n\
+                    example : True := by\n  sorry\n";
+        let out = "F.lean:2:2: error: type mismatch\n\
+                   F.lean:5:8: error: unknown constant 'Nat.zero_add''\n";
+        let diags = crate::diagnostics::parse_diagnostics_from(out, "");
+        let broken = broken_decls(text, &diags, true);
+        let names: Vec<&str> = broken.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "a", "example@7"]);
+        assert_eq!(broken[1].first_error_line, Some(5));
+        assert!(broken[2].has_placeholder && broken[2].first_error_line.is_none());
+
+        let order: Vec<&str> = repair_order(text, &broken)
+            .into_iter()
+            .map(|i| broken[i].name.as_str())
+            .collect();
+        assert_eq!(order, vec!["a", "b", "example@7"]);
+
+        let mut ex = broken[2].clone();
+        shift_after_edit(
+            &mut ex,
+            &EditRecord {
+                byte_start: 0,
+                old_text: "x".to_string(),
+                new_text: "y\n  z".to_string(),
+                line: 5,
+            },
+        );
+        assert_eq!((ex.name.as_str(), ex.line), ("example@8", 8));
+    }
+
+    #[test]
+    fn earlier_repairs_stay_in_context_after_edits_above_them() {
+        let before = "example : True := by\n  sorry\n\n\
+                      theorem a (n : ℕ) : n = n := by\n  sorry\n\n\
+                      example : 1 = 1 := by\n  sorry\n";
+        let edit = |text: &str, name: &str, proof: &str| {
+            apply_patch(text, &PatchTarget::DeclProof(name.to_string()), proof).unwrap()
+        };
+        // The second example is repaired first, then the one above it grows by a line.
+        let (text, _) = edit(before, "example@7", "by\n  rfl");
+        let mut repaired = vec!["example@7".to_string()];
+        let (text, e) = edit(&text, "example@1", "by\n  trivial\n  done");
+        for name in &mut repaired {
+            shift_name_after_edit(name, &e);
+        }
+        assert_eq!(repaired, vec!["example@8"]);
+        assert_eq!(
+            context_for(&text, "a", &repaired, 4),
+            vec!["example : 1 = 1 := by\n  rfl".to_string()]
+        );
+    }
+}
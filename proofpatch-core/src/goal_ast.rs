@@ -0,0 +1,1472 @@
+//! Typed AST for Lean pretty-printed goals.
+//!
+//! Lean's pretty printer output (`pp_dump` hyps, `$/lean/plainGoal`, error messages) is the only
+//! goal representation we get without running Lean metaprograms. This module tokenizes and parses
+//! that text into a small typed tree: identifiers, literals, applications, prefix/infix operators
+//! (with Lean's precedences), arrows, and binders (`∀`, `∃`, `fun`, big operators).
+//!
+//! It is a parser for the *pretty-printed* surface, not for Lean source: notation we don't know
+//! is a parse error (`Err`), and callers are expected to fall back to "unknown".
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Ident(String),
+    Num(String),
+    Str(String),
+    Sym(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    /// Index into the token stream (or char offset for tokenizer errors).
+    pub pos: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at {})", self.message, self.pos)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn perr(message: impl Into<String>, pos: usize) -> ParseError {
+    ParseError {
+        message: message.into(),
+        pos,
+    }
+}
+
+/// Symbols, longest first so greedy matching works.
+const SYMBOLS: &[&str] = &[
+    "<->", ":=", "->", "<=", ">=", "!=", "=>", "&&", "||", "++", "::", "⁻¹", "↔", "→", "∧", "∨",
+    "¬", "=", "≠", "≤", "≥", "<", ">", "+", "-", "*", "/", "%", "^", "∣", "∈", "∉", "⊆", "⊂", "∩",
+    "∪", "•", "∘", "↑", "·", "∑", "∏", "∀", "∃", "λ", "↦", ",", ":", "(", ")", "[", "]", "{", "}",
+    "⟨", "⟩", "⦃", "⦄", "|", "⊢", "×",
+];
+
+fn is_ident_start(c: char) -> bool {
+    (c.is_alphabetic() && c != 'λ') || c == '_'
+}
+
+fn is_ident_rest(c: char) -> bool {
+    is_ident_start(c) || c.is_numeric() || c == '\'' || c == '!' || c == '?'
+}
+
+/// Tokenize pretty-printed Lean text.
+pub fn tokenize(s: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0usize;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        // Metavariables: `?m.12`, `?a`.
+        if c == '?' && chars.get(i + 1).is_some_and(|&n| is_ident_start(n)) {
+            let start = i;
+            i += 1;
+            i = scan_ident(&chars, i);
+            out.push(Token::Ident(chars[start..i].iter().collect()));
+            continue;
+        }
+        if c == '«' {
+            let start = i;
+            while i < chars.len() && chars[i] != '»' {
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err(perr("unterminated «»", start));
+            }
+            i += 1;
+            i = scan_ident(&chars, i);
+            out.push(Token::Ident(chars[start..i].iter().collect()));
+            continue;
+        }
+        if is_ident_start(c) {
+            let start = i;
+            i = scan_ident(&chars, i);
+            let id: String = chars[start..i].iter().collect();
+            if id == "fun" {
+                out.push(Token::Sym("λ"));
+            } else {
+                out.push(Token::Ident(id));
+            }
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            if chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit()) {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            out.push(Token::Num(chars[start..i].iter().collect()));
+            continue;
+        }
+        if c == '"' {
+            let start = i;
+            i += 1;
+            let mut lit = String::new();
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' && i + 1 < chars.len() {
+                    i += 1;
+                }
+                lit.push(chars[i]);
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err(perr("unterminated string", start));
+            }
+            i += 1;
+            out.push(Token::Str(lit));
+            continue;
+        }
+        let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+        match SYMBOLS.iter().find(|sym| rest.starts_with(**sym)) {
+            Some(sym) => {
+                out.push(Token::Sym(canonical_sym(sym)));
+                i += sym.chars().count();
+            }
+            None => return Err(perr(format!("unexpected character {c:?}"), i)),
+        }
+    }
+    Ok(out)
+}
+
+fn scan_ident(chars: &[char], mut i: usize) -> usize {
+    loop {
+        while i < chars.len() && is_ident_rest(chars[i]) {
+            i += 1;
+        }
+        // Dotted names and projections: `Nat.succ`, `h.le`, `p.1`.
+        if chars.get(i) == Some(&'.')
+            && chars
+                .get(i + 1)
+                .is_some_and(|&n| is_ident_rest(n) || n == '«')
+        {
+            i += 1;
+            continue;
+        }
+        return i;
+    }
+}
+
+fn canonical_sym(s: &'static str) -> &'static str {
+    match s {
+        "<->" => "↔",
+        "->" => "→",
+        "<=" => "≤",
+        ">=" => "≥",
+        "!=" => "≠",
+        "=>" => "↦",
+        "&&" => "∧",
+        "||" => "∨",
+        other => other,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinOp {
+    Iff,
+    And,
+    Or,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Dvd,
+    Mem,
+    NotMem,
+    Subset,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    /// Infix notation we parse but don't interpret (`∩`, `∪`, `•`, `∘`, `++`, `::`, `×`, `⊂`).
+    Other(String),
+}
+
+impl BinOp {
+    fn from_sym(s: &str) -> Option<BinOp> {
+        Some(match s {
+            "↔" => BinOp::Iff,
+            "∧" => BinOp::And,
+            "∨" => BinOp::Or,
+            "=" => BinOp::Eq,
+            "≠" => BinOp::Ne,
+            "<" => BinOp::Lt,
+            "≤" => BinOp::Le,
+            ">" => BinOp::Gt,
+            "≥" => BinOp::Ge,
+            "∣" => BinOp::Dvd,
+            "∈" => BinOp::Mem,
+            "∉" => BinOp::NotMem,
+            "⊆" => BinOp::Subset,
+            "+" => BinOp::Add,
+            "-" => BinOp::Sub,
+            "*" => BinOp::Mul,
+            "/" => BinOp::Div,
+            "%" => BinOp::Mod,
+            "^" => BinOp::Pow,
+            "∩" | "∪" | "•" | "∘" | "++" | "::" | "×" | "⊂" => {
+                BinOp::Other(s.to_string())
+            }
+            _ => return None,
+        })
+    }
+
+    pub fn symbol(&self) -> &str {
+        match self {
+            BinOp::Iff => "↔",
+            BinOp::And => "∧",
+            BinOp::Or => "∨",
+            BinOp::Eq => "=",
+            BinOp::Ne => "≠",
+            BinOp::Lt => "<",
+            BinOp::Le => "≤",
+            BinOp::Gt => ">",
+            BinOp::Ge => "≥",
+            BinOp::Dvd => "∣",
+            BinOp::Mem => "∈",
+            BinOp::NotMem => "∉",
+            BinOp::Subset => "⊆",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Pow => "^",
+            BinOp::Other(s) => s,
+        }
+    }
+
+    /// `(precedence, right_assoc)` following Lean 4 core/Mathlib notation.
+    fn binding(&self) -> (u32, bool) {
+        match self {
+            BinOp::Iff => (20, false),
+            BinOp::Or => (30, true),
+            BinOp::And => (35, true),
+            BinOp::Eq
+            | BinOp::Ne
+            | BinOp::Lt
+            | BinOp::Le
+            | BinOp::Gt
+            | BinOp::Ge
+            | BinOp::Dvd
+            | BinOp::Mem
+            | BinOp::NotMem
+            | BinOp::Subset => (50, false),
+            BinOp::Add | BinOp::Sub => (65, false),
+            BinOp::Mul | BinOp::Div | BinOp::Mod => (70, false),
+            BinOp::Pow => (75, true),
+            BinOp::Other(s) => match s.as_str() {
+                "×" => (35, true),
+                "⊂" => (50, false),
+                "∪" | "++" => (65, false),
+                "::" => (67, true),
+                "∩" => (70, false),
+                "•" => (73, true),
+                _ => (90, true),
+            },
+        }
+    }
+
+    /// True for `=`, `≠`, `<`, `≤`, `>`, `≥`.
+    pub fn is_relation(&self) -> bool {
+        matches!(
+            self,
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Not,
+    Neg,
+    /// Coercion arrow `↑x`.
+    Coe,
+    /// Postfix `x⁻¹`.
+    Inv,
+}
+
+impl UnOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnOp::Not => "¬",
+            UnOp::Neg => "-",
+            UnOp::Coe => "↑",
+            UnOp::Inv => "⁻¹",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinderKind {
+    Forall,
+    Exists,
+    Lambda,
+    /// Big operators such as `∑` / `∏`.
+    BigOp(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinderInfo {
+    Default,
+    Implicit,
+    StrictImplicit,
+    InstImplicit,
+}
+
+/// One binder group: `x y : T`, `{α : Type}`, `i ∈ s`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binder {
+    pub names: Vec<String>,
+    pub ty: Option<Expr>,
+    /// Bounded binders: `∀ x ∈ s, …`, `∑ i ∈ range n, …`.
+    pub domain: Option<Expr>,
+    pub info: BinderInfo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Ident(String),
+    Num(String),
+    Str(String),
+    /// `·` placeholder.
+    Cdot,
+    App {
+        func: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    Unary {
+        op: UnOp,
+        arg: Box<Expr>,
+    },
+    Binary {
+        op: BinOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    /// Non-dependent arrow `A → B`.
+    Arrow {
+        dom: Box<Expr>,
+        cod: Box<Expr>,
+    },
+    Binder {
+        kind: BinderKind,
+        binders: Vec<Binder>,
+        body: Box<Expr>,
+    },
+    /// `(a, b)` and `⟨a, b⟩`.
+    Tuple {
+        anonymous: bool,
+        items: Vec<Expr>,
+    },
+    /// `[a, b]` list literal.
+    List(Vec<Expr>),
+    /// `(e : T)`.
+    Ascription {
+        expr: Box<Expr>,
+        ty: Box<Expr>,
+    },
+}
+
+impl Expr {
+    pub fn ident(&self) -> Option<&str> {
+        match self {
+            Expr::Ident(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Literal value for natural-number literals.
+    pub fn as_nat_lit(&self) -> Option<i64> {
+        match self {
+            Expr::Num(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Head symbol and arguments of an application (`f a b` → (`f`, [a, b])); non-applications
+    /// are their own head with no arguments.
+    pub fn head_args(&self) -> (&Expr, &[Expr]) {
+        match self {
+            Expr::App { func, args } => (func, args),
+            other => (other, &[]),
+        }
+    }
+
+    /// True if the expression is `ℕ` / `Nat`.
+    pub fn is_nat_type(&self) -> bool {
+        matches!(self.ident(), Some("ℕ" | "Nat"))
+    }
+
+    /// True if the expression is `ℤ` / `Int`.
+    pub fn is_int_type(&self) -> bool {
+        matches!(self.ident(), Some("ℤ" | "Int"))
+    }
+
+    /// Visit every sub-expression (pre-order).
+    pub fn walk<'a>(&'a self, f: &mut dyn FnMut(&'a Expr)) {
+        f(self);
+        match self {
+            Expr::Ident(_) | Expr::Num(_) | Expr::Str(_) | Expr::Cdot => {}
+            Expr::App { func, args } => {
+                func.walk(f);
+                args.iter().for_each(|a| a.walk(f));
+            }
+            Expr::Unary { arg, .. } => arg.walk(f),
+            Expr::Binary { lhs, rhs, .. } => {
+                lhs.walk(f);
+                rhs.walk(f);
+            }
+            Expr::Arrow { dom, cod } => {
+                dom.walk(f);
+                cod.walk(f);
+            }
+            Expr::Binder { binders, body, .. } => {
+                for b in binders {
+                    if let Some(t) = &b.ty {
+                        t.walk(f);
+                    }
+                    if let Some(d) = &b.domain {
+                        d.walk(f);
+                    }
+                }
+                body.walk(f);
+            }
+            Expr::Tuple { items, .. } | Expr::List(items) => items.iter().for_each(|a| a.walk(f)),
+            Expr::Ascription { expr, ty } => {
+                expr.walk(f);
+                ty.walk(f);
+            }
+        }
+    }
+
+    /// Precedence of the outermost construct (for minimal parenthesization).
+    fn prec(&self) -> u32 {
+        match self {
+            Expr::Binder { .. } => 0,
+            Expr::Arrow { .. } => 25,
+            Expr::Binary { op, .. } => op.binding().0,
+            Expr::Unary { op: UnOp::Not, .. } => 40,
+            Expr::Unary { op: UnOp::Neg, .. } => 75,
+            Expr::App { .. } => MAX_PREC,
+            _ => MAX_PREC + 1,
+        }
+    }
+}
+
+const MAX_PREC: u32 = 1024;
+
+struct Wrap<'a>(&'a Expr, u32);
+
+impl fmt::Display for Wrap<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.prec() < self.1 {
+            write!(f, "({})", self.0)
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+fn fmt_binder(b: &Binder, f: &mut fmt::Formatter<'_>, bracket: bool) -> fmt::Result {
+    let (open, close) = match b.info {
+        BinderInfo::Default if bracket => ("(", ")"),
+        BinderInfo::Default => ("", ""),
+        BinderInfo::Implicit => ("{", "}"),
+        BinderInfo::StrictImplicit => ("⦃", "⦄"),
+        BinderInfo::InstImplicit => ("[", "]"),
+    };
+    write!(f, "{open}{}", b.names.join(" "))?;
+    if let Some(t) = &b.ty {
+        write!(f, " : {t}")?;
+    }
+    if let Some(d) = &b.domain {
+        write!(f, " ∈ {d}")?;
+    }
+    write!(f, "{close}")
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Ident(s) | Expr::Num(s) => write!(f, "{s}"),
+            Expr::Str(s) => write!(f, "{s:?}"),
+            Expr::Cdot => write!(f, "·"),
+            Expr::App { func, args } => {
+                write!(f, "{}", Wrap(func, MAX_PREC))?;
+                for a in args {
+                    write!(f, " {}", Wrap(a, MAX_PREC + 1))?;
+                }
+                Ok(())
+            }
+            Expr::Unary { op: UnOp::Inv, arg } => write!(f, "{}⁻¹", Wrap(arg, MAX_PREC + 1)),
+            Expr::Unary { op, arg } => {
+                let p = match op {
+                    UnOp::Not => 40,
+                    UnOp::Neg => 75,
+                    _ => MAX_PREC + 1,
+                };
+                write!(f, "{}{}", op.symbol(), Wrap(arg, p))
+            }
+            Expr::Binary { op, lhs, rhs } => {
+                let (p, right) = op.binding();
+                let (lp, rp) = if right { (p + 1, p) } else { (p, p + 1) };
+                write!(f, "{} {} {}", Wrap(lhs, lp), op.symbol(), Wrap(rhs, rp))
+            }
+            Expr::Arrow { dom, cod } => write!(f, "{} → {}", Wrap(dom, 26), Wrap(cod, 25)),
+            Expr::Binder {
+                kind,
+                binders,
+                body,
+            } => {
+                let head = match kind {
+                    BinderKind::Forall => "∀",
+                    BinderKind::Exists => "∃",
+                    BinderKind::Lambda => "fun",
+                    BinderKind::BigOp(s) => s.as_str(),
+                };
+                write!(f, "{head}")?;
+                let bracket = binders.len() > 1;
+                for b in binders {
+                    write!(f, " ")?;
+                    fmt_binder(b, f, bracket)?;
+                }
+                if *kind == BinderKind::Lambda {
+                    write!(f, " ↦ {body}")
+                } else {
+                    write!(f, ", {body}")
+                }
+            }
+            Expr::Tuple { anonymous, items } => {
+                let (o, c) = if *anonymous {
+                    ("⟨", "⟩")
+                } else {
+                    ("(", ")")
+                };
+                let parts: Vec<String> = items.iter().map(|e| e.to_string()).collect();
+                write!(f, "{o}{}{c}", parts.join(", "))
+            }
+            Expr::List(items) => {
+                let parts: Vec<String> = items.iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", parts.join(", "))
+            }
+            Expr::Ascription { expr, ty } => write!(f, "({expr} : {ty})"),
+        }
+    }
+}
+
+struct Parser {
+    toks: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.toks.get(self.pos)
+    }
+
+    fn peek_sym(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Sym(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn eat_sym(&mut self, s: &str) -> bool {
+        if self.peek_sym() == Some(s) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_sym(&mut self, s: &str) -> Result<(), ParseError> {
+        if self.eat_sym(s) {
+            Ok(())
+        } else {
+            Err(perr(format!("expected `{s}`"), self.pos))
+        }
+    }
+
+    fn starts_atom(&self) -> bool {
+        match self.peek() {
+            Some(Token::Ident(_) | Token::Num(_) | Token::Str(_)) => true,
+            Some(Token::Sym(s)) => matches!(*s, "(" | "⟨" | "[" | "↑" | "·"),
+            None => false,
+        }
+    }
+
+    fn expr(&mut self, min_prec: u32) -> Result<Expr, ParseError> {
+        let mut lhs = self.prefix()?;
+        while let Some(sym) = self.peek_sym() {
+            if sym == "→" {
+                if min_prec > 25 {
+                    break;
+                }
+                self.pos += 1;
+                let cod = self.expr(25)?;
+                lhs = Expr::Arrow {
+                    dom: Box::new(lhs),
+                    cod: Box::new(cod),
+                };
+                continue;
+            }
+            if sym == "⁻¹" {
+                self.pos += 1;
+                lhs = Expr::Unary {
+                    op: UnOp::Inv,
+                    arg: Box::new(lhs),
+                };
+                continue;
+            }
+            let Some(op) = BinOp::from_sym(sym) else {
+                break;
+            };
+            let (p, right) = op.binding();
+            if p < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expr(if right { p } else { p + 1 })?;
+            lhs = Expr::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn prefix(&mut self) -> Result<Expr, ParseError> {
+        match self.peek_sym() {
+            Some("¬") => {
+                self.pos += 1;
+                let arg = self.expr(40)?;
+                Ok(Expr::Unary {
+                    op: UnOp::Not,
+                    arg: Box::new(arg),
+                })
+            }
+            Some("-") => {
+                self.pos += 1;
+                let arg = self.expr(75)?;
+                Ok(Expr::Unary {
+                    op: UnOp::Neg,
+                    arg: Box::new(arg),
+                })
+            }
+            Some(s @ ("∀" | "∃" | "λ" | "∑" | "∏")) => {
+                self.pos += 1;
+                let kind = match s {
+                    "∀" => BinderKind::Forall,
+                    "∃" => BinderKind::Exists,
+                    "λ" => BinderKind::Lambda,
+                    other => BinderKind::BigOp(other.to_string()),
+                };
+                let binders = self.binders()?;
+                if kind == BinderKind::Lambda {
+                    self.expect_sym("↦")?;
+                } else {
+                    self.expect_sym(",")?;
+                }
+                let body = self.expr(0)?;
+                Ok(Expr::Binder {
+                    kind,
+                    binders,
+                    body: Box::new(body),
+                })
+            }
+            _ => self.application(),
+        }
+    }
+
+    fn application(&mut self) -> Result<Expr, ParseError> {
+        let func = self.atom()?;
+        let mut args = Vec::new();
+        while self.starts_atom() {
+            args.push(self.atom()?);
+        }
+        if args.is_empty() {
+            Ok(func)
+        } else {
+            Ok(Expr::App {
+                func: Box::new(func),
+                args,
+            })
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        let pos = self.pos;
+        let Some(tok) = self.peek().cloned() else {
+            return Err(perr("unexpected end of input", pos));
+        };
+        self.pos += 1;
+        match tok {
+            Token::Ident(s) => Ok(Expr::Ident(s)),
+            Token::Num(s) => Ok(Expr::Num(s)),
+            Token::Str(s) => Ok(Expr::Str(s)),
+            Token::Sym("·") => Ok(Expr::Cdot),
+            Token::Sym("↑") => {
+                let arg = self.atom()?;
+                Ok(Expr::Unary {
+                    op: UnOp::Coe,
+                    arg: Box::new(arg),
+                })
+            }
+            Token::Sym("(") => {
+                let first = self.expr(0)?;
+                if self.eat_sym(":") {
+                    let ty = self.expr(0)?;
+                    self.expect_sym(")")?;
+                    return Ok(Expr::Ascription {
+                        expr: Box::new(first),
+                        ty: Box::new(ty),
+                    });
+                }
+                if self.eat_sym(")") {
+                    return Ok(first);
+                }
+                self.expect_sym(",")?;
+                let mut items = vec![first];
+                items.extend(self.comma_list(")")?);
+                Ok(Expr::Tuple {
+                    anonymous: false,
+                    items,
+                })
+            }
+            Token::Sym("⟨") => {
+                let items = self.comma_list("⟩")?;
+                Ok(Expr::Tuple {
+                    anonymous: true,
+                    items,
+                })
+            }
+            Token::Sym("[") => Ok(Expr::List(self.comma_list("]")?)),
+            Token::Sym(s) => Err(perr(format!("unexpected `{s}`"), pos)),
+        }
+    }
+
+    fn comma_list(&mut self, close: &str) -> Result<Vec<Expr>, ParseError> {
+        let mut items = Vec::new();
+        if self.eat_sym(close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(self.expr(0)?);
+            if self.eat_sym(close) {
+                return Ok(items);
+            }
+            self.expect_sym(",")?;
+        }
+    }
+
+    fn names(&mut self) -> Vec<String> {
+        let mut names = Vec::new();
+        while let Some(Token::Ident(n)) = self.peek() {
+            names.push(n.clone());
+            self.pos += 1;
+        }
+        names
+    }
+
+    /// Binder groups up to (not including) `,` / `↦`.
+    fn binders(&mut self) -> Result<Vec<Binder>, ParseError> {
+        let mut out = Vec::new();
+        loop {
+            let bracket = match self.peek_sym() {
+                Some("(") => Some((BinderInfo::Default, ")")),
+                Some("{") => Some((BinderInfo::Implicit, "}")),
+                Some("⦃") => Some((BinderInfo::StrictImplicit, "⦄")),
+                Some("[") => Some((BinderInfo::InstImplicit, "]")),
+                _ => None,
+            };
+            if let Some((info, close)) = bracket {
+                self.pos += 1;
+                let names = self.names();
+                // `[Foo α]` (anonymous instance) parses as a type with no names.
+                let (names, ty) = if self.eat_sym(":") {
+                    (names, Some(self.expr(0)?))
+                } else if info == BinderInfo::InstImplicit {
+                    self.pos -= names.len();
+                    (Vec::new(), Some(self.expr(0)?))
+                } else {
+                    (names, None)
+                };
+                self.expect_sym(close)?;
+                out.push(Binder {
+                    names,
+                    ty,
+                    domain: None,
+                    info,
+                });
+                continue;
+            }
+            let names = self.names();
+            if names.is_empty() {
+                break;
+            }
+            let mut b = Binder {
+                names,
+                ty: None,
+                domain: None,
+                info: BinderInfo::Default,
+            };
+            if self.eat_sym(":") {
+                b.ty = Some(self.expr(0)?);
+            } else if self.eat_sym("∈") || self.eat_ident("in") {
+                b.domain = Some(self.expr(51)?);
+            }
+            out.push(b);
+            if !matches!(self.peek_sym(), Some("(" | "{" | "⦃" | "[")) {
+                break;
+            }
+        }
+        if out.is_empty() {
+            return Err(perr("expected binder", self.pos));
+        }
+        Ok(out)
+    }
+
+    fn eat_ident(&mut self, s: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(n)) if n == s) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Parse one pretty-printed term.
+pub fn parse_expr(s: &str) -> Result<Expr, ParseError> {
+    let toks = tokenize(s)?;
+    let mut p = Parser { toks, pos: 0 };
+    let e = p.expr(0)?;
+    if p.pos != p.toks.len() {
+        return Err(perr("trailing tokens", p.pos));
+    }
+    Ok(e)
+}
+
+/// One local-context entry: `x y : T` or `x : T := v`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hyp {
+    pub names: Vec<String>,
+    pub ty: Expr,
+    pub value: Option<Expr>,
+}
+
+/// Parse a hypothesis line as printed in goals (`h : a ≤ b`, `a b : ℕ`, `x : ℕ := 3`).
+pub fn parse_hyp(s: &str) -> Result<Hyp, ParseError> {
+    let toks = tokenize(s)?;
+    let mut p = Parser { toks, pos: 0 };
+    let names = p.names();
+    if names.is_empty() {
+        return Err(perr("expected hypothesis name", p.pos));
+    }
+    p.expect_sym(":")?;
+    let ty = p.expr(0)?;
+    let value = if p.eat_sym(":=") {
+        Some(p.expr(0)?)
+    } else {
+        None
+    };
+    if p.pos != p.toks.len() {
+        return Err(perr("trailing tokens", p.pos));
+    }
+    Ok(Hyp { names, ty, value })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Goal {
+    pub hyps: Vec<Hyp>,
+    /// Hypothesis lines we could not parse (kept verbatim).
+    pub unparsed_hyps: Vec<String>,
+    pub target: Expr,
+}
+
+/// Parse one pretty-printed goal (`hyps…\n⊢ target`).
+pub fn parse_goal(pretty: &str) -> Result<Goal, ParseError> {
+    let (hyp_lines, target) = crate::lean_lsp::split_plain_goal(pretty);
+    let target = target.ok_or_else(|| perr("missing `⊢`", 0))?;
+    let target = parse_expr(&target)?;
+    let mut hyps = Vec::new();
+    let mut unparsed_hyps = Vec::new();
+    for h in hyp_lines {
+        match parse_hyp(&h) {
+            Ok(x) => hyps.push(x),
+            Err(_) => unparsed_hyps.push(h),
+        }
+    }
+    Ok(Goal {
+        hyps,
+        unparsed_hyps,
+        target,
+    })
+}
+
+/// True if `name` occurs in `e` (as an identifier or the head of a projection like `n.succ`).
+/// Shadowing is ignored, so this can only over-approximate.
+fn mentions(e: &Expr, name: &str) -> bool {
+    let mut hit = false;
+    e.walk(&mut |x| {
+        if let Expr::Ident(s) = x {
+            if s == name || s.strip_prefix(name).is_some_and(|r| r.starts_with('.')) {
+                hit = true;
+            }
+        }
+    });
+    hit
+}
+
+impl Expr {
+    /// Canonical form for comparing statements up to binder presentation:
+    /// - `{n : ℕ}` / `⦃n : ℕ⦄` become explicit, typeclass binders (`[Ring R]`) are dropped;
+    /// - nested `∀`s are merged and regrouped (`∀ (a : ℕ) (b : ℕ), ∀ c : ℕ, …` = `∀ a b c : ℕ, …`);
+    /// - binders that the rest of the statement never mentions become arrows (`∀ (h : P), Q` = `P → Q`).
+    ///
+    /// Bound variable names are kept. The result is for comparison only: it is not the same Lean
+    /// term when implicit arguments are involved.
+    pub fn normalize_binders(&self) -> Expr {
+        let n = |e: &Expr| Box::new(e.normalize_binders());
+        match self {
+            Expr::Ident(_) | Expr::Num(_) | Expr::Str(_) | Expr::Cdot => self.clone(),
+            Expr::App { func, args } => Expr::App {
+                func: n(func),
+                args: args.iter().map(Expr::normalize_binders).collect(),
+            },
+            Expr::Unary { op, arg } => Expr::Unary {
+                op: *op,
+                arg: n(arg),
+            },
+            Expr::Binary { op, lhs, rhs } => Expr::Binary {
+                op: op.clone(),
+                lhs: n(lhs),
+                rhs: n(rhs),
+            },
+            Expr::Arrow { dom, cod } => Expr::Arrow {
+                dom: n(dom),
+                cod: n(cod),
+            },
+            Expr::Binder {
+                kind: BinderKind::Forall,
+                binders,
+                body,
+            } => {
+                // One name per binder, typeclass arguments dropped.
+                let mut singles: Vec<(String, Option<Expr>, Option<Expr>)> = Vec::new();
+                for b in binders
+                    .iter()
+                    .filter(|b| b.info != BinderInfo::InstImplicit)
+                {
+                    for name in &b.names {
+                        singles.push((
+                            name.clone(),
+                            b.ty.as_ref().map(Expr::normalize_binders),
+                            b.domain.as_ref().map(Expr::normalize_binders),
+                        ));
+                    }
+                }
+                // Rebuild from the inside out; the body is already normalized (so its own leading
+                // `∀` is in canonical form and binders merge into it).
+                let mut cur = body.normalize_binders();
+                for (name, ty, domain) in singles.into_iter().rev() {
+                    if let (Some(t), None, false) = (&ty, &domain, mentions(&cur, &name)) {
+                        cur = Expr::Arrow {
+                            dom: Box::new(t.clone()),
+                            cod: Box::new(cur),
+                        };
+                        continue;
+                    }
+                    let b = Binder {
+                        names: vec![name],
+                        ty,
+                        domain,
+                        info: BinderInfo::Default,
+                    };
+                    cur = match cur {
+                        Expr::Binder {
+                            kind: BinderKind::Forall,
+                            mut binders,
+                            body,
+                        } => {
+                            match binders.first_mut() {
+                                Some(first)
+                                    if first.domain.is_none()
+                                        && b.domain.is_none()
+                                        && first.ty.is_some()
+                                        && first.ty == b.ty =>
+                                {
+                                    first.names.insert(0, b.names[0].clone());
+                                }
+                                _ => binders.insert(0, b),
+                            }
+                            Expr::Binder {
+                                kind: BinderKind::Forall,
+                                binders,
+                                body,
+                            }
+                        }
+                        other => Expr::Binder {
+                            kind: BinderKind::Forall,
+                            binders: vec![b],
+                            body: Box::new(other),
+                        },
+                    };
+                }
+                cur
+            }
+            Expr::Binder {
+                kind,
+                binders,
+                body,
+            } => Expr::Binder {
+                kind: kind.clone(),
+                binders: binders
+                    .iter()
+                    .map(|b| Binder {
+                        names: b.names.clone(),
+                        ty: b.ty.as_ref().map(Expr::normalize_binders),
+                        domain: b.domain.as_ref().map(Expr::normalize_binders),
+                        info: b.info,
+                    })
+                    .collect(),
+                body: n(body),
+            },
+            Expr::Tuple { anonymous, items } => Expr::Tuple {
+                anonymous: *anonymous,
+                items: items.iter().map(Expr::normalize_binders).collect(),
+            },
+            Expr::List(items) => Expr::List(items.iter().map(Expr::normalize_binders).collect()),
+            Expr::Ascription { expr, ty } => Expr::Ascription {
+                expr: n(expr),
+                ty: n(ty),
+            },
+        }
+    }
+}
+
+/// Parse a declaration signature (`{n : ℕ} (h : 0 < n) : n ≠ 0`, everything between the name and
+/// `:=`) as the equivalent `∀ {n : ℕ} (h : 0 < n), n ≠ 0`.
+pub fn parse_signature(s: &str) -> Result<Expr, ParseError> {
+    let toks = tokenize(s)?;
+    let mut p = Parser { toks, pos: 0 };
+    let binders = if matches!(p.peek_sym(), Some("(" | "{" | "⦃" | "[")) {
+        p.binders()?
+    } else {
+        Vec::new()
+    };
+    p.expect_sym(":")?;
+    let body = p.expr(0)?;
+    if p.pos != p.toks.len() {
+        return Err(perr("trailing tokens", p.pos));
+    }
+    Ok(if binders.is_empty() {
+        body
+    } else {
+        Expr::Binder {
+            kind: BinderKind::Forall,
+            binders,
+            body: Box::new(body),
+        }
+    })
+}
+
+/// Textual fallback for statements we cannot parse: collapse whitespace and, inside `∀ … ,`
+/// binder lists, make `{…}`/`⦃…⦄` explicit and drop `[…]` instance binders.
+fn normalize_statement_text(s: &str) -> String {
+    let s = s.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut out = String::with_capacity(s.len());
+    let mut in_binders = false;
+    let mut inst_depth = 0usize;
+    for ch in s.chars() {
+        if inst_depth > 0 {
+            match ch {
+                '[' => inst_depth += 1,
+                ']' => inst_depth -= 1,
+                _ => {}
+            }
+            continue;
+        }
+        match ch {
+            '∀' => {
+                in_binders = true;
+                out.push(ch);
+            }
+            ',' if in_binders => {
+                in_binders = false;
+                out.push(ch);
+            }
+            '{' | '⦃' if in_binders => out.push('('),
+            '}' | '⦄' if in_binders => out.push(')'),
+            '[' if in_binders => inst_depth = 1,
+            _ => out.push(ch),
+        }
+    }
+    out.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace(" ,", ",")
+}
+
+/// Binder-normalized text of a statement (`Expr::normalize_binders`), accepting a pretty-printed
+/// proposition (optionally after `⊢`) or a declaration signature. Statements that differ only in
+/// binder presentation map to the same string.
+pub fn normalize_statement(s: &str) -> String {
+    let t = s.trim().trim_start_matches('⊢').trim();
+    let parsed = match t.strip_prefix(':') {
+        Some(rest) => parse_expr(rest),
+        None if matches!(t.chars().next(), Some('(' | '{' | '⦃' | '[')) => {
+            parse_signature(t).or_else(|_| parse_expr(t))
+        }
+        None => parse_expr(t),
+    };
+    match parsed {
+        Ok(e) => e.normalize_binders().to_string(),
+        Err(_) => normalize_statement_text(t),
+    }
+}
+
+/// Tactics whose `[…]` lemma list is a set: order does not change what they do.
+const SIMP_LIKE: &[&str] = &[
+    "simp",
+    "simp?",
+    "simp_all",
+    "simp_arith",
+    "dsimp",
+    "norm_num",
+    "field_simp",
+];
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '\'' | '!' | '?' | '✝')
+}
+
+fn opener(c: char) -> Option<char> {
+    match c {
+        ')' => Some('('),
+        ']' => Some('['),
+        '}' => Some('{'),
+        '⟩' => Some('⟨'),
+        _ => None,
+    }
+}
+
+/// Split `line` at `sep` where it is outside brackets and string literals.
+fn split_top_level(line: &str, sep: impl Fn(&str, usize) -> bool) -> Vec<&str> {
+    let mut out = Vec::new();
+    let (mut depth, mut in_str, mut prev, mut last) = (0usize, false, ' ', 0);
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' if prev != '\\' => in_str = !in_str,
+            _ if in_str => {}
+            '(' | '[' | '{' | '⟨' => depth += 1,
+            _ if opener(c).is_some() => depth = depth.saturating_sub(1),
+            _ if depth == 0 && sep(line, i) => {
+                out.push(&line[last..i]);
+                last = i + c.len_utf8();
+            }
+            _ => {}
+        }
+        prev = c;
+    }
+    out.push(&line[last..]);
+    out
+}
+
+/// Tactics chained with `;` (not `<;>`) on one line, one per line.
+fn split_semicolons(line: &str) -> Vec<&str> {
+    split_top_level(line, |l, i| {
+        l[i..].starts_with(';') && !l[..i].ends_with('<')
+    })
+}
+
+/// `line` with the lemma list of each `SIMP_LIKE` tactic sorted (and duplicates dropped).
+fn sort_simp_lists(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(open) = rest.find('[') {
+        let head = rest[..open].trim_end();
+        let head = head.strip_suffix(" only").unwrap_or(head);
+        let simp_like = SIMP_LIKE.iter().any(|t| {
+            head.strip_suffix(t)
+                .is_some_and(|pre| !pre.ends_with(|c: char| is_word_char(c) || c == '.'))
+        });
+        let mut depth = 0usize;
+        let close = rest[open..].char_indices().find_map(|(i, c)| {
+            match c {
+                '(' | '[' | '{' | '⟨' => depth += 1,
+                _ if opener(c).is_some() => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(open + i);
+                    }
+                }
+                _ => {}
+            }
+            None
+        });
+        let Some(close) = close.filter(|_| simp_like) else {
+            out.push_str(&rest[..=open]);
+            rest = &rest[open + 1..];
+            continue;
+        };
+        let mut lemmas: Vec<String> =
+            split_top_level(&rest[open + 1..close], |l, i| l[i..].starts_with(','))
+                .into_iter()
+                .map(|x| sort_simp_lists(x.trim()))
+                .filter(|x| !x.is_empty())
+                .collect();
+        lemmas.sort();
+        lemmas.dedup();
+        out.push_str(&rest[..open]);
+        out.push('[');
+        out.push_str(&lemmas.join(", "));
+        out.push(']');
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Whole-word occurrences of `names` replaced by their value (not after a `.`, so qualified
+/// names keep their last component).
+fn rename_words(line: &str, names: &std::collections::HashMap<String, String>) -> String {
+    if names.is_empty() {
+        return line.to_string();
+    }
+    let mut out = String::new();
+    let mut word = String::new();
+    let mut after_dot = false;
+    let flush = |out: &mut String, word: &mut String, after_dot: bool| {
+        match names.get(word.as_str()).filter(|_| !after_dot) {
+            Some(n) => out.push_str(n),
+            None => out.push_str(word),
+        }
+        word.clear();
+    };
+    for c in line.chars() {
+        if is_word_char(c) {
+            word.push(c);
+            continue;
+        }
+        flush(&mut out, &mut word, after_dot);
+        after_dot = c == '.';
+        out.push(c);
+    }
+    flush(&mut out, &mut word, after_dot);
+    out
+}
+
+/// Names introduced by `have` renamed `h✝1`, `h✝2`, … in order, uses included (a later `have`
+/// of the same name shadows the earlier one from its own line on).
+fn rename_have_binders(lines: Vec<String>) -> Vec<String> {
+    let mut names: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    let mut n = 0;
+    lines
+        .into_iter()
+        .map(|l| {
+            let Some(rest) = l.strip_prefix("have ") else {
+                return rename_words(&l, &names);
+            };
+            let name: String = rest.chars().take_while(|c| is_word_char(*c)).collect();
+            let after = &rest[name.len()..];
+            if name.is_empty() || name == "this" || !(after.starts_with(' ') || after.is_empty()) {
+                return rename_words(&l, &names);
+            }
+            // The statement and proof still see the earlier binding.
+            let body = rename_words(after, &names);
+            n += 1;
+            let fresh = format!("h✝{n}");
+            names.insert(name, fresh.clone());
+            format!("have {fresh}{body}")
+        })
+        .collect()
+}
+
+/// Deduplication key for a tactic candidate; variants that elaborate alike share it:
+/// - whitespace is collapsed and blank lines are dropped;
+/// - tactics chained with `;` go on their own lines, as if separated by newlines;
+/// - the lemma lists of `simp`-like tactics (`SIMP_LIKE`) are sorted;
+/// - the statements of `have name : … :=` lines are binder-normalized;
+/// - names introduced by `have` are renamed in order of appearance (`h✝1`, …), uses included.
+pub fn candidate_key(cand: &str) -> String {
+    let lines: Vec<String> = cand
+        .lines()
+        .flat_map(split_semicolons)
+        .map(|l| sort_simp_lists(&l.split_whitespace().collect::<Vec<_>>().join(" ")))
+        .filter(|l| !l.is_empty())
+        .map(|t| {
+            let Some(rest) = t.strip_prefix("have ") else {
+                return t;
+            };
+            match (rest.find(" : "), rest.rfind(" :=")) {
+                (Some(c), Some(e)) if c < e => format!(
+                    "have {} : {} :={}",
+                    rest[..c].trim(),
+                    normalize_statement(&rest[c + 3..e]),
+                    &rest[e + 3..]
+                ),
+                _ => t,
+            }
+        })
+        .collect();
+    rename_have_binders(lines).join("\n")
+}
+
+/// Hash of `candidate_key` (stable within a process only), for cheap duplicate checks before
+/// verification.
+pub fn candidate_hash(cand: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    candidate_key(cand).hash(&mut h);
+    h.finish()
+}
+
+/// Drop candidates whose `candidate_hash` an earlier one already had, keeping order; returns
+/// how many were dropped.
+pub fn dedup_candidates(cands: &mut Vec<String>) -> usize {
+    let before = cands.len();
+    let mut seen = std::collections::HashSet::new();
+    cands.retain(|c| seen.insert(candidate_hash(c)));
+    before - cands.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(s: &str) -> String {
+        parse_expr(s).expect("parse").to_string()
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        let e = parse_expr("a + b * c ≤ d ^ 2 ^ k → ¬p x ∧ q").unwrap();
+        let Expr::Arrow { dom, cod } = &e else {
+            panic!("expected arrow, got {e:?}");
+        };
+        assert!(matches!(**dom, Expr::Binary { op: BinOp::Le, .. }));
+        assert!(matches!(**cod, Expr::Binary { op: BinOp::And, .. }));
+        assert_eq!(roundtrip("(a + b) * c"), "(a + b) * c");
+        assert_eq!(roundtrip("a - (b - c)"), "a - (b - c)");
+        assert_eq!(roundtrip("a → b → c"), "a → b → c");
+        assert_eq!(roundtrip("(a → b) → c"), "(a → b) → c");
+    }
+
+    #[test]
+    fn binders_applications_and_unicode() {
+        let e = parse_expr("∀ (n : ℕ) {m : ℕ}, n ≤ m → ∃ k, m = n + k").unwrap();
+        let Expr::Binder { kind, binders, .. } = &e else {
+            panic!("expected binder");
+        };
+        assert_eq!(*kind, BinderKind::Forall);
+        assert_eq!(binders.len(), 2);
+        assert_eq!(binders[1].info, BinderInfo::Implicit);
+
+        let e = parse_expr("∑ i ∈ Finset.range n, f ↑i").unwrap();
+        assert!(matches!(
+            e,
+            Expr::Binder {
+                kind: BinderKind::BigOp(_),
+                ..
+            }
+        ));
+
+        let e = parse_expr("Nat.succ (x.1 + ?m.12)").unwrap();
+        let (head, args) = e.head_args();
+        assert_eq!(head.ident(), Some("Nat.succ"));
+        assert_eq!(args.len(), 1);
+        assert!(parse_expr("a ≤ b ∧ fun x => x").is_ok());
+    }
+
+    #[test]
+    fn goals_and_hyps() {
+        let g = parse_goal("a b : ℕ\nh : a <= b\nx : ℕ := 3\n⊢ a < b + 1").unwrap();
+        assert_eq!(g.hyps.len(), 4);
+        assert!(g.hyps[0].ty.is_nat_type());
+        assert!(g.hyps[3].value.is_some());
+        assert!(matches!(g.target, Expr::Binary { op: BinOp::Lt, .. }));
+        assert!(parse_expr("a ≤").is_err());
+        assert!(parse_expr("{x | p x}").is_err());
+    }
+
+    #[test]
+    fn binder_normalization() {
+        let a = normalize_statement("∀ {n : ℕ}, 0 < n → n ≠ 0");
+        assert_eq!(a, "∀ n : ℕ, 0 < n → n ≠ 0");
+        assert_eq!(normalize_statement("∀ (n : ℕ), 0 < n → n ≠ 0"), a);
+        assert_eq!(normalize_statement("{n : ℕ} (h : 0 < n) : n ≠ 0"), a);
+        assert_eq!(
+            normalize_statement(
+                "∀ {α : Type} [inst : LinearOrder α] (a : α) (b : α), ∀ ⦃c : α⦄, a ≤ c"
+            ),
+            normalize_statement("∀ (α : Type) (a b c : α), a ≤ c")
+        );
+        assert_ne!(a, normalize_statement("∀ (n : ℤ), 0 < n → n ≠ 0"));
+        assert_eq!(
+            normalize_statement("∀ {x : T} [Foo x], {x | p x} = s"),
+            "∀ (x : T), {x | p x} = s"
+        );
+        assert_eq!(
+            candidate_key("have h : ∀ {n : ℕ}, n ≤ n := by\n  intro n; rfl"),
+            candidate_key("have h : ∀ (n : ℕ), n ≤ n := by\n    intro n;  rfl")
+        );
+    }
+
+    #[test]
+    fn candidate_keys_identify_trivial_variants() {
+        let same =
+            |a: &str, b: &str| assert_eq!(candidate_key(a), candidate_key(b), "{a:?} vs {b:?}");
+        same("by\n  intro n; simp", "by\n  intro n\n  simp");
+        same(
+            "by\n  simp only [Nat.add_comm, foo, Nat.add_comm] at h ⊢",
+            "by\n  simp only [foo,Nat.add_comm] at h ⊢",
+        );
+        same(
+            "by\n  have h1 : 0 < n := by omega\n  exact Nat.pos_of_ne_zero (ne_of_gt h1)",
+            "by\n  have hn : 0 < n := by omega\n  exact Nat.pos_of_ne_zero (ne_of_gt hn)",
+        );
+        // Chained with `<;>`, in parentheses, and `rw` lists (ordered) stay as they are.
+        assert_eq!(
+            candidate_key("by\n  cases h <;> simp"),
+            "by\ncases h <;> simp"
+        );
+        assert_eq!(candidate_key("by\n  (simp; done)"), "by\n(simp; done)");
+        assert_ne!(
+            candidate_key("by\n  rw [a, b]"),
+            candidate_key("by\n  rw [b, a]")
+        );
+        // `h.1` renames with `h`, `Foo.h` does not.
+        assert_eq!(
+            candidate_key("have h : p ∧ q := hpq\nexact ⟨h.1, Foo.h⟩"),
+            "have h✝1 : p ∧ q := hpq\nexact ⟨h✝1.1, Foo.h⟩"
+        );
+
+        let mut cands: Vec<String> = ["by simp [a, b]", "by  simp [b, a]", "by omega"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(dedup_candidates(&mut cands), 1);
+        assert_eq!(cands, ["by simp [a, b]", "by omega"]);
+    }
+}
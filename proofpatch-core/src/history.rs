@@ -0,0 +1,307 @@
+//! Run history and fine-tuning export.
+//!
+//! With `RepairOptions::record_history`, every `repair_decl` run appends a `RunRecord` to
+//! `.generated/proofpatch-history/runs.jsonl`: the goal at the placeholder, the LLM excerpt of
+//! the file, the last prompt the LLM was asked with, the accepted patch, how the run ended, and
+//! the tactic family, model and result of every attempt (the counts behind `priors`).
+//!
+//! `export` turns records into chat-format training rows (`ChatFormat`): the prompt is the
+//! recorded one, or the built-in `repair` template rendered from the goal and context when the
+//! LLM was not involved, and the completion is the accepted patch. Rows are filtered by stop
+//! reason and deduplicated by (binder-normalized goal or context, candidate key of the patch).
+
+use crate::prompts::{PromptTemplate, PromptVars, RenderedPrompt};
+use crate::repair::RepairOutcome;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub file: String,
+    pub decl: String,
+    /// Pretty-printed goal at the placeholder, when the goal dump succeeded.
+    #[serde(default)]
+    pub goal: Option<String>,
+    /// The excerpt of the file the LLM is shown (`prompt_context::minimal_context`).
+    #[serde(default)]
+    pub context: String,
+    /// The last prompt sent to the LLM (`None`: the LLM was not used).
+    #[serde(default)]
+    pub prompt: Option<RenderedPrompt>,
+    /// The accepted (minimized) proof.
+    #[serde(default)]
+    pub patch: Option<String>,
+    /// Which generator proposed `patch` (`RepairAttempt::source`).
+    #[serde(default)]
+    pub patch_source: Option<String>,
+    pub ok: bool,
+    /// `RepairOutcome::stop_reason`.
+    pub stop_reason: String,
+    pub verifications: usize,
+    /// Unix seconds.
+    #[serde(default)]
+    pub added_at: u64,
+    /// The verified attempts, for success-rate priors (`priors`).
+    #[serde(default)]
+    pub attempts: Vec<AttemptRecord>,
+    /// `RepairOutcome::smt_entails`, for difficulty estimates (`difficulty`).
+    #[serde(default)]
+    pub smt_entails: Option<bool>,
+}
+
+/// One verified candidate of a run, reduced to what `priors` counts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttemptRecord {
+    /// `RepairAttempt::source`.
+    pub source: String,
+    /// Tactic family of the candidate (`priors::family`).
+    pub family: String,
+    /// The model that proposed it (`RepairAttempt::model`).
+    #[serde(default)]
+    pub model: Option<String>,
+    pub ok: bool,
+    /// `RepairAttempt::round`.
+    #[serde(default)]
+    pub round: usize,
+}
+
+impl RunRecord {
+    pub fn from_outcome(o: &RepairOutcome, context: &str) -> Self {
+        Self {
+            file: o.file.clone(),
+            decl: o.decl.clone(),
+            goal: o.goal.clone(),
+            context: context.to_string(),
+            prompt: o.llm_prompt.clone(),
+            patch: o.solution.clone(),
+            patch_source: o
+                .attempts
+                .iter()
+                .rev()
+                .find(|a| a.ok)
+                .map(|a| a.source.clone()),
+            ok: o.ok,
+            stop_reason: o.stop_reason.clone(),
+            verifications: o.verifications,
+            added_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            attempts: o
+                .attempts
+                .iter()
+                .map(|a| AttemptRecord {
+                    source: a.source.clone(),
+                    family: crate::priors::family(&a.candidate),
+                    model: a.model.clone(),
+                    ok: a.ok,
+                    round: a.round,
+                })
+                .collect(),
+            smt_entails: o.smt_entails,
+        }
+    }
+}
+
+pub fn path(repo_root: &Path) -> PathBuf {
+    repo_root
+        .join(".generated")
+        .join("proofpatch-history")
+        .join("runs.jsonl")
+}
+
+pub fn append(repo_root: &Path, rec: &RunRecord) -> Result<(), String> {
+    let p = path(repo_root);
+    if let Some(dir) = p.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("mkdir {}: {e}", dir.display()))?;
+    }
+    let line = serde_json::to_string(rec).map_err(|e| e.to_string())?;
+    let mut f = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&p)
+        .map_err(|e| format!("open {}: {e}", p.display()))?;
+    writeln!(f, "{line}").map_err(|e| format!("write {}: {e}", p.display()))
+}
+
+/// The repo's history, oldest first (empty when there is none). Unparseable lines are skipped.
+pub fn load(repo_root: &Path) -> Vec<RunRecord> {
+    std::fs::read_to_string(path(repo_root))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatFormat {
+    /// `{"messages": [{"role": "system"|"user"|"assistant", "content"}]}` (OpenAI, and most
+    /// local trainers).
+    Openai,
+    /// `{"conversations": [{"from": "system"|"human"|"gpt", "value"}]}`.
+    Sharegpt,
+    /// `{"instruction", "input", "output"}`, with the system prompt as the instruction.
+    Alpaca,
+}
+
+impl ChatFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_lowercase().as_str() {
+            "openai" | "messages" => Ok(Self::Openai),
+            "sharegpt" => Ok(Self::Sharegpt),
+            "alpaca" => Ok(Self::Alpaca),
+            other => Err(format!(
+                "unknown chat format: {other} (expected openai|sharegpt|alpaca)"
+            )),
+        }
+    }
+
+    fn row(self, system: &str, user: &str, completion: &str) -> Value {
+        match self {
+            Self::Openai => json!({ "messages": [
+                { "role": "system", "content": system },
+                { "role": "user", "content": user },
+                { "role": "assistant", "content": completion },
+            ]}),
+            Self::Sharegpt => json!({ "conversations": [
+                { "from": "system", "value": system },
+                { "from": "human", "value": user },
+                { "from": "gpt", "value": completion },
+            ]}),
+            Self::Alpaca => json!({
+                "instruction": system,
+                "input": user,
+                "output": completion,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ChatFormat,
+    /// Keep records with one of these stop reasons (empty: any). Records without an accepted
+    /// patch have no completion and are never exported.
+    pub outcomes: Vec<String>,
+    pub dedupe: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ChatFormat::Openai,
+            outcomes: vec!["solved".to_string()],
+            dedupe: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportReport {
+    pub records: usize,
+    pub exported: usize,
+    pub skipped_outcome: usize,
+    pub skipped_no_patch: usize,
+    pub duplicates: usize,
+    #[serde(skip)]
+    pub rows: Vec<Value>,
+}
+
+/// Training rows for `records` (see the module docs).
+pub fn export(records: &[RunRecord], opts: &ExportOptions) -> Result<ExportReport, String> {
+    let template = PromptTemplate::builtin("repair")
+        .ok_or_else(|| "missing built-in repair template".to_string())?;
+    let mut report = ExportReport {
+        records: records.len(),
+        ..Default::default()
+    };
+    let mut seen = HashSet::new();
+    for r in records {
+        if !opts.outcomes.is_empty() && !opts.outcomes.contains(&r.stop_reason) {
+            report.skipped_outcome += 1;
+            continue;
+        }
+        let Some(patch) = r.patch.as_deref().filter(|p| !p.trim().is_empty()) else {
+            report.skipped_no_patch += 1;
+            continue;
+        };
+        let key = (
+            crate::goal_ast::normalize_statement(r.goal.as_deref().unwrap_or(&r.context)),
+            crate::goal_ast::candidate_key(patch),
+        );
+        if opts.dedupe && !seen.insert(key) {
+            report.duplicates += 1;
+            continue;
+        }
+        let prompt = match &r.prompt {
+            Some(p) => p.clone(),
+            None => template.render(
+                &PromptVars::new()
+                    .set("context", r.context.as_str())
+                    .set("goal", r.goal.clone().unwrap_or_default()),
+            )?,
+        };
+        report
+            .rows
+            .push(opts.format.row(&prompt.system, &prompt.user, patch.trim()));
+    }
+    report.exported = report.rows.len();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_solved_runs_once_in_each_format() {
+        let td = tempfile::tempdir().unwrap();
+        let rec = |goal: &str, patch: Option<&str>, stop: &str| RunRecord {
+            file: "A.lean".to_string(),
+            decl: "foo".to_string(),
+            goal: Some(goal.to_string()),
+            context: "theorem foo : 1 + 1 = 2 := by\n  sorry".to_string(),
+            prompt: None,
+            patch: patch.map(str::to_string),
+            patch_source: None,
+            ok: patch.is_some(),
+            stop_reason: stop.to_string(),
+            verifications: 1,
+            added_at: 0,
+            attempts: Vec::new(),
+            smt_entails: None,
+        };
+        append(
+            td.path(),
+            &rec("⊢ 1 + 1 = 2", Some("by norm_num"), "solved"),
+        )
+        .unwrap();
+        append(
+            td.path(),
+            &rec("⊢ 1 + 1 = 2", Some("by  norm_num"), "solved"),
+        )
+        .unwrap();
+        append(td.path(), &rec("⊢ 2 + 2 = 4", None, "budget_exhausted")).unwrap();
+        let records = load(td.path());
+        assert_eq!(records.len(), 3);
+
+        let r = export(&records, &ExportOptions::default()).unwrap();
+        assert_eq!((r.exported, r.duplicates, r.skipped_outcome), (1, 1, 1));
+        let m = &r.rows[0]["messages"];
+        assert_eq!(m[2]["content"], "by norm_num");
+        assert!(m[1]["content"].as_str().unwrap().contains("1 + 1 = 2"));
+
+        let opts = ExportOptions {
+            format: ChatFormat::parse("alpaca").unwrap(),
+            outcomes: Vec::new(),
+            dedupe: false,
+        };
+        let r = export(&records, &opts).unwrap();
+        assert_eq!((r.exported, r.skipped_no_patch), (2, 1));
+        assert_eq!(r.rows[1]["output"], "by  norm_num");
+    }
+}
@@ -0,0 +1,286 @@
+//! Module dependency graph from `import` headers.
+//!
+//! Every `.lean` file in the repo becomes a node named by its module (`Foo/Bar.lean` →
+//! `Foo.Bar`); edges point from a module to the in-repo modules it imports. Imports of modules
+//! outside the repo (Mathlib, Std, …) are recorded per node but are not nodes themselves.
+//!
+//! Uses:
+//! - `rebuild_set`: a changed module plus everything that transitively imports it, i.e. what
+//!   `lake build` has to re-elaborate; `build_targets` reduces it to the modules nothing else in
+//!   the set imports (building those builds the rest).
+//! - `topo_order` / `schedule_files`: dependencies before dependents, so batch repairs fix a
+//!   module before the modules that import it are checked.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModuleNode {
+    pub file: String,
+    /// In-repo modules imported by this one.
+    pub imports: Vec<String>,
+    /// Imports that do not resolve to a file in the repo.
+    pub external: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportGraph {
+    pub modules: BTreeMap<String, ModuleNode>,
+}
+
+/// Module names imported by a file's header.
+///
+/// The header ends at the first line that is not blank, a comment, `prelude`, `module`, or an
+/// import. Handles several modules per `import`, trailing comments, and the module-system forms
+/// `public import`, `meta import`, and `import all`.
+pub fn parse_imports(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut in_block = 0usize;
+    for raw in text.lines() {
+        let mut line = raw.trim();
+        if in_block > 0 || line.starts_with("/-") {
+            let (opens, closes) = (line.matches("/-").count(), line.matches("-/").count());
+            in_block = (in_block + opens).saturating_sub(closes);
+            continue;
+        }
+        if let Some(i) = line.find("--") {
+            line = line[..i].trim();
+        }
+        if line.is_empty() || line == "prelude" || line == "module" {
+            continue;
+        }
+        let rest = line
+            .trim_start_matches("public ")
+            .trim_start_matches("meta ")
+            .trim_start_matches("private ");
+        let Some(rest) = rest.strip_prefix("import ") else {
+            break;
+        };
+        out.extend(
+            rest.split_whitespace()
+                .filter(|m| *m != "all")
+                .map(str::to_string),
+        );
+    }
+    out
+}
+
+impl ImportGraph {
+    /// Build the graph from `(file_rel, text)` pairs.
+    pub fn from_sources<'a>(files: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut parsed: Vec<(String, String, Vec<String>)> = Vec::new();
+        for (file, text) in files {
+            if let Some(m) = crate::module_name_from_file_rel(file) {
+                parsed.push((m, file.to_string(), parse_imports(text)));
+            }
+        }
+        let known: BTreeSet<String> = parsed.iter().map(|(m, _, _)| m.clone()).collect();
+        let mut modules = BTreeMap::new();
+        for (m, file, imports) in parsed {
+            let (inside, outside): (Vec<String>, Vec<String>) =
+                imports.into_iter().partition(|i| known.contains(i));
+            modules.insert(
+                m,
+                ModuleNode {
+                    file,
+                    imports: inside,
+                    external: outside,
+                },
+            );
+        }
+        Self { modules }
+    }
+
+    /// Build the graph for every `.lean` file in the repo (the lakefile excluded).
+    pub fn build(repo_root: &Path) -> Result<Self, String> {
+        let repo_root = crate::find_lean_repo_root(repo_root)?;
+        let mut sources = Vec::new();
+        for rel in crate::scan::list_lean_files(&repo_root) {
+            if rel == "lakefile.lean" {
+                continue;
+            }
+            let text = std::fs::read_to_string(repo_root.join(&rel))
+                .map_err(|e| format!("read {rel}: {e}"))?;
+            sources.push((rel, text));
+        }
+        Ok(Self::from_sources(
+            sources.iter().map(|(f, t)| (f.as_str(), t.as_str())),
+        ))
+    }
+
+    pub fn module_for_file(&self, file_rel: &str) -> Option<&str> {
+        self.modules
+            .iter()
+            .find(|(_, n)| n.file == file_rel)
+            .map(|(m, _)| m.as_str())
+    }
+
+    /// Reverse edges: module → modules that import it directly.
+    fn dependents_map(&self) -> BTreeMap<&str, Vec<&str>> {
+        let mut rev: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (m, n) in &self.modules {
+            for i in &n.imports {
+                rev.entry(i.as_str()).or_default().push(m.as_str());
+            }
+        }
+        rev
+    }
+
+    /// Modules that transitively import `module` (excluding `module`).
+    pub fn dependents(&self, module: &str) -> BTreeSet<String> {
+        let rev = self.dependents_map();
+        let mut seen = BTreeSet::new();
+        let mut queue = VecDeque::from([module]);
+        while let Some(m) = queue.pop_front() {
+            for d in rev.get(m).into_iter().flatten() {
+                if seen.insert(d.to_string()) {
+                    queue.push_back(d);
+                }
+            }
+        }
+        seen.remove(module);
+        seen
+    }
+
+    /// Kahn's algorithm over in-repo edges; dependencies first, ties by name.
+    ///
+    /// On a cycle, returns the modules that could not be ordered.
+    pub fn topo_order(&self) -> Result<Vec<String>, Vec<String>> {
+        let mut indeg: BTreeMap<&str, usize> = self
+            .modules
+            .iter()
+            .map(|(m, n)| (m.as_str(), n.imports.len()))
+            .collect();
+        let rev = self.dependents_map();
+        let mut ready: BTreeSet<&str> = indeg
+            .iter()
+            .filter(|(_, d)| **d == 0)
+            .map(|(m, _)| *m)
+            .collect();
+        let mut out = Vec::new();
+        while let Some(m) = ready.pop_first() {
+            out.push(m.to_string());
+            for d in rev.get(m).into_iter().flatten() {
+                if let Some(k) = indeg.get_mut(d) {
+                    *k -= 1;
+                    if *k == 0 {
+                        ready.insert(d);
+                    }
+                }
+            }
+        }
+        if out.len() == self.modules.len() {
+            Ok(out)
+        } else {
+            let done: BTreeSet<&str> = out.iter().map(String::as_str).collect();
+            Err(self
+                .modules
+                .keys()
+                .filter(|m| !done.contains(m.as_str()))
+                .cloned()
+                .collect())
+        }
+    }
+
+    /// Changed modules plus all their dependents, in topological order.
+    pub fn rebuild_set(&self, changed: &[String]) -> Vec<String> {
+        let mut set: BTreeSet<String> = BTreeSet::new();
+        for c in changed {
+            if self.modules.contains_key(c) {
+                set.insert(c.clone());
+                set.extend(self.dependents(c));
+            }
+        }
+        match self.topo_order() {
+            Ok(order) => order.into_iter().filter(|m| set.contains(m)).collect(),
+            Err(_) => set.into_iter().collect(),
+        }
+    }
+
+    /// Minimal `lake build` targets covering `rebuild_set(changed)`: the members no other member
+    /// imports.
+    pub fn build_targets(&self, changed: &[String]) -> Vec<String> {
+        let set = self.rebuild_set(changed);
+        let members: BTreeSet<&str> = set.iter().map(String::as_str).collect();
+        let imported: BTreeSet<&str> = set
+            .iter()
+            .filter_map(|m| self.modules.get(m))
+            .flat_map(|n| n.imports.iter().map(String::as_str))
+            .filter(|i| members.contains(i))
+            .collect();
+        set.iter()
+            .filter(|m| !imported.contains(m.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    /// Order repo-relative files dependencies-first (unknown files keep their order, last).
+    pub fn schedule_files(&self, files: &[String]) -> Vec<String> {
+        let rank: BTreeMap<String, usize> = self
+            .topo_order()
+            .unwrap_or_else(|_| self.modules.keys().cloned().collect())
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, m)| self.modules.get(&m).map(|n| (n.file.clone(), i)))
+            .collect();
+        let mut out = files.to_vec();
+        out.sort_by_key(|f| rank.get(f).copied().unwrap_or(usize::MAX));
+        out
+    }
+
+    /// Graphviz `dot` rendering.
+    pub fn to_dot(&self) -> String {
+        let mut s = String::from("digraph imports {\n");
+        for (m, n) in &self.modules {
+            s.push_str(&format!("  \"{m}\";\n"));
+            for i in &n.imports {
+                s.push_str(&format!("  \"{m}\" -> \"{i}\";\n"));
+            }
+        }
+        s.push_str("}\n");
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_headers_and_computes_rebuild_sets() {
+        let a = "/- Copyright\n  header -/\nimport Mathlib.Data.Nat.Basic -- nat\nimport Foo.B Foo.C\n\ntheorem x : True := trivial\nimport Foo.D\n";
+        assert_eq!(
+            parse_imports(a),
+            vec!["Mathlib.Data.Nat.Basic", "Foo.B", "Foo.C"]
+        );
+        assert_eq!(
+            parse_imports("module\npublic import all Foo.B\n"),
+            vec!["Foo.B"]
+        );
+
+        let g = ImportGraph::from_sources([
+            ("Foo/A.lean", a),
+            ("Foo/B.lean", "import Foo.C\n"),
+            ("Foo/C.lean", "import Mathlib\n"),
+            ("Foo/E.lean", "import Foo.B\n"),
+        ]);
+        assert_eq!(g.modules["Foo.A"].external, vec!["Mathlib.Data.Nat.Basic"]);
+        assert_eq!(
+            g.topo_order().unwrap(),
+            vec!["Foo.C", "Foo.B", "Foo.A", "Foo.E"]
+        );
+        assert_eq!(
+            g.rebuild_set(&["Foo.B".into()]),
+            vec!["Foo.B", "Foo.A", "Foo.E"]
+        );
+        assert_eq!(g.build_targets(&["Foo.C".into()]), vec!["Foo.A", "Foo.E"]);
+        assert_eq!(
+            g.schedule_files(&["Foo/E.lean".into(), "X.lean".into(), "Foo/C.lean".into()]),
+            vec!["Foo/C.lean", "Foo/E.lean", "X.lean"]
+        );
+
+        let cyc = ImportGraph::from_sources([("P.lean", "import Q\n"), ("Q.lean", "import P\n")]);
+        assert_eq!(cyc.topo_order().unwrap_err(), vec!["P", "Q"]);
+    }
+}
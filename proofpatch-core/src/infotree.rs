@@ -0,0 +1,521 @@
+//! InfoTree / elaborated-term goal ingestion.
+//!
+//! `pretty` text loses information (implicit arguments, coercions, shadowed names). A Lean-side
+//! exporter can instead emit goals with the elaborated `Expr` of every hypothesis and target:
+//!
+//! ```json
+//! {"tool":"proofpatch","kind":"infotree","goals":[{
+//!    "mvarId":"_uniq.9",
+//!    "target": {"app":[{"app":[{"const":"LE.le"}, …]}, …]},
+//!    "hyps":[{"fvarId":"_uniq.4","userName":"n","type":{"const":"Nat"}}]
+//! }]}
+//! ```
+//!
+//! Terms use one JSON object per `Lean.Expr` constructor (`bvar`, `fvar`, `mvar`, `sort`,
+//! `const`, `app`, `lam`, `forallE`, `letE`, `lit`, `mdata`, `proj`). Applications may be given
+//! curried (`{"app":[f,a]}`) or as a spine (`{"app":[f,a,b,c]}`).
+//!
+//! We decode these into `goal_ast::Expr`, mapping fvars to stable user-facing names (shadowed
+//! names get a numeric suffix), and can re-emit the result as an ordinary `pp_dump` so every
+//! downstream consumer accepts it.
+
+use crate::goal_ast::{BinOp, Binder, BinderInfo, BinderKind, Expr, Goal, Hyp, UnOp};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Stable names for free variables, in local-context order.
+#[derive(Debug, Clone, Default)]
+pub struct FVarNames {
+    by_id: BTreeMap<String, String>,
+    taken: BTreeMap<String, usize>,
+}
+
+impl FVarNames {
+    /// Register an fvar; returns the stable name (user name, suffixed `_1`, `_2`, … on reuse).
+    pub fn insert(&mut self, fvar_id: &str, user_name: &str) -> String {
+        // Inaccessible names (`n✝`) print with a dagger; give them a usable base name.
+        let base = match user_name.trim_end_matches('✝') {
+            "" => "x".to_string(),
+            b => b.to_string(),
+        };
+        let n = self.taken.entry(base.clone()).or_insert(0);
+        let name = if *n == 0 {
+            base.clone()
+        } else {
+            format!("{base}_{n}")
+        };
+        *n += 1;
+        self.by_id.insert(fvar_id.to_string(), name.clone());
+        name
+    }
+
+    pub fn get(&self, fvar_id: &str) -> Option<&str> {
+        self.by_id.get(fvar_id).map(|s| s.as_str())
+    }
+
+    /// `fvarId -> stable name` mapping (for tools that need to go back to Lean).
+    pub fn to_json(&self) -> Value {
+        json!(self.by_id)
+    }
+}
+
+struct Decoder<'a> {
+    fvars: &'a FVarNames,
+    /// Bound variable names, innermost last.
+    bound: Vec<String>,
+}
+
+fn field<'v>(v: &'v Value, k: &str) -> Option<&'v Value> {
+    v.as_object().and_then(|o| o.get(k))
+}
+
+fn const_display(name: &str) -> String {
+    match name {
+        "Nat" => "ℕ".to_string(),
+        "Int" => "ℤ".to_string(),
+        "Rat" => "ℚ".to_string(),
+        "Real" => "ℝ".to_string(),
+        "Complex" => "ℂ".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Const name → (binary operator, explicit arity). Implicit type/instance arguments come first.
+fn binop_for_const(name: &str) -> Option<(BinOp, usize)> {
+    Some(match name {
+        "HAdd.hAdd" => (BinOp::Add, 6),
+        "HSub.hSub" => (BinOp::Sub, 6),
+        "HMul.hMul" => (BinOp::Mul, 6),
+        "HDiv.hDiv" => (BinOp::Div, 6),
+        "HMod.hMod" => (BinOp::Mod, 6),
+        "HPow.hPow" => (BinOp::Pow, 6),
+        "LE.le" => (BinOp::Le, 4),
+        "LT.lt" => (BinOp::Lt, 4),
+        "GE.ge" => (BinOp::Ge, 4),
+        "GT.gt" => (BinOp::Gt, 4),
+        "Eq" => (BinOp::Eq, 3),
+        "Ne" => (BinOp::Ne, 3),
+        "Dvd.dvd" => (BinOp::Dvd, 4),
+        "And" => (BinOp::And, 2),
+        "Or" => (BinOp::Or, 2),
+        "Iff" => (BinOp::Iff, 2),
+        "Membership.mem" => (BinOp::Mem, 5),
+        "HasSubset.Subset" => (BinOp::Subset, 4),
+        _ => return None,
+    })
+}
+
+impl Decoder<'_> {
+    fn spine(&self, v: &Value) -> Result<(Value, Vec<Value>), String> {
+        // Flatten nested `app` nodes into head + args.
+        let mut args: Vec<Value> = Vec::new();
+        let mut cur = v.clone();
+        while let Some(xs) = field(&cur, "app").and_then(|x| x.as_array()) {
+            if xs.is_empty() {
+                return Err("empty app".to_string());
+            }
+            let mut rest: Vec<Value> = xs[1..].to_vec();
+            rest.append(&mut args);
+            args = rest;
+            cur = xs[0].clone();
+        }
+        Ok((cur, args))
+    }
+
+    fn decode(&mut self, v: &Value) -> Result<Expr, String> {
+        if let Some(i) = field(v, "bvar").and_then(|x| x.as_u64()) {
+            let i = i as usize;
+            let n = self.bound.len();
+            return self
+                .bound
+                .get(n.wrapping_sub(i + 1))
+                .map(|s| Expr::Ident(s.clone()))
+                .ok_or_else(|| format!("loose bvar {i}"));
+        }
+        if let Some(id) = field(v, "fvar").and_then(|x| x.as_str()) {
+            return Ok(Expr::Ident(
+                self.fvars
+                    .get(id)
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| id.to_string()),
+            ));
+        }
+        if let Some(id) = field(v, "mvar").and_then(|x| x.as_str()) {
+            return Ok(Expr::Ident(format!("?{}", id.trim_start_matches("_uniq."))));
+        }
+        if let Some(s) = field(v, "sort") {
+            let lvl = s.as_str().unwrap_or("");
+            return Ok(match lvl {
+                "0" => Expr::Ident("Prop".to_string()),
+                "1" => Expr::Ident("Type".to_string()),
+                l => Expr::App {
+                    func: Box::new(Expr::Ident("Sort".to_string())),
+                    args: vec![Expr::Ident(l.to_string())],
+                },
+            });
+        }
+        if let Some(c) = field(v, "const") {
+            let name = c
+                .as_str()
+                .or_else(|| field(c, "name").and_then(|x| x.as_str()))
+                .ok_or("const without name")?;
+            return Ok(Expr::Ident(const_display(name)));
+        }
+        if let Some(l) = field(v, "lit") {
+            if let Some(n) = field(l, "natVal") {
+                return Ok(Expr::Num(
+                    n.as_str()
+                        .map(|s| s.to_string())
+                        .unwrap_or_else(|| n.to_string()),
+                ));
+            }
+            if let Some(s) = field(l, "strVal").and_then(|x| x.as_str()) {
+                return Ok(Expr::Str(s.to_string()));
+            }
+            return Err("unknown literal".to_string());
+        }
+        if let Some(e) = field(v, "mdata") {
+            let inner = field(e, "expr").unwrap_or(e);
+            return self.decode(inner);
+        }
+        if let Some(p) = field(v, "proj") {
+            let idx = field(p, "idx").and_then(|x| x.as_u64()).unwrap_or(0);
+            let inner = self.decode(field(p, "expr").ok_or("proj without expr")?)?;
+            return Ok(Expr::Ident(format!("{inner}.{}", idx + 1)));
+        }
+        if let Some(b) = field(v, "forallE").or_else(|| field(v, "lam")) {
+            return self.binder(b, field(v, "lam").is_some());
+        }
+        if let Some(b) = field(v, "letE") {
+            // Zeta-reduce for display purposes: `let x := v; b` becomes `b` with `x` named.
+            let name = field(b, "name").and_then(|x| x.as_str()).unwrap_or("x");
+            self.bound.push(name.to_string());
+            let body = self.decode(field(b, "body").ok_or("letE without body")?);
+            self.bound.pop();
+            return body;
+        }
+        if field(v, "app").is_some() {
+            return self.app(v);
+        }
+        Err(format!("unrecognized term node: {}", truncate(v)))
+    }
+
+    fn binder(&mut self, b: &Value, is_lam: bool) -> Result<Expr, String> {
+        let name = field(b, "name")
+            .or_else(|| field(b, "binderName"))
+            .and_then(|x| x.as_str())
+            .unwrap_or("x")
+            .to_string();
+        let ty = self.decode(
+            field(b, "type")
+                .or_else(|| field(b, "binderType"))
+                .ok_or("binder without type")?,
+        )?;
+        let info = match field(b, "binderInfo").and_then(|x| x.as_str()) {
+            Some("implicit") => BinderInfo::Implicit,
+            Some("strictImplicit") => BinderInfo::StrictImplicit,
+            Some("instImplicit") => BinderInfo::InstImplicit,
+            _ => BinderInfo::Default,
+        };
+        let body_json = field(b, "body").ok_or("binder without body")?;
+        if !is_lam && info == BinderInfo::Default && !mentions_bvar(body_json, 0) {
+            // Non-dependent pi: an arrow.
+            self.bound.push(name);
+            let cod = self.decode(body_json);
+            self.bound.pop();
+            return Ok(Expr::Arrow {
+                dom: Box::new(ty),
+                cod: Box::new(cod?),
+            });
+        }
+        self.bound.push(name.clone());
+        let body = self.decode(body_json);
+        self.bound.pop();
+        Ok(Expr::Binder {
+            kind: if is_lam {
+                BinderKind::Lambda
+            } else {
+                BinderKind::Forall
+            },
+            binders: vec![Binder {
+                names: vec![name],
+                ty: Some(ty),
+                domain: None,
+                info,
+            }],
+            body: Box::new(body?),
+        })
+    }
+
+    fn app(&mut self, v: &Value) -> Result<Expr, String> {
+        let (head, args) = self.spine(v)?;
+        let head_name = field(&head, "const").and_then(|c| {
+            c.as_str()
+                .or_else(|| field(c, "name").and_then(|x| x.as_str()))
+                .map(|s| s.to_string())
+        });
+        if let Some(name) = head_name.as_deref() {
+            if let Some((op, arity)) = binop_for_const(name) {
+                if args.len() == arity {
+                    let (mut l, mut r) = (&args[arity - 2], &args[arity - 1]);
+                    if op == BinOp::Mem {
+                        // `Membership.mem γ α inst s a` is `a ∈ s` (collection first).
+                        std::mem::swap(&mut l, &mut r);
+                    }
+                    return Ok(Expr::Binary {
+                        op,
+                        lhs: Box::new(self.decode(l)?),
+                        rhs: Box::new(self.decode(r)?),
+                    });
+                }
+            }
+            match (name, args.len()) {
+                ("Not", 1) => {
+                    return Ok(Expr::Unary {
+                        op: UnOp::Not,
+                        arg: Box::new(self.decode(&args[0])?),
+                    })
+                }
+                ("Neg.neg", 3) => {
+                    return Ok(Expr::Unary {
+                        op: UnOp::Neg,
+                        arg: Box::new(self.decode(&args[2])?),
+                    })
+                }
+                ("OfNat.ofNat", 3) => return self.decode(&args[1]),
+                ("Nat.cast" | "NatCast.natCast" | "Int.cast" | "IntCast.intCast", 3) => {
+                    return Ok(Expr::Unary {
+                        op: UnOp::Coe,
+                        arg: Box::new(self.decode(&args[2])?),
+                    })
+                }
+                ("Exists", 2) => {
+                    if let Some(b) = field(&args[1], "lam") {
+                        if let Expr::Binder { binders, body, .. } = self.binder(b, true)? {
+                            return Ok(Expr::Binder {
+                                kind: BinderKind::Exists,
+                                binders,
+                                body,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let func = self.decode(&head)?;
+        let args = args
+            .iter()
+            .map(|a| self.decode(a))
+            .collect::<Result<Vec<_>, _>>()?;
+        if args.is_empty() {
+            return Ok(func);
+        }
+        Ok(Expr::App {
+            func: Box::new(func),
+            args,
+        })
+    }
+}
+
+fn mentions_bvar(v: &Value, depth: u64) -> bool {
+    if let Some(i) = field(v, "bvar").and_then(|x| x.as_u64()) {
+        return i == depth;
+    }
+    let Some(obj) = v.as_object() else {
+        return v
+            .as_array()
+            .is_some_and(|xs| xs.iter().any(|x| mentions_bvar(x, depth)));
+    };
+    for (k, x) in obj {
+        let under_binder = matches!(k.as_str(), "forallE" | "lam" | "letE");
+        if under_binder {
+            if let Some(o) = x.as_object() {
+                for (bk, bx) in o {
+                    let d = if bk == "body" { depth + 1 } else { depth };
+                    if mentions_bvar(bx, d) {
+                        return true;
+                    }
+                }
+            }
+        } else if mentions_bvar(x, depth) {
+            return true;
+        }
+    }
+    false
+}
+
+fn truncate(v: &Value) -> String {
+    let s = v.to_string();
+    if s.chars().count() > 120 {
+        format!("{}…", s.chars().take(120).collect::<String>())
+    } else {
+        s
+    }
+}
+
+/// Decode one elaborated term, with fvars resolved through `fvars`.
+pub fn decode_term(v: &Value, fvars: &FVarNames) -> Result<Expr, String> {
+    Decoder {
+        fvars,
+        bound: Vec::new(),
+    }
+    .decode(v)
+}
+
+/// Decode one InfoTree goal into a typed `Goal` plus its fvar naming.
+pub fn decode_goal(goal: &Value) -> Result<(Goal, FVarNames), String> {
+    let mut fvars = FVarNames::default();
+    let mut hyps = Vec::new();
+    let hyp_vals = goal
+        .get("hyps")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default();
+    for h in &hyp_vals {
+        let id = h
+            .get("fvarId")
+            .and_then(|v| v.as_str())
+            .ok_or("hyp without fvarId")?;
+        let user = h.get("userName").and_then(|v| v.as_str()).unwrap_or("h");
+        // Types may only mention earlier locals, so decode before registering this one.
+        let ty = decode_term(h.get("type").ok_or("hyp without type")?, &fvars)?;
+        let value = match h.get("value") {
+            Some(v) if !v.is_null() => Some(decode_term(v, &fvars)?),
+            _ => None,
+        };
+        let name = fvars.insert(id, user);
+        hyps.push(Hyp {
+            names: vec![name],
+            ty,
+            value,
+        });
+    }
+    let target = decode_term(goal.get("target").ok_or("goal without target")?, &fvars)?;
+    Ok((
+        Goal {
+            hyps,
+            unparsed_hyps: Vec::new(),
+            target,
+        },
+        fvars,
+    ))
+}
+
+/// Render a decoded goal in `pretty` form (`hyps…\n⊢ target`).
+pub fn goal_pretty(goal: &Goal) -> String {
+    let mut lines: Vec<String> = goal.hyps.iter().map(hyp_text).collect();
+    lines.push(format!("⊢ {}", goal.target));
+    lines.join("\n")
+}
+
+fn hyp_text(h: &Hyp) -> String {
+    match &h.value {
+        Some(v) => format!("{} : {} := {v}", h.names.join(" "), h.ty),
+        None => format!("{} : {}", h.names.join(" "), h.ty),
+    }
+}
+
+/// Convert an `infotree` payload into a `pp_dump` payload.
+///
+/// Each goal keeps its `fvars` mapping and `mvarId` so later tools can address the exact Lean
+/// objects; goals that fail to decode are reported under `errors` rather than dropped silently.
+pub fn pp_dump_from_infotree(v: &Value) -> Result<Value, String> {
+    let goals = v
+        .get("goals")
+        .and_then(|g| g.as_array())
+        .ok_or("infotree payload without goals")?;
+    let mut out_goals = Vec::new();
+    let mut errors = Vec::new();
+    for (i, g) in goals.iter().enumerate() {
+        match decode_goal(g) {
+            Ok((goal, fvars)) => out_goals.push(json!({
+                "pretty": goal_pretty(&goal),
+                "hyps": goal.hyps.iter().map(|h| json!({ "text": hyp_text(h) })).collect::<Vec<_>>(),
+                "target": goal.target.to_string(),
+                "mvarId": g.get("mvarId").cloned().unwrap_or(Value::Null),
+                "fvars": fvars.to_json(),
+            })),
+            Err(e) => errors.push(json!({ "goal_index": i, "error": e })),
+        }
+    }
+    if out_goals.is_empty() && !errors.is_empty() {
+        return Err(format!("no goal decoded: {}", Value::Array(errors)));
+    }
+    Ok(json!({
+        "tool": "proofpatch",
+        "kind": "pp_dump",
+        "source": "infotree",
+        "goals": out_goals,
+        "errors": errors,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(name: &str) -> Value {
+        json!({ "const": name })
+    }
+
+    fn app(xs: Vec<Value>) -> Value {
+        json!({ "app": xs })
+    }
+
+    fn le_nat(a: Value, b: Value) -> Value {
+        app(vec![c("LE.le"), c("Nat"), c("instLENat"), a, b])
+    }
+
+    #[test]
+    fn decodes_operators_and_shadowed_fvars() {
+        let lit = |n: u64| {
+            app(vec![
+                c("OfNat.ofNat"),
+                c("Nat"),
+                json!({"lit":{"natVal":n}}),
+                c("inst"),
+            ])
+        };
+        let add = app(vec![
+            c("HAdd.hAdd"),
+            c("Nat"),
+            c("Nat"),
+            c("Nat"),
+            c("inst"),
+            json!({"fvar":"_uniq.2"}),
+            lit(1),
+        ]);
+        let goal = json!({
+            "mvarId": "_uniq.9",
+            "hyps": [
+                {"fvarId":"_uniq.1","userName":"n","type":c("Nat")},
+                {"fvarId":"_uniq.2","userName":"n","type":c("Nat")},
+                {"fvarId":"_uniq.3","userName":"h","type":le_nat(json!({"fvar":"_uniq.1"}), json!({"fvar":"_uniq.2"}))}
+            ],
+            "target": le_nat(json!({"fvar":"_uniq.1"}), add),
+        });
+        let (g, fv) = decode_goal(&goal).unwrap();
+        assert_eq!(fv.get("_uniq.2"), Some("n_1"));
+        assert_eq!(
+            goal_pretty(&g),
+            "n : ℕ\nn_1 : ℕ\nh : n ≤ n_1\n⊢ n ≤ n_1 + 1"
+        );
+    }
+
+    #[test]
+    fn dependent_pi_is_forall_and_nondependent_is_arrow() {
+        let pi = json!({"forallE": {
+            "name": "k", "type": c("Nat"), "binderInfo": "default",
+            "body": {"forallE": {"name": "a", "type": le_nat(json!({"bvar":0}), json!({"bvar":0})),
+                                  "binderInfo": "default", "body": c("False")}}
+        }});
+        let e = decode_term(&pi, &FVarNames::default()).unwrap();
+        assert_eq!(e.to_string(), "∀ k : ℕ, k ≤ k → False");
+
+        let v = json!({"tool":"proofpatch","kind":"infotree","goals":[{"hyps":[],"target":pi}]});
+        let pp = pp_dump_from_infotree(&v).unwrap();
+        assert_eq!(pp["kind"], "pp_dump");
+        assert_eq!(pp["goals"][0]["pretty"], "⊢ ∀ k : ℕ, k ≤ k → False");
+    }
+}
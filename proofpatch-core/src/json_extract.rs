@@ -33,3 +33,173 @@ pub fn extract_first_json_value(s: &str) -> Option<Value> {
     let cand = s[i..=j].trim();
     serde_json::from_str::<Value>(cand).ok()
 }
+
+/// What `StreamingJsonExtractor::push` found once the output is complete enough to stop.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamStop {
+    /// A complete JSON object/array: the whole reply so far (after whitespace), or a fenced
+    /// ```json block.
+    Json(Value),
+    /// The body of a closed ``` fence.
+    Fence(String),
+}
+
+/// Incremental counterpart of `extract_first_json_value` for streamed model output: feed deltas
+/// to `push`, and it reports as soon as a complete JSON value or a closed code fence has arrived,
+/// so the caller can cancel the rest of the generation.
+///
+/// A reply that starts with `{` / `[` is tracked as bare JSON (string- and escape-aware bracket
+/// depth); anything else is watched for an opening fence line and its closing fence.
+#[derive(Debug, Default)]
+pub struct StreamingJsonExtractor {
+    buf: String,
+    scanned: usize,
+    /// `None`: only whitespace so far; `Some(true)`: tracking bare JSON; `Some(false)`: not JSON.
+    bare: Option<bool>,
+    json_start: usize,
+    depth: usize,
+    in_str: bool,
+    esc: bool,
+    end: Option<usize>,
+}
+
+impl StreamingJsonExtractor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything pushed so far.
+    pub fn text(&self) -> &str {
+        &self.buf
+    }
+
+    /// After a stop: the length of the output up to the end of the value / closing fence.
+    pub fn end(&self) -> Option<usize> {
+        self.end
+    }
+
+    pub fn push(&mut self, delta: &str) -> Option<StreamStop> {
+        if self.end.is_some() {
+            return None;
+        }
+        self.buf.push_str(delta);
+        if self.bare != Some(false) {
+            if let Some(stop) = self.scan_bare_json() {
+                return Some(stop);
+            }
+        }
+        if self.bare == Some(false) {
+            return self.closed_fence();
+        }
+        None
+    }
+
+    fn scan_bare_json(&mut self) -> Option<StreamStop> {
+        let bytes = self.buf.as_bytes();
+        while self.scanned < bytes.len() {
+            let i = self.scanned;
+            let b = bytes[i];
+            self.scanned += 1;
+            if self.bare.is_none() {
+                if b.is_ascii_whitespace() {
+                    continue;
+                }
+                if b == b'{' || b == b'[' {
+                    self.bare = Some(true);
+                    self.json_start = i;
+                    self.depth = 1;
+                    continue;
+                }
+                self.bare = Some(false);
+                return None;
+            }
+            if self.in_str {
+                if self.esc {
+                    self.esc = false;
+                } else if b == b'\\' {
+                    self.esc = true;
+                } else if b == b'"' {
+                    self.in_str = false;
+                }
+                continue;
+            }
+            match b {
+                b'"' => self.in_str = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        return match serde_json::from_str(&self.buf[self.json_start..=i]) {
+                            Ok(v) => {
+                                self.end = Some(i + 1);
+                                Some(StreamStop::Json(v))
+                            }
+                            Err(_) => {
+                                self.bare = Some(false);
+                                None
+                            }
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn closed_fence(&mut self) -> Option<StreamStop> {
+        let open = self.buf.find("```")?;
+        let nl = open + self.buf[open..].find('\n')?;
+        let lang = self.buf[open + 3..nl].trim();
+        let close = nl + 1 + self.buf[nl..].find("\n```")?;
+        let body = &self.buf[nl + 1..close];
+        self.end = Some(close + 3);
+        if lang == "json" {
+            if let Ok(v) = serde_json::from_str(body.trim()) {
+                return Some(StreamStop::Json(v));
+            }
+        }
+        Some(StreamStop::Fence(body.trim_end().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(chunks: &[&str]) -> (Option<StreamStop>, usize) {
+        let mut x = StreamingJsonExtractor::new();
+        for (n, c) in chunks.iter().enumerate() {
+            if let Some(stop) = x.push(c) {
+                return (Some(stop), n);
+            }
+        }
+        (None, chunks.len())
+    }
+
+    #[test]
+    fn stops_at_the_first_complete_value_or_fence() {
+        let (stop, at) = feed(&[
+            "  [\"by simp\", \"by ",
+            "omega]\"",
+            ", \"x\"]",
+            " trailing",
+            "more",
+        ]);
+        assert_eq!(
+            stop,
+            Some(StreamStop::Json(serde_json::json!([
+                "by simp",
+                "by omega]",
+                "x"
+            ])))
+        );
+        assert_eq!(at, 2);
+        let (stop, at) = feed(&["Here:\n```le", "an\nby\n  simp\n", "```", "\nExplanation…"]);
+        assert_eq!(stop, Some(StreamStop::Fence("by\n  simp".to_string())));
+        assert_eq!(at, 2);
+        let (stop, _) = feed(&["```json\n{\"a\": ", "1}\n```"]);
+        assert_eq!(stop, Some(StreamStop::Json(serde_json::json!({"a": 1}))));
+        assert_eq!(feed(&["by\n  simp", "\n  ring"]).0, None);
+    }
+}
@@ -0,0 +1,204 @@
+//! Live goal states from the Lean language server.
+//!
+//! Lean's server answers `$/lean/plainGoal` (tactic goals) and `$/lean/plainTermGoal`
+//! (expected type in term position) for any cursor position. We convert those answers into the
+//! same `pp_dump` JSON shape our injected `pp_dump` tactic logs, so downstream consumers
+//! (`smt_lia::entails_from_pp_dump`, `analyze_pp_dump`, tree search) need no second code path.
+//!
+//! The conversion helpers are always available; the server round-trip requires the `lsp` feature.
+
+use serde_json::{json, Value};
+
+/// Split one pretty-printed goal into hypothesis lines (one entry per local) and the target.
+///
+/// Handles the grouped-binder form `a b : ℕ` (expanded to `a : ℕ`, `b : ℕ`) and indented
+/// continuation lines (appended to the previous hypothesis).
+pub fn split_plain_goal(goal: &str) -> (Vec<String>, Option<String>) {
+    let mut hyps: Vec<String> = Vec::new();
+    let mut target: Option<String> = None;
+    for line in goal.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(rest) = line.trim_start().strip_prefix('⊢') {
+            target = Some(rest.trim().to_string());
+            continue;
+        }
+        if let Some(t) = target.as_mut() {
+            // Continuation of a multi-line target.
+            t.push(' ');
+            t.push_str(line.trim());
+            continue;
+        }
+        let is_continuation = line.starts_with(' ') || line.starts_with('\t');
+        if is_continuation {
+            if let Some(last) = hyps.last_mut() {
+                last.push(' ');
+                last.push_str(line.trim());
+                continue;
+            }
+        }
+        let Some((names, ty)) = line.split_once(" : ") else {
+            hyps.push(line.trim().to_string());
+            continue;
+        };
+        let names: Vec<&str> = names.split_whitespace().collect();
+        if names.len() > 1 {
+            for n in names {
+                hyps.push(format!("{n} : {}", ty.trim()));
+            }
+        } else {
+            hyps.push(format!("{} : {}", names.join(" "), ty.trim()));
+        }
+    }
+    (hyps, target)
+}
+
+/// Build a `pp_dump`-shaped payload from the goal strings returned by `$/lean/plainGoal`.
+pub fn pp_dump_from_plain_goals(goals: &[String]) -> Value {
+    let goals_json: Vec<Value> = goals
+        .iter()
+        .map(|g| {
+            let (hyps, _) = split_plain_goal(g);
+            json!({
+                "pretty": g,
+                "hyps": hyps.iter().map(|h| json!({ "text": h })).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    json!({
+        "tool": "proofpatch",
+        "kind": "pp_dump",
+        "source": "lsp",
+        "goals": goals_json,
+    })
+}
+
+/// Build a `pp_dump`-shaped payload from a `$/lean/plainTermGoal` answer.
+pub fn pp_dump_from_plain_term_goal(goal: &str) -> Value {
+    let mut v = pp_dump_from_plain_goals(&[goal.to_string()]);
+    v["source"] = json!("lsp_term");
+    v
+}
+
+#[cfg(feature = "lsp")]
+pub use live::{goal_state_at, GoalQuery};
+
+#[cfg(feature = "lsp")]
+mod live {
+    use super::*;
+    use std::path::Path;
+    use std::time::Duration;
+
+    /// Which server query to issue.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum GoalQuery {
+        /// `$/lean/plainGoal`: tactic-state goals at the position.
+        Tactic,
+        /// `$/lean/plainTermGoal`: expected type at the position.
+        Term,
+        /// Try tactic goals first, then fall back to the term goal.
+        Auto,
+    }
+
+    /// Open `file_rel` in the repo's Lean server and return the goal state at a 1-based position.
+    ///
+    /// The file text is mirrored into `.generated/proofpatch-lsp/` (never the real path), the same
+    /// buffer strategy `verify_lean_text` uses with `PROOFPATCH_VERIFY_BACKEND=lsp`.
+    ///
+    /// Returns `Ok(None)` when the server reports no goal at that position.
+    pub async fn goal_state_at(
+        repo_root: &Path,
+        file_rel: &str,
+        line_1: usize,
+        col_1: usize,
+        query: GoalQuery,
+        timeout_s: Duration,
+    ) -> Result<Option<Value>, String> {
+        let repo_root = crate::find_lean_repo_root(repo_root)?;
+        let abs = repo_root.join(file_rel);
+        let text =
+            std::fs::read_to_string(&abs).map_err(|e| format!("read {}: {e}", abs.display()))?;
+        let line0 = line_1.saturating_sub(1);
+        // LSP positions are UTF-16 code units.
+        let character = text
+            .lines()
+            .nth(line0)
+            .map(|l| {
+                l.chars()
+                    .take(col_1.saturating_sub(1))
+                    .map(char::len_utf16)
+                    .sum::<usize>()
+            })
+            .ok_or_else(|| format!("line {line_1} out of range for {file_rel}"))?;
+
+        let buf = repo_root
+            .join(".generated")
+            .join("proofpatch-lsp")
+            .join("proofpatch_goal_buffer.lean");
+        crate::lsp_client::check_text_via_lsp(&repo_root, &buf, text, timeout_s).await?;
+
+        let uri = url::Url::from_file_path(&buf)
+            .map_err(|_| format!("failed to build file:// uri for {}", buf.display()))?;
+        let params = json!({
+            "textDocument": { "uri": uri.as_str() },
+            "position": { "line": line0, "character": character },
+        });
+
+        if matches!(query, GoalQuery::Tactic | GoalQuery::Auto) {
+            let r = crate::lsp_client::request_via_lsp(
+                &repo_root,
+                "$/lean/plainGoal",
+                params.clone(),
+                timeout_s,
+            )
+            .await?;
+            let goals: Vec<String> = r
+                .get("goals")
+                .and_then(|v| v.as_array())
+                .map(|xs| {
+                    xs.iter()
+                        .filter_map(|g| g.as_str().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            if !goals.is_empty() {
+                return Ok(Some(pp_dump_from_plain_goals(&goals)));
+            }
+        }
+        if matches!(query, GoalQuery::Term | GoalQuery::Auto) {
+            let r = crate::lsp_client::request_via_lsp(
+                &repo_root,
+                "$/lean/plainTermGoal",
+                params,
+                timeout_s,
+            )
+            .await?;
+            if let Some(g) = r.get("goal").and_then(|v| v.as_str()) {
+                return Ok(Some(pp_dump_from_plain_term_goal(g)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_goal_expands_grouped_binders() {
+        let g = "a b : ℕ\nh : a ≤ b\n⊢ a < b + 1";
+        let (hyps, target) = split_plain_goal(g);
+        assert_eq!(hyps, vec!["a : ℕ", "b : ℕ", "h : a ≤ b"]);
+        assert_eq!(target.as_deref(), Some("a < b + 1"));
+    }
+
+    #[test]
+    fn plain_goal_dump_feeds_smt_shape() {
+        let v = pp_dump_from_plain_goals(&["x : ℤ\nhx :\n  x ≤ 3\n⊢ x ≤ 4".to_string()]);
+        assert_eq!(v["kind"], "pp_dump");
+        let hyps = v["goals"][0]["hyps"].as_array().unwrap();
+        assert_eq!(hyps[1]["text"], "hx : x ≤ 3");
+    }
+}
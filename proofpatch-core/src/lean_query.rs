@@ -0,0 +1,312 @@
+//! Small `#check` / `#print` queries against the project.
+//!
+//! Queries are batched into one scratch file (imports + `open`s + one command per line) and
+//! elaborated once; each result is matched back to its query by line. With the `lsp` feature the
+//! file goes to the cached per-repo Lean server, so repeated batches only pay for elaborating the
+//! commands, not for loading imports. Otherwise (or with `PROOFPATCH_QUERY_BACKEND=process`) we
+//! fall back to `verify_lean_text`.
+//!
+//! `#print axioms` results are parsed into `AxiomsReport` so callers can flag declarations that
+//! rely on `sorryAx` or on non-standard axioms.
+
+use crate::diagnostics::{parse_diagnostics, Severity};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "arg", rename_all = "snake_case")]
+pub enum LeanQuery {
+    /// `#check <term>`
+    Check(String),
+    /// `#print <name>`
+    Print(String),
+    /// `#print axioms <name>`
+    PrintAxioms(String),
+    /// `#eval <term>`
+    Eval(String),
+}
+
+impl LeanQuery {
+    /// Parse `#check …`, `#print axioms …`, `#print …`, `#eval …`; a bare term means `#check`.
+    pub fn parse(s: &str) -> Self {
+        let t = s.trim();
+        if let Some(r) = t.strip_prefix("#print axioms ") {
+            Self::PrintAxioms(r.trim().to_string())
+        } else if let Some(r) = t.strip_prefix("#print ") {
+            Self::Print(r.trim().to_string())
+        } else if let Some(r) = t.strip_prefix("#eval ") {
+            Self::Eval(r.trim().to_string())
+        } else {
+            Self::Check(t.strip_prefix("#check ").unwrap_or(t).trim().to_string())
+        }
+    }
+
+    /// The command on a single line (newlines in the argument are folded).
+    pub fn command(&self) -> String {
+        let one = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+        match self {
+            Self::Check(t) => format!("#check {}", one(t)),
+            Self::Print(n) => format!("#print {}", one(n)),
+            Self::PrintAxioms(n) => format!("#print axioms {}", one(n)),
+            Self::Eval(t) => format!("#eval {}", one(t)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AxiomsReport {
+    pub decl: String,
+    pub axioms: Vec<String>,
+    pub uses_sorry: bool,
+    /// Axioms outside `propext`, `Classical.choice`, `Quot.sound` (and `sorryAx`).
+    pub nonstandard: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryResult {
+    pub query: LeanQuery,
+    pub ok: bool,
+    /// Information output of the command (what Lean printed).
+    pub output: String,
+    pub errors: Vec<String>,
+    /// For `#check`: the part after the top-level ` : `.
+    #[serde(default)]
+    pub type_signature: Option<String>,
+    #[serde(default)]
+    pub axioms: Option<AxiomsReport>,
+}
+
+const STANDARD_AXIOMS: &[&str] = &["propext", "Classical.choice", "Quot.sound"];
+
+/// Parse `#print axioms` output.
+///
+/// Shapes: `'Foo.bar' depends on axioms: [propext, sorryAx]` and
+/// `'Foo.bar' does not depend on any axioms`.
+pub fn parse_axioms_output(decl: &str, output: &str) -> Option<AxiomsReport> {
+    let axioms: Vec<String> = if output.contains("does not depend on any axioms") {
+        Vec::new()
+    } else {
+        let i = output.find("depends on axioms:")?;
+        let rest = &output[i + "depends on axioms:".len()..];
+        let inner = rest.trim().trim_start_matches('[');
+        let inner = inner.split(']').next().unwrap_or(inner);
+        inner
+            .split(',')
+            .map(|a| a.trim().to_string())
+            .filter(|a| !a.is_empty())
+            .collect()
+    };
+    Some(AxiomsReport {
+        decl: decl.to_string(),
+        uses_sorry: axioms.iter().any(|a| a == "sorryAx"),
+        nonstandard: axioms
+            .iter()
+            .filter(|a| *a != "sorryAx" && !STANDARD_AXIOMS.contains(&a.as_str()))
+            .cloned()
+            .collect(),
+        axioms,
+    })
+}
+
+/// Split `#check` output `name : type` at the first top-level ` : `.
+pub fn check_type_signature(output: &str) -> Option<String> {
+    let mut depth = 0i32;
+    let chars: Vec<(usize, char)> = output.char_indices().collect();
+    for (k, &(i, c)) in chars.iter().enumerate() {
+        match c {
+            '(' | '[' | '{' | '⦃' => depth += 1,
+            ')' | ']' | '}' | '⦄' => depth -= 1,
+            ':' if depth == 0
+                && i > 0
+                && output[..i].ends_with(' ')
+                && chars.get(k + 1).is_some_and(|(_, n)| n.is_whitespace()) =>
+            {
+                return Some(output[i + 1..].trim().to_string());
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Scratch file text for `queries`, and the 1-based line of each query.
+pub fn build_query_file(
+    imports: &[String],
+    opens: &[String],
+    queries: &[LeanQuery],
+) -> (String, Vec<usize>) {
+    let mut lines: Vec<String> = imports
+        .iter()
+        .map(|m| format!("import {}", m.trim_start_matches("import ").trim()))
+        .collect();
+    lines.push(String::new());
+    if !opens.is_empty() {
+        lines.push(format!(
+            "open {}",
+            opens
+                .iter()
+                .map(|o| o.trim_start_matches("open ").trim())
+                .collect::<Vec<_>>()
+                .join(" ")
+        ));
+    }
+    let mut at = Vec::new();
+    for q in queries {
+        lines.push(q.command());
+        at.push(lines.len());
+    }
+    (lines.join("\n") + "\n", at)
+}
+
+/// Match compiler output back to queries by line.
+pub fn parse_query_output(
+    output: &str,
+    queries: &[LeanQuery],
+    lines: &[usize],
+) -> Vec<QueryResult> {
+    let diags = parse_diagnostics(output);
+    queries
+        .iter()
+        .zip(lines)
+        .map(|(q, &line)| {
+            let here: Vec<_> = diags.iter().filter(|d| d.line == line).collect();
+            let output = here
+                .iter()
+                .filter(|d| d.severity == Severity::Info)
+                .map(|d| d.message.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            let errors: Vec<String> = here
+                .iter()
+                .filter(|d| d.severity == Severity::Error)
+                .map(|d| d.message.clone())
+                .collect();
+            let (type_signature, axioms) = match q {
+                LeanQuery::Check(_) => (check_type_signature(&output), None),
+                LeanQuery::PrintAxioms(n) => (None, parse_axioms_output(n, &output)),
+                _ => (None, None),
+            };
+            QueryResult {
+                query: q.clone(),
+                ok: errors.is_empty() && !output.is_empty(),
+                output,
+                errors,
+                type_signature,
+                axioms,
+            }
+        })
+        .collect()
+}
+
+#[cfg(feature = "lsp")]
+pub(crate) async fn elaborate(
+    repo_root: &Path,
+    text: &str,
+    timeout: Duration,
+) -> Result<String, String> {
+    let use_lsp = std::env::var("PROOFPATCH_QUERY_BACKEND")
+        .map(|v| v.trim() != "process")
+        .unwrap_or(true);
+    if use_lsp {
+        let dir = repo_root.join(".generated").join("proofpatch-lsp");
+        let _ = std::fs::create_dir_all(&dir);
+        let p = dir.join("proofpatch_query.lean");
+        if let Ok(d) =
+            crate::lsp_client::check_text_via_lsp(repo_root, &p, text.to_string(), timeout).await
+        {
+            let mut all = d.lean_lines;
+            all.extend(d.info_lines);
+            return Ok(all.join("\n"));
+        }
+    }
+    let v = crate::verify_lean_text(repo_root, text, timeout).await?;
+    Ok(format!("{}\n{}", v.stdout, v.stderr))
+}
+
+#[cfg(not(feature = "lsp"))]
+pub(crate) async fn elaborate(
+    repo_root: &Path,
+    text: &str,
+    timeout: Duration,
+) -> Result<String, String> {
+    let v = crate::verify_lean_text(repo_root, text, timeout).await?;
+    Ok(format!("{}\n{}", v.stdout, v.stderr))
+}
+
+/// Run a batch of queries with the given imports/opens (e.g. `["Mathlib"]`, `["Nat"]`).
+pub async fn run_queries(
+    repo_root: &Path,
+    imports: &[String],
+    opens: &[String],
+    queries: &[LeanQuery],
+    timeout: Duration,
+) -> Result<Vec<QueryResult>, String> {
+    if queries.is_empty() {
+        return Ok(Vec::new());
+    }
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let (text, lines) = build_query_file(imports, opens, queries);
+    let out = elaborate(&repo_root, &text, timeout).await?;
+    Ok(parse_query_output(&out, queries, &lines))
+}
+
+/// `#print axioms` for each declaration in `decls`, importing `module` (e.g. the file's module).
+pub async fn audit_axioms(
+    repo_root: &Path,
+    module: &str,
+    decls: &[String],
+    timeout: Duration,
+) -> Result<Vec<AxiomsReport>, String> {
+    let qs: Vec<LeanQuery> = decls.iter().cloned().map(LeanQuery::PrintAxioms).collect();
+    let res = run_queries(repo_root, &[module.to_string()], &[], &qs, timeout).await?;
+    res.into_iter()
+        .map(|r| {
+            let name = match &r.query {
+                LeanQuery::PrintAxioms(n) => n.clone(),
+                _ => String::new(),
+            };
+            r.axioms.ok_or_else(|| {
+                format!(
+                    "#print axioms {name}: {}",
+                    r.errors.first().map(String::as_str).unwrap_or("no output")
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_file_and_matches_results_by_line() {
+        let qs = vec![
+            LeanQuery::parse("#check @Nat.le_trans"),
+            LeanQuery::parse("#print axioms Foo.bar"),
+            LeanQuery::parse("Nat.nope"),
+        ];
+        let (text, lines) = build_query_file(&["Mathlib".into()], &["Nat".into()], &qs);
+        assert!(text.starts_with("import Mathlib\n\nopen Nat\n#check @Nat.le_trans\n"));
+        assert_eq!(lines, vec![4, 5, 6]);
+
+        let out = "\
+q.lean:4:0: @Nat.le_trans : ∀ {n m k : ℕ}, n ≤ m → m ≤ k → n ≤ k
+q.lean:5:0: info: 'Foo.bar' depends on axioms: [propext, sorryAx, Foo.myAxiom]
+q.lean:6:7: error: unknown identifier 'Nat.nope'
+";
+        let r = parse_query_output(out, &qs, &lines);
+        assert_eq!(
+            r[0].type_signature.as_deref(),
+            Some("∀ {n m k : ℕ}, n ≤ m → m ≤ k → n ≤ k")
+        );
+        let ax = r[1].axioms.as_ref().unwrap();
+        assert!(ax.uses_sorry);
+        assert_eq!(ax.nonstandard, vec!["Foo.myAxiom".to_string()]);
+        assert!(!r[2].ok && r[2].errors[0].contains("Nat.nope"));
+
+        let clean = parse_axioms_output("x", "'x' does not depend on any axioms").unwrap();
+        assert!(clean.axioms.is_empty() && !clean.uses_sorry);
+    }
+}
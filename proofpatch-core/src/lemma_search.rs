@@ -0,0 +1,289 @@
+//! Lemma retrieval from Loogle and LeanSearch-style semantic search.
+//!
+//! Both services return Mathlib declarations, but in different shapes. We normalize them into
+//! `LemmaHit { name, type_signature, module, doc }` so hits can be rendered into prompts
+//! (`lemma_prompt_block`) or turned directly into candidate tactics (`candidates_from_lemmas`).
+//!
+//! Endpoints can be overridden for self-hosted instances:
+//! - `PROOFPATCH_LOOGLE_URL` (default `https://loogle.lean-lang.org/json`)
+//! - `PROOFPATCH_LEANSEARCH_URL` (default `https://leansearch.net/search`)
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LemmaHit {
+    pub name: String,
+    /// Pretty-printed statement (may be empty when the backend omits it).
+    #[serde(rename = "type")]
+    pub type_signature: String,
+    #[serde(default)]
+    pub module: Option<String>,
+    #[serde(default)]
+    pub doc: Option<String>,
+    /// "loogle" or "leansearch".
+    pub source: String,
+    /// Backend relevance (LeanSearch distance; lower is better). `None` for Loogle.
+    #[serde(default)]
+    pub score: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LemmaBackend {
+    Loogle,
+    LeanSearch,
+}
+
+impl LemmaBackend {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "loogle" => Some(Self::Loogle),
+            "leansearch" | "lean-search" | "semantic" => Some(Self::LeanSearch),
+            _ => None,
+        }
+    }
+}
+
+fn env_url(var: &str, default: &str) -> String {
+    std::env::var(var)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
+fn client(timeout: Duration) -> Result<reqwest::Client, String> {
+    let ua = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .user_agent(ua)
+        .build()
+        .map_err(|e| format!("reqwest client: {e}"))
+}
+
+fn non_empty(v: Option<&Value>) -> Option<String> {
+    v.and_then(|x| x.as_str())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// Dotted name from either `"Nat.add_comm"` or `["Nat", "add_comm"]`.
+fn dotted(v: Option<&Value>) -> Option<String> {
+    match v? {
+        Value::String(s) => Some(s.clone()),
+        Value::Array(xs) => {
+            let parts: Vec<&str> = xs.iter().filter_map(|x| x.as_str()).collect();
+            (!parts.is_empty()).then(|| parts.join("."))
+        }
+        _ => None,
+    }
+}
+
+/// Parse a Loogle `/json` response.
+///
+/// Loogle reports query errors as `{"error": "...", "suggestions": [...]}`; we surface the
+/// error and first suggestions so callers can retry with a corrected query.
+pub fn parse_loogle_json(v: &Value, max_results: usize) -> Result<Vec<LemmaHit>, String> {
+    if let Some(err) = non_empty(v.get("error")) {
+        let sugg: Vec<&str> = v
+            .get("suggestions")
+            .and_then(|s| s.as_array())
+            .map(|xs| xs.iter().filter_map(|x| x.as_str()).take(3).collect())
+            .unwrap_or_default();
+        return Err(if sugg.is_empty() {
+            format!("loogle: {err}")
+        } else {
+            format!("loogle: {err} (suggestions: {})", sugg.join(" | "))
+        });
+    }
+    let hits = v
+        .get("hits")
+        .and_then(|h| h.as_array())
+        .ok_or("loogle: response without `hits`")?;
+    Ok(hits
+        .iter()
+        .filter_map(|h| {
+            Some(LemmaHit {
+                name: non_empty(h.get("name"))?,
+                type_signature: non_empty(h.get("type")).unwrap_or_default(),
+                module: non_empty(h.get("module")),
+                doc: non_empty(h.get("doc")),
+                source: "loogle".to_string(),
+                score: None,
+            })
+        })
+        .take(max_results)
+        .collect())
+}
+
+/// Parse a LeanSearch response.
+///
+/// Accepts the batched shape `[[{"result": {...}, "distance": d}, ...]]`, a flat list, and
+/// `{"results": [...]}`; entry fields may be `name`/`formal_name`, `formal_type`/`type`,
+/// `module_name`/`module`, `docstring`/`doc` (falling back to `informal_description`).
+pub fn parse_leansearch_json(v: &Value, max_results: usize) -> Result<Vec<LemmaHit>, String> {
+    let items: Vec<Value> = match v {
+        Value::Array(xs) if xs.first().is_some_and(|x| x.is_array()) => {
+            xs[0].as_array().cloned().unwrap_or_default()
+        }
+        Value::Array(xs) => xs.clone(),
+        Value::Object(_) => v
+            .get("results")
+            .and_then(|r| r.as_array())
+            .cloned()
+            .ok_or("leansearch: response without results")?,
+        _ => return Err("leansearch: unexpected response".to_string()),
+    };
+    Ok(items
+        .iter()
+        .filter_map(|it| {
+            let r = it.get("result").unwrap_or(it);
+            let name = dotted(r.get("name")).or_else(|| dotted(r.get("formal_name")))?;
+            Some(LemmaHit {
+                name,
+                type_signature: non_empty(r.get("formal_type"))
+                    .or_else(|| non_empty(r.get("type")))
+                    .unwrap_or_default(),
+                module: dotted(r.get("module_name")).or_else(|| dotted(r.get("module"))),
+                doc: non_empty(r.get("docstring"))
+                    .or_else(|| non_empty(r.get("doc")))
+                    .or_else(|| non_empty(r.get("informal_description"))),
+                source: "leansearch".to_string(),
+                score: it.get("distance").and_then(|d| d.as_f64()),
+            })
+        })
+        .take(max_results)
+        .collect())
+}
+
+/// Query Loogle (`Nat.succ_le`, `_ * (_ + _) = _`, `"comm"`, …).
+pub async fn loogle_search(
+    query: &str,
+    max_results: usize,
+    timeout: Duration,
+) -> Result<Vec<LemmaHit>, String> {
+    let base = env_url("PROOFPATCH_LOOGLE_URL", "https://loogle.lean-lang.org/json");
+    let mut url = reqwest::Url::parse(&base).map_err(|e| format!("parse loogle url: {e}"))?;
+    url.query_pairs_mut().append_pair("q", query);
+    let resp = client(timeout)?
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("loogle fetch: {e}"))?;
+    if !resp.status().is_success() {
+        return Err(format!("loogle fetch status: {}", resp.status()));
+    }
+    let v: Value = resp.json().await.map_err(|e| format!("loogle json: {e}"))?;
+    parse_loogle_json(&v, max_results)
+}
+
+/// Query a LeanSearch-style semantic search endpoint with natural language.
+pub async fn leansearch_search(
+    query: &str,
+    max_results: usize,
+    timeout: Duration,
+) -> Result<Vec<LemmaHit>, String> {
+    let url = env_url("PROOFPATCH_LEANSEARCH_URL", "https://leansearch.net/search");
+    let body = json!({ "query": [query], "num_results": max_results });
+    let resp = client(timeout)?
+        .post(&url)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("leansearch fetch: {e}"))?;
+    if !resp.status().is_success() {
+        return Err(format!("leansearch fetch status: {}", resp.status()));
+    }
+    let v: Value = resp
+        .json()
+        .await
+        .map_err(|e| format!("leansearch json: {e}"))?;
+    parse_leansearch_json(&v, max_results)
+}
+
+pub async fn lemma_search(
+    backend: LemmaBackend,
+    query: &str,
+    max_results: usize,
+    timeout: Duration,
+) -> Result<Vec<LemmaHit>, String> {
+    let max_results = max_results.clamp(1, 50);
+    match backend {
+        LemmaBackend::Loogle => loogle_search(query, max_results, timeout).await,
+        LemmaBackend::LeanSearch => leansearch_search(query, max_results, timeout).await,
+    }
+}
+
+/// Render hits as a compact block for LLM prompts.
+pub fn lemma_prompt_block(hits: &[LemmaHit], max: usize) -> String {
+    let mut out = String::from("Possibly relevant Mathlib lemmas:\n");
+    for h in hits.iter().take(max) {
+        if h.type_signature.is_empty() {
+            out.push_str(&format!("- {}\n", h.name));
+        } else {
+            out.push_str(&format!("- {} : {}\n", h.name, h.type_signature));
+        }
+    }
+    out
+}
+
+/// Candidate tactic scripts that use retrieved lemmas directly.
+///
+/// Equalities/iffs also get `rw`/`simp` forms; everything gets `exact` and `apply`.
+pub fn candidates_from_lemmas(hits: &[LemmaHit], max_lemmas: usize) -> Vec<String> {
+    let mut out = Vec::new();
+    for h in hits.iter().take(max_lemmas) {
+        let n = &h.name;
+        out.push(format!("by\n  exact {n}"));
+        out.push(format!("by\n  apply {n} <;> simp_all"));
+        let concl = h.type_signature.rsplit('→').next().unwrap_or("");
+        if concl.contains(" = ") || concl.contains(" ↔ ") {
+            out.push(format!("by\n  rw [{n}]"));
+            out.push(format!("by\n  simp [{n}]"));
+        }
+    }
+    crate::tree_search::sanitize_candidates(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_loogle_hits_and_errors() {
+        let v = json!({"count": 2, "header": "Found 2", "hits": [
+            {"name": "Nat.add_comm", "type": "∀ (n m : ℕ), n + m = m + n", "module": "Init.Data.Nat.Basic", "doc": ""},
+            {"name": "Nat.le_refl", "type": "∀ (n : ℕ), n ≤ n", "module": "Init.Prelude"}
+        ]});
+        let hits = parse_loogle_json(&v, 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].doc, None);
+        let cands = candidates_from_lemmas(&hits, 2);
+        assert!(cands.contains(&"by\n  rw [Nat.add_comm]".to_string()));
+        assert!(!cands.contains(&"by\n  rw [Nat.le_refl]".to_string()));
+
+        let err = json!({"error": "Unknown identifier 'Nat.foo'", "suggestions": ["Nat.find"]});
+        assert!(parse_loogle_json(&err, 10)
+            .unwrap_err()
+            .contains("Nat.find"));
+    }
+
+    #[test]
+    fn parses_leansearch_batched_shape() {
+        let v = json!([[{
+            "result": {
+                "name": ["Nat", "succ_le_of_lt"],
+                "module_name": ["Mathlib", "Order", "Basic"],
+                "formal_type": "n < m → n.succ ≤ m",
+                "informal_description": "successor is at most"
+            },
+            "distance": 0.12
+        }]]);
+        let hits = parse_leansearch_json(&v, 5).unwrap();
+        assert_eq!(hits[0].name, "Nat.succ_le_of_lt");
+        assert_eq!(hits[0].module.as_deref(), Some("Mathlib.Order.Basic"));
+        assert_eq!(hits[0].score, Some(0.12));
+        assert!(lemma_prompt_block(&hits, 3).contains("Nat.succ_le_of_lt : n < m"));
+    }
+}
@@ -31,16 +31,71 @@ use std::time::Duration;
 use tokio::process::Command;
 
 pub mod arxiv;
+pub mod beam;
+pub mod checkpoint;
+pub mod code_action;
 pub mod config;
+pub mod conversation;
+pub mod corpus;
+pub mod diagnostics;
+pub mod difficulty;
+pub mod ensemble;
+pub mod failure_clusters;
+pub mod fewshot;
+pub mod file_repair;
+pub mod goal_ast;
+pub mod history;
+pub mod import_graph;
+pub mod infotree;
 pub mod json_extract;
+pub mod lean_lsp;
+pub mod lean_query;
+pub mod lemma_search;
+pub mod library_search;
 pub mod llm;
 #[cfg(feature = "lsp")]
 mod lsp_client;
+pub mod mathlib_cache;
+pub mod matrix;
+pub mod mcts;
+pub mod metrics;
+pub mod minimize;
+pub mod mutate;
+pub mod parallel_verify;
+pub mod patch_manifest;
+pub mod patching;
 #[cfg(feature = "planner")]
 pub mod planner;
+pub mod pp_export;
+pub mod premise;
+pub mod priors;
+pub mod prompt_budget;
+pub mod prompt_context;
+pub mod prompts;
+pub mod ranking;
+pub mod renames;
+pub mod repair;
+pub mod repl;
+pub mod replay;
+pub mod rerank;
+pub mod research;
+pub mod research_summary;
 pub mod review;
+pub mod safety;
+pub mod scan;
+pub mod score_hook;
+pub mod simp_sets;
 pub mod smt_lia;
+pub mod strategy;
+pub mod style;
+pub mod subproofs;
+pub mod target;
+pub mod toolchain;
 pub mod tree_search;
+pub mod untrusted;
+pub mod verify;
+pub mod warm;
+pub mod worklist;
 
 #[derive(Debug, Clone)]
 struct LeanEnv {
@@ -100,6 +155,7 @@ async fn run_lean_with_env(
     repo_root: &Path,
     lean_args: &[String],
     timeout_s: Duration,
+    abort: impl Fn(&str) -> bool,
 ) -> Result<(bool, bool, Option<i32>, String, String), String> {
     let Some(lean_env) = get_or_compute_lean_env(repo_root, timeout_s).await else {
         return Err("no lean env available".to_string());
@@ -111,27 +167,21 @@ async fn run_lean_with_env(
     for a in lean_args {
         cmd.arg(a);
     }
-    let out = tokio::time::timeout(timeout_s, cmd.output())
-        .await
-        .map_err(|_| "timeout".to_string());
-    let (ok, timeout, returncode, stdout, stderr) = match out {
-        Err(_) => (false, true, None, String::new(), String::new()),
-        Ok(Err(e)) => (
-            false,
-            false,
-            None,
-            String::new(),
-            format!("failed to execute: {}", e),
-        ),
-        Ok(Ok(output)) => {
-            let ok = output.status.success();
-            let returncode = output.status.code();
-            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-            (ok, false, returncode, stdout, stderr)
-        }
-    };
-    Ok((ok, timeout, returncode, stdout, stderr))
+    Ok(verify::run_until(cmd, timeout_s, abort).await.0)
+}
+
+/// `lake env lean <file>` in `repo_root`, killed at the first output line `abort` accepts.
+async fn lake_env_lean(
+    lake: &Path,
+    file: &Path,
+    repo_root: &Path,
+    timeout_s: Duration,
+    abort: impl Fn(&str) -> bool,
+) -> (bool, bool, Option<i32>, String, String) {
+    let mut cmd = Command::new(lake);
+    cmd.arg("env").arg("lean").arg(file).current_dir(repo_root);
+    maybe_extend_lean_path_for_lake_env(&mut cmd);
+    verify::run_until(cmd, timeout_s, abort).await.0
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -149,6 +199,9 @@ pub struct ResearchNotes {
     pub raw_urls: usize,
     pub deduped_urls: usize,
     pub sources: Vec<ResearchSource>,
+    /// Cited LLM summary of `sources` (`research_summary`), when requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<research_summary::ResearchSummary>,
 }
 
 fn canonicalize_url(url: &str) -> Option<String> {
@@ -321,6 +374,7 @@ pub fn ingest_research_json(v: &serde_json::Value) -> ResearchNotes {
         raw_urls,
         deduped_urls: sources.len(),
         sources,
+        summary: None,
     }
 }
 
@@ -496,6 +550,9 @@ pub struct VerifyResult {
     pub cmd: Vec<String>,
     pub cwd: String,
     pub tmp_file: Option<String>,
+    /// Killed at the first error inside the checked lines (`verify_lean_text_until`).
+    #[serde(default)]
+    pub aborted: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -783,7 +840,12 @@ fn has_any_llm_key() -> bool {
     // We keep this narrow: these are the most common OpenAI-compatible key envs.
     // (We do NOT print or log them anywhere.)
     let ok = |k: &str| std::env::var(k).ok().as_deref().unwrap_or("").trim().len() > 0;
-    ok("OPENROUTER_API_KEY") || ok("OPENAI_API_KEY") || ok("GROQ_API_KEY")
+    ok("OPENROUTER_API_KEY")
+        || ok("OPENAI_API_KEY")
+        || ok("GROQ_API_KEY")
+        || ok("ANTHROPIC_API_KEY")
+        || ok("GEMINI_API_KEY")
+        || ok("AZURE_OPENAI_API_KEY")
 }
 
 fn looks_like_missing_olean(stdout: &str, stderr: &str) -> bool {
@@ -841,6 +903,9 @@ pub fn load_cursor_mcp_env_if_present() {
 /// - `PROOFPATCH_DOTENV_SEARCH` (default: on): set to 0/false/off to disable
 /// - `PROOFPATCH_DOTENV_SEARCH_ROOT` (default: repo_root.parent): override search root
 pub fn load_dotenv_smart(repo_root: &Path) {
+    // `[llm]` in proofpatch.toml picks the chat backend; keys still come from the env.
+    llm::configure_from_repo(repo_root);
+
     // Base: repo-local .env
     load_dotenv_if_present(repo_root);
 
@@ -1011,18 +1076,47 @@ fn decl_header_regex(decl_name: &str) -> Result<Regex, String> {
     //
     // We intentionally stay line-anchored to avoid matching mentions inside proofs/comments.
     let pat = format!(
-        r"^\s*(?:@[^\n]*\s+)*(?:(?:private|protected|noncomputable|unsafe|partial)\s+)*\b(theorem|lemma|def|abbrev|instance)\s+{}\b",
+        r"^\s*(?:@[^\n]*\s+)*(?:(?:private|protected|noncomputable|unsafe|partial)\s+)*\b(theorem|lemma|def|abbrev|instance)\s+(?:\(priority\s*:=[^)]*\)\s+)?{}\b",
         decl
     );
     Regex::new(&pat).map_err(|e| format!("invalid decl regex: {}", e))
 }
 
-fn extract_decl_signature_prefix(lines: &[&str], decl_name: &str) -> Result<Vec<String>, String> {
+/// Name for a declaration that has none (`example`, anonymous `instance`): `<kind>@<line>`, e.g.
+/// `example@12`. `@` cannot occur in a Lean identifier, so these never collide with real names and
+/// are accepted wherever a declaration name is. They are positional (file + header line): derive
+/// them again after edits above the declaration.
+pub fn anonymous_decl_name(kind: &str, line_1: usize) -> String {
+    format!("{kind}@{line_1}")
+}
+
+/// Split an `anonymous_decl_name` back into kind and 1-based header line.
+pub fn parse_anonymous_decl_name(name: &str) -> Option<(&str, usize)> {
+    let (kind, line) = name.split_once('@')?;
+    let line = line.parse::<usize>().ok().filter(|&l| l > 0)?;
+    matches!(kind, "example" | "instance").then_some((kind, line))
+}
+
+/// 0-based index of the header line of `decl_name` (named, or `anonymous_decl_name`) in `lines`.
+fn find_decl_header<S: AsRef<str>>(lines: &[S], decl_name: &str) -> Result<usize, String> {
+    if let Some((kind, line_1)) = parse_anonymous_decl_name(decl_name) {
+        let pat = any_decl_header_regex()?;
+        return lines
+            .get(line_1 - 1)
+            .and_then(|ln| pat.captures(ln.as_ref()))
+            .filter(|cap| &cap[1] == kind && decl_header_name(cap).is_none())
+            .map(|_| line_1 - 1)
+            .ok_or_else(|| format!("No anonymous {kind} declaration on line {line_1}"));
+    }
     let pat = decl_header_regex(decl_name)?;
-    let start0 = lines
+    lines
         .iter()
-        .position(|ln| pat.is_match(ln))
-        .ok_or_else(|| format!("Could not find theorem/lemma/def named {}", decl_name))?;
+        .position(|ln| pat.is_match(ln.as_ref()))
+        .ok_or_else(|| format!("Could not find theorem/lemma/def named {}", decl_name))
+}
+
+fn extract_decl_signature_prefix(lines: &[&str], decl_name: &str) -> Result<Vec<String>, String> {
+    let start0 = find_decl_header(lines, decl_name)?;
 
     // We accept multi-line decl signatures and cut at the first top-level `:=`.
     //
@@ -1056,11 +1150,20 @@ fn extract_decl_signature_prefix(lines: &[&str], decl_name: &str) -> Result<Vec<
     ))
 }
 
+/// `rest` of an `instance` header past a leading `(priority := …)` (the name follows it);
+/// unchanged without one.
+fn strip_instance_priority(rest: &str) -> &str {
+    match rest.trim_start().strip_prefix("(priority") {
+        Some(p) if p.trim_start().starts_with(":=") => p.find(')').map_or(rest, |k| &p[k + 1..]),
+        _ => rest,
+    }
+}
+
 fn replace_decl_name_in_header_line(line: &str, old: &str, new: &str) -> String {
     // Best-effort anchored replacement using a decl-header regex that understands `@[attr]` prefixes.
     // We do not attempt full Lean parsing here.
     let Ok(re) = Regex::new(
-        r"^(\s*(?:@[^\n]*\s+)*(?:(?:private|protected|noncomputable|unsafe|partial)\s+)*\b(?:theorem|lemma|def|abbrev|instance)\s+)([^\s:(]+)(.*)$",
+        r"^(\s*(?:@[^\n]*\s+)*(?:(?:private|protected|noncomputable|unsafe|partial)\s+)*\b(?:theorem|lemma|def|abbrev|instance)\s+(?:\(priority\s*:=[^)]*\)\s+)?)([^\s:(]+)(.*)$",
     ) else {
         return line.replace(old, new);
     };
@@ -1151,7 +1254,8 @@ end ProofpatchInline
 "#
 }
 
-fn module_name_from_file_rel(file_rel: &str) -> Option<String> {
+/// Map a repo-relative `.lean` path to its Lake module name (`Foo/Bar.lean` -> `Foo.Bar`).
+pub fn module_name_from_file_rel(file_rel: &str) -> Option<String> {
     let rel = file_rel.trim();
     if rel.is_empty() {
         return None;
@@ -1314,16 +1418,24 @@ fn synthesize_pp_dump_shadow_decl_from_text(
         return Err("empty decl signature".to_string());
     }
 
-    let pat = decl_header_regex(decl_name)?;
-    let start0 = lines
-        .iter()
-        .position(|ln| pat.is_match(ln))
-        .ok_or_else(|| format!("Could not find theorem/lemma/def named {}", decl_name))?;
+    let start0 = find_decl_header(&lines, decl_name)?;
 
     let (ctx_prefix, ctx_closers) = collect_shadow_context_prefix(&lines, start0);
 
-    let shadow_name = format!("{}_proofpatch_shadow", decl_name);
-    sig[0] = replace_decl_name_in_header_line(&sig[0], decl_name, &shadow_name);
+    match parse_anonymous_decl_name(decl_name) {
+        // An `example` needs no name; an anonymous instance gets one so its generated name
+        // cannot clash with the original's (the shadow file imports the module).
+        Some(("instance", line_1)) => {
+            let k = sig[0].find("instance").map_or(0, |k| k + "instance".len());
+            let at = sig[0].len() - strip_instance_priority(&sig[0][k..]).len();
+            sig[0].insert_str(at, &format!(" proofpatch_shadow_{line_1}"));
+        }
+        Some(_) => {}
+        None => {
+            let shadow_name = format!("{}_proofpatch_shadow", decl_name);
+            sig[0] = replace_decl_name_in_header_line(&sig[0], decl_name, &shadow_name);
+        }
+    }
     for ln in sig.iter_mut() {
         *ln = strip_redundant_named_args(ln).trim_end().to_string();
     }
@@ -1386,12 +1498,7 @@ pub fn synthesize_pp_dump_shadow_decl(
 
 pub fn extract_decl_block(text: &str, decl_name: &str) -> Result<String, String> {
     let lines: Vec<&str> = text.lines().collect();
-    let pat = decl_header_regex(decl_name)?;
-
-    let start = lines
-        .iter()
-        .position(|ln| pat.is_match(ln))
-        .ok_or_else(|| format!("Could not find theorem/lemma/def named {}", decl_name))?;
+    let start = find_decl_header(&lines, decl_name)?;
 
     // Try to find `:=` line to anchor the end of the signature; then include some tail context.
     let mut end = None;
@@ -1419,11 +1526,7 @@ pub fn patch_first_sorry_in_decl(
     replacement: &str,
 ) -> Result<PatchResult, String> {
     let mut lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
-    let pat = decl_header_regex(decl_name)?;
-    let start = lines
-        .iter()
-        .position(|ln| pat.is_match(ln))
-        .ok_or_else(|| format!("Could not find theorem/lemma/def named {}", decl_name))?;
+    let start = find_decl_header(&lines, decl_name)?;
 
     let stop = usize::min(lines.len(), start + 350);
     let sorry_pat = Regex::new(r"\b(sorry|admit)\b")
@@ -1634,6 +1737,27 @@ end Foo
         assert!(out.contains("pp_dump"));
         assert!(out.contains("sorry"));
     }
+
+    #[test]
+    fn prioritised_instances_keep_their_names() {
+        let txt = "import Mathlib\n\n\
+                   instance (priority := 100) fooInst : Inhabited Nat := by\n  sorry\n\n\
+                   instance (priority := low) : Inhabited Int := by\n  sorry\n";
+        let d = nearest_decl_header_in_text(txt, 4, 200).expect("decl");
+        assert_eq!(d.name, "fooInst");
+        assert!(crate::patching::decl_byte_range(txt, "fooInst").is_ok());
+        let out = synthesize_pp_dump_shadow_decl_from_text("Foo/Bar.lean", "fooInst", txt)
+            .expect("synthesize should succeed");
+        assert!(
+            out.contains("instance (priority := 100) fooInst_proofpatch_shadow : Inhabited Nat")
+        );
+
+        let d = nearest_decl_header_in_text(txt, 7, 200).expect("decl");
+        assert_eq!(d.name, "instance@6");
+        let out = synthesize_pp_dump_shadow_decl_from_text("Foo/Bar.lean", "instance@6", txt)
+            .expect("synthesize should succeed");
+        assert!(out.contains("instance (priority := low) proofpatch_shadow_6 : Inhabited Int"));
+    }
 }
 
 pub fn patch_first_sorry_in_region(
@@ -1795,13 +1919,22 @@ pub fn locate_sorries_in_text(
             "instance",
             "structure",
             "class",
+            "example",
         ] {
             let prefix = format!("{kw} ");
             if let Some(rest) = t.strip_prefix(&prefix) {
+                let rest = if kw == "instance" {
+                    strip_instance_priority(rest)
+                } else {
+                    rest
+                };
                 let rest = rest.trim_start();
                 if rest.is_empty() {
                     return None;
                 }
+                if kw == "example" {
+                    return Some((kw, String::new()));
+                }
                 // name is the first token, stopping at whitespace or common delimiters
                 let mut name = String::new();
                 for ch in rest.chars() {
@@ -1810,7 +1943,7 @@ pub fn locate_sorries_in_text(
                     }
                     name.push(ch);
                 }
-                if name.is_empty() {
+                if name.is_empty() && kw != "instance" {
                     return None;
                 }
                 return Some((kw, name));
@@ -1826,6 +1959,11 @@ pub fn locate_sorries_in_text(
         let mut i = usize::min(line0, lines.len().saturating_sub(1));
         loop {
             if let Some((kw, name)) = parse_decl_header(lines[i]) {
+                let name = if name.is_empty() {
+                    anonymous_decl_name(kw, i + 1)
+                } else {
+                    name
+                };
                 return (Some(kw.to_string()), Some(name), Some(i + 1));
             }
             if i == 0 {
@@ -2087,8 +2225,32 @@ pub fn build_proof_prompt(
     let txt = std::fs::read_to_string(&p)
         .map_err(|e| format!("failed to read {}: {}", p.display(), e))?;
     let excerpt = extract_decl_block(&txt, decl)?;
+    Ok(proof_prompt_payload(&repo_root, &p, decl, excerpt))
+}
 
-    let system = proof_system_prompt();
+/// Like `build_proof_prompt`, but the excerpt is `prompt_context::minimal_context` rendered
+/// within `max_tokens` (scope, same-file dependencies, nearby lemmas) instead of a line window.
+pub fn build_proof_prompt_minimal(
+    repo_root: &Path,
+    file_rel: &str,
+    decl: &str,
+    max_tokens: usize,
+) -> Result<PromptPayload, String> {
+    let repo_root = find_lean_repo_root(repo_root)?;
+    load_dotenv_smart(&repo_root);
+
+    let p = repo_root.join(file_rel);
+    if !p.exists() {
+        return Err(format!("File not found: {}", p.display()));
+    }
+    let txt = std::fs::read_to_string(&p)
+        .map_err(|e| format!("failed to read {}: {}", p.display(), e))?;
+    let excerpt = prompt_context::minimal_context(&txt, decl, max_tokens)?.render();
+    Ok(proof_prompt_payload(&repo_root, &p, decl, excerpt))
+}
+
+fn proof_prompt_payload(repo_root: &Path, p: &Path, decl: &str, excerpt: String) -> PromptPayload {
+    let system = prompts::system_prompt(prompts::Task::Patch, &proof_system_prompt());
     let user = proof_user_prompt(&excerpt);
     let prompt_combined = format!("{system}\n\n{user}");
     let prompt_combined_chars = prompt_combined.chars().count();
@@ -2099,7 +2261,7 @@ pub fn build_proof_prompt(
         format!("{:x}", h.finalize())
     };
 
-    Ok(PromptPayload {
+    PromptPayload {
         repo_root: repo_root.display().to_string(),
         file: p.display().to_string(),
         decl: decl.to_string(),
@@ -2109,7 +2271,7 @@ pub fn build_proof_prompt(
         prompt_combined,
         prompt_combined_sha256,
         prompt_combined_chars,
-    })
+    }
 }
 
 /// System prompt for proof suggestion (reused across CLI/MCP).
@@ -2481,13 +2643,33 @@ pub struct ContextPack {
 
 fn any_decl_header_regex() -> Result<Regex, String> {
     // Lean identifiers can include `.`, `_`, `'`, unicode, etc.
-    // We capture the next token up to whitespace/colon/paren as a best-effort name.
+    // We capture the next token up to whitespace/colon/paren as a best-effort name, past an
+    // instance's `(priority := …)`.
     Regex::new(
-        r"^\s*(?:@[^\n]*\s+)*(?:(?:private|protected|noncomputable|unsafe|partial)\s+)*\b(theorem|lemma|def|abbrev|instance)\s+([^\s:(]+)",
+        r"^\s*(?:@[^\n]*\s+)*(?:(?:private|protected|noncomputable|unsafe|partial)\s+)*\b(theorem|lemma|def|abbrev|instance|example)\b(?:\s+\(priority\s*:=[^)]*\))?(?:\s+([^\s:(\[{]+))?",
     )
         .map_err(|e| format!("invalid decl-header regex: {}", e))
 }
 
+/// The name captured by `any_decl_header_regex` (`None` for `example` and anonymous instances).
+fn decl_header_name(cap: &regex::Captures<'_>) -> Option<String> {
+    if &cap[1] == "example" {
+        return None;
+    }
+    cap.get(2).map(|m| m.as_str().to_string())
+}
+
+/// The name to report for a header on line `line_1`: its own, or `anonymous_decl_name`.
+fn decl_header_ident(cap: &regex::Captures<'_>, line_1: usize) -> Option<String> {
+    match decl_header_name(cap) {
+        Some(name) => Some(name),
+        None if matches!(&cap[1], "example" | "instance") => {
+            Some(anonymous_decl_name(&cap[1], line_1))
+        }
+        None => None,
+    }
+}
+
 /// Best-effort: find the nearest declaration header at/above `focus_line_1`.
 ///
 /// This is intentionally shallow (regex-based) and only meant to support UX flows like
@@ -2553,12 +2735,10 @@ pub fn nearest_decl_header_in_text(
         if !t.is_empty() && !t.starts_with("--") && !in_block[i0] && decl_pat.captures(ln).is_some()
         {
             let cap = decl_pat.captures(ln)?;
-            let kind = cap.get(1).map(|m| m.as_str()).unwrap_or("").to_string();
-            let name = cap.get(2).map(|m| m.as_str()).unwrap_or("").to_string();
-            if !kind.is_empty() && !name.is_empty() {
+            if let Some(name) = decl_header_ident(&cap, i0 + 1) {
                 return Some(NearbyDecl {
                     line: i0 + 1,
-                    kind,
+                    kind: cap[1].to_string(),
                     name,
                     header: ln.to_string(),
                 });
@@ -2574,11 +2754,7 @@ pub fn nearest_decl_header_in_text(
 }
 
 fn extract_decl_span(lines: &[&str], decl_name: &str) -> Result<(usize, usize, String), String> {
-    let pat = decl_header_regex(decl_name)?;
-    let start0 = lines
-        .iter()
-        .position(|ln| pat.is_match(ln))
-        .ok_or_else(|| format!("Could not find theorem/lemma/def named {}", decl_name))?;
+    let start0 = find_decl_header(lines, decl_name)?;
 
     // Try to find `:=` line to anchor the end of the signature; then include some tail context.
     let mut sig_end0 = None;
@@ -2715,12 +2891,13 @@ pub fn build_context_pack(
     if !lines.is_empty() {
         for i1 in start_near..=end_near {
             let ln = lines[i1 - 1];
-            if let Some(cap) = decl_pat.captures(ln) {
-                let kind = cap.get(1).map(|m| m.as_str()).unwrap_or("").to_string();
-                let name = cap.get(2).map(|m| m.as_str()).unwrap_or("").to_string();
+            if let Some((cap, name)) = decl_pat
+                .captures(ln)
+                .and_then(|cap| decl_header_ident(&cap, i1).map(|n| (cap, n)))
+            {
                 nearby_decls.push(NearbyDecl {
                     line: i1,
-                    kind,
+                    kind: cap[1].to_string(),
                     name,
                     header: ln.to_string(),
                 });
@@ -2743,13 +2920,35 @@ pub fn build_context_pack(
     })
 }
 
+/// Upper bound for one automatic `lake exe cache get` (see `mathlib_cache`).
+const MATHLIB_CACHE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
 pub async fn verify_lean_text(
     repo_root: &Path,
     lean_text: &str,
     timeout_s: Duration,
+) -> Result<VerifyResult, String> {
+    verify_lean_text_until(repo_root, lean_text, timeout_s, None).await
+}
+
+/// `verify_lean_text`, streaming Lean's output and killing it at the first error inside the
+/// 1-based line range `abort_in` of `lean_text` (`verify::is_fatal_line`; `aborted` is set then):
+/// the text has failed already, and the rest of the file would only delay the next check.
+pub async fn verify_lean_text_until(
+    repo_root: &Path,
+    lean_text: &str,
+    timeout_s: Duration,
+    abort_in: Option<(usize, usize)>,
 ) -> Result<VerifyResult, String> {
     let repo_root = find_lean_repo_root(repo_root)?;
     load_dotenv_smart(&repo_root);
+    // Fetch prebuilt Mathlib `.olean`s first; otherwise `lake` rebuilds Mathlib from source.
+    mathlib_cache::ensure_mathlib_cache(
+        &repo_root,
+        &mathlib_cache::mathlib_imports(lean_text),
+        MATHLIB_CACHE_TIMEOUT,
+    )
+    .await;
     let lake = resolve_lake();
 
     // NOTE: `needs_process_stdout` is only relevant when the `lsp` feature is enabled.
@@ -2790,6 +2989,7 @@ pub async fn verify_lean_text(
                     cmd: vec![lake.display().to_string(), "build".to_string()],
                     cwd: repo_root.display().to_string(),
                     tmp_file: None,
+                    aborted: false,
                 });
             }
             Ok(Err(e)) => {
@@ -2802,6 +3002,7 @@ pub async fn verify_lean_text(
                     cmd: vec![lake.display().to_string(), "build".to_string()],
                     cwd: repo_root.display().to_string(),
                     tmp_file: None,
+                    aborted: false,
                 });
             }
             Err(_) => {
@@ -2814,6 +3015,7 @@ pub async fn verify_lean_text(
                     cmd: vec![lake.display().to_string(), "build".to_string()],
                     cwd: repo_root.display().to_string(),
                     tmp_file: None,
+                    aborted: false,
                 });
             }
         }
@@ -2828,6 +3030,15 @@ pub async fn verify_lean_text(
         .map_err(|e| format!("failed to write temp lean file: {}", e))?;
     let tmp_path = tmp.into_temp_path();
     let tmp_path_buf = tmp_path.to_path_buf();
+    let tmp_name = tmp_path_buf.display().to_string();
+    let aborted = std::sync::atomic::AtomicBool::new(false);
+    let abort = |l: &str| {
+        let hit = abort_in.is_some_and(|r| verify::is_fatal_line(l, &tmp_name, Some(r)));
+        if hit {
+            aborted.store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+        hit
+    };
 
     // Verifier backend:
     // - default: "auto" (try lean-env, fallback to lake env)
@@ -2874,38 +3085,18 @@ pub async fn verify_lean_text(
 
                     if needs_process_stdout && force_process_oracle && !has_oracle_like_output {
                         // LSP didn't surface the oracle-style output we need; fall back to process verifier.
-                        match run_lean_with_env(&repo_root, &lean_args, timeout_s).await {
+                        match run_lean_with_env(&repo_root, &lean_args, timeout_s, &abort).await {
                             Ok(r) => (r.0, r.1, r.2, r.3, r.4, lean_cmd_vec.clone()),
                             Err(_) => {
                                 // Fall back to `lake env lean` so we still capture stdout/stderr.
-                                let mut cmd = Command::new(&lake);
-                                cmd.arg("env")
-                                    .arg("lean")
-                                    .arg(&tmp_path_buf)
-                                    .current_dir(&repo_root);
-                                maybe_extend_lean_path_for_lake_env(&mut cmd);
-                                let out = tokio::time::timeout(timeout_s, cmd.output())
-                                    .await
-                                    .map_err(|_| "timeout".to_string());
-                                let r = match out {
-                                    Err(_) => (false, true, None, String::new(), String::new()),
-                                    Ok(Err(e)) => (
-                                        false,
-                                        false,
-                                        None,
-                                        String::new(),
-                                        format!("failed to execute: {}", e),
-                                    ),
-                                    Ok(Ok(output)) => {
-                                        let ok = output.status.success();
-                                        let returncode = output.status.code();
-                                        let stdout =
-                                            String::from_utf8_lossy(&output.stdout).to_string();
-                                        let stderr =
-                                            String::from_utf8_lossy(&output.stderr).to_string();
-                                        (ok, false, returncode, stdout, stderr)
-                                    }
-                                };
+                                let r = lake_env_lean(
+                                    &lake,
+                                    &tmp_path_buf,
+                                    &repo_root,
+                                    timeout_s,
+                                    &abort,
+                                )
+                                .await;
                                 (r.0, r.1, r.2, r.3, r.4, lake_cmd_vec.clone())
                             }
                         }
@@ -2946,35 +3137,10 @@ pub async fn verify_lean_text(
             }
         }
         "lake" => {
-            let mut cmd = Command::new(&lake);
-            cmd.arg("env")
-                .arg("lean")
-                .arg(&tmp_path_buf)
-                .current_dir(&repo_root);
-            maybe_extend_lean_path_for_lake_env(&mut cmd);
-            let out = tokio::time::timeout(timeout_s, cmd.output())
-                .await
-                .map_err(|_| "timeout".to_string());
-            let r = match out {
-                Err(_) => (false, true, None, String::new(), String::new()),
-                Ok(Err(e)) => (
-                    false,
-                    false,
-                    None,
-                    String::new(),
-                    format!("failed to execute: {}", e),
-                ),
-                Ok(Ok(output)) => {
-                    let ok = output.status.success();
-                    let returncode = output.status.code();
-                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                    (ok, false, returncode, stdout, stderr)
-                }
-            };
+            let r = lake_env_lean(&lake, &tmp_path_buf, &repo_root, timeout_s, &abort).await;
             (r.0, r.1, r.2, r.3, r.4, lake_cmd_vec.clone())
         }
-        "lean" => match run_lean_with_env(&repo_root, &lean_args, timeout_s).await {
+        "lean" => match run_lean_with_env(&repo_root, &lean_args, timeout_s, &abort).await {
             Ok(r) => (r.0, r.1, r.2, r.3, r.4, lean_cmd_vec.clone()),
             Err(e) => (
                 false,
@@ -3012,37 +3178,12 @@ pub async fn verify_lean_text(
                         vec!["lean".to_string(), "--server".to_string()],
                     )
                 } else {
-                    match run_lean_with_env(&repo_root, &lean_args, timeout_s).await {
+                    match run_lean_with_env(&repo_root, &lean_args, timeout_s, &abort).await {
                         Ok(r) => (r.0, r.1, r.2, r.3, r.4, lean_cmd_vec.clone()),
                         Err(_) => {
-                            let mut cmd = Command::new(&lake);
-                            cmd.arg("env")
-                                .arg("lean")
-                                .arg(&tmp_path_buf)
-                                .current_dir(&repo_root);
-                            maybe_extend_lean_path_for_lake_env(&mut cmd);
-                            let out = tokio::time::timeout(timeout_s, cmd.output())
-                                .await
-                                .map_err(|_| "timeout".to_string());
-                            let r = match out {
-                                Err(_) => (false, true, None, String::new(), String::new()),
-                                Ok(Err(e)) => (
-                                    false,
-                                    false,
-                                    None,
-                                    String::new(),
-                                    format!("failed to execute: {}", e),
-                                ),
-                                Ok(Ok(output)) => {
-                                    let ok = output.status.success();
-                                    let returncode = output.status.code();
-                                    let stdout =
-                                        String::from_utf8_lossy(&output.stdout).to_string();
-                                    let stderr =
-                                        String::from_utf8_lossy(&output.stderr).to_string();
-                                    (ok, false, returncode, stdout, stderr)
-                                }
-                            };
+                            let r =
+                                lake_env_lean(&lake, &tmp_path_buf, &repo_root, timeout_s, &abort)
+                                    .await;
                             (r.0, r.1, r.2, r.3, r.4, lake_cmd_vec.clone())
                         }
                     }
@@ -3050,35 +3191,11 @@ pub async fn verify_lean_text(
             }
             #[cfg(not(feature = "lsp"))]
             {
-                match run_lean_with_env(&repo_root, &lean_args, timeout_s).await {
+                match run_lean_with_env(&repo_root, &lean_args, timeout_s, &abort).await {
                     Ok(r) => (r.0, r.1, r.2, r.3, r.4, lean_cmd_vec.clone()),
                     Err(_) => {
-                        let mut cmd = Command::new(&lake);
-                        cmd.arg("env")
-                            .arg("lean")
-                            .arg(&tmp_path_buf)
-                            .current_dir(&repo_root);
-                        maybe_extend_lean_path_for_lake_env(&mut cmd);
-                        let out = tokio::time::timeout(timeout_s, cmd.output())
-                            .await
-                            .map_err(|_| "timeout".to_string());
-                        let r = match out {
-                            Err(_) => (false, true, None, String::new(), String::new()),
-                            Ok(Err(e)) => (
-                                false,
-                                false,
-                                None,
-                                String::new(),
-                                format!("failed to execute: {}", e),
-                            ),
-                            Ok(Ok(output)) => {
-                                let ok = output.status.success();
-                                let returncode = output.status.code();
-                                let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                                (ok, false, returncode, stdout, stderr)
-                            }
-                        };
+                        let r = lake_env_lean(&lake, &tmp_path_buf, &repo_root, timeout_s, &abort)
+                            .await;
                         (r.0, r.1, r.2, r.3, r.4, lake_cmd_vec.clone())
                     }
                 }
@@ -3097,32 +3214,7 @@ pub async fn verify_lean_text(
             || merged.contains("unknown module prefix")
             || merged.contains("unknown module")
         {
-            let mut cmd = Command::new(&lake);
-            cmd.arg("env")
-                .arg("lean")
-                .arg(&tmp_path_buf)
-                .current_dir(&repo_root);
-            maybe_extend_lean_path_for_lake_env(&mut cmd);
-            let out = tokio::time::timeout(timeout_s, cmd.output())
-                .await
-                .map_err(|_| "timeout".to_string());
-            let r = match out {
-                Err(_) => (false, true, None, String::new(), String::new()),
-                Ok(Err(e)) => (
-                    false,
-                    false,
-                    None,
-                    String::new(),
-                    format!("failed to execute: {}", e),
-                ),
-                Ok(Ok(output)) => {
-                    let ok = output.status.success();
-                    let returncode = output.status.code();
-                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                    (ok, false, returncode, stdout, stderr)
-                }
-            };
+            let r = lake_env_lean(&lake, &tmp_path_buf, &repo_root, timeout_s, &abort).await;
             ok = r.0;
             timeout = r.1;
             returncode = r.2;
@@ -3181,63 +3273,11 @@ pub async fn verify_lean_text(
             let _ = retry;
             // Retry using the same selection logic (lean-env if available, else lake).
             let r = match backend.as_str() {
-                "lake" => {
-                    let mut cmd = Command::new(&lake);
-                    cmd.arg("env")
-                        .arg("lean")
-                        .arg(&tmp_path_buf)
-                        .current_dir(&repo_root);
-                    maybe_extend_lean_path_for_lake_env(&mut cmd);
-                    let out2 = tokio::time::timeout(timeout_s, cmd.output())
-                        .await
-                        .map_err(|_| "timeout".to_string());
-                    match out2 {
-                        Err(_) => (false, true, None, String::new(), String::new()),
-                        Ok(Err(e)) => (
-                            false,
-                            false,
-                            None,
-                            String::new(),
-                            format!("failed to execute: {}", e),
-                        ),
-                        Ok(Ok(output)) => {
-                            let ok = output.status.success();
-                            let returncode = output.status.code();
-                            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                            (ok, false, returncode, stdout, stderr)
-                        }
-                    }
-                }
-                _ => match run_lean_with_env(&repo_root, &lean_args, timeout_s).await {
+                "lake" => lake_env_lean(&lake, &tmp_path_buf, &repo_root, timeout_s, &abort).await,
+                _ => match run_lean_with_env(&repo_root, &lean_args, timeout_s, &abort).await {
                     Ok(r) => (r.0, r.1, r.2, r.3, r.4),
                     Err(_) => {
-                        let mut cmd = Command::new(&lake);
-                        cmd.arg("env")
-                            .arg("lean")
-                            .arg(&tmp_path_buf)
-                            .current_dir(&repo_root);
-                        maybe_extend_lean_path_for_lake_env(&mut cmd);
-                        let out2 = tokio::time::timeout(timeout_s, cmd.output())
-                            .await
-                            .map_err(|_| "timeout".to_string());
-                        match out2 {
-                            Err(_) => (false, true, None, String::new(), String::new()),
-                            Ok(Err(e)) => (
-                                false,
-                                false,
-                                None,
-                                String::new(),
-                                format!("failed to execute: {}", e),
-                            ),
-                            Ok(Ok(output)) => {
-                                let ok = output.status.success();
-                                let returncode = output.status.code();
-                                let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                                (ok, false, returncode, stdout, stderr)
-                            }
-                        }
+                        lake_env_lean(&lake, &tmp_path_buf, &repo_root, timeout_s, &abort).await
                     }
                 },
             };
@@ -3262,6 +3302,7 @@ pub async fn verify_lean_text(
         cwd: repo_root.display().to_string(),
         // We delete the temp file before returning; keep output truthful.
         tmp_file: None,
+        aborted: aborted.load(std::sync::atomic::Ordering::Relaxed),
     })
 }
 
@@ -3276,6 +3317,12 @@ pub async fn verify_lean_file(
     if !p.exists() {
         return Err(format!("File not found: {}", p.display()));
     }
+    mathlib_cache::ensure_mathlib_cache(
+        &repo_root,
+        &mathlib_cache::mathlib_imports(&std::fs::read_to_string(&p).unwrap_or_default()),
+        MATHLIB_CACHE_TIMEOUT,
+    )
+    .await;
 
     // Prefer verifying the real file path. This avoids module-resolution problems for repos
     // that use their own module roots (e.g. `MIL.*`) and haven’t been built yet.
@@ -3357,7 +3404,13 @@ pub async fn verify_lean_file(
                 }
                 Err(_) => {
                     // fall back to lean-env (captured `lake env env`) for file verification
-                    match run_lean_with_env(&repo_root, &[p.display().to_string()], timeout_s).await
+                    match run_lean_with_env(
+                        &repo_root,
+                        &[p.display().to_string()],
+                        timeout_s,
+                        |_| false,
+                    )
+                    .await
                     {
                         Ok(r) => (r.0, r.1, r.2, r.3, r.4, lean_cmd_vec),
                         Err(e) => (
@@ -3373,7 +3426,9 @@ pub async fn verify_lean_file(
             }
         }
         "lean" => {
-            match run_lean_with_env(&repo_root, &[p.display().to_string()], timeout_s).await {
+            match run_lean_with_env(&repo_root, &[p.display().to_string()], timeout_s, |_| false)
+                .await
+            {
                 Ok(r) => (r.0, r.1, r.2, r.3, r.4, lean_cmd_vec),
                 Err(e) => (
                     false,
@@ -3407,7 +3462,13 @@ pub async fn verify_lean_file(
                         vec!["lean".to_string(), "--server".to_string()],
                     )
                 } else {
-                    match run_lean_with_env(&repo_root, &[p.display().to_string()], timeout_s).await
+                    match run_lean_with_env(
+                        &repo_root,
+                        &[p.display().to_string()],
+                        timeout_s,
+                        |_| false,
+                    )
+                    .await
                     {
                         Ok(r) => (r.0, r.1, r.2, r.3, r.4, lean_cmd_vec),
                         Err(_) => {
@@ -3419,7 +3480,11 @@ pub async fn verify_lean_file(
             }
             #[cfg(not(feature = "lsp"))]
             {
-                match run_lean_with_env(&repo_root, &[p.display().to_string()], timeout_s).await {
+                match run_lean_with_env(&repo_root, &[p.display().to_string()], timeout_s, |_| {
+                    false
+                })
+                .await
+                {
                     Ok(r) => (r.0, r.1, r.2, r.3, r.4, lean_cmd_vec),
                     Err(_) => {
                         let r = run_lake().await;
@@ -3455,6 +3520,7 @@ pub async fn verify_lean_file(
                     cmd: vec![lake.display().to_string(), "build".to_string()],
                     cwd: repo_root.display().to_string(),
                     tmp_file: None,
+                    aborted: false,
                 });
             }
             Ok(Err(e)) => {
@@ -3467,6 +3533,7 @@ pub async fn verify_lean_file(
                     cmd: vec![lake.display().to_string(), "build".to_string()],
                     cwd: repo_root.display().to_string(),
                     tmp_file: None,
+                    aborted: false,
                 });
             }
             Err(_) => {
@@ -3479,6 +3546,7 @@ pub async fn verify_lean_file(
                     cmd: vec![lake.display().to_string(), "build".to_string()],
                     cwd: repo_root.display().to_string(),
                     tmp_file: None,
+                    aborted: false,
                 });
             }
         }
@@ -3486,7 +3554,11 @@ pub async fn verify_lean_file(
         // Re-run verification after build using the same backend selection logic.
         match backend.as_str() {
             "lake" => run_lake().await,
-            _ => match run_lean_with_env(&repo_root, &[p.display().to_string()], timeout_s).await {
+            _ => match run_lean_with_env(&repo_root, &[p.display().to_string()], timeout_s, |_| {
+                false
+            })
+            .await
+            {
                 Ok(r) => r,
                 Err(_) => run_lake().await,
             },
@@ -3504,6 +3576,7 @@ pub async fn verify_lean_file(
         cmd: cmd_vec,
         cwd: repo_root.display().to_string(),
         tmp_file: None,
+        aborted: false,
     })
 }
 
@@ -3598,6 +3671,14 @@ pub fn extract_pp_dump_from_lean_output(merged_stdout_stderr: &str) -> Option<se
         if is_pp {
             return Some(obj);
         }
+        // Higher-fidelity exporter output: decode to the same shape.
+        let is_infotree = obj.get("tool").and_then(|v| v.as_str()) == Some("proofpatch")
+            && obj.get("kind").and_then(|v| v.as_str()) == Some("infotree");
+        if is_infotree {
+            if let Ok(pp) = infotree::pp_dump_from_infotree(&obj) {
+                return Some(pp);
+            }
+        }
     }
     None
 }
@@ -4633,6 +4714,24 @@ end ProofpatchInline
         }
     }
 
+    // Lets `smt_lia` resolve names printed relative to (or qualified by) these namespaces.
+    if let Some(pp) = pp_dump.as_mut().and_then(|v| v.as_object_mut()) {
+        pp.insert(
+            "open_namespaces".to_string(),
+            serde_json::json!(prompt_context::open_namespaces_at(base_text, selected.line)),
+        );
+        // Lets the patch engine map the goal back to this exact `sorry` (`patching::GoalRef`).
+        let line_start: usize = base_text
+            .split_inclusive('\n')
+            .take(selected.line - 1)
+            .map(str::len)
+            .sum();
+        let byte = line_start + selected.col.saturating_sub(1);
+        if let Some(g) = patching::GoalRef::at_byte(base_text, byte) {
+            pp.insert("decl".to_string(), serde_json::json!(g.decl));
+            pp.insert("sorry_index".to_string(), serde_json::json!(g.sorry_index));
+        }
+    }
     let selected_v =
         serde_json::to_value(&selected).map_err(|e| format!("failed to serialize sorry: {e}"))?;
 
@@ -0,0 +1,445 @@
+//! Lean's own library search (`exact?`, `apply?`, `rw?`) as a candidate source.
+//!
+//! For one hole we replace the placeholder with a search tactic, elaborate the patched text, and
+//! parse the `Try this:` / `Try these:` messages reported at the hole into `LibrarySuggestion`s.
+//! Lean is the judge here, so these candidates are cheap and high-precision compared to
+//! heuristics or the LLM; the cost is one elaboration per search tactic.
+//!
+//! Suggestions are ordered by confidence:
+//! 1. scripts that close the goal with `exact` (Lean already type-checked them),
+//! 2. closing `rw [...]` chains (`-- no goals`),
+//! 3. other closing scripts,
+//! 4. `apply`/`refine` scripts that leave subgoals (fewer subgoals first),
+//! 5. non-closing rewrites.
+//!
+//! Ties keep Lean's own ordering.
+
+use crate::patching::{apply_patch, PatchTarget};
+use crate::scan::{find_placeholders, PlaceholderHit, PLACEHOLDER_TOKENS};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchTactic {
+    Exact,
+    Apply,
+    Rw,
+}
+
+impl SearchTactic {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Exact => "exact?",
+            Self::Apply => "apply?",
+            Self::Rw => "rw?",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().trim_end_matches('?') {
+            "exact" => Some(Self::Exact),
+            "apply" => Some(Self::Apply),
+            "rw" => Some(Self::Rw),
+            _ => None,
+        }
+    }
+}
+
+/// One structured `Try this:` suggestion.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibrarySuggestion {
+    /// The tactic script Lean suggested (may span several lines).
+    pub script: String,
+    /// Which search tactic produced it.
+    pub source: SearchTactic,
+    /// Leading tactic of the script (`exact`, `refine`, `rw`, …).
+    pub head: String,
+    /// Whether the script closes the goal on its own.
+    pub closes_goal: bool,
+    /// Subgoals left open (from `-- Remaining subgoals:` or `?_` holes).
+    pub remaining_goals: usize,
+    /// Goals Lean printed after the script (`-- ⊢ …` lines), if any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remaining: Vec<String>,
+    /// Ordering score in `[0, 1]`; see the module docs.
+    pub confidence: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LibrarySearchOptions {
+    pub tactics: Vec<SearchTactic>,
+    pub timeout: Duration,
+    /// Skip the remaining tactics once a pass produced a goal-closing suggestion.
+    pub stop_on_closing: bool,
+}
+
+impl Default for LibrarySearchOptions {
+    fn default() -> Self {
+        Self {
+            tactics: vec![SearchTactic::Exact, SearchTactic::Rw, SearchTactic::Apply],
+            timeout: Duration::from_secs(120),
+            stop_on_closing: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibrarySearchPass {
+    pub tactic: SearchTactic,
+    pub elapsed_ms: u64,
+    pub timeout: bool,
+    pub suggestions: usize,
+    /// First error reported at the hole (e.g. `exact? could not close the goal`).
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibrarySearchReport {
+    pub file: String,
+    /// 1-based line of the hole.
+    pub line: usize,
+    pub passes: Vec<LibrarySearchPass>,
+    /// All suggestions, deduplicated and in confidence order.
+    pub suggestions: Vec<LibrarySuggestion>,
+}
+
+/// A compiler message split out of `lake env lean` output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Message {
+    line: Option<usize>,
+    severity: String,
+    body: String,
+}
+
+fn header_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^(?:.*?):(\d+):(\d+): (?:(info|warning|error): )?(.*)$").expect("regex")
+    })
+}
+
+/// Split output into messages. Text before the first `file:line:col:` header (or output without
+/// headers at all) becomes a single unlocated `info` message.
+fn split_messages(output: &str) -> Vec<Message> {
+    let mut out: Vec<Message> = Vec::new();
+    for raw in output.lines() {
+        if let Some(c) = header_re().captures(raw) {
+            out.push(Message {
+                line: c[1].parse().ok(),
+                severity: c
+                    .get(3)
+                    .map(|m| m.as_str().to_string())
+                    .unwrap_or_else(|| "info".to_string()),
+                body: c[4].to_string(),
+            });
+        } else if let Some(last) = out.last_mut() {
+            last.body.push('\n');
+            last.body.push_str(raw);
+        } else {
+            out.push(Message {
+                line: None,
+                severity: "info".to_string(),
+                body: raw.to_string(),
+            });
+        }
+    }
+    out
+}
+
+fn is_bullet(t: &str) -> Option<&str> {
+    t.strip_prefix('•')
+        .or_else(|| t.strip_prefix("- "))
+        .map(str::trim)
+}
+
+/// Raw suggestion blocks: the script lines plus trailing `-- …` comment lines.
+fn suggestion_blocks(body: &str) -> Vec<(Vec<String>, Vec<String>)> {
+    let lines: Vec<&str> = body.lines().collect();
+    let mut out = Vec::new();
+    let mut i = 0usize;
+    while i < lines.len() {
+        let t = lines[i].trim();
+        let Some(pos) = t.find("Try this:").or_else(|| t.find("Try these:")) else {
+            i += 1;
+            continue;
+        };
+        let many = t[pos..].starts_with("Try these:");
+        let inline = t[pos..].split_once(':').map(|x| x.1.trim()).unwrap_or("");
+        i += 1;
+        let mut cur: Option<(Vec<String>, Vec<String>)> = None;
+        if !inline.is_empty() {
+            cur = Some((vec![inline.to_string()], Vec::new()));
+        }
+        while i < lines.len() {
+            let raw = lines[i];
+            let t = raw.trim();
+            if t.is_empty() {
+                if cur.is_some() && !many {
+                    break;
+                }
+                i += 1;
+                continue;
+            }
+            if t.contains("Try this:") || t.contains("Try these:") {
+                break;
+            }
+            let indented = raw.starts_with([' ', '\t']);
+            if let Some(c) = t.strip_prefix("--") {
+                match cur.as_mut() {
+                    Some((_, comments)) => comments.push(c.trim().to_string()),
+                    None => break,
+                }
+            } else if let Some(b) = is_bullet(t).filter(|_| many) {
+                out.extend(cur.take());
+                cur = Some((vec![b.to_string()], Vec::new()));
+            } else if cur.is_none() {
+                // `Try this:` followed by the script on the next line(s), optionally `[tag]`-prefixed.
+                let s = match t.strip_prefix('[').and_then(|r| r.split_once(']')) {
+                    Some((_, s)) => s.trim(),
+                    None => t,
+                };
+                cur = Some((vec![s.to_string()], Vec::new()));
+            } else if indented && cur.as_ref().is_some_and(|c| c.1.is_empty()) {
+                if let Some((script, _)) = cur.as_mut() {
+                    script.push(t.to_string());
+                }
+            } else {
+                break;
+            }
+            i += 1;
+        }
+        out.extend(cur);
+    }
+    out
+}
+
+fn classify(source: SearchTactic, script: Vec<String>, comments: Vec<String>) -> LibrarySuggestion {
+    let script = script.join("\n");
+    let head = script
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .trim_end_matches(['[', '?'])
+        .to_string();
+    let no_goals = comments.iter().any(|c| c == "no goals");
+    let remaining: Vec<String> = comments
+        .iter()
+        .filter_map(|c| c.strip_prefix('⊢').map(|g| format!("⊢ {}", g.trim())))
+        .collect();
+    static HOLE: OnceLock<Regex> = OnceLock::new();
+    let holes = HOLE
+        .get_or_init(|| Regex::new(r"\?[A-Za-z_][A-Za-z0-9_']*").expect("regex"))
+        .find_iter(&script)
+        .count();
+    let remaining_goals = if no_goals {
+        0
+    } else if !remaining.is_empty() {
+        remaining.len()
+    } else if head == "rw" && !comments.is_empty() {
+        // `rw?` prints the rewritten goal as a bare comment.
+        comments.len()
+    } else {
+        holes
+    };
+    let closes_goal = remaining_goals == 0;
+    let confidence = match (closes_goal, head.as_str()) {
+        (true, "exact") => 1.0,
+        (true, "rw" | "rwa" | "simp_rw") => 0.9,
+        (true, _) => 0.85,
+        (false, "rw" | "rwa" | "simp_rw") => 0.3,
+        (false, _) => (0.6 - 0.1 * (remaining_goals.saturating_sub(1)) as f64).max(0.35),
+    };
+    let remaining = if remaining.is_empty() && head == "rw" && !no_goals {
+        comments.iter().map(|c| format!("⊢ {c}")).collect()
+    } else {
+        remaining
+    };
+    LibrarySuggestion {
+        script,
+        source,
+        head,
+        closes_goal,
+        remaining_goals,
+        remaining,
+        confidence,
+    }
+}
+
+/// Parse the suggestions produced by `source` in compiler output.
+///
+/// When `at_line` is set, only messages reported on that line (plus unlocated text) are used, so
+/// suggestion tactics elsewhere in the file do not leak in. Scripts containing `sorry` are dropped.
+pub fn parse_library_suggestions(
+    output: &str,
+    source: SearchTactic,
+    at_line: Option<usize>,
+) -> Vec<LibrarySuggestion> {
+    let mut out: Vec<LibrarySuggestion> = split_messages(output)
+        .into_iter()
+        .filter(|m| m.severity != "error")
+        .filter(|m| at_line.is_none() || m.line.is_none() || m.line == at_line)
+        .flat_map(|m| suggestion_blocks(&m.body))
+        .map(|(script, comments)| classify(source, script, comments))
+        .filter(|s| !s.script.is_empty() && !s.script.contains("sorry"))
+        .collect();
+    rank_suggestions(&mut out);
+    out
+}
+
+/// Deduplicate by script (keeping the most confident) and sort by confidence, stable otherwise.
+pub fn rank_suggestions(suggestions: &mut Vec<LibrarySuggestion>) {
+    suggestions.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    let mut seen = std::collections::HashSet::new();
+    suggestions.retain(|s| seen.insert(s.script.clone()));
+}
+
+/// Suggestions as replacement candidates (`by\n  <script>`), closing ones only.
+pub fn candidates_from_suggestions(suggestions: &[LibrarySuggestion]) -> Vec<String> {
+    let out = suggestions
+        .iter()
+        .filter(|s| s.closes_goal)
+        .map(|s| format!("by\n  {}", s.script.replace('\n', "\n  ")))
+        .collect();
+    crate::tree_search::sanitize_candidates(out)
+}
+
+/// The placeholder nearest to `line` (1-based).
+pub fn placeholder_near_line(text: &str, line: usize) -> Option<PlaceholderHit> {
+    let tokens: Vec<String> = PLACEHOLDER_TOKENS.iter().map(|s| s.to_string()).collect();
+    find_placeholders(text, &tokens)
+        .into_iter()
+        .min_by_key(|h| h.line.abs_diff(line))
+}
+
+fn first_error_at(output: &str, line: usize) -> Option<String> {
+    split_messages(output)
+        .into_iter()
+        .find(|m| m.severity == "error" && m.line == Some(line))
+        .map(|m| m.body.lines().next().unwrap_or("").trim().to_string())
+}
+
+/// Run the search tactics at `target` in `text` (the current contents of `file_rel`).
+pub async fn library_search_in_text(
+    repo_root: &Path,
+    file_rel: &str,
+    text: &str,
+    target: &PatchTarget,
+    opts: &LibrarySearchOptions,
+) -> Result<LibrarySearchReport, String> {
+    let (start, _) = crate::patching::resolve_target(text, target)?;
+    let line = text[..start].matches('\n').count() + 1;
+    let line_text = text.lines().nth(line - 1).unwrap_or("");
+    let tactic_ctx = crate::is_tactic_context_for_sorry(text, line, line_text);
+
+    let mut passes = Vec::new();
+    let mut suggestions: Vec<LibrarySuggestion> = Vec::new();
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let caps = crate::toolchain::detect(&repo_root).capabilities;
+    for &tac in &opts.tactics {
+        if !caps.supports_candidate(tac.as_str()) {
+            continue;
+        }
+        let replacement = if tactic_ctx {
+            tac.as_str().to_string()
+        } else {
+            format!("by {}", tac.as_str())
+        };
+        let (patched, rec) = apply_patch(text, target, &replacement)?;
+        let t0 = Instant::now();
+        let vr = crate::verify_lean_text(&repo_root, &patched, opts.timeout).await?;
+        let merged = format!("{}\n{}", vr.stdout, vr.stderr);
+        let found = parse_library_suggestions(&merged, tac, Some(rec.line));
+        let closing = found.iter().any(|s| s.closes_goal);
+        passes.push(LibrarySearchPass {
+            tactic: tac,
+            elapsed_ms: t0.elapsed().as_millis() as u64,
+            timeout: vr.timeout,
+            suggestions: found.len(),
+            error: first_error_at(&merged, rec.line),
+        });
+        suggestions.extend(found);
+        if closing && opts.stop_on_closing {
+            break;
+        }
+    }
+    rank_suggestions(&mut suggestions);
+    Ok(LibrarySearchReport {
+        file: file_rel.to_string(),
+        line,
+        passes,
+        suggestions,
+    })
+}
+
+/// `library_search_in_text` on the file as it is on disk.
+pub async fn library_search(
+    repo_root: &Path,
+    file_rel: &str,
+    target: &PatchTarget,
+    opts: &LibrarySearchOptions,
+) -> Result<LibrarySearchReport, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    crate::load_dotenv_smart(&repo_root);
+    let abs = repo_root.join(file_rel);
+    let text = std::fs::read_to_string(&abs).map_err(|e| format!("read {}: {e}", abs.display()))?;
+    library_search_in_text(&repo_root, file_rel, &text, target, opts).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_orders_try_this_shapes() {
+        let out = "\
+F.lean:3:2: info: Try this: refine Nat.le_trans ?_ ?_
+  -- Remaining subgoals:
+  -- ⊢ a ≤ ?b
+  -- ⊢ ?b ≤ c
+F.lean:3:2: info: Try this: exact Nat.le_of_lt h
+F.lean:9:2: info: Try this: exact foo
+F.lean:3:2: error: apply? leaves sorry: exact sorry
+";
+        let s = parse_library_suggestions(out, SearchTactic::Apply, Some(3));
+        assert_eq!(s.len(), 2);
+        assert_eq!(s[0].script, "exact Nat.le_of_lt h");
+        assert!(s[0].closes_goal);
+        assert_eq!(s[1].head, "refine");
+        assert_eq!(s[1].remaining_goals, 2);
+        assert!(!s[1].closes_goal);
+        assert_eq!(
+            candidates_from_suggestions(&s),
+            vec!["by\n  exact Nat.le_of_lt h".to_string()]
+        );
+    }
+
+    #[test]
+    fn parses_rw_and_try_these_lists() {
+        let out = "\
+F.lean:5:4: info: Try these:
+• rw [Nat.add_comm]
+  -- no goals
+• rw [Nat.add_assoc]
+  -- a + (b + c) = c + b + a
+";
+        let s = parse_library_suggestions(out, SearchTactic::Rw, None);
+        assert_eq!(s.len(), 2);
+        assert!(s[0].closes_goal && s[0].confidence > s[1].confidence);
+        assert_eq!(
+            s[1].remaining,
+            vec!["⊢ a + (b + c) = c + b + a".to_string()]
+        );
+
+        // Older, unlocated shape with a `[tag]` on the following line.
+        let s = parse_library_suggestions(
+            "Try this:\n  [apply] exact h.le\n",
+            SearchTactic::Exact,
+            Some(1),
+        );
+        assert_eq!(s[0].script, "exact h.le");
+    }
+}
@@ -1,8 +1,83 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::borrow::Cow;
+use std::path::Path;
+use std::sync::RwLock;
 use std::time::Duration;
 
+pub mod batch;
+pub mod bedrock;
+pub mod cache;
+pub mod cost;
+pub mod embeddings;
+pub mod failover;
+#[cfg(feature = "gguf")]
+pub mod gguf;
+pub mod mock;
+pub mod provider;
+pub mod routing;
+pub mod scheduler;
+pub mod speculative;
+pub mod tools;
+pub mod transcripts;
+pub mod window;
+
+/// The `[llm]` section in effect for this process (see `configure`).
+static CONFIGURED: RwLock<Option<crate::config::LlmConfig>> = RwLock::new(None);
+
+/// Route `chat_completion` through the `[llm]` provider instead of the env-based selection
+/// (`None`, or a config without `provider` or `providers`, restores the latter).
+pub fn configure(cfg: Option<crate::config::LlmConfig>) {
+    let cfg = cfg
+        .map(|mut c| {
+            if c.provider.is_none() {
+                c.provider = c.providers.first().cloned();
+            }
+            c
+        })
+        .filter(|c| c.provider.is_some());
+    if let Ok(mut g) = CONFIGURED.write() {
+        *g = cfg;
+    }
+    scheduler::reset();
+}
+
+/// `configure` (and `cost::configure_budget`) from `<repo_root>/proofpatch.toml`; a missing or
+/// unreadable file leaves the current settings alone. Also points the response cache at the
+/// repo (`cache`), unless `[llm] cache = false`, starts recording transcripts there
+/// (`transcripts`), and applies `[prompts]` (`prompts::configure`).
+pub fn configure_from_repo(repo_root: &Path) {
+    let cfg = crate::config::load_from_repo_root(repo_root);
+    let llm = match &cfg {
+        Ok(Some(c)) => c.llm.clone(),
+        _ => Default::default(),
+    };
+    cache::configure_cache(llm.cache.unwrap_or(true).then(|| {
+        cache::CacheSettings {
+            dir: cache::default_dir(repo_root),
+            ttl: llm
+                .cache_ttl_s
+                .map(Duration::from_secs)
+                .unwrap_or(cache::DEFAULT_TTL),
+        }
+    }));
+    // An invalid `redact` pattern turns transcripts off rather than writing what it should hide.
+    transcripts::configure(transcripts::settings_from_config(repo_root, &llm).unwrap_or(None));
+    if let Ok(Some(cfg)) = cfg {
+        crate::prompts::configure(Some(cfg.prompts));
+        if cfg.llm.provider.is_some() || !cfg.llm.providers.is_empty() {
+            configure(Some(cfg.llm));
+        }
+        if cfg.budget != crate::config::BudgetConfig::default() {
+            cost::configure_budget(Some(cfg.budget));
+        }
+    }
+}
+
+pub fn configured() -> Option<crate::config::LlmConfig> {
+    CONFIGURED.read().ok().and_then(|g| g.clone())
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StructuredChatResult<T> {
     pub provider: String,
@@ -30,6 +105,29 @@ pub struct ChatCompletionResult {
     pub model_env: String,
     pub content: String,
     pub raw: Value,
+    /// Generation was cancelled once the reply held a complete JSON value / code fence.
+    #[serde(default)]
+    pub stopped_early: bool,
+    /// Tokens and estimated cost of this request (`cost`).
+    #[serde(default)]
+    pub usage: cost::RequestUsage,
+    /// Served from the response cache (`cache`); `usage` is then zero.
+    #[serde(default)]
+    pub cached: bool,
+    /// The request carried `ChatOptions::schema` to a provider that enforces it.
+    #[serde(default)]
+    pub schema_enforced: bool,
+    /// Providers that failed before `provider` served the request (`failover`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failover: Vec<failover::FailoverAttempt>,
+    /// Token logprobs of `content`, with `ChatOptions::logprobs` and a provider that returns
+    /// them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Vec<provider::TokenLogprob>>,
+    /// Tools the model called before answering (`ChatOptions::tools`); `usage` covers every
+    /// round trip.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<tools::ToolInvocation>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -258,125 +356,451 @@ pub async fn select_provider_info(timeout: Duration) -> Result<SelectedProviderI
     })
 }
 
-#[derive(Debug, Deserialize)]
-struct ChatCompletionChoice {
-    message: Value,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatCompletionResponse {
-    choices: Vec<ChatCompletionChoice>,
+fn openrouter_headers() -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    for (env, header) in [
+        ("OPENROUTER_SITE_URL", "HTTP-Referer"),
+        ("OPENROUTER_APP_NAME", "X-Title"),
+    ] {
+        if let Ok(v) = std::env::var(env) {
+            let v = v.trim().to_string();
+            if !v.is_empty() {
+                out.push((header.to_string(), v));
+            }
+        }
+    }
+    out
 }
 
-/// OpenAI-compatible chat completions, with provider selection matching the legacy Python CLI.
+/// Chat completion through the `[llm]` provider (`configure`) or, without one, the env-selected
+/// OpenAI-compatible provider (selection matching the legacy Python CLI).
 ///
-/// Invariants (should not change lightly):
+/// Invariants of the env path (should not change lightly):
 /// - request path is `POST <base_url>/chat/completions`
 /// - uses `Authorization: Bearer <key>` when provider requires a key
 /// - OpenRouter adds `HTTP-Referer` and `X-Title` when configured
 /// - default temperature is 0.2
+///
+/// The reply is streamed, and generation is cancelled as soon as a complete JSON value or closed
+/// code fence has arrived (`provider::chat_until_complete`); `PROOFPATCH_LLM_STREAM=0` waits for
+/// the whole reply instead. Replies are cached (`cache`) when the repo's cache is on.
 pub async fn chat_completion(
     system: &str,
     user: &str,
     timeout: Duration,
 ) -> Result<ChatCompletionResult, String> {
-    let (provider, model, model_source) = select_provider(Duration::from_secs(3)).await?;
+    chat_completion_with(system, user, timeout, &ChatOptions::default()).await
+}
 
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert(
-        reqwest::header::CONTENT_TYPE,
-        reqwest::header::HeaderValue::from_static("application/json"),
-    );
-    if let Some(k) = provider.api_key_env {
-        let key = std::env::var(k).ok().unwrap_or_default();
-        let key = key.trim().to_string();
-        if key.is_empty() {
-            return Err(format!("missing {}", k));
-        }
-        let v = format!("Bearer {}", key);
-        let hv = reqwest::header::HeaderValue::from_str(&v)
-            .map_err(|e| format!("invalid Authorization header: {e}"))?;
-        headers.insert(reqwest::header::AUTHORIZATION, hv);
-    }
-    if provider.name == "openrouter" {
-        if let Ok(site) = std::env::var("OPENROUTER_SITE_URL") {
-            let site = site.trim().to_string();
-            if !site.is_empty() {
-                if let Ok(hv) = reqwest::header::HeaderValue::from_str(&site) {
-                    headers.insert("HTTP-Referer", hv);
+/// Per-request knobs for `chat_completion_with`.
+#[derive(Debug, Clone, Default)]
+pub struct ChatOptions {
+    /// Template the prompt was rendered from; part of the cache key.
+    pub template: Option<crate::prompts::PromptVersion>,
+    /// Skip the response cache for this request (neither read nor written).
+    pub no_cache: bool,
+    /// Override the configured sampling temperature.
+    pub temperature: Option<f32>,
+    /// Index of this sample among several drawn for one prompt: it offsets the seed and is part
+    /// of the cache key, so N samples are N requests (and N cache entries), not one.
+    pub sample: Option<u32>,
+    /// Ask for a reply matching this schema, when the provider can enforce one
+    /// (`Provider::supports_schema`); otherwise it is ignored and the reply is free-form.
+    pub schema: Option<provider::ResponseSchema>,
+    /// Earlier turns of the conversation, sent between the system prompt and `user` (part of the
+    /// cache key).
+    pub history: Vec<provider::ChatMessage>,
+    /// Ask this `[llm.routing]` tier instead of the `[llm]` model (`routing`).
+    pub tier: Option<usize>,
+    /// Byte length of a prefix of `user` that stays the same across requests, for providers
+    /// with prompt caching (`provider::ChatRequest::cache_prefix`; not part of the cache key).
+    pub stable_prefix: Option<usize>,
+    /// Ask for token logprobs, when the provider can return them
+    /// (`Provider::supports_logprobs`; not part of the cache key).
+    pub logprobs: bool,
+    /// Tools the model may call while answering, when the provider supports them
+    /// (`Provider::supports_tools`; `tools`). Requests with tools bypass the response cache and
+    /// `schema`.
+    pub tools: Option<std::sync::Arc<tools::ToolRegistry>>,
+}
+
+/// `chat_completion` with `ChatOptions`. With `[llm] providers`, a request the provider cannot
+/// serve moves on to the next one in the chain (`failover`) within the same `timeout`.
+pub async fn chat_completion_with(
+    system: &str,
+    user: &str,
+    timeout: Duration,
+    opts: &ChatOptions,
+) -> Result<ChatCompletionResult, String> {
+    let Some(base) = configured() else {
+        return complete_once(system, user, timeout, opts, None)
+            .await
+            .map_err(String::from);
+    };
+    let primary = match base
+        .routing
+        .as_ref()
+        .zip(opts.tier)
+        .and_then(|(r, i)| r.tiers.get(i))
+    {
+        Some(t) => routing::tier_config(&base, t),
+        None => base.clone(),
+    };
+    let chain = failover::order(failover::chain(&base, primary));
+    let cooldown = base
+        .failover_cooldown_s
+        .map(Duration::from_secs)
+        .unwrap_or(failover::DEFAULT_COOLDOWN);
+    let deadline = std::time::Instant::now() + timeout;
+    let last = chain.len() - 1;
+    let mut failed: Vec<failover::FailoverAttempt> = Vec::new();
+    for (i, cfg) in chain.into_iter().enumerate() {
+        let name = cfg.provider.clone().unwrap_or_default();
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        match complete_once(system, user, left, opts, Some(cfg)).await {
+            Ok(mut r) => {
+                if last > 0 {
+                    failover::mark_up(&name);
                 }
+                r.failover = failed;
+                return Ok(r);
             }
-        }
-        if let Ok(app) = std::env::var("OPENROUTER_APP_NAME") {
-            let app = app.trim().to_string();
-            if !app.is_empty() {
-                if let Ok(hv) = reqwest::header::HeaderValue::from_str(&app) {
-                    headers.insert("X-Title", hv);
-                }
+            Err(Failure::Provider(e))
+                if i < last && failover::should_failover(e.kind) && !left.is_zero() =>
+            {
+                failover::mark_down(&name, cooldown);
+                failed.push(failover::FailoverAttempt::new(&e));
             }
+            Err(e) => return Err(e.into()),
         }
     }
+    Err("no LLM provider could serve the request".to_string())
+}
 
-    let payload = serde_json::json!({
-        "model": model,
-        "messages": [
-            { "role": "system", "content": system },
-            { "role": "user", "content": user }
-        ],
-        "temperature": 0.2
-    });
+/// Why one provider did not answer: its own failure (a candidate for failover), or anything
+/// else (budget, env-based selection).
+enum Failure {
+    Provider(provider::LlmError),
+    Other(String),
+}
 
-    let url = format!("{}/chat/completions", provider.base_url);
-    let client = reqwest::Client::builder()
-        .timeout(timeout)
-        .default_headers(headers)
-        .build()
-        .map_err(|e| format!("http client build: {e}"))?;
-    let resp = client
-        .post(url)
-        .json(&payload)
-        .send()
-        .await
-        .map_err(|e| format!("http request failed: {e}"))?;
+impl From<provider::LlmError> for Failure {
+    fn from(e: provider::LlmError) -> Self {
+        Self::Provider(e)
+    }
+}
 
-    let status = resp.status();
-    let raw: Value = resp
-        .json()
-        .await
-        .map_err(|e| format!("http json decode: {e}"))?;
-    if !status.is_success() {
-        return Err(format!(
-            "provider {} returned {}: {}",
-            provider.name,
-            status.as_u16(),
-            raw
-        ));
+impl From<String> for Failure {
+    fn from(e: String) -> Self {
+        Self::Other(e)
     }
+}
 
-    let parsed: ChatCompletionResponse =
-        serde_json::from_value(raw.clone()).map_err(|e| format!("invalid chat response: {e}"))?;
-    let msg = parsed
-        .choices
-        .get(0)
-        .and_then(|c| c.message.as_object())
-        .cloned()
-        .ok_or_else(|| "missing choices[0].message".to_string())?;
-    let content = msg
-        .get("content")
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .to_string();
+impl From<Failure> for String {
+    fn from(f: Failure) -> Self {
+        match f {
+            Failure::Provider(e) => e.to_string(),
+            Failure::Other(e) => e,
+        }
+    }
+}
 
-    Ok(ChatCompletionResult {
-        provider: provider.name.to_string(),
+/// What the cache key and token estimates see as "the user prompt": the earlier turns and `user`.
+fn transcript(user: &str, opts: &ChatOptions) -> String {
+    opts.history
+        .iter()
+        .map(|m| format!("{}\0{}\0", m.role, m.content))
+        .chain(std::iter::once(user.to_string()))
+        .collect()
+}
+
+/// The request `opts` make of `p`, and its response-cache key. `cfg` (the `[llm]` section `p`
+/// was built from) supplies the sampling settings; `None` keeps the provider defaults.
+pub fn prepare_request(
+    p: &dyn provider::Provider,
+    system: &str,
+    user: &str,
+    timeout: Duration,
+    opts: &ChatOptions,
+    cfg: Option<&crate::config::LlmConfig>,
+) -> (provider::ChatRequest, cache::CacheKey) {
+    let mut req = provider::ChatRequest::simple(system, user, timeout);
+    req.messages.splice(0..0, opts.history.iter().cloned());
+    let seed = cfg.and_then(|c| c.seed).map(u64::from);
+    if let Some(cfg) = cfg {
+        req.temperature = cfg.temperature;
+        req.max_tokens = cfg.max_tokens;
+    }
+    if opts.temperature.is_some() {
+        req.temperature = opts.temperature;
+    }
+    if let Some(i) = opts.sample {
+        req.seed = Some((seed.unwrap_or(0) as u32).wrapping_add(i));
+    }
+    if p.supports_schema() {
+        req.response_schema = opts.schema.clone();
+    }
+    req.cache_prefix = opts.stable_prefix;
+    req.logprobs = opts.logprobs && p.supports_logprobs();
+    if let Some(t) = opts
+        .tools
+        .as_ref()
+        .filter(|t| !t.is_empty() && p.supports_tools())
+    {
+        req.tools = t.specs();
+        req.response_schema = None;
+    }
+    let mut key = cache::CacheKey::new(
+        p.name(),
+        p.model(),
+        opts.template.as_ref(),
+        system,
+        &transcript(user, opts),
+        req.temperature,
+        seed,
+    );
+    key.sample = opts.sample;
+    key.schema = req
+        .response_schema
+        .as_ref()
+        .map(|rs| cache::schema_id(&rs.name, &rs.schema));
+    (req, key)
+}
+
+/// The transcript line for `req` (not cached, no error, no timing).
+fn transcript_entry<'a>(
+    req: &'a provider::ChatRequest,
+    opts: &'a ChatOptions,
+    provider: &'a str,
+    model: &'a str,
+    response: Option<&'a str>,
+    usage: &'a cost::RequestUsage,
+) -> transcripts::Entry<'a> {
+    transcripts::Entry {
+        ts: cache::now_s(),
+        provider,
         model,
+        template: opts.template.as_ref(),
+        system: &req.system,
+        messages: &req.messages,
+        response,
+        tool_calls: &[],
+        error: None,
+        usage: Some(usage),
+        cached: false,
+        elapsed_ms: 0,
+    }
+}
+
+/// One request to `cfg`'s provider (`None`: the env-based selection).
+async fn complete_once(
+    system: &str,
+    user: &str,
+    timeout: Duration,
+    opts: &ChatOptions,
+    cfg: Option<crate::config::LlmConfig>,
+) -> Result<ChatCompletionResult, Failure> {
+    let (p, model_source, model_env): (Box<dyn provider::Provider>, String, String) = match &cfg {
+        Some(cfg) => (
+            provider::from_config(cfg)?,
+            "config".to_string(),
+            String::new(),
+        ),
+        None => {
+            let info = select_provider_info(Duration::from_secs(3)).await?;
+            let extra_headers = if info.provider == "openrouter" {
+                openrouter_headers()
+            } else {
+                Vec::new()
+            };
+            (
+                Box::new(provider::OpenAiProvider {
+                    name: info.provider,
+                    base_url: info.base_url,
+                    api_key: info.api_key,
+                    model: info.model,
+                    azure: None,
+                    extra_headers,
+                }),
+                info.model_source,
+                info.model_env,
+            )
+        }
+    };
+    let (mut req, key) = prepare_request(p.as_ref(), system, user, timeout, opts, cfg.as_ref());
+    let cache = cache::settings().filter(|_| !opts.no_cache && req.tools.is_empty());
+    let schema_enforced = req.response_schema.is_some();
+    if let Some(hit) = cache
+        .as_ref()
+        .and_then(|c| cache::get(&c.dir, &key, c.ttl, cache::now_s()))
+    {
+        let usage = cost::RequestUsage {
+            provider: key.provider.clone(),
+            model: key.model.clone(),
+            cost_usd: Some(0.0),
+            ..Default::default()
+        };
+        transcripts::record(&transcripts::Entry {
+            cached: true,
+            ..transcript_entry(
+                &req,
+                opts,
+                &key.provider,
+                &key.model,
+                Some(&hit.content),
+                &usage,
+            )
+        });
+        return Ok(ChatCompletionResult {
+            provider: key.provider.clone(),
+            model: key.model.clone(),
+            model_source,
+            model_env,
+            content: hit.content,
+            raw: serde_json::json!({ "cached": true, "created_at": hit.created_at }),
+            stopped_early: hit.stopped_early,
+            usage,
+            cached: true,
+            schema_enforced,
+            failover: Vec::new(),
+            logprobs: hit.logprobs,
+            tool_calls: Vec::new(),
+        });
+    }
+    let (r, usage, tool_calls) = match opts.tools.as_ref().filter(|_| !req.tools.is_empty()) {
+        Some(t) => tools::run(p.as_ref(), &mut req, opts, t).await?,
+        None => {
+            let stream = env_truthy("PROOFPATCH_LLM_STREAM", true);
+            let (r, usage) = send(p.as_ref(), &mut req, opts, stream).await?;
+            (r, usage, Vec::new())
+        }
+    };
+    if let Some(c) = &cache {
+        let _ = cache::put(
+            &c.dir,
+            &cache::CachedCompletion {
+                key,
+                created_at: cache::now_s(),
+                content: r.content.clone(),
+                usage: r.usage.clone(),
+                stopped_early: r.stopped_early,
+                logprobs: r.logprobs.clone(),
+            },
+        );
+    }
+    Ok(ChatCompletionResult {
+        provider: r.provider,
+        model: r.model,
         model_source,
-        model_env: provider.model_env.to_string(),
-        content,
-        raw,
+        model_env,
+        content: r.content,
+        raw: r.raw,
+        stopped_early: r.stopped_early,
+        usage,
+        cached: false,
+        schema_enforced,
+        failover: Vec::new(),
+        logprobs: r.logprobs,
+        tool_calls,
     })
 }
 
+/// The prompt text of `req` as token estimates see it (`transcript`).
+fn prompt_text(req: &provider::ChatRequest) -> String {
+    let mut out = format!("{}\n\n", req.system);
+    for (i, m) in req.messages.iter().enumerate() {
+        if i + 1 == req.messages.len() {
+            out.push_str(&m.content);
+        } else {
+            out.push_str(&format!("{}\0{}\0", m.role, m.content));
+        }
+    }
+    out
+}
+
+/// Send `req` to `p`, bypassing the response cache: check the budget, wait for a request slot
+/// (`scheduler`; the wait comes off `req.timeout`), then record cost and transcript.
+async fn send(
+    p: &dyn provider::Provider,
+    req: &mut provider::ChatRequest,
+    opts: &ChatOptions,
+    stream: bool,
+) -> Result<(provider::ChatResponse, cost::RequestUsage), Failure> {
+    cost::check_budget()?;
+    let slot = scheduler::acquire(p.name(), req.timeout).await?;
+    if let Some(s) = &slot {
+        req.timeout = req.timeout.saturating_sub(s.waited);
+    }
+    let started = std::time::Instant::now();
+    let r = if stream {
+        provider::chat_until_complete(p, req).await
+    } else {
+        p.chat(req).await
+    };
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    let r = match r {
+        Ok(r) => r,
+        Err(e) => {
+            transcripts::record(&transcripts::Entry {
+                error: Some(e.to_string()),
+                usage: None,
+                elapsed_ms,
+                ..transcript_entry(req, opts, p.name(), p.model(), None, &Default::default())
+            });
+            return Err(e.into());
+        }
+    };
+    let usage = cost::RequestUsage::new(
+        &r.provider,
+        &r.model,
+        &r.usage,
+        &prompt_text(req),
+        &r.content,
+    );
+    cost::record(&usage);
+    transcripts::record(&transcripts::Entry {
+        elapsed_ms,
+        tool_calls: &r.tool_calls,
+        ..transcript_entry(req, opts, &r.provider, &r.model, Some(&r.content), &usage)
+    });
+    Ok((r, usage))
+}
+
+/// `n` concurrent `chat_completion_with` requests at `temperature`, each a distinct sample
+/// (`ChatOptions::sample` 0..n), in sample order. Used for self-consistency voting.
+pub async fn sample_completions(
+    system: &str,
+    user: &str,
+    timeout: Duration,
+    opts: &ChatOptions,
+    n: usize,
+    temperature: f32,
+) -> Vec<Result<ChatCompletionResult, String>> {
+    let mut set = tokio::task::JoinSet::new();
+    for i in 0..n {
+        let (system, user) = (system.to_string(), user.to_string());
+        let opts = ChatOptions {
+            temperature: Some(temperature),
+            sample: Some(i as u32),
+            ..opts.clone()
+        };
+        set.spawn(async move {
+            (
+                i,
+                chat_completion_with(&system, &user, timeout, &opts).await,
+            )
+        });
+    }
+    let mut out: Vec<(usize, Result<ChatCompletionResult, String>)> = Vec::new();
+    while let Some(r) = set.join_next().await {
+        if let Ok(x) = r {
+            out.push(x);
+        }
+    }
+    out.sort_by_key(|(i, _)| *i);
+    out.into_iter().map(|(_, r)| r).collect()
+}
+
 /// Lower-level entrypoint: send an OpenAI-compatible request with an explicit `messages` array and optional `tools`.
 ///
 /// Returns the raw JSON response plus injected `provider`/`model` fields for callers that want tool loops.
@@ -386,6 +810,7 @@ pub async fn chat_completion_raw(
     tool_choice: Option<&serde_json::Value>,
     timeout: Duration,
 ) -> Result<serde_json::Value, String> {
+    cost::check_budget()?;
     let (provider, model, model_source) = select_provider(Duration::from_secs(3)).await?;
 
     let mut headers = reqwest::header::HeaderMap::new();
@@ -469,7 +894,10 @@ pub async fn chat_completion_raw(
             "provider".to_string(),
             serde_json::Value::String(provider.name.to_string()),
         );
-        obj.insert("model".to_string(), serde_json::Value::String(model));
+        obj.insert(
+            "model".to_string(),
+            serde_json::Value::String(model.clone()),
+        );
         obj.insert(
             "model_source".to_string(),
             serde_json::Value::String(model_source),
@@ -479,6 +907,24 @@ pub async fn chat_completion_raw(
             serde_json::Value::String(provider.model_env.to_string()),
         );
     }
+    let reported = provider::Usage {
+        input_tokens: provider::u64_at(&raw, "/usage/prompt_tokens"),
+        output_tokens: provider::u64_at(&raw, "/usage/completion_tokens"),
+        ..Default::default()
+    };
+    let prompt = messages
+        .iter()
+        .filter_map(|m| m.get("content").and_then(|c| c.as_str()))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let reply = extract_message_content(&raw).unwrap_or("").to_string();
+    cost::record(&cost::RequestUsage::new(
+        provider.name,
+        &model,
+        &reported,
+        &prompt,
+        &reply,
+    ));
     Ok(raw)
 }
 
@@ -0,0 +1,579 @@
+//! Offline batch generation (OpenAI Batch API, Anthropic Message Batches).
+//!
+//! Batch endpoints take thousands of requests at once, answer within a day, and bill at half the
+//! synchronous price. A job is built from ready-made requests (`build`: the same
+//! `ChatRequest` and response-cache key `chat_completion_with` would use), submitted (`submit`),
+//! polled (`poll`), and collected (`fetch_results`, `collect`). Collecting writes every answer
+//! into the response cache (`cache`) under the request's key, so the pipeline that queued the
+//! requests picks up where it left off: when it asks the same question again, the answer is
+//! already there.
+//!
+//! Jobs are kept under `.generated/proofpatch-batch/<id>.json` between the steps, which usually
+//! run in different processes (`batch-submit`, `batch-status`, `batch-collect`).
+
+use super::cache::{self, CacheKey, CachedCompletion};
+use super::cost::{RequestUsage, UsageTotals};
+use super::provider::{classify_status, parse_messages_response, LlmError, Provider, Usage};
+use super::ChatOptions;
+use crate::config::LlmConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Batch requests cost this fraction of the synchronous price (OpenAI and Anthropic alike).
+pub const BATCH_DISCOUNT: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchKind {
+    /// `POST /files` (purpose `batch`), then `POST /batches`.
+    Openai,
+    /// `POST /v1/messages/batches`.
+    Anthropic,
+}
+
+/// Where a provider's batch API lives (`Provider::batch_api`).
+#[derive(Debug, Clone)]
+pub struct BatchApi {
+    pub kind: BatchKind,
+    /// The provider's `base_url`.
+    pub base_url: String,
+    /// Authentication headers.
+    pub headers: Vec<(&'static str, String)>,
+}
+
+/// One request to queue.
+#[derive(Debug, Clone)]
+pub struct BatchRequest {
+    pub file: String,
+    pub decl: String,
+    pub system: String,
+    pub user: String,
+    pub opts: ChatOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItem {
+    pub custom_id: String,
+    pub file: String,
+    pub decl: String,
+    /// Where the answer goes in the response cache.
+    pub key: CacheKey,
+}
+
+/// Progress as the provider reports it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchState {
+    /// The provider's status string (`in_progress`, `completed`, `ended`, …).
+    pub status: String,
+    /// No more requests will finish.
+    pub done: bool,
+    pub total: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// OpenAI output file id, or Anthropic results URL.
+    #[serde(default)]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CollectReport {
+    pub results: usize,
+    /// Answers written to the response cache.
+    pub cached: usize,
+    pub errored: usize,
+    /// Queued requests without a result line.
+    pub missing: usize,
+    /// Tokens and cost at the batch price.
+    pub usage: UsageTotals,
+    /// The first few per-request errors (`custom_id: error`).
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchJob {
+    pub id: String,
+    pub kind: BatchKind,
+    pub provider: String,
+    pub model: String,
+    /// Unix seconds.
+    pub submitted_at: u64,
+    /// The repair settings the prompts were built with (`repair::first_round_prompt`); resuming
+    /// with the same ones finds the answers in the cache.
+    pub goal_dump: bool,
+    pub few_shot: usize,
+    pub items: Vec<BatchItem>,
+    #[serde(default)]
+    pub state: Option<BatchState>,
+    #[serde(default)]
+    pub collected: Option<CollectReport>,
+}
+
+/// One line of a results file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResult {
+    pub custom_id: String,
+    pub outcome: Result<(String, Usage), String>,
+}
+
+/// The `[llm]` section batch requests are sent with: the first `[llm.routing]` tier when there
+/// is one (what the repair loop asks first), else `[llm]` itself.
+pub fn batch_config(base: &LlmConfig) -> LlmConfig {
+    match base.routing.as_ref().and_then(|r| r.tiers.first()) {
+        Some(t) => super::routing::tier_config(base, t),
+        None => base.clone(),
+    }
+}
+
+/// Request lines and job items for `requests` (custom ids `r0`, `r1`, …).
+pub fn build(
+    p: &dyn Provider,
+    cfg: &LlmConfig,
+    requests: &[BatchRequest],
+) -> Result<(Vec<Value>, Vec<BatchItem>), String> {
+    let kind = p
+        .batch_api()
+        .ok_or_else(|| format!("provider {} has no batch API", p.name()))?
+        .kind;
+    let mut lines = Vec::with_capacity(requests.len());
+    let mut items = Vec::with_capacity(requests.len());
+    for (i, r) in requests.iter().enumerate() {
+        let custom_id = format!("r{i}");
+        let (req, key) = super::prepare_request(
+            p,
+            &r.system,
+            &r.user,
+            Duration::from_secs(0),
+            &r.opts,
+            Some(cfg),
+        );
+        let body = p
+            .batch_body(&req)
+            .ok_or_else(|| format!("provider {} has no batch API", p.name()))?;
+        lines.push(match kind {
+            BatchKind::Openai => json!({
+                "custom_id": custom_id,
+                "method": "POST",
+                "url": "/v1/chat/completions",
+                "body": body,
+            }),
+            BatchKind::Anthropic => json!({ "custom_id": custom_id, "params": body }),
+        });
+        items.push(BatchItem {
+            custom_id,
+            file: r.file.clone(),
+            decl: r.decl.clone(),
+            key,
+        });
+    }
+    Ok((lines, items))
+}
+
+fn provider_name(kind: BatchKind) -> &'static str {
+    match kind {
+        BatchKind::Openai => "openai",
+        BatchKind::Anthropic => "anthropic",
+    }
+}
+
+/// Send `rb` with the API's headers; a non-2xx status becomes an error carrying the body.
+async fn send(api: &BatchApi, mut rb: reqwest::RequestBuilder) -> Result<String, String> {
+    let name = provider_name(api.kind);
+    for (k, v) in &api.headers {
+        rb = rb.header(*k, v);
+    }
+    let resp = rb
+        .send()
+        .await
+        .map_err(|e| format!("{name} batch request failed: {e}"))?;
+    let status = resp.status().as_u16();
+    let text = resp
+        .text()
+        .await
+        .map_err(|e| format!("{name} batch body read: {e}"))?;
+    if !(200..300).contains(&status) {
+        return Err(LlmError {
+            kind: classify_status(status, &text),
+            provider: name.to_string(),
+            status: Some(status),
+            message: text,
+        }
+        .to_string());
+    }
+    Ok(text)
+}
+
+fn client(timeout: Duration) -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| format!("http client build: {e}"))
+}
+
+fn json_of(text: &str) -> Result<Value, String> {
+    serde_json::from_str(text).map_err(|e| format!("batch json decode: {e}"))
+}
+
+fn id_of(v: &Value) -> Result<String, String> {
+    v.get("id")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| format!("batch response without an id: {v}"))
+}
+
+/// `multipart/form-data` body uploading `data` as a batch input file.
+fn multipart_jsonl(boundary: &str, data: &str) -> Vec<u8> {
+    format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\nbatch\r\n\
+         --{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"proofpatch-batch.jsonl\"\r\n\
+         Content-Type: application/jsonl\r\n\r\n{data}\r\n--{boundary}--\r\n"
+    )
+    .into_bytes()
+}
+
+/// Queue `lines`; returns the provider's batch id.
+pub async fn submit(api: &BatchApi, lines: &[Value], timeout: Duration) -> Result<String, String> {
+    if lines.is_empty() {
+        return Err("no requests to submit".to_string());
+    }
+    let c = client(timeout)?;
+    match api.kind {
+        BatchKind::Openai => {
+            let data = lines
+                .iter()
+                .map(Value::to_string)
+                .collect::<Vec<_>>()
+                .join("\n");
+            let boundary = format!("proofpatch-{}", cache::now_s());
+            let file = send(
+                api,
+                c.post(format!("{}/files", api.base_url))
+                    .header(
+                        "content-type",
+                        format!("multipart/form-data; boundary={boundary}"),
+                    )
+                    .body(multipart_jsonl(&boundary, &data)),
+            )
+            .await?;
+            let file_id = id_of(&json_of(&file)?)?;
+            let batch = send(
+                api,
+                c.post(format!("{}/batches", api.base_url)).json(&json!({
+                    "input_file_id": file_id,
+                    "endpoint": "/v1/chat/completions",
+                    "completion_window": "24h",
+                })),
+            )
+            .await?;
+            id_of(&json_of(&batch)?)
+        }
+        BatchKind::Anthropic => {
+            let batch = send(
+                api,
+                c.post(format!("{}/v1/messages/batches", api.base_url))
+                    .json(&json!({ "requests": lines })),
+            )
+            .await?;
+            id_of(&json_of(&batch)?)
+        }
+    }
+}
+
+fn count(v: &Value, ptr: &str) -> u64 {
+    super::provider::u64_at(v, ptr)
+}
+
+/// `BatchState` from a batch object (`GET /batches/<id>` or `/v1/messages/batches/<id>`).
+pub fn parse_state(kind: BatchKind, v: &Value) -> BatchState {
+    match kind {
+        BatchKind::Openai => {
+            let status = v
+                .get("status")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            BatchState {
+                done: matches!(
+                    status.as_str(),
+                    "completed" | "failed" | "expired" | "cancelled"
+                ),
+                status,
+                total: count(v, "/request_counts/total"),
+                succeeded: count(v, "/request_counts/completed"),
+                failed: count(v, "/request_counts/failed"),
+                output: v
+                    .get("output_file_id")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            }
+        }
+        BatchKind::Anthropic => {
+            let status = v
+                .get("processing_status")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            let failed = ["errored", "canceled", "expired"]
+                .iter()
+                .map(|k| count(v, &format!("/request_counts/{k}")))
+                .sum::<u64>();
+            let succeeded = count(v, "/request_counts/succeeded");
+            BatchState {
+                done: status == "ended",
+                status,
+                total: succeeded + failed + count(v, "/request_counts/processing"),
+                succeeded,
+                failed,
+                output: v
+                    .get("results_url")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            }
+        }
+    }
+}
+
+pub async fn poll(api: &BatchApi, id: &str, timeout: Duration) -> Result<BatchState, String> {
+    let url = match api.kind {
+        BatchKind::Openai => format!("{}/batches/{id}", api.base_url),
+        BatchKind::Anthropic => format!("{}/v1/messages/batches/{id}", api.base_url),
+    };
+    let text = send(api, client(timeout)?.get(url)).await?;
+    Ok(parse_state(api.kind, &json_of(&text)?))
+}
+
+/// Results of a finished batch (empty when it produced no output).
+pub async fn fetch_results(
+    api: &BatchApi,
+    state: &BatchState,
+    timeout: Duration,
+) -> Result<Vec<BatchResult>, String> {
+    let Some(out) = &state.output else {
+        return Ok(Vec::new());
+    };
+    let url = match api.kind {
+        BatchKind::Openai => format!("{}/files/{out}/content", api.base_url),
+        BatchKind::Anthropic => out.clone(),
+    };
+    let text = send(api, client(timeout)?.get(url)).await?;
+    Ok(parse_results(api.kind, &text))
+}
+
+/// Parse a results file (JSONL, one line per request, in any order). Unparseable lines are
+/// skipped.
+pub fn parse_results(kind: BatchKind, text: &str) -> Vec<BatchResult> {
+    let name = provider_name(kind);
+    text.lines()
+        .filter_map(|l| serde_json::from_str::<Value>(l).ok())
+        .filter_map(|v| {
+            let custom_id = v.get("custom_id")?.as_str()?.to_string();
+            let outcome = match kind {
+                BatchKind::Openai => match (v.pointer("/response/body"), v.get("error")) {
+                    (_, Some(e)) if !e.is_null() => Err(e.to_string()),
+                    (Some(body), _) if count(&v, "/response/status_code") == 200 => body
+                        .pointer("/choices/0/message/content")
+                        .and_then(Value::as_str)
+                        .map(|c| {
+                            (
+                                c.to_string(),
+                                Usage {
+                                    input_tokens: count(body, "/usage/prompt_tokens"),
+                                    output_tokens: count(body, "/usage/completion_tokens"),
+                                    ..Default::default()
+                                },
+                            )
+                        })
+                        .ok_or_else(|| "response without choices[0].message".to_string()),
+                    (body, _) => Err(body.map(Value::to_string).unwrap_or_default()),
+                },
+                BatchKind::Anthropic => match v.pointer("/result/type").and_then(Value::as_str) {
+                    Some("succeeded") => v
+                        .pointer("/result/message")
+                        .ok_or_else(|| "result without a message".to_string())
+                        .and_then(|m| parse_messages_response(name, m).map_err(String::from)),
+                    other => Err(v
+                        .pointer("/result/error")
+                        .map(Value::to_string)
+                        .unwrap_or_else(|| other.unwrap_or("unknown").to_string())),
+                },
+            };
+            Some(BatchResult { custom_id, outcome })
+        })
+        .collect()
+}
+
+/// Write the answers in `results` to the response cache in `cache_dir` under their items' keys.
+pub fn collect(cache_dir: &Path, job: &BatchJob, results: &[BatchResult]) -> CollectReport {
+    let items: HashMap<&str, &BatchItem> = job
+        .items
+        .iter()
+        .map(|i| (i.custom_id.as_str(), i))
+        .collect();
+    let mut report = CollectReport {
+        results: results.len(),
+        ..Default::default()
+    };
+    let mut seen = 0usize;
+    for r in results {
+        let Some(item) = items.get(r.custom_id.as_str()) else {
+            continue;
+        };
+        seen += 1;
+        match &r.outcome {
+            Ok((content, usage)) => {
+                let mut u = RequestUsage::new(&job.provider, &job.model, usage, "", content);
+                u.cost_usd = u.cost_usd.map(|c| c * BATCH_DISCOUNT);
+                report.usage.add(&u);
+                let entry = CachedCompletion {
+                    key: item.key.clone(),
+                    created_at: cache::now_s(),
+                    content: content.clone(),
+                    usage: usage.clone(),
+                    stopped_early: false,
+                    logprobs: None,
+                };
+                match cache::put(cache_dir, &entry) {
+                    Ok(_) => report.cached += 1,
+                    Err(e) => report.errors.push(format!("{}: {e}", r.custom_id)),
+                }
+            }
+            Err(e) => {
+                report.errored += 1;
+                if report.errors.len() < 10 {
+                    report.errors.push(format!("{}: {e}", r.custom_id));
+                }
+            }
+        }
+    }
+    report.missing = job.items.len().saturating_sub(seen);
+    report
+}
+
+pub fn dir(repo_root: &Path) -> PathBuf {
+    repo_root.join(".generated").join("proofpatch-batch")
+}
+
+pub fn save(repo_root: &Path, job: &BatchJob) -> Result<PathBuf, String> {
+    let d = dir(repo_root);
+    std::fs::create_dir_all(&d).map_err(|e| format!("mkdir {}: {e}", d.display()))?;
+    let p = d.join(format!("{}.json", job.id));
+    let data = serde_json::to_vec_pretty(job).map_err(|e| e.to_string())?;
+    std::fs::write(&p, data).map_err(|e| format!("write {}: {e}", p.display()))?;
+    Ok(p)
+}
+
+pub fn load(repo_root: &Path, id: &str) -> Result<BatchJob, String> {
+    let p = dir(repo_root).join(format!("{id}.json"));
+    let txt = std::fs::read_to_string(&p).map_err(|e| format!("read {}: {e}", p.display()))?;
+    serde_json::from_str(&txt).map_err(|e| format!("parse {}: {e}", p.display()))
+}
+
+/// The repo's jobs, oldest first.
+pub fn list(repo_root: &Path) -> Vec<BatchJob> {
+    let mut jobs: Vec<BatchJob> = std::fs::read_dir(dir(repo_root))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| std::fs::read_to_string(e.path()).ok())
+        .filter_map(|t| serde_json::from_str(&t).ok())
+        .collect();
+    jobs.sort_by_key(|j| j.submitted_at);
+    jobs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::provider::{AnthropicProvider, OpenAiProvider};
+
+    #[test]
+    fn builds_lines_and_caches_collected_answers() {
+        let openai = OpenAiProvider {
+            name: "openai".into(),
+            base_url: "https://api.openai.com/v1".into(),
+            api_key: Some("k".into()),
+            model: "gpt-4o-mini".into(),
+            azure: None,
+            extra_headers: Vec::new(),
+        };
+        let cfg = LlmConfig {
+            provider: Some("openai".into()),
+            temperature: Some(0.1),
+            ..Default::default()
+        };
+        let reqs = vec![BatchRequest {
+            file: "A.lean".into(),
+            decl: "foo".into(),
+            system: "sys".into(),
+            user: "prove foo".into(),
+            opts: ChatOptions::default(),
+        }];
+        let (lines, items) = build(&openai, &cfg, &reqs).unwrap();
+        assert_eq!(lines[0]["url"], "/v1/chat/completions");
+        assert_eq!(lines[0]["body"]["model"], "gpt-4o-mini");
+        let (req, key) = crate::llm::prepare_request(
+            &openai,
+            "sys",
+            "prove foo",
+            Duration::from_secs(9),
+            &ChatOptions::default(),
+            Some(&cfg),
+        );
+        assert_eq!(items[0].key, key);
+        assert_eq!(req.temperature, Some(0.1));
+
+        let anthropic = AnthropicProvider {
+            base_url: "https://api.anthropic.com".into(),
+            api_key: "k".into(),
+            model: "claude-sonnet-4".into(),
+            prompt_cache: false,
+        };
+        let (lines, _) = build(&anthropic, &cfg, &reqs).unwrap();
+        assert_eq!(lines[0]["params"]["system"], "sys");
+
+        let state = parse_state(
+            BatchKind::Anthropic,
+            &json!({"processing_status": "ended", "results_url": "u",
+                    "request_counts": {"succeeded": 1, "errored": 1, "processing": 0}}),
+        );
+        assert!(state.done && state.total == 2 && state.output.as_deref() == Some("u"));
+
+        let results = parse_results(
+            BatchKind::Openai,
+            &[
+                json!({"custom_id": "r0", "response": {"status_code": 200, "body": {
+                    "choices": [{"message": {"content": "by simp"}}],
+                    "usage": {"prompt_tokens": 10, "completion_tokens": 2}}}, "error": null}),
+                json!({"custom_id": "r9", "response": {"status_code": 429, "body": {}}}),
+            ]
+            .map(|v| v.to_string())
+            .join("\n"),
+        );
+        assert_eq!(results.len(), 2);
+        assert!(results[1].outcome.is_err());
+
+        let td = tempfile::tempdir().unwrap();
+        let job = BatchJob {
+            id: "batch_1".into(),
+            kind: BatchKind::Openai,
+            provider: "openai".into(),
+            model: "gpt-4o-mini".into(),
+            submitted_at: 0,
+            goal_dump: false,
+            few_shot: 0,
+            items,
+            state: None,
+            collected: None,
+        };
+        let report = collect(td.path(), &job, &results);
+        assert_eq!((report.cached, report.errored, report.missing), (1, 0, 0));
+        let hit = cache::get(td.path(), &key, Duration::from_secs(60), cache::now_s()).unwrap();
+        assert_eq!(hit.content, "by simp");
+
+        save(td.path(), &job).unwrap();
+        assert_eq!(load(td.path(), "batch_1").unwrap().items.len(), 1);
+        assert_eq!(list(td.path()).len(), 1);
+    }
+}
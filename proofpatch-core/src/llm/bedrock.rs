@@ -0,0 +1,516 @@
+//! AWS Bedrock (`InvokeModel`) with SigV4 request signing.
+//!
+//! Bedrock's request and response bodies depend on the model family: Claude models take the
+//! Anthropic messages shape (plus `anthropic_version`), Llama models take one pre-formatted
+//! prompt string. `ModelFamily::of` picks the shape from the model id (cross-region inference
+//! profiles such as `us.anthropic.…` included).
+//!
+//! Credentials follow the usual AWS chain, minus the SDK-only sources: `AWS_ACCESS_KEY_ID` /
+//! `AWS_SECRET_ACCESS_KEY` / `AWS_SESSION_TOKEN`, then the profile (`[llm] profile`,
+//! `AWS_PROFILE`, or `default`) in `~/.aws/credentials` (`AWS_SHARED_CREDENTIALS_FILE`). The
+//! region comes from `[llm] region`, `AWS_REGION` / `AWS_DEFAULT_REGION`, or the profile in
+//! `~/.aws/config` (`AWS_CONFIG_FILE`). SSO and instance-metadata credentials are not read;
+//! export them first (`aws configure export-credentials --format env`).
+
+use super::provider::{
+    parse_messages_response, post_json, u64_at, ChatFuture, ChatRequest, ChatResponse, LlmError,
+    LlmErrorKind, Provider, Usage,
+};
+use crate::config::LlmConfig;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+const SERVICE: &str = "bedrock";
+const BEDROCK_ANTHROPIC_VERSION: &str = "bedrock-2023-05-31";
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelFamily {
+    Anthropic,
+    Llama,
+}
+
+impl ModelFamily {
+    pub fn of(model_id: &str) -> Option<Self> {
+        if model_id.contains("anthropic.") {
+            Some(Self::Anthropic)
+        } else if model_id.contains("meta.llama") {
+            Some(Self::Llama)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+fn env_nonempty(key: &str) -> Option<String> {
+    std::env::var(key)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+fn aws_file(env_key: &str, name: &str) -> Option<PathBuf> {
+    env_nonempty(env_key)
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|h| h.join(".aws").join(name)))
+}
+
+/// `key = value` pairs of `[section]` in an AWS ini file.
+pub fn ini_section(text: &str, section: &str) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    let mut inside = false;
+    for line in text.lines() {
+        let t = line.trim();
+        if t.starts_with('#') || t.starts_with(';') || t.is_empty() {
+            continue;
+        }
+        if let Some(name) = t.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+            inside = name.trim() == section;
+            continue;
+        }
+        if inside {
+            if let Some((k, v)) = t.split_once('=') {
+                out.push((k.trim().to_string(), v.trim().to_string()));
+            }
+        }
+    }
+    out
+}
+
+fn lookup(pairs: &[(String, String)], key: &str) -> Option<String> {
+    pairs
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.clone())
+        .filter(|v| !v.is_empty())
+}
+
+fn profile_name(configured: Option<&str>) -> String {
+    configured
+        .map(str::to_string)
+        .or_else(|| env_nonempty("AWS_PROFILE"))
+        .unwrap_or_else(|| "default".to_string())
+}
+
+impl AwsCredentials {
+    pub fn from_chain(profile: Option<&str>) -> Result<Self, String> {
+        if let (Some(id), Some(secret)) = (
+            env_nonempty("AWS_ACCESS_KEY_ID"),
+            env_nonempty("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(Self {
+                access_key_id: id,
+                secret_access_key: secret,
+                session_token: env_nonempty("AWS_SESSION_TOKEN"),
+            });
+        }
+        let profile = profile_name(profile);
+        let path = aws_file("AWS_SHARED_CREDENTIALS_FILE", "credentials")
+            .ok_or("no AWS credentials: set AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY")?;
+        let text = std::fs::read_to_string(&path).map_err(|e| {
+            format!(
+                "no AWS credentials in env, and {} is unreadable: {e}",
+                path.display()
+            )
+        })?;
+        let pairs = ini_section(&text, &profile);
+        match (
+            lookup(&pairs, "aws_access_key_id"),
+            lookup(&pairs, "aws_secret_access_key"),
+        ) {
+            (Some(id), Some(secret)) => Ok(Self {
+                access_key_id: id,
+                secret_access_key: secret,
+                session_token: lookup(&pairs, "aws_session_token"),
+            }),
+            _ => Err(format!(
+                "profile [{profile}] in {} has no aws_access_key_id/aws_secret_access_key",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// Region from the config, the env, or the profile in `~/.aws/config`.
+pub fn resolve_region(configured: Option<&str>, profile: Option<&str>) -> Option<String> {
+    if let Some(r) = configured.filter(|r| !r.trim().is_empty()) {
+        return Some(r.trim().to_string());
+    }
+    if let Some(r) = env_nonempty("AWS_REGION").or_else(|| env_nonempty("AWS_DEFAULT_REGION")) {
+        return Some(r);
+    }
+    let profile = profile_name(profile);
+    let text = std::fs::read_to_string(aws_file("AWS_CONFIG_FILE", "config")?).ok()?;
+    let section = if profile == "default" {
+        profile
+    } else {
+        format!("profile {profile}")
+    };
+    lookup(&ini_section(&text, &section), "region")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut k = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        k[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        k[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(k.map(|b| b ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(k.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// RFC 3986 percent-encoding as SigV4 wants it (unreserved characters pass through).
+fn uri_encode(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+/// `YYYYMMDDTHHMMSSZ` for seconds since the Unix epoch.
+pub fn amz_date(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64;
+    let rem = unix_secs % 86_400;
+    // Civil date from day count (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// SigV4 headers (`x-amz-date`, optional `x-amz-security-token`, `authorization`) for a request
+/// without a query string. `path` is the request path as sent (already percent-encoded once);
+/// per SigV4 for services other than S3, its segments are encoded again in the canonical request.
+#[allow(clippy::too_many_arguments)]
+pub fn sigv4_headers(
+    creds: &AwsCredentials,
+    method: &str,
+    host: &str,
+    path: &str,
+    region: &str,
+    service: &str,
+    payload: &[u8],
+    amz_date: &str,
+) -> Vec<(&'static str, String)> {
+    let date = &amz_date[..8];
+    let canonical_uri = path
+        .split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/");
+    let mut headers = vec![
+        ("host", host.to_string()),
+        ("x-amz-date", amz_date.to_string()),
+    ];
+    if let Some(t) = &creds.session_token {
+        headers.push(("x-amz-security-token", t.clone()));
+    }
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(k, v)| format!("{k}:{}\n", v.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(k, _)| *k)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{method}\n{canonical_uri}\n\n{canonical_headers}\n{signed_headers}\n{}",
+        hex::encode(Sha256::digest(payload))
+    );
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let k_date = hmac_sha256(
+        format!("AWS4{}", creds.secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let k_region = hmac_sha256(&k_date, region.as_bytes());
+    let k_service = hmac_sha256(&k_region, service.as_bytes());
+    let k_signing = hmac_sha256(&k_service, b"aws4_request");
+    let signature = hex::encode(hmac_sha256(&k_signing, string_to_sign.as_bytes()));
+    let mut out: Vec<(&'static str, String)> =
+        headers.into_iter().filter(|(k, _)| *k != "host").collect();
+    out.push((
+        "authorization",
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            creds.access_key_id
+        ),
+    ));
+    out
+}
+
+/// Llama 3 chat format (`<|start_header_id|>role<|end_header_id|>…<|eot_id|>`), ending with the
+/// assistant header so the model answers.
+pub fn llama3_prompt(req: &ChatRequest) -> String {
+    let mut out = String::from("<|begin_of_text|>");
+    let turn = |out: &mut String, role: &str, text: &str| {
+        out.push_str(&format!(
+            "<|start_header_id|>{role}<|end_header_id|>\n\n{}<|eot_id|>",
+            text.trim()
+        ));
+    };
+    if !req.system.trim().is_empty() {
+        turn(&mut out, "system", &req.system);
+    }
+    for m in &req.messages {
+        turn(&mut out, &m.role, &m.content);
+    }
+    out.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+    out
+}
+
+#[derive(Debug, Clone)]
+pub struct BedrockProvider {
+    pub region: String,
+    /// Model or inference-profile id, e.g. `anthropic.claude-3-5-sonnet-20240620-v1:0`.
+    pub model: String,
+    pub family: ModelFamily,
+    pub credentials: AwsCredentials,
+    /// Override of `https://bedrock-runtime.<region>.amazonaws.com` (VPC endpoints).
+    pub endpoint: Option<String>,
+}
+
+impl BedrockProvider {
+    pub fn from_config(cfg: &LlmConfig) -> Result<Self, LlmError> {
+        let err = |msg: String| LlmError::new(LlmErrorKind::Config, "bedrock", msg);
+        let model = cfg
+            .model
+            .clone()
+            .filter(|m| !m.trim().is_empty())
+            .ok_or_else(|| err("[llm] provider = \"bedrock\" needs a model id".to_string()))?;
+        let family = ModelFamily::of(&model).ok_or_else(|| {
+            err(format!(
+                "unsupported Bedrock model {model:?} (Anthropic and Meta Llama models are supported)"
+            ))
+        })?;
+        let region = resolve_region(cfg.region.as_deref(), cfg.profile.as_deref())
+            .ok_or_else(|| err("no AWS region: set [llm] region or AWS_REGION".to_string()))?;
+        let credentials = AwsCredentials::from_chain(cfg.profile.as_deref()).map_err(err)?;
+        Ok(Self {
+            region,
+            model,
+            family,
+            credentials,
+            endpoint: cfg
+                .base_url
+                .as_deref()
+                .map(|u| u.trim_end_matches('/').to_string()),
+        })
+    }
+
+    pub fn endpoint(&self) -> String {
+        self.endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://bedrock-runtime.{}.amazonaws.com", self.region))
+    }
+
+    /// Request path, with the model id percent-encoded (ids contain `:`).
+    pub fn path(&self) -> String {
+        format!("/model/{}/invoke", uri_encode(&self.model))
+    }
+
+    pub fn request_body(&self, req: &ChatRequest) -> Value {
+        let temperature = req.temperature.unwrap_or(0.2);
+        let max_tokens = req.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
+        match self.family {
+            ModelFamily::Anthropic => json!({
+                "anthropic_version": BEDROCK_ANTHROPIC_VERSION,
+                "system": req.system,
+                "messages": req
+                    .messages
+                    .iter()
+                    .map(|m| json!({ "role": m.role, "content": m.content }))
+                    .collect::<Vec<_>>(),
+                "temperature": temperature,
+                "max_tokens": max_tokens,
+            }),
+            ModelFamily::Llama => json!({
+                "prompt": llama3_prompt(req),
+                "temperature": temperature,
+                "max_gen_len": max_tokens,
+            }),
+        }
+    }
+
+    pub fn parse_response(&self, raw: &Value) -> Result<(String, Usage), LlmError> {
+        match self.family {
+            ModelFamily::Anthropic => parse_messages_response("bedrock", raw),
+            ModelFamily::Llama => {
+                let text = raw
+                    .get("generation")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| {
+                        LlmError::new(LlmErrorKind::Decode, "bedrock", "missing generation")
+                    })?;
+                Ok((
+                    text.to_string(),
+                    Usage {
+                        input_tokens: u64_at(raw, "/prompt_token_count"),
+                        output_tokens: u64_at(raw, "/generation_token_count"),
+                        ..Default::default()
+                    },
+                ))
+            }
+        }
+    }
+}
+
+impl Provider for BedrockProvider {
+    fn name(&self) -> &str {
+        "bedrock"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let body = self.request_body(req);
+            let payload = serde_json::to_vec(&body).map_err(|e| {
+                LlmError::new(LlmErrorKind::InvalidRequest, "bedrock", e.to_string())
+            })?;
+            let endpoint = self.endpoint();
+            let host = endpoint
+                .split("://")
+                .nth(1)
+                .unwrap_or(&endpoint)
+                .split('/')
+                .next()
+                .unwrap_or("")
+                .to_string();
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let path = self.path();
+            let headers = sigv4_headers(
+                &self.credentials,
+                "POST",
+                &host,
+                &path,
+                &self.region,
+                SERVICE,
+                &payload,
+                &amz_date(now),
+            );
+            let url = format!("{endpoint}{path}");
+            let raw = post_json("bedrock", &url, &headers, &body, req.timeout).await?;
+            let (content, usage) = self.parse_response(&raw)?;
+            Ok(ChatResponse {
+                provider: "bedrock".to_string(),
+                model: self.model.clone(),
+                content,
+                usage,
+                raw,
+                stopped_early: false,
+                logprobs: None,
+                tool_calls: Vec::new(),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn signs_like_the_sigv4_suite_and_shapes_bodies_by_family() {
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(amz_date(1_440_938_160), "20150830T123600Z");
+        // `get-vanilla` from the AWS SigV4 test suite.
+        let creds = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into(),
+            session_token: None,
+        };
+        let h = sigv4_headers(
+            &creds,
+            "GET",
+            "example.amazonaws.com",
+            "/",
+            "us-east-1",
+            "service",
+            b"",
+            "20150830T123600Z",
+        );
+        assert!(h[1].1.ends_with(
+            "SignedHeaders=host;x-amz-date, Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        ));
+
+        let mut p = BedrockProvider {
+            region: "us-east-1".into(),
+            model: "anthropic.claude-3-haiku-20240307-v1:0".into(),
+            family: ModelFamily::Anthropic,
+            credentials: creds,
+            endpoint: None,
+        };
+        assert_eq!(
+            p.path(),
+            "/model/anthropic.claude-3-haiku-20240307-v1%3A0/invoke"
+        );
+        let req = ChatRequest::simple("sys", "goal", Duration::from_secs(1));
+        assert_eq!(
+            p.request_body(&req)["anthropic_version"],
+            BEDROCK_ANTHROPIC_VERSION
+        );
+        p.model = "us.meta.llama3-1-70b-instruct-v1:0".into();
+        p.family = ModelFamily::of(&p.model).unwrap();
+        let body = p.request_body(&req);
+        assert!(body["prompt"]
+            .as_str()
+            .unwrap()
+            .ends_with("assistant<|end_header_id|>\n\n"));
+        let (text, usage) = p
+            .parse_response(&json!({"generation": "by simp", "prompt_token_count": 9, "generation_token_count": 3}))
+            .unwrap();
+        assert_eq!((text.as_str(), usage.input_tokens), ("by simp", 9));
+        assert_eq!(
+            ini_section(
+                "[default]\nregion = eu-west-1\n[profile x]\nregion=us-west-2\n",
+                "profile x"
+            ),
+            vec![("region".to_string(), "us-west-2".to_string())]
+        );
+    }
+}
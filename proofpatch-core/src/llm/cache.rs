@@ -0,0 +1,198 @@
+//! Content-addressed cache of LLM completions.
+//!
+//! An entry is keyed on the SHA-256 of (provider, model, prompt template version, rendered prompt
+//! hash, temperature, seed, sample index, response schema) (`CacheKey::digest`) and stored as
+//! `<repo>/.generated/proofpatch-cache/llm/<2 hex>/<digest>.json`, so re-running the same repair or
+//! evaluation with the same prompts and sampling settings does not pay for the same request
+//! twice. Entries older than the TTL are ignored and overwritten.
+//!
+//! `configure_from_repo` turns the cache on for the repo (`[llm] cache`, default on;
+//! `[llm] cache_ttl_s`, default a week). `PROOFPATCH_LLM_CACHE=0` or `disable()` turns it off for
+//! the process, and `ChatOptions::no_cache` for one request.
+
+use super::provider::Usage;
+use crate::prompts::PromptVersion;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheSettings {
+    pub dir: PathBuf,
+    pub ttl: Duration,
+}
+
+static SETTINGS: RwLock<Option<CacheSettings>> = RwLock::new(None);
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn configure_cache(settings: Option<CacheSettings>) {
+    if let Ok(mut g) = SETTINGS.write() {
+        *g = settings;
+    }
+}
+
+/// Turn the cache off for the rest of the process (a later `configure_cache` does not undo it).
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// The cache in effect, unless it is off (`disable`, `PROOFPATCH_LLM_CACHE=0`).
+pub fn settings() -> Option<CacheSettings> {
+    if DISABLED.load(Ordering::Relaxed) || !super::env_truthy("PROOFPATCH_LLM_CACHE", true) {
+        return None;
+    }
+    SETTINGS.read().ok().and_then(|g| g.clone())
+}
+
+pub fn default_dir(repo_root: &Path) -> PathBuf {
+    repo_root
+        .join(".generated")
+        .join("proofpatch-cache")
+        .join("llm")
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub provider: String,
+    pub model: String,
+    /// `name@version#sha256` of the prompt template, when the prompt came from one.
+    #[serde(default)]
+    pub template: Option<String>,
+    /// SHA-256 of the system prompt, a NUL, and the user prompt.
+    pub prompt_sha256: String,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Which of several samples drawn for the same prompt (`ChatOptions::sample`).
+    #[serde(default)]
+    pub sample: Option<u32>,
+    /// `schema_id` of the response schema the request was constrained to.
+    #[serde(default)]
+    pub schema: Option<String>,
+}
+
+impl CacheKey {
+    pub fn new(
+        provider: &str,
+        model: &str,
+        template: Option<&PromptVersion>,
+        system: &str,
+        user: &str,
+        temperature: Option<f32>,
+        seed: Option<u64>,
+    ) -> Self {
+        Self {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            template: template.map(|t| format!("{}@{}#{}", t.name, t.version, t.sha256)),
+            prompt_sha256: sha256_hex(&[system.as_bytes(), &[0], user.as_bytes()]),
+            temperature,
+            seed,
+            sample: None,
+            schema: None,
+        }
+    }
+
+    pub fn digest(&self) -> String {
+        sha256_hex(&[serde_json::to_string(self).unwrap_or_default().as_bytes()])
+    }
+}
+
+/// `name#<16 hex of the schema's SHA-256>`.
+pub fn schema_id(name: &str, schema: &serde_json::Value) -> String {
+    let h = sha256_hex(&[schema.to_string().as_bytes()]);
+    format!("{name}#{}", &h[..16])
+}
+
+fn sha256_hex(parts: &[&[u8]]) -> String {
+    use sha2::{Digest, Sha256};
+    let mut h = Sha256::new();
+    for p in parts {
+        h.update(p);
+    }
+    format!("{:x}", h.finalize())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedCompletion {
+    pub key: CacheKey,
+    /// Unix seconds.
+    pub created_at: u64,
+    pub content: String,
+    /// Usage of the original request (a hit itself costs nothing).
+    #[serde(default)]
+    pub usage: Usage,
+    #[serde(default)]
+    pub stopped_early: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Vec<super::provider::TokenLogprob>>,
+}
+
+fn entry_path(dir: &Path, key: &CacheKey) -> PathBuf {
+    let d = key.digest();
+    dir.join(&d[..2]).join(format!("{d}.json"))
+}
+
+pub(super) fn now_s() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The entry for `key`, if present, younger than `ttl` at `now` (unix seconds), and for the
+/// same key (not just the same digest).
+pub fn get(dir: &Path, key: &CacheKey, ttl: Duration, now: u64) -> Option<CachedCompletion> {
+    let txt = std::fs::read_to_string(entry_path(dir, key)).ok()?;
+    let c: CachedCompletion = serde_json::from_str(&txt).ok()?;
+    (c.key == *key && now.saturating_sub(c.created_at) < ttl.as_secs()).then_some(c)
+}
+
+pub fn put(dir: &Path, entry: &CachedCompletion) -> Result<PathBuf, String> {
+    let p = entry_path(dir, &entry.key);
+    let parent = p.parent().unwrap_or(dir);
+    std::fs::create_dir_all(parent).map_err(|e| format!("mkdir {}: {e}", parent.display()))?;
+    let data = serde_json::to_vec(entry).map_err(|e| e.to_string())?;
+    // Write-then-rename, so a concurrent reader never sees half an entry.
+    let mut tmp = tempfile::NamedTempFile::new_in(parent).map_err(|e| e.to_string())?;
+    std::io::Write::write_all(&mut tmp, &data).map_err(|e| e.to_string())?;
+    tmp.persist(&p)
+        .map_err(|e| format!("write {}: {e}", p.display()))?;
+    Ok(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_on_prompt_and_sampling_and_expires() {
+        let td = tempfile::tempdir().unwrap();
+        let key = CacheKey::new("openai", "gpt-4o", None, "sys", "user", Some(0.2), None);
+        let entry = CachedCompletion {
+            key: key.clone(),
+            created_at: 1_000,
+            content: "by simp".to_string(),
+            usage: Usage::default(),
+            stopped_early: false,
+            logprobs: None,
+        };
+        put(td.path(), &entry).unwrap();
+        let hit = get(td.path(), &key, Duration::from_secs(60), 1_030).unwrap();
+        assert_eq!(hit.content, "by simp");
+        assert!(get(td.path(), &key, Duration::from_secs(60), 1_060).is_none());
+        for other in [
+            CacheKey::new("openai", "gpt-4o", None, "sys", "user", Some(0.7), None),
+            CacheKey::new("openai", "gpt-4o", None, "sys", "user!", Some(0.2), None),
+            CacheKey::new("openai", "gpt-4o", None, "sy", "suser", Some(0.2), None),
+        ] {
+            assert_ne!(other.digest(), key.digest());
+            assert!(get(td.path(), &other, Duration::from_secs(60), 1_030).is_none());
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! Token usage and cost accounting for LLM requests, and the `[budget]` caps.
+//!
+//! Every request made through `chat_completion` / `chat_completion_raw` is turned into a
+//! `RequestUsage`: the provider-reported token counts when the response has them, otherwise
+//! `prompt_context::estimate_tokens` over the prompt and reply (`estimated: true`). Its cost comes
+//! from `[budget.prices]` in `proofpatch.toml` or the built-in price list (USD per million tokens,
+//! matched by the longest key the model name contains; local backends are free). Requests without
+//! a known price are counted but not costed. Prompt-cache reads and writes (Anthropic) are
+//! counted separately from `input_tokens` and priced at `CACHE_READ_FACTOR` /
+//! `CACHE_WRITE_FACTOR` times the input price.
+//!
+//! Usage is summed per process (`run_totals`) and, by the repair loop, per repair
+//! (`RepairOutcome::llm_usage`). `check_budget` refuses a request once the process has reached
+//! any `[budget]` cap, with an error starting with `BUDGET_EXHAUSTED`.
+
+use super::provider::Usage;
+use crate::config::{BudgetConfig, ModelPrice};
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, RwLock};
+
+/// Prefix of the error returned for requests refused by `check_budget`.
+pub const BUDGET_EXHAUSTED: &str = "LLM budget exhausted";
+
+/// Price of prompt-cache reads and writes relative to the input price (Anthropic's 5-minute
+/// cache).
+pub const CACHE_READ_FACTOR: f64 = 0.1;
+pub const CACHE_WRITE_FACTOR: f64 = 1.25;
+
+/// (model name fragment, USD per million input tokens, per million output tokens).
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("o3-mini", 1.10, 4.40),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-sonnet-4", 3.00, 15.00),
+    ("claude-opus-4", 15.00, 75.00),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("gemini-1.5-pro", 1.25, 5.00),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-2.5-flash", 0.30, 2.50),
+    ("gemini-2.5-pro", 1.25, 10.00),
+];
+
+static BUDGET: RwLock<Option<BudgetConfig>> = RwLock::new(None);
+static RUN: Mutex<UsageTotals> = Mutex::new(UsageTotals::zero());
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RequestUsage {
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Input tokens read from / written to the provider's prompt cache (not in `input_tokens`).
+    #[serde(default)]
+    pub cache_read_tokens: u64,
+    #[serde(default)]
+    pub cache_write_tokens: u64,
+    /// At least one count is a character-based estimate (the provider reported none).
+    pub estimated: bool,
+    /// `None`: no price known for the model.
+    pub cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_read_tokens: u64,
+    #[serde(default)]
+    pub cache_write_tokens: u64,
+    /// Requests whose token counts were (partly) estimated.
+    pub estimated_requests: u64,
+    /// Requests with no known price (not included in `cost_usd`).
+    pub unpriced_requests: u64,
+    /// Requests refused by `[budget]`.
+    pub refused_requests: u64,
+    /// Requests cancelled mid-reply (`speculative`); their partial output is not counted.
+    #[serde(default)]
+    pub cancelled_requests: u64,
+    pub cost_usd: f64,
+}
+
+impl UsageTotals {
+    const fn zero() -> Self {
+        Self {
+            requests: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            cache_read_tokens: 0,
+            cache_write_tokens: 0,
+            estimated_requests: 0,
+            unpriced_requests: 0,
+            refused_requests: 0,
+            cancelled_requests: 0,
+            cost_usd: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, u: &RequestUsage) {
+        self.requests += 1;
+        self.input_tokens += u.input_tokens;
+        self.output_tokens += u.output_tokens;
+        self.cache_read_tokens += u.cache_read_tokens;
+        self.cache_write_tokens += u.cache_write_tokens;
+        self.estimated_requests += u.estimated as u64;
+        match u.cost_usd {
+            Some(c) => self.cost_usd += c,
+            None => self.unpriced_requests += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &UsageTotals) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_read_tokens += other.cache_read_tokens;
+        self.cache_write_tokens += other.cache_write_tokens;
+        self.estimated_requests += other.estimated_requests;
+        self.unpriced_requests += other.unpriced_requests;
+        self.refused_requests += other.refused_requests;
+        self.cancelled_requests += other.cancelled_requests;
+        self.cost_usd += other.cost_usd;
+    }
+
+    /// Input (cached or not) and output tokens.
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.cache_read_tokens + self.cache_write_tokens + self.output_tokens
+    }
+
+    /// Which cap of `budget` these totals have reached, if any.
+    pub fn exceeded(&self, budget: &BudgetConfig) -> Option<String> {
+        if let Some(n) = budget.max_requests.filter(|n| self.requests >= *n) {
+            return Some(format!("{} requests (max_requests = {n})", self.requests));
+        }
+        if let Some(n) = budget.max_tokens.filter(|n| self.total_tokens() >= *n) {
+            return Some(format!("{} tokens (max_tokens = {n})", self.total_tokens()));
+        }
+        if let Some(c) = budget.max_cost_usd.filter(|c| self.cost_usd >= *c) {
+            return Some(format!("${:.4} (max_cost_usd = {c})", self.cost_usd));
+        }
+        None
+    }
+}
+
+/// Set the `[budget]` in effect for this process (`None`: no caps).
+pub fn configure_budget(budget: Option<BudgetConfig>) {
+    if let Ok(mut g) = BUDGET.write() {
+        *g = budget;
+    }
+}
+
+pub fn budget() -> Option<BudgetConfig> {
+    BUDGET.read().ok().and_then(|g| g.clone())
+}
+
+/// Price of `model` on `provider`: `[budget.prices]` first, then the built-in list.
+pub fn price_for(provider: &str, model: &str, budget: Option<&BudgetConfig>) -> Option<ModelPrice> {
+    if matches!(provider, "ollama" | "gguf") {
+        return Some(ModelPrice::default());
+    }
+    let m = model.to_lowercase();
+    let longest = |keys: &mut dyn Iterator<Item = &str>| {
+        keys.filter(|k| m.contains(&k.to_lowercase()))
+            .max_by_key(|k| k.len())
+            .map(str::to_string)
+    };
+    if let Some(b) = budget {
+        if let Some(k) = longest(&mut b.prices.keys().map(|k| k.as_str())) {
+            return b.prices.get(&k).cloned();
+        }
+    }
+    let k = longest(&mut PRICES.iter().map(|(k, _, _)| *k))?;
+    PRICES
+        .iter()
+        .find(|(name, _, _)| *name == k)
+        .map(|(_, i, o)| ModelPrice {
+            input_per_mtok: *i,
+            output_per_mtok: *o,
+        })
+}
+
+impl RequestUsage {
+    /// Usage of one request, filling in counts the provider did not report from the text.
+    pub fn new(provider: &str, model: &str, usage: &Usage, prompt: &str, reply: &str) -> Self {
+        let est = crate::prompt_context::estimate_tokens;
+        let estimated_input = usage.input_tokens == 0
+            && usage.cache_read_tokens == 0
+            && usage.cache_write_tokens == 0
+            && !prompt.is_empty();
+        let estimated_output = usage.output_tokens == 0 && !reply.is_empty();
+        let input_tokens = if estimated_input {
+            est(prompt) as u64
+        } else {
+            usage.input_tokens
+        };
+        let output_tokens = if estimated_output {
+            est(reply) as u64
+        } else {
+            usage.output_tokens
+        };
+        let cached_input = usage.cache_read_tokens as f64 * CACHE_READ_FACTOR
+            + usage.cache_write_tokens as f64 * CACHE_WRITE_FACTOR;
+        let cost_usd = price_for(provider, model, budget().as_ref()).map(|p| {
+            ((input_tokens as f64 + cached_input) * p.input_per_mtok
+                + output_tokens as f64 * p.output_per_mtok)
+                / 1e6
+        });
+        Self {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            input_tokens,
+            output_tokens,
+            cache_read_tokens: usage.cache_read_tokens,
+            cache_write_tokens: usage.cache_write_tokens,
+            estimated: estimated_input || estimated_output,
+            cost_usd,
+        }
+    }
+
+    /// Add `other`, a later request of the same exchange (a tool-call round trip, `tools`).
+    pub fn merge(&mut self, other: &RequestUsage) {
+        self.provider.clone_from(&other.provider);
+        self.model.clone_from(&other.model);
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_read_tokens += other.cache_read_tokens;
+        self.cache_write_tokens += other.cache_write_tokens;
+        self.estimated |= other.estimated;
+        self.cost_usd = self.cost_usd.zip(other.cost_usd).map(|(a, b)| a + b);
+    }
+}
+
+/// Totals for every request this process has made.
+pub fn run_totals() -> UsageTotals {
+    RUN.lock().map(|g| g.clone()).unwrap_or_default()
+}
+
+pub fn reset_run() {
+    if let Ok(mut g) = RUN.lock() {
+        *g = UsageTotals::default();
+    }
+}
+
+/// Count a finished request against the run.
+pub fn record(u: &RequestUsage) {
+    if let Ok(mut g) = RUN.lock() {
+        g.add(u);
+    }
+}
+
+/// `Err` (and a refused request in the run totals) once any `[budget]` cap is reached.
+pub fn check_budget() -> Result<(), String> {
+    let Some(b) = budget() else {
+        return Ok(());
+    };
+    let Ok(mut g) = RUN.lock() else {
+        return Ok(());
+    };
+    match g.exceeded(&b) {
+        Some(why) => {
+            g.refused_requests += 1;
+            Err(format!("{BUDGET_EXHAUSTED}: {why}"))
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prices_estimates_and_caps() {
+        let p = price_for("openai", "gpt-4o-mini-2024-07-18", None).unwrap();
+        assert_eq!(p.input_per_mtok, 0.15);
+        let p = price_for(
+            "bedrock",
+            "us.anthropic.claude-3-5-sonnet-20240620-v1:0",
+            None,
+        )
+        .unwrap();
+        assert_eq!(p.output_per_mtok, 15.0);
+        assert!(price_for("openai", "mystery-model", None).is_none());
+        assert_eq!(
+            price_for("ollama", "llama3", None),
+            Some(ModelPrice::default())
+        );
+        let mut b = BudgetConfig::default();
+        b.prices.insert(
+            "gpt-4o".to_string(),
+            ModelPrice {
+                input_per_mtok: 1.0,
+                output_per_mtok: 2.0,
+            },
+        );
+        assert_eq!(
+            price_for("openai", "gpt-4o-mini", Some(&b))
+                .unwrap()
+                .input_per_mtok,
+            1.0
+        );
+
+        let reported = Usage {
+            input_tokens: 1_000_000,
+            output_tokens: 0,
+            ..Default::default()
+        };
+        let u = RequestUsage::new("openai", "gpt-4o", &reported, "system + user", "12345678");
+        assert!(u.estimated);
+        assert_eq!((u.input_tokens, u.output_tokens), (1_000_000, 2));
+        assert!((u.cost_usd.unwrap() - 2.50002).abs() < 1e-9);
+
+        let cached = Usage {
+            input_tokens: 100_000,
+            output_tokens: 0,
+            cache_read_tokens: 1_000_000,
+            cache_write_tokens: 100_000,
+        };
+        let c = RequestUsage::new("anthropic", "claude-sonnet-4", &cached, "x", "");
+        assert!(!c.estimated);
+        // 0.1M at $3, 1M reads at $0.30, 0.1M writes at $3.75.
+        assert!((c.cost_usd.unwrap() - (0.3 + 0.3 + 0.375)).abs() < 1e-9);
+
+        let mut t = UsageTotals::default();
+        t.add(&c);
+        assert_eq!(t.total_tokens(), 1_200_000);
+        let mut t = UsageTotals::default();
+        t.add(&u);
+        b.max_cost_usd = Some(2.0);
+        assert!(t.exceeded(&b).unwrap().contains("max_cost_usd"));
+        b.max_cost_usd = None;
+        b.max_requests = Some(2);
+        assert!(t.exceeded(&b).is_none());
+    }
+}
@@ -0,0 +1,458 @@
+//! Text embeddings for retrieval (premise selection, few-shot exemplars).
+//!
+//! `EmbeddingBackend` is the API abstraction: the offline `premise::HashingEmbedder`, an
+//! OpenAI-compatible `/embeddings` endpoint, or a local Ollama model (`/api/embed`).
+//! `Embeddings` wraps a backend with batching (at most `batch_size` texts per request, repeated
+//! texts sent once) and a persistent `VectorCache` keyed on the backend id and the SHA-256 of
+//! the text, so re-indexing or re-querying the same text costs nothing. Vectors are
+//! L2-normalized, so a dot product is a cosine similarity.
+//!
+//! `[embeddings]` in `proofpatch.toml` picks the backend (`from_repo`); without it, retrieval
+//! uses the hashing embedder as before.
+
+use super::provider::{post_json, LlmError};
+use crate::config::EmbeddingsConfig;
+use crate::premise::{Embedder, HashingEmbedder};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub type EmbedFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Vec<f32>>, String>> + Send + 'a>>;
+
+pub trait EmbeddingBackend: Send + Sync {
+    /// Stable identifier (backend and model); vectors from different ids are not comparable.
+    fn id(&self) -> String;
+
+    /// One vector per text, in order.
+    fn embed_batch<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a>;
+}
+
+impl EmbeddingBackend for HashingEmbedder {
+    fn id(&self) -> String {
+        Embedder::id(self)
+    }
+
+    fn embed_batch<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a> {
+        Box::pin(async move { Ok(texts.iter().map(|t| self.embed(t)).collect()) })
+    }
+}
+
+fn llm_err(e: LlmError) -> String {
+    e.to_string()
+}
+
+/// `POST {base_url}/embeddings` (OpenAI and compatible servers).
+#[derive(Debug, Clone)]
+pub struct OpenAiEmbeddings {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    /// Ask for shortened vectors (`text-embedding-3-*` only).
+    pub dimensions: Option<usize>,
+    pub timeout: Duration,
+}
+
+impl OpenAiEmbeddings {
+    pub fn request_body(&self, texts: &[String]) -> Value {
+        let mut body = json!({ "model": self.model, "input": texts });
+        if let Some(d) = self.dimensions {
+            body["dimensions"] = json!(d);
+        }
+        body
+    }
+}
+
+/// Vectors from an OpenAI-style `{"data": [{"index", "embedding"}]}` response, in input order.
+pub fn parse_openai_embeddings(v: &Value, n: usize) -> Result<Vec<Vec<f32>>, String> {
+    let data = v
+        .get("data")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "embeddings response without `data`".to_string())?;
+    let mut out: Vec<Option<Vec<f32>>> = vec![None; n];
+    for (i, d) in data.iter().enumerate() {
+        let idx = d
+            .get("index")
+            .and_then(Value::as_u64)
+            .map_or(i, |x| x as usize);
+        if let (Some(slot), Some(e)) = (out.get_mut(idx), d.get("embedding")) {
+            *slot = Some(floats(e));
+        }
+    }
+    out.into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| format!("embeddings response has {} of {n} vectors", data.len()))
+}
+
+fn floats(v: &Value) -> Vec<f32> {
+    v.as_array()
+        .map(|xs| {
+            xs.iter()
+                .filter_map(Value::as_f64)
+                .map(|x| x as f32)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl EmbeddingBackend for OpenAiEmbeddings {
+    fn id(&self) -> String {
+        match self.dimensions {
+            Some(d) => format!("openai:{}:{d}", self.model),
+            None => format!("openai:{}", self.model),
+        }
+    }
+
+    fn embed_batch<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a> {
+        Box::pin(async move {
+            let url = format!("{}/embeddings", self.base_url.trim_end_matches('/'));
+            let headers: Vec<(&str, String)> = self
+                .api_key
+                .iter()
+                .map(|k| ("authorization", format!("Bearer {k}")))
+                .collect();
+            let raw = post_json(
+                "openai",
+                &url,
+                &headers,
+                &self.request_body(texts),
+                self.timeout,
+            )
+            .await
+            .map_err(llm_err)?;
+            parse_openai_embeddings(&raw, texts.len())
+        })
+    }
+}
+
+/// A local Ollama model (`POST {base_url}/api/embed`).
+#[derive(Debug, Clone)]
+pub struct OllamaEmbeddings {
+    pub base_url: String,
+    pub model: String,
+    pub timeout: Duration,
+}
+
+impl EmbeddingBackend for OllamaEmbeddings {
+    fn id(&self) -> String {
+        format!("ollama:{}", self.model)
+    }
+
+    fn embed_batch<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a> {
+        Box::pin(async move {
+            let url = format!("{}/api/embed", self.base_url.trim_end_matches('/'));
+            let body = json!({ "model": self.model, "input": texts });
+            let raw = post_json("ollama", &url, &[], &body, self.timeout)
+                .await
+                .map_err(llm_err)?;
+            let vs: Vec<Vec<f32>> = raw
+                .get("embeddings")
+                .and_then(Value::as_array)
+                .map(|xs| xs.iter().map(floats).collect())
+                .unwrap_or_default();
+            if vs.len() != texts.len() {
+                return Err(format!(
+                    "ollama embed returned {} of {} vectors",
+                    vs.len(),
+                    texts.len()
+                ));
+            }
+            Ok(vs)
+        })
+    }
+}
+
+/// Vectors on disk: one JSONL file per backend id (`{"h": <sha256 of text>, "v": [...]}`),
+/// loaded on open and appended to.
+#[derive(Debug)]
+pub struct VectorCache {
+    path: PathBuf,
+    map: Mutex<HashMap<String, Vec<f32>>>,
+}
+
+pub fn text_hash(text: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+impl VectorCache {
+    pub fn open(dir: &Path, backend_id: &str) -> Self {
+        let file: String = backend_id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let path = dir.join(format!("{file}.jsonl"));
+        let map = std::fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|l| serde_json::from_str::<Value>(l).ok())
+            .filter_map(|v| Some((v.get("h")?.as_str()?.to_string(), floats(v.get("v")?))))
+            .collect();
+        Self {
+            path,
+            map: Mutex::new(map),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.lock().map(|m| m.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, hash: &str) -> Option<Vec<f32>> {
+        self.map.lock().ok()?.get(hash).cloned()
+    }
+
+    pub fn put_all(&self, entries: &[(String, Vec<f32>)]) -> Result<(), String> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("mkdir {}: {e}", dir.display()))?;
+        }
+        let mut f = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("open {}: {e}", self.path.display()))?;
+        let mut buf = String::new();
+        for (h, v) in entries {
+            buf.push_str(&json!({ "h": h, "v": v }).to_string());
+            buf.push('\n');
+        }
+        f.write_all(buf.as_bytes())
+            .map_err(|e| format!("write {}: {e}", self.path.display()))?;
+        if let Ok(mut m) = self.map.lock() {
+            m.extend(entries.iter().cloned());
+        }
+        Ok(())
+    }
+}
+
+pub fn default_cache_dir(repo_root: &Path) -> PathBuf {
+    repo_root
+        .join(".generated")
+        .join("proofpatch-cache")
+        .join("embeddings")
+}
+
+pub struct Embeddings {
+    pub backend: Box<dyn EmbeddingBackend>,
+    pub batch_size: usize,
+    pub cache: Option<VectorCache>,
+}
+
+fn normalize(mut v: Vec<f32>) -> Vec<f32> {
+    let n = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if n > 0.0 {
+        v.iter_mut().for_each(|x| *x /= n);
+    }
+    v
+}
+
+impl Embeddings {
+    /// The offline hashing embedder, uncached (it is cheaper than a cache lookup).
+    pub fn hashing(dim: usize) -> Self {
+        Self {
+            backend: Box::new(HashingEmbedder { dim }),
+            batch_size: usize::MAX,
+            cache: None,
+        }
+    }
+
+    pub fn id(&self) -> String {
+        self.backend.id()
+    }
+
+    /// Whether this is the built-in hashing embedder.
+    pub fn is_hashing(&self) -> bool {
+        self.id().starts_with("hashing-")
+    }
+
+    /// Normalized vectors for `texts`, in order: cached ones from the cache, the rest in batches
+    /// (each distinct text once), which are then cached.
+    pub async fn embed_all(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let hashes: Vec<String> = texts.iter().map(|t| text_hash(t)).collect();
+        let mut found: HashMap<String, Vec<f32>> = HashMap::new();
+        let mut missing: Vec<(String, String)> = Vec::new();
+        for (t, h) in texts.iter().zip(&hashes) {
+            if found.contains_key(h) || missing.iter().any(|(mh, _)| mh == h) {
+                continue;
+            }
+            match self.cache.as_ref().and_then(|c| c.get(h)) {
+                Some(v) => {
+                    found.insert(h.clone(), v);
+                }
+                None => missing.push((h.clone(), t.clone())),
+            }
+        }
+        for batch in missing.chunks(self.batch_size.max(1)) {
+            let texts: Vec<String> = batch.iter().map(|(_, t)| t.clone()).collect();
+            let vs = self.backend.embed_batch(&texts).await?;
+            let fresh: Vec<(String, Vec<f32>)> = batch
+                .iter()
+                .zip(vs)
+                .map(|((h, _), v)| (h.clone(), normalize(v)))
+                .collect();
+            if let Some(c) = &self.cache {
+                let _ = c.put_all(&fresh);
+            }
+            found.extend(fresh);
+        }
+        Ok(hashes
+            .iter()
+            .map(|h| found.get(h).cloned().unwrap_or_default())
+            .collect())
+    }
+
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+        Ok(self
+            .embed_all(&[text.to_string()])
+            .await?
+            .pop()
+            .unwrap_or_default())
+    }
+}
+
+/// Embeddings for `cfg`: `hashing` (default), `openai`, or `ollama`. Remote backends are cached
+/// under `cache_dir` unless `cache = false`.
+pub fn from_config(cfg: &EmbeddingsConfig, cache_dir: &Path) -> Result<Embeddings, String> {
+    let timeout = Duration::from_secs(cfg.timeout_s.unwrap_or(60));
+    let backend: Box<dyn EmbeddingBackend> = match cfg.provider.as_deref().unwrap_or("hashing") {
+        "hashing" => return Ok(Embeddings::hashing(cfg.dimensions.unwrap_or(512))),
+        "openai" => {
+            let key_env = cfg.api_key_env.as_deref().unwrap_or("OPENAI_API_KEY");
+            Box::new(OpenAiEmbeddings {
+                base_url: cfg
+                    .base_url
+                    .clone()
+                    .unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
+                api_key: std::env::var(key_env).ok().filter(|k| !k.trim().is_empty()),
+                model: cfg
+                    .model
+                    .clone()
+                    .unwrap_or_else(|| "text-embedding-3-small".to_string()),
+                dimensions: cfg.dimensions,
+                timeout,
+            })
+        }
+        "ollama" => Box::new(OllamaEmbeddings {
+            base_url: cfg.base_url.clone().unwrap_or_else(|| {
+                std::env::var("OLLAMA_HOST").unwrap_or_else(|_| "http://localhost:11434".into())
+            }),
+            model: cfg
+                .model
+                .clone()
+                .unwrap_or_else(|| "nomic-embed-text".to_string()),
+            timeout,
+        }),
+        other => {
+            return Err(format!(
+                "unknown [embeddings] provider: {other} (expected hashing|openai|ollama)"
+            ))
+        }
+    };
+    let cache = cfg
+        .cache
+        .unwrap_or(true)
+        .then(|| VectorCache::open(cache_dir, &backend.id()));
+    Ok(Embeddings {
+        backend,
+        batch_size: cfg.batch_size.unwrap_or(64),
+        cache,
+    })
+}
+
+/// `[embeddings]` from the repo's `proofpatch.toml` (the hashing embedder without one).
+pub fn from_repo(repo_root: &Path) -> Result<Embeddings, String> {
+    let cfg = crate::config::load_from_repo_root(repo_root)?
+        .map(|c| c.embeddings)
+        .unwrap_or_default();
+    from_config(&cfg, &default_cache_dir(repo_root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Counting(Arc<(AtomicUsize, AtomicUsize)>);
+
+    impl EmbeddingBackend for Counting {
+        fn id(&self) -> String {
+            "test:counting".to_string()
+        }
+
+        fn embed_batch<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a> {
+            self.0 .0.fetch_add(1, Ordering::Relaxed);
+            self.0 .1.fetch_add(texts.len(), Ordering::Relaxed);
+            Box::pin(async move { Ok(texts.iter().map(|t| vec![t.len() as f32, 1.0]).collect()) })
+        }
+    }
+
+    #[test]
+    fn batches_dedupes_and_persists_vectors() {
+        let td = tempfile::tempdir().unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let texts: Vec<String> = ["a", "bb", "a", "ccc", "dddd"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let calls = Arc::new((AtomicUsize::new(0), AtomicUsize::new(0)));
+        let emb = |cache| Embeddings {
+            backend: Box::new(Counting(calls.clone())),
+            batch_size: 2,
+            cache: Some(cache),
+        };
+        let vs = rt
+            .block_on(emb(VectorCache::open(td.path(), "test:counting")).embed_all(&texts))
+            .unwrap();
+        assert_eq!(vs.len(), 5);
+        assert_eq!(vs[0], vs[2]);
+        assert!((vs[1].iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-6);
+        assert_eq!(
+            (
+                calls.0.load(Ordering::Relaxed),
+                calls.1.load(Ordering::Relaxed)
+            ),
+            (2, 4)
+        );
+
+        // A fresh process reads the vectors back instead of asking the backend again.
+        let reopened = VectorCache::open(td.path(), "test:counting");
+        assert_eq!(reopened.len(), 4);
+        let again = rt.block_on(emb(reopened).embed_all(&texts)).unwrap();
+        assert_eq!(again, vs);
+        assert_eq!(calls.0.load(Ordering::Relaxed), 2);
+
+        let body = OpenAiEmbeddings {
+            base_url: "b".into(),
+            api_key: None,
+            model: "text-embedding-3-small".into(),
+            dimensions: Some(256),
+            timeout: Duration::from_secs(1),
+        }
+        .request_body(&texts[..2]);
+        assert_eq!(body["dimensions"], 256);
+        let parsed = parse_openai_embeddings(
+            &json!({"data": [{"index": 1, "embedding": [0.5]}, {"index": 0, "embedding": [0.25]}]}),
+            2,
+        )
+        .unwrap();
+        assert_eq!(parsed, vec![vec![0.25], vec![0.5]]);
+    }
+}
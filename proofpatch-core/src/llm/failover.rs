@@ -0,0 +1,138 @@
+//! Provider failover (`[llm] providers`).
+//!
+//! `chat_completion_with` tries the primary provider and then the rest of the chain (`chain`)
+//! when a request fails in a way another provider might not: the provider is down or
+//! rate-limited (`LlmErrorKind::is_retryable`), rejects the credentials, or cannot be built
+//! (e.g. no key in the environment). A provider that failed over is put on cooldown for
+//! `failover_cooldown_s`, and later requests in the process try it after the healthy ones.
+//!
+//! Requests are provider-neutral (`provider::ChatRequest`), so the conversation so far
+//! (`ChatOptions::history`) is sent to the fallback unchanged. Each result records the provider
+//! that served it and the ones that failed before it (`ChatCompletionResult::failover`).
+
+use super::provider::{LlmError, LlmErrorKind};
+use super::routing::tier_config;
+use crate::config::{LlmConfig, ModelTier};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Providers on cooldown, and until when.
+static DOWN: RwLock<Option<HashMap<String, Instant>>> = RwLock::new(None);
+
+/// A provider that failed before another one served the request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailoverAttempt {
+    pub provider: String,
+    pub kind: LlmErrorKind,
+    pub error: String,
+}
+
+impl FailoverAttempt {
+    pub fn new(e: &LlmError) -> Self {
+        Self {
+            provider: e.provider.clone(),
+            kind: e.kind,
+            error: e.to_string(),
+        }
+    }
+}
+
+/// Whether a failure with this kind should move on to the next provider.
+pub fn should_failover(kind: LlmErrorKind) -> bool {
+    kind.is_retryable() || matches!(kind, LlmErrorKind::Auth | LlmErrorKind::Config)
+}
+
+/// `primary` (the `[llm]` section in effect, after any routing tier), then one config per other
+/// provider in `base.providers`, with its `[llm.failover.<name>]` settings laid over `base`.
+pub fn chain(base: &LlmConfig, primary: LlmConfig) -> Vec<LlmConfig> {
+    let mut out = vec![primary];
+    for name in &base.providers {
+        if out
+            .iter()
+            .any(|c| c.provider.as_deref() == Some(name.as_str()))
+        {
+            continue;
+        }
+        let tier = ModelTier {
+            provider: Some(name.clone()),
+            ..base.failover.get(name).cloned().unwrap_or_default()
+        };
+        out.push(tier_config(base, &tier));
+    }
+    out
+}
+
+pub fn is_down(provider: &str) -> bool {
+    DOWN.read()
+        .ok()
+        .and_then(|g| g.as_ref()?.get(provider).copied())
+        .is_some_and(|until| Instant::now() < until)
+}
+
+pub fn mark_down(provider: &str, cooldown: Duration) {
+    if let Ok(mut g) = DOWN.write() {
+        g.get_or_insert_with(HashMap::new)
+            .insert(provider.to_string(), Instant::now() + cooldown);
+    }
+}
+
+pub fn mark_up(provider: &str) {
+    if let Ok(mut g) = DOWN.write() {
+        if let Some(m) = g.as_mut() {
+            m.remove(provider);
+        }
+    }
+}
+
+/// `chain` with providers on cooldown moved to the end (in chain order otherwise).
+pub fn order(chain: Vec<LlmConfig>) -> Vec<LlmConfig> {
+    let (up, down): (Vec<_>, Vec<_>) = chain
+        .into_iter()
+        .partition(|c| !is_down(c.provider.as_deref().unwrap_or_default()));
+    up.into_iter().chain(down).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_skips_primary_and_orders_cooled_down_last() {
+        let base = LlmConfig {
+            provider: Some("anthropic".into()),
+            model: Some("claude-sonnet-4".into()),
+            providers: vec!["anthropic".into(), "openai".into(), "ollama".into()],
+            failover: HashMap::from([(
+                "ollama".to_string(),
+                ModelTier {
+                    model: Some("qwen2.5-coder:7b".into()),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        let c = chain(&base, base.clone());
+        let names: Vec<_> = c.iter().map(|c| c.provider.clone().unwrap()).collect();
+        assert_eq!(names, ["anthropic", "openai", "ollama"]);
+        assert!(c[1].model.is_none());
+        assert_eq!(c[2].model.as_deref(), Some("qwen2.5-coder:7b"));
+
+        mark_down("openai", Duration::from_secs(60));
+        mark_down("anthropic", Duration::ZERO);
+        let names: Vec<_> = order(c)
+            .iter()
+            .map(|c| c.provider.clone().unwrap())
+            .collect();
+        assert_eq!(names, ["anthropic", "ollama", "openai"]);
+        mark_up("openai");
+        assert!(!is_down("openai"));
+
+        assert!(should_failover(LlmErrorKind::RateLimited));
+        assert!(should_failover(LlmErrorKind::Auth));
+        assert!(!should_failover(LlmErrorKind::ContextLength));
+    }
+}
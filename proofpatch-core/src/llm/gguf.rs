@@ -0,0 +1,264 @@
+//! In-process inference on a GGUF model through llama.cpp (cargo feature `gguf`).
+//!
+//! No daemon and no network: the model file is loaded into this process (once per path, shared
+//! by every `GgufProvider` on it) and each chat runs in a fresh context on a blocking thread.
+//! With the default temperature of 0 sampling is greedy, so the same prompt gives the same
+//! completion, which is what CI fixtures need. The llama.cpp calls live in `gguf_shim.c`;
+//! `build.rs` compiles it against `LLAMA_CPP_DIR` (or `LLAMA_CPP_INCLUDE_DIR` /
+//! `LLAMA_CPP_LIB_DIR`) and links `libllama`.
+
+use super::provider::{
+    ChatFuture, ChatRequest, ChatResponse, LlmError, LlmErrorKind, Provider, Usage,
+};
+use crate::config::LlmConfig;
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+const DEFAULT_CONTEXT: u32 = 4096;
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+#[repr(C)]
+struct PpGguf {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    fn pp_gguf_load(path: *const c_char, n_ctx: i32, n_gpu_layers: i32) -> *mut PpGguf;
+    fn pp_gguf_free(h: *mut PpGguf);
+    fn pp_gguf_string_free(s: *mut c_char);
+    #[allow(clippy::too_many_arguments)]
+    fn pp_gguf_chat(
+        h: *mut PpGguf,
+        roles: *const *const c_char,
+        contents: *const *const c_char,
+        n: usize,
+        max_tokens: i32,
+        temperature: f32,
+        seed: u32,
+        out: *mut *mut c_char,
+        n_prompt: *mut i32,
+        n_gen: *mut i32,
+    ) -> i32;
+}
+
+struct Model(*mut PpGguf);
+
+// The handle is only used under the `Mutex` below; llama.cpp models may move between threads.
+unsafe impl Send for Model {}
+
+impl Drop for Model {
+    fn drop(&mut self) {
+        unsafe { pp_gguf_free(self.0) }
+    }
+}
+
+type Loaded = Arc<Mutex<Model>>;
+
+fn loaded() -> &'static Mutex<HashMap<(PathBuf, u32), Loaded>> {
+    static MODELS: OnceLock<Mutex<HashMap<(PathBuf, u32), Loaded>>> = OnceLock::new();
+    MODELS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn err(kind: LlmErrorKind, message: impl Into<String>) -> LlmError {
+    LlmError {
+        kind,
+        provider: "gguf".to_string(),
+        status: None,
+        message: message.into(),
+    }
+}
+
+#[derive(Clone)]
+pub struct GgufProvider {
+    pub path: PathBuf,
+    /// File stem of `path`, reported as the model name.
+    pub model: String,
+    pub context_size: u32,
+    pub seed: u32,
+    handle: Loaded,
+}
+
+impl GgufProvider {
+    /// Load `path` (or reuse it if this process already has it loaded with the same context size).
+    pub fn load(
+        path: &Path,
+        context_size: u32,
+        gpu_layers: u32,
+        seed: u32,
+    ) -> Result<Self, LlmError> {
+        if !path.is_file() {
+            return Err(err(
+                LlmErrorKind::Config,
+                format!("GGUF model not found: {}", path.display()),
+            ));
+        }
+        let key = (path.to_path_buf(), context_size);
+        let mut cache = loaded()
+            .lock()
+            .map_err(|_| err(LlmErrorKind::Config, "model cache poisoned"))?;
+        let handle = match cache.get(&key) {
+            Some(h) => h.clone(),
+            None => {
+                let c = CString::new(path.display().to_string())
+                    .map_err(|e| err(LlmErrorKind::Config, format!("model path: {e}")))?;
+                let h = unsafe { pp_gguf_load(c.as_ptr(), context_size as i32, gpu_layers as i32) };
+                if h.is_null() {
+                    return Err(err(
+                        LlmErrorKind::Config,
+                        format!("llama.cpp could not load {}", path.display()),
+                    ));
+                }
+                let h = Arc::new(Mutex::new(Model(h)));
+                cache.insert(key, h.clone());
+                h
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            model: path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            context_size,
+            seed,
+            handle,
+        })
+    }
+
+    /// `[llm] provider = "gguf"`: `model` is the path of the `.gguf` file.
+    pub fn from_config(cfg: &LlmConfig) -> Result<Self, LlmError> {
+        let path = cfg
+            .model
+            .as_deref()
+            .filter(|m| !m.trim().is_empty())
+            .ok_or_else(|| {
+                err(
+                    LlmErrorKind::Config,
+                    "[llm] provider = \"gguf\" needs model = \"<path to .gguf>\"",
+                )
+            })?;
+        Self::load(
+            Path::new(path),
+            cfg.context_size.unwrap_or(DEFAULT_CONTEXT),
+            cfg.gpu_layers.unwrap_or(0),
+            cfg.seed.unwrap_or(0),
+        )
+    }
+}
+
+fn generate(
+    handle: &Loaded,
+    messages: &[(String, String)],
+    max_tokens: u32,
+    temperature: f32,
+    seed: u32,
+) -> Result<(String, Usage), LlmError> {
+    let cstr = |s: &str| {
+        CString::new(s.replace('\0', ""))
+            .map_err(|e| err(LlmErrorKind::InvalidRequest, e.to_string()))
+    };
+    let roles = messages
+        .iter()
+        .map(|(r, _)| cstr(r))
+        .collect::<Result<Vec<_>, _>>()?;
+    let contents = messages
+        .iter()
+        .map(|(_, c)| cstr(c))
+        .collect::<Result<Vec<_>, _>>()?;
+    let role_ptrs: Vec<*const c_char> = roles.iter().map(|c| c.as_ptr()).collect();
+    let content_ptrs: Vec<*const c_char> = contents.iter().map(|c| c.as_ptr()).collect();
+    let model = handle
+        .lock()
+        .map_err(|_| err(LlmErrorKind::Server, "model lock poisoned"))?;
+    let mut out: *mut c_char = std::ptr::null_mut();
+    let (mut n_prompt, mut n_gen) = (0i32, 0i32);
+    let rc = unsafe {
+        pp_gguf_chat(
+            model.0,
+            role_ptrs.as_ptr(),
+            content_ptrs.as_ptr(),
+            role_ptrs.len(),
+            max_tokens as i32,
+            temperature,
+            seed,
+            &mut out,
+            &mut n_prompt,
+            &mut n_gen,
+        )
+    };
+    if rc != 0 {
+        let (kind, what) = match rc {
+            -1 => (
+                LlmErrorKind::Config,
+                "the model has no usable chat template",
+            ),
+            -2 => (LlmErrorKind::Server, "could not create a llama.cpp context"),
+            -3 => (LlmErrorKind::Decode, "tokenization failed"),
+            -4 => (
+                LlmErrorKind::ContextLength,
+                "prompt plus max_tokens exceeds [llm] context_size",
+            ),
+            -5 => (LlmErrorKind::Server, "llama_decode failed"),
+            _ => (LlmErrorKind::Server, "out of memory"),
+        };
+        return Err(err(kind, what));
+    }
+    let text = unsafe {
+        let s = CStr::from_ptr(out).to_string_lossy().to_string();
+        pp_gguf_string_free(out);
+        s
+    };
+    Ok((
+        text,
+        Usage {
+            input_tokens: n_prompt.max(0) as u64,
+            output_tokens: n_gen.max(0) as u64,
+            ..Default::default()
+        },
+    ))
+}
+
+impl Provider for GgufProvider {
+    fn name(&self) -> &str {
+        "gguf"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let mut messages = vec![("system".to_string(), req.system.clone())];
+            messages.extend(
+                req.messages
+                    .iter()
+                    .map(|m| (m.role.clone(), m.content.clone())),
+            );
+            let handle = self.handle.clone();
+            let max_tokens = req.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
+            // Deterministic unless a temperature is asked for.
+            let temperature = req.temperature.unwrap_or(0.0);
+            let seed = req.seed.unwrap_or(self.seed);
+            let job = tokio::task::spawn_blocking(move || {
+                generate(&handle, &messages, max_tokens, temperature, seed)
+            });
+            let (content, usage) = tokio::time::timeout(req.timeout, job)
+                .await
+                .map_err(|_| err(LlmErrorKind::Timeout, "generation timed out"))?
+                .map_err(|e| err(LlmErrorKind::Server, format!("generation task: {e}")))??;
+            Ok(ChatResponse {
+                provider: "gguf".to_string(),
+                model: self.model.clone(),
+                content,
+                usage,
+                raw: serde_json::json!({ "path": self.path.display().to_string() }),
+                stopped_early: false,
+                logprobs: None,
+                tool_calls: Vec::new(),
+            })
+        })
+    }
+}
@@ -0,0 +1,173 @@
+// Thin C layer over llama.cpp for `llm::gguf` (cargo feature `gguf`).
+//
+// llama.cpp's API passes parameter structs by value and changes between releases; keeping those
+// calls here means the Rust side only sees plain pointers and integers.
+
+#include "llama.h"
+
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct pp_gguf {
+    struct llama_model *model;
+    int32_t n_ctx;
+} pp_gguf;
+
+static int backend_ready = 0;
+
+pp_gguf *pp_gguf_load(const char *path, int32_t n_ctx, int32_t n_gpu_layers) {
+    if (!backend_ready) {
+        llama_backend_init();
+        backend_ready = 1;
+    }
+    struct llama_model_params mp = llama_model_default_params();
+    mp.n_gpu_layers = n_gpu_layers;
+    struct llama_model *model = llama_model_load_from_file(path, mp);
+    if (!model) {
+        return NULL;
+    }
+    pp_gguf *h = malloc(sizeof *h);
+    if (!h) {
+        llama_model_free(model);
+        return NULL;
+    }
+    h->model = model;
+    h->n_ctx = n_ctx;
+    return h;
+}
+
+void pp_gguf_free(pp_gguf *h) {
+    if (!h) {
+        return;
+    }
+    llama_model_free(h->model);
+    free(h);
+}
+
+void pp_gguf_string_free(char *s) { free(s); }
+
+// Apply the model's chat template to `n` messages and generate up to `max_tokens` tokens in a
+// fresh context. `temperature <= 0` samples greedily (deterministic); otherwise `seed` fixes the
+// sampler. On success `*out` is a NUL-terminated string to release with `pp_gguf_string_free`.
+//
+// Returns 0, or: -1 chat template, -2 context creation, -3 tokenization, -4 prompt does not fit
+// the context, -5 decode, -6 out of memory.
+int32_t pp_gguf_chat(pp_gguf *h, const char **roles, const char **contents, size_t n,
+                     int32_t max_tokens, float temperature, uint32_t seed, char **out,
+                     int32_t *n_prompt, int32_t *n_gen) {
+    const struct llama_vocab *vocab = llama_model_get_vocab(h->model);
+
+    struct llama_chat_message *msgs = malloc((n ? n : 1) * sizeof *msgs);
+    if (!msgs) {
+        return -6;
+    }
+    for (size_t i = 0; i < n; i++) {
+        msgs[i].role = roles[i];
+        msgs[i].content = contents[i];
+    }
+    const char *tmpl = llama_model_chat_template(h->model, NULL);
+    int32_t len = llama_chat_apply_template(tmpl, msgs, n, true, NULL, 0);
+    if (len < 0) {
+        free(msgs);
+        return -1;
+    }
+    char *prompt = malloc((size_t)len + 1);
+    if (!prompt) {
+        free(msgs);
+        return -6;
+    }
+    llama_chat_apply_template(tmpl, msgs, n, true, prompt, len + 1);
+    prompt[len] = '\0';
+    free(msgs);
+
+    int32_t n_tok = -llama_tokenize(vocab, prompt, len, NULL, 0, true, true);
+    if (n_tok <= 0) {
+        free(prompt);
+        return -3;
+    }
+    llama_token *toks = malloc((size_t)n_tok * sizeof *toks);
+    if (!toks) {
+        free(prompt);
+        return -6;
+    }
+    if (llama_tokenize(vocab, prompt, len, toks, n_tok, true, true) < 0) {
+        free(prompt);
+        free(toks);
+        return -3;
+    }
+    free(prompt);
+    if (n_tok + max_tokens > h->n_ctx) {
+        free(toks);
+        return -4;
+    }
+
+    struct llama_context_params cp = llama_context_default_params();
+    cp.n_ctx = (uint32_t)h->n_ctx;
+    cp.n_batch = (uint32_t)h->n_ctx;
+    struct llama_context *ctx = llama_init_from_model(h->model, cp);
+    if (!ctx) {
+        free(toks);
+        return -2;
+    }
+
+    struct llama_sampler *smpl = llama_sampler_chain_init(llama_sampler_chain_default_params());
+    if (temperature <= 0.0f) {
+        llama_sampler_chain_add(smpl, llama_sampler_init_greedy());
+    } else {
+        llama_sampler_chain_add(smpl, llama_sampler_init_temp(temperature));
+        llama_sampler_chain_add(smpl, llama_sampler_init_dist(seed));
+    }
+
+    size_t cap = 1024, used = 0;
+    char *buf = malloc(cap);
+    int32_t rc = buf ? 0 : -6;
+    int32_t generated = 0;
+    llama_token next = 0;
+    struct llama_batch batch = llama_batch_get_one(toks, n_tok);
+    while (rc == 0 && generated < max_tokens) {
+        if (llama_decode(ctx, batch) != 0) {
+            rc = -5;
+            break;
+        }
+        next = llama_sampler_sample(smpl, ctx, -1);
+        if (llama_vocab_is_eog(vocab, next)) {
+            break;
+        }
+        char piece[256];
+        int32_t k = llama_token_to_piece(vocab, next, piece, sizeof piece, 0, false);
+        if (k < 0) {
+            rc = -3;
+            break;
+        }
+        if (used + (size_t)k + 1 > cap) {
+            while (used + (size_t)k + 1 > cap) {
+                cap *= 2;
+            }
+            char *grown = realloc(buf, cap);
+            if (!grown) {
+                rc = -6;
+                break;
+            }
+            buf = grown;
+        }
+        memcpy(buf + used, piece, (size_t)k);
+        used += (size_t)k;
+        generated++;
+        batch = llama_batch_get_one(&next, 1);
+    }
+
+    llama_sampler_free(smpl);
+    llama_free(ctx);
+    free(toks);
+    if (rc != 0) {
+        free(buf);
+        return rc;
+    }
+    buf[used] = '\0';
+    *out = buf;
+    *n_prompt = n_tok;
+    *n_gen = generated;
+    return 0;
+}
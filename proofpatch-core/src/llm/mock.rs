@@ -0,0 +1,340 @@
+//! Fixture-backed mock provider (`[llm] provider = "mock"`), for deterministic tests without
+//! network access or API keys.
+//!
+//! Replies come from scenario files: `[llm] fixtures` (or `PROOFPATCH_MOCK_FIXTURES`) names a
+//! `.toml` file or a directory of them. Each `[[response]]` matches a request by the SHA-256 of
+//! its prompt (`prompt_hash`) or by substrings of it, and answers with its `replies` in turn
+//! (the last one repeats; the count is process-wide, since a provider is built per request),
+//! calls its `tool_calls` (when the request offers tools, `llm::tools`), or fails with `error`:
+//!
+//! ```toml
+//! name = "add_zero"
+//! default = "by simp"            # for requests no response matches (optional)
+//!
+//! [[response]]
+//! contains = ["theorem add_zero'"]
+//! replies = ["by rfl", "by simp"]
+//!
+//! [[response]]
+//! contains = ["search it"]
+//! tool_calls = [{ name = "search_mathlib", arguments = { query = "add zero" } }]
+//!
+//! [[response]]
+//! prompt_sha256 = "3f1c…"
+//! error = "rate_limited"
+//! ```
+//!
+//! A request that matches nothing, without a `default`, fails with `InvalidRequest` and its
+//! prompt hash, so a fixture for it can be added. Token usage is the character estimate, so cost
+//! accounting is deterministic too.
+
+use super::provider::{
+    ChatFuture, ChatRequest, ChatResponse, LlmError, LlmErrorKind, Provider, ToolCall, Usage,
+};
+use crate::config::LlmConfig;
+use crate::prompt_context::estimate_tokens;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Calls answered so far, by (fixture source, index into `responses`).
+static CALLS: Mutex<Option<HashMap<(String, usize), usize>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockResponse {
+    /// `prompt_hash` of the request.
+    #[serde(default)]
+    pub prompt_sha256: Option<String>,
+    /// Substrings that must all occur in the system prompt or a message.
+    #[serde(default)]
+    pub contains: Vec<String>,
+    /// Successive replies; the last one repeats.
+    #[serde(default)]
+    pub replies: Vec<String>,
+    /// Tool calls made along with the reply (ids default to `call_<n>`).
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCall>,
+    /// Fail with this error kind (`rate_limited`, `timeout`, …) instead of replying.
+    #[serde(default)]
+    pub error: Option<LlmErrorKind>,
+}
+
+/// One scenario file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub response: Vec<MockResponse>,
+}
+
+#[derive(Debug, Default)]
+pub struct MockProvider {
+    pub model: String,
+    pub responses: Vec<MockResponse>,
+    pub default: Option<String>,
+    /// Where the scenarios came from; keys the reply counts in `CALLS`.
+    source: String,
+}
+
+/// SHA-256 (hex) of the system prompt and every message, role included.
+pub fn prompt_hash(req: &ChatRequest) -> String {
+    use sha2::{Digest, Sha256};
+    let mut h = Sha256::new();
+    h.update(req.system.as_bytes());
+    for m in &req.messages {
+        h.update([0u8]);
+        h.update(m.role.as_bytes());
+        h.update([0u8]);
+        h.update(m.content.as_bytes());
+    }
+    format!("{:x}", h.finalize())
+}
+
+impl MockProvider {
+    pub fn new(scenarios: Vec<Scenario>) -> Self {
+        let default = scenarios.iter().find_map(|s| s.default.clone());
+        let source = scenarios
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        Self {
+            model: "mock".to_string(),
+            responses: scenarios.into_iter().flat_map(|s| s.response).collect(),
+            default,
+            source,
+        }
+    }
+
+    /// Scenarios from a `.toml` file, or every `.toml` file in a directory (in name order).
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut files = Vec::new();
+        if path.is_dir() {
+            for e in std::fs::read_dir(path).map_err(|e| format!("{}: {e}", path.display()))? {
+                let p = e.map_err(|e| e.to_string())?.path();
+                if p.extension().is_some_and(|x| x == "toml") {
+                    files.push(p);
+                }
+            }
+            files.sort();
+        } else {
+            files.push(path.to_path_buf());
+        }
+        let mut scenarios = Vec::new();
+        for f in files {
+            let txt = std::fs::read_to_string(&f).map_err(|e| format!("{}: {e}", f.display()))?;
+            let mut s: Scenario =
+                toml::from_str(&txt).map_err(|e| format!("{}: {e}", f.display()))?;
+            if s.name.is_empty() {
+                s.name = f
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string();
+            }
+            scenarios.push(s);
+        }
+        Ok(Self {
+            source: path.display().to_string(),
+            ..Self::new(scenarios)
+        })
+    }
+
+    pub fn from_config(cfg: &LlmConfig) -> Result<Self, LlmError> {
+        let path = std::env::var("PROOFPATCH_MOCK_FIXTURES")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .or_else(|| cfg.fixtures.clone())
+            .ok_or_else(|| {
+                LlmError::new(
+                    LlmErrorKind::Config,
+                    "mock",
+                    "[llm] provider = \"mock\" needs `fixtures` (or PROOFPATCH_MOCK_FIXTURES)",
+                )
+            })?;
+        let mut p = Self::load(Path::new(&path))
+            .map_err(|e| LlmError::new(LlmErrorKind::Config, "mock", e))?;
+        if let Some(m) = cfg.model.clone().filter(|m| !m.trim().is_empty()) {
+            p.model = m;
+        }
+        Ok(p)
+    }
+
+    /// The response for `req`: by prompt hash first, then by substrings.
+    fn find(&self, req: &ChatRequest, hash: &str) -> Option<usize> {
+        let by_hash = self
+            .responses
+            .iter()
+            .position(|r| r.prompt_sha256.as_deref() == Some(hash));
+        by_hash.or_else(|| {
+            self.responses.iter().position(|r| {
+                !r.contains.is_empty()
+                    && r.contains.iter().all(|s| {
+                        req.system.contains(s.as_str())
+                            || req.messages.iter().any(|m| m.content.contains(s.as_str()))
+                    })
+            })
+        })
+    }
+
+    fn reply(&self, req: &ChatRequest) -> Result<(String, Vec<ToolCall>), LlmError> {
+        let hash = prompt_hash(req);
+        let Some(i) = self.find(req, &hash) else {
+            return self
+                .default
+                .clone()
+                .map(|d| (d, Vec::new()))
+                .ok_or_else(|| {
+                    LlmError::new(
+                        LlmErrorKind::InvalidRequest,
+                        "mock",
+                        format!("no mock fixture matches prompt_sha256 = \"{hash}\""),
+                    )
+                });
+        };
+        let r = &self.responses[i];
+        if let Some(kind) = r.error {
+            return Err(LlmError::new(kind, "mock", "mock fixture error"));
+        }
+        let n = {
+            let mut g = CALLS.lock().unwrap_or_else(|e| e.into_inner());
+            let count = g
+                .get_or_insert_with(HashMap::new)
+                .entry((self.source.clone(), i))
+                .or_default();
+            *count += 1;
+            *count - 1
+        };
+        let content = r
+            .replies
+            .get(n)
+            .or(r.replies.last())
+            .cloned()
+            .unwrap_or_default();
+        let calls = match req.tools.is_empty() {
+            true => Vec::new(),
+            false => (r.tool_calls.iter().enumerate())
+                .map(|(k, c)| ToolCall {
+                    id: match c.id.is_empty() {
+                        true => format!("call_{k}"),
+                        false => c.id.clone(),
+                    },
+                    ..c.clone()
+                })
+                .collect(),
+        };
+        Ok((content, calls))
+    }
+}
+
+impl Provider for MockProvider {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let (content, tool_calls) = self.reply(req)?;
+            let prompt: usize = estimate_tokens(&req.system)
+                + req
+                    .messages
+                    .iter()
+                    .map(|m| estimate_tokens(&m.content))
+                    .sum::<usize>();
+            Ok(ChatResponse {
+                provider: "mock".to_string(),
+                model: self.model.clone(),
+                usage: Usage {
+                    input_tokens: prompt as u64,
+                    output_tokens: estimate_tokens(&content) as u64,
+                    ..Default::default()
+                },
+                raw: serde_json::json!({ "mock": true }),
+                content,
+                stopped_early: false,
+                logprobs: None,
+                tool_calls,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn replays_fixtures_through_chat_completion() {
+        let td = tempfile::tempdir().unwrap();
+        std::fs::write(
+            td.path().join("add_zero.toml"),
+            "[[response]]\ncontains = [\"theorem add_zero'\"]\n\
+             replies = [\"by rfl\", \"```lean\\nby simp\\n```\"]\n\n\
+             [[response]]\ncontains = [\"flaky\"]\nerror = \"rate_limited\"\n",
+        )
+        .unwrap();
+        let cfg = LlmConfig {
+            provider: Some("mock".into()),
+            fixtures: Some(td.path().display().to_string()),
+            ..Default::default()
+        };
+        let opts = super::super::ChatOptions {
+            no_cache: true,
+            ..Default::default()
+        };
+        let ask = |user: &'static str| {
+            let (cfg, opts) = (cfg.clone(), opts.clone());
+            async move {
+                super::super::complete_once("sys", user, Duration::from_secs(5), &opts, Some(cfg))
+                    .await
+            }
+        };
+        let prompt = "theorem add_zero' (n : Nat) : n + 0 = n := by\n  sorry";
+        let r = ask(prompt).await.ok().unwrap();
+        assert_eq!(
+            (r.provider.as_str(), r.content.as_str()),
+            ("mock", "by rfl")
+        );
+        assert!(r.usage.input_tokens > 0 && !r.usage.estimated);
+        let r = ask(prompt).await.ok().unwrap();
+        assert_eq!(
+            crate::repair::candidates_from_llm_reply(&r.content),
+            ["by simp"]
+        );
+        // The last reply repeats.
+        assert!(ask(prompt).await.ok().unwrap().content.contains("by simp"));
+
+        let p = MockProvider::from_config(&cfg).unwrap();
+        let e = p
+            .reply(&ChatRequest::simple("s", "flaky", Duration::ZERO))
+            .unwrap_err();
+        assert_eq!(e.kind, LlmErrorKind::RateLimited);
+        let req = ChatRequest::simple("s", "unknown", Duration::ZERO);
+        let e = p.reply(&req).unwrap_err();
+        assert!(e.message.contains(&prompt_hash(&req)));
+        let by_hash = MockProvider::new(vec![Scenario {
+            response: vec![MockResponse {
+                prompt_sha256: Some(prompt_hash(&req)),
+                replies: vec!["exact rfl".into()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }]);
+        assert_eq!(by_hash.reply(&req).unwrap().0, "exact rfl");
+    }
+}
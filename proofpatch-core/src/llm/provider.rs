@@ -0,0 +1,2035 @@
+//! Provider-neutral chat interface and the HTTP backends behind it.
+//!
+//! `Provider` is one chat-completion call plus the provider's name and model; every backend
+//! reports token usage the same way (`Usage`) and maps HTTP/transport failures onto one
+//! taxonomy (`LlmErrorKind`), so callers can decide about retries without knowing the API.
+//! Backends:
+//! - `OpenAiProvider`: `POST <base>/chat/completions` (OpenAI and any compatible server), or an
+//!   Azure OpenAI deployment (`<endpoint>/openai/deployments/<name>/chat/completions?api-version=…`,
+//!   `api-key` or Azure AD bearer auth);
+//! - `AnthropicProvider`: `POST <base>/v1/messages` (`x-api-key`, `anthropic-version`);
+//! - `GeminiProvider`: `POST <base>/models/<model>:generateContent` (`x-goog-api-key`);
+//! - `bedrock::BedrockProvider`: AWS Bedrock `InvokeModel` (SigV4; Claude and Llama models);
+//! - `OllamaProvider`: `POST <base>/api/chat` on a local daemon (no key; nothing leaves the machine);
+//! - `gguf::GgufProvider`: a GGUF model loaded in-process by llama.cpp (cargo feature `gguf`).
+//!
+//! `Provider::probe` checks that the backend can serve the model before a run starts.
+//! `from_config` builds one from the `[llm]` section of `proofpatch.toml`.
+
+use crate::config::LlmConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+pub const PROVIDERS: &[&str] = &[
+    "openai",
+    "azure",
+    "anthropic",
+    "gemini",
+    "bedrock",
+    "ollama",
+    "gguf",
+];
+
+/// Azure OpenAI GA API version used when none is configured.
+pub const AZURE_API_VERSION: &str = "2024-10-21";
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const DEFAULT_TEMPERATURE: f32 = 0.2;
+/// Anthropic requires `max_tokens`; the others only send it when configured.
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LlmErrorKind {
+    /// Missing or rejected credentials (401/403).
+    Auth,
+    /// 429 / quota exhausted.
+    RateLimited,
+    /// The prompt does not fit the model's context window.
+    ContextLength,
+    /// Any other 4xx: bad model name, malformed request, …
+    InvalidRequest,
+    /// 5xx, including overloaded (529).
+    Server,
+    Timeout,
+    /// Connection failures before a response arrived.
+    Network,
+    /// The response is not the shape the backend expects.
+    Decode,
+    /// The provider cannot be built (unknown name, missing key or model).
+    Config,
+}
+
+impl LlmErrorKind {
+    /// Worth trying again later (or with another provider) unchanged.
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited | Self::Server | Self::Timeout | Self::Network
+        )
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Auth => "auth",
+            Self::RateLimited => "rate_limited",
+            Self::ContextLength => "context_length",
+            Self::InvalidRequest => "invalid_request",
+            Self::Server => "server",
+            Self::Timeout => "timeout",
+            Self::Network => "network",
+            Self::Decode => "decode",
+            Self::Config => "config",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmError {
+    pub kind: LlmErrorKind,
+    pub provider: String,
+    pub status: Option<u16>,
+    pub message: String,
+}
+
+impl LlmError {
+    pub(super) fn new(kind: LlmErrorKind, provider: &str, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            provider: provider.to_string(),
+            status: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for LlmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.status {
+            Some(s) => write!(
+                f,
+                "provider {} returned {s} ({:?}): {}",
+                self.provider, self.kind, self.message
+            ),
+            None => write!(
+                f,
+                "provider {} ({:?}): {}",
+                self.provider, self.kind, self.message
+            ),
+        }
+    }
+}
+
+impl From<LlmError> for String {
+    fn from(e: LlmError) -> Self {
+        e.to_string()
+    }
+}
+
+/// Map an HTTP error status (and the error body) onto the taxonomy.
+pub fn classify_status(status: u16, body: &str) -> LlmErrorKind {
+    let lower = body.to_lowercase();
+    let context = [
+        "context length",
+        "context_length",
+        "maximum context",
+        "too long",
+        "too many tokens",
+    ]
+    .iter()
+    .any(|m| lower.contains(m));
+    match status {
+        401 | 403 => LlmErrorKind::Auth,
+        429 => LlmErrorKind::RateLimited,
+        408 | 504 => LlmErrorKind::Timeout,
+        400..=499 if context => LlmErrorKind::ContextLength,
+        400..=499 => LlmErrorKind::InvalidRequest,
+        _ => LlmErrorKind::Server,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatMessage {
+    /// `user`, `assistant`, or `tool` (the result of a tool call).
+    pub role: String,
+    pub content: String,
+    /// `assistant` turns: the tools the model called (`ChatRequest::tools`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// `tool` turns: the `ToolCall::id` this is the result of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    /// An assistant turn that called `calls` (with any text it wrote alongside).
+    pub fn assistant_tool_calls(content: impl Into<String>, calls: Vec<ToolCall>) -> Self {
+        Self {
+            tool_calls: calls,
+            ..Self::assistant(content)
+        }
+    }
+
+    /// The result of tool call `id`.
+    pub fn tool_result(id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: "tool".to_string(),
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: Some(id.into()),
+        }
+    }
+}
+
+/// A function the model may call (`ChatRequest::tools`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolSpec {
+    /// `[a-zA-Z0-9_-]`.
+    pub name: String,
+    pub description: String,
+    /// JSON Schema of the arguments object.
+    pub parameters: Value,
+}
+
+/// A tool call in a model's reply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Backend-assigned; the result is sent back under it (`ChatMessage::tool_result`).
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub system: String,
+    pub messages: Vec<ChatMessage>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Sampling seed, for backends that take one (OpenAI-compatible, Ollama, GGUF).
+    pub seed: Option<u32>,
+    /// Constrain the reply to this schema. Only set it when `Provider::supports_schema`.
+    pub response_schema: Option<ResponseSchema>,
+    /// Byte length of a prefix of the last message that repeats across requests (e.g. the
+    /// declaration context before the error history): a prompt-cache breakpoint for backends
+    /// with prompt caching (Anthropic).
+    pub cache_prefix: Option<usize>,
+    /// Ask for the log probability of each generated token. Only set it when
+    /// `Provider::supports_logprobs`.
+    pub logprobs: bool,
+    /// Tools the model may call instead of answering; the calls come back in
+    /// `ChatResponse::tool_calls`. Only set it when `Provider::supports_tools`, and not together
+    /// with `response_schema`.
+    pub tools: Vec<ToolSpec>,
+    pub timeout: Duration,
+}
+
+/// A JSON Schema the reply must satisfy: OpenAI structured outputs (`response_format`), a forced
+/// Anthropic tool call (whose input becomes the reply text), or Ollama's `format`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseSchema {
+    /// Schema / tool name (`[a-zA-Z0-9_-]`).
+    pub name: String,
+    pub schema: Value,
+}
+
+impl ResponseSchema {
+    /// The schema of `T` (schemars), made strict: every property required and no additional
+    /// properties, as OpenAI's strict mode demands.
+    pub fn of<T: schemars::JsonSchema>(name: &str) -> Self {
+        let mut schema = serde_json::to_value(schemars::schema_for!(T)).unwrap_or(Value::Null);
+        if let Some(o) = schema.as_object_mut() {
+            o.remove("$schema");
+        }
+        make_strict(&mut schema);
+        Self {
+            name: name.to_string(),
+            schema,
+        }
+    }
+}
+
+fn make_strict(v: &mut Value) {
+    match v {
+        Value::Object(o) => {
+            if o.get("type").and_then(Value::as_str) == Some("object") {
+                let keys: Vec<Value> = o
+                    .get("properties")
+                    .and_then(Value::as_object)
+                    .map(|p| p.keys().map(|k| json!(k)).collect())
+                    .unwrap_or_default();
+                o.insert("required".to_string(), Value::Array(keys));
+                o.insert("additionalProperties".to_string(), json!(false));
+            }
+            for (_, x) in o.iter_mut() {
+                make_strict(x);
+            }
+        }
+        Value::Array(xs) => xs.iter_mut().for_each(make_strict),
+        _ => {}
+    }
+}
+
+impl ChatRequest {
+    /// One system prompt and one user message (what `llm::chat_completion` sends).
+    pub fn simple(system: &str, user: &str, timeout: Duration) -> Self {
+        Self {
+            system: system.to_string(),
+            messages: vec![ChatMessage::user(user)],
+            temperature: None,
+            max_tokens: None,
+            seed: None,
+            response_schema: None,
+            cache_prefix: None,
+            logprobs: false,
+            tools: Vec::new(),
+            timeout,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    /// Uncached input tokens (Anthropic reports cache reads and writes separately).
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Input tokens read from the provider's prompt cache.
+    #[serde(default)]
+    pub cache_read_tokens: u64,
+    /// Input tokens written to the provider's prompt cache.
+    #[serde(default)]
+    pub cache_write_tokens: u64,
+}
+
+/// One generated token and its log probability (natural log).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+}
+
+/// Token logprobs in a response body or stream event: OpenAI's `choices[0].logprobs.content`
+/// or Gemini's `candidates[0].logprobsResult.chosenCandidates`.
+pub fn token_logprobs(v: &Value) -> Option<Vec<TokenLogprob>> {
+    let (items, key) = match v.pointer("/choices/0/logprobs/content") {
+        Some(xs) => (xs, "logprob"),
+        None => (
+            v.pointer("/candidates/0/logprobsResult/chosenCandidates")?,
+            "logProbability",
+        ),
+    };
+    items
+        .as_array()?
+        .iter()
+        .map(|t| {
+            Some(TokenLogprob {
+                token: t.get("token")?.as_str()?.to_string(),
+                logprob: t.get(key)?.as_f64()?,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatResponse {
+    pub provider: String,
+    pub model: String,
+    pub content: String,
+    pub usage: Usage,
+    /// The provider's response body, unchanged (for a stream, `{"stream": true, "events": n}`).
+    pub raw: Value,
+    /// `chat_stream` was cancelled by its callback before the model finished.
+    #[serde(default)]
+    pub stopped_early: bool,
+    /// Per-token log probabilities, when `ChatRequest::logprobs` was set and the backend
+    /// returned them.
+    #[serde(default)]
+    pub logprobs: Option<Vec<TokenLogprob>>,
+    /// Tools the model called (`ChatRequest::tools`); `content` is then any text written
+    /// alongside.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+}
+
+pub type ChatFuture<'a> = Pin<Box<dyn Future<Output = Result<ChatResponse, LlmError>> + Send + 'a>>;
+pub type ProbeFuture<'a> = Pin<Box<dyn Future<Output = Result<(), LlmError>> + Send + 'a>>;
+/// Receives each piece of streamed text; `false` cancels the rest of the generation.
+pub type OnDelta<'a> = &'a mut (dyn FnMut(&str) -> bool + Send);
+
+pub trait Provider: Send + Sync {
+    fn name(&self) -> &str;
+    fn model(&self) -> &str;
+    fn chat<'a>(&'a self, req: &'a ChatRequest) -> ChatFuture<'a>;
+
+    /// Whether the backend is reachable and serves `model()`. Hosted APIs are assumed available
+    /// (their failures surface on the first `chat`).
+    fn probe(&self, _timeout: Duration) -> ProbeFuture<'_> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Whether `ChatRequest::response_schema` is enforced by the backend. Callers leave it unset
+    /// otherwise and parse the free-form reply instead.
+    fn supports_schema(&self) -> bool {
+        false
+    }
+
+    /// Whether the backend can return token logprobs (`ChatRequest::logprobs`).
+    fn supports_logprobs(&self) -> bool {
+        false
+    }
+
+    /// Whether `chat` honors `ChatRequest::tools` (`llm::tools`). Streaming may ignore them.
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    /// The backend's batch API (`batch`), for backends that have one.
+    fn batch_api(&self) -> Option<super::batch::BatchApi> {
+        None
+    }
+
+    /// `req` as the body of one batch request (`batch`; `None` without `batch_api`).
+    fn batch_body(&self, _req: &ChatRequest) -> Option<Value> {
+        None
+    }
+
+    /// `chat`, handing text to `on_delta` as it is generated. Backends without streaming call it
+    /// once with the whole reply.
+    fn chat_stream<'a>(&'a self, req: &'a ChatRequest, on_delta: OnDelta<'a>) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let r = self.chat(req).await?;
+            on_delta(&r.content);
+            Ok(r)
+        })
+    }
+}
+
+/// `chat_stream` that stops as soon as a complete JSON value or closed code fence has arrived
+/// (`json_extract::StreamingJsonExtractor`); the content is cut after it.
+pub async fn chat_until_complete(
+    p: &dyn Provider,
+    req: &ChatRequest,
+) -> Result<ChatResponse, LlmError> {
+    let mut x = crate::json_extract::StreamingJsonExtractor::new();
+    let mut on_delta = |d: &str| x.push(d).is_none();
+    let mut r = p.chat_stream(req, &mut on_delta).await?;
+    if let Some(end) = x.end() {
+        r.content.truncate(end.min(r.content.len()));
+    }
+    Ok(r)
+}
+
+/// Send the request; a non-2xx status becomes an error carrying the response body.
+async fn send(
+    provider: &str,
+    url: &str,
+    headers: &[(&str, String)],
+    body: &Value,
+    timeout: Duration,
+) -> Result<reqwest::Response, LlmError> {
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| {
+            LlmError::new(
+                LlmErrorKind::Config,
+                provider,
+                format!("http client build: {e}"),
+            )
+        })?;
+    let mut rb = client.post(url).json(body);
+    for (k, v) in headers {
+        rb = rb.header(*k, v);
+    }
+    let resp = rb.send().await.map_err(|e| {
+        let kind = if e.is_timeout() {
+            LlmErrorKind::Timeout
+        } else {
+            LlmErrorKind::Network
+        };
+        LlmError::new(kind, provider, format!("http request failed: {e}"))
+    })?;
+    let status = resp.status().as_u16();
+    if !(200..300).contains(&status) {
+        let text = resp.text().await.unwrap_or_default();
+        return Err(LlmError {
+            kind: classify_status(status, &text),
+            provider: provider.to_string(),
+            status: Some(status),
+            message: text,
+        });
+    }
+    Ok(resp)
+}
+
+fn read_error(provider: &str, e: reqwest::Error) -> LlmError {
+    let kind = if e.is_timeout() {
+        LlmErrorKind::Timeout
+    } else {
+        LlmErrorKind::Network
+    };
+    LlmError::new(kind, provider, format!("http body read: {e}"))
+}
+
+pub(super) async fn post_json(
+    provider: &str,
+    url: &str,
+    headers: &[(&str, String)],
+    body: &Value,
+    timeout: Duration,
+) -> Result<Value, LlmError> {
+    let resp = send(provider, url, headers, body, timeout).await?;
+    let text = resp.text().await.map_err(|e| read_error(provider, e))?;
+    serde_json::from_str(&text).map_err(|e| {
+        LlmError::new(
+            LlmErrorKind::Decode,
+            provider,
+            format!("http json decode: {e}"),
+        )
+    })
+}
+
+/// Splits a streamed body into JSON events: server-sent events (`data: {...}` lines; `[DONE]`
+/// and other fields skipped) or newline-delimited JSON.
+#[derive(Debug)]
+pub struct StreamDecoder {
+    sse: bool,
+    pending: Vec<u8>,
+}
+
+impl StreamDecoder {
+    pub fn sse() -> Self {
+        Self {
+            sse: true,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn ndjson() -> Self {
+        Self {
+            sse: false,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Events completed by `chunk` (`None`: end of body, flush the last line).
+    pub fn feed(&mut self, chunk: Option<&[u8]>) -> Result<Vec<Value>, String> {
+        match chunk {
+            Some(c) => self.pending.extend_from_slice(c),
+            None if !self.pending.is_empty() => self.pending.push(b'\n'),
+            None => {}
+        }
+        let mut out = Vec::new();
+        while let Some(nl) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=nl).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim();
+            let data = if self.sse {
+                match line.strip_prefix("data:") {
+                    Some(d) => d.trim(),
+                    None => continue,
+                }
+            } else {
+                line
+            };
+            if data.is_empty() || data == "[DONE]" {
+                continue;
+            }
+            out.push(serde_json::from_str(data).map_err(|e| format!("stream event: {e}"))?);
+        }
+        Ok(out)
+    }
+}
+
+/// Send a streaming request and fold its events: `on_event` returns the text each event adds
+/// (recording usage as it goes), which is passed on to `on_delta`.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn stream_chat(
+    provider: &str,
+    model: &str,
+    url: &str,
+    headers: &[(&str, String)],
+    body: &Value,
+    timeout: Duration,
+    mut decoder: StreamDecoder,
+    mut on_event: impl FnMut(&Value, &mut Usage) -> Result<Option<String>, LlmError>,
+    on_delta: OnDelta<'_>,
+) -> Result<ChatResponse, LlmError> {
+    let mut resp = send(provider, url, headers, body, timeout).await?;
+    let mut content = String::new();
+    let mut usage = Usage::default();
+    let mut events = 0usize;
+    let mut stopped_early = false;
+    let mut logprobs: Option<Vec<TokenLogprob>> = None;
+    'read: loop {
+        let chunk = resp.chunk().await.map_err(|e| read_error(provider, e))?;
+        let done = chunk.is_none();
+        let batch = decoder
+            .feed(chunk.as_deref())
+            .map_err(|e| LlmError::new(LlmErrorKind::Decode, provider, e))?;
+        for v in batch {
+            events += 1;
+            if let Some(lp) = token_logprobs(&v) {
+                logprobs.get_or_insert_with(Vec::new).extend(lp);
+            }
+            let Some(delta) = on_event(&v, &mut usage)? else {
+                continue;
+            };
+            if delta.is_empty() {
+                continue;
+            }
+            content.push_str(&delta);
+            if !on_delta(&delta) {
+                // Dropping the response closes the connection, which ends generation.
+                stopped_early = true;
+                break 'read;
+            }
+        }
+        if done {
+            break;
+        }
+    }
+    Ok(ChatResponse {
+        provider: provider.to_string(),
+        model: model.to_string(),
+        content,
+        usage,
+        raw: json!({ "stream": true, "events": events }),
+        stopped_early,
+        logprobs,
+        tool_calls: Vec::new(),
+    })
+}
+
+pub(super) fn u64_at(v: &Value, path: &str) -> u64 {
+    v.pointer(path).and_then(Value::as_u64).unwrap_or(0)
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenAiProvider {
+    /// Shown in results and errors (`openai`, or e.g. `groq` for a compatible server).
+    pub name: String,
+    /// Up to and including the version segment, e.g. `https://api.openai.com/v1`.
+    pub base_url: String,
+    /// Sent as `Authorization: Bearer` (unused with `azure`).
+    pub api_key: Option<String>,
+    /// For Azure, the deployment name.
+    pub model: String,
+    /// Address an Azure OpenAI deployment; `base_url` is then the resource endpoint
+    /// (`https://<resource>.openai.azure.com`).
+    pub azure: Option<AzureDeployment>,
+    /// Sent with every request (e.g. OpenRouter's `HTTP-Referer` / `X-Title`).
+    pub extra_headers: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AzureDeployment {
+    pub api_version: String,
+    pub auth: AzureAuth,
+}
+
+#[derive(Debug, Clone)]
+pub enum AzureAuth {
+    /// Resource key, sent as `api-key`.
+    ApiKey(String),
+    /// Microsoft Entra ID (Azure AD) access token, sent as `Authorization: Bearer`.
+    AdToken(String),
+}
+
+impl OpenAiProvider {
+    pub fn endpoint(&self) -> String {
+        match &self.azure {
+            Some(az) => format!(
+                "{}/openai/deployments/{}/chat/completions?api-version={}",
+                self.base_url, self.model, az.api_version
+            ),
+            None => format!("{}/chat/completions", self.base_url),
+        }
+    }
+
+    pub fn auth_headers(&self) -> Vec<(&'static str, String)> {
+        match (&self.azure, &self.api_key) {
+            (Some(az), _) => match &az.auth {
+                AzureAuth::ApiKey(k) => vec![("api-key", k.clone())],
+                AzureAuth::AdToken(t) => vec![("authorization", format!("Bearer {t}"))],
+            },
+            (None, Some(k)) => vec![("authorization", format!("Bearer {k}"))],
+            (None, None) => Vec::new(),
+        }
+    }
+
+    fn headers(&self) -> Vec<(&str, String)> {
+        let mut h: Vec<(&str, String)> = self.auth_headers();
+        h.extend(
+            self.extra_headers
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.clone())),
+        );
+        h
+    }
+
+    /// Text and usage carried by one `chat.completion.chunk`.
+    pub fn stream_event(v: &Value, usage: &mut Usage) -> Result<Option<String>, LlmError> {
+        if v.get("usage").is_some_and(|u| !u.is_null()) {
+            usage.input_tokens = u64_at(v, "/usage/prompt_tokens");
+            usage.output_tokens = u64_at(v, "/usage/completion_tokens");
+        }
+        Ok(v.pointer("/choices/0/delta/content")
+            .and_then(Value::as_str)
+            .map(str::to_string))
+    }
+
+    pub fn request_body(&self, req: &ChatRequest) -> Value {
+        let mut messages = vec![json!({ "role": "system", "content": req.system })];
+        messages.extend(req.messages.iter().map(|m| {
+            let mut msg = json!({ "role": m.role, "content": m.content });
+            if let Some(id) = &m.tool_call_id {
+                msg["tool_call_id"] = json!(id);
+            }
+            if !m.tool_calls.is_empty() {
+                msg["tool_calls"] = m
+                    .tool_calls
+                    .iter()
+                    .map(|c| {
+                        json!({
+                            "id": c.id,
+                            "type": "function",
+                            "function": { "name": c.name, "arguments": c.arguments.to_string() },
+                        })
+                    })
+                    .collect();
+            }
+            msg
+        }));
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "temperature": req.temperature.unwrap_or(DEFAULT_TEMPERATURE),
+        });
+        if !req.tools.is_empty() {
+            body["tools"] = req
+                .tools
+                .iter()
+                .map(|t| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": t.name,
+                            "description": t.description,
+                            "parameters": t.parameters,
+                        },
+                    })
+                })
+                .collect();
+        }
+        if let Some(n) = req.max_tokens {
+            body["max_tokens"] = json!(n);
+        }
+        if let Some(seed) = req.seed {
+            body["seed"] = json!(seed);
+        }
+        if req.logprobs {
+            body["logprobs"] = json!(true);
+        }
+        if let Some(rs) = &req.response_schema {
+            body["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": { "name": rs.name, "schema": rs.schema, "strict": true },
+            });
+        }
+        body
+    }
+
+    pub fn parse_response(&self, raw: &Value) -> Result<(String, Usage), LlmError> {
+        let content = raw
+            .pointer("/choices/0/message")
+            .ok_or_else(|| {
+                LlmError::new(
+                    LlmErrorKind::Decode,
+                    &self.name,
+                    "missing choices[0].message",
+                )
+            })?
+            .get("content")
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_string();
+        let usage = Usage {
+            input_tokens: u64_at(raw, "/usage/prompt_tokens"),
+            output_tokens: u64_at(raw, "/usage/completion_tokens"),
+            ..Default::default()
+        };
+        Ok((content, usage))
+    }
+
+    /// `choices[0].message.tool_calls`; arguments that are not valid JSON are kept as a string.
+    pub fn tool_calls(raw: &Value) -> Vec<ToolCall> {
+        let Some(calls) = raw
+            .pointer("/choices/0/message/tool_calls")
+            .and_then(Value::as_array)
+        else {
+            return Vec::new();
+        };
+        calls
+            .iter()
+            .filter_map(|c| {
+                let args = c.pointer("/function/arguments")?;
+                Some(ToolCall {
+                    id: c.get("id")?.as_str()?.to_string(),
+                    name: c.pointer("/function/name")?.as_str()?.to_string(),
+                    arguments: match args.as_str() {
+                        Some(a) => serde_json::from_str(a).unwrap_or_else(|_| json!(a)),
+                        None => args.clone(),
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+impl Provider for OpenAiProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// OpenAI and Azure honor `response_format: json_schema`; compatible servers vary, so they
+    /// get the free-form path.
+    fn supports_schema(&self) -> bool {
+        self.name == "openai" || self.azure.is_some()
+    }
+
+    /// Likewise `logprobs`: some compatible servers (Groq) reject it.
+    fn supports_logprobs(&self) -> bool {
+        self.name == "openai" || self.azure.is_some()
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    /// OpenAI's Batch API; Azure and compatible servers are not supported.
+    fn batch_api(&self) -> Option<super::batch::BatchApi> {
+        (self.name == "openai" && self.azure.is_none()).then(|| super::batch::BatchApi {
+            kind: super::batch::BatchKind::Openai,
+            base_url: self.base_url.clone(),
+            headers: self.auth_headers(),
+        })
+    }
+
+    fn batch_body(&self, req: &ChatRequest) -> Option<Value> {
+        self.batch_api().map(|_| self.request_body(req))
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let headers = self.headers();
+            let raw = post_json(
+                &self.name,
+                &self.endpoint(),
+                &headers,
+                &self.request_body(req),
+                req.timeout,
+            )
+            .await?;
+            let (content, usage) = self.parse_response(&raw)?;
+            Ok(ChatResponse {
+                provider: self.name.clone(),
+                model: self.model.clone(),
+                content,
+                usage,
+                logprobs: token_logprobs(&raw),
+                tool_calls: Self::tool_calls(&raw),
+                raw,
+                stopped_early: false,
+            })
+        })
+    }
+
+    fn chat_stream<'a>(&'a self, req: &'a ChatRequest, on_delta: OnDelta<'a>) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let mut body = self.request_body(req);
+            body["stream"] = json!(true);
+            // Not every compatible server knows `stream_options`; OpenAI and Azure report usage
+            // only with it.
+            if self.name == "openai" || self.azure.is_some() {
+                body["stream_options"] = json!({ "include_usage": true });
+            }
+            stream_chat(
+                &self.name,
+                &self.model,
+                &self.endpoint(),
+                &self.headers(),
+                &body,
+                req.timeout,
+                StreamDecoder::sse(),
+                Self::stream_event,
+                on_delta,
+            )
+            .await
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnthropicProvider {
+    /// Without the version segment, e.g. `https://api.anthropic.com`.
+    pub base_url: String,
+    pub api_key: String,
+    pub model: String,
+    /// Mark stable prefixes with `cache_control` breakpoints (`[llm] prompt_cache`).
+    pub prompt_cache: bool,
+}
+
+impl AnthropicProvider {
+    pub fn request_body(&self, req: &ChatRequest) -> Value {
+        let mut body = json!({
+            "model": self.model,
+            "system": req.system,
+            "messages": messages_api_turns(&req.messages),
+            "temperature": req.temperature.unwrap_or(DEFAULT_TEMPERATURE),
+            "max_tokens": req.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        });
+        if self.prompt_cache {
+            mark_cache_breakpoints(&mut body, req);
+        }
+        if !req.tools.is_empty() {
+            body["tools"] = req
+                .tools
+                .iter()
+                .map(|t| {
+                    json!({
+                        "name": t.name,
+                        "description": t.description,
+                        "input_schema": t.parameters,
+                    })
+                })
+                .collect();
+        }
+        // A forced tool call: its `input` is the structured reply.
+        if let Some(rs) = &req.response_schema {
+            body["tools"] = json!([{
+                "name": rs.name,
+                "description": "Submit the answer.",
+                "input_schema": rs.schema,
+            }]);
+            body["tool_choice"] = json!({ "type": "tool", "name": rs.name });
+        }
+        body
+    }
+
+    pub fn parse_response(&self, raw: &Value) -> Result<(String, Usage), LlmError> {
+        parse_messages_response("anthropic", raw)
+    }
+
+    fn headers(&self) -> [(&'static str, String); 2] {
+        [
+            ("x-api-key", self.api_key.clone()),
+            ("anthropic-version", ANTHROPIC_VERSION.to_string()),
+        ]
+    }
+
+    /// Text and usage carried by one messages-API stream event.
+    pub fn stream_event(v: &Value, usage: &mut Usage) -> Result<Option<String>, LlmError> {
+        match v.get("type").and_then(Value::as_str) {
+            Some("message_start") => {
+                usage.input_tokens = u64_at(v, "/message/usage/input_tokens");
+                usage.cache_read_tokens = u64_at(v, "/message/usage/cache_read_input_tokens");
+                usage.cache_write_tokens = u64_at(v, "/message/usage/cache_creation_input_tokens");
+                Ok(None)
+            }
+            Some("content_block_delta") => Ok(v
+                .pointer("/delta/text")
+                .or_else(|| v.pointer("/delta/partial_json"))
+                .and_then(Value::as_str)
+                .map(str::to_string)),
+            Some("message_delta") => {
+                usage.output_tokens = u64_at(v, "/usage/output_tokens");
+                Ok(None)
+            }
+            Some("error") => {
+                let kind = match v.pointer("/error/type").and_then(Value::as_str) {
+                    Some("rate_limit_error") => LlmErrorKind::RateLimited,
+                    Some("invalid_request_error") => LlmErrorKind::InvalidRequest,
+                    Some("authentication_error" | "permission_error") => LlmErrorKind::Auth,
+                    _ => LlmErrorKind::Server,
+                };
+                let msg = v
+                    .pointer("/error/message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("stream error");
+                Err(LlmError::new(kind, "anthropic", msg))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// `messages` in the messages-API shape: tool calls become `tool_use` blocks of the assistant
+/// turn, and consecutive tool results one user turn of `tool_result` blocks.
+fn messages_api_turns(messages: &[ChatMessage]) -> Vec<Value> {
+    let mut out: Vec<Value> = Vec::new();
+    for m in messages {
+        if let Some(id) = &m.tool_call_id {
+            let block = json!({ "type": "tool_result", "tool_use_id": id, "content": m.content });
+            match out.last_mut().and_then(|t| {
+                let results = t["content"]
+                    .as_array()
+                    .is_some_and(|bs| bs.iter().all(|b| b["type"] == "tool_result"));
+                (t["role"] == "user" && results).then(|| t["content"].as_array_mut())?
+            }) {
+                Some(blocks) => blocks.push(block),
+                None => out.push(json!({ "role": "user", "content": [block] })),
+            }
+        } else if !m.tool_calls.is_empty() {
+            let mut blocks: Vec<Value> = Vec::new();
+            if !m.content.is_empty() {
+                blocks.push(json!({ "type": "text", "text": m.content }));
+            }
+            blocks.extend(m.tool_calls.iter().map(
+                |c| json!({ "type": "tool_use", "id": c.id, "name": c.name, "input": c.arguments }),
+            ));
+            out.push(json!({ "role": "assistant", "content": blocks }));
+        } else {
+            out.push(json!({ "role": m.role, "content": m.content }));
+        }
+    }
+    out
+}
+
+/// `tool_use` blocks of a messages-API response.
+pub(super) fn messages_tool_calls(raw: &Value) -> Vec<ToolCall> {
+    raw.get("content")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|b| b["type"] == "tool_use")
+        .filter_map(|b| {
+            Some(ToolCall {
+                id: b.get("id")?.as_str()?.to_string(),
+                name: b.get("name")?.as_str()?.to_string(),
+                arguments: b.get("input").cloned().unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Prompt-cache breakpoints (at most 3 of the API's 4): the system prompt, the last earlier turn
+/// (so a conversation's history is read from the cache on the next turn), and
+/// `ChatRequest::cache_prefix` of the new message. Prefixes shorter than the model's minimum
+/// (1024 tokens for most models) are simply not cached.
+fn mark_cache_breakpoints(body: &mut Value, req: &ChatRequest) {
+    let cached =
+        |text: &str| json!({"type": "text", "text": text, "cache_control": {"type": "ephemeral"}});
+    if !req.system.is_empty() {
+        body["system"] = json!([cached(&req.system)]);
+    }
+    let Some(msgs) = body["messages"].as_array_mut() else {
+        return;
+    };
+    let n = msgs.len();
+    if n >= 2 {
+        match msgs[n - 2]["content"].as_str().map(str::to_string) {
+            Some(text) => msgs[n - 2]["content"] = json!([cached(&text)]),
+            // Tool-call turns are already blocks: mark the last one.
+            None => {
+                if let Some(b) = msgs[n - 2]["content"]
+                    .as_array_mut()
+                    .and_then(|bs| bs.last_mut())
+                {
+                    b["cache_control"] = json!({"type": "ephemeral"});
+                }
+            }
+        }
+    }
+    if let (Some(last), Some(k)) = (msgs.last_mut(), req.cache_prefix) {
+        let text = last["content"].as_str().unwrap_or_default().to_string();
+        if k > 0 && k <= text.len() && text.is_char_boundary(k) {
+            let (head, tail) = text.split_at(k);
+            let mut blocks = vec![cached(head)];
+            if !tail.is_empty() {
+                blocks.push(json!({"type": "text", "text": tail}));
+            }
+            last["content"] = json!(blocks);
+        }
+    }
+}
+
+/// Text and usage of an Anthropic messages-API response (also what Bedrock returns for Claude).
+pub(super) fn parse_messages_response(
+    provider: &str,
+    raw: &Value,
+) -> Result<(String, Usage), LlmError> {
+    let blocks = raw
+        .get("content")
+        .and_then(Value::as_array)
+        .ok_or_else(|| LlmError::new(LlmErrorKind::Decode, provider, "missing content"))?;
+    // Text blocks, or the input of a (forced) tool call as JSON text.
+    let content = blocks
+        .iter()
+        .filter_map(|b| match b.get("type").and_then(Value::as_str) {
+            Some("text") => b.get("text").and_then(Value::as_str).map(str::to_string),
+            Some("tool_use") => b.get("input").map(Value::to_string),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("");
+    let usage = Usage {
+        input_tokens: u64_at(raw, "/usage/input_tokens"),
+        output_tokens: u64_at(raw, "/usage/output_tokens"),
+        cache_read_tokens: u64_at(raw, "/usage/cache_read_input_tokens"),
+        cache_write_tokens: u64_at(raw, "/usage/cache_creation_input_tokens"),
+    };
+    Ok((content, usage))
+}
+
+impl Provider for AnthropicProvider {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn supports_schema(&self) -> bool {
+        true
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn batch_api(&self) -> Option<super::batch::BatchApi> {
+        Some(super::batch::BatchApi {
+            kind: super::batch::BatchKind::Anthropic,
+            base_url: self.base_url.clone(),
+            headers: self.headers().to_vec(),
+        })
+    }
+
+    fn batch_body(&self, req: &ChatRequest) -> Option<Value> {
+        Some(self.request_body(req))
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let url = format!("{}/v1/messages", self.base_url);
+            let raw = post_json(
+                "anthropic",
+                &url,
+                &self.headers(),
+                &self.request_body(req),
+                req.timeout,
+            )
+            .await?;
+            let (mut content, usage) = self.parse_response(&raw)?;
+            let tool_calls = if req.tools.is_empty() {
+                Vec::new()
+            } else {
+                messages_tool_calls(&raw)
+            };
+            if !tool_calls.is_empty() {
+                // Only the text blocks: the calls' inputs are not part of the reply.
+                content = raw["content"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|b| b.get("text").and_then(Value::as_str))
+                    .collect();
+            }
+            Ok(ChatResponse {
+                provider: "anthropic".to_string(),
+                model: self.model.clone(),
+                content,
+                usage,
+                raw,
+                stopped_early: false,
+                logprobs: None,
+                tool_calls,
+            })
+        })
+    }
+
+    fn chat_stream<'a>(&'a self, req: &'a ChatRequest, on_delta: OnDelta<'a>) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let mut body = self.request_body(req);
+            body["stream"] = json!(true);
+            stream_chat(
+                "anthropic",
+                &self.model,
+                &format!("{}/v1/messages", self.base_url),
+                &self.headers(),
+                &body,
+                req.timeout,
+                StreamDecoder::sse(),
+                Self::stream_event,
+                on_delta,
+            )
+            .await
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GeminiProvider {
+    /// Up to and including the version segment, e.g.
+    /// `https://generativelanguage.googleapis.com/v1beta`.
+    pub base_url: String,
+    pub api_key: String,
+    pub model: String,
+}
+
+impl GeminiProvider {
+    pub fn request_body(&self, req: &ChatRequest) -> Value {
+        let contents: Vec<Value> = req
+            .messages
+            .iter()
+            .map(|m| {
+                let role = if m.role == "assistant" {
+                    "model"
+                } else {
+                    "user"
+                };
+                json!({ "role": role, "parts": [{ "text": m.content }] })
+            })
+            .collect();
+        let mut generation =
+            json!({ "temperature": req.temperature.unwrap_or(DEFAULT_TEMPERATURE) });
+        if let Some(n) = req.max_tokens {
+            generation["maxOutputTokens"] = json!(n);
+        }
+        if req.logprobs {
+            generation["responseLogprobs"] = json!(true);
+        }
+        json!({
+            "systemInstruction": { "parts": [{ "text": req.system }] },
+            "contents": contents,
+            "generationConfig": generation,
+        })
+    }
+
+    pub fn parse_response(&self, raw: &Value) -> Result<(String, Usage), LlmError> {
+        let parts = raw
+            .pointer("/candidates/0/content/parts")
+            .and_then(Value::as_array)
+            .ok_or_else(|| {
+                // A blocked prompt comes back 200 with no candidates.
+                let why = raw
+                    .pointer("/promptFeedback/blockReason")
+                    .and_then(Value::as_str)
+                    .map(|r| format!("prompt blocked: {r}"))
+                    .unwrap_or_else(|| "missing candidates[0].content.parts".to_string());
+                LlmError::new(LlmErrorKind::Decode, "gemini", why)
+            })?;
+        let content = parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("");
+        let usage = Usage {
+            input_tokens: u64_at(raw, "/usageMetadata/promptTokenCount"),
+            output_tokens: u64_at(raw, "/usageMetadata/candidatesTokenCount"),
+            ..Default::default()
+        };
+        Ok((content, usage))
+    }
+
+    /// Each streamed event is a partial `GenerateContentResponse`; usage is cumulative.
+    pub fn stream_event(v: &Value, usage: &mut Usage) -> Result<Option<String>, LlmError> {
+        if v.get("usageMetadata").is_some() {
+            usage.input_tokens = u64_at(v, "/usageMetadata/promptTokenCount");
+            usage.output_tokens = u64_at(v, "/usageMetadata/candidatesTokenCount");
+        }
+        Ok(v.pointer("/candidates/0/content/parts")
+            .and_then(Value::as_array)
+            .map(|ps| {
+                ps.iter()
+                    .filter_map(|p| p.get("text").and_then(Value::as_str))
+                    .collect::<String>()
+            }))
+    }
+}
+
+impl Provider for GeminiProvider {
+    fn name(&self) -> &str {
+        "gemini"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn supports_logprobs(&self) -> bool {
+        true
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let headers = [("x-goog-api-key", self.api_key.clone())];
+            let url = format!("{}/models/{}:generateContent", self.base_url, self.model);
+            let raw = post_json(
+                "gemini",
+                &url,
+                &headers,
+                &self.request_body(req),
+                req.timeout,
+            )
+            .await?;
+            let (content, usage) = self.parse_response(&raw)?;
+            Ok(ChatResponse {
+                provider: "gemini".to_string(),
+                model: self.model.clone(),
+                content,
+                usage,
+                logprobs: token_logprobs(&raw),
+                tool_calls: Vec::new(),
+                raw,
+                stopped_early: false,
+            })
+        })
+    }
+
+    fn chat_stream<'a>(&'a self, req: &'a ChatRequest, on_delta: OnDelta<'a>) -> ChatFuture<'a> {
+        Box::pin(async move {
+            stream_chat(
+                "gemini",
+                &self.model,
+                &format!(
+                    "{}/models/{}:streamGenerateContent?alt=sse",
+                    self.base_url, self.model
+                ),
+                &[("x-goog-api-key", self.api_key.clone())],
+                &self.request_body(req),
+                req.timeout,
+                StreamDecoder::sse(),
+                Self::stream_event,
+                on_delta,
+            )
+            .await
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OllamaProvider {
+    /// The daemon, e.g. `http://localhost:11434` (`OLLAMA_HOST`).
+    pub base_url: String,
+    pub model: String,
+}
+
+impl OllamaProvider {
+    pub fn request_body(&self, req: &ChatRequest) -> Value {
+        let mut messages = vec![json!({ "role": "system", "content": req.system })];
+        messages.extend(
+            req.messages
+                .iter()
+                .map(|m| json!({ "role": m.role, "content": m.content })),
+        );
+        let mut options = json!({ "temperature": req.temperature.unwrap_or(DEFAULT_TEMPERATURE) });
+        if let Some(n) = req.max_tokens {
+            options["num_predict"] = json!(n);
+        }
+        if let Some(seed) = req.seed {
+            options["seed"] = json!(seed);
+        }
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "stream": false,
+            "options": options,
+        });
+        if let Some(rs) = &req.response_schema {
+            body["format"] = rs.schema.clone();
+        }
+        body
+    }
+
+    pub fn parse_response(&self, raw: &Value) -> Result<(String, Usage), LlmError> {
+        let content = raw
+            .pointer("/message/content")
+            .and_then(Value::as_str)
+            .ok_or_else(|| {
+                LlmError::new(LlmErrorKind::Decode, "ollama", "missing message.content")
+            })?
+            .to_string();
+        let usage = Usage {
+            input_tokens: u64_at(raw, "/prompt_eval_count"),
+            output_tokens: u64_at(raw, "/eval_count"),
+            ..Default::default()
+        };
+        Ok((content, usage))
+    }
+
+    /// Models pulled into the daemon (`GET /api/tags`).
+    pub async fn list_models(&self, timeout: Duration) -> Result<Vec<String>, LlmError> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| {
+                LlmError::new(
+                    LlmErrorKind::Config,
+                    "ollama",
+                    format!("http client build: {e}"),
+                )
+            })?;
+        let resp = client
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await
+            .map_err(|e| self.unreachable(&e))?;
+        let status = resp.status().as_u16();
+        let raw: Value = resp.json().await.map_err(|e| {
+            LlmError::new(
+                LlmErrorKind::Decode,
+                "ollama",
+                format!("http json decode: {e}"),
+            )
+        })?;
+        if !(200..300).contains(&status) {
+            return Err(LlmError {
+                kind: classify_status(status, &raw.to_string()),
+                provider: "ollama".to_string(),
+                status: Some(status),
+                message: raw.to_string(),
+            });
+        }
+        Ok(model_names(&raw))
+    }
+
+    fn unreachable(&self, e: &reqwest::Error) -> LlmError {
+        let kind = if e.is_timeout() {
+            LlmErrorKind::Timeout
+        } else {
+            LlmErrorKind::Network
+        };
+        LlmError::new(
+            kind,
+            "ollama",
+            format!(
+                "no Ollama daemon answering at {} (start it with `ollama serve`, or set OLLAMA_HOST / [llm] base_url): {e}",
+                self.base_url
+            ),
+        )
+    }
+
+    /// Say what to do when the daemon is down or the model is missing.
+    fn explain(&self, e: LlmError) -> LlmError {
+        match (e.kind, e.status) {
+            (LlmErrorKind::Network, _) => LlmError {
+                message: format!(
+                    "no Ollama daemon answering at {} (start it with `ollama serve`): {}",
+                    self.base_url, e.message
+                ),
+                ..e
+            },
+            (_, Some(404)) => self.not_pulled(),
+            _ => e,
+        }
+    }
+
+    /// One line of an `/api/chat` stream (usage arrives with `done`).
+    pub fn stream_event(v: &Value, usage: &mut Usage) -> Result<Option<String>, LlmError> {
+        if let Some(e) = v.get("error").and_then(Value::as_str) {
+            return Err(LlmError::new(LlmErrorKind::Server, "ollama", e));
+        }
+        if v.get("done").and_then(Value::as_bool) == Some(true) {
+            usage.input_tokens = u64_at(v, "/prompt_eval_count");
+            usage.output_tokens = u64_at(v, "/eval_count");
+        }
+        Ok(v.pointer("/message/content")
+            .and_then(Value::as_str)
+            .map(str::to_string))
+    }
+
+    fn not_pulled(&self) -> LlmError {
+        LlmError::new(
+            LlmErrorKind::Config,
+            "ollama",
+            format!(
+                "model {:?} is not available locally (run `ollama pull {}`)",
+                self.model, self.model
+            ),
+        )
+    }
+}
+
+/// `models[].name` of an `/api/tags` response.
+pub fn model_names(tags: &Value) -> Vec<String> {
+    tags.get("models")
+        .and_then(Value::as_array)
+        .map(|ms| {
+            ms.iter()
+                .filter_map(|m| m.get("name").and_then(Value::as_str))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether `model` is among `available`; an untagged name matches its `:latest` tag.
+pub fn has_model(available: &[String], model: &str) -> bool {
+    let want = if model.contains(':') {
+        model.to_string()
+    } else {
+        format!("{model}:latest")
+    };
+    available.iter().any(|m| m == model || *m == want)
+}
+
+impl Provider for OllamaProvider {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn supports_schema(&self) -> bool {
+        true
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let url = format!("{}/api/chat", self.base_url);
+            let raw = post_json("ollama", &url, &[], &self.request_body(req), req.timeout)
+                .await
+                .map_err(|e| self.explain(e))?;
+            let (content, usage) = self.parse_response(&raw)?;
+            Ok(ChatResponse {
+                provider: "ollama".to_string(),
+                model: self.model.clone(),
+                content,
+                usage,
+                raw,
+                stopped_early: false,
+                logprobs: None,
+                tool_calls: Vec::new(),
+            })
+        })
+    }
+
+    fn chat_stream<'a>(&'a self, req: &'a ChatRequest, on_delta: OnDelta<'a>) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let mut body = self.request_body(req);
+            body["stream"] = json!(true);
+            stream_chat(
+                "ollama",
+                &self.model,
+                &format!("{}/api/chat", self.base_url),
+                &[],
+                &body,
+                req.timeout,
+                StreamDecoder::ndjson(),
+                Self::stream_event,
+                on_delta,
+            )
+            .await
+            .map_err(|e| self.explain(e))
+        })
+    }
+
+    fn probe(&self, timeout: Duration) -> ProbeFuture<'_> {
+        Box::pin(async move {
+            let models = self.list_models(timeout).await?;
+            if has_model(&models, &self.model) {
+                Ok(())
+            } else {
+                Err(self.not_pulled())
+            }
+        })
+    }
+}
+
+fn default_model(provider: &str) -> &'static str {
+    match provider {
+        "anthropic" => "claude-3-5-haiku-latest",
+        "gemini" => "gemini-2.0-flash",
+        _ => "gpt-4o-mini",
+    }
+}
+
+fn env_or(configured: Option<&str>, key: &str) -> Option<String> {
+    configured
+        .map(str::to_string)
+        .or_else(|| std::env::var(key).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// `[llm] provider = "azure"`: `base_url` is the resource endpoint (`AZURE_OPENAI_ENDPOINT`),
+/// `model` the deployment (`AZURE_OPENAI_DEPLOYMENT`), `api_version` the REST version
+/// (`OPENAI_API_VERSION`, default `AZURE_API_VERSION`). Auth is the key in `api_key_env`
+/// (`AZURE_OPENAI_API_KEY`), else an Azure AD token from `azure_ad_token_env`
+/// (`AZURE_OPENAI_AD_TOKEN`).
+pub fn azure_from_config(cfg: &LlmConfig) -> Result<OpenAiProvider, LlmError> {
+    let err = |msg: String| LlmError::new(LlmErrorKind::Config, "azure", msg);
+    let base_url = env_or(cfg.base_url.as_deref(), "AZURE_OPENAI_ENDPOINT")
+        .ok_or_else(|| err("missing [llm] base_url (or AZURE_OPENAI_ENDPOINT)".to_string()))?;
+    let deployment = env_or(cfg.model.as_deref(), "AZURE_OPENAI_DEPLOYMENT").ok_or_else(|| {
+        err("missing [llm] model = \"<deployment>\" (or AZURE_OPENAI_DEPLOYMENT)".to_string())
+    })?;
+    let api_version = env_or(cfg.api_version.as_deref(), "OPENAI_API_VERSION")
+        .unwrap_or_else(|| AZURE_API_VERSION.to_string());
+    let key_env = cfg.api_key_env.as_deref().unwrap_or("AZURE_OPENAI_API_KEY");
+    let token_env = cfg
+        .azure_ad_token_env
+        .as_deref()
+        .unwrap_or("AZURE_OPENAI_AD_TOKEN");
+    let auth = match (env_or(None, key_env), env_or(None, token_env)) {
+        (Some(k), _) => AzureAuth::ApiKey(k),
+        (None, Some(t)) => AzureAuth::AdToken(t),
+        (None, None) => return Err(err(format!("missing {key_env} (or {token_env})"))),
+    };
+    Ok(OpenAiProvider {
+        name: "azure".to_string(),
+        base_url: base_url.trim_end_matches('/').to_string(),
+        api_key: None,
+        model: deployment,
+        azure: Some(AzureDeployment { api_version, auth }),
+        extra_headers: Vec::new(),
+    })
+}
+
+/// Build the provider named by `[llm] provider`. The key is read from `api_key_env` (or the
+/// provider's usual variable); `model` falls back to a small default.
+pub fn from_config(cfg: &LlmConfig) -> Result<Box<dyn Provider>, LlmError> {
+    let name = cfg
+        .provider
+        .as_deref()
+        .map(|s| s.trim().to_lowercase())
+        .unwrap_or_default();
+    let err = |msg: String| LlmError::new(LlmErrorKind::Config, &name, msg);
+    if name == "gguf" {
+        #[cfg(feature = "gguf")]
+        return super::gguf::GgufProvider::from_config(cfg)
+            .map(|p| Box::new(p) as Box<dyn Provider>);
+        #[cfg(not(feature = "gguf"))]
+        return Err(err(
+            "[llm] provider = \"gguf\" needs proofpatch built with the `gguf` cargo feature"
+                .to_string(),
+        ));
+    }
+    if name == "mock" {
+        return super::mock::MockProvider::from_config(cfg)
+            .map(|p| Box::new(p) as Box<dyn Provider>);
+    }
+    if name == "bedrock" {
+        return super::bedrock::BedrockProvider::from_config(cfg)
+            .map(|p| Box::new(p) as Box<dyn Provider>);
+    }
+    if name == "azure" {
+        return azure_from_config(cfg).map(|p| Box::new(p) as Box<dyn Provider>);
+    }
+    if name == "ollama" {
+        // No built-in model: local installs vary.
+        let model = cfg
+            .model
+            .clone()
+            .filter(|m| !m.trim().is_empty())
+            .ok_or_else(|| err("[llm] provider = \"ollama\" needs a model".to_string()))?;
+        let base_url = cfg
+            .base_url
+            .clone()
+            .or_else(|| std::env::var("OLLAMA_HOST").ok())
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or_else(|| "http://localhost:11434".to_string());
+        return Ok(Box::new(OllamaProvider {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model,
+        }));
+    }
+    let (key_env, base) = match name.as_str() {
+        "openai" => ("OPENAI_API_KEY", "https://api.openai.com/v1"),
+        "anthropic" => ("ANTHROPIC_API_KEY", "https://api.anthropic.com"),
+        "gemini" => (
+            "GEMINI_API_KEY",
+            "https://generativelanguage.googleapis.com/v1beta",
+        ),
+        _ => {
+            return Err(err(format!(
+                "unknown [llm] provider {name:?} (expected one of: {})",
+                PROVIDERS.join(", ")
+            )))
+        }
+    };
+    let key_env = cfg.api_key_env.as_deref().unwrap_or(key_env);
+    let api_key = std::env::var(key_env)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    let base_url = cfg
+        .base_url
+        .as_deref()
+        .unwrap_or(base)
+        .trim_end_matches('/')
+        .to_string();
+    let model = cfg
+        .model
+        .clone()
+        .filter(|m| !m.trim().is_empty())
+        .unwrap_or_else(|| default_model(&name).to_string());
+    Ok(match name.as_str() {
+        // A custom base URL may be a keyless local server.
+        "openai" if api_key.is_none() && cfg.base_url.is_none() => {
+            return Err(err(format!("missing {key_env}")))
+        }
+        "openai" => Box::new(OpenAiProvider {
+            name,
+            base_url,
+            api_key,
+            model,
+            azure: None,
+            extra_headers: Vec::new(),
+        }),
+        "anthropic" => Box::new(AnthropicProvider {
+            base_url,
+            api_key: api_key.ok_or_else(|| err(format!("missing {key_env}")))?,
+            model,
+            prompt_cache: cfg.prompt_cache.unwrap_or(true),
+        }),
+        _ => Box::new(GeminiProvider {
+            base_url,
+            api_key: api_key.ok_or_else(|| err(format!("missing {key_env}")))?,
+            model,
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backends_map_requests_usage_and_errors() {
+        let mut req = ChatRequest::simple("sys", "prove it", Duration::from_secs(1));
+        req.messages.push(ChatMessage::assistant("by simp"));
+
+        let a = AnthropicProvider {
+            base_url: "https://api.anthropic.com".into(),
+            api_key: "k".into(),
+            model: "m".into(),
+            prompt_cache: false,
+        };
+        let body = a.request_body(&req);
+        assert_eq!(body["system"], "sys");
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+        let (text, usage) = a
+            .parse_response(&json!({
+                "content": [{"type": "text", "text": "by "}, {"type": "text", "text": "omega"}],
+                "usage": {"input_tokens": 12, "output_tokens": 3},
+            }))
+            .unwrap();
+        assert_eq!(text, "by omega");
+        assert_eq!(
+            usage,
+            Usage {
+                input_tokens: 12,
+                output_tokens: 3,
+                ..Default::default()
+            }
+        );
+
+        let cached = AnthropicProvider {
+            prompt_cache: true,
+            ..a.clone()
+        };
+        let mut turn = req.clone();
+        turn.messages.push(ChatMessage::user("context\nerrors"));
+        turn.cache_prefix = Some("context\n".len());
+        let body = cached.request_body(&turn);
+        assert_eq!(body["system"][0]["cache_control"]["type"], "ephemeral");
+        assert_eq!(body["messages"][0]["content"], "prove it");
+        assert_eq!(body["messages"][1]["content"][0]["text"], "by simp");
+        assert!(body["messages"][1]["content"][0]["cache_control"].is_object());
+        assert_eq!(body["messages"][2]["content"][0]["text"], "context\n");
+        assert!(body["messages"][2]["content"][0]["cache_control"].is_object());
+        assert_eq!(body["messages"][2]["content"][1]["text"], "errors");
+        assert!(body["messages"][2]["content"][1]
+            .get("cache_control")
+            .is_none());
+        let (_, usage) = cached
+            .parse_response(&json!({
+                "content": [{"type": "text", "text": "rfl"}],
+                "usage": {"input_tokens": 20, "output_tokens": 2,
+                          "cache_read_input_tokens": 1500, "cache_creation_input_tokens": 0},
+            }))
+            .unwrap();
+        assert_eq!((usage.input_tokens, usage.cache_read_tokens), (20, 1500));
+
+        let g = GeminiProvider {
+            base_url: "b".into(),
+            api_key: "k".into(),
+            model: "m".into(),
+        };
+        let body = g.request_body(&req);
+        assert_eq!(body["contents"][1]["role"], "model");
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "sys");
+        let e = g
+            .parse_response(&json!({"promptFeedback": {"blockReason": "SAFETY"}}))
+            .unwrap_err();
+        assert!(e.message.contains("SAFETY"));
+
+        let o = OpenAiProvider {
+            name: "openai".into(),
+            base_url: "b".into(),
+            api_key: None,
+            model: "m".into(),
+            azure: None,
+            extra_headers: Vec::new(),
+        };
+        assert!(o.auth_headers().is_empty());
+        assert_eq!(o.request_body(&req)["messages"][0]["role"], "system");
+        let (_, usage) = o
+            .parse_response(&json!({
+                "choices": [{"message": {"content": "x"}}],
+                "usage": {"prompt_tokens": 5, "completion_tokens": 1},
+            }))
+            .unwrap();
+        assert_eq!(usage.input_tokens, 5);
+        let az = OpenAiProvider {
+            name: "azure".into(),
+            base_url: "https://res.openai.azure.com".into(),
+            api_key: None,
+            model: "gpt4o-prod".into(),
+            azure: Some(AzureDeployment {
+                api_version: AZURE_API_VERSION.into(),
+                auth: AzureAuth::AdToken("tok".into()),
+            }),
+            extra_headers: Vec::new(),
+        };
+        assert_eq!(
+            az.endpoint(),
+            "https://res.openai.azure.com/openai/deployments/gpt4o-prod/chat/completions?api-version=2024-10-21"
+        );
+        assert_eq!(
+            az.auth_headers(),
+            vec![("authorization", "Bearer tok".to_string())]
+        );
+
+        let l = OllamaProvider {
+            base_url: "http://localhost:11434".into(),
+            model: "qwen2.5-coder".into(),
+        };
+        let body = l.request_body(&req);
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["messages"][2]["role"], "assistant");
+        let (text, usage) = l
+            .parse_response(&json!({
+                "message": {"role": "assistant", "content": "by rfl"},
+                "prompt_eval_count": 40, "eval_count": 4,
+            }))
+            .unwrap();
+        assert_eq!((text.as_str(), usage.output_tokens), ("by rfl", 4));
+        let tags = json!({"models": [{"name": "qwen2.5-coder:latest"}, {"name": "llama3.1:8b"}]});
+        let names = model_names(&tags);
+        assert!(has_model(&names, "qwen2.5-coder"));
+        assert!(has_model(&names, "llama3.1:8b"));
+        assert!(!has_model(&names, "llama3.1"));
+
+        // Streams: events split across chunks, usage folded in as it arrives.
+        let mut d = StreamDecoder::sse();
+        let mut evs = d
+            .feed(Some(b"event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":7}}}\n\ndata: {\"type\":\"content_block_delta\",\"del"))
+            .unwrap();
+        evs.extend(
+            d.feed(Some(b"ta\":{\"type\":\"text_delta\",\"text\":\"by \"}}\n"))
+                .unwrap(),
+        );
+        evs.extend(d.feed(None).unwrap());
+        let mut usage = Usage::default();
+        let texts: Vec<_> = evs
+            .iter()
+            .map(|v| AnthropicProvider::stream_event(v, &mut usage).unwrap())
+            .collect();
+        assert_eq!(texts, vec![None, Some("by ".to_string())]);
+        assert_eq!(usage.input_tokens, 7);
+        let mut d = StreamDecoder::ndjson();
+        let evs = d
+            .feed(Some(b"{\"message\":{\"content\":\"x\"},\"done\":false}\n{\"done\":true,\"eval_count\":2}"))
+            .unwrap();
+        assert_eq!(evs.len(), 1);
+        let last = d.feed(None).unwrap();
+        OllamaProvider::stream_event(&last[0], &mut usage).unwrap();
+        assert_eq!(usage.output_tokens, 2);
+        assert_eq!(
+            OpenAiProvider::stream_event(
+                &json!({"choices": [{"delta": {"content": "ring"}}]}),
+                &mut usage
+            )
+            .unwrap()
+            .as_deref(),
+            Some("ring")
+        );
+
+        assert_eq!(classify_status(529, "overloaded"), LlmErrorKind::Server);
+        assert_eq!(
+            classify_status(400, "prompt is too long: 210000 tokens"),
+            LlmErrorKind::ContextLength
+        );
+        assert!(classify_status(429, "").is_retryable());
+        assert!(!classify_status(401, "").is_retryable());
+        let cfg = LlmConfig {
+            provider: Some("mistral".into()),
+            ..Default::default()
+        };
+        assert_eq!(from_config(&cfg).err().unwrap().kind, LlmErrorKind::Config);
+        let cfg = LlmConfig {
+            provider: Some("gguf".into()),
+            model: Some("/nonexistent/model.gguf".into()),
+            ..Default::default()
+        };
+        assert_eq!(from_config(&cfg).err().unwrap().kind, LlmErrorKind::Config);
+    }
+
+    #[derive(schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct Reply {
+        candidates: Vec<String>,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn response_schema_per_backend() {
+        let rs = ResponseSchema::of::<Reply>("reply");
+        assert!(rs.schema.get("$schema").is_none());
+        assert_eq!(rs.schema["additionalProperties"], false);
+        assert_eq!(rs.schema["required"], json!(["candidates", "note"]));
+
+        let mut req = ChatRequest::simple("sys", "prove it", Duration::from_secs(1));
+        req.response_schema = Some(rs.clone());
+        let o = OpenAiProvider {
+            name: "openai".into(),
+            base_url: "b".into(),
+            api_key: None,
+            model: "m".into(),
+            azure: None,
+            extra_headers: Vec::new(),
+        };
+        assert!(o.supports_schema());
+        let body = o.request_body(&req);
+        assert_eq!(body["response_format"]["type"], "json_schema");
+        assert_eq!(body["response_format"]["json_schema"]["strict"], true);
+        let groq = OpenAiProvider {
+            name: "groq".into(),
+            ..o
+        };
+        assert!(!groq.supports_schema());
+
+        let a = AnthropicProvider {
+            base_url: "b".into(),
+            api_key: "k".into(),
+            model: "m".into(),
+            prompt_cache: true,
+        };
+        let body = a.request_body(&req);
+        assert_eq!(body["tools"][0]["input_schema"], rs.schema);
+        assert_eq!(body["tool_choice"]["name"], "reply");
+        let (text, _) = a
+            .parse_response(&json!({
+                "content": [{"type": "tool_use", "name": "reply", "input": {"candidates": ["by simp"], "note": null}}],
+                "usage": {"input_tokens": 1, "output_tokens": 1},
+            }))
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&text).unwrap()["candidates"][0],
+            "by simp"
+        );
+        let mut usage = Usage::default();
+        assert_eq!(
+            AnthropicProvider::stream_event(
+                &json!({"type": "content_block_delta", "delta": {"type": "input_json_delta", "partial_json": "{\"cand"}}),
+                &mut usage
+            )
+            .unwrap()
+            .as_deref(),
+            Some("{\"cand")
+        );
+
+        let l = OllamaProvider {
+            base_url: "b".into(),
+            model: "m".into(),
+        };
+        assert_eq!(l.request_body(&req)["format"], rs.schema);
+    }
+
+    #[test]
+    fn tool_calls_round_trip_per_backend() {
+        let call = ToolCall {
+            id: "c1".into(),
+            name: "double".into(),
+            arguments: json!({"n": 21}),
+        };
+        let mut req = ChatRequest::simple("sys", "prove it", Duration::from_secs(1));
+        req.tools = vec![ToolSpec {
+            name: "double".into(),
+            description: "Double `n`.".into(),
+            parameters: json!({"type": "object"}),
+        }];
+        req.messages.extend([
+            ChatMessage::assistant_tool_calls("", vec![call.clone(), call.clone()]),
+            ChatMessage::tool_result("c1", "42"),
+            ChatMessage::tool_result("c1", "42"),
+        ]);
+        let o = OpenAiProvider {
+            name: "openai".into(),
+            base_url: "b".into(),
+            api_key: None,
+            model: "m".into(),
+            azure: None,
+            extra_headers: Vec::new(),
+        };
+        let body = o.request_body(&req);
+        assert_eq!(body["tools"][0]["function"]["name"], "double");
+        assert_eq!(
+            body["messages"][2]["tool_calls"][0]["function"]["arguments"],
+            "{\"n\":21}"
+        );
+        assert_eq!(body["messages"][3]["tool_call_id"], "c1");
+        let raw = json!({"choices": [{"message": {"content": null, "tool_calls": [
+            {"id": "c1", "type": "function", "function": {"name": "double", "arguments": "{\"n\":21}"}}
+        ]}}]});
+        assert_eq!(
+            OpenAiProvider::tool_calls(&raw),
+            std::slice::from_ref(&call)
+        );
+
+        // Both results go back in one user turn.
+        let turns = messages_api_turns(&req.messages);
+        assert_eq!(turns.len(), 3);
+        assert_eq!(turns[1]["content"][1]["type"], "tool_use");
+        assert_eq!(turns[2]["content"].as_array().unwrap().len(), 2);
+        let raw = json!({"content": [
+            {"type": "text", "text": "let me check"},
+            {"type": "tool_use", "id": "c1", "name": "double", "input": {"n": 21}},
+        ]});
+        assert_eq!(messages_tool_calls(&raw), [call]);
+    }
+}
@@ -0,0 +1,186 @@
+//! Model routing with escalation (`[llm.routing]`).
+//!
+//! The repair loop asks the first (cheapest) tier and moves to the next one after
+//! `escalate_after` LLM rounds without a verified candidate; goals predicted hard (`is_hard`: a
+//! long goal, many hypotheses, or a listed error class) start at the last tier. A tier is the
+//! `[llm]` section with the tier's fields laid over it (`tier_config`), so one tier can differ
+//! only in `model` while another switches `provider`.
+
+use crate::config::{LlmConfig, ModelTier, RoutingConfig};
+use crate::diagnostics::ErrorClass;
+
+/// `base` with `tier` laid over it. Switching provider drops the base's endpoint and
+/// provider-specific settings, which would not apply to the new one.
+pub fn tier_config(base: &LlmConfig, tier: &ModelTier) -> LlmConfig {
+    let mut cfg = match &tier.provider {
+        Some(p) if Some(p) != base.provider.as_ref() => LlmConfig {
+            provider: Some(p.clone()),
+            temperature: base.temperature,
+            max_tokens: base.max_tokens,
+            seed: base.seed,
+            cache: base.cache,
+            cache_ttl_s: base.cache_ttl_s,
+            ..Default::default()
+        },
+        _ => base.clone(),
+    };
+    cfg.routing = None;
+    let over = |dst: &mut Option<String>, src: &Option<String>| {
+        if src.is_some() {
+            dst.clone_from(src);
+        }
+    };
+    over(&mut cfg.model, &tier.model);
+    over(&mut cfg.base_url, &tier.base_url);
+    over(&mut cfg.api_key_env, &tier.api_key_env);
+    cfg.temperature = tier.temperature.or(cfg.temperature);
+    cfg.max_tokens = tier.max_tokens.or(cfg.max_tokens);
+    cfg.context_window = tier.context_window.or(cfg.context_window);
+    cfg
+}
+
+pub fn tier_name(tier: &ModelTier, index: usize) -> String {
+    tier.name
+        .clone()
+        .or_else(|| tier.model.clone())
+        .unwrap_or_else(|| format!("tier{index}"))
+}
+
+/// Whether a goal should skip the cheap tiers.
+pub fn is_hard(
+    routing: &RoutingConfig,
+    goal: Option<&str>,
+    error_class: Option<ErrorClass>,
+) -> bool {
+    if let Some(c) = error_class {
+        if routing.hard_error_classes.iter().any(|x| x == c.as_str()) {
+            return true;
+        }
+    }
+    let Some(goal) = goal else {
+        return false;
+    };
+    if let Some(n) = routing.hard_goal_tokens {
+        if crate::prompt_context::estimate_tokens(goal) > n {
+            return true;
+        }
+    }
+    if let Some(n) = routing.hard_hypotheses {
+        let hyps = goal
+            .lines()
+            .take_while(|l| !l.trim_start().starts_with('⊢'))
+            .filter(|l| l.contains(" : "))
+            .count();
+        if hyps > n {
+            return true;
+        }
+    }
+    false
+}
+
+/// Which tier to ask next, for one repair.
+#[derive(Debug, Clone)]
+pub struct Router {
+    names: Vec<String>,
+    current: usize,
+    failed_rounds: usize,
+    escalate_after: usize,
+}
+
+impl Router {
+    /// `None` when no tiers are configured.
+    pub fn new(routing: &RoutingConfig, hard: bool) -> Option<Self> {
+        let names: Vec<String> = routing
+            .tiers
+            .iter()
+            .enumerate()
+            .map(|(i, t)| tier_name(t, i))
+            .collect();
+        let last = names.len().checked_sub(1)?;
+        Some(Self {
+            names,
+            current: if hard { last } else { 0 },
+            failed_rounds: 0,
+            escalate_after: routing.escalate_after.unwrap_or(1).max(1),
+        })
+    }
+
+    /// Index into `[llm.routing] tiers` (`ChatOptions::tier`).
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn current_name(&self) -> &str {
+        &self.names[self.current]
+    }
+
+    /// Count an LLM round that produced no verified candidate; returns whether this escalated.
+    pub fn round_failed(&mut self) -> bool {
+        self.failed_rounds += 1;
+        if self.failed_rounds >= self.escalate_after && self.current + 1 < self.names.len() {
+            self.current += 1;
+            self.failed_rounds = 0;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escalates_after_failures_and_starts_hard_goals_high() {
+        let tier = |provider: Option<&str>, model: &str| ModelTier {
+            provider: provider.map(str::to_string),
+            model: Some(model.to_string()),
+            ..Default::default()
+        };
+        let routing = RoutingConfig {
+            tiers: vec![
+                tier(None, "gpt-4o-mini"),
+                tier(Some("anthropic"), "claude-sonnet-4"),
+            ],
+            escalate_after: Some(2),
+            hard_hypotheses: Some(2),
+            hard_error_classes: vec!["deterministic_timeout".to_string()],
+            ..Default::default()
+        };
+        let base = LlmConfig {
+            provider: Some("openai".into()),
+            model: Some("gpt-4o".into()),
+            base_url: Some("https://proxy.example/v1".into()),
+            temperature: Some(0.1),
+            routing: Some(routing.clone()),
+            ..Default::default()
+        };
+        let cheap = tier_config(&base, &routing.tiers[0]);
+        assert_eq!(cheap.model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(cheap.base_url, base.base_url);
+        assert!(cheap.routing.is_none());
+        let strong = tier_config(&base, &routing.tiers[1]);
+        assert_eq!(strong.provider.as_deref(), Some("anthropic"));
+        assert!(strong.base_url.is_none());
+        assert_eq!(strong.temperature, Some(0.1));
+
+        let mut r = Router::new(&routing, false).unwrap();
+        assert!(!r.round_failed());
+        assert!(r.round_failed());
+        assert_eq!(r.current_name(), "claude-sonnet-4");
+        assert!(!r.round_failed() && !r.round_failed());
+
+        assert!(!is_hard(&routing, Some("a b : ℕ\n⊢ a + b = b + a"), None));
+        assert!(is_hard(
+            &routing,
+            Some("a : ℕ\nb : ℕ\nh : a < b\n⊢ a ≤ b"),
+            None
+        ));
+        assert!(is_hard(
+            &routing,
+            None,
+            Some(ErrorClass::DeterministicTimeout)
+        ));
+        assert!(Router::new(&RoutingConfig::default(), true).is_none());
+    }
+}
@@ -0,0 +1,157 @@
+//! Per-provider request limits (`[llm.limits.<provider>]`).
+//!
+//! Every request that goes to a provider (cache hits do not) first takes a slot from that
+//! provider's limiter: at most `max_concurrent` requests in flight and at most
+//! `requests_per_minute` started in any 60-second window, process-wide. Requests over either
+//! limit wait in line (FIFO for the concurrency limit) instead of being sent and rejected with a
+//! 429, which the parallel repair workers would otherwise all hit at once. The wait counts
+//! against the request's timeout; a request that times out in the queue fails with
+//! `LlmErrorKind::Timeout`, so failover (`failover`) can move on to the next provider.
+
+use super::provider::{LlmError, LlmErrorKind};
+use crate::config::ProviderLimits;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Limiters by provider name, created on first use from the `[llm]` config in effect.
+static LIMITERS: Mutex<Option<HashMap<String, Arc<Limiter>>>> = Mutex::new(None);
+
+#[derive(Debug)]
+pub struct Limiter {
+    slots: Option<Arc<Semaphore>>,
+    per_minute: Option<usize>,
+    window: Duration,
+    /// Start times of requests within the last `window`.
+    started: tokio::sync::Mutex<VecDeque<Instant>>,
+}
+
+/// A request slot; dropping it frees the concurrency slot.
+#[derive(Debug)]
+pub struct Slot {
+    _permit: Option<OwnedSemaphorePermit>,
+    /// Time spent waiting for the slot.
+    pub waited: Duration,
+}
+
+impl Limiter {
+    pub fn new(limits: &ProviderLimits) -> Self {
+        Self::with_window(limits, WINDOW)
+    }
+
+    fn with_window(limits: &ProviderLimits, window: Duration) -> Self {
+        Self {
+            slots: limits
+                .max_concurrent
+                .map(|n| Arc::new(Semaphore::new(n.max(1)))),
+            per_minute: limits.requests_per_minute.map(|n| n.max(1) as usize),
+            window,
+            started: tokio::sync::Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Wait for a concurrency slot, then for room in the per-minute window.
+    pub async fn acquire(&self) -> Slot {
+        let t0 = Instant::now();
+        let permit = match &self.slots {
+            Some(s) => s.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        if let Some(max) = self.per_minute {
+            let mut started = self.started.lock().await;
+            loop {
+                let now = Instant::now();
+                while started.front().is_some_and(|t| now - *t >= self.window) {
+                    started.pop_front();
+                }
+                if started.len() < max {
+                    started.push_back(now);
+                    break;
+                }
+                // Holding the lock keeps later requests queued behind this one.
+                let oldest = *started.front().expect("window is full");
+                tokio::time::sleep_until(oldest + self.window).await;
+            }
+        }
+        Slot {
+            _permit: permit,
+            waited: t0.elapsed(),
+        }
+    }
+}
+
+/// The limiter for `provider` (`None`: no limits configured for it).
+pub fn limiter(provider: &str) -> Option<Arc<Limiter>> {
+    let mut g = LIMITERS.lock().ok()?;
+    let map = g.get_or_insert_with(HashMap::new);
+    if let Some(l) = map.get(provider) {
+        return Some(l.clone());
+    }
+    let limits = super::configured()?.limits.get(provider).cloned()?;
+    if limits.max_concurrent.is_none() && limits.requests_per_minute.is_none() {
+        return None;
+    }
+    let l = Arc::new(Limiter::new(&limits));
+    map.insert(provider.to_string(), l.clone());
+    Some(l)
+}
+
+/// Drop all limiters (after the config changes); requests already waiting keep theirs.
+pub fn reset() {
+    if let Ok(mut g) = LIMITERS.lock() {
+        *g = None;
+    }
+}
+
+/// A slot for a request to `provider`, waiting at most `timeout`.
+pub async fn acquire(provider: &str, timeout: Duration) -> Result<Option<Slot>, LlmError> {
+    let Some(l) = limiter(provider) else {
+        return Ok(None);
+    };
+    tokio::time::timeout(timeout, l.acquire())
+        .await
+        .map(Some)
+        .map_err(|_| {
+            LlmError::new(
+                LlmErrorKind::Timeout,
+                provider,
+                "timed out waiting for a request slot ([llm.limits])",
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn limits_concurrency_and_rate() {
+        let window = Duration::from_millis(400);
+        let limits = ProviderLimits {
+            max_concurrent: Some(2),
+            requests_per_minute: Some(3),
+        };
+        let l = Arc::new(Limiter::with_window(&limits, window));
+        let a = l.acquire().await;
+        let _b = l.acquire().await;
+        assert!(a.waited < Duration::from_millis(50));
+        // A third concurrent request waits until a slot frees.
+        let c = tokio::spawn({
+            let l = l.clone();
+            async move { l.acquire().await.waited }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!c.is_finished());
+        drop(a);
+        let waited = c.await.unwrap();
+        assert!(waited >= Duration::from_millis(100) && waited < window);
+
+        // A fourth start within the window waits for the first to leave it.
+        let d = l.acquire().await;
+        assert!(d.waited >= Duration::from_millis(200), "{:?}", d.waited);
+    }
+}
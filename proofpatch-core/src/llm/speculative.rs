@@ -0,0 +1,170 @@
+//! Speculative parallel sampling (`[llm.speculative]`): trade requests for latency.
+//!
+//! Instead of one reply (or `RepairOptions::samples` replies, all awaited), `requests` samples
+//! of the prompt are sent at once, each streamed like any other request. Candidates are
+//! extracted from replies as they finish and deduplicated on their normalized text; once
+//! `min_candidates` distinct ones are in, the requests still running are cancelled (dropping a
+//! streamed request closes its connection, which ends generation). Replies that finish in the
+//! meantime are kept.
+//!
+//! Cancelled requests never reach cost accounting, although providers bill the output they had
+//! generated; they are counted (`SpeculativeOutcome::cancelled`) so reports can show them. The
+//! mode is off unless configured, e.g. `[llm.speculative] requests = 4`, `min_candidates = 3`.
+
+use super::{chat_completion_with, ChatCompletionResult, ChatOptions};
+use crate::config::SpeculativeConfig;
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::Duration;
+
+pub const DEFAULT_TEMPERATURE: f32 = 0.8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Speculative {
+    pub requests: usize,
+    pub min_candidates: usize,
+    pub temperature: f32,
+}
+
+impl Speculative {
+    /// `None` when the mode is off (`requests` below 2).
+    pub fn from_config(c: &SpeculativeConfig) -> Option<Self> {
+        let requests = c.requests.filter(|n| *n >= 2)?;
+        Some(Self {
+            requests,
+            min_candidates: c.min_candidates.unwrap_or(usize::MAX).max(1),
+            temperature: c.temperature.unwrap_or(DEFAULT_TEMPERATURE),
+        })
+    }
+}
+
+/// The configured `[llm.speculative]` settings, when the mode is on.
+pub fn settings() -> Option<Speculative> {
+    super::configured()?
+        .speculative
+        .as_ref()
+        .and_then(Speculative::from_config)
+}
+
+#[derive(Debug)]
+pub struct SpeculativeOutcome<T> {
+    /// Requests that finished, in the order they did.
+    pub results: Vec<T>,
+    /// Distinct candidates (the first spelling of each), in the order they arrived.
+    pub candidates: Vec<String>,
+    /// Requests cancelled before they finished.
+    pub cancelled: usize,
+}
+
+/// Run `requests` futures from `start` (given the sample index) concurrently, feeding each
+/// finished one to `extract`; cancel the rest once `min_candidates` distinct candidates (by
+/// `key`) have been extracted.
+pub async fn speculate<T, F>(
+    spec: &Speculative,
+    start: impl Fn(usize) -> F,
+    extract: impl Fn(&T) -> Vec<String>,
+    key: impl Fn(&str) -> String,
+) -> SpeculativeOutcome<T>
+where
+    T: Send + 'static,
+    F: Future<Output = T> + Send + 'static,
+{
+    let mut set = tokio::task::JoinSet::new();
+    for i in 0..spec.requests {
+        set.spawn(start(i));
+    }
+    let mut out = SpeculativeOutcome {
+        results: Vec::new(),
+        candidates: Vec::new(),
+        cancelled: 0,
+    };
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut stopped = false;
+    while let Some(r) = set.join_next().await {
+        let r = match r {
+            Ok(r) => r,
+            Err(e) => {
+                out.cancelled += usize::from(e.is_cancelled());
+                continue;
+            }
+        };
+        for c in extract(&r) {
+            if seen.insert(key(&c)) {
+                out.candidates.push(c);
+            }
+        }
+        out.results.push(r);
+        if !stopped && out.candidates.len() >= spec.min_candidates {
+            // Aborted requests still come out of `join_next`, as cancelled (or finished, if
+            // they got there first).
+            set.abort_all();
+            stopped = true;
+        }
+    }
+    out
+}
+
+/// `speculate` over `chat_completion_with` samples (`ChatOptions::sample` 0..requests at
+/// `spec.temperature`), with candidates from `extract(reply content)`.
+pub async fn speculative_completions(
+    system: &str,
+    user: &str,
+    timeout: Duration,
+    opts: &ChatOptions,
+    spec: &Speculative,
+    extract: impl Fn(&str) -> Vec<String>,
+    key: impl Fn(&str) -> String,
+) -> SpeculativeOutcome<Result<ChatCompletionResult, String>> {
+    let start = |i: usize| {
+        let (system, user) = (system.to_string(), user.to_string());
+        let opts = ChatOptions {
+            temperature: Some(spec.temperature),
+            sample: Some(i as u32),
+            ..opts.clone()
+        };
+        async move { chat_completion_with(&system, &user, timeout, &opts).await }
+    };
+    let extract = |r: &Result<ChatCompletionResult, String>| match r {
+        Ok(r) => extract(&r.content),
+        Err(_) => Vec::new(),
+    };
+    speculate(spec, start, extract, key).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cancels_slow_requests_once_enough_candidates_arrived() {
+        let spec = Speculative::from_config(&SpeculativeConfig {
+            requests: Some(4),
+            min_candidates: Some(2),
+            temperature: None,
+        })
+        .unwrap();
+        assert_eq!(spec.temperature, DEFAULT_TEMPERATURE);
+        // Two quick replies that agree up to whitespace, one more, and one that never finishes.
+        let replies = ["by  simp", "by simp", "by omega", "by rfl"];
+        let delays = [10, 20, 30, 60_000];
+        let out = speculate(
+            &spec,
+            |i| async move {
+                tokio::time::sleep(Duration::from_millis(delays[i])).await;
+                replies[i].to_string()
+            },
+            |r: &String| vec![r.clone()],
+            crate::goal_ast::candidate_key,
+        )
+        .await;
+        assert_eq!(out.candidates, ["by  simp", "by omega"]);
+        assert_eq!(out.results.len(), 3);
+        assert_eq!(out.cancelled, 1);
+
+        let off = SpeculativeConfig {
+            requests: Some(1),
+            ..Default::default()
+        };
+        assert!(Speculative::from_config(&off).is_none());
+    }
+}
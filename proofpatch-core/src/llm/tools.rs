@@ -0,0 +1,344 @@
+//! Tool calling: the model asks for a registered tool mid-conversation, gets its result, and
+//! answers with it in hand.
+//!
+//! A `ToolRegistry` in `ChatOptions::tools` is offered to providers that support tool calls
+//! (`Provider::supports_tools`: OpenAI-compatible backends, Anthropic, `mock`). When the reply
+//! calls tools, each call is run, its result sent back as a `tool` turn, and the model asked
+//! again, up to `ToolRegistry::max_steps` requests; every round trip goes through the budget,
+//! request limits, cost accounting, and transcripts like any other request, and none is cached.
+//! A tool that fails answers with its error, so the model can correct the call.
+//!
+//! `ToolRegistry::standard` has the tools repair uses (`RepairOptions::tools`): Mathlib lemma
+//! search (`lemma_search`) and an SMT check of linear-arithmetic goals (`smt_lia`).
+
+use super::cost::RequestUsage;
+use super::provider::{ChatMessage, ChatRequest, ChatResponse, Provider, ToolCall, ToolSpec};
+use super::{ChatOptions, Failure};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+/// Requests per exchange (the first one included) when `max_steps` is not set.
+pub const DEFAULT_MAX_STEPS: usize = 4;
+
+/// Tool results longer than this many characters are cut.
+const MAX_RESULT_CHARS: usize = 4_000;
+
+pub type ToolFuture<'a> = Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>>;
+
+pub trait Tool: Send + Sync {
+    fn spec(&self) -> ToolSpec;
+    /// Run with the model's `arguments` (an object matching `spec().parameters`, unchecked).
+    fn call<'a>(&'a self, arguments: &'a Value) -> ToolFuture<'a>;
+}
+
+/// One tool call the model made, and what it got back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolInvocation {
+    pub name: String,
+    pub arguments: Value,
+    pub ok: bool,
+    pub result: String,
+    pub elapsed_ms: u64,
+}
+
+pub struct ToolRegistry {
+    tools: Vec<Box<dyn Tool>>,
+    max_steps: usize,
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for ToolRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolRegistry")
+            .field(
+                "tools",
+                &self.tools.iter().map(|t| t.spec().name).collect::<Vec<_>>(),
+            )
+            .field("max_steps", &self.max_steps)
+            .finish()
+    }
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self {
+            tools: Vec::new(),
+            max_steps: DEFAULT_MAX_STEPS,
+        }
+    }
+
+    /// `search_mathlib` and `check_arithmetic`.
+    pub fn standard() -> Self {
+        Self::new().with(SearchMathlib).with(CheckArithmetic)
+    }
+
+    pub fn with(mut self, tool: impl Tool + 'static) -> Self {
+        self.tools.push(Box::new(tool));
+        self
+    }
+
+    pub fn max_steps(mut self, n: usize) -> Self {
+        self.max_steps = n.max(1);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    pub fn specs(&self) -> Vec<ToolSpec> {
+        self.tools.iter().map(|t| t.spec()).collect()
+    }
+
+    /// Run `call`; an unknown tool or a failure is reported in the result.
+    pub async fn call(&self, call: &ToolCall) -> ToolInvocation {
+        let t0 = Instant::now();
+        let out = match self.tools.iter().find(|t| t.spec().name == call.name) {
+            Some(t) => t.call(&call.arguments).await,
+            None => Err(format!("unknown tool `{}`", call.name)),
+        };
+        let (ok, mut result) = match out {
+            Ok(r) => (true, r),
+            Err(e) => (false, format!("error: {e}")),
+        };
+        if result.chars().count() > MAX_RESULT_CHARS {
+            result = result.chars().take(MAX_RESULT_CHARS).collect::<String>() + "\n…";
+        }
+        ToolInvocation {
+            name: call.name.clone(),
+            arguments: call.arguments.clone(),
+            ok,
+            result,
+            elapsed_ms: t0.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+/// Ask `p` with `req` (tools set), running the tools it calls, until it answers without calling
+/// any or `registry.max_steps` requests have been made (the last reply is then returned as is).
+/// The steps share `req.timeout`.
+pub(super) async fn run(
+    p: &dyn Provider,
+    req: &mut ChatRequest,
+    opts: &ChatOptions,
+    registry: &ToolRegistry,
+) -> Result<(ChatResponse, RequestUsage, Vec<ToolInvocation>), Failure> {
+    let deadline = Instant::now() + req.timeout;
+    let mut usage: Option<RequestUsage> = None;
+    let mut calls = Vec::new();
+    for step in 1.. {
+        req.timeout = deadline.saturating_duration_since(Instant::now());
+        let (r, u) = super::send(p, req, opts, false).await?;
+        match usage.as_mut() {
+            Some(total) => total.merge(&u),
+            None => usage = Some(u),
+        }
+        if r.tool_calls.is_empty() || step >= registry.max_steps || req.timeout.is_zero() {
+            return Ok((r, usage.unwrap_or_default(), calls));
+        }
+        req.messages.push(ChatMessage::assistant_tool_calls(
+            r.content.clone(),
+            r.tool_calls.clone(),
+        ));
+        for c in &r.tool_calls {
+            let inv = registry.call(c).await;
+            req.messages
+                .push(ChatMessage::tool_result(c.id.clone(), inv.result.clone()));
+            calls.push(inv);
+        }
+    }
+    unreachable!("the step loop only returns")
+}
+
+fn str_arg<'a>(args: &'a Value, key: &str) -> Result<&'a str, String> {
+    args.get(key)
+        .and_then(Value::as_str)
+        .filter(|s| !s.trim().is_empty())
+        .ok_or_else(|| format!("missing string argument `{key}`"))
+}
+
+/// Mathlib lemma search (`lemma_search`).
+pub struct SearchMathlib;
+
+impl Tool for SearchMathlib {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "search_mathlib".to_string(),
+            description: "Search Mathlib for lemmas. With backend `leansearch` (default) the \
+                query is a natural-language description; with `loogle` it is a name fragment or \
+                a type pattern such as `(_ + _ ≤ _ + _)`."
+                .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" },
+                    "backend": { "type": "string", "enum": ["leansearch", "loogle"] },
+                },
+                "required": ["query"],
+            }),
+        }
+    }
+
+    fn call<'a>(&'a self, arguments: &'a Value) -> ToolFuture<'a> {
+        Box::pin(async move {
+            let query = str_arg(arguments, "query")?;
+            let backend = arguments
+                .get("backend")
+                .and_then(Value::as_str)
+                .and_then(crate::lemma_search::LemmaBackend::parse)
+                .unwrap_or(crate::lemma_search::LemmaBackend::LeanSearch);
+            let hits =
+                crate::lemma_search::lemma_search(backend, query, 8, Duration::from_secs(15))
+                    .await?;
+            if hits.is_empty() {
+                return Ok("no lemmas found".to_string());
+            }
+            let block = crate::lemma_search::lemma_prompt_block(&hits, 8);
+            Ok(crate::untrusted::delimit("lemma search", &block))
+        })
+    }
+}
+
+/// Whether a goal follows from hypotheses by linear arithmetic (`smt_lia`).
+pub struct CheckArithmetic;
+
+impl Tool for CheckArithmetic {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "check_arithmetic".to_string(),
+            description: "Check with an SMT solver whether `goal` follows from `hypotheses` by \
+                linear integer arithmetic (what `omega` / `linarith` can prove). Hypotheses are \
+                Lean context lines such as `x y : ℕ` or `h : x ≤ y`; the goal is a Lean \
+                proposition."
+                .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "hypotheses": { "type": "array", "items": { "type": "string" } },
+                    "goal": { "type": "string" },
+                },
+                "required": ["hypotheses", "goal"],
+            }),
+        }
+    }
+
+    fn call<'a>(&'a self, arguments: &'a Value) -> ToolFuture<'a> {
+        Box::pin(async move {
+            let goal = str_arg(arguments, "goal")?;
+            let hyps: Vec<String> = arguments
+                .get("hypotheses")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .enumerate()
+                .map(|(i, h)| {
+                    if h.contains(" : ") {
+                        h.trim().to_string()
+                    } else {
+                        format!("h{i} : {}", h.trim())
+                    }
+                })
+                .collect();
+            let plain = hyps
+                .iter()
+                .map(String::as_str)
+                .chain([format!("⊢ {}", goal.trim()).as_str()])
+                .collect::<Vec<_>>()
+                .join("\n");
+            let pp = crate::lean_lsp::pp_dump_from_plain_goals(&[plain]);
+            Ok(match crate::smt_lia::entails_from_pp_dump(&pp, 2_000, 0)? {
+                Some(true) => "entailed: the goal follows by linear arithmetic (try `omega` or \
+                    `linarith`)"
+                    .to_string(),
+                Some(false) => "not entailed: the hypotheses do not imply the goal \
+                    arithmetically (a counterexample exists)"
+                    .to_string(),
+                None => "unknown: outside linear integer arithmetic, or the solver timed out"
+                    .to_string(),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LlmConfig;
+    use std::sync::Arc;
+
+    struct Double;
+
+    impl Tool for Double {
+        fn spec(&self) -> ToolSpec {
+            ToolSpec {
+                name: "double".to_string(),
+                description: "Double `n`.".to_string(),
+                parameters: json!({ "type": "object", "properties": { "n": { "type": "integer" } } }),
+            }
+        }
+
+        fn call<'a>(&'a self, arguments: &'a Value) -> ToolFuture<'a> {
+            Box::pin(async move {
+                let n = arguments["n"].as_i64().ok_or("`n` must be an integer")?;
+                Ok(format!("result: {}", 2 * n))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_tool_calls_until_the_model_answers() {
+        let td = tempfile::tempdir().unwrap();
+        let fixtures = td.path().join("tools.toml");
+        std::fs::write(
+            &fixtures,
+            "[[response]]\ncontains = [\"result: 42\"]\nreplies = [\"by norm_num\"]\n\n\
+             [[response]]\ncontains = [\"prove it\"]\n\
+             tool_calls = [{ name = \"double\", arguments = { n = 21 } }, { name = \"nope\" }]\n",
+        )
+        .unwrap();
+        let cfg = LlmConfig {
+            provider: Some("mock".into()),
+            fixtures: Some(fixtures.display().to_string()),
+            ..Default::default()
+        };
+        let opts = ChatOptions {
+            no_cache: true,
+            tools: Some(Arc::new(ToolRegistry::new().with(Double))),
+            ..Default::default()
+        };
+        let r = super::super::complete_once(
+            "sys",
+            "prove it",
+            Duration::from_secs(5),
+            &opts,
+            Some(cfg),
+        )
+        .await
+        .ok()
+        .unwrap();
+        assert_eq!(r.content, "by norm_num");
+        let got: Vec<_> = r
+            .tool_calls
+            .iter()
+            .map(|c| (c.name.as_str(), c.ok))
+            .collect();
+        assert_eq!(got, [("double", true), ("nope", false)]);
+        assert_eq!(r.tool_calls[0].result, "result: 42");
+        assert!(r.usage.input_tokens > 0);
+
+        let specs = ToolRegistry::standard().specs();
+        let names: Vec<_> = specs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["search_mathlib", "check_arithmetic"]);
+        let e = CheckArithmetic.call(&json!({ "hypotheses": [] })).await;
+        assert_eq!(e.unwrap_err(), "missing string argument `goal`");
+    }
+}
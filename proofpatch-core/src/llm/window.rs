@@ -0,0 +1,119 @@
+//! Model context windows, for prompt budgeting (`prompt_budget`).
+//!
+//! `[llm] context_window` (or a routing tier's) wins; `gguf` uses its `context_size`; otherwise
+//! the built-in table is matched by the longest key the model name contains, like the price list
+//! in `cost`. Unknown models get `DEFAULT_WINDOW`, which is deliberately small.
+
+use super::routing::tier_config;
+use crate::config::LlmConfig;
+
+/// Window assumed for models not in the table.
+pub const DEFAULT_WINDOW: usize = 8_192;
+
+/// Output tokens reserved when `max_tokens` is not configured.
+pub const DEFAULT_RESERVE: usize = 4_096;
+
+/// (model name fragment, context window in tokens).
+const WINDOWS: &[(&str, usize)] = &[
+    ("gpt-4o", 128_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4-mini", 200_000),
+    ("claude", 200_000),
+    ("gemini-1.5", 1_000_000),
+    ("gemini-2", 1_000_000),
+    ("llama-3.1", 128_000),
+    ("llama-3.3", 128_000),
+    ("llama3", 8_192),
+    ("qwen2.5-coder", 32_768),
+    ("qwen2.5", 32_768),
+    ("deepseek", 64_000),
+    ("mistral", 32_000),
+    ("mixtral", 32_000),
+];
+
+/// Window of `model` from the built-in table (`None`: unknown).
+pub fn known_window(model: &str) -> Option<usize> {
+    let m = model.to_ascii_lowercase();
+    WINDOWS
+        .iter()
+        .filter(|(k, _)| m.contains(k))
+        .max_by_key(|(k, _)| k.len())
+        .map(|(_, w)| *w)
+}
+
+/// Context window of the model `cfg` selects.
+pub fn window_of(cfg: &LlmConfig) -> usize {
+    if let Some(w) = cfg.context_window {
+        return w as usize;
+    }
+    let provider = cfg
+        .provider
+        .clone()
+        .or_else(|| super::provider_order().into_iter().next())
+        .unwrap_or_default();
+    if provider == "gguf" {
+        return cfg.context_size.unwrap_or(4096) as usize;
+    }
+    cfg.model
+        .clone()
+        .or_else(|| super::default_model_from_env(&provider).map(|(m, _)| m))
+        .or_else(|| super::hardcoded_default_model(&provider).map(str::to_string))
+        .and_then(|m| known_window(&m))
+        .unwrap_or(DEFAULT_WINDOW)
+}
+
+/// (context window, output tokens to reserve) for a request with the `[llm]` config in effect,
+/// asked at routing tier `tier` (`ChatOptions::tier`).
+pub fn window_for(tier: Option<usize>) -> (usize, usize) {
+    let base = super::configured().unwrap_or_default();
+    let cfg = match base
+        .routing
+        .as_ref()
+        .zip(tier)
+        .and_then(|(r, i)| r.tiers.get(i))
+    {
+        Some(t) => tier_config(&base, t),
+        None => base,
+    };
+    let reserve = cfg
+        .max_tokens
+        .map(|n| n as usize)
+        .unwrap_or(DEFAULT_RESERVE);
+    let window = window_of(&cfg);
+    // Small local windows: never reserve more than a quarter of the window for the reply.
+    (window, reserve.min(window / 4))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_match_and_overrides() {
+        assert_eq!(known_window("gpt-4o-mini-2024-07-18"), Some(128_000));
+        assert_eq!(known_window("anthropic/claude-opus-4.5"), Some(200_000));
+        assert_eq!(known_window("qwen2.5-coder:7b"), Some(32_768));
+        assert_eq!(known_window("my-finetune"), None);
+        let cfg = LlmConfig {
+            provider: Some("openai".into()),
+            model: Some("my-finetune".into()),
+            ..Default::default()
+        };
+        assert_eq!(window_of(&cfg), DEFAULT_WINDOW);
+        let cfg = LlmConfig {
+            context_window: Some(16_000),
+            ..cfg
+        };
+        assert_eq!(window_of(&cfg), 16_000);
+        let gguf = LlmConfig {
+            provider: Some("gguf".into()),
+            context_size: Some(2048),
+            ..Default::default()
+        };
+        assert_eq!(window_of(&gguf), 2048);
+    }
+}
@@ -29,6 +29,9 @@ pub struct LspDiag {
     /// Best-effort log lines (e.g. from `window/logMessage`), used to recover `pp_dump` JSON
     /// and other Lean log output that doesn't appear in diagnostics.
     pub log_lines: Vec<String>,
+    /// Information-severity messages (`#check`, `#print`, `Try this:`), newlines preserved, in
+    /// the same `path:line:col: info: msg` shape as `lean_lines`.
+    pub info_lines: Vec<String>,
     pub stderr: String,
     /// Time spent waiting for diagnostics (end-to-end) for this request.
     pub waited_ms: u64,
@@ -50,7 +53,6 @@ struct ServerState {
     child: Child,
     stdin: ChildStdin,
     stdout: ChildStdout,
-    #[allow(dead_code)]
     next_id: u64,
     init_id: u64,
     ready_tx: Option<tokio::sync::oneshot::Sender<()>>,
@@ -77,6 +79,12 @@ enum LspRequest {
         timeout_s: Duration,
         resp: tokio::sync::oneshot::Sender<LspDiag>,
     },
+    /// Raw JSON-RPC request; the full response envelope (`result` or `error`) is sent back.
+    Request {
+        method: String,
+        params: serde_json::Value,
+        resp: tokio::sync::oneshot::Sender<serde_json::Value>,
+    },
 }
 
 fn lsp_uri_for_path(p: &Path) -> Result<Uri, String> {
@@ -179,6 +187,7 @@ async fn pump_stdout(mut state: ServerState, mut rx: tokio::sync::mpsc::Receiver
                                     diagnostics_count: 0,
                                     lean_lines: Vec::new(),
                                 log_lines: Vec::new(),
+                                    info_lines: Vec::new(),
                                     stderr: e,
                                     waited_ms: 0,
                                 });
@@ -237,6 +246,21 @@ async fn pump_stdout(mut state: ServerState, mut rx: tokio::sync::mpsc::Receiver
                                 log_lines: Vec::new(),
                         });
                     }
+                    LspRequest::Request { method, params, resp } => {
+                        let id = state.next_id;
+                        state.next_id = state.next_id.saturating_add(1);
+                        state.resp_waiters.insert(id, resp);
+                        let msg = json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "method": method,
+                            "params": params,
+                        });
+                        if write_msg(&mut state.stdin, &msg).await.is_err() {
+                            // Dropping the waiter closes the caller's channel.
+                            state.resp_waiters.remove(&id);
+                        }
+                    }
                 }
             }
 
@@ -261,6 +285,7 @@ async fn pump_stdout(mut state: ServerState, mut rx: tokio::sync::mpsc::Receiver
                         };
 
                         let waited_ms = now.duration_since(pending.started).as_millis() as u64;
+                        let mut info_lines: Vec<String> = Vec::new();
                         let (ok, first_line, first_col, first_msg, diag_count, lean_lines) =
                             if let Some(p) = pending.last_diag.take() {
                                 let mut first_line = None;
@@ -274,9 +299,12 @@ async fn pump_stdout(mut state: ServerState, mut rx: tokio::sync::mpsc::Receiver
                                         Some(lsp_types::DiagnosticSeverity::WARNING) => Some("warning"),
                                         _ => None,
                                     };
-                                    let Some(sev_s) = sev else { continue; };
                                     let line_1 = d.range.start.line as usize + 1;
                                     let col_1 = d.range.start.character as usize + 1;
+                                    let Some(sev_s) = sev else {
+                                        info_lines.push(format!("{uri}:{line_1}:{col_1}: info: {}", d.message));
+                                        continue;
+                                    };
                                     let msg = d.message.replace('\n', " ");
                                     lean_lines.push(format!("{uri}:{line_1}:{col_1}: {sev_s}: {msg}"));
                                     if sev_s == "error" {
@@ -310,6 +338,7 @@ async fn pump_stdout(mut state: ServerState, mut rx: tokio::sync::mpsc::Receiver
                             diagnostics_count: diag_count,
                             lean_lines,
                             log_lines: pending.log_lines,
+                            info_lines,
                             stderr: if should_timeout { format!("timeout waiting for diagnostics\n{stderr_txt}") } else { stderr_txt },
                             waited_ms,
                         });
@@ -545,3 +574,53 @@ pub async fn check_text_via_lsp(
         .map_err(|_| "lsp diag channel closed".to_string())?;
     Ok(diag)
 }
+
+/// Send one JSON-RPC request to the (cached) server for `repo_root` and return its `result`.
+///
+/// Callers are expected to have opened the document first (e.g. via `check_text_via_lsp`),
+/// so that Lean has elaborated it by the time the request arrives.
+pub async fn request_via_lsp(
+    repo_root: &Path,
+    method: &str,
+    params: serde_json::Value,
+    timeout_s: Duration,
+) -> Result<serde_json::Value, String> {
+    let cache = LSP_SERVERS.get_or_init(|| Mutex::new(HashMap::new()));
+    let key = repo_root.to_path_buf();
+    let cached = {
+        let g = cache
+            .lock()
+            .map_err(|_| "lsp cache lock poisoned".to_string())?;
+        g.get(&key).cloned()
+    };
+    let tx = match cached {
+        Some(tx) => tx,
+        None => {
+            let tx = start_server(repo_root, timeout_s).await?;
+            let mut g = cache
+                .lock()
+                .map_err(|_| "lsp cache lock poisoned".to_string())?;
+            g.insert(key, tx.clone());
+            tx
+        }
+    };
+    let (resp_tx, resp_rx) = tokio::sync::oneshot::channel();
+    tx.send(LspRequest::Request {
+        method: method.to_string(),
+        params,
+        resp: resp_tx,
+    })
+    .await
+    .map_err(|_| "lsp server task channel closed".to_string())?;
+    let msg = tokio::time::timeout(timeout_s, resp_rx)
+        .await
+        .map_err(|_| format!("timeout waiting for {method} response"))?
+        .map_err(|_| "lsp response channel closed".to_string())?;
+    if let Some(err) = msg.get("error") {
+        return Err(format!("{method} failed: {err}"));
+    }
+    Ok(msg
+        .get("result")
+        .cloned()
+        .unwrap_or(serde_json::Value::Null))
+}
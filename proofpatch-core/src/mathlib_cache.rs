@@ -0,0 +1,305 @@
+//! Automatic `lake exe cache get` before verification.
+//!
+//! In a fresh checkout Mathlib's `.olean` files are missing, and the first `lake env lean` call
+//! rebuilds Mathlib from source (hours) or times out. We check for the build outputs instead and
+//! fetch the prebuilt cache once per process per repo when they are missing.
+//!
+//! Controls:
+//! - `PROOFPATCH_AUTO_CACHE=0|1` (wins over the config file)
+//! - `proofpatch.toml`: `[verify] auto_cache = false`
+//! - `PROOFPATCH_CACHE_PROGRESS=0` silences the progress lines written to stderr.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheStatus {
+    /// Mathlib is a dependency of the repo (or the repo is Mathlib itself).
+    pub uses_mathlib: bool,
+    /// Checked-out Mathlib package directory, if any.
+    pub package_dir: Option<String>,
+    /// Build directory that holds Mathlib's `.olean` files.
+    pub olean_root: Option<String>,
+    /// Sample of expected `.olean` files that are missing (module names).
+    pub missing: Vec<String>,
+    pub needed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheReport {
+    pub status: CacheStatus,
+    /// `false` when not needed, disabled, or already attempted in this process.
+    pub ran: bool,
+    pub ok: bool,
+    pub elapsed_ms: u64,
+    pub returncode: Option<i32>,
+    pub skipped_reason: Option<String>,
+    /// Last lines of `lake exe cache get` output.
+    pub output_tail: Vec<String>,
+}
+
+fn env_switch(name: &str) -> Option<bool> {
+    let v = std::env::var(name).ok()?.trim().to_lowercase();
+    match v.as_str() {
+        "" => None,
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => Some(true),
+    }
+}
+
+/// Whether automatic cache fetching is enabled for this repo (default: on).
+pub fn auto_cache_enabled(repo_root: &Path) -> bool {
+    if let Some(v) = env_switch("PROOFPATCH_AUTO_CACHE") {
+        return v;
+    }
+    crate::config::load_from_repo_root(repo_root)
+        .ok()
+        .flatten()
+        .and_then(|c| c.verify.auto_cache)
+        .unwrap_or(true)
+}
+
+fn mathlib_package_dir(repo_root: &Path) -> Option<PathBuf> {
+    [".lake/packages/mathlib", "lake-packages/mathlib"]
+        .iter()
+        .map(|p| repo_root.join(p))
+        .find(|p| p.is_dir())
+}
+
+/// Candidate build roots (newer Lake: `.lake/build/lib/lean`, older: `.lake/build/lib`, `build/lib`).
+fn olean_roots(pkg: &Path) -> Vec<PathBuf> {
+    [
+        "build/lib/lean",
+        ".lake/build/lib/lean",
+        ".lake/build/lib",
+        "build/lib",
+    ]
+    .iter()
+    .map(|p| pkg.join(p))
+    .collect()
+}
+
+fn olean_rel(module: &str) -> PathBuf {
+    let mut p: PathBuf = module.split('.').collect();
+    p.set_extension("olean");
+    p
+}
+
+/// Mathlib modules imported by `text` (`import Mathlib.Foo.Bar`).
+pub fn mathlib_imports(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|l| l.trim().strip_prefix("import "))
+        .flat_map(|rest| rest.split_whitespace())
+        .filter(|m| *m == "Mathlib" || m.starts_with("Mathlib."))
+        .map(|m| m.to_string())
+        .collect()
+}
+
+/// Check whether Mathlib's build outputs exist for `Mathlib` and the given modules.
+pub fn cache_status(repo_root: &Path, modules: &[String]) -> CacheStatus {
+    let info = crate::toolchain::detect(repo_root);
+    let pkg = mathlib_package_dir(repo_root);
+    let is_mathlib_itself = repo_root.join("Mathlib.lean").is_file() && info.mathlib().is_none();
+    let uses_mathlib = info.capabilities.mathlib || is_mathlib_itself;
+    let base = if is_mathlib_itself {
+        Some(repo_root.to_path_buf())
+    } else {
+        pkg.clone()
+    };
+
+    let mut wanted: Vec<String> = vec!["Mathlib".to_string()];
+    wanted.extend(modules.iter().take(16).cloned());
+    wanted.dedup();
+
+    let olean_root = base.as_ref().and_then(|b| {
+        olean_roots(b)
+            .into_iter()
+            .find(|r| r.join("Mathlib.olean").is_file())
+    });
+    let missing: Vec<String> = match &olean_root {
+        Some(r) => wanted
+            .into_iter()
+            .filter(|m| !r.join(olean_rel(m)).is_file())
+            .collect(),
+        None if uses_mathlib => wanted,
+        None => Vec::new(),
+    };
+    CacheStatus {
+        uses_mathlib,
+        package_dir: pkg.map(|p| p.display().to_string()),
+        olean_root: olean_root.map(|p| p.display().to_string()),
+        needed: uses_mathlib && !missing.is_empty(),
+        missing,
+    }
+}
+
+fn attempted() -> &'static Mutex<HashSet<PathBuf>> {
+    static SEEN: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+    SEEN.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// Run `lake exe cache get`, streaming throttled progress lines to stderr.
+async fn run_cache_get(repo_root: &Path, timeout: Duration) -> (bool, Option<i32>, Vec<String>) {
+    let progress = env_switch("PROOFPATCH_CACHE_PROGRESS").unwrap_or(true);
+    let mut cmd = Command::new(crate::resolve_lake());
+    cmd.args(["exe", "cache", "get"])
+        .current_dir(repo_root)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = match cmd.spawn() {
+        Ok(c) => c,
+        Err(e) => {
+            return (
+                false,
+                None,
+                vec![format!("failed to run `lake exe cache get`: {e}")],
+            )
+        }
+    };
+    let (Some(mut out), Some(mut err)) = (child.stdout.take(), child.stderr.take()) else {
+        return (
+            false,
+            None,
+            vec!["failed to capture cache output".to_string()],
+        );
+    };
+
+    let mut tail: Vec<String> = Vec::new();
+    let mut last_print = Instant::now() - Duration::from_secs(10);
+    let mut on_chunk = |bytes: &[u8], pending: &mut String| {
+        pending.push_str(&String::from_utf8_lossy(bytes));
+        // `cache get` redraws its progress line with `\r`.
+        while let Some(i) = pending.find(['\r', '\n']) {
+            let line: String = pending.drain(..=i).collect();
+            let line = line.trim().to_string();
+            if line.is_empty() {
+                continue;
+            }
+            if progress && last_print.elapsed() >= Duration::from_secs(1) {
+                eprintln!("mathlib-cache: {line}");
+                last_print = Instant::now();
+            }
+            tail.push(line);
+            if tail.len() > 20 {
+                tail.remove(0);
+            }
+        }
+    };
+
+    let pump = async {
+        let (mut b1, mut b2) = ([0u8; 4096], [0u8; 4096]);
+        let (mut p1, mut p2) = (String::new(), String::new());
+        let (mut done1, mut done2) = (false, false);
+        while !(done1 && done2) {
+            tokio::select! {
+                r = out.read(&mut b1), if !done1 => match r {
+                    Ok(0) | Err(_) => done1 = true,
+                    Ok(n) => on_chunk(&b1[..n], &mut p1),
+                },
+                r = err.read(&mut b2), if !done2 => match r {
+                    Ok(0) | Err(_) => done2 = true,
+                    Ok(n) => on_chunk(&b2[..n], &mut p2),
+                },
+            }
+        }
+        child.wait().await
+    };
+    let res = tokio::time::timeout(timeout, pump).await;
+    match res {
+        Err(_) => {
+            tail.push(format!("timed out after {}s", timeout.as_secs()));
+            (false, None, tail)
+        }
+        Ok(Err(e)) => {
+            tail.push(format!("wait failed: {e}"));
+            (false, None, tail)
+        }
+        Ok(Ok(st)) => (st.success(), st.code(), tail),
+    }
+}
+
+/// Fetch the Mathlib cache if `.olean` files are missing (at most once per process per repo).
+///
+/// Never fails verification: problems are reported in the returned `CacheReport`.
+pub async fn ensure_mathlib_cache(
+    repo_root: &Path,
+    modules: &[String],
+    timeout: Duration,
+) -> CacheReport {
+    let status = cache_status(repo_root, modules);
+    let mut report = CacheReport {
+        status,
+        ran: false,
+        ok: true,
+        elapsed_ms: 0,
+        returncode: None,
+        skipped_reason: None,
+        output_tail: Vec::new(),
+    };
+    if !report.status.needed {
+        return report;
+    }
+    if !auto_cache_enabled(repo_root) {
+        report.skipped_reason = Some("disabled".to_string());
+        return report;
+    }
+    let first = attempted()
+        .lock()
+        .map(|mut s| s.insert(repo_root.to_path_buf()))
+        .unwrap_or(false);
+    if !first {
+        report.skipped_reason = Some("already_attempted".to_string());
+        return report;
+    }
+    if env_switch("PROOFPATCH_CACHE_PROGRESS").unwrap_or(true) {
+        eprintln!(
+            "mathlib-cache: missing build outputs ({}); running `lake exe cache get`",
+            report.status.missing.join(", ")
+        );
+    }
+    let t0 = Instant::now();
+    let (ok, code, tail) = run_cache_get(repo_root, timeout).await;
+    report.ran = true;
+    report.ok = ok;
+    report.returncode = code;
+    report.output_tail = tail;
+    report.elapsed_ms = t0.elapsed().as_millis() as u64;
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_missing_oleans_for_imports() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path();
+        std::fs::write(root.join("lean-toolchain"), "leanprover/lean4:v4.9.0\n").unwrap();
+        std::fs::write(
+            root.join("lakefile.lean"),
+            "import Lake\nrequire mathlib from git \"https://x\" @ \"v4.9.0\"\n",
+        )
+        .unwrap();
+        let imports = mathlib_imports("import Mathlib.Data.Nat.Basic\nimport Foo\n");
+        assert_eq!(imports, vec!["Mathlib.Data.Nat.Basic".to_string()]);
+
+        let s = cache_status(root, &imports);
+        assert!(s.uses_mathlib && s.needed);
+        assert_eq!(s.missing, vec!["Mathlib", "Mathlib.Data.Nat.Basic"]);
+
+        let lib = root.join(".lake/packages/mathlib/.lake/build/lib");
+        std::fs::create_dir_all(lib.join("Mathlib/Data/Nat")).unwrap();
+        std::fs::write(lib.join("Mathlib.olean"), b"").unwrap();
+        let s = cache_status(root, &imports);
+        assert_eq!(s.missing, vec!["Mathlib.Data.Nat.Basic"]);
+        std::fs::write(lib.join("Mathlib/Data/Nat/Basic.olean"), b"").unwrap();
+        assert!(!cache_status(root, &imports).needed);
+    }
+}
@@ -0,0 +1,319 @@
+//! Check a patched file against several toolchain / Mathlib versions.
+//!
+//! A proof that compiles on the pinned toolchain can still break on the next Mathlib bump (a
+//! renamed lemma, a changed `simp` set). Projects that support stable and nightly list the extra
+//! versions in `proofpatch.toml`:
+//!
+//! ```toml
+//! [[verify.matrix]]
+//! name = "nightly"
+//! toolchain = "leanprover/lean4:nightly-2025-01-10"
+//! mathlib_rev = "nightly-testing"
+//!
+//! [[verify.matrix]]
+//! name = "stable"
+//! root = "../myproject-stable"   # an existing checkout pinned to that version
+//! ```
+//!
+//! Entries without a `root` get a scratch copy of the project's sources under
+//! `.generated/proofpatch-matrix/<name>` with `lean-toolchain` and the `require mathlib` revision
+//! rewritten; `prepare_entry` runs `lake update` and fetches the Mathlib cache there once.
+//! `verify_matrix` compiles the patched file in the repo itself and in each entry (elan picks the
+//! toolchain from each directory's `lean-toolchain`) and reports one cell per version.
+
+use crate::config::MatrixEntry;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatrixCell {
+    /// `current` for the repo itself, else the entry name.
+    pub name: String,
+    pub root: String,
+    pub toolchain: Option<String>,
+    pub mathlib_rev: Option<String>,
+    pub ok: bool,
+    pub errors: usize,
+    pub first_error: Option<String>,
+    pub elapsed_ms: u64,
+    /// Why the version was not checked (setup failed); `ok` is false then.
+    pub skipped: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatrixReport {
+    pub file: String,
+    pub cells: Vec<MatrixCell>,
+    /// Every checked version accepts the file (skipped versions count as failures).
+    pub all_ok: bool,
+}
+
+impl MatrixReport {
+    /// Markdown table, one row per version.
+    pub fn render_table(&self) -> String {
+        let mut out =
+            String::from("| version | toolchain | mathlib | result |\n|---|---|---|---|\n");
+        for c in &self.cells {
+            let result = match (&c.skipped, c.ok) {
+                (Some(why), _) => format!("skipped: {why}"),
+                (None, true) => "ok".to_string(),
+                (None, false) => format!(
+                    "{} error(s): {}",
+                    c.errors,
+                    c.first_error.as_deref().unwrap_or("")
+                ),
+            };
+            out.push_str(&format!(
+                "| {} | {} | {} | {} |\n",
+                c.name,
+                c.toolchain.as_deref().unwrap_or("?"),
+                c.mathlib_rev
+                    .as_deref()
+                    .map(|r| r.chars().take(12).collect::<String>())
+                    .unwrap_or_else(|| "?".to_string()),
+                result.replace('|', "\\|")
+            ));
+        }
+        out
+    }
+}
+
+/// Rewrite the Mathlib revision in a lakefile (`require mathlib from git "…" @ "rev"` in
+/// `lakefile.lean`, `rev = "…"` in the mathlib `[[require]]` table of `lakefile.toml`).
+pub fn pin_mathlib_rev(lakefile: &str, toml: bool, rev: &str) -> String {
+    let mut out = Vec::new();
+    let mut in_mathlib = false;
+    let mut lines = lakefile.lines().peekable();
+    while let Some(l) = lines.next() {
+        let t = l.trim();
+        if toml {
+            if t.starts_with('[') {
+                in_mathlib = false;
+            }
+            if t.starts_with("name") && t.contains("\"mathlib\"") {
+                in_mathlib = true;
+            }
+            if in_mathlib && t.starts_with("rev") && t.contains('=') {
+                let indent = &l[..l.len() - l.trim_start().len()];
+                out.push(format!("{indent}rev = \"{rev}\""));
+                continue;
+            }
+            out.push(l.to_string());
+            continue;
+        }
+        if !(t.starts_with("require") && t.contains("mathlib")) {
+            out.push(l.to_string());
+            continue;
+        }
+        // The URL and the `@ "rev"` part may sit on continuation lines.
+        let mut stmt = vec![l.to_string()];
+        while let Some(next) = lines.peek() {
+            if next.starts_with([' ', '\t']) && !next.trim().is_empty() {
+                stmt.push(lines.next().unwrap_or_default().to_string());
+            } else {
+                break;
+            }
+        }
+        let joined = stmt.join("\n");
+        match joined.rfind('@') {
+            Some(i) => {
+                let head = &joined[..i];
+                let git = if joined[i + 1..].trim_start().starts_with("git") {
+                    "git "
+                } else {
+                    ""
+                };
+                out.push(format!("{head}@ {git}\"{rev}\""));
+            }
+            None => out.push(format!("{} @ \"{rev}\"", joined.trim_end())),
+        }
+    }
+    let mut s = out.join("\n");
+    if lakefile.ends_with('\n') {
+        s.push('\n');
+    }
+    s
+}
+
+/// Copy a project's sources (no dot-directories, no build output).
+pub(crate) fn copy_sources(from: &Path, to: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(to).map_err(|e| format!("mkdir {}: {e}", to.display()))?;
+    let rd = std::fs::read_dir(from).map_err(|e| format!("read {}: {e}", from.display()))?;
+    for ent in rd.flatten() {
+        let name = ent.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || name == "build" || name == "lake-packages" {
+            continue;
+        }
+        let (src, dst) = (ent.path(), to.join(&name));
+        match ent.file_type() {
+            Ok(ft) if ft.is_dir() => copy_sources(&src, &dst)?,
+            Ok(ft) if ft.is_file() => {
+                std::fs::copy(&src, &dst).map_err(|e| format!("copy {}: {e}", src.display()))?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Directory for `entry`: its `root`, or a scratch copy pinned to its versions (created and
+/// `lake update`d on first use; later calls reuse it).
+pub async fn prepare_entry(
+    repo_root: &Path,
+    entry: &MatrixEntry,
+    timeout: Duration,
+) -> Result<PathBuf, String> {
+    if let Some(r) = &entry.root {
+        let p = repo_root.join(r);
+        return crate::find_lean_repo_root(&p);
+    }
+    let dir = repo_root
+        .join(".generated")
+        .join("proofpatch-matrix")
+        .join(&entry.name);
+    let stamp = dir.join(".proofpatch-matrix.json");
+    let want = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    if std::fs::read_to_string(&stamp).ok().as_deref() == Some(want.as_str()) {
+        return Ok(dir);
+    }
+    copy_sources(repo_root, &dir)?;
+    if let Some(tc) = &entry.toolchain {
+        std::fs::write(dir.join("lean-toolchain"), format!("{}\n", tc.trim()))
+            .map_err(|e| format!("write lean-toolchain: {e}"))?;
+    }
+    if let Some(rev) = &entry.mathlib_rev {
+        for (f, toml) in [("lakefile.lean", false), ("lakefile.toml", true)] {
+            let p = dir.join(f);
+            if let Ok(t) = std::fs::read_to_string(&p) {
+                std::fs::write(&p, pin_mathlib_rev(&t, toml, rev))
+                    .map_err(|e| format!("write {}: {e}", p.display()))?;
+            }
+        }
+    }
+    let lake = crate::resolve_lake();
+    for args in [&["update", "mathlib"][..], &["exe", "cache", "get"][..]] {
+        let mut cmd = tokio::process::Command::new(&lake);
+        cmd.args(args).current_dir(&dir).kill_on_drop(true);
+        let out = tokio::time::timeout(timeout, cmd.output())
+            .await
+            .map_err(|_| format!("timeout during `lake {}`", args.join(" ")))?
+            .map_err(|e| format!("failed to run lake: {e}"))?;
+        if !out.status.success() {
+            return Err(format!(
+                "`lake {}` failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&out.stderr)
+                    .lines()
+                    .last()
+                    .unwrap_or("")
+            ));
+        }
+    }
+    std::fs::write(&stamp, want).map_err(|e| format!("write {}: {e}", stamp.display()))?;
+    Ok(dir)
+}
+
+async fn check_cell(name: &str, root: &Path, text: &str, timeout: Duration) -> MatrixCell {
+    let info = crate::toolchain::detect(root);
+    let mut cell = MatrixCell {
+        name: name.to_string(),
+        root: root.display().to_string(),
+        toolchain: info.toolchain.clone(),
+        mathlib_rev: info
+            .mathlib()
+            .and_then(|d| d.rev.clone().or(d.input_rev.clone())),
+        ok: false,
+        errors: 0,
+        first_error: None,
+        elapsed_ms: 0,
+        skipped: None,
+    };
+    let t0 = Instant::now();
+    match crate::verify_lean_text(root, text, timeout).await {
+        Ok(vr) => {
+            let diags = crate::diagnostics::parse_diagnostics_from(&vr.stdout, &vr.stderr);
+            cell.errors = diags
+                .iter()
+                .filter(|d| d.severity == crate::diagnostics::Severity::Error)
+                .count();
+            cell.first_error = crate::diagnostics::first_error(&diags)
+                .map(|d| d.headline().to_string())
+                .or_else(|| (!vr.ok).then(|| vr.stderr.lines().last().unwrap_or("").to_string()));
+            cell.ok = vr.ok && cell.errors == 0;
+        }
+        Err(e) => cell.skipped = Some(e),
+    }
+    cell.elapsed_ms = t0.elapsed().as_millis() as u64;
+    cell
+}
+
+/// Compile `text` (the patched contents of `file_rel`) in the repo and in every matrix entry.
+pub async fn verify_matrix(
+    repo_root: &Path,
+    file_rel: &str,
+    text: &str,
+    entries: &[MatrixEntry],
+    timeout: Duration,
+) -> Result<MatrixReport, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let mut cells = vec![check_cell("current", &repo_root, text, timeout).await];
+    for e in entries {
+        match prepare_entry(&repo_root, e, timeout).await {
+            Ok(root) => cells.push(check_cell(&e.name, &root, text, timeout).await),
+            Err(why) => cells.push(MatrixCell {
+                name: e.name.clone(),
+                root: e.root.clone().unwrap_or_default(),
+                toolchain: e.toolchain.clone(),
+                mathlib_rev: e.mathlib_rev.clone(),
+                ok: false,
+                errors: 0,
+                first_error: None,
+                elapsed_ms: 0,
+                skipped: Some(why),
+            }),
+        }
+    }
+    Ok(MatrixReport {
+        file: file_rel.to_string(),
+        all_ok: cells.iter().all(|c| c.ok),
+        cells,
+    })
+}
+
+/// Matrix entries from the repo's `proofpatch.toml` (empty when absent).
+pub fn configured_entries(repo_root: &Path) -> Vec<MatrixEntry> {
+    crate::config::load_from_repo_root(repo_root)
+        .ok()
+        .flatten()
+        .map(|c| c.verify.matrix)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins_mathlib_revisions_in_both_lakefile_formats() {
+        let lean = "import Lake\nopen Lake DSL\n\nrequire mathlib from git\n  \"https://github.com/leanprover-community/mathlib4.git\"\n  @ \"v4.14.0\"\n\npackage foo\n";
+        let out = pin_mathlib_rev(lean, false, "nightly-testing");
+        assert!(out.contains("mathlib4.git\"\n  @ \"nightly-testing\"\n\npackage foo\n"));
+        let new_style = "require \"leanprover-community\" / \"mathlib\" @ git \"v4.14.0\"\n";
+        assert_eq!(
+            pin_mathlib_rev(new_style, false, "abc"),
+            "require \"leanprover-community\" / \"mathlib\" @ git \"abc\"\n"
+        );
+
+        let toml = "name = \"foo\"\n\n[[require]]\nname = \"batteries\"\nrev = \"main\"\n\n[[require]]\nname = \"mathlib\"\nscope = \"leanprover-community\"\nrev = \"v4.14.0\"\n";
+        let out = pin_mathlib_rev(toml, true, "v4.15.0");
+        assert!(out.contains("name = \"batteries\"\nrev = \"main\""));
+        assert!(out.ends_with("rev = \"v4.15.0\"\n"));
+
+        let cfg: crate::config::ProofpatchConfig = toml::from_str(
+            "[[verify.matrix]]\nname = \"nightly\"\ntoolchain = \"leanprover/lean4:nightly\"\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.verify.matrix[0].name, "nightly");
+    }
+}
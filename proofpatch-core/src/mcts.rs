@@ -0,0 +1,811 @@
+//! Monte-Carlo tree search over tactic states, for proofs the beam search gives up on.
+//!
+//! Nodes are proof states reached by a tactic from their parent (run in the REPL sandbox through
+//! `beam::Transitions`). Each iteration descends from the root by PUCT (mean value plus an
+//! exploration bonus weighted by the policy's prior), expands the leaf with every tactic the
+//! `Policy` proposes, evaluates the new states with a `ValueFn` (in [0, 1]), and backs the best
+//! child's value up the path. Tactics that fail, and states repeating an ancestor or a sibling,
+//! are dropped; a node left without children is dead, and so is one whose children all are.
+//!
+//! Policies: `HeuristicPolicy` (`beam::step_tactics`) and `LlmPolicy` (next tactics asked of the
+//! model, the heuristics after them). Values: `HeuristicValue`, goal progress relative to the
+//! root, lifted when SMT finds the first goal LIA-entailed and cut when it is refuted.
+//!
+//! The tree (`SearchTree`) is serializable and saved to `MctsOptions::checkpoint` every
+//! `checkpoint_every` iterations and when a run stops, so a search stopped by its budgets can be
+//! resumed. REPL proof-state ids do not survive the session, so a resumed search replays the
+//! tactics leading to a node the first time it descends there.
+
+use crate::beam::{goals_key, Placeholder, Transitions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Proposal {
+    pub tactic: String,
+    /// Prior probability the tactic is the right next step; priors of one proposal sum to 1.
+    pub prior: f64,
+}
+
+pub type ProposeFuture<'a> = Pin<Box<dyn Future<Output = Vec<Proposal>> + Send + 'a>>;
+
+/// Proposes next tactics for a state (its goals).
+pub trait Policy: Send + Sync {
+    fn propose<'a>(&'a self, goals: &'a [String]) -> ProposeFuture<'a>;
+}
+
+/// How promising a state is, in [0, 1] (1: solved). `root` holds the goals the search started
+/// from.
+pub trait ValueFn: Send + Sync {
+    fn value(&self, goals: &[String], root: &[String]) -> f64;
+}
+
+/// Proposals from `(tactic, weight)` pairs: duplicates dropped, weights normalized into priors.
+pub fn with_priors(weighted: Vec<(String, f64)>) -> Vec<Proposal> {
+    let mut seen = HashSet::new();
+    let mut out: Vec<Proposal> = weighted
+        .into_iter()
+        .filter(|(t, _)| seen.insert(t.clone()))
+        .map(|(tactic, prior)| Proposal { tactic, prior })
+        .collect();
+    let total: f64 = out.iter().map(|p| p.prior).sum();
+    if total > 0.0 {
+        for p in &mut out {
+            p.prior /= total;
+        }
+    }
+    out
+}
+
+/// Rank-decaying weights (`scale / (rank + 1)`) for tactics listed best first.
+fn ranked(tactics: Vec<String>, scale: f64) -> Vec<(String, f64)> {
+    tactics
+        .into_iter()
+        .enumerate()
+        .map(|(i, t)| (t, scale / (i + 1) as f64))
+        .collect()
+}
+
+/// `beam::step_tactics`, earlier ones more likely.
+#[derive(Debug, Clone)]
+pub struct HeuristicPolicy {
+    pub expansions: usize,
+}
+
+impl Default for HeuristicPolicy {
+    fn default() -> Self {
+        Self { expansions: 12 }
+    }
+}
+
+impl Policy for HeuristicPolicy {
+    fn propose<'a>(&'a self, goals: &'a [String]) -> ProposeFuture<'a> {
+        let mut tactics = crate::beam::step_tactics(goals);
+        tactics.truncate(self.expansions);
+        Box::pin(async move { with_priors(ranked(tactics, 1.0)) })
+    }
+}
+
+const LLM_POLICY_SYSTEM: &str = "You are proposing the next step of a Lean 4 tactic proof. Given \
+the current goals, reply with a JSON array of single tactics (strings) to try next, most \
+promising first. Each must apply to the first goal as is; do not close the proof with `sorry`.";
+
+/// Next tactics asked of the configured model, with `beam::step_tactics` after them (at a
+/// quarter of the weight) when `fallback` is set or the model does not answer.
+#[derive(Debug, Clone)]
+pub struct LlmPolicy {
+    pub timeout: Duration,
+    pub expansions: usize,
+    pub fallback: bool,
+}
+
+impl Default for LlmPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(60),
+            expansions: 8,
+            fallback: true,
+        }
+    }
+}
+
+impl Policy for LlmPolicy {
+    fn propose<'a>(&'a self, goals: &'a [String]) -> ProposeFuture<'a> {
+        Box::pin(async move {
+            let system =
+                crate::prompts::system_prompt(crate::prompts::Task::Patch, LLM_POLICY_SYSTEM);
+            let user = format!(
+                "Goals:\n{}\n\nReply with a JSON array of up to {} next tactics.",
+                goals.join("\n\n"),
+                self.expansions
+            );
+            let opts = crate::llm::ChatOptions::default();
+            let mut tactics: Vec<String> =
+                crate::llm::chat_completion_with(&system, &user, self.timeout, &opts)
+                    .await
+                    .ok()
+                    .and_then(|r| crate::tree_search::parse_json_string_array(&r.content))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|t| crate::ranking::well_formed(t))
+                    .collect();
+            tactics.truncate(self.expansions);
+            let asked = !tactics.is_empty();
+            let mut weighted = ranked(tactics, 1.0);
+            if self.fallback || !asked {
+                let mut heur = crate::beam::step_tactics(goals);
+                heur.truncate(self.expansions);
+                weighted.extend(ranked(heur, if asked { 0.25 } else { 1.0 }));
+            }
+            with_priors(weighted)
+        })
+    }
+}
+
+/// Goal progress: `w(root) / (w(root) + w(goals))`, where `w` counts goals plus their size in
+/// units of 200 characters, so the root is worth 0.5 and no goals 1. With `smt`, the first
+/// goal's LIA check lifts the value to at least 0.9 when entailed and quarters it when refuted.
+#[derive(Debug, Clone)]
+pub struct HeuristicValue {
+    pub smt: bool,
+    pub smt_timeout_ms: u64,
+}
+
+impl Default for HeuristicValue {
+    fn default() -> Self {
+        Self {
+            smt: false,
+            smt_timeout_ms: 2_000,
+        }
+    }
+}
+
+fn goal_weight(goals: &[String]) -> f64 {
+    let size: usize = goals.iter().map(|g| g.chars().count()).sum();
+    goals.len() as f64 + size as f64 / 200.0
+}
+
+impl ValueFn for HeuristicValue {
+    fn value(&self, goals: &[String], root: &[String]) -> f64 {
+        if goals.is_empty() {
+            return 1.0;
+        }
+        let r = goal_weight(root).max(1.0);
+        let v = r / (r + goal_weight(goals));
+        if !self.smt {
+            return v;
+        }
+        let pp = crate::lean_lsp::pp_dump_from_plain_goals(&goals[..1]);
+        match crate::smt_lia::entails_from_pp_dump(&pp, self.smt_timeout_ms, 0) {
+            Ok(Some(true)) => v.max(0.9),
+            Ok(Some(false)) => v * 0.25,
+            _ => v,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeNode {
+    pub parent: Option<usize>,
+    /// The tactic leading here from the parent (`None` at the root).
+    pub tactic: Option<String>,
+    pub goals: Vec<String>,
+    pub prior: f64,
+    pub visits: u32,
+    pub value_sum: f64,
+    pub children: Vec<usize>,
+    pub expanded: bool,
+    /// No goals left.
+    pub solved: bool,
+    /// Nothing left to explore below.
+    pub dead: bool,
+    /// REPL proof state, valid in the current session only.
+    #[serde(skip)]
+    state: Option<u64>,
+}
+
+impl TreeNode {
+    fn new(parent: Option<usize>, tactic: Option<String>, goals: Vec<String>, prior: f64) -> Self {
+        Self {
+            parent,
+            tactic,
+            goals,
+            prior,
+            visits: 0,
+            value_sum: 0.0,
+            children: Vec::new(),
+            expanded: false,
+            solved: false,
+            dead: false,
+            state: None,
+        }
+    }
+
+    /// Mean value (0 before the first visit).
+    pub fn q(&self) -> f64 {
+        if self.visits == 0 {
+            0.0
+        } else {
+            self.value_sum / self.visits as f64
+        }
+    }
+}
+
+/// The search tree; `nodes[0]` is the root. Saved and loaded as JSON for checkpoints.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchTree {
+    pub version: u32,
+    pub nodes: Vec<TreeNode>,
+    /// Iterations and tactic runs across all runs on this tree.
+    pub iterations: usize,
+    pub transitions: usize,
+    pub elapsed_ms: u64,
+    /// The solved node, once found.
+    #[serde(default)]
+    pub solution: Option<usize>,
+}
+
+impl SearchTree {
+    pub fn new(goals: Vec<String>) -> Self {
+        Self {
+            version: CHECKPOINT_VERSION,
+            nodes: vec![TreeNode::new(None, None, goals, 1.0)],
+            iterations: 0,
+            transitions: 0,
+            elapsed_ms: 0,
+            solution: None,
+        }
+    }
+
+    pub fn root_goals(&self) -> &[String] {
+        &self.nodes[0].goals
+    }
+
+    /// Tactics from the root to `node`.
+    pub fn steps(&self, node: usize) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut i = node;
+        while let (Some(p), Some(t)) = (self.nodes[i].parent, &self.nodes[i].tactic) {
+            out.push(t.clone());
+            i = p;
+        }
+        out.reverse();
+        out
+    }
+
+    fn path(&self, node: usize) -> Vec<usize> {
+        let mut out = vec![node];
+        let mut i = node;
+        while let Some(p) = self.nodes[i].parent {
+            out.push(p);
+            i = p;
+        }
+        out.reverse();
+        out
+    }
+
+    /// The solution, or else the end of the most visited path.
+    pub fn best_node(&self) -> usize {
+        if let Some(s) = self.solution {
+            return s;
+        }
+        let mut i = 0;
+        while let Some(&c) = self.nodes[i]
+            .children
+            .iter()
+            .max_by_key(|&&c| self.nodes[c].visits)
+        {
+            i = c;
+        }
+        i
+    }
+
+    /// Mark `node` dead, and each ancestor whose children are now all dead.
+    fn kill(&mut self, node: usize) {
+        let mut i = node;
+        loop {
+            self.nodes[i].dead = true;
+            let Some(p) = self.nodes[i].parent else {
+                break;
+            };
+            if !self.nodes[p].children.iter().all(|&c| self.nodes[c].dead) {
+                break;
+            }
+            i = p;
+        }
+    }
+
+    fn backprop(&mut self, path: &[usize], value: f64) {
+        for &i in path {
+            self.nodes[i].visits += 1;
+            self.nodes[i].value_sum += value;
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        crate::checkpoint::save_json(path, self)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let s =
+            std::fs::read_to_string(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+        let tree: Self = serde_json::from_str(&s)
+            .map_err(|e| format!("parse checkpoint {}: {e}", path.display()))?;
+        if tree.version != CHECKPOINT_VERSION || tree.nodes.is_empty() {
+            return Err(format!(
+                "checkpoint {}: unsupported version {}",
+                path.display(),
+                tree.version
+            ));
+        }
+        Ok(tree)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MctsOptions {
+    /// Iterations per run.
+    pub iterations: usize,
+    /// Tactic runs per run (checked between iterations).
+    pub max_transitions: usize,
+    pub time_budget: Duration,
+    pub step_timeout: Duration,
+    /// Exploration constant of PUCT.
+    pub exploration: f64,
+    /// Tactics in a script at most.
+    pub max_depth: usize,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_every: usize,
+    /// Confirm a solution with `verify_lean_text` (`mcts_search_decl`).
+    pub confirm: bool,
+}
+
+impl Default for MctsOptions {
+    fn default() -> Self {
+        Self {
+            iterations: 200,
+            max_transitions: 1_000,
+            time_budget: Duration::from_secs(600),
+            step_timeout: Duration::from_secs(20),
+            exploration: 1.5,
+            max_depth: 12,
+            checkpoint: None,
+            checkpoint_every: 10,
+            confirm: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MctsOutcome {
+    pub solved: bool,
+    /// The solving script, or (unsolved) the most visited one.
+    pub steps: Vec<String>,
+    /// Goals left after `steps`.
+    pub goals: Vec<String>,
+    /// This run's iterations and tactic runs (the tree keeps the totals).
+    pub iterations: usize,
+    pub transitions: usize,
+    pub nodes: usize,
+    /// "solved", "exhausted" (the root is dead), "iterations", "max_transitions",
+    /// "time_budget", or "repl_error".
+    pub stop_reason: String,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Whether the run continued a checkpointed tree.
+    pub resumed: bool,
+    pub elapsed_ms: u64,
+}
+
+impl MctsOutcome {
+    pub fn script(&self) -> String {
+        crate::beam::render_script(&self.steps)
+    }
+}
+
+enum Step {
+    Ok(crate::repl::TacticResponse),
+    Failed,
+    Died(String),
+}
+
+async fn run_step(env: &mut dyn Transitions, state: u64, tactic: &str) -> Step {
+    match env.step(state, tactic).await {
+        Ok(r) if r.messages.iter().any(|m| m.severity == "error") => Step::Failed,
+        Ok(r) => Step::Ok(r),
+        Err(_) if env.alive() => Step::Failed,
+        Err(e) => Step::Died(e),
+    }
+}
+
+/// The REPL state of `node`, replaying tactics from its nearest ancestor that has one. A replay
+/// that no longer applies kills the node.
+async fn materialize(
+    env: &mut dyn Transitions,
+    tree: &mut SearchTree,
+    node: usize,
+    transitions: &mut usize,
+) -> Result<Option<u64>, String> {
+    let path = tree.path(node);
+    let start = path
+        .iter()
+        .rposition(|&i| tree.nodes[i].state.is_some())
+        .ok_or("mcts: no state at the root")?;
+    let mut state = tree.nodes[path[start]].state.unwrap_or_default();
+    for &i in &path[start + 1..] {
+        let tactic = tree.nodes[i].tactic.clone().unwrap_or_default();
+        *transitions += 1;
+        let next = match run_step(env, state, &tactic).await {
+            Step::Ok(r) => r.proof_state,
+            Step::Failed => None,
+            Step::Died(e) => return Err(e),
+        };
+        let Some(next) = next else {
+            tree.kill(i);
+            return Ok(None);
+        };
+        tree.nodes[i].state = Some(next);
+        state = next;
+    }
+    Ok(Some(state))
+}
+
+fn select(tree: &SearchTree, c: f64) -> Vec<usize> {
+    let mut path = vec![0];
+    let mut i = 0;
+    while tree.nodes[i].expanded {
+        let sqrt_n = (tree.nodes[i].visits.max(1) as f64).sqrt();
+        let best = tree.nodes[i]
+            .children
+            .iter()
+            .copied()
+            .filter(|&k| !tree.nodes[k].dead)
+            .max_by(|&a, &b| {
+                let u = |k: usize| {
+                    let n = &tree.nodes[k];
+                    n.q() + c * n.prior * sqrt_n / (1.0 + n.visits as f64)
+                };
+                u(a).total_cmp(&u(b))
+            });
+        let Some(k) = best else {
+            break;
+        };
+        path.push(k);
+        i = k;
+    }
+    path
+}
+
+/// Run MCTS on `tree` (fresh from `SearchTree::new`, or loaded from a checkpoint) from `root`,
+/// the REPL state of its root in `env`.
+pub async fn mcts_search(
+    env: &mut dyn Transitions,
+    root: u64,
+    tree: &mut SearchTree,
+    policy: &dyn Policy,
+    value: &dyn ValueFn,
+    opts: &MctsOptions,
+) -> MctsOutcome {
+    let t0 = Instant::now();
+    let resumed = tree.iterations > 0;
+    for n in &mut tree.nodes {
+        n.state = None;
+    }
+    tree.nodes[0].state = Some(root);
+    let root_goals = tree.root_goals().to_vec();
+    let mut out = MctsOutcome {
+        solved: false,
+        steps: Vec::new(),
+        goals: Vec::new(),
+        iterations: 0,
+        transitions: 0,
+        nodes: 0,
+        stop_reason: "iterations".to_string(),
+        error: None,
+        resumed,
+        elapsed_ms: 0,
+    };
+    let save = |tree: &SearchTree| {
+        if let Some(p) = &opts.checkpoint {
+            // A failed save loses progress, not the search.
+            let _ = tree.save(p);
+        }
+    };
+
+    loop {
+        if tree.solution.is_some() {
+            out.stop_reason = "solved".to_string();
+            break;
+        }
+        if tree.nodes[0].dead {
+            out.stop_reason = "exhausted".to_string();
+            break;
+        }
+        if out.iterations >= opts.iterations {
+            out.stop_reason = "iterations".to_string();
+            break;
+        }
+        if out.transitions >= opts.max_transitions {
+            out.stop_reason = "max_transitions".to_string();
+            break;
+        }
+        if t0.elapsed() >= opts.time_budget {
+            out.stop_reason = "time_budget".to_string();
+            break;
+        }
+        out.iterations += 1;
+        tree.iterations += 1;
+        if opts.checkpoint_every > 0 && out.iterations.is_multiple_of(opts.checkpoint_every) {
+            save(tree);
+        }
+
+        let path = select(tree, opts.exploration);
+        let leaf = *path.last().unwrap_or(&0);
+        if tree.nodes[leaf].expanded || path.len() > opts.max_depth {
+            tree.kill(leaf);
+            tree.backprop(&path, 0.0);
+            continue;
+        }
+        let before = out.transitions;
+        let state = materialize(env, tree, leaf, &mut out.transitions).await;
+        tree.transitions += out.transitions - before;
+        let state = match state {
+            Ok(Some(s)) => s,
+            Ok(None) => {
+                tree.backprop(&path, 0.0);
+                continue;
+            }
+            Err(e) => {
+                out.stop_reason = "repl_error".to_string();
+                out.error = Some(e);
+                break;
+            }
+        };
+
+        let goals = tree.nodes[leaf].goals.clone();
+        let mut seen: HashSet<String> = path
+            .iter()
+            .map(|&i| goals_key(&tree.nodes[i].goals))
+            .collect();
+        let mut best = 0.0f64;
+        let mut died = None;
+        for p in policy.propose(&goals).await {
+            out.transitions += 1;
+            tree.transitions += 1;
+            let r = match run_step(env, state, &p.tactic).await {
+                Step::Ok(r) => r,
+                Step::Failed => continue,
+                Step::Died(e) => {
+                    died = Some(e);
+                    break;
+                }
+            };
+            let solved = r.solved();
+            if !solved && (r.proof_state.is_none() || !seen.insert(goals_key(&r.goals))) {
+                continue;
+            }
+            let mut child = TreeNode::new(Some(leaf), Some(p.tactic), r.goals, p.prior);
+            child.state = r.proof_state;
+            child.solved = solved;
+            let v = if solved {
+                1.0
+            } else {
+                value.value(&child.goals, &root_goals)
+            };
+            child.visits = 1;
+            child.value_sum = v;
+            best = best.max(v);
+            let k = tree.nodes.len();
+            tree.nodes.push(child);
+            tree.nodes[leaf].children.push(k);
+            if solved {
+                tree.solution = Some(k);
+                break;
+            }
+        }
+        if let Some(e) = died {
+            // The leaf stays unexpanded, to be expanded again on resume.
+            for k in std::mem::take(&mut tree.nodes[leaf].children) {
+                tree.nodes[k].dead = true;
+            }
+            out.stop_reason = "repl_error".to_string();
+            out.error = Some(e);
+            break;
+        }
+        tree.nodes[leaf].expanded = true;
+        if tree.nodes[leaf].children.is_empty() {
+            tree.kill(leaf);
+        }
+        tree.backprop(&path, best);
+    }
+
+    tree.elapsed_ms += t0.elapsed().as_millis() as u64;
+    save(tree);
+    let best = tree.best_node();
+    out.solved = tree.solution.is_some();
+    out.steps = tree.steps(best);
+    out.goals = tree.nodes[best].goals.clone();
+    out.nodes = tree.nodes.len();
+    out.elapsed_ms = t0.elapsed().as_millis() as u64;
+    out
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MctsReport {
+    pub file: String,
+    pub decl: String,
+    pub outcome: MctsOutcome,
+    #[serde(default)]
+    pub verified: Option<bool>,
+    #[serde(skip)]
+    pub patched_text: Option<String>,
+}
+
+/// MCTS for the placeholder of `decl_name` in `text` (the contents of `file_rel`), resuming
+/// from `opts.checkpoint` when it exists.
+pub async fn mcts_search_decl(
+    repo_root: &Path,
+    file_rel: &str,
+    text: &str,
+    decl_name: &str,
+    policy: &dyn Policy,
+    value: &dyn ValueFn,
+    opts: &MctsOptions,
+) -> Result<MctsReport, String> {
+    let setup = opts.time_budget.max(opts.step_timeout);
+    let Placeholder {
+        verifier: mut w,
+        root,
+        goals,
+        hole_line,
+    } = crate::beam::open_placeholder(repo_root, file_rel, text, decl_name, setup).await?;
+    let mut tree = match opts.checkpoint.as_deref().filter(|p| p.exists()) {
+        Some(p) => {
+            let tree = SearchTree::load(p)?;
+            if goals_key(tree.root_goals()) != goals_key(&goals) {
+                w.close().await;
+                return Err(format!(
+                    "checkpoint {} is for a different goal",
+                    p.display()
+                ));
+            }
+            tree
+        }
+        None => SearchTree::new(goals),
+    };
+    w.session().set_timeout(opts.step_timeout);
+    let outcome = mcts_search(w.session(), root, &mut tree, policy, value, opts).await;
+    w.close().await;
+
+    let mut report = MctsReport {
+        file: file_rel.to_string(),
+        decl: decl_name.to_string(),
+        outcome,
+        verified: None,
+        patched_text: None,
+    };
+    if report.outcome.solved {
+        let confirm = opts.confirm.then_some(opts.step_timeout * 6);
+        let (patched, verified) = crate::beam::patch_placeholder(
+            repo_root,
+            text,
+            decl_name,
+            &report.outcome.script(),
+            hole_line,
+            confirm,
+        )
+        .await?;
+        report.verified = verified;
+        report.patched_text = (verified != Some(false)).then_some(patched);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beam::StepFuture;
+    use crate::repl::{ReplMessage, TacticResponse};
+    use std::collections::HashMap;
+
+    /// States are goal lists; a tactic rewrites the first goal, or fails.
+    struct Toy {
+        states: Vec<Vec<String>>,
+        rules: HashMap<(&'static str, &'static str), Vec<&'static str>>,
+    }
+
+    impl Transitions for Toy {
+        fn step<'a>(&'a mut self, proof_state: u64, tactic: &'a str) -> StepFuture<'a> {
+            Box::pin(async move {
+                let goals = &self.states[proof_state as usize];
+                let Some(new) = goals
+                    .first()
+                    .and_then(|g| self.rules.get(&(g.as_str(), tactic)))
+                else {
+                    return Ok(TacticResponse {
+                        messages: vec![ReplMessage {
+                            severity: "error".into(),
+                            pos: Default::default(),
+                            end_pos: None,
+                            data: "failed".into(),
+                        }],
+                        ..Default::default()
+                    });
+                };
+                let mut goals: Vec<String> = new.iter().map(|s| s.to_string()).collect();
+                goals.extend(self.states[proof_state as usize][1..].iter().cloned());
+                self.states.push(goals.clone());
+                Ok(TacticResponse {
+                    proof_state: Some(self.states.len() as u64 - 1),
+                    goals,
+                    messages: Vec::new(),
+                })
+            })
+        }
+    }
+
+    fn toy() -> Toy {
+        Toy {
+            states: vec![vec!["⊢ ∀ n, P n ∧ Q n".to_string()]],
+            rules: HashMap::from([
+                (("⊢ ∀ n, P n ∧ Q n", "intro"), vec!["n : ℕ\n⊢ P n ∧ Q n"]),
+                (("⊢ ∀ n, P n ∧ Q n", "simp"), vec!["⊢ ∀ n, P n ∧ Q n"]),
+                (
+                    ("n : ℕ\n⊢ P n ∧ Q n", "constructor"),
+                    vec!["n : ℕ\n⊢ P n", "n : ℕ\n⊢ Q n"],
+                ),
+                (("n : ℕ\n⊢ P n", "simp"), vec![]),
+                (("n : ℕ\n⊢ Q n", "omega"), vec![]),
+            ]),
+        }
+    }
+
+    #[tokio::test]
+    async fn solves_and_resumes_from_a_checkpoint() {
+        let goals = vec!["⊢ ∀ n, P n ∧ Q n".to_string()];
+        let dir = tempfile::tempdir().unwrap();
+        let ckpt = dir.path().join("mcts.json");
+        let (policy, value) = (HeuristicPolicy::default(), HeuristicValue::default());
+
+        // Pause after two iterations; the checkpoint holds the tree so far.
+        let opts = MctsOptions {
+            iterations: 2,
+            checkpoint: Some(ckpt.clone()),
+            ..Default::default()
+        };
+        let mut tree = SearchTree::new(goals.clone());
+        let out = mcts_search(&mut toy(), 0, &mut tree, &policy, &value, &opts).await;
+        assert!(!out.solved, "{out:?}");
+        assert_eq!(out.stop_reason, "iterations");
+        assert_eq!(out.steps[0], "intro");
+
+        // Resume in a fresh sandbox: states are rebuilt by replaying tactics.
+        let mut tree = SearchTree::load(&ckpt).unwrap();
+        assert_eq!(tree.iterations, 2);
+        let opts = MctsOptions {
+            iterations: 50,
+            ..opts
+        };
+        let out = mcts_search(&mut toy(), 0, &mut tree, &policy, &value, &opts).await;
+        assert!(out.solved && out.resumed, "{out:?}");
+        assert_eq!(out.steps, ["intro", "constructor", "simp", "omega"]);
+        assert!(out.goals.is_empty());
+        assert!(SearchTree::load(&ckpt).unwrap().solution.is_some());
+
+        let p = with_priors(vec![
+            ("a".into(), 1.0),
+            ("b".into(), 3.0),
+            ("a".into(), 5.0),
+        ]);
+        assert_eq!(p.len(), 2);
+        assert_eq!(p[1].prior, 0.75);
+        assert_eq!(value.value(&goals, &goals), 0.5);
+        assert_eq!(value.value(&[], &goals), 1.0);
+    }
+}
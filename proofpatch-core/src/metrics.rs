@@ -0,0 +1,337 @@
+//! Proof complexity metrics.
+//!
+//! Purely lexical: no elaboration, so it is cheap enough to run on every candidate. `ProofMetrics`
+//! counts lines, tactics (one per tactic step, splitting on newlines, `;`, and `<;>`), `simp`-family
+//! calls, term size (identifier/literal/notation tokens), and the distinct global names the proof
+//! refers to (dependency fan-in). `score` folds these into one number for ranking; `compare` gives
+//! the before/after delta for a replaced proof.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofMetrics {
+    /// Non-blank, non-comment lines.
+    pub lines: usize,
+    /// Characters after stripping comments and collapsing whitespace.
+    pub chars: usize,
+    pub tactic_count: usize,
+    /// `simp`, `simp only`, `simp_all`, `simpa`, `dsimp`, `simp?`, …
+    pub simp_calls: usize,
+    /// Identifier, literal, and notation tokens.
+    pub term_size: usize,
+    /// Distinct global names referenced (qualified, capitalized, or snake_case lemma names).
+    pub dependencies: Vec<String>,
+}
+
+impl ProofMetrics {
+    pub fn fan_in(&self) -> usize {
+        self.dependencies.len()
+    }
+
+    /// Lower is simpler. Tactic steps dominate; `simp` calls are cheap to write but slow and
+    /// brittle, so they cost a little extra.
+    pub fn score(&self) -> f64 {
+        self.tactic_count as f64 * 2.0
+            + self.simp_calls as f64
+            + self.term_size as f64 * 0.25
+            + self.fan_in() as f64
+            + self.lines as f64 * 0.5
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsDelta {
+    pub before: ProofMetrics,
+    pub after: ProofMetrics,
+    pub lines: i64,
+    pub tactic_count: i64,
+    pub simp_calls: i64,
+    pub term_size: i64,
+    pub fan_in: i64,
+    pub score: f64,
+}
+
+/// Keywords and tactic names that are not dependencies.
+const NON_DEPS: &[&str] = &[
+    "by",
+    "at",
+    "with",
+    "fun",
+    "show",
+    "from",
+    "have",
+    "this",
+    "calc",
+    "using",
+    "only",
+    "then",
+    "else",
+    "if",
+    "match",
+    "let",
+    "in",
+    "do",
+    "intro",
+    "intros",
+    "exact",
+    "apply",
+    "refine",
+    "rw",
+    "rwa",
+    "simp",
+    "simp_all",
+    "simpa",
+    "dsimp",
+    "omega",
+    "linarith",
+    "nlinarith",
+    "norm_num",
+    "norm_cast",
+    "push_cast",
+    "ring",
+    "ring_nf",
+    "field_simp",
+    "positivity",
+    "aesop",
+    "decide",
+    "rfl",
+    "constructor",
+    "cases",
+    "rcases",
+    "obtain",
+    "induction",
+    "use",
+    "exists",
+    "ext",
+    "funext",
+    "congr",
+    "gcongr",
+    "trivial",
+    "exfalso",
+    "contradiction",
+    "assumption",
+    "specialize",
+    "classical",
+    "done",
+    "sorry",
+    "admit",
+    "unfold",
+    "split",
+    "left",
+    "right",
+    "subst",
+    "symm",
+    "trans",
+    "change",
+    "convert",
+    "generalize",
+    "revert",
+    "clear",
+    "set",
+    "all_goals",
+    "any_goals",
+    "first",
+    "try",
+    "repeat",
+    "case",
+    "next",
+    "suffices",
+    "exact_mod_cast",
+    "infer_instance",
+    "tauto",
+    "rintro",
+    "nth_rewrite",
+    "nth_rw",
+    "conv",
+    "lhs",
+    "rhs",
+    "arg",
+    "Type",
+    "Prop",
+    "Sort",
+];
+
+/// Drop `--` line comments and `/- … -/` block comments.
+fn strip_comments(proof: &str) -> String {
+    let mut out = String::with_capacity(proof.len());
+    let mut depth = 0usize;
+    let mut it = proof.chars().peekable();
+    while let Some(c) = it.next() {
+        match (c, it.peek().copied()) {
+            ('/', Some('-')) => {
+                it.next();
+                depth += 1;
+            }
+            ('-', Some('/')) if depth > 0 => {
+                it.next();
+                depth -= 1;
+            }
+            ('-', Some('-')) if depth == 0 => {
+                for n in it.by_ref() {
+                    if n == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            _ if depth > 0 => {
+                if c == '\n' {
+                    out.push('\n');
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '\'' | '?' | '!')
+}
+
+fn tokens(s: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    for c in s.chars() {
+        if is_ident_char(c) {
+            cur.push(c);
+            continue;
+        }
+        if !cur.is_empty() {
+            out.push(std::mem::take(&mut cur));
+        }
+        if !c.is_whitespace() && !matches!(c, '(' | ')' | '[' | ']' | '{' | '}' | ',' | '·') {
+            out.push(c.to_string());
+        }
+    }
+    if !cur.is_empty() {
+        out.push(cur);
+    }
+    out
+}
+
+fn is_simp(tok: &str) -> bool {
+    let t = tok.trim_end_matches(['?', '!']);
+    matches!(
+        t,
+        "simp" | "simp_all" | "simpa" | "dsimp" | "simp_rw" | "simp_arith"
+    )
+}
+
+fn looks_like_global(tok: &str) -> bool {
+    let t = tok.trim_start_matches('@').trim_end_matches(['?', '!']);
+    let first = t.chars().next().unwrap_or(' ');
+    if !first.is_alphabetic() || NON_DEPS.contains(&t) {
+        return false;
+    }
+    // `h.1`, `hx.le`: projections on locals are not dependencies.
+    let head = t.split('.').next().unwrap_or(t);
+    if t.contains('.') && head.chars().next().is_some_and(char::is_lowercase) && head.len() <= 3 {
+        return false;
+    }
+    t.contains('.') || first.is_uppercase() || (t.contains('_') && t.len() > 3)
+}
+
+/// Compute metrics for a proof term or tactic block (with or without the leading `by`).
+pub fn proof_metrics(proof: &str) -> ProofMetrics {
+    let text = strip_comments(proof);
+    let code_lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+
+    let mut tactic_count = 0usize;
+    let mut simp_calls = 0usize;
+    let mut term_size = 0usize;
+    let mut deps = BTreeSet::new();
+    for line in &code_lines {
+        for step in line.split("<;>").flat_map(|s| s.split(';')) {
+            let step = step.trim().trim_start_matches(['·', '(', ')']).trim();
+            let step = match step.strip_prefix("by") {
+                Some(r) if r.is_empty() || r.starts_with(char::is_whitespace) => r.trim(),
+                _ => step,
+            };
+            let toks = tokens(step);
+            let Some(head) = toks.first() else {
+                continue;
+            };
+            if head.chars().next().is_some_and(char::is_alphabetic) && head != "done" {
+                tactic_count += 1;
+            }
+            for t in &toks {
+                if is_simp(t) {
+                    simp_calls += 1;
+                }
+                if looks_like_global(t) {
+                    deps.insert(t.trim_start_matches('@').to_string());
+                }
+            }
+            term_size += toks.len();
+        }
+    }
+    ProofMetrics {
+        lines: code_lines.len(),
+        chars: text.split_whitespace().collect::<Vec<_>>().join(" ").len(),
+        tactic_count,
+        simp_calls,
+        term_size,
+        dependencies: deps.into_iter().collect(),
+    }
+}
+
+/// Before/after delta (`after - before`; negative means the new proof is simpler).
+pub fn compare(before: &str, after: &str) -> MetricsDelta {
+    let (b, a) = (proof_metrics(before), proof_metrics(after));
+    let d = |x: usize, y: usize| y as i64 - x as i64;
+    MetricsDelta {
+        lines: d(b.lines, a.lines),
+        tactic_count: d(b.tactic_count, a.tactic_count),
+        simp_calls: d(b.simp_calls, a.simp_calls),
+        term_size: d(b.term_size, a.term_size),
+        fan_in: d(b.fan_in(), a.fan_in()),
+        score: a.score() - b.score(),
+        before: b,
+        after: a,
+    }
+}
+
+/// Stable sort of `(source, candidate)` pairs, simplest first.
+pub fn rank_by_simplicity(mut cands: Vec<(String, String)>) -> Vec<(String, String)> {
+    let mut scored: Vec<(f64, (String, String))> = cands
+        .drain(..)
+        .map(|c| (proof_metrics(&c.1).score(), c))
+        .collect();
+    scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+    scored.into_iter().map(|(_, c)| c).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_tactics_simp_and_dependencies() {
+        let p = "by
+  -- normalize first
+  intro n
+  simp only [Nat.add_zero, mul_comm] at h ⊢
+  constructor <;> simp
+  · exact Nat.le_of_lt h.1
+  · omega";
+        let m = proof_metrics(p);
+        assert_eq!(m.lines, 6);
+        assert_eq!(m.tactic_count, 6);
+        assert_eq!(m.simp_calls, 2);
+        assert_eq!(
+            m.dependencies,
+            vec!["Nat.add_zero", "Nat.le_of_lt", "mul_comm"]
+        );
+
+        let d = compare(p, "by\n  omega");
+        assert!(d.score < 0.0 && d.tactic_count == -5 && d.fan_in == -3);
+
+        let r = rank_by_simplicity(vec![
+            ("a".into(), p.to_string()),
+            ("b".into(), "by\n  omega".into()),
+        ]);
+        assert_eq!(r[0].0, "b");
+    }
+}
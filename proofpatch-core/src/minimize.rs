@@ -0,0 +1,327 @@
+//! Shrink a proof that already compiles.
+//!
+//! Machine-found proofs tend to carry dead steps, oversized `simp only [...]` lists, and heavier
+//! automation than the goal needs. `minimize_proof` re-verifies the proof at its target with:
+//! 1. tactic steps removed (delta debugging over `replay::split_tactic_steps`, as in
+//!    `simp_sets::prune_lemmas`);
+//! 2. each remaining step's lemma list (`simp only [...]`, `linarith [...]`, …) pruned the same way;
+//! 3. each remaining step swapped for a lighter tactic (`nlinarith` → `linarith`, `aesop` → `simp`, …).
+//!
+//! Every trial is one `verify_lean_text` call, bounded by `MinimizeOptions::max_checks`; a change
+//! is kept only if the file still compiles without the edited declaration being admitted.
+
+use crate::diagnostics::{parse_diagnostics_from, Severity};
+use crate::metrics::MetricsDelta;
+use crate::patching::{apply_patch, PatchTarget};
+use crate::simp_sets::ddmin;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct MinimizeOptions {
+    pub timeout: Duration,
+    /// Total `verify_lean_text` calls, including the initial check.
+    pub max_checks: usize,
+    /// Compile the unmodified proof first (skip when the caller just verified it).
+    pub verify_initial: bool,
+}
+
+impl Default for MinimizeOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(120),
+            max_checks: 24,
+            verify_initial: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinimizeReport {
+    /// The original proof compiled (assumed when `verify_initial` is off).
+    pub initial_ok: bool,
+    pub original: String,
+    pub minimized: String,
+    pub removed_steps: Vec<String>,
+    pub removed_lemmas: Vec<String>,
+    /// `(before, after)` step replacements that were kept.
+    pub replaced: Vec<(String, String)>,
+    pub checks: usize,
+    /// Some trials were skipped because the budget ran out.
+    pub budget_exhausted: bool,
+    pub metrics: MetricsDelta,
+}
+
+/// Tactics on the bracketed-list steps whose lists are pruned.
+const LIST_TACTICS: &[&str] = &[
+    "simp",
+    "simp_all",
+    "simpa",
+    "dsimp",
+    "norm_num",
+    "field_simp",
+    "linarith",
+    "nlinarith",
+    "positivity",
+    "simp_arith",
+];
+
+/// Lighter replacements for a (single-line) step, cheapest first.
+pub fn lighter_alternatives(step: &str) -> Vec<String> {
+    if step.contains('\n') {
+        return Vec::new();
+    }
+    let t = step.trim();
+    let head = t.split_whitespace().next().unwrap_or("");
+    let has_args = t != head;
+    let alts: &[&str] = match head {
+        "nlinarith" | "polyrith" => &["linarith", "positivity"],
+        "linarith" if has_args => &["linarith"],
+        "aesop" | "tauto" => &["simp"],
+        "simp_all" => &["simp"],
+        "norm_num" | "simp_arith" if has_args => &["norm_num", "simp"],
+        "field_simp" => &["ring"],
+        "slim_check" | "decide" => &["rfl"],
+        _ => &[],
+    };
+    alts.iter()
+        .filter(|a| **a != t)
+        .map(|a| a.to_string())
+        .collect()
+}
+
+/// The bracketed argument list of a `simp only [...]`-style step: byte range of the brackets and
+/// the top-level items.
+fn step_list(step: &str) -> Option<(usize, usize, Vec<String>)> {
+    let first = step.lines().next()?;
+    let head = first.split_whitespace().next()?;
+    if !LIST_TACTICS.contains(&head.trim_end_matches('?')) {
+        return None;
+    }
+    let open = first.find('[')?;
+    let mut depth = 0usize;
+    let mut items = Vec::new();
+    let mut cur = String::new();
+    for (i, ch) in first[open..].char_indices() {
+        match ch {
+            '[' | '(' | '{' | '⟨' => {
+                depth += 1;
+                if depth == 1 {
+                    continue;
+                }
+            }
+            ']' | ')' | '}' | '⟩' => {
+                depth -= 1;
+                if depth == 0 {
+                    items.push(cur.trim().to_string());
+                    let items = items.into_iter().filter(|s| !s.is_empty()).collect();
+                    return Some((open, open + i + 1, items));
+                }
+            }
+            ',' if depth == 1 => {
+                items.push(std::mem::take(&mut cur).trim().to_string());
+                continue;
+            }
+            _ => {}
+        }
+        cur.push(ch);
+    }
+    None
+}
+
+/// `step` with its list replaced by `items` (the brackets go away when `items` is empty).
+fn with_list(step: &str, start: usize, end: usize, items: &[String]) -> String {
+    if items.is_empty() {
+        let before = step[..start].trim_end();
+        format!("{before}{}", &step[end..])
+    } else {
+        format!("{}[{}]{}", &step[..start], items.join(", "), &step[end..])
+    }
+}
+
+/// Steps back to a replacement: a tactic block in tactic position, else `by` + indented block.
+pub fn render_steps(steps: &[String], tactic_ctx: bool) -> String {
+    if tactic_ctx {
+        return steps.join("\n");
+    }
+    let mut out = String::from("by");
+    for s in steps {
+        for l in s.lines() {
+            out.push_str("\n  ");
+            out.push_str(l);
+        }
+    }
+    out
+}
+
+/// Minimize `proof` (which closes `target` in `text`, a file of the repo at `repo_root`).
+pub async fn minimize_proof(
+    repo_root: &Path,
+    text: &str,
+    target: &PatchTarget,
+    proof: &str,
+    opts: &MinimizeOptions,
+) -> Result<MinimizeReport, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let (start, _) = crate::patching::resolve_target(text, target)?;
+    let line = text[..start].matches('\n').count() + 1;
+    let line_text = text.lines().nth(line - 1).unwrap_or("");
+    let tactic_ctx = matches!(target, PatchTarget::DeclPlaceholder(_))
+        && crate::is_tactic_context_for_sorry(text, line, line_text);
+    let t = proof.trim();
+    let is_tactic_block = tactic_ctx
+        || t.strip_prefix("by")
+            .is_some_and(|r| r.is_empty() || r.starts_with(char::is_whitespace));
+
+    let checks = std::cell::Cell::new(0usize);
+    let exhausted = std::cell::Cell::new(false);
+    let run = |steps: Vec<String>| {
+        let repo_root = repo_root.clone();
+        let (checks, exhausted) = (&checks, &exhausted);
+        async move {
+            if steps.is_empty() {
+                return Some(false);
+            }
+            if checks.get() >= opts.max_checks {
+                exhausted.set(true);
+                return None;
+            }
+            checks.set(checks.get() + 1);
+            let Ok((patched, rec)) = apply_patch(text, target, &render_steps(&steps, tactic_ctx))
+            else {
+                return Some(false);
+            };
+            let vr = crate::verify_lean_text(&repo_root, &patched, opts.timeout)
+                .await
+                .ok()?;
+            let errors = parse_diagnostics_from(&vr.stdout, &vr.stderr)
+                .into_iter()
+                .filter(|d| d.severity == Severity::Error)
+                .count();
+            let merged = format!("{}\n{}", vr.stdout, vr.stderr);
+            let admitted = merged.lines().any(|l| {
+                l.contains(&format!(":{}:", rec.line)) && l.contains("declaration uses 'sorry'")
+            });
+            Some(vr.ok && errors == 0 && !admitted)
+        }
+    };
+
+    let original: Vec<String> = crate::replay::split_tactic_steps(proof);
+    let mut report = MinimizeReport {
+        initial_ok: true,
+        original: proof.to_string(),
+        minimized: proof.to_string(),
+        removed_steps: Vec::new(),
+        removed_lemmas: Vec::new(),
+        replaced: Vec::new(),
+        checks: 0,
+        budget_exhausted: false,
+        metrics: crate::metrics::compare(proof, proof),
+    };
+    if !is_tactic_block || original.is_empty() {
+        return Ok(report);
+    }
+    if opts.verify_initial {
+        report.initial_ok = run(original.clone()).await.unwrap_or(false);
+        if !report.initial_ok {
+            report.checks = checks.get();
+            return Ok(report);
+        }
+    }
+
+    // 1. Drop steps.
+    let mut steps = if original.len() > 1 {
+        ddmin(original.clone(), &run).await.0
+    } else {
+        original.clone()
+    };
+    report.removed_steps = original
+        .iter()
+        .filter(|s| !steps.contains(s))
+        .cloned()
+        .collect();
+
+    // 2. Prune lemma lists.
+    for i in 0..steps.len() {
+        let Some((a, b, items)) = step_list(&steps[i]) else {
+            continue;
+        };
+        let step = steps[i].clone();
+        let (kept, _) = ddmin(items.clone(), |subset: Vec<String>| {
+            let mut trial = steps.clone();
+            trial[i] = with_list(&step, a, b, &subset);
+            run(trial)
+        })
+        .await;
+        if kept.len() < items.len() {
+            report
+                .removed_lemmas
+                .extend(items.iter().filter(|x| !kept.contains(x)).cloned());
+            steps[i] = with_list(&step, a, b, &kept);
+        }
+    }
+
+    // 3. Lighter tactics.
+    for i in 0..steps.len() {
+        for alt in lighter_alternatives(&steps[i]) {
+            let mut trial = steps.clone();
+            trial[i] = alt.clone();
+            match run(trial).await {
+                Some(true) => {
+                    report.replaced.push((steps[i].clone(), alt.clone()));
+                    steps[i] = alt;
+                    break;
+                }
+                Some(false) => {}
+                None => break,
+            }
+        }
+    }
+
+    if steps != original {
+        report.minimized = render_steps(&steps, tactic_ctx);
+        report.metrics = crate::metrics::compare(proof, &report.minimized);
+    }
+    report.checks = checks.get();
+    report.budget_exhausted = exhausted.get();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_alternatives_and_rendering() {
+        let step = "simp only [Nat.add_comm, foo (bar x), List.length_append] at h ⊢";
+        let (a, b, items) = step_list(step).unwrap();
+        assert_eq!(
+            items,
+            vec!["Nat.add_comm", "foo (bar x)", "List.length_append"]
+        );
+        assert_eq!(
+            with_list(step, a, b, &items[1..2]),
+            "simp only [foo (bar x)] at h ⊢"
+        );
+        assert_eq!(with_list("linarith [h1, h2]", 9, 17, &[]), "linarith");
+        assert!(step_list("rw [h]").is_none());
+
+        assert_eq!(
+            lighter_alternatives("nlinarith [sq_nonneg x]"),
+            vec!["linarith", "positivity"]
+        );
+        assert_eq!(lighter_alternatives("linarith [h]"), vec!["linarith"]);
+        assert!(lighter_alternatives("linarith").is_empty());
+
+        let steps = crate::replay::split_tactic_steps(
+            "by\n  intro n\n  cases n with\n    | zero => simp\n    | succ k => omega",
+        );
+        assert_eq!(steps.len(), 2);
+        assert_eq!(
+            render_steps(&steps, false),
+            "by\n  intro n\n  cases n with\n    | zero => simp\n    | succ k => omega"
+        );
+        assert_eq!(render_steps(&steps[..1], true), "intro n");
+    }
+}
@@ -0,0 +1,277 @@
+//! Local variants of near-miss candidates, generated without another LLM round-trip.
+//!
+//! A candidate whose errors all fall in one tactic step (`failing_step`) came close: the steps
+//! before it ran. `mutate` rewrites that step with cheap operators:
+//! - `swap_lemma`: a lemma the step names is replaced by a sibling from premise selection
+//!   (`PremiseIndex::siblings`: the lemmas whose statements are most like it);
+//! - `simp_arith`: `simp` becomes `simp_arith`;
+//! - `add_omega`: `omega` runs after the step, for arithmetic it leaves behind;
+//! - `flip_rewrite`: one rule of a `rw [...]` is rewritten in the other direction.
+//!
+//! `unsolved goals` errors are reported at the `by`, not at a step; they are put on the last
+//! step. The repair loop verifies the variants of a failed candidate in the next round, as
+//! source "mutation" (`RepairOptions::mutations`).
+
+use crate::premise::PremiseIndex;
+use crate::replay::split_tactic_steps;
+use serde::{Deserialize, Serialize};
+
+/// Variants generated per failed candidate at most.
+pub const MAX_MUTANTS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mutant {
+    /// "swap_lemma", "simp_arith", "add_omega", or "flip_rewrite".
+    pub operator: String,
+    pub candidate: String,
+}
+
+/// The steps of `cand` (`replay::split_tactic_steps`) and the 1-based line of `cand` each
+/// starts on.
+fn step_lines(cand: &str) -> (Vec<String>, Vec<usize>) {
+    let steps = split_tactic_steps(cand);
+    let lines: Vec<&str> = cand.lines().collect();
+    let mut starts = Vec::with_capacity(steps.len());
+    let mut next = 0;
+    for s in &steps {
+        let first = s.lines().next().unwrap_or("").trim();
+        let at = (next..lines.len()).find(|&k| {
+            let l = lines[k].trim();
+            l == first || l.strip_prefix("by").map(str::trim) == Some(first)
+        });
+        let k = at.unwrap_or(next);
+        starts.push(k + 1);
+        next = k + 1;
+    }
+    (steps, starts)
+}
+
+/// The one step of `cand` that the errors at `error_lines` (1-based lines of `cand`) fall in;
+/// `None` when they fall in several. Errors on the `by` line count for the last step.
+pub fn failing_step(cand: &str, error_lines: &[usize]) -> Option<usize> {
+    let (steps, starts) = step_lines(cand);
+    if steps.is_empty() || error_lines.is_empty() {
+        return None;
+    }
+    let step_of = |line: usize| {
+        starts
+            .iter()
+            .rposition(|&s| s <= line)
+            .unwrap_or(steps.len() - 1)
+    };
+    let first = step_of(error_lines[0]);
+    error_lines
+        .iter()
+        .all(|&l| step_of(l) == first)
+        .then_some(first)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '\'' | '?' | '!' | '₀'..='₉')
+}
+
+/// Words of `s` (identifiers, with dots), with their byte offsets.
+fn words(s: &str) -> Vec<(usize, &str)> {
+    let mut out = Vec::new();
+    let mut start = None;
+    for (i, c) in s.char_indices().chain(std::iter::once((s.len(), ' '))) {
+        match (start, is_word_char(c)) {
+            (None, true) => start = Some(i),
+            (Some(b), false) => {
+                out.push((b, &s[b..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Names in a step that look like lemmas rather than tactics or local hypotheses: dotted, or
+/// snake_case with at least two parts.
+fn lemma_names(step: &str) -> Vec<&str> {
+    let mut out: Vec<&str> = Vec::new();
+    for (_, w) in words(step) {
+        let w = w.trim_end_matches('.');
+        let lemma_like = (w.contains('.') && !w.starts_with('.'))
+            || w.split('_').filter(|p| !p.is_empty()).count() >= 2;
+        let tactic = matches!(
+            w,
+            "simp_all"
+                | "simp_arith"
+                | "norm_num"
+                | "ring_nf"
+                | "field_simp"
+                | "push_cast"
+                | "exact_mod_cast"
+                | "norm_cast"
+                | "split_ifs"
+                | "simp_rw"
+                | "rw_mod_cast"
+                | "nth_rewrite"
+                | "nth_rw"
+                | "bv_decide"
+                | "native_decide"
+        );
+        if lemma_like
+            && !tactic
+            && w.chars().next().is_some_and(char::is_alphabetic)
+            && !out.contains(&w)
+        {
+            out.push(w);
+        }
+    }
+    out
+}
+
+fn replace_word(s: &str, at: usize, old: &str, new: &str) -> String {
+    format!("{}{new}{}", &s[..at], &s[at + old.len()..])
+}
+
+/// `step` with its `simp` head turned into `simp_arith`.
+fn simp_arith(step: &str) -> Option<String> {
+    let (at, w) = words(step).into_iter().next()?;
+    (w == "simp").then(|| replace_word(step, at, w, "simp_arith"))
+}
+
+/// The variants of `step` with one rule of its `rw`/`rewrite`/`rwa` list reversed.
+fn flip_rewrites(step: &str) -> Vec<String> {
+    let head = words(step).into_iter().next().map(|(_, w)| w);
+    if !matches!(
+        head,
+        Some("rw" | "rewrite" | "rwa" | "nth_rewrite" | "nth_rw")
+    ) {
+        return Vec::new();
+    }
+    let (Some(open), Some(close)) = (step.find('['), step.rfind(']')) else {
+        return Vec::new();
+    };
+    let rules: Vec<&str> = step[open + 1..close].split(',').collect();
+    (0..rules.len())
+        .map(|i| {
+            let flipped: Vec<String> = rules
+                .iter()
+                .enumerate()
+                .map(|(j, r)| {
+                    let t = r.trim();
+                    if j != i {
+                        t.to_string()
+                    } else if let Some(rest) = t.strip_prefix('←').or(t.strip_prefix("<-")) {
+                        rest.trim().to_string()
+                    } else {
+                        format!("← {t}")
+                    }
+                })
+                .collect();
+            format!(
+                "{}[{}]{}",
+                &step[..open],
+                flipped.join(", "),
+                &step[close + 1..]
+            )
+        })
+        .collect()
+}
+
+fn with_step(steps: &[String], i: usize, replacement: &[String]) -> String {
+    let mut out: Vec<String> = steps[..i].to_vec();
+    out.extend(replacement.iter().cloned());
+    out.extend(steps[i + 1..].iter().cloned());
+    crate::beam::render_script(&out)
+}
+
+/// Variants of `cand` with step `i` rewritten (see the module docs), at most `MAX_MUTANTS`,
+/// without duplicates or `cand` itself. `premises` supplies sibling lemmas; `omega` says whether
+/// the toolchain has it.
+pub fn mutate(cand: &str, i: usize, premises: Option<&PremiseIndex>, omega: bool) -> Vec<Mutant> {
+    let steps = split_tactic_steps(cand);
+    let Some(step) = steps.get(i) else {
+        return Vec::new();
+    };
+    let mut out: Vec<Mutant> = Vec::new();
+    let mut push = |operator: &str, candidate: String| {
+        if candidate.trim() != cand.trim() && !out.iter().any(|m| m.candidate == candidate) {
+            out.push(Mutant {
+                operator: operator.to_string(),
+                candidate,
+            });
+        }
+    };
+    if let Some(s) = simp_arith(step) {
+        push("simp_arith", with_step(&steps, i, &[s]));
+    }
+    if omega && step.trim() != "omega" {
+        push(
+            "add_omega",
+            with_step(&steps, i, &[step.clone(), "omega".to_string()]),
+        );
+    }
+    for s in flip_rewrites(step) {
+        push("flip_rewrite", with_step(&steps, i, &[s]));
+    }
+    if let Some(idx) = premises {
+        for name in lemma_names(step).into_iter().take(2) {
+            for sib in idx.siblings(name, 3) {
+                let swapped = words(step)
+                    .into_iter()
+                    .rev()
+                    .filter(|(_, w)| w.trim_end_matches('.') == name)
+                    .fold(step.clone(), |s, (at, _)| replace_word(&s, at, name, &sib));
+                push("swap_lemma", with_step(&steps, i, &[swapped]));
+            }
+        }
+    }
+    out.truncate(MAX_MUTANTS);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::premise::{extract_premises, HashingEmbedder};
+
+    #[test]
+    fn locates_the_failing_step_and_mutates_it() {
+        let cand = "by\n  intro n\n  rw [Nat.add_comm, h]\n  simp [foo]";
+        assert_eq!(failing_step(cand, &[3]), Some(1));
+        assert_eq!(failing_step(cand, &[1]), Some(2));
+        assert_eq!(failing_step(cand, &[2, 3]), None);
+        assert_eq!(failing_step("by simp", &[1]), Some(0));
+
+        let ms = mutate(cand, 1, None, true);
+        let got: Vec<(&str, &str)> = ms
+            .iter()
+            .map(|m| (m.operator.as_str(), m.candidate.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            [
+                (
+                    "add_omega",
+                    "by\n  intro n\n  rw [Nat.add_comm, h]\n  omega\n  simp [foo]"
+                ),
+                (
+                    "flip_rewrite",
+                    "by\n  intro n\n  rw [← Nat.add_comm, h]\n  simp [foo]"
+                ),
+                (
+                    "flip_rewrite",
+                    "by\n  intro n\n  rw [Nat.add_comm, ← h]\n  simp [foo]"
+                ),
+            ]
+        );
+        let last = mutate(cand, 2, None, false);
+        assert_eq!(
+            last[0].candidate,
+            "by\n  intro n\n  rw [Nat.add_comm, h]\n  simp_arith [foo]"
+        );
+
+        let src = "theorem Nat.le_of_lt_succ {m n : ℕ} (h : m < n + 1) : m ≤ n := sorry\n\
+                   theorem Nat.lt_succ_iff {m n : ℕ} : m < n + 1 ↔ m ≤ n := sorry\n\
+                   theorem List.length_append (as bs : List α) : (as ++ bs).length = as.length + bs.length := sorry\n";
+        let idx = PremiseIndex::build(extract_premises(src, None), &HashingEmbedder::default());
+        let ms = mutate("by\n  exact Nat.le_of_lt_succ h", 0, Some(&idx), false);
+        assert_eq!(ms[0].operator, "swap_lemma");
+        assert_eq!(ms[0].candidate, "by\n  exact Nat.lt_succ_iff h");
+    }
+}
@@ -0,0 +1,149 @@
+//! Speculative parallel verification (`[verify] parallel`): trade CPU for latency.
+//!
+//! Instead of compiling a round's ranked candidates one after another, the repair loop takes
+//! them `k` at a time and compiles each batch concurrently. Every candidate is checked in its
+//! own temporary copy of the patched file (`verify_lean_text`), so the compiles share nothing.
+//! Results are judged as they finish; once one is accepted, the compiles still running are
+//! cancelled (their `lean` processes are killed when the task is dropped). With
+//! `keep_searching`, the batch runs to completion instead and the caller keeps the shortest
+//! accepted proof. A winner can still be rejected afterwards (`safety::check_axioms`); the
+//! candidates cancelled in its favour then go back to the front of the queue.
+//!
+//! Cancelled compiles do not count against `RepairOptions::max_verifications`; they are
+//! counted separately (`RepairOutcome::cancelled_verifications`). The mode is off unless
+//! configured, e.g. `[verify] parallel = 4`, `keep_searching = true`.
+
+use crate::config::VerifyConfig;
+use std::future::Future;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelVerify {
+    /// Candidates compiled at once.
+    pub k: usize,
+    /// Finish the batch after the first success, to pick the shortest proof.
+    pub keep_searching: bool,
+}
+
+impl ParallelVerify {
+    /// `None` when the mode is off (`parallel` below 2).
+    pub fn from_config(c: &VerifyConfig) -> Option<Self> {
+        let k = c.parallel.filter(|k| *k >= 2)?;
+        Some(Self {
+            k,
+            keep_searching: c.keep_searching.unwrap_or(false),
+        })
+    }
+}
+
+/// The configured `[verify] parallel` settings, when the mode is on.
+pub fn settings(repo_root: &Path) -> Option<ParallelVerify> {
+    crate::config::load_from_repo_root(repo_root)
+        .ok()
+        .flatten()
+        .and_then(|c| ParallelVerify::from_config(&c.verify))
+}
+
+#[derive(Debug)]
+pub struct BatchOutcome<T> {
+    /// One entry per future, in the order they were given (`None`: cancelled).
+    pub results: Vec<Option<T>>,
+    /// Futures cancelled before they finished.
+    pub cancelled: usize,
+}
+
+/// Run `futures` concurrently, passing each result to `accept` (with its index) as it finishes;
+/// unless `keep_searching`, cancel the rest once one is accepted.
+pub async fn race<T, F>(
+    futures: Vec<F>,
+    keep_searching: bool,
+    mut accept: impl FnMut(usize, &T) -> bool,
+) -> BatchOutcome<T>
+where
+    T: Send + 'static,
+    F: Future<Output = T> + Send + 'static,
+{
+    let mut out = BatchOutcome {
+        results: futures.iter().map(|_| None).collect(),
+        cancelled: 0,
+    };
+    let mut set = tokio::task::JoinSet::new();
+    for (i, f) in futures.into_iter().enumerate() {
+        set.spawn(async move { (i, f.await) });
+    }
+    let mut stopped = false;
+    while let Some(r) = set.join_next().await {
+        let (i, r) = match r {
+            Ok(r) => r,
+            Err(e) => {
+                out.cancelled += usize::from(e.is_cancelled());
+                continue;
+            }
+        };
+        let accepted = accept(i, &r);
+        out.results[i] = Some(r);
+        if accepted && !keep_searching && !stopped {
+            // Aborted tasks still come out of `join_next`, as cancelled (or finished, if they
+            // got there first).
+            set.abort_all();
+            stopped = true;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn delayed(ms: u64, ok: bool) -> bool {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        ok
+    }
+
+    #[tokio::test]
+    async fn cancels_the_rest_once_one_is_accepted() {
+        let out = race(
+            vec![
+                delayed(5_000, true),
+                delayed(10, true),
+                delayed(5_000, false),
+            ],
+            false,
+            |_, ok| *ok,
+        )
+        .await;
+        assert_eq!(out.results, vec![None, Some(true), None]);
+        assert_eq!(out.cancelled, 2);
+    }
+
+    #[tokio::test]
+    async fn keep_searching_waits_for_every_result() {
+        let out = race(
+            vec![delayed(30, true), delayed(10, true), delayed(20, false)],
+            true,
+            |_, ok| *ok,
+        )
+        .await;
+        assert_eq!(out.results, vec![Some(true), Some(true), Some(false)]);
+        assert_eq!(out.cancelled, 0);
+    }
+
+    #[test]
+    fn off_below_two() {
+        let c = |k| VerifyConfig {
+            parallel: k,
+            ..Default::default()
+        };
+        assert_eq!(ParallelVerify::from_config(&c(None)), None);
+        assert_eq!(ParallelVerify::from_config(&c(Some(1))), None);
+        assert_eq!(
+            ParallelVerify::from_config(&c(Some(3))),
+            Some(ParallelVerify {
+                k: 3,
+                keep_searching: false
+            })
+        );
+    }
+}
@@ -0,0 +1,514 @@
+//! Applied patches, recorded per run so they can be re-checked after a merge (`proofpatch verify`).
+//!
+//! Commands that write repairs to disk (`patch --write`, `repair-file --write`) append them to the
+//! run's manifest, `.generated/proofpatch-patches/<run id>.json`; the run id is the name of the
+//! process's run directory (`transcripts::artifacts_dir`), so a manifest sits next to the run's
+//! transcript by name. Manifests live outside the run directories: transcript retention
+//! (`transcripts::prune`) never removes them. `recheck` takes a manifest and:
+//! - checks each patch is still in its file (`PatchState`, `state`: its new text is at the
+//!   recorded place in its declaration's proof, or the file changed since and the patch is gone);
+//! - rebuilds just the affected modules: the patched ones and the in-repo modules importing them
+//!   (`import_graph::ImportGraph::build_targets`, one `lake build`);
+//! - reports a regression for every patched declaration that has errors or is admitted again,
+//!   and for every error in the other rebuilt modules.
+
+use crate::diagnostics::{Diagnostic, ErrorClass, Severity};
+use crate::patching::{EditRecord, PatchTarget};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedPatch {
+    /// Repo-relative path.
+    pub file: String,
+    /// The patched declaration, named as in the written file.
+    pub decl: Option<String>,
+    pub edit: EditRecord,
+    /// Byte offset of the new text in the declaration's proof, as written (`state`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof_offset: Option<usize>,
+    /// Unix seconds.
+    pub applied_at: u64,
+    /// The command that wrote it (`patch`, `repair-file`).
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PatchManifest {
+    pub patches: Vec<AppliedPatch>,
+}
+
+/// The repo's manifests directory.
+pub fn dir(repo_root: &Path) -> PathBuf {
+    repo_root.join(".generated").join("proofpatch-patches")
+}
+
+/// The manifest of run `run_id` in `dir`.
+pub fn path(dir: &Path, run_id: &str) -> PathBuf {
+    dir.join(format!("{run_id}.json"))
+}
+
+pub fn load(path: &Path) -> Result<PatchManifest, String> {
+    let txt = std::fs::read_to_string(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+    serde_json::from_str(&txt).map_err(|e| format!("parse {}: {e}", path.display()))
+}
+
+/// Append `patches` to the manifest at `path`.
+pub fn record(path: &Path, patches: &[AppliedPatch]) -> Result<(), String> {
+    let mut m = if path.is_file() {
+        load(path)?
+    } else {
+        PatchManifest::default()
+    };
+    m.patches.extend_from_slice(patches);
+    if let Some(d) = path.parent() {
+        std::fs::create_dir_all(d).map_err(|e| format!("mkdir {}: {e}", d.display()))?;
+    }
+    let data = serde_json::to_string_pretty(&m).map_err(|e| e.to_string())?;
+    std::fs::write(path, data).map_err(|e| format!("write {}: {e}", path.display()))
+}
+
+/// Where the new text of `edits[i]` ends up once all of `edits` are applied in order: the
+/// declaration `decl` (anonymous names follow the lines the later edits move) and the byte offset.
+fn final_place(edits: &[EditRecord], i: usize, decl: &str) -> (String, usize) {
+    let mut name = decl.to_string();
+    let mut pos = edits[i].byte_start;
+    for e in &edits[i + 1..] {
+        crate::file_repair::shift_name_after_edit(&mut name, e);
+        if e.byte_start < pos {
+            pos = (pos + e.new_text.len()).saturating_sub(e.old_text.len());
+        }
+    }
+    (name, pos)
+}
+
+/// The patches of `report`, as written (`report.patched_text`) by `command`.
+pub fn file_repair_patches(
+    report: &crate::file_repair::FileRepairReport,
+    command: &str,
+    applied_at: u64,
+) -> Vec<AppliedPatch> {
+    let text = &report.patched_text;
+    let mut next = 0;
+    let mut out = Vec::new();
+    for o in report.outcomes.iter().filter(|o| o.ok) {
+        let Some(edit) = o.edit.clone() else {
+            continue;
+        };
+        // Each repair's edit comes last among the edits made for its declaration.
+        let at = report.edits[next..]
+            .iter()
+            .position(|e| *e == edit)
+            .map(|j| next + j);
+        let (decl, proof_offset) = match at {
+            Some(i) => {
+                next = i + 1;
+                let (decl, pos) = final_place(&report.edits, i, &o.decl);
+                let offset =
+                    crate::patching::resolve_target(text, &PatchTarget::DeclProof(decl.clone()))
+                        .ok()
+                        .filter(|&(s, e)| s <= pos && pos < e)
+                        .filter(|_| text[pos..].starts_with(&edit.new_text))
+                        .map(|(s, _)| pos - s);
+                (decl, offset)
+            }
+            None => (o.decl.clone(), None),
+        };
+        out.push(AppliedPatch {
+            file: report.file.clone(),
+            decl: Some(decl),
+            edit,
+            proof_offset,
+            applied_at,
+            command: command.to_string(),
+        });
+    }
+    out
+}
+
+/// The repairs of `report` written to disk by `command`, recorded in the manifest of this
+/// process's run; returns the manifest path (`None`: nothing fixed).
+pub fn record_file_repair(
+    repo_root: &Path,
+    report: &crate::file_repair::FileRepairReport,
+    command: &str,
+) -> Result<Option<PathBuf>, String> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let patches = file_repair_patches(report, command, now);
+    if patches.is_empty() {
+        return Ok(None);
+    }
+    let cfg = crate::config::load_from_repo_root(repo_root)?.unwrap_or_default();
+    let runs_dir = crate::llm::transcripts::runs_dir(repo_root, &cfg.llm.transcripts);
+    let run = crate::llm::transcripts::artifacts_dir(&runs_dir);
+    let run_id = run
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| format!("bad run directory {}", run.display()))?;
+    let p = path(&dir(repo_root), &run_id);
+    record(&p, &patches)?;
+    Ok(Some(p))
+}
+
+/// The manifest of run `run_id` in `dir`.
+pub fn for_run(dir: &Path, run_id: &str) -> Result<PathBuf, String> {
+    let p = path(dir, run_id);
+    if p.is_file() {
+        Ok(p)
+    } else {
+        Err(format!(
+            "no patch manifest for run {run_id} ({})",
+            p.display()
+        ))
+    }
+}
+
+/// The newest manifest in `dir`.
+pub fn latest(dir: &Path) -> Option<PathBuf> {
+    let mut runs: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().is_some_and(|x| x == "json"))
+        .collect();
+    // Run ids start with the creation time: newest last.
+    runs.sort();
+    runs.pop()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatchState {
+    /// The patch's new text is still in the file.
+    Present,
+    /// The file no longer contains it.
+    Changed,
+    /// The file is gone.
+    Missing,
+}
+
+/// Whether `p` is still applied in `text` (`None`: the file is gone): its new text at the recorded
+/// offset in its declaration's proof (anywhere in the declaration for patches without one). Only
+/// when the declaration is no longer found (renamed, or an anonymous one moved) is the new text
+/// looked for anywhere in the file.
+pub fn state(text: Option<&str>, p: &AppliedPatch) -> PatchState {
+    let Some(t) = text else {
+        return PatchState::Missing;
+    };
+    let new = p.edit.new_text.as_str();
+    let proof = |d: &str| crate::patching::resolve_target(t, &PatchTarget::DeclProof(d.into()));
+    let present = match p.decl.as_deref() {
+        Some(d) => match (crate::patching::decl_byte_range(t, d), p.proof_offset) {
+            (Ok(_), Some(off)) => proof(d)
+                .ok()
+                .and_then(|(s, e)| t.get(s + off..e))
+                .is_some_and(|r| r.starts_with(new)),
+            (Ok((s, e)), None) => t[s..e].contains(new),
+            (Err(_), _) => t.contains(new),
+        },
+        None => t.contains(new),
+    };
+    if present {
+        PatchState::Present
+    } else {
+        PatchState::Changed
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchCheck {
+    pub file: String,
+    pub decl: Option<String>,
+    pub line: usize,
+    pub state: PatchState,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Regression {
+    pub file: String,
+    /// The patched declaration, when the regression is in one.
+    pub decl: Option<String>,
+    pub line: usize,
+    pub class: ErrorClass,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecheckReport {
+    pub manifest: String,
+    pub patches: Vec<PatchCheck>,
+    /// Modules passed to `lake build`.
+    pub build_targets: Vec<String>,
+    /// Modules the build covers: the patched ones and their dependents.
+    pub rebuilt: Vec<String>,
+    pub build_ok: bool,
+    pub timeout: bool,
+    pub elapsed_ms: u64,
+    pub regressions: Vec<Regression>,
+    /// Every patch present, the build ok, and no regressions.
+    pub ok: bool,
+}
+
+/// Regressions in `diags` (one build's diagnostics): errors and `sorry` warnings inside the
+/// patched declarations of `patches` (present ones only; `texts` are the current file texts), and
+/// errors anywhere in the other files of `rebuilt_files`.
+pub fn regressions(
+    diags: &[Diagnostic],
+    patches: &[(AppliedPatch, PatchState)],
+    texts: &[(String, String)],
+    rebuilt_files: &[String],
+) -> Vec<Regression> {
+    let matches = |d: &Diagnostic, f: &str| Path::new(&d.file).ends_with(f);
+    let text_of = |f: &str| texts.iter().find(|(n, _)| n == f).map(|(_, t)| t.as_str());
+    let spans: Vec<(&str, &str, usize, usize)> = patches
+        .iter()
+        .filter(|(_, s)| *s == PatchState::Present)
+        .filter_map(|(p, _)| {
+            let decl = p.decl.as_deref()?;
+            let text = text_of(&p.file)?;
+            let (s, e) = crate::patching::decl_byte_range(text, decl).ok()?;
+            let first = text[..s].matches('\n').count() + 1;
+            Some((
+                p.file.as_str(),
+                decl,
+                first,
+                first + text[s..e].matches('\n').count(),
+            ))
+        })
+        .collect();
+    let patched_files: BTreeSet<&str> = patches.iter().map(|(p, _)| p.file.as_str()).collect();
+    let mut out: Vec<Regression> = Vec::new();
+    for d in diags {
+        let is_error = d.severity == Severity::Error && d.class != ErrorClass::Linter;
+        let admitted = d.class == ErrorClass::DeclarationUsesSorry;
+        if !is_error && !admitted {
+            continue;
+        }
+        let decl = spans
+            .iter()
+            .find(|(f, _, a, b)| matches(d, f) && (*a..=*b).contains(&d.line))
+            .map(|(_, decl, _, _)| decl.to_string());
+        let dependent = is_error
+            && rebuilt_files
+                .iter()
+                .any(|f| !patched_files.contains(f.as_str()) && matches(d, f));
+        if decl.is_none() && !dependent {
+            continue;
+        }
+        let file = rebuilt_files
+            .iter()
+            .map(String::as_str)
+            .chain(patched_files.iter().copied())
+            .find(|f| matches(d, f))
+            .unwrap_or(&d.file)
+            .to_string();
+        out.push(Regression {
+            file,
+            decl,
+            line: d.line,
+            class: d.class,
+            message: d.headline().to_string(),
+        });
+    }
+    out
+}
+
+/// Re-check the patches of the manifest at `manifest_path` (see the module docs).
+pub async fn recheck(
+    repo_root: &Path,
+    manifest_path: &Path,
+    timeout: Duration,
+) -> Result<RecheckReport, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let manifest = load(manifest_path)?;
+    let files: BTreeSet<String> = manifest.patches.iter().map(|p| p.file.clone()).collect();
+    let texts: Vec<(String, String)> = files
+        .iter()
+        .filter_map(|f| {
+            let t = std::fs::read_to_string(repo_root.join(f)).ok()?;
+            Some((f.clone(), t))
+        })
+        .collect();
+    let text_of = |f: &str| texts.iter().find(|(n, _)| n == f).map(|(_, t)| t.as_str());
+    let states: Vec<(AppliedPatch, PatchState)> = manifest
+        .patches
+        .iter()
+        .map(|p| (p.clone(), state(text_of(&p.file), p)))
+        .collect();
+
+    let changed: Vec<String> = texts
+        .iter()
+        .filter_map(|(f, _)| crate::module_name_from_file_rel(f))
+        .collect();
+    let graph = crate::import_graph::ImportGraph::build(&repo_root)?;
+    let (build_targets, rebuilt) = if changed.iter().all(|m| graph.modules.contains_key(m)) {
+        (graph.build_targets(&changed), graph.rebuild_set(&changed))
+    } else {
+        (changed.clone(), changed.clone())
+    };
+    let rebuilt_files: Vec<String> = rebuilt
+        .iter()
+        .filter_map(|m| graph.modules.get(m).map(|n| n.file.clone()))
+        .chain(texts.iter().map(|(f, _)| f.clone()))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let started = Instant::now();
+    let (build_ok, timeout_hit, _, stdout, stderr) = if build_targets.is_empty() {
+        (true, false, None, String::new(), String::new())
+    } else {
+        crate::verify::lake_build_targets(&repo_root, &build_targets, timeout).await
+    };
+    let diags = crate::diagnostics::parse_diagnostics_from(&stdout, &stderr);
+    let regressions = regressions(&diags, &states, &texts, &rebuilt_files);
+    let patches: Vec<PatchCheck> = states
+        .iter()
+        .map(|(p, s)| PatchCheck {
+            file: p.file.clone(),
+            decl: p.decl.clone(),
+            line: p.edit.line,
+            state: *s,
+        })
+        .collect();
+    let ok = build_ok
+        && regressions.is_empty()
+        && patches.iter().all(|p| p.state == PatchState::Present);
+    Ok(RecheckReport {
+        manifest: manifest_path.display().to_string(),
+        patches,
+        build_targets,
+        rebuilt,
+        build_ok,
+        timeout: timeout_hit,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        regressions,
+        ok,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_manifests_and_finds_regressions() {
+        let patch = |file: &str, decl: &str, new_text: &str| AppliedPatch {
+            file: file.into(),
+            decl: Some(decl.into()),
+            edit: EditRecord {
+                byte_start: 0,
+                old_text: "sorry".into(),
+                new_text: new_text.into(),
+                line: 2,
+            },
+            proof_offset: None,
+            applied_at: 1,
+            command: "patch".into(),
+        };
+        let td = tempfile::tempdir().unwrap();
+        let (old, p) = (
+            path(td.path(), "1700000001-1"),
+            path(td.path(), "1700000002-1"),
+        );
+        record(&old, &[patch("Foo/A.lean", "a", "omega")]).unwrap();
+        record(&p, &[patch("Foo/A.lean", "a", "omega")]).unwrap();
+        record(&p, &[patch("Foo/A.lean", "b", "simp")]).unwrap();
+        assert_eq!(load(&p).unwrap().patches.len(), 2);
+        assert_eq!(latest(td.path()).unwrap(), p);
+        assert_eq!(for_run(td.path(), "1700000002-1").unwrap(), p);
+        assert!(for_run(td.path(), "1700000003-1").is_err());
+
+        let a = "theorem a (n : ℕ) (h : n < 3) : n < 4 := by\n  omega\n\n\
+                 theorem b : True := by\n  trivial\n";
+        let pa = patch("Foo/A.lean", "a", "omega");
+        let pb = patch("Foo/A.lean", "b", "simp");
+        assert_eq!(state(Some(a), &pa), PatchState::Present);
+        assert_eq!(state(Some(a), &pb), PatchState::Changed);
+        assert_eq!(state(None, &pa), PatchState::Missing);
+
+        let out = "error: ./././Foo/A.lean:2:2: omega could not prove the goal\n\
+                   warning: ./././Foo/A.lean:4:8: declaration uses 'sorry'\n\
+                   error: ./././Foo/B.lean:7:4: unknown identifier 'a'\n\
+                   error: ./././Foo/C.lean:1:0: unknown identifier 'c'\n";
+        let diags = crate::diagnostics::parse_diagnostics_from(out, "");
+        let got = regressions(
+            &diags,
+            &[(pa.clone(), PatchState::Present), (pb, PatchState::Changed)],
+            &[("Foo/A.lean".into(), a.into())],
+            &["Foo/A.lean".into(), "Foo/B.lean".into()],
+        );
+        let got: Vec<(&str, Option<&str>, usize)> = got
+            .iter()
+            .map(|r| (r.file.as_str(), r.decl.as_deref(), r.line))
+            .collect();
+        // `b` changed since: its `sorry` is not this run's regression; `C` was not rebuilt.
+        assert_eq!(got, [("Foo/A.lean", Some("a"), 2), ("Foo/B.lean", None, 7)]);
+    }
+
+    #[test]
+    fn patches_are_checked_where_they_were_written() {
+        let original = "theorem a : 1 = 1 := by\n  sorry\n\n\
+                        theorem b (n : ℕ) (h : n < 3) : n < 4 := by\n  sorry\n";
+        let edit = |text: &str, from: usize, new_text: &str| {
+            let at = from + text[from..].find("sorry").unwrap();
+            EditRecord {
+                byte_start: at,
+                old_text: "sorry".into(),
+                new_text: new_text.into(),
+                line: text[..at].lines().count(),
+            }
+        };
+        let apply = |text: &str, e: &EditRecord| {
+            let mut t = text.to_string();
+            t.replace_range(e.byte_start..e.byte_start + e.old_text.len(), &e.new_text);
+            t
+        };
+        // `b` first, then `a` above it: `b`'s edit moves.
+        let eb = edit(original, original.find("theorem b").unwrap(), "omega");
+        let mid = apply(original, &eb);
+        let ea = edit(&mid, 0, "rfl");
+        let written = apply(&mid, &ea);
+        let outcome = |decl: &str, e: &EditRecord| {
+            serde_json::json!({
+                "file": "Foo/A.lean", "decl": decl, "ok": true, "rounds": 1,
+                "verifications": 1, "stop_reason": "solved", "attempts": [],
+                "solution": e.new_text, "edit": e, "has_placeholder": false,
+            })
+        };
+        let mut report: crate::file_repair::FileRepairReport =
+            serde_json::from_value(serde_json::json!({
+                "file": "Foo/A.lean", "errors_before": 0, "broken": [], "order": ["b", "a"],
+                "outcomes": [outcome("b", &eb), outcome("a", &ea)], "fixed": 2,
+                "edits": [eb, ea],
+            }))
+            .unwrap();
+        report.patched_text = written.clone();
+        let got = file_repair_patches(&report, "repair-file", 1);
+        assert_eq!(got.len(), 2);
+        let pb = &got[0];
+        assert!(pb.proof_offset.is_some());
+        assert_eq!(state(Some(&written), pb), PatchState::Present);
+
+        // `b` reverted; the same tactic now closes `a`: not `b`'s patch.
+        let reverted = written.replacen("rfl", "omega", 1).replacen(
+            "n < 4 := by\n  omega",
+            "n < 4 := by\n  sorry",
+            1,
+        );
+        assert_eq!(state(Some(&reverted), pb), PatchState::Changed);
+        // Without an offset, anywhere in the declaration.
+        let loose = AppliedPatch {
+            proof_offset: None,
+            ..pb.clone()
+        };
+        assert_eq!(state(Some(&reverted), &loose), PatchState::Changed);
+        // `b` renamed: looked for anywhere in the file.
+        let renamed = written.replace("theorem b", "theorem b'");
+        assert_eq!(state(Some(&renamed), pb), PatchState::Present);
+    }
+}
@@ -0,0 +1,446 @@
+//! Source-span patch application with reversible edit records.
+//!
+//! The older line-oriented helpers (`patch_first_sorry_in_decl`, `patch_first_sorry_in_region`)
+//! work on the first match in a region. This module edits an exact byte span instead, addressed
+//! directly, by the first (or n-th) placeholder inside a named declaration, or by a declaration's
+//! whole proof. `GoalRef` maps a dumped goal (declaration + placeholder index, as recorded in
+//! `pp_dump`) back to that span. `DeclTail` addresses the rest of a tactic block from a given
+//! step, for repairs deep inside an existing proof: the steps before it are kept, and
+//! `tail_goal_text` exposes the goal they leave (as a `sorry` the goal dump can see). It adapts the replacement to its context:
+//! - `… := by sorry` with a `by …` replacement does not produce `by by`;
+//! - multi-line tactic scripts spliced after an inline `by` are moved onto their own indented
+//!   lines (Lean requires tactic columns to line up);
+//! - continuation lines are re-indented relative to the edit site.
+//!
+//! Every edit returns an `EditRecord` that can re-apply or revert exactly that edit.
+
+use crate::scan::{find_placeholders, PLACEHOLDER_TOKENS};
+use serde::{Deserialize, Serialize};
+
+/// What to replace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchTarget {
+    /// Exact byte span `[start, end)` in the text.
+    Span { start: usize, end: usize },
+    /// The first `sorry` / `admit` / `stop` inside the named declaration.
+    DeclPlaceholder(String),
+    /// Everything after the declaration's top-level `:=`.
+    DeclProof(String),
+    /// The `index`-th placeholder (0-based, source order) inside the named declaration.
+    DeclSorry { decl: String, index: usize },
+    /// The tactic step of the named declaration starting on 1-based `line`, through the end of
+    /// its enclosing tactic block (later lines indented at least as deep).
+    DeclTail { decl: String, line: usize },
+}
+
+/// One applied edit: enough to re-apply or revert it byte-for-byte.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditRecord {
+    /// Byte offset of the edit in the original text.
+    pub byte_start: usize,
+    pub old_text: String,
+    pub new_text: String,
+    /// 1-based line of `byte_start`.
+    pub line: usize,
+}
+
+impl EditRecord {
+    /// Re-apply to the original text (fails if the original no longer matches).
+    pub fn apply(&self, original: &str) -> Result<String, String> {
+        splice_checked(original, self.byte_start, &self.old_text, &self.new_text)
+    }
+
+    /// Undo on the patched text (fails if the patched region was changed since).
+    pub fn revert(&self, patched: &str) -> Result<String, String> {
+        splice_checked(patched, self.byte_start, &self.new_text, &self.old_text)
+    }
+}
+
+/// Revert a sequence of edits that were applied in order.
+pub fn revert_all(patched: &str, records: &[EditRecord]) -> Result<String, String> {
+    records
+        .iter()
+        .rev()
+        .try_fold(patched.to_string(), |t, r| r.revert(&t))
+}
+
+fn splice_checked(text: &str, start: usize, expect: &str, with: &str) -> Result<String, String> {
+    let end = start + expect.len();
+    if text.get(start..end) != Some(expect) {
+        return Err(format!(
+            "edit record does not match text at byte {start} (expected {:?})",
+            truncate(expect, 60)
+        ));
+    }
+    Ok(format!("{}{}{}", &text[..start], with, &text[end..]))
+}
+
+fn truncate(s: &str, n: usize) -> String {
+    if s.chars().count() <= n {
+        s.to_string()
+    } else {
+        format!("{}…", s.chars().take(n).collect::<String>())
+    }
+}
+
+fn leading_ws(s: &str) -> &str {
+    &s[..s.len() - s.trim_start().len()]
+}
+
+fn ends_with_word(s: &str, w: &str) -> bool {
+    s.strip_suffix(w).is_some_and(|p| {
+        p.chars()
+            .last()
+            .is_none_or(|c| !(c.is_alphanumeric() || c == '_' || c == '.'))
+    })
+}
+
+/// Dedent a replacement: first line trimmed, later lines shifted by their common indentation.
+fn dedent(repl: &str) -> Vec<String> {
+    let lines: Vec<&str> = repl.trim_matches('\n').lines().collect();
+    let min = lines
+        .iter()
+        .skip(1)
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| leading_ws(l).len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, l)| {
+            if i == 0 {
+                l.trim().to_string()
+            } else if l.trim().is_empty() {
+                String::new()
+            } else {
+                l[min.min(leading_ws(l).len())..].trim_end().to_string()
+            }
+        })
+        .collect()
+}
+
+/// Split a leading `by` off a replacement (`by` on its own line or `by tac`).
+fn strip_by(lines: &[String]) -> Option<Vec<String>> {
+    let first = lines.first()?;
+    if first == "by" {
+        return Some(lines[1..].to_vec());
+    }
+    let rest = first.strip_prefix("by ")?;
+    let mut out = vec![rest.trim_start().to_string()];
+    out.extend(lines[1..].iter().cloned());
+    Some(out)
+}
+
+fn indent_block(lines: &[String], indent: &str) -> String {
+    lines
+        .iter()
+        .map(|l| {
+            if l.is_empty() {
+                String::new()
+            } else {
+                format!("{indent}{l}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Replace `[start, end)` with `replacement`, adapting `by`/indentation to the edit site.
+pub fn apply_span(
+    text: &str,
+    start: usize,
+    end: usize,
+    replacement: &str,
+) -> Result<(String, EditRecord), String> {
+    if start > end || end > text.len() {
+        return Err(format!(
+            "span {start}..{end} out of range (len {})",
+            text.len()
+        ));
+    }
+    if !text.is_char_boundary(start) || !text.is_char_boundary(end) {
+        return Err(format!("span {start}..{end} is not on a char boundary"));
+    }
+    let lines = dedent(replacement);
+    if lines.iter().all(|l| l.trim().is_empty()) {
+        return Err("Empty replacement.".to_string());
+    }
+
+    let line_start = text[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let prefix = &text[line_start..start];
+    let line_indent = leading_ws(prefix).to_string();
+    let prefix_trim = prefix.trim_end();
+    let step = "  ";
+
+    let mut edit_start = start;
+    let new_text = if prefix_trim.is_empty() {
+        // Own line: either a tactic inside a `by` block or a term on its own line.
+        let prev_is_by = text[..line_start]
+            .trim_end()
+            .lines()
+            .last()
+            .is_some_and(|l| ends_with_word(l.trim_end(), "by"));
+        let body = match strip_by(&lines) {
+            Some(b) if prev_is_by => b,
+            _ => lines.clone(),
+        };
+        if body.iter().all(|l| l.trim().is_empty()) {
+            return Err("Replacement reduced to empty after `by` normalization.".to_string());
+        }
+        let mut out = body[0].clone();
+        if body.len() > 1 {
+            out.push('\n');
+            out.push_str(&indent_block(&body[1..], prefix));
+        }
+        out
+    } else if ends_with_word(prefix_trim, "by") {
+        let body = strip_by(&lines).unwrap_or_else(|| lines.clone());
+        if body.iter().all(|l| l.trim().is_empty()) {
+            return Err("Replacement reduced to empty after `by` normalization.".to_string());
+        }
+        if body.len() == 1 {
+            body[0].clone()
+        } else {
+            // `by tac1\n tac2` needs tac2 aligned with tac1; put the script on its own lines.
+            edit_start = line_start + prefix_trim.len();
+            format!("\n{}", indent_block(&body, &format!("{line_indent}{step}")))
+        }
+    } else if lines.len() == 1 {
+        lines[0].clone()
+    } else if prefix_trim.ends_with('·') {
+        // Focused tactic block: continuation lines align with the first tactic.
+        let col = " ".repeat(prefix.chars().count());
+        format!("{}\n{}", lines[0], indent_block(&lines[1..], &col))
+    } else if let Some(body) = strip_by(&lines) {
+        let body: Vec<String> = body.into_iter().filter(|l| !l.is_empty()).collect();
+        format!(
+            "by\n{}",
+            indent_block(&body, &format!("{line_indent}{step}"))
+        )
+    } else {
+        format!(
+            "{}\n{}",
+            lines[0],
+            indent_block(&lines[1..], &format!("{line_indent}{step}"))
+        )
+    };
+
+    let rec = EditRecord {
+        byte_start: edit_start,
+        old_text: text[edit_start..end].to_string(),
+        new_text,
+        line: text[..edit_start].matches('\n').count() + 1,
+    };
+    Ok((rec.apply(text)?, rec))
+}
+
+/// Byte range `[header_line_start, decl_end)` of a named declaration (or an anonymous one,
+/// addressed as `example@<line>`, see `anonymous_decl_name`).
+///
+/// The declaration ends before the next non-blank line at column 0 that is not part of it
+/// (`termination_by`, `decreasing_by`, `where`, and `|` equations continue the declaration).
+pub fn decl_byte_range(text: &str, decl_name: &str) -> Result<(usize, usize), String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let header0 = crate::find_decl_header(&lines, decl_name)?;
+    let mut offset = 0usize;
+    let mut start: Option<usize> = None;
+    let mut end = text.len();
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        match start {
+            None => {
+                if i == header0 {
+                    start = Some(offset);
+                }
+            }
+            Some(_) => {
+                let continues = line.trim().is_empty()
+                    || line.starts_with([' ', '\t'])
+                    || ["termination_by", "decreasing_by", "where", "|"]
+                        .iter()
+                        .any(|k| line.starts_with(k));
+                if !continues {
+                    end = offset;
+                    break;
+                }
+            }
+        }
+        offset += line.len();
+    }
+    let start =
+        start.ok_or_else(|| format!("Could not find theorem/lemma/def named {decl_name}"))?;
+    let trimmed = text[start..end].trim_end().len();
+    Ok((start, start + trimmed))
+}
+
+/// Resolve a target to a byte span.
+pub fn resolve_target(text: &str, target: &PatchTarget) -> Result<(usize, usize), String> {
+    match target {
+        PatchTarget::Span { start, end } => Ok((*start, *end)),
+        PatchTarget::DeclPlaceholder(name) => decl_placeholders(text, name)?
+            .first()
+            .copied()
+            .ok_or_else(|| format!("Could not find a `sorry`/`admit` token inside {name}")),
+        PatchTarget::DeclSorry { decl, index } => {
+            let hs = decl_placeholders(text, decl)?;
+            hs.get(*index).copied().ok_or_else(|| {
+                format!(
+                    "{decl} has {} `sorry`/`admit` token(s); no placeholder #{index}",
+                    hs.len()
+                )
+            })
+        }
+        PatchTarget::DeclTail { decl, line } => tail_span(text, decl, *line),
+        PatchTarget::DeclProof(name) => {
+            let (ds, de) = decl_byte_range(text, name)?;
+            let mut off = ds;
+            for line in text[ds..de].split_inclusive('\n') {
+                if let Some(i) = crate::find_top_level_colon_eq(line) {
+                    let after = off + i + 2;
+                    let ws = text[after..de].len() - text[after..de].trim_start().len();
+                    return Ok((after + ws, de));
+                }
+                off += line.len();
+            }
+            Err(format!("Declaration {name} has no top-level `:=` proof"))
+        }
+    }
+}
+
+/// Span of the steps from `line` (1-based) to the end of its tactic block inside `decl_name`.
+fn tail_span(text: &str, decl_name: &str, line: usize) -> Result<(usize, usize), String> {
+    let (ds, de) = decl_byte_range(text, decl_name)?;
+    let header_line = text[..ds].matches('\n').count() + 1;
+    let mut off = ds;
+    let mut start: Option<(usize, usize)> = None;
+    let mut end = 0usize;
+    for (i, l) in text[ds..de].split_inclusive('\n').enumerate() {
+        let line_no = header_line + i;
+        let indent = leading_ws(l).len();
+        let body = l.trim_end();
+        match start {
+            None if line_no == line => {
+                if i == 0 || body.trim().is_empty() || indent == 0 {
+                    return Err(format!(
+                        "line {line} is not a tactic step inside {decl_name}"
+                    ));
+                }
+                start = Some((off + indent, indent));
+                end = off + body.len();
+            }
+            None => {}
+            Some((_, step_indent)) => {
+                if body.trim().is_empty() {
+                    // Blank lines belong to the block only if it continues after them.
+                } else if indent >= step_indent {
+                    end = off + body.len();
+                } else {
+                    break;
+                }
+            }
+        }
+        off += l.len();
+    }
+    let (start, _) = start.ok_or_else(|| format!("line {line} is not inside {decl_name}"))?;
+    Ok((start, end))
+}
+
+/// `text` with the steps from `line` on replaced by `sorry`, so a goal dump at `line` shows the
+/// goal left by the steps before it.
+pub fn tail_goal_text(text: &str, decl_name: &str, line: usize) -> Result<String, String> {
+    let (start, end) = tail_span(text, decl_name, line)?;
+    Ok(apply_span(text, start, end, "sorry")?.0)
+}
+
+/// Byte spans of the placeholders inside the named declaration, in source order.
+pub fn decl_placeholders(text: &str, decl_name: &str) -> Result<Vec<(usize, usize)>, String> {
+    let (ds, de) = decl_byte_range(text, decl_name)?;
+    let tokens: Vec<String> = PLACEHOLDER_TOKENS.iter().map(|s| s.to_string()).collect();
+    Ok(find_placeholders(&text[ds..de], &tokens)
+        .iter()
+        .map(|h| (ds + h.byte_start, ds + h.byte_end))
+        .collect())
+}
+
+/// Which source span a goal came from: a declaration and, when the goal is at a placeholder, its
+/// index among the declaration's placeholders (`None`: the goal is the statement itself, as dumped
+/// by `#pp_dump_decl`, and the span is the whole proof).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GoalRef {
+    pub decl: String,
+    #[serde(default)]
+    pub sorry_index: Option<usize>,
+}
+
+impl GoalRef {
+    /// Read `decl` / `sorry_index` from a `pp_dump` record (`goal_dump_in_text_at` and
+    /// `pp_export` set them).
+    pub fn from_pp_dump(pp_dump: &serde_json::Value) -> Option<Self> {
+        Some(Self {
+            decl: pp_dump.get("decl")?.as_str()?.to_string(),
+            sorry_index: pp_dump
+                .get("sorry_index")
+                .and_then(|v| v.as_u64())
+                .map(|i| i as usize),
+        })
+    }
+
+    /// The placeholder at `byte` (a `sorry` token's start), if it lies in a named declaration.
+    pub fn at_byte(text: &str, byte: usize) -> Option<Self> {
+        let line_1 = text[..byte.min(text.len())].matches('\n').count() + 1;
+        let decl = crate::nearest_decl_header_in_text(text, line_1, 20_000)?.name;
+        let index = decl_placeholders(text, &decl)
+            .ok()?
+            .iter()
+            .position(|&(s, e)| s <= byte && byte < e)?;
+        Some(Self {
+            decl,
+            sorry_index: Some(index),
+        })
+    }
+
+    pub fn target(&self) -> PatchTarget {
+        match self.sorry_index {
+            Some(index) => PatchTarget::DeclSorry {
+                decl: self.decl.clone(),
+                index,
+            },
+            None => PatchTarget::DeclProof(self.decl.clone()),
+        }
+    }
+
+    /// Byte span in `text`. Qualified names (`Foo.bar`, as Lean prints them) also match a
+    /// declaration written as `bar` inside `namespace Foo`.
+    pub fn resolve(&self, text: &str) -> Result<(usize, usize), String> {
+        let mut name = self.decl.as_str();
+        loop {
+            let r = GoalRef {
+                decl: name.to_string(),
+                sorry_index: self.sorry_index,
+            };
+            match resolve_target(text, &r.target()) {
+                Ok(span) => return Ok(span),
+                Err(e) => match name.split_once('.') {
+                    Some((_, rest)) if decl_byte_range(text, name).is_err() => name = rest,
+                    _ => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+/// Resolve `target` and apply `replacement` there.
+pub fn apply_patch(
+    text: &str,
+    target: &PatchTarget,
+    replacement: &str,
+) -> Result<(String, EditRecord), String> {
+    let (start, end) = resolve_target(text, target)?;
+    if let PatchTarget::DeclTail { .. } = target {
+        // The tail continues an open tactic block: splice the script without its `by`.
+        if let Some(body) = strip_by(&dedent(replacement)) {
+            return apply_span(text, start, end, &body.join("\n"));
+        }
+    }
+    apply_span(text, start, end, replacement)
+}
@@ -0,0 +1,199 @@
+//! Bundled Lean-side exporter for the `pp_dump` JSON format.
+//!
+//! Everything in `smt_lia`, `goal_ast`, and the repair loop consumes `pp_dump` records:
+//! `{"tool": "proofpatch", "kind": "pp_dump", "goals": [{"pretty": …, "hyps": [{"text": …}]}]}`.
+//! This module ships the Lean meta-program that produces them, in two forms:
+//! - `exporter_module()`, a standalone `ProofpatchDump.lean` that `install` copies into a project
+//!   (optionally registering a `lean_lib`), after which any file can `import ProofpatchDump` and use
+//!   `pp_dump` (tactic) or `#pp_dump_decl foo` (command);
+//! - `exporter_inline()`, the same definitions without the import, which the drivers here splice
+//!   after a file's imports so nothing has to be installed.
+//!
+//! `#pp_dump_decl foo` dumps the statement of `foo` as a goal: its binders become hypotheses.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Definitions shared by both forms (no `import`).
+const EXPORTER_BODY: &str = r##"
+namespace ProofpatchDump
+
+-- Scoped to the namespace so splicing this into a file does not change its name resolution.
+open Lean Meta Elab Tactic Command
+
+def exprToString (e : Expr) : MetaM String := do
+  let fmt ← ppExpr e
+  pure fmt.pretty
+
+def localDeclToJson (d : LocalDecl) : MetaM Json := do
+  let tyStr ← exprToString d.type
+  return Json.mkObj [("text", Json.str s!"{d.userName} : {tyStr}")]
+
+def goalToJson (g : MVarId) : MetaM Json := do
+  g.withContext do
+    let fmt ← Meta.ppGoal g
+    let mut hyps : Array Json := #[]
+    for d in (← getLCtx) do
+      if hyps.size >= 40 then
+        break
+      if d.isImplementationDetail then
+        continue
+      hyps := hyps.push (← localDeclToJson d)
+    return Json.mkObj [("pretty", Json.str fmt.pretty), ("hyps", Json.arr hyps)]
+
+def dumpJson (goals : Array Json) (extra : List (String × Json) := []) : Json :=
+  Json.mkObj ([("tool", Json.str "proofpatch"), ("kind", Json.str "pp_dump"),
+    ("goals", Json.arr goals)] ++ extra)
+
+elab "pp_dump" : tactic => do
+  let mut goals : Array Json := #[]
+  for g in ← getGoals do
+    goals := goals.push (← liftMetaM (goalToJson g))
+  logWarning m!"\n{toString (dumpJson goals)}"
+
+elab "#pp_dump_decl " id:ident : command => do
+  let name ← liftCoreM <| realizeGlobalConstNoOverloadWithInfo id
+  let info ← getConstInfo name
+  let j ← liftTermElabM <| forallTelescope info.type fun _ body => do
+    let g ← mkFreshExprMVar body
+    goalToJson g.mvarId!
+  logInfo m!"\n{toString (dumpJson #[j] [("decl", Json.str name.toString)])}"
+
+end ProofpatchDump
+"##;
+
+/// Standalone module source (`ProofpatchDump.lean`).
+pub fn exporter_module() -> String {
+    format!("import Lean\n{EXPORTER_BODY}")
+}
+
+/// Definitions to splice after a file's imports (the file must import `Lean` or Mathlib).
+pub fn exporter_inline() -> &'static str {
+    EXPORTER_BODY
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallReport {
+    pub module_path: String,
+    /// Lakefile that was edited, if any.
+    pub lakefile_edited: Option<String>,
+    /// What to add to the lakefile by hand when it was not edited.
+    pub lakefile_snippet: String,
+}
+
+/// Write `ProofpatchDump.lean` to the repo root; with `edit_lakefile`, also register it as a
+/// `lean_lib` (idempotent; `lakefile.lean` or `lakefile.toml`).
+pub fn install(repo_root: &Path, edit_lakefile: bool) -> Result<InstallReport, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let module_path = repo_root.join("ProofpatchDump.lean");
+    std::fs::write(&module_path, exporter_module())
+        .map_err(|e| format!("write {}: {e}", module_path.display()))?;
+
+    let lean = repo_root.join("lakefile.lean");
+    let toml = repo_root.join("lakefile.toml");
+    let (lakefile, snippet): (Option<PathBuf>, &str) = if lean.is_file() {
+        (Some(lean), "\nlean_lib ProofpatchDump\n")
+    } else if toml.is_file() {
+        (Some(toml), "\n[[lean_lib]]\nname = \"ProofpatchDump\"\n")
+    } else {
+        (None, "\nlean_lib ProofpatchDump\n")
+    };
+    let mut lakefile_edited = None;
+    if let (true, Some(p)) = (edit_lakefile, lakefile) {
+        let cur = std::fs::read_to_string(&p).map_err(|e| format!("read {}: {e}", p.display()))?;
+        if !cur.contains("ProofpatchDump") {
+            std::fs::write(&p, format!("{}{snippet}", cur.trim_end_matches('\n')))
+                .map_err(|e| format!("write {}: {e}", p.display()))?;
+        }
+        lakefile_edited = Some(p.display().to_string());
+    }
+    Ok(InstallReport {
+        module_path: module_path.display().to_string(),
+        lakefile_edited,
+        lakefile_snippet: snippet.trim().to_string(),
+    })
+}
+
+/// `text` with the exporter spliced in and `#pp_dump_decl decl_name` right after the declaration
+/// (same namespace/section scope).
+pub fn instrument_decl(text: &str, decl_name: &str) -> Result<String, String> {
+    let (_, end) = crate::patching::decl_byte_range(text, decl_name)?;
+    let mut patched = String::with_capacity(text.len() + 64);
+    patched.push_str(&text[..end]);
+    if !patched.ends_with('\n') {
+        patched.push('\n');
+    }
+    patched.push_str(&format!("\n#pp_dump_decl {decl_name}\n\n"));
+    patched.push_str(&text[end..]);
+    Ok(crate::insert_after_imports(&patched, exporter_inline()))
+}
+
+/// First `pp_dump` record in Lean output.
+pub fn pp_dump_from_output(stdout: &str, stderr: &str) -> Option<Value> {
+    crate::extract_json_object_by_brace_balance(&format!("{stdout}\n{stderr}"))
+        .into_iter()
+        .find(|o| {
+            o.get("tool").and_then(|v| v.as_str()) == Some("proofpatch")
+                && o.get("kind").and_then(|v| v.as_str()) == Some("pp_dump")
+        })
+}
+
+/// `pp_dump` for the statement of `decl_name` in `file_rel` (one compile, nothing written).
+pub async fn dump_decl(
+    repo_root: &Path,
+    file_rel: &str,
+    decl_name: &str,
+    timeout: Duration,
+) -> Result<Value, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let abs = repo_root.join(file_rel);
+    let text = std::fs::read_to_string(&abs).map_err(|e| format!("read {}: {e}", abs.display()))?;
+    let instrumented = instrument_decl(&text, decl_name)?;
+    let vr = crate::verify_lean_text(&repo_root, &instrumented, timeout).await?;
+    pp_dump_from_output(&vr.stdout, &vr.stderr).ok_or_else(|| {
+        let first = crate::diagnostics::parse_diagnostics_from(&vr.stdout, &vr.stderr);
+        match crate::diagnostics::first_error(&first) {
+            Some(d) => format!("no pp_dump produced: {}", d.headline()),
+            None => "no pp_dump produced".to_string(),
+        }
+    })
+}
+
+/// `pp_dump` of the goal at the `sorry` nearest to `line` (see `goal_dump_in_text_at`).
+pub async fn dump_at_line(
+    repo_root: &Path,
+    file_rel: &str,
+    line: usize,
+    timeout: Duration,
+) -> Result<Value, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let abs = repo_root.join(file_rel);
+    let text = std::fs::read_to_string(&abs).map_err(|e| format!("read {}: {e}", abs.display()))?;
+    let v =
+        crate::goal_dump_in_text_at(&repo_root, file_rel, &text, timeout, Some(line), None).await?;
+    v.get("pp_dump")
+        .filter(|p| !p.is_null())
+        .cloned()
+        .ok_or_else(|| "no pp_dump produced".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instruments_decl_in_scope_and_extracts_dump() {
+        let text = "import Mathlib\n\nnamespace Foo\n\ntheorem bar (n : ℕ) (h : n ≤ 3) : n < 4 := by\n  omega\n\nend Foo\n";
+        let out = instrument_decl(text, "bar").unwrap();
+        let cmd = out.find("#pp_dump_decl bar").unwrap();
+        assert!(out.find("omega").unwrap() < cmd && cmd < out.find("end Foo").unwrap());
+        assert!(out.find("elab \"#pp_dump_decl \"").unwrap() < out.find("namespace Foo").unwrap());
+        assert!(exporter_module().starts_with("import Lean\n"));
+
+        let stderr = "Foo.lean:9:0: info: \n{\"tool\":\"proofpatch\",\"kind\":\"pp_dump\",\"goals\":[{\"pretty\":\"n : ℕ\\nh : n ≤ 3\\n⊢ n < 4\",\"hyps\":[]}],\"decl\":\"Foo.bar\"}";
+        let v = pp_dump_from_output("", stderr).unwrap();
+        assert_eq!(v["decl"], "Foo.bar");
+    }
+}
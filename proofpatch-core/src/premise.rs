@@ -0,0 +1,507 @@
+//! Premise selection: a local vector index over declaration statements.
+//!
+//! We extract `theorem`/`lemma` statements from a Lean source tree (typically
+//! `.lake/packages/mathlib`), embed each statement, and store the vectors in a flat index on disk.
+//! Given a goal, `PremiseIndex::top_k` returns the most similar statements by cosine similarity.
+//!
+//! Embedding is behind the `Embedder` trait. The built-in `HashingEmbedder` is deterministic and
+//! offline (feature-hashed identifier/notation tokens, with dotted and snake_case names split into
+//! parts); it is a strong baseline for lemma names, which are built from the same vocabulary as
+//! the statements they name. Model-backed embeddings (OpenAI, Ollama) come from
+//! `llm::embeddings` via `PremiseIndex::build_with` / `top_k_with`; an index records the
+//! embedder id and refuses queries from a different one.
+//!
+//! On-disk format (`index.bin`): one JSON header line (`PremiseIndexMeta` + entries), followed by
+//! `entries.len() * dim` little-endian `f32`s.
+
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PremiseDecl {
+    pub name: String,
+    /// Binders and type, whitespace-normalized (everything between the name and `:=`).
+    pub statement: String,
+    #[serde(default)]
+    pub module: Option<String>,
+}
+
+pub trait Embedder {
+    /// Stable identifier; indexes built with one embedder can't be queried with another.
+    fn id(&self) -> String;
+    fn dim(&self) -> usize;
+    fn embed(&self, text: &str) -> Vec<f32>;
+}
+
+/// Deterministic feature-hashing embedder (no network, no model files).
+#[derive(Debug, Clone)]
+pub struct HashingEmbedder {
+    pub dim: usize,
+}
+
+impl Default for HashingEmbedder {
+    fn default() -> Self {
+        Self { dim: 512 }
+    }
+}
+
+/// Split text into lowercase identifier parts and notation symbols.
+///
+/// `Nat.succ_le_of_lt` → `nat.succ_le_of_lt`, `nat`, `succ`, `le`, `of`, `lt`; `≤` → `≤`.
+pub fn premise_tokens(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    let flush = |cur: &mut String, out: &mut Vec<String>| {
+        if cur.is_empty() {
+            return;
+        }
+        let full = cur.to_lowercase();
+        let parts: Vec<&str> = full
+            .split(['.', '_'])
+            .filter(|p| !p.is_empty() && !p.chars().all(|c| c.is_ascii_digit()))
+            .collect();
+        if parts.len() > 1 {
+            out.push(full.clone());
+        }
+        out.extend(parts.into_iter().map(|p| p.to_string()));
+        cur.clear();
+    };
+    for ch in text.chars() {
+        if ch.is_alphanumeric() || ch == '_' || ch == '.' || ch == '\'' {
+            cur.push(ch);
+        } else {
+            flush(&mut cur, &mut out);
+            if !ch.is_whitespace() && !matches!(ch, '(' | ')' | '{' | '}' | '[' | ']' | ',' | ':') {
+                out.push(ch.to_string());
+            }
+        }
+    }
+    flush(&mut cur, &mut out);
+    out
+}
+
+fn fnv1a(s: &str) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for b in s.as_bytes() {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h
+}
+
+impl Embedder for HashingEmbedder {
+    fn id(&self) -> String {
+        format!("hashing-v1-{}", self.dim)
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut v = vec![0f32; self.dim.max(1)];
+        for t in premise_tokens(text) {
+            let h = fnv1a(&t);
+            let idx = (h % v.len() as u64) as usize;
+            let sign = if (h >> 63) == 0 { 1.0 } else { -1.0 };
+            // Notation symbols are common; down-weight them relative to names.
+            let w = if t.chars().count() == 1 && !t.chars().all(char::is_alphanumeric) {
+                0.5
+            } else {
+                1.0
+            };
+            v[idx] += sign * w;
+        }
+        normalize(&mut v);
+        v
+    }
+}
+
+fn normalize(v: &mut [f32]) {
+    let n = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if n > 0.0 {
+        v.iter_mut().for_each(|x| *x /= n);
+    }
+}
+
+/// Extract `theorem`/`lemma` statements from Lean source text.
+pub fn extract_premises(text: &str, module: Option<&str>) -> Vec<PremiseDecl> {
+    let Ok(header) = regex::Regex::new(
+        r"^(?:@\[[^\]]*\]\s*)*(?:(?:private|protected|nonrec)\s+)*(?:theorem|lemma)\s+([^\s:({\[]+)",
+    ) else {
+        return Vec::new();
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out = Vec::new();
+    let mut i = 0usize;
+    while i < lines.len() {
+        let Some(caps) = header.captures(lines[i]) else {
+            i += 1;
+            continue;
+        };
+        let name = caps.get(1).map(|m| m.as_str()).unwrap_or("").to_string();
+        if lines[i].starts_with("private") {
+            i += 1;
+            continue;
+        }
+        let mut stmt = lines[i][caps.get(0).map(|m| m.end()).unwrap_or(0)..].to_string();
+        let mut j = i;
+        while crate::find_top_level_colon_eq(&stmt).is_none()
+            && !stmt.contains(" where")
+            && j + 1 < lines.len()
+            && j < i + 30
+        {
+            j += 1;
+            if lines[j].trim_start().starts_with('|') {
+                break;
+            }
+            stmt.push(' ');
+            stmt.push_str(lines[j].trim());
+        }
+        if let Some(k) = crate::find_top_level_colon_eq(&stmt) {
+            stmt.truncate(k);
+        }
+        let stmt = stmt.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !stmt.is_empty() {
+            out.push(PremiseDecl {
+                name,
+                statement: stmt,
+                module: module.map(|s| s.to_string()),
+            });
+        }
+        i = j + 1;
+    }
+    out
+}
+
+/// Declarations in every `.lean` file under `src_root` (module names are derived from relative
+/// paths).
+pub fn premises_in_dir(src_root: &Path) -> Result<Vec<PremiseDecl>, String> {
+    let mut entries = Vec::new();
+    for rel in crate::scan::list_lean_files(src_root) {
+        let Ok(text) = std::fs::read_to_string(src_root.join(&rel)) else {
+            continue;
+        };
+        let module = crate::module_name_from_file_rel(&rel);
+        entries.extend(extract_premises(&text, module.as_deref()));
+    }
+    if entries.is_empty() {
+        return Err(format!(
+            "no declarations found under {}",
+            src_root.display()
+        ));
+    }
+    Ok(entries)
+}
+
+/// Bumped when what gets embedded changes (2: binder-normalized statements).
+const INDEX_VERSION: u32 = 2;
+
+/// Statements and queries are embedded binder-normalized, so typeclass arguments don't add noise
+/// and implicit/explicit variants of a lemma land on the same vector.
+fn embed_text(s: &str) -> String {
+    crate::goal_ast::normalize_statement(s)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PremiseIndexMeta {
+    version: u32,
+    embedder: String,
+    dim: usize,
+    entries: Vec<PremiseDecl>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PremiseIndex {
+    pub embedder: String,
+    pub dim: usize,
+    pub entries: Vec<PremiseDecl>,
+    vectors: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PremiseHit {
+    pub score: f32,
+    #[serde(flatten)]
+    pub decl: PremiseDecl,
+}
+
+impl PremiseIndex {
+    pub fn build(entries: Vec<PremiseDecl>, embedder: &dyn Embedder) -> Self {
+        let dim = embedder.dim();
+        let mut vectors = Vec::with_capacity(entries.len() * dim);
+        for e in &entries {
+            vectors.extend(embedder.embed(&format!("{} {}", e.name, embed_text(&e.statement))));
+        }
+        Self {
+            embedder: embedder.id(),
+            dim,
+            entries,
+            vectors,
+        }
+    }
+
+    /// Index every `.lean` file under `src_root` (module names are derived from relative paths).
+    pub fn build_from_dir(src_root: &Path, embedder: &dyn Embedder) -> Result<Self, String> {
+        Ok(Self::build(premises_in_dir(src_root)?, embedder))
+    }
+
+    /// `build` with an embeddings backend (batched and cached; `llm::embeddings`).
+    pub async fn build_with(
+        entries: Vec<PremiseDecl>,
+        emb: &crate::llm::embeddings::Embeddings,
+    ) -> Result<Self, String> {
+        let texts: Vec<String> = entries
+            .iter()
+            .map(|e| format!("{} {}", e.name, embed_text(&e.statement)))
+            .collect();
+        let vs = emb.embed_all(&texts).await?;
+        let dim = vs.first().map_or(0, Vec::len);
+        if vs.iter().any(|v| v.len() != dim) {
+            return Err(format!(
+                "embedder {} returned vectors of mixed sizes",
+                emb.id()
+            ));
+        }
+        Ok(Self {
+            embedder: emb.id(),
+            dim,
+            entries,
+            vectors: vs.concat(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn top_k(
+        &self,
+        query: &str,
+        embedder: &dyn Embedder,
+        k: usize,
+    ) -> Result<Vec<PremiseHit>, String> {
+        if embedder.id() != self.embedder {
+            return Err(format!(
+                "index was built with embedder {}, query uses {}",
+                self.embedder,
+                embedder.id()
+            ));
+        }
+        Ok(self.rank(&embedder.embed(&embed_text(query)), k))
+    }
+
+    /// `top_k` with an embeddings backend; it must be the one the index was built with.
+    pub async fn top_k_with(
+        &self,
+        query: &str,
+        emb: &crate::llm::embeddings::Embeddings,
+        k: usize,
+    ) -> Result<Vec<PremiseHit>, String> {
+        if emb.id() != self.embedder {
+            return Err(format!(
+                "index was built with embedder {}, query uses {}",
+                self.embedder,
+                emb.id()
+            ));
+        }
+        Ok(self.rank(&emb.embed(&embed_text(query)).await?, k))
+    }
+
+    /// Up to `k` lemmas most like `name`, best first: by its statement's vector when it is
+    /// indexed, else (hashing indexes only) by the parts of the name. `name` itself is left out.
+    pub fn siblings(&self, name: &str, k: usize) -> Vec<String> {
+        let is_name = |n: &str| n == name || n.strip_suffix(name).is_some_and(|p| p.ends_with('.'));
+        let q = match self.entries.iter().position(|e| is_name(&e.name)) {
+            Some(i) => self.vectors[i * self.dim..(i + 1) * self.dim].to_vec(),
+            None if self.embedder.starts_with("hashing-") => {
+                HashingEmbedder { dim: self.dim }.embed(name)
+            }
+            None => return Vec::new(),
+        };
+        self.rank(&q, k + 1)
+            .into_iter()
+            .map(|h| h.decl.name)
+            .filter(|n| !is_name(n))
+            .take(k)
+            .collect()
+    }
+
+    fn rank(&self, q: &[f32], k: usize) -> Vec<PremiseHit> {
+        if self.dim == 0 {
+            return Vec::new();
+        }
+        let mut scored: Vec<(f32, usize)> = self
+            .vectors
+            .chunks(self.dim)
+            .enumerate()
+            .map(|(i, v)| (v.iter().zip(q).map(|(a, b)| a * b).sum::<f32>(), i))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        scored
+            .into_iter()
+            .take(k)
+            .map(|(score, i)| PremiseHit {
+                score,
+                decl: self.entries[i].clone(),
+            })
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("mkdir {}: {e}", dir.display()))?;
+        }
+        let meta = PremiseIndexMeta {
+            version: INDEX_VERSION,
+            embedder: self.embedder.clone(),
+            dim: self.dim,
+            entries: self.entries.clone(),
+        };
+        let f =
+            std::fs::File::create(path).map_err(|e| format!("create {}: {e}", path.display()))?;
+        let mut w = std::io::BufWriter::new(f);
+        let header = serde_json::to_string(&meta).map_err(|e| format!("encode index: {e}"))?;
+        w.write_all(header.as_bytes())
+            .and_then(|_| w.write_all(b"\n"))
+            .map_err(|e| format!("write {}: {e}", path.display()))?;
+        for x in &self.vectors {
+            w.write_all(&x.to_le_bytes())
+                .map_err(|e| format!("write {}: {e}", path.display()))?;
+        }
+        w.flush()
+            .map_err(|e| format!("write {}: {e}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let f = std::fs::File::open(path).map_err(|e| format!("open {}: {e}", path.display()))?;
+        let mut r = BufReader::new(f);
+        let mut header = String::new();
+        r.read_line(&mut header)
+            .map_err(|e| format!("read {}: {e}", path.display()))?;
+        let meta: PremiseIndexMeta =
+            serde_json::from_str(&header).map_err(|e| format!("decode index header: {e}"))?;
+        if meta.version != INDEX_VERSION {
+            return Err(format!(
+                "index {} has format version {} (expected {INDEX_VERSION}); rebuild it with `premise-index`",
+                path.display(),
+                meta.version
+            ));
+        }
+        let mut bytes = Vec::new();
+        r.read_to_end(&mut bytes)
+            .map_err(|e| format!("read {}: {e}", path.display()))?;
+        if bytes.len() != meta.entries.len() * meta.dim * 4 {
+            return Err(format!(
+                "corrupt index {}: vector size mismatch",
+                path.display()
+            ));
+        }
+        let vectors = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        Ok(Self {
+            embedder: meta.embedder,
+            dim: meta.dim,
+            entries: meta.entries,
+            vectors,
+        })
+    }
+}
+
+/// Default index location for a repo.
+pub fn default_index_path(repo_root: &Path) -> PathBuf {
+    repo_root
+        .join(".generated")
+        .join("proofpatch-premises")
+        .join("index.bin")
+}
+
+/// Default source tree to index: the repo's Mathlib checkout when present, else the repo itself.
+pub fn default_source_root(repo_root: &Path) -> PathBuf {
+    let mathlib = repo_root.join(".lake").join("packages").join("mathlib");
+    if mathlib.join("Mathlib").exists() {
+        mathlib
+    } else {
+        repo_root.to_path_buf()
+    }
+}
+
+/// Candidate tactics from selected premises: `simp only [top…]` plus per-lemma forms.
+///
+/// Hits whose statements differ only in binder presentation (`foo` with `{n : ℕ}`, `foo'` with
+/// `(n : ℕ)`) count once, under the better-ranked name.
+pub fn candidates_from_premises(hits: &[PremiseHit], max_lemmas: usize) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    let as_lemmas: Vec<crate::lemma_search::LemmaHit> = hits
+        .iter()
+        .filter(|h| seen.insert(crate::goal_ast::normalize_statement(&h.decl.statement)))
+        .map(|h| crate::lemma_search::LemmaHit {
+            name: h.decl.name.clone(),
+            type_signature: h.decl.statement.clone(),
+            module: h.decl.module.clone(),
+            doc: None,
+            source: "premise".to_string(),
+            score: Some(h.score as f64),
+        })
+        .collect();
+    let mut out = crate::simp_sets::simp_candidates(None, &as_lemmas, max_lemmas);
+    out.extend(crate::lemma_search::candidates_from_lemmas(
+        &as_lemmas, max_lemmas,
+    ));
+    crate::tree_search::sanitize_candidates(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "\
+theorem Nat.succ_le_of_lt {n m : ℕ} (h : n < m) : n.succ ≤ m := h
+@[simp] lemma List.length_append (as bs : List α) :
+    (as ++ bs).length = as.length + bs.length := by
+  simp
+private theorem hidden : True := trivial
+lemma Nat.mul_comm' (a b : ℕ) : a * b = b * a := Nat.mul_comm a b
+";
+
+    #[test]
+    fn extracts_multiline_statements() {
+        let ps = extract_premises(SRC, Some("Demo"));
+        let names: Vec<&str> = ps.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["Nat.succ_le_of_lt", "List.length_append", "Nat.mul_comm'"]
+        );
+        assert_eq!(
+            ps[1].statement,
+            "(as bs : List α) : (as ++ bs).length = as.length + bs.length"
+        );
+    }
+
+    #[test]
+    fn ranks_relevant_premise_first_and_roundtrips() {
+        let emb = HashingEmbedder::default();
+        let idx = PremiseIndex::build(extract_premises(SRC, None), &emb);
+        let hits = idx
+            .top_k("⊢ (xs ++ ys).length = xs.length + ys.length", &emb, 2)
+            .unwrap();
+        assert_eq!(hits[0].decl.name, "List.length_append");
+        assert!(candidates_from_premises(&hits, 1)[0].contains("simp only [List.length_append]"));
+
+        let td = tempfile::tempdir().unwrap();
+        let p = td.path().join("index.bin");
+        idx.save(&p).unwrap();
+        let back = PremiseIndex::load(&p).unwrap();
+        assert_eq!(back.len(), 3);
+        let again = back.top_k("a * b = b * a", &emb, 1).unwrap();
+        assert_eq!(again[0].decl.name, "Nat.mul_comm'");
+        assert!(back.top_k("x", &HashingEmbedder { dim: 8 }, 1).is_err());
+    }
+}
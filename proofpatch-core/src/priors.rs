@@ -0,0 +1,196 @@
+//! Success-rate priors learned from the run history (`history`).
+//!
+//! Every recorded run lists its verified attempts (`history::AttemptRecord`): the tactic family
+//! of the candidate (its head tactic, `family`), which model proposed it, and whether it
+//! compiled. `Priors` counts attempts and successes per family and per model over the whole
+//! history, so the repair loop adapts to the repository it runs in: a repo where `omega`
+//! closes most goals and `aesop` never does ranks them accordingly.
+//!
+//! Counts are turned into rates by shrinking towards a built-in prior (the `ranking`
+//! `source_prior`), weighted as `STRENGTH` attempts, so a family seen twice moves the
+//! estimate a little and one seen a hundred times decides it. With
+//! `RepairOptions::history_priors`, the rates replace the source prior in the `compile` signal
+//! (`compile_prior`) and order the fixed heuristic portfolio (`order_portfolio`).
+
+use crate::history::RunRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// How many attempts the built-in prior counts for.
+pub const STRENGTH: f64 = 4.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rate {
+    pub tried: u64,
+    pub solved: u64,
+}
+
+impl Rate {
+    /// The success rate, shrunk towards `prior` (see the module docs).
+    pub fn shrunk(&self, prior: f64) -> f64 {
+        (self.solved as f64 + STRENGTH * prior) / (self.tried as f64 + STRENGTH)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Priors {
+    /// By tactic family (`family`).
+    pub families: BTreeMap<String, Rate>,
+    /// By model name, over the attempts it proposed.
+    pub models: BTreeMap<String, Rate>,
+}
+
+/// The tactic family of a candidate: its head tactic without trailing punctuation
+/// (`simp` for `by\n  (simp; done)`).
+pub fn family(cand: &str) -> String {
+    crate::ranking::head_tactic(cand)
+        .trim_end_matches([';', ',', ')', '<'])
+        .to_string()
+}
+
+impl Priors {
+    pub fn from_records(records: &[RunRecord]) -> Self {
+        let mut p = Self::default();
+        for a in records.iter().flat_map(|r| &r.attempts) {
+            let count = |rate: &mut Rate| {
+                rate.tried += 1;
+                rate.solved += u64::from(a.ok);
+            };
+            if !a.family.is_empty() {
+                count(p.families.entry(a.family.clone()).or_default());
+            }
+            if let Some(m) = &a.model {
+                count(p.models.entry(m.clone()).or_default());
+            }
+        }
+        p
+    }
+
+    /// From the repo's run history (empty when there is none).
+    pub fn load(repo_root: &Path) -> Self {
+        Self::from_records(&crate::history::load(repo_root))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.families.is_empty() && self.models.is_empty()
+    }
+
+    /// Predicted compile success of `cand` from `source`: `ranking::source_prior`, shrunk
+    /// towards the proposing model's history when there is one, then towards its family's.
+    pub fn compile_prior(&self, source: &str, cand: &str, model: Option<&str>) -> f64 {
+        let mut p = crate::ranking::source_prior(source);
+        if let Some(r) = model.and_then(|m| self.models.get(m)) {
+            p = r.shrunk(p);
+        }
+        match self.families.get(&family(cand)) {
+            Some(r) => r.shrunk(p),
+            None => p,
+        }
+    }
+
+    /// `cands` (the fixed heuristics) with the historically most successful families first;
+    /// families without history keep their place relative to each other.
+    pub fn order_portfolio(&self, cands: Vec<String>) -> Vec<String> {
+        if self.families.is_empty() {
+            return cands;
+        }
+        let prior = crate::ranking::source_prior("heuristic");
+        let mut keyed: Vec<(f64, String)> = cands
+            .into_iter()
+            .map(|c| {
+                let rate = self
+                    .families
+                    .get(&family(&c))
+                    .map_or(prior, |r| r.shrunk(prior));
+                (rate, c)
+            })
+            .collect();
+        keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+        keyed.into_iter().map(|(_, c)| c).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::AttemptRecord;
+
+    fn run(attempts: &[(&str, Option<&str>, bool)]) -> RunRecord {
+        RunRecord {
+            file: "A.lean".into(),
+            decl: "foo".into(),
+            goal: None,
+            context: String::new(),
+            prompt: None,
+            patch: None,
+            patch_source: None,
+            ok: attempts.iter().any(|a| a.2),
+            stop_reason: "solved".into(),
+            verifications: attempts.len(),
+            added_at: 0,
+            attempts: attempts
+                .iter()
+                .map(|(c, m, ok)| AttemptRecord {
+                    source: if m.is_some() { "llm" } else { "heuristic" }.into(),
+                    family: family(c),
+                    model: m.map(str::to_string),
+                    ok: *ok,
+                    round: 0,
+                })
+                .collect(),
+            smt_entails: None,
+        }
+    }
+
+    #[test]
+    fn history_moves_rates_and_portfolio_order() {
+        assert_eq!(family("by\n  (simp; done)"), "simp");
+        assert_eq!(family("by\n  classical\n  aesop"), "classical");
+
+        let records: Vec<RunRecord> = (0..20)
+            .map(|_| {
+                run(&[
+                    ("by\n  (simp; done)", None, false),
+                    ("by\n  exact foo", Some("m1"), false),
+                    ("by\n  (omega; done)", None, true),
+                ])
+            })
+            .collect();
+        let p = Priors::from_records(&records);
+        assert_eq!(
+            p.families["omega"],
+            Rate {
+                tried: 20,
+                solved: 20
+            }
+        );
+        assert_eq!(p.models["m1"].tried, 20);
+
+        let base = crate::ranking::source_prior("heuristic");
+        assert!(p.compile_prior("heuristic", "by\n  omega", None) > 0.8);
+        assert!(p.compile_prior("heuristic", "by\n  simp", None) < 0.1);
+        assert_eq!(p.compile_prior("heuristic", "by\n  ring", None), base);
+        assert!(
+            p.compile_prior("llm", "by\n  exact bar", Some("m1"))
+                < p.compile_prior("llm", "by\n  exact bar", Some("m2"))
+        );
+
+        let order = p.order_portfolio(vec![
+            "by\n  (simp; done)".into(),
+            "by\n  (ring_nf; done)".into(),
+            "by\n  (omega; done)".into(),
+            "by\n  aesop".into(),
+        ]);
+        assert_eq!(
+            order,
+            [
+                "by\n  (omega; done)",
+                "by\n  (ring_nf; done)",
+                "by\n  aesop",
+                "by\n  (simp; done)"
+            ]
+        );
+        assert!(Priors::default().is_empty());
+    }
+}
@@ -0,0 +1,301 @@
+//! Fitting a rendered prompt into the model's context window.
+//!
+//! `fit` renders a template and, when the estimated prompt plus the reserved output
+//! (`llm::window::window_for`) does not fit the window, gives each prompt section a token budget
+//! and shrinks the sections over it instead of sending a request that fails with a
+//! context-length error. The budget is shared by weight (`SHARES`); sections that need less than
+//! their share keep all of it and the rest is split among the others.
+//!
+//! Each section shrinks in its own way: the error history drops its oldest entries (it is
+//! newest first), lemmas, research chunks and few-shot examples drop their lowest-ranked entries, the goal
+//! shortens long hypotheses and then omits the ones furthest from the target, and the context
+//! excerpt drops lines from the top (the declaration being repaired is at the bottom). Text
+//! fenced by `untrusted::delimit` keeps its fence.
+
+use crate::prompt_context::estimate_tokens;
+use crate::prompts::{PromptTemplate, PromptVars, RenderedPrompt};
+use serde::{Deserialize, Serialize};
+
+/// Budgeted sections and their weights.
+pub const SHARES: &[(&str, f64)] = &[
+    ("context", 0.35),
+    ("goal", 0.25),
+    ("lemmas", 0.1),
+    ("research", 0.1),
+    ("errors", 0.1),
+    ("examples", 0.1),
+    ("related", 0.1),
+];
+
+/// Hypotheses longer than this many characters are shortened first.
+const LONG_HYPOTHESIS_CHARS: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SectionCut {
+    pub section: String,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+}
+
+/// What `fit` did to a prompt that did not fit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BudgetReport {
+    pub window: usize,
+    /// Output tokens kept free.
+    pub reserved: usize,
+    /// Estimated prompt tokens (including `extra_tokens`) before and after.
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+    pub cuts: Vec<SectionCut>,
+}
+
+/// Render `template` with `vars`, shrinking sections (in `vars`) so that the prompt,
+/// `extra_tokens` (e.g. earlier conversation turns) and `reserved` output tokens fit in
+/// `window`. The report is `None` when nothing had to be cut.
+pub fn fit(
+    template: &PromptTemplate,
+    vars: &mut PromptVars,
+    extra_tokens: usize,
+    window: usize,
+    reserved: usize,
+) -> Result<(RenderedPrompt, Option<BudgetReport>), String> {
+    let prompt = template.render(vars)?;
+    let tokens =
+        |p: &RenderedPrompt| estimate_tokens(&p.system) + estimate_tokens(&p.user) + extra_tokens;
+    let before = tokens(&prompt);
+    let limit = window.saturating_sub(reserved);
+    if before <= limit {
+        return Ok((prompt, None));
+    }
+    let needs: Vec<usize> = SHARES
+        .iter()
+        .map(|(name, _)| estimate_tokens(vars.get(name)))
+        .collect();
+    let fixed = before.saturating_sub(needs.iter().sum());
+    let budgets = allocate(&needs, limit.saturating_sub(fixed));
+    let mut cuts = Vec::new();
+    for (((name, _), &need), &budget) in SHARES.iter().zip(&needs).zip(&budgets) {
+        if need <= budget {
+            continue;
+        }
+        let cut = shrink(name, vars.get(name), budget);
+        cuts.push(SectionCut {
+            section: name.to_string(),
+            tokens_before: need,
+            tokens_after: estimate_tokens(&cut),
+        });
+        *vars = std::mem::take(vars).set(name, cut);
+    }
+    let prompt = template.render(vars)?;
+    let report = BudgetReport {
+        window,
+        reserved,
+        tokens_before: before,
+        tokens_after: tokens(&prompt),
+        cuts,
+    };
+    Ok((prompt, Some(report)))
+}
+
+/// Budget per section (`SHARES` order): sections needing less than their share of what is left
+/// get what they need, the rest split what remains by weight.
+fn allocate(needs: &[usize], available: usize) -> Vec<usize> {
+    let mut out = vec![0usize; needs.len()];
+    let mut open: Vec<usize> = (0..needs.len()).collect();
+    let mut left = available;
+    while !open.is_empty() {
+        let weight: f64 = open.iter().map(|&i| SHARES[i].1).sum();
+        let share = |i: usize| (left as f64 * SHARES[i].1 / weight) as usize;
+        let (sat, rest): (Vec<usize>, Vec<usize>) =
+            open.iter().partition(|&&i| needs[i] <= share(i));
+        if sat.is_empty() {
+            for &i in &rest {
+                out[i] = share(i);
+            }
+            break;
+        }
+        for &i in &sat {
+            out[i] = needs[i];
+            left = left.saturating_sub(needs[i]);
+        }
+        open = rest;
+    }
+    out
+}
+
+/// `text` (the value of section `name`) cut to about `max_tokens`.
+fn shrink(name: &str, text: &str, max_tokens: usize) -> String {
+    // Keep an `untrusted::delimit` fence around whatever is left.
+    if let Some((open, body)) = text
+        .strip_suffix("\n</untrusted>")
+        .filter(|_| text.starts_with("<untrusted"))
+        .and_then(|t| t.split_once('\n'))
+    {
+        let fence = estimate_tokens(open) + estimate_tokens("</untrusted>") + 1;
+        if max_tokens <= fence {
+            return String::new();
+        }
+        let body = shrink(name, body, max_tokens - fence);
+        if body.is_empty() {
+            return String::new();
+        }
+        return format!("{open}\n{body}\n</untrusted>");
+    }
+    let out = match name {
+        "errors" => keep_head(entries(text).into_iter(), max_tokens),
+        "lemmas" => keep_head(text.split_inclusive('\n').map(str::to_string), max_tokens),
+        "examples" | "related" | "research" => {
+            keep_head(text.split_inclusive("\n\n").map(str::to_string), max_tokens)
+        }
+        "goal" => shrink_goal(text, max_tokens),
+        "context" => keep_tail_lines(text, max_tokens),
+        _ => text.to_string(),
+    };
+    cut_chars(&out, max_tokens)
+}
+
+/// `- …` entries, each with its continuation lines.
+fn entries(text: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for line in text.split_inclusive('\n') {
+        match out.last_mut() {
+            Some(last) if !line.starts_with("- ") => last.push_str(line),
+            _ => out.push(line.to_string()),
+        }
+    }
+    out
+}
+
+/// The leading `items` that fit in `max_tokens`.
+fn keep_head(items: impl Iterator<Item = String>, max_tokens: usize) -> String {
+    let mut out = String::new();
+    for item in items {
+        if estimate_tokens(&out) + estimate_tokens(&item) > max_tokens {
+            break;
+        }
+        out.push_str(&item);
+    }
+    out
+}
+
+/// The trailing lines that fit, after a `-- … (n lines omitted)` marker.
+fn keep_tail_lines(text: &str, max_tokens: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let line_tokens = |l: &str| estimate_tokens(&format!("{l}\n"));
+    let mut used = line_tokens("-- … (0000 lines omitted)");
+    let mut start = lines.len();
+    while start > 0 && used + line_tokens(lines[start - 1]) <= max_tokens {
+        used += line_tokens(lines[start - 1]);
+        start -= 1;
+    }
+    if start == 0 {
+        return text.to_string();
+    }
+    format!(
+        "-- … ({start} lines omitted)\n{}",
+        lines[start..].join("\n")
+    )
+}
+
+/// Shorten long hypotheses, then omit hypotheses from the top (furthest from the target); the
+/// `⊢` lines are kept.
+fn shrink_goal(goal: &str, max_tokens: usize) -> String {
+    let mut lines: Vec<String> = goal
+        .lines()
+        .map(|l| {
+            if l.trim_start().starts_with('⊢') || l.chars().count() <= LONG_HYPOTHESIS_CHARS {
+                l.to_string()
+            } else {
+                l.chars()
+                    .take(LONG_HYPOTHESIS_CHARS / 2)
+                    .collect::<String>()
+                    + " …"
+            }
+        })
+        .collect();
+    let mut omitted = 0usize;
+    let render = |lines: &[String], omitted: usize| {
+        let body = lines.join("\n");
+        if omitted == 0 {
+            body
+        } else {
+            format!("… ({omitted} hypotheses omitted)\n{body}")
+        }
+    };
+    while estimate_tokens(&render(&lines, omitted)) > max_tokens {
+        // The first line that is a hypothesis (not a target or `case` tag) goes.
+        let Some(i) = lines.iter().position(|l| {
+            let t = l.trim_start();
+            !t.starts_with('⊢') && !t.starts_with("case ") && !l.starts_with(' ')
+        }) else {
+            break;
+        };
+        lines.remove(i);
+        omitted += 1;
+    }
+    render(&lines, omitted)
+}
+
+/// `text` cut to `max_tokens` (estimated), ending in `…` when cut.
+fn cut_chars(text: &str, max_tokens: usize) -> String {
+    if estimate_tokens(text) <= max_tokens {
+        return text.to_string();
+    }
+    if max_tokens == 0 {
+        return String::new();
+    }
+    text.chars()
+        .take(max_tokens.saturating_mul(4).saturating_sub(1))
+        .collect::<String>()
+        + "…"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuts_oldest_errors_and_hypotheses_but_keeps_target() {
+        let template = PromptTemplate::builtin("repair").unwrap();
+        let context = (0..600)
+            .map(|i| format!("theorem t{i} : {i} = {i} := rfl"))
+            .collect::<Vec<_>>()
+            .join("\n")
+            + "\ntheorem goal : x = x := by\n  sorry";
+        let goal = (0..120)
+            .map(|i| format!("h{i} : {}", "a + ".repeat(80)))
+            .chain(["⊢ x = x".to_string()])
+            .collect::<Vec<_>>()
+            .join("\n");
+        let errors = (0..40)
+            .map(|i| format!("- error {i}: {}\n", "unsolved goals ".repeat(10)))
+            .collect::<String>();
+        let mut vars = PromptVars::new()
+            .set("context", context.clone())
+            .set("goal", goal)
+            .set("errors", errors)
+            .set(
+                "lemmas",
+                crate::untrusted::delimit("lemma search", "Nat.le_refl : n ≤ n"),
+            );
+
+        let (_, report) = fit(&template, &mut vars.clone(), 0, 200_000, 4_096).unwrap();
+        assert!(report.is_none());
+
+        let (p, report) = fit(&template, &mut vars, 0, 6_000, 1_000).unwrap();
+        let report = report.unwrap();
+        assert!(report.tokens_after <= 5_000, "{report:?}");
+        assert!(report.tokens_before > report.tokens_after);
+        assert!(p.user.contains("theorem goal : x = x := by\n  sorry"));
+        assert!(p.user.contains("lines omitted)"));
+        assert!(p.user.contains("hypotheses omitted)\n"));
+        assert!(p.user.contains("⊢ x = x"));
+        assert!(p.user.contains("- error 0:") && !p.user.contains("- error 39:"));
+        // Small enough to keep whole, fence included.
+        assert!(p
+            .user
+            .contains("<untrusted source=\"lemma search\">\nNat.le_refl"));
+        let cut: Vec<_> = report.cuts.iter().map(|c| c.section.as_str()).collect();
+        assert_eq!(cut, ["context", "goal", "errors"]);
+    }
+}
@@ -0,0 +1,438 @@
+//! Minimal, budgeted prompt context for one declaration.
+//!
+//! `extract_decl_block` gives the model a fixed window around the declaration, which misses the
+//! definitions it depends on and pays for unrelated lines. This module collects, in priority order:
+//! 1. the target declaration (always included);
+//! 2. the scope it is elaborated in (`namespace`/`section`, `open`, `variable`, `universe`);
+//! 3. same-file declarations the target mentions, transitively (definitions in full, theorems as
+//!    statements only);
+//! 4. the nearest preceding lemma statements;
+//! 5. the file's imports,
+//!
+//! and stops adding items once the token budget is spent. Tokens are estimated as
+//! `ceil(chars / 4)`, which is close enough for budgeting Lean text.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextItem {
+    pub name: String,
+    pub kind: String,
+    /// 1-based line of the declaration header.
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinimalContext {
+    pub decl: String,
+    pub target: ContextItem,
+    pub imports: Vec<String>,
+    /// Scope commands in effect at the target, in source order.
+    pub scope: Vec<String>,
+    /// Same-file declarations the target depends on.
+    pub dependencies: Vec<ContextItem>,
+    pub nearby_lemmas: Vec<ContextItem>,
+    pub max_tokens: usize,
+    pub estimated_tokens: usize,
+    /// Names of items dropped for budget.
+    pub omitted: Vec<String>,
+}
+
+pub fn estimate_tokens(s: &str) -> usize {
+    s.chars().count().div_ceil(4)
+}
+
+fn header_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"^(?:@\[[^\]]*\]\s*)*(?:(?:private|protected|noncomputable|unsafe|partial)\s+)*(theorem|lemma|def|abbrev|instance|structure|class|inductive|example)\b(?:\s+([^\s:({\[]+))?",
+        )
+        .expect("regex")
+    })
+}
+
+fn ident_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"[\p{L}_][\p{L}\p{N}_'!?]*(?:\.[\p{L}_][\p{L}\p{N}_'!?]*)*").expect("regex")
+    })
+}
+
+/// A top-level declaration: header line and `[start, end)` line range (0-based).
+#[derive(Debug, Clone)]
+struct Decl {
+    kind: String,
+    name: String,
+    start: usize,
+    end: usize,
+}
+
+fn is_continuation(line: &str) -> bool {
+    line.trim().is_empty()
+        || line.starts_with([' ', '\t'])
+        || ["termination_by", "decreasing_by", "where", "|", "deriving"]
+            .iter()
+            .any(|k| line.starts_with(k))
+}
+
+fn top_level_decls(lines: &[&str]) -> Vec<Decl> {
+    let mut out: Vec<Decl> = Vec::new();
+    let mut i = 0usize;
+    while i < lines.len() {
+        let Some((cap, name)) = header_re().captures(lines[i]).and_then(|cap| {
+            let name = match (&cap[1], cap.get(2)) {
+                ("example", _) | ("instance", None) => crate::anonymous_decl_name(&cap[1], i + 1),
+                (_, Some(m)) => m.as_str().to_string(),
+                (_, None) => return None,
+            };
+            Some((cap, name))
+        }) else {
+            i += 1;
+            continue;
+        };
+        let mut j = i + 1;
+        while j < lines.len() && is_continuation(lines[j]) {
+            j += 1;
+        }
+        let mut end = j;
+        while end > i + 1 && lines[end - 1].trim().is_empty() {
+            end -= 1;
+        }
+        out.push(Decl {
+            kind: cap[1].to_string(),
+            name,
+            start: i,
+            end,
+        });
+        i = j;
+    }
+    out
+}
+
+/// Statement only: everything up to the top-level `:=`.
+fn statement_of(lines: &[&str], d: &Decl) -> String {
+    let mut out = Vec::new();
+    for ln in &lines[d.start..d.end] {
+        if let Some(k) = crate::find_top_level_colon_eq(ln) {
+            out.push(ln[..k].trim_end().to_string());
+            break;
+        }
+        out.push(ln.to_string());
+    }
+    out.join("\n")
+}
+
+/// Scope commands in effect before line `upto` (0-based, exclusive).
+fn scope_at(lines: &[&str], upto: usize) -> Vec<String> {
+    // Each entry: (depth, line). `end` drops the current block and everything opened inside it.
+    let mut stack: Vec<(usize, String)> = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0usize;
+    while i < upto {
+        let ln = lines[i];
+        let t = ln.trim_end();
+        let first = t.split_whitespace().next().unwrap_or("");
+        match first {
+            "namespace" | "section" | "noncomputable"
+                if !t.ends_with(" in")
+                    && (first != "noncomputable" || t.starts_with("noncomputable section")) =>
+            {
+                depth += 1;
+                stack.push((depth, t.to_string()));
+            }
+            "end" if !ln.starts_with([' ', '\t']) => {
+                stack.retain(|(d, _)| *d < depth);
+                depth = depth.saturating_sub(1);
+            }
+            "open" | "universe" | "variable" | "set_option" | "local" | "scoped"
+                if !t.ends_with(" in") && !ln.starts_with([' ', '\t']) =>
+            {
+                let mut cmd = vec![t.to_string()];
+                while i + 1 < upto && lines[i + 1].starts_with([' ', '\t']) {
+                    i += 1;
+                    cmd.push(lines[i].trim_end().to_string());
+                }
+                stack.push((depth, cmd.join("\n")));
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    stack.into_iter().map(|(_, s)| s).collect()
+}
+
+/// Namespaces whose names resolve unqualified at line `line_1` (1-based): the enclosing
+/// `namespace`s (and their parents, `namespace A.B` inside `namespace C` gives `C`, `C.A`,
+/// `C.A.B`) and the `open`ed ones.
+pub fn open_namespaces_at(text: &str, line_1: usize) -> Vec<String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let upto = line_1.saturating_sub(1).min(lines.len());
+    let mut current: Vec<String> = Vec::new();
+    let mut out: Vec<String> = Vec::new();
+    for cmd in scope_at(&lines, upto) {
+        let mut words = cmd.split_whitespace();
+        match words.next() {
+            Some("namespace") => {
+                for part in words.next().unwrap_or("").split('.') {
+                    current.push(part.to_string());
+                    out.push(current.join("."));
+                }
+            }
+            Some("open") => {
+                // `open Foo Bar`, `open Foo (a b)`, `open Foo hiding x`, `open scoped Foo`.
+                for w in words {
+                    if w.starts_with('(') || matches!(w, "hiding" | "renaming" | "in") {
+                        break;
+                    }
+                    if w != "scoped" {
+                        out.push(w.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut seen = HashSet::new();
+    out.retain(|n| !n.is_empty() && seen.insert(n.clone()));
+    out
+}
+
+pub(crate) fn mentioned_names(text: &str) -> HashSet<String> {
+    let mut out = HashSet::new();
+    for m in ident_re().find_iter(text) {
+        let s = m.as_str();
+        out.insert(s.to_string());
+        // `Foo.bar` may refer to `bar` inside `namespace Foo`; `x.bar` is dot notation on `bar`.
+        if let Some((_, last)) = s.rsplit_once('.') {
+            out.insert(last.to_string());
+        }
+    }
+    out
+}
+
+fn short_name(name: &str) -> &str {
+    name.rsplit_once('.').map(|(_, s)| s).unwrap_or(name)
+}
+
+fn is_theorem(kind: &str) -> bool {
+    kind == "theorem" || kind == "lemma"
+}
+
+/// Build the minimal context for `decl_name` in `text` within `max_tokens`.
+pub fn minimal_context(
+    text: &str,
+    decl_name: &str,
+    max_tokens: usize,
+) -> Result<MinimalContext, String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let decls = top_level_decls(&lines);
+    let ti = decls
+        .iter()
+        .position(|d| d.name == decl_name || short_name(&d.name) == decl_name)
+        .ok_or_else(|| format!("Could not find theorem/lemma/def named {decl_name}"))?;
+    let td = &decls[ti];
+    let target = ContextItem {
+        name: td.name.clone(),
+        kind: td.kind.clone(),
+        line: td.start + 1,
+        text: lines[td.start..td.end].join("\n"),
+    };
+
+    let mut used = estimate_tokens(&target.text);
+    let mut omitted = Vec::new();
+    let fits = |s: &str, used: &mut usize| {
+        let t = estimate_tokens(s);
+        if *used + t <= max_tokens {
+            *used += t;
+            true
+        } else {
+            false
+        }
+    };
+
+    let mut scope = Vec::new();
+    for s in scope_at(&lines, td.start) {
+        if fits(&s, &mut used) {
+            scope.push(s);
+        } else {
+            omitted.push(s.lines().next().unwrap_or("").to_string());
+        }
+    }
+
+    // Dependencies: breadth-first over mentions, nearest-first within a level.
+    let mut dependencies = Vec::new();
+    let mut seen: HashSet<usize> = HashSet::from([ti]);
+    let mut queue: VecDeque<String> = VecDeque::from([target.text.clone()]);
+    while let Some(src) = queue.pop_front() {
+        let names = mentioned_names(&src);
+        let mut hits: Vec<usize> = (0..decls.len())
+            .filter(|i| !seen.contains(i))
+            .filter(|&i| {
+                names.contains(&decls[i].name) || names.contains(short_name(&decls[i].name))
+            })
+            .collect();
+        hits.sort_by_key(|&i| decls[i].start.abs_diff(td.start));
+        for i in hits {
+            seen.insert(i);
+            let d = &decls[i];
+            let body = if is_theorem(&d.kind) {
+                statement_of(&lines, d)
+            } else {
+                lines[d.start..d.end].join("\n")
+            };
+            if fits(&body, &mut used) {
+                queue.push_back(body.clone());
+                dependencies.push(ContextItem {
+                    name: d.name.clone(),
+                    kind: d.kind.clone(),
+                    line: d.start + 1,
+                    text: body,
+                });
+            } else {
+                omitted.push(d.name.clone());
+            }
+        }
+    }
+    dependencies.sort_by_key(|d| d.line);
+
+    let mut nearby_lemmas = Vec::new();
+    for d in decls[..ti].iter().rev().filter(|d| is_theorem(&d.kind)) {
+        if nearby_lemmas.len() >= 8 {
+            break;
+        }
+        if dependencies.iter().any(|x| x.name == d.name) {
+            continue;
+        }
+        let stmt = statement_of(&lines, d);
+        if !fits(&stmt, &mut used) {
+            break;
+        }
+        nearby_lemmas.push(ContextItem {
+            name: d.name.clone(),
+            kind: d.kind.clone(),
+            line: d.start + 1,
+            text: stmt,
+        });
+    }
+    nearby_lemmas.reverse();
+
+    let mut imports = Vec::new();
+    for ln in lines.iter().take_while(|l| {
+        l.trim().is_empty() || l.starts_with("import") || l.trim_start().starts_with("--")
+    }) {
+        let t = ln.trim();
+        if t.starts_with("import") && fits(t, &mut used) {
+            imports.push(t.to_string());
+        }
+    }
+
+    Ok(MinimalContext {
+        decl: decl_name.to_string(),
+        target,
+        imports,
+        scope,
+        dependencies,
+        nearby_lemmas,
+        max_tokens,
+        estimated_tokens: used,
+        omitted,
+    })
+}
+
+impl MinimalContext {
+    /// Render as Lean-looking text for a prompt: imports, scope, context, then the target.
+    pub fn render(&self) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        if !self.imports.is_empty() {
+            parts.push(self.imports.join("\n"));
+        }
+        if !self.scope.is_empty() {
+            parts.push(self.scope.join("\n"));
+        }
+        if !self.dependencies.is_empty() {
+            parts.push(format!(
+                "-- Definitions and lemmas used by `{}`:\n{}",
+                self.decl,
+                self.dependencies
+                    .iter()
+                    .map(|d| d.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            ));
+        }
+        if !self.nearby_lemmas.is_empty() {
+            parts.push(format!(
+                "-- Nearby lemmas (statements only):\n{}",
+                self.nearby_lemmas
+                    .iter()
+                    .map(|d| d.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            ));
+        }
+        parts.push(self.target.text.clone());
+        parts.join("\n\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "\
+import Mathlib.Data.Nat.Basic
+
+namespace Foo
+
+open Nat
+
+section Aux
+variable (k : ℕ)
+def junk : ℕ := k
+end Aux
+
+def double (n : ℕ) : ℕ := n + n
+
+lemma double_eq (n : ℕ) : double n = 2 * n := by
+  unfold double
+  omega
+
+lemma unrelated : True := trivial
+
+theorem target (n : ℕ) : double (double n) = 4 * n := by
+  rw [Foo.double_eq]
+  sorry
+
+end Foo
+";
+
+    #[test]
+    fn collects_scope_dependencies_and_nearby() {
+        let c = minimal_context(SRC, "target", 2_000).unwrap();
+        assert_eq!(c.scope, vec!["namespace Foo", "open Nat"]);
+        let line = SRC.lines().position(|l| l.contains("rw [Foo")).unwrap() + 1;
+        assert_eq!(open_namespaces_at(SRC, line), vec!["Foo", "Nat"]);
+        let deps: Vec<&str> = c.dependencies.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(deps, vec!["double", "double_eq"]);
+        // Theorems contribute their statement only.
+        assert!(!c.dependencies[1].text.contains("omega"));
+        assert_eq!(c.nearby_lemmas[0].name, "unrelated");
+        assert_eq!(c.imports, vec!["import Mathlib.Data.Nat.Basic"]);
+        let r = c.render();
+        assert!(r.ends_with("  sorry"));
+        assert!(!r.contains("junk"));
+    }
+
+    #[test]
+    fn respects_token_budget() {
+        let target_only = estimate_tokens(&minimal_context(SRC, "target", 0).unwrap().target.text);
+        let c = minimal_context(SRC, "target", target_only + 8).unwrap();
+        assert!(c.estimated_tokens <= target_only + 8);
+        assert!(!c.omitted.is_empty());
+    }
+}
@@ -0,0 +1,453 @@
+//! Versioned prompt templates.
+//!
+//! A template is a `system` / `user` pair with a `version`, written in a small mustache-like
+//! language:
+//! - `{{name}}` inserts a variable (unset variables are empty);
+//! - `{{#name}}…{{/name}}` keeps its body only when `name` is non-empty;
+//! - `{{^name}}…{{/name}}` keeps its body only when `name` is empty.
+//!
+//! Variables are limited to `VARIABLES` (goal, context, retrieved lemmas, prior errors, …), so a
+//! typo in a template is an error when it is loaded, not a silently empty prompt section.
+//!
+//! `PromptTemplate::load` looks for `<name>.toml` in each directory of `PROOFPATCH_PROMPTS_DIR`
+//! (a path list), then of `[prompts] paths` in `proofpatch.toml` (relative to the repo root), and
+//! falls back to the built-in template. The file holds `version`, `system`, and `user`:
+//!
+//! ```toml
+//! version = "team-3"
+//! system = "You are a Lean 4 proof assistant. ..."
+//! user = """
+//! {{context}}
+//! {{#goal}}Goal: {{goal}}{{/goal}}
+//! """
+//! ```
+//!
+//! Every rendered prompt carries a `PromptVersion` (name, declared version, content hash, and
+//! where the template came from), which the repair loop records in `RepairOutcome::prompt`, so
+//! results can be traced to the prompt revision that produced them even when a template was
+//! edited without bumping `version`.
+//!
+//! System prompts are also configurable per task (`Task`) in `[prompts]`: `persona` goes before
+//! every task's system prompt, and `[prompts.system.<task>]` replaces (`replace`) or extends
+//! (`append`) the task's own, which is a built-in string or, for `patch`, the `repair`
+//! template's `system` (`system_prompt`).
+
+use crate::config::PromptsConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// The `[prompts]` section in effect for this process (see `configure`).
+static CONFIGURED: RwLock<Option<PromptsConfig>> = RwLock::new(None);
+
+/// LLM tasks with their own system prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Task {
+    /// Proof generation: the repair loop, `suggest`, `loop`, and the prompt payloads.
+    Patch,
+    /// Summaries of a proof-search run.
+    Summarize,
+    /// The proof-search planner choosing where to focus.
+    Triage,
+    /// Cited summaries of research sources and papers.
+    ResearchSummary,
+    /// `review-diff`.
+    Review,
+}
+
+/// Use `cfg` for `system_prompt` (`None`: built-in system prompts only).
+pub fn configure(cfg: Option<PromptsConfig>) {
+    if let Ok(mut g) = CONFIGURED.write() {
+        *g = cfg;
+    }
+}
+
+/// `builtin`, the system prompt `task` would use by default, merged with the configured
+/// `[prompts]` section.
+pub fn system_prompt(task: Task, builtin: &str) -> String {
+    let cfg = CONFIGURED.read().ok().and_then(|g| g.clone());
+    merge_system(cfg.as_ref(), task, builtin)
+}
+
+/// `persona`, then `replace` (or `builtin`), then `append`, separated by blank lines.
+pub fn merge_system(cfg: Option<&PromptsConfig>, task: Task, builtin: &str) -> String {
+    let Some(cfg) = cfg else {
+        return builtin.to_string();
+    };
+    let t = cfg.system.get(&task);
+    [
+        cfg.persona.as_deref(),
+        Some(t.and_then(|t| t.replace.as_deref()).unwrap_or(builtin)),
+        t.and_then(|t| t.append.as_deref()),
+    ]
+    .into_iter()
+    .flatten()
+    .map(str::trim)
+    .filter(|s| !s.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n\n")
+}
+
+/// Placeholders a template may use.
+pub const VARIABLES: &[&str] = &[
+    // The declaration excerpt (`prompt_context::minimal_context`).
+    "context",
+    // Pretty-printed goal at the hole, when a goal dump is available.
+    "goal",
+    // 1-based line the repair starts from (`RepairOptions::from_line`), when repairing a tail.
+    "from_line",
+    // One sentence for the current repair strategy (`RepairStrategy::prompt_hint`).
+    "strategy",
+    // Declarations repaired earlier in the same file.
+    "related",
+    // Retrieved lemma statements (`lemma_search::lemma_prompt_block`), fenced as untrusted.
+    "lemmas",
+    // Research chunks retrieved for the goal (`research::rag::prompt_block`), fenced as untrusted.
+    "research",
+    // Solved goals similar to this one, with their proofs (`fewshot::examples_prompt_block`).
+    "examples",
+    // First errors of the previous failed attempts, newest first, one `- …` line each.
+    "errors",
+];
+
+/// The built-in repair template. Bump `version` whenever the text changes.
+const REPAIR_VERSION: &str = "repair-4";
+const REPAIR_USER: &str = r#"We are working in a Lean 4 + Mathlib project.
+Here is the declaration context (excerpt):
+
+{{context}}
+
+Task: provide the Lean proof code that replaces the `sorry`/`admit` (the proof term only).{{#from_line}}
+
+Keep the proof before line {{from_line}}; reply with only the tactic steps that replace it from line {{from_line}} to the end of its block.{{/from_line}}{{#goal}}
+
+Goal {{#from_line}}after the kept steps{{/from_line}}{{^from_line}}at the `sorry`{{/from_line}}:
+{{goal}}{{/goal}}{{#strategy}}
+
+{{strategy}}{{/strategy}}{{#related}}
+
+Related declarations (already repaired in this file):
+{{related}}{{/related}}{{#lemmas}}
+
+Lemmas that may help:
+{{lemmas}}{{/lemmas}}{{#research}}
+
+Research notes that may help:
+{{research}}{{/research}}{{#examples}}
+
+Solved examples from this project:
+{{examples}}{{/examples}}{{#errors}}
+
+Previous attempts failed with:
+{{errors}}{{/errors}}"#;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PromptTemplate {
+    #[serde(default)]
+    pub name: String,
+    pub version: String,
+    pub system: String,
+    pub user: String,
+    /// File the template was read from (`None`: built in).
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+/// Which template revision produced a prompt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptVersion {
+    pub name: String,
+    /// The template's declared `version`.
+    pub version: String,
+    /// First 16 hex digits of the SHA-256 of `system` and `user` (before rendering).
+    pub sha256: String,
+    /// `builtin`, or the path of the template file.
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderedPrompt {
+    pub system: String,
+    pub user: String,
+    pub template: PromptVersion,
+}
+
+/// Values for a template's placeholders.
+#[derive(Debug, Clone, Default)]
+pub struct PromptVars(BTreeMap<&'static str, String>);
+
+impl PromptVars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set `name` (one of `VARIABLES`).
+    pub fn set(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.0.insert(name, value.into());
+        self
+    }
+
+    /// The value of `name` (empty when unset).
+    pub fn get(&self, name: &str) -> &str {
+        self.0.get(name).map(|s| s.as_str()).unwrap_or("")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Var(String),
+    /// `(name, inverted, body)`.
+    Section(String, bool, Vec<Node>),
+}
+
+fn parse(src: &str) -> Result<Vec<Node>, String> {
+    // Stack of open sections: (name, inverted, nodes collected so far in the enclosing level).
+    let mut stack: Vec<(String, bool, Vec<Node>)> = Vec::new();
+    let mut cur: Vec<Node> = Vec::new();
+    let mut rest = src;
+    while let Some(open) = rest.find("{{") {
+        if open > 0 {
+            cur.push(Node::Text(rest[..open].to_string()));
+        }
+        let after = &rest[open + 2..];
+        let close = after
+            .find("}}")
+            .ok_or_else(|| "unclosed `{{`".to_string())?;
+        let tag = after[..close].trim();
+        rest = &after[close + 2..];
+        let (sigil, name) = match tag.chars().next() {
+            Some(c @ ('#' | '^' | '/')) => (Some(c), tag[1..].trim()),
+            _ => (None, tag),
+        };
+        if !VARIABLES.contains(&name) {
+            return Err(format!(
+                "unknown placeholder `{name}` (known: {})",
+                VARIABLES.join(", ")
+            ));
+        }
+        match sigil {
+            Some('#') | Some('^') => {
+                stack.push((
+                    name.to_string(),
+                    sigil == Some('^'),
+                    std::mem::take(&mut cur),
+                ));
+            }
+            Some(_) => {
+                let (open_name, inverted, parent) = stack
+                    .pop()
+                    .ok_or_else(|| format!("`{{{{/{name}}}}}` without an open section"))?;
+                if open_name != name {
+                    return Err(format!("`{{{{/{name}}}}}` closes section `{open_name}`"));
+                }
+                let body = std::mem::replace(&mut cur, parent);
+                cur.push(Node::Section(open_name, inverted, body));
+            }
+            None => cur.push(Node::Var(name.to_string())),
+        }
+    }
+    if let Some((name, _, _)) = stack.last() {
+        return Err(format!("section `{name}` is not closed"));
+    }
+    if !rest.is_empty() {
+        cur.push(Node::Text(rest.to_string()));
+    }
+    Ok(cur)
+}
+
+fn render_nodes(nodes: &[Node], vars: &PromptVars, out: &mut String) {
+    for n in nodes {
+        match n {
+            Node::Text(t) => out.push_str(t),
+            Node::Var(v) => out.push_str(vars.get(v)),
+            Node::Section(v, inverted, body) => {
+                if vars.get(v).trim().is_empty() == *inverted {
+                    render_nodes(body, vars, out);
+                }
+            }
+        }
+    }
+}
+
+impl PromptTemplate {
+    /// Built-in templates: `repair` (the repair loop's LLM call).
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "repair" => Some(Self {
+                name: name.to_string(),
+                version: REPAIR_VERSION.to_string(),
+                system: format!(
+                    "{}\n{}",
+                    crate::proof_system_prompt(),
+                    crate::untrusted::SYSTEM_NOTE
+                ),
+                user: REPAIR_USER.to_string(),
+                source: None,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Parse a template file's contents and check its placeholders.
+    pub fn parse_toml(name: &str, txt: &str) -> Result<Self, String> {
+        let mut t: Self = toml::from_str(txt).map_err(|e| e.to_string())?;
+        if t.name.is_empty() {
+            t.name = name.to_string();
+        }
+        if t.version.trim().is_empty() {
+            return Err("`version` is empty".to_string());
+        }
+        parse(&t.system).map_err(|e| format!("system: {e}"))?;
+        parse(&t.user).map_err(|e| format!("user: {e}"))?;
+        Ok(t)
+    }
+
+    /// Directories searched for `<name>.toml`, in order.
+    pub fn search_dirs(repo_root: &Path) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = std::env::var_os("PROOFPATCH_PROMPTS_DIR")
+            .map(|v| std::env::split_paths(&v).collect())
+            .unwrap_or_default();
+        if let Ok(Some(cfg)) = crate::config::load_from_repo_root(repo_root) {
+            dirs.extend(cfg.prompts.paths.iter().map(|p| repo_root.join(p)));
+        }
+        dirs
+    }
+
+    /// The first `<name>.toml` on the search path, else the built-in template. A template file
+    /// that exists but does not parse is an error rather than a silent fallback.
+    pub fn load(repo_root: &Path, name: &str) -> Result<Self, String> {
+        for dir in Self::search_dirs(repo_root) {
+            let p = dir.join(format!("{name}.toml"));
+            if !p.is_file() {
+                continue;
+            }
+            let txt =
+                std::fs::read_to_string(&p).map_err(|e| format!("read {}: {e}", p.display()))?;
+            let mut t =
+                Self::parse_toml(name, &txt).map_err(|e| format!("{}: {e}", p.display()))?;
+            t.source = Some(p);
+            return Ok(t.with_task_system());
+        }
+        Self::builtin(name)
+            .map(Self::with_task_system)
+            .ok_or_else(|| format!("no prompt template named `{name}`"))
+    }
+
+    /// The template's `system` merged with `[prompts]` for its task (`repair`: `Task::Patch`).
+    fn with_task_system(mut self) -> Self {
+        if self.name == "repair" {
+            self.system = system_prompt(Task::Patch, &self.system);
+        }
+        self
+    }
+
+    pub fn version(&self) -> PromptVersion {
+        use sha2::{Digest, Sha256};
+        let mut h = Sha256::new();
+        h.update(self.system.as_bytes());
+        h.update([0u8]);
+        h.update(self.user.as_bytes());
+        let hex = format!("{:x}", h.finalize());
+        PromptVersion {
+            name: self.name.clone(),
+            version: self.version.clone(),
+            sha256: hex[..16].to_string(),
+            source: self
+                .source
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "builtin".to_string()),
+        }
+    }
+
+    pub fn render(&self, vars: &PromptVars) -> Result<RenderedPrompt, String> {
+        let mut system = String::new();
+        render_nodes(&parse(&self.system)?, vars, &mut system);
+        let mut user = String::new();
+        render_nodes(&parse(&self.user)?, vars, &mut user);
+        Ok(RenderedPrompt {
+            system,
+            user,
+            template: self.version(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_sections_and_rejects_unknown_placeholders() {
+        let t = PromptTemplate::builtin("repair").unwrap();
+        let r = t
+            .render(
+                &PromptVars::new()
+                    .set("context", "theorem t : 1 = 1 := by\n  sorry")
+                    .set("goal", "⊢ 1 = 1")
+                    .set("errors", "- unsolved goals\n"),
+            )
+            .unwrap();
+        assert_eq!(
+            r.user,
+            format!(
+                "{}\n\nGoal at the `sorry`:\n⊢ 1 = 1\n\nPrevious attempts failed with:\n- unsolved goals\n",
+                crate::proof_user_prompt("theorem t : 1 = 1 := by\n  sorry")
+            )
+        );
+        assert_eq!(
+            r.system,
+            format!(
+                "{}\n{}",
+                crate::proof_system_prompt(),
+                crate::untrusted::SYSTEM_NOTE
+            )
+        );
+        assert_eq!(r.template.version, REPAIR_VERSION);
+        assert_eq!(r.template.source, "builtin");
+
+        let t = PromptTemplate::parse_toml(
+            "repair",
+            "version = \"v2\"\nsystem = \"s\"\nuser = \"{{#from_line}}from {{from_line}}{{/from_line}}{{^goal}}no goal{{/goal}}\"",
+        )
+        .unwrap();
+        let r = t.render(&PromptVars::new().set("from_line", "7")).unwrap();
+        assert_eq!(r.user, "from 7no goal");
+        assert_ne!(
+            r.template.sha256,
+            PromptTemplate::builtin("repair").unwrap().version().sha256
+        );
+
+        let bad = "version = \"v\"\nsystem = \"\"\nuser = \"{{goall}}\"";
+        assert!(PromptTemplate::parse_toml("repair", bad)
+            .unwrap_err()
+            .contains("unknown placeholder `goall`"));
+        let unclosed = "version = \"v\"\nsystem = \"\"\nuser = \"{{#goal}}x\"";
+        assert!(PromptTemplate::parse_toml("repair", unclosed).is_err());
+
+        let cfg: crate::config::ProofpatchConfig = toml::from_str(
+            "[prompts]\npersona = \"Project Foo.\"\n\
+             [prompts.system.review]\nappend = \"Flag new axioms.\"\n\
+             [prompts.system.triage]\nreplace = \"Pick a hole.\"",
+        )
+        .unwrap();
+        let p = Some(&cfg.prompts);
+        assert_eq!(
+            merge_system(p, Task::Review, "Review it."),
+            "Project Foo.\n\nReview it.\n\nFlag new axioms."
+        );
+        assert_eq!(
+            merge_system(p, Task::Triage, "x"),
+            "Project Foo.\n\nPick a hole."
+        );
+        assert_eq!(merge_system(None, Task::Patch, "x"), "x");
+        assert!(toml::from_str::<crate::config::ProofpatchConfig>(
+            "[prompts.system.reveiw]\nappend = \"x\""
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,584 @@
+//! Candidate ranking: one weighted score per candidate, from every signal the repair loop has.
+//!
+//! `CandidateScore` holds each signal and their weighted sum (`total`); candidates are verified
+//! in decreasing total, ties in the order they were proposed. The signals:
+//! - `smt`: +1 for arithmetic closers (`omega`, `linarith`, …) when the goal is LIA-entailed,
+//!   −1 when it is refuted (no arithmetic closer can prove it), 0 otherwise or for others;
+//! - `validity`: 1 when the candidate is well formed (`well_formed`), else 0;
+//! - `compile`: predicted compile success in [0, 1], a prior by source (`source_prior`), moved
+//!   by the repo's history of the candidate's tactic family and model when known (`priors`),
+//!   cut down for candidates using a name Lean has already reported unknown;
+//! - `length`: −ln(1 + `metrics::ProofMetrics::score`), so shorter proofs go first;
+//! - `lint`: minus the number of style issues (`style::lint_candidate`);
+//! - `confidence`: the model's confidence in the candidate, the mean of its token-logprob
+//!   confidence and its vote share when known, 0.5 for candidates no model proposed;
+//! - `rerank`: a learned reranker's score (`rerank::apply`), 0 without one;
+//! - `script`: the repo's score script's adjustment (`score_hook::apply`), added unweighted,
+//!   0 without one.
+//!
+//! Weights come from `[ranking]` in `proofpatch.toml` (`Weights::from_config`).
+//!
+//! When only the best few can be verified, `select` picks them greedily, discounting each by
+//! `Weights::diversity` times its similarity to those already picked (same head tactic, shared
+//! premises), so five `simp` variants do not crowd out a different approach.
+//!
+//! `explain` breaks a score down into what each signal contributed (value times weight), for
+//! reports (`RepairAttempt::explanation`, `repair::ranking_markdown`).
+
+use crate::config::RankingConfig;
+use crate::goal_ast::candidate_key;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Weights {
+    pub smt: f64,
+    pub validity: f64,
+    pub compile: f64,
+    pub length: f64,
+    pub lint: f64,
+    pub confidence: f64,
+    pub rerank: f64,
+    /// Not a signal: the similarity penalty of `select`.
+    pub diversity: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            smt: 3.0,
+            validity: 4.0,
+            compile: 3.0,
+            length: 0.25,
+            lint: 0.25,
+            confidence: 1.0,
+            rerank: 1.0,
+            diversity: 1.5,
+        }
+    }
+}
+
+impl Weights {
+    /// The defaults, overridden by what `cfg` sets.
+    pub fn from_config(cfg: &RankingConfig) -> Self {
+        let d = Self::default();
+        Self {
+            smt: cfg.smt.unwrap_or(d.smt),
+            validity: cfg.validity.unwrap_or(d.validity),
+            compile: cfg.compile.unwrap_or(d.compile),
+            length: cfg.length.unwrap_or(d.length),
+            lint: cfg.lint.unwrap_or(d.lint),
+            confidence: cfg.confidence.unwrap_or(d.confidence),
+            rerank: cfg.rerank.unwrap_or(d.rerank),
+            diversity: cfg.diversity.unwrap_or(d.diversity),
+        }
+    }
+}
+
+/// Weights from the repo's `proofpatch.toml` (the defaults when absent).
+pub fn configured_weights(repo_root: &Path) -> Weights {
+    crate::config::load_from_repo_root(repo_root)
+        .ok()
+        .flatten()
+        .map(|c| Weights::from_config(&c.ranking))
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CandidateScore {
+    pub smt: f64,
+    pub validity: f64,
+    pub compile: f64,
+    pub length: f64,
+    pub lint: f64,
+    pub confidence: f64,
+    #[serde(default)]
+    pub rerank: f64,
+    /// Added by the score script (`score_hook`), unweighted.
+    #[serde(default)]
+    pub script: f64,
+    /// Weighted sum of the above.
+    pub total: f64,
+    /// Penalty `select` charged for similarity to candidates picked before this one (not part
+    /// of `total`).
+    #[serde(default)]
+    pub diversity: f64,
+}
+
+/// What is known about the goal and the model's replies when candidates are ranked.
+#[derive(Debug, Clone, Default)]
+pub struct RankContext {
+    /// SMT signal on the goal (`Some(true)`: LIA-entailed).
+    pub smt_entails: Option<bool>,
+    /// Names Lean reported as unknown identifiers or constants so far.
+    pub unknown_names: HashSet<String>,
+    /// Token-logprob confidence, by `candidate_key`.
+    pub confidence: HashMap<String, f64>,
+    /// Votes and the number of replies voting, by `candidate_key`.
+    pub votes: HashMap<String, (usize, usize)>,
+    /// The goal at the placeholder, when known (for `rerank::features`).
+    pub goal: Option<String>,
+    /// The model that proposed each LLM candidate, by `candidate_key`.
+    pub models: HashMap<String, String>,
+    /// Success rates from the run history (empty: the source priors alone).
+    pub priors: crate::priors::Priors,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ranked {
+    pub source: String,
+    pub candidate: String,
+    pub score: CandidateScore,
+}
+
+pub fn is_arith_closer(c: &str) -> bool {
+    ["omega", "linarith", "nlinarith", "norm_num", "positivity"]
+        .iter()
+        .any(|t| c.contains(t))
+}
+
+/// Prior probability that a candidate from `source` (`RepairAttempt::source`) compiles: renames
+/// of a failure fix what Lean pointed at, reused proofs closed the same goal elsewhere, library search suggestions were checked by Lean, strategy seeds and LLM proofs target the goal, and the
+/// fixed heuristics are long shots.
+pub fn source_prior(source: &str) -> f64 {
+    match source {
+        "rename" | "reuse" => 0.9,
+        "library" => 0.85,
+        "strategy" => 0.75,
+        "llm" => 0.7,
+        "mutation" => 0.6,
+        "calc" => 0.55,
+        "goal" => 0.5,
+        "stepping_stone" => 0.45,
+        "heuristic" => 0.3,
+        _ => 0.4,
+    }
+}
+
+/// Brackets balance (outside comments and string literals), no placeholder is left, and the
+/// proof does not stop mid-tactic.
+pub fn well_formed(cand: &str) -> bool {
+    let t = cand.trim();
+    if t.is_empty() {
+        return false;
+    }
+    let words = |w: &str| {
+        t.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .any(|x| x == w)
+    };
+    if words("sorry") || words("admit") {
+        return false;
+    }
+    let mut stack: Vec<char> = Vec::new();
+    for line in t.lines() {
+        let line = line.split("--").next().unwrap_or("");
+        let mut in_str = false;
+        let mut prev = ' ';
+        for c in line.chars() {
+            match c {
+                '"' if prev != '\\' => in_str = !in_str,
+                _ if in_str => {}
+                '(' | '[' | '{' | '⟨' => stack.push(c),
+                ')' | ']' | '}' | '⟩' => {
+                    let open = match c {
+                        ')' => '(',
+                        ']' => '[',
+                        '}' => '{',
+                        _ => '⟨',
+                    };
+                    if stack.pop() != Some(open) {
+                        return false;
+                    }
+                }
+                _ => {}
+            }
+            prev = c;
+        }
+    }
+    let last = t.split_whitespace().last().unwrap_or("");
+    stack.is_empty()
+        && !t.ends_with([';', ','])
+        && ![":=", "<;>", "by", "·", "=>", "fun", "<|"].contains(&last)
+}
+
+fn uses_unknown(cand: &str, unknown: &HashSet<String>) -> bool {
+    !unknown.is_empty()
+        && cand
+            .split(|c: char| !(c.is_alphanumeric() || "_.'@".contains(c)))
+            .any(|w| unknown.contains(w.trim_start_matches('@')))
+}
+
+pub fn score(source: &str, cand: &str, ctx: &RankContext, w: &Weights) -> CandidateScore {
+    let smt = match ctx.smt_entails {
+        Some(entailed) if is_arith_closer(cand) => {
+            if entailed {
+                1.0
+            } else {
+                -1.0
+            }
+        }
+        _ => 0.0,
+    };
+    let validity = if well_formed(cand) { 1.0 } else { 0.0 };
+    let key = candidate_key(cand);
+    let model = ctx.models.get(&key).map(String::as_str);
+    let mut compile = ctx.priors.compile_prior(source, cand, model);
+    if uses_unknown(cand, &ctx.unknown_names) {
+        compile *= 0.1;
+    }
+    let length = -(1.0 + crate::metrics::proof_metrics(cand).score()).ln();
+    let lint = -(crate::style::lint_candidate(cand).len() as f64);
+    let model: Vec<f64> = [
+        ctx.confidence.get(&key).copied(),
+        ctx.votes
+            .get(&key)
+            .filter(|(_, n)| *n > 0)
+            .map(|(v, n)| *v as f64 / *n as f64),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let confidence = if model.is_empty() {
+        0.5
+    } else {
+        model.iter().sum::<f64>() / model.len() as f64
+    };
+    CandidateScore {
+        smt,
+        validity,
+        compile,
+        length,
+        lint,
+        confidence,
+        rerank: 0.0,
+        script: 0.0,
+        total: w.smt * smt
+            + w.validity * validity
+            + w.compile * compile
+            + w.length * length
+            + w.lint * lint
+            + w.confidence * confidence,
+        diversity: 0.0,
+    }
+}
+
+/// Stable sort, highest total first.
+pub fn sort(ranked: &mut [Ranked]) {
+    ranked.sort_by(|a, b| b.score.total.total_cmp(&a.score.total));
+}
+
+/// Score `(source, candidate)` pairs and sort them.
+pub fn rank(cands: Vec<(String, String)>, ctx: &RankContext, w: &Weights) -> Vec<Ranked> {
+    let mut out: Vec<Ranked> = cands
+        .into_iter()
+        .map(|(source, candidate)| Ranked {
+            score: score(&source, &candidate, ctx, w),
+            source,
+            candidate,
+        })
+        .collect();
+    sort(&mut out);
+    out
+}
+
+/// The first tactic of a candidate (`simp` for `by simp only [h]`).
+pub fn head_tactic(cand: &str) -> String {
+    let key = candidate_key(cand);
+    key.split_whitespace()
+        .find(|w| *w != "by")
+        .map(|w| w.trim_start_matches(['(', '·']).to_string())
+        .unwrap_or_default()
+}
+
+/// Names a candidate relies on: qualified names, and the entries of `[…]` lemma lists.
+pub fn premises(cand: &str) -> HashSet<String> {
+    let mut out = HashSet::new();
+    let mut depth = 0usize;
+    let mut word = String::new();
+    let mut flush = |word: &mut String, depth: usize| {
+        let w = word
+            .trim_start_matches(['←', '@', '-'])
+            .trim_end_matches('.');
+        if !w.is_empty() && (depth > 0 || w.contains('.')) && !w.starts_with(char::is_numeric) {
+            out.insert(w.to_string());
+        }
+        word.clear();
+    };
+    for c in cand.chars() {
+        if c.is_alphanumeric() || "_.'@←".contains(c) {
+            word.push(c);
+            continue;
+        }
+        flush(&mut word, depth);
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    flush(&mut word, depth);
+    out
+}
+
+/// Similarity in [0, 1]: half for the same head tactic, half the Jaccard overlap of premises
+/// (both without premises count as overlapping).
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let head = if head_tactic(a) == head_tactic(b) {
+        0.5
+    } else {
+        0.0
+    };
+    let (pa, pb) = (premises(a), premises(b));
+    let union = pa.union(&pb).count();
+    let overlap = if union == 0 {
+        1.0
+    } else {
+        pa.intersection(&pb).count() as f64 / union as f64
+    };
+    head + 0.5 * overlap
+}
+
+/// Keep `k` of `ranked` (sorted), trading score for diversity: each pick maximizes its total
+/// minus `w.diversity` times its largest similarity to an earlier pick. With `diversity` 0 this
+/// is the top `k`. The picks stay in pick order.
+pub fn select(ranked: Vec<Ranked>, k: usize, w: &Weights) -> Vec<Ranked> {
+    if w.diversity == 0.0 || ranked.len() <= k {
+        let mut ranked = ranked;
+        ranked.truncate(k);
+        return ranked;
+    }
+    let mut pool = ranked;
+    let mut picked: Vec<Ranked> = Vec::with_capacity(k);
+    while picked.len() < k && !pool.is_empty() {
+        let adjusted = |r: &Ranked| {
+            let sim = picked
+                .iter()
+                .map(|p| similarity(&p.candidate, &r.candidate))
+                .fold(0.0, f64::max);
+            r.score.total - w.diversity * sim
+        };
+        // Ties go to the earlier (higher-ranked) candidate.
+        let best = (1..pool.len()).fold(0, |best, i| {
+            if adjusted(&pool[i]) > adjusted(&pool[best]) {
+                i
+            } else {
+                best
+            }
+        });
+        let mut r = pool.remove(best);
+        r.score.diversity = r.score.total - adjusted(&r);
+        picked.push(r);
+    }
+    picked
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contribution {
+    pub signal: String,
+    pub value: f64,
+    pub weight: f64,
+    /// `value * weight`, its share of the total.
+    pub contribution: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Explanation {
+    /// One per signal, in `CandidateScore` order.
+    pub contributions: Vec<Contribution>,
+    pub total: f64,
+    /// `CandidateScore::diversity`.
+    pub diversity: f64,
+    /// The nonzero contributions, largest first, e.g. `7.12 = validity +4.00, compile +2.10, …`.
+    pub summary: String,
+}
+
+/// What each signal of `s` contributed under `w`.
+pub fn explain(s: &CandidateScore, w: &Weights) -> Explanation {
+    let contributions: Vec<Contribution> = [
+        ("smt", s.smt, w.smt),
+        ("validity", s.validity, w.validity),
+        ("compile", s.compile, w.compile),
+        ("length", s.length, w.length),
+        ("lint", s.lint, w.lint),
+        ("confidence", s.confidence, w.confidence),
+        ("rerank", s.rerank, w.rerank),
+        ("script", s.script, 1.0),
+    ]
+    .into_iter()
+    .map(|(signal, value, weight)| Contribution {
+        signal: signal.to_string(),
+        value,
+        weight,
+        contribution: value * weight,
+    })
+    .collect();
+    let mut parts: Vec<&Contribution> = contributions
+        .iter()
+        .filter(|c| c.contribution.abs() >= 0.005)
+        .collect();
+    parts.sort_by(|a, b| b.contribution.abs().total_cmp(&a.contribution.abs()));
+    let mut summary = format!("{:.2} =", s.total);
+    for (i, c) in parts.iter().enumerate() {
+        let sep = if i == 0 { "" } else { "," };
+        summary.push_str(&format!("{sep} {} {:+.2}", c.signal, c.contribution));
+    }
+    if s.diversity > 0.0 {
+        summary.push_str(&format!(" (diversity −{:.2} at selection)", s.diversity));
+    }
+    Explanation {
+        contributions,
+        total: s.total,
+        diversity: s.diversity,
+        summary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combines_signals_into_one_order() {
+        let cands = |xs: &[(&str, &str)]| {
+            xs.iter()
+                .map(|(s, c)| (s.to_string(), c.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let order = |r: Vec<Ranked>| r.into_iter().map(|r| r.candidate).collect::<Vec<_>>();
+        let w = Weights::default();
+        let pool = cands(&[
+            ("heuristic", "by\n  (simp; done)"),
+            ("heuristic", "by\n  (linarith; done)"),
+            ("llm", "by\n  exact Nat.foo_bar n"),
+            ("llm", "by\n  exact (Nat.le_of_lt h"),
+        ]);
+
+        // SMT entailment lifts arithmetic closers over everything; malformed text sinks.
+        let ctx = RankContext {
+            smt_entails: Some(true),
+            ..Default::default()
+        };
+        let r = order(rank(pool.clone(), &ctx, &w));
+        assert_eq!(r[0], "by\n  (linarith; done)");
+        assert_eq!(r[3], "by\n  exact (Nat.le_of_lt h");
+
+        // Without it, the LLM's proof beats the heuristics, unless it uses an unknown name.
+        let mut ctx = RankContext::default();
+        assert_eq!(
+            order(rank(pool.clone(), &ctx, &w))[0],
+            "by\n  exact Nat.foo_bar n"
+        );
+        ctx.unknown_names.insert("Nat.foo_bar".to_string());
+        assert_eq!(
+            order(rank(pool.clone(), &ctx, &w))[0],
+            "by\n  (linarith; done)"
+        );
+
+        // Model confidence orders otherwise equal candidates.
+        let ctx = RankContext {
+            votes: HashMap::from([(candidate_key("by\n  omega"), (3, 4))]),
+            confidence: HashMap::from([(candidate_key("by\n  simp"), 0.2)]),
+            ..Default::default()
+        };
+        let r = rank(
+            cands(&[("llm", "by\n  simp"), ("llm", "by\n  omega")]),
+            &ctx,
+            &w,
+        );
+        assert_eq!(r[0].candidate, "by\n  omega");
+        assert_eq!(r[0].score.confidence, 0.75);
+
+        assert!(well_formed(
+            "by\n  rw [foo] -- (unbalanced in a comment\n  simp"
+        ));
+        assert!(!well_formed("by\n  refine ⟨_, ?_⟩ <;>"));
+        assert!(!well_formed("by\n  sorry"));
+        let tuned = Weights::from_config(&RankingConfig {
+            smt: Some(0.0),
+            ..Default::default()
+        });
+        assert_eq!((tuned.smt, tuned.validity), (0.0, w.validity));
+    }
+
+    #[test]
+    fn selection_spreads_over_head_tactics_and_premises() {
+        let ctx = RankContext::default();
+        let w = Weights::default();
+        // Strategy seeds (`simp` variants) outrank the heuristics on their own.
+        let pool: Vec<(String, String)> = [
+            ("strategy", "by\n  simp [Nat.add_comm]"),
+            ("strategy", "by\n  simp [Nat.add_comm, Nat.mul_comm]"),
+            ("strategy", "by\n  simp only [Nat.add_comm]"),
+            ("strategy", "by\n  simp [Nat.add_comm] at *"),
+            ("heuristic", "by\n  omega"),
+            ("heuristic", "by\n  exact Nat.le_refl n"),
+        ]
+        .iter()
+        .map(|(s, c)| (s.to_string(), c.to_string()))
+        .collect();
+        let ranked = rank(pool, &ctx, &w);
+        let top: Vec<String> = select(
+            ranked.clone(),
+            3,
+            &Weights {
+                diversity: 0.0,
+                ..w
+            },
+        )
+        .into_iter()
+        .map(|r| head_tactic(&r.candidate))
+        .collect();
+        assert_eq!(top.iter().filter(|h| *h == "simp").count(), 3, "{top:?}");
+        // A second `simp` gets in only with other premises; then a different tactic.
+        let picked: Vec<String> = select(ranked.clone(), 3, &w)
+            .into_iter()
+            .map(|r| r.candidate)
+            .collect();
+        assert_eq!(
+            picked,
+            [
+                "by\n  simp [Nat.add_comm]",
+                "by\n  simp [Nat.add_comm, Nat.mul_comm]",
+                "by\n  omega"
+            ]
+        );
+        let picked: Vec<String> = select(
+            ranked,
+            3,
+            &Weights {
+                diversity: 4.0,
+                ..w
+            },
+        )
+        .into_iter()
+        .map(|r| head_tactic(&r.candidate))
+        .collect();
+        assert_eq!(picked, ["simp", "omega", "exact"]);
+
+        let r = &select(
+            rank(vec![("llm".into(), "by\n  omega".into())], &ctx, &w),
+            1,
+            &w,
+        )[0];
+        let e = explain(&r.score, &w);
+        let sum: f64 = e.contributions.iter().map(|c| c.contribution).sum();
+        assert!((sum - e.total).abs() < 1e-9);
+        assert!(
+            e.summary
+                .starts_with(&format!("{:.2} = validity +4.00", e.total)),
+            "{}",
+            e.summary
+        );
+
+        assert_eq!(
+            premises("by\n  rw [← foo, Nat.succ_le] at h\n  exact h.le"),
+            HashSet::from([
+                "foo".to_string(),
+                "Nat.succ_le".to_string(),
+                "h.le".to_string()
+            ])
+        );
+        assert_eq!(similarity("by simp [a]", "by simp [a]"), 1.0);
+    }
+}
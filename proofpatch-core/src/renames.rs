@@ -0,0 +1,331 @@
+//! Deprecated/renamed lemma repair.
+//!
+//! Mathlib renames lemmas regularly and keeps the old name as a deprecated alias for a while; after
+//! the alias is removed, old proofs (and LLM output trained on them) fail with `unknown
+//! identifier`. The fix is mechanical, so it is tried before anything expensive:
+//! - `RenameTable` maps old names to new ones: a small built-in table, plus entries harvested from
+//!   the `@[deprecated new (since := …)]` attributes of the mathlib checkout in `.lake/packages`
+//!   (`refresh_from_sources`, cached in `.generated/proofpatch-renames.json`), plus anything Lean
+//!   itself reports in a "has been deprecated, use `new` instead" warning;
+//! - `rename_patches` turns the unknown-identifier errors of a compile into per-line edits.
+
+use crate::diagnostics::{Diagnostic, ErrorClass};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Renames known without a mathlib checkout.
+const BUILTIN: &[(&str, &str)] = &[
+    ("Finset.card_le_of_subset", "Finset.card_le_card"),
+    ("pow_lt_pow", "pow_lt_pow_right"),
+    ("pow_le_pow", "pow_le_pow_right"),
+    ("div_le_iff", "div_le_iff₀"),
+    ("le_div_iff", "le_div_iff₀"),
+    ("Real.rpow_nat_cast", "Real.rpow_natCast"),
+    ("Int.coe_nat_dvd", "Int.natCast_dvd_natCast"),
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RenameTable {
+    /// Fully qualified old name → new name.
+    pub entries: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenamePatch {
+    /// 1-based line of the edit.
+    pub line: usize,
+    pub old: String,
+    pub new: String,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '\'' | '!' | '?' | '₀'..='₉')
+}
+
+/// Replace whole-identifier occurrences of `old` in `s`.
+fn replace_ident(s: &str, old: &str, new: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find(old) {
+        let before = rest[..i].chars().next_back();
+        let after = rest[i + old.len()..].chars().next();
+        out.push_str(&rest[..i]);
+        if before.is_some_and(is_ident_char) || after.is_some_and(is_ident_char) {
+            out.push_str(old);
+        } else {
+            out.push_str(new);
+        }
+        rest = &rest[i + old.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The identifier of an `unknown identifier 'foo'` / ``unknown constant `foo` `` error.
+pub fn unknown_name(d: &Diagnostic) -> Option<String> {
+    if !matches!(
+        d.class,
+        ErrorClass::UnknownIdentifier | ErrorClass::UnknownConstant
+    ) {
+        return None;
+    }
+    if let Some(n) = &d.name {
+        return Some(n.trim_start_matches('@').to_string());
+    }
+    let h = d.headline();
+    let start = h.find(['\'', '`'])? + 1;
+    let end = start + h[start..].find(['\'', '`'])?;
+    Some(h[start..end].trim_start_matches('@').to_string())
+}
+
+/// `` `old` has been deprecated, use `new` instead `` (Lean's deprecation warning).
+fn deprecation_warning(message: &str) -> Option<(String, String)> {
+    let head = message.lines().next()?;
+    let rest = head.split("has been deprecated").collect::<Vec<_>>();
+    if rest.len() != 2 {
+        return None;
+    }
+    let tick = |s: &str| -> Option<String> {
+        let a = s.find('`')? + 1;
+        let b = a + s[a..].find('`')?;
+        Some(s[a..b].to_string())
+    };
+    Some((tick(rest[0])?, tick(rest[1])?))
+}
+
+impl RenameTable {
+    pub fn builtin() -> Self {
+        Self {
+            entries: BUILTIN
+                .iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn cache_path(repo_root: &Path) -> PathBuf {
+        repo_root.join(".generated").join("proofpatch-renames.json")
+    }
+
+    /// Built-in entries plus the repo's cache (if `refresh_from_sources` has been run).
+    pub fn load(repo_root: &Path) -> Self {
+        let mut t = Self::builtin();
+        if let Some(cached) = std::fs::read_to_string(Self::cache_path(repo_root))
+            .ok()
+            .and_then(|s| serde_json::from_str::<RenameTable>(&s).ok())
+        {
+            t.entries.extend(cached.entries);
+        }
+        t
+    }
+
+    pub fn save(&self, repo_root: &Path) -> Result<PathBuf, String> {
+        let p = Self::cache_path(repo_root);
+        if let Some(dir) = p.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("mkdir {}: {e}", dir.display()))?;
+        }
+        let s = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(&p, s).map_err(|e| format!("write {}: {e}", p.display()))?;
+        Ok(p)
+    }
+
+    /// Harvest `@[deprecated new …] theorem old` and `@[deprecated …] alias old := new` from one
+    /// source file (names qualified by the enclosing `namespace`s).
+    pub fn harvest_source(&mut self, text: &str) -> usize {
+        let mut ns: Vec<String> = Vec::new();
+        let mut pending: Option<Option<String>> = None;
+        let mut added = 0usize;
+        let qualify = |ns: &[String], n: &str| {
+            if let Some(root) = n.strip_prefix("_root_.") {
+                root.to_string()
+            } else if ns.is_empty() {
+                n.to_string()
+            } else {
+                format!("{}.{n}", ns.join("."))
+            }
+        };
+        for line in text.lines() {
+            let t = line.trim();
+            let mut words = t.split_whitespace();
+            match words.next() {
+                Some("namespace") => {
+                    ns.extend(words.next().unwrap_or("").split('.').map(str::to_string));
+                    continue;
+                }
+                Some("end") => {
+                    let n = words.next().map_or(0, |w| w.split('.').count());
+                    ns.truncate(ns.len().saturating_sub(n));
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(i) = t.find("@[deprecated") {
+                // `@[deprecated new (since := …)]`, `@[deprecated (since := …)]`, `@[simp, deprecated new]`.
+                let target = t[i + "@[deprecated".len()..]
+                    .split_whitespace()
+                    .next()
+                    .map(|w| w.trim_end_matches([']', ',']))
+                    .filter(|w| !w.is_empty() && !w.starts_with('(') && !w.starts_with('"'))
+                    .map(str::to_string);
+                pending = Some(target);
+            }
+            let Some(target) = pending.clone() else {
+                continue;
+            };
+            let decl = t.rsplit(']').next().unwrap_or(t).trim();
+            let mut dw = decl.split_whitespace();
+            let (kw, name) = loop {
+                match dw.next() {
+                    Some("protected" | "private" | "nonrec" | "noncomputable") => continue,
+                    kw => break (kw, dw.next()),
+                }
+            };
+            let new = match (kw, name) {
+                (Some("alias"), Some(_)) => decl
+                    .split_once(":=")
+                    .map(|(_, r)| r.split_whitespace().next().unwrap_or("").to_string())
+                    .or(target.clone()),
+                (Some("theorem" | "lemma" | "def" | "abbrev" | "instance"), Some(_)) => {
+                    target.clone()
+                }
+                _ => continue,
+            };
+            pending = None;
+            let (Some(old), Some(new)) = (name, new.filter(|n| !n.is_empty())) else {
+                continue;
+            };
+            let old = qualify(&ns, old);
+            // The replacement is written relative to the same namespace; keep it short-form-safe by
+            // qualifying it only if it has no dot of its own.
+            let new = if new.contains('.') {
+                new
+            } else {
+                qualify(&ns, &new)
+            };
+            if old != new && self.entries.insert(old, new).is_none() {
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// Harvest every `.lean` file under `.lake/packages/mathlib/Mathlib` (and the repo's own
+    /// sources); returns the number of new entries.
+    pub fn refresh_from_sources(&mut self, repo_root: &Path) -> Result<usize, String> {
+        let repo_root = crate::find_lean_repo_root(repo_root)?;
+        let mut added = 0usize;
+        let mut roots = vec![repo_root.join(".lake/packages/mathlib/Mathlib")];
+        roots.push(repo_root.clone());
+        for root in roots.into_iter().filter(|r| r.is_dir()) {
+            for rel in crate::scan::list_lean_files(&root) {
+                if let Ok(text) = std::fs::read_to_string(root.join(&rel)) {
+                    if text.contains("deprecated") {
+                        added += self.harvest_source(&text);
+                    }
+                }
+            }
+        }
+        Ok(added)
+    }
+
+    /// New name for `name` as written (`Foo.bar`, or `bar` under an open `Foo`).
+    pub fn lookup(&self, name: &str) -> Option<(String, String)> {
+        if let Some(n) = self.entries.get(name) {
+            return Some((name.to_string(), n.clone()));
+        }
+        // Written relative to a namespace: match on the suffix and keep the same relative form.
+        self.entries.iter().find_map(|(old, new)| {
+            let prefix = old.strip_suffix(name)?.strip_suffix('.')?;
+            let rel = new
+                .strip_prefix(prefix)
+                .and_then(|r| r.strip_prefix('.'))
+                .unwrap_or(new);
+            Some((name.to_string(), rel.to_string()))
+        })
+    }
+
+    /// Learn renames announced by Lean's own deprecation warnings.
+    pub fn learn_from_diagnostics(&mut self, diags: &[Diagnostic]) -> usize {
+        let mut n = 0;
+        for d in diags {
+            if let Some((old, new)) = deprecation_warning(&d.message) {
+                if self.entries.insert(old, new).is_none() {
+                    n += 1;
+                }
+            }
+        }
+        n
+    }
+}
+
+/// Edits for the unknown-identifier errors (and deprecation warnings) in `diags`.
+pub fn rename_patches(diags: &[Diagnostic], table: &RenameTable) -> Vec<RenamePatch> {
+    let mut out: Vec<RenamePatch> = Vec::new();
+    for d in diags {
+        let hit = unknown_name(d)
+            .and_then(|n| table.lookup(&n))
+            .or_else(|| deprecation_warning(&d.message));
+        if let Some((old, new)) = hit {
+            let p = RenamePatch {
+                line: d.line,
+                old,
+                new,
+            };
+            if !out.contains(&p) {
+                out.push(p);
+            }
+        }
+    }
+    out
+}
+
+/// Apply `patches` to `text` (each on its own line only).
+pub fn apply_rename_patches(text: &str, patches: &[RenamePatch]) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let mut l = line.to_string();
+        for p in patches.iter().filter(|p| p.line == i + 1) {
+            l = replace_ident(&l, &p.old, &p.new);
+        }
+        out.push_str(&l);
+    }
+    out
+}
+
+/// A candidate proof with every known-renamed identifier from `diags` replaced (anywhere in the
+/// candidate: its line numbers are not the file's), or `None` if nothing applies.
+pub fn rename_candidate(cand: &str, diags: &[Diagnostic], table: &RenameTable) -> Option<String> {
+    let mut out = cand.to_string();
+    for p in rename_patches(diags, table) {
+        out = replace_ident(&out, &p.old, &p.new);
+    }
+    (out != cand).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn harvests_deprecations_and_patches_unknown_identifiers() {
+        let src = "namespace Finset\n\n@[deprecated card_le_card (since := \"2023-12-27\")]\ntheorem card_le_of_subset : True := trivial\n\nend Finset\n\n@[deprecated (since := \"2024-10-02\")] alias div_le_iff := div_le_iff₀\n";
+        let mut t = RenameTable::default();
+        assert_eq!(t.harvest_source(src), 2);
+        assert_eq!(t.entries["Finset.card_le_of_subset"], "Finset.card_le_card");
+        assert_eq!(t.entries["div_le_iff"], "div_le_iff₀");
+
+        let diags = crate::diagnostics::parse_diagnostics(
+            "F.lean:3:8: error: unknown identifier 'card_le_of_subset'\nF.lean:4:2: warning: `pow_le_pow` has been deprecated, use `pow_le_pow_right` instead\n",
+        );
+        let ps = rename_patches(&diags, &t);
+        assert_eq!(ps[0].new, "card_le_card");
+        assert_eq!(ps[1].new, "pow_le_pow_right");
+        let text =
+            "a\nb\n  exact card_le_of_subset h -- not card_le_of_subset'\n  exact pow_le_pow h\n";
+        assert_eq!(
+            apply_rename_patches(text, &ps),
+            "a\nb\n  exact card_le_card h -- not card_le_of_subset'\n  exact pow_le_pow_right h\n"
+        );
+    }
+}
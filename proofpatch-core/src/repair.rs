@@ -0,0 +1,1789 @@
+//! Generate → rank → apply → compile repair loop for one declaration.
+//!
+//! Each round:
+//! 1. generate candidates (deterministic heuristics, goal-derived candidates, a `calc` chain or
+//!    `have` stepping stones built from linear hypotheses, optionally the LLM, which also sees
+//!    the compiler errors from the previous round, as a follow-up turn in the same conversation
+//!    with `multi_turn` (`conversation`); failures on a renamed mathlib lemma are
+//!    retried with the new name first, and the LLM is skipped for that round);
+//!    candidates specific to the kind of error being repaired come first (`strategy`: the class of
+//!    `error_class`, then of the first error of the previous round);
+//! 2. rank them (arithmetic closers first when the SMT oracle says the goal is LIA-entailed);
+//! 3. apply each to the first placeholder in the declaration (`patching`), or, with `from_line`,
+//!    in place of the tactic steps from that line on (the steps before it are kept, and the goal
+//!    dump and the LLM see the goal they leave), compile the patched
+//!    file with `verify_lean_text`, and keep the first error of each failure as feedback
+//!    (optionally, the failing step of a step-by-step `replay`). Candidates get the mechanical
+//!    mathlib style fixes first (`style::autofix`); with `reject_style_issues`, one that compiles
+//!    but fails `style` lint counts as a failure too. With `[verify] parallel = k`, candidates
+//!    are compiled `k` at a time and the rest of a batch is cancelled once one compiles
+//!    (`parallel_verify`). Candidates that fail the safety gate (`safety`: `sorry`, new axioms,
+//!    edits outside the declaration, …) are never compiled, and one that compiles is accepted
+//!    only once `#print axioms` confirms it adds no axioms.
+//!
+//! The first candidate that compiles without errors and without the declaration being admitted is
+//! shrunk with the remaining budget (`minimize`) and returned. Otherwise the loop stops when the
+//! verification budget is spent or when a round produces no untried candidates. Nothing is
+//! written to disk; callers decide what to do with the result. (With `record_exemplars`, the
+//! solved goal is appended to the few-shot exemplar store, `fewshot`; with `record_history`, the
+//! run is appended to the run history, `history`.)
+
+use crate::diagnostics::ErrorClass;
+use crate::goal_ast::{candidate_hash, candidate_key};
+use crate::lean_query::AxiomsReport;
+use crate::llm::provider::TokenLogprob;
+use crate::metrics::MetricsDelta;
+use crate::patching::{apply_patch, EditRecord, PatchTarget};
+use crate::tree_search::{adapt_candidates_for_error, default_det_candidates, sanitize_candidates};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct RepairOptions {
+    /// Maximum generate/verify rounds.
+    pub max_rounds: usize,
+    /// Candidates verified per round.
+    pub candidates_per_round: usize,
+    /// Total `verify_lean_text` calls across all rounds (the main cost budget).
+    pub max_verifications: usize,
+    pub verify_timeout: Duration,
+    /// Ask the configured LLM for candidates (see `llm::chat_completion`).
+    pub use_llm: bool,
+    pub llm_timeout: Duration,
+    /// Dump the goal at the placeholder once (costs one extra compile) to derive candidates and
+    /// the SMT ranking signal.
+    pub goal_dump: bool,
+    pub smt_timeout_ms: u64,
+    /// Token budget for the LLM excerpt (`prompt_context::minimal_context`).
+    pub context_tokens: usize,
+    /// Weigh proof length in the candidate ranking (`ranking`), so simpler candidates go first.
+    pub prefer_simple: bool,
+    /// Replay the first failing multi-step candidate of each round step by step (`replay`) and
+    /// use the failing step and its goals as feedback. Costs one extra compile per round.
+    pub replay_failures: bool,
+    /// When the goal dump is available, propose `have … := by omega` stepping stones chained
+    /// from linear hypotheses (`smt_lia::stepping_stones_from_pp_dump`). Solver calls only.
+    pub stepping_stones: bool,
+    /// When a candidate fails on an unknown identifier with a known rename
+    /// (`renames::RenameTable::load`), retry it with the new name next round, ahead of the LLM.
+    pub renames: bool,
+    /// When a candidate's errors all fall in one step, verify cheap local variants of it next
+    /// round (`mutate`: sibling lemmas, `simp_arith`, a trailing `omega`, flipped rewrites).
+    pub mutations: bool,
+    /// Shrink the winning candidate (`minimize::minimize_proof`) with whatever remains of
+    /// `max_verifications`.
+    pub minimize: bool,
+    /// Apply `style::autofix` to candidates and report mathlib style issues of the solution.
+    pub lint_style: bool,
+    /// Treat a candidate that compiles but has style issues as a failure (with lint as feedback).
+    pub reject_style_issues: bool,
+    /// Check candidates against a warm REPL (`warm::WarmVerifier`) instead of compiling the file
+    /// each time. Only failures are decided warm: a candidate that passes is confirmed with
+    /// `verify_lean_text` (which is also the fallback when the REPL is unavailable).
+    pub warm: bool,
+    /// Check the solution against the `[[verify.matrix]]` versions in `proofpatch.toml`
+    /// (`matrix::verify_matrix`; one compile per version, plus setup on first use).
+    pub version_matrix: bool,
+    /// Repair from this 1-based line of the declaration's tactic script instead of at its first
+    /// placeholder: earlier steps are kept and only the rest of the block is regenerated
+    /// (`patching::PatchTarget::DeclTail`).
+    pub from_line: Option<usize>,
+    /// Judge candidates only by errors inside the declaration, so other broken declarations in
+    /// the same file do not mask a fix (`file_repair`).
+    pub scope_errors_to_decl: bool,
+    /// Stream Lean's output while verifying a candidate and kill it at the first error inside
+    /// the declaration (`verify_candidate`), instead of waiting for the rest of the file.
+    pub early_abort: bool,
+    /// Extra text for the LLM prompt, e.g. lemmas repaired earlier in the same file.
+    pub extra_context: Vec<String>,
+    /// Retrieved lemma statements for the LLM prompt (`{{lemmas}}` in the `repair` template).
+    pub lemmas: Vec<String>,
+    /// Research preset whose results are retrieved against the goal for the LLM prompt
+    /// (`research::rag`; `{{research}}` in the `repair` template).
+    pub research_preset: Option<String>,
+    /// Research chunks retrieved per prompt.
+    pub research_k: usize,
+    /// Estimated tokens per research chunk.
+    pub research_chunk_tokens: usize,
+    /// Self-consistency: draw this many LLM replies per round at `sample_temperature` (in
+    /// parallel) and order their candidates by how many replies agree on them (1: one reply at
+    /// the configured temperature). `[llm.speculative]`, when configured, takes its place
+    /// (`llm::speculative`).
+    pub samples: usize,
+    pub sample_temperature: f32,
+    /// Keep one LLM conversation per declaration and answer failed candidates with their errors
+    /// in it, instead of re-prompting from scratch each round (`conversation`).
+    pub multi_turn: bool,
+    /// Token budget for the conversation's earlier turns.
+    pub conversation_tokens: usize,
+    /// Ask providers that can enforce a JSON Schema for a `CandidateList` (valid by
+    /// construction); other providers' replies go through the free-form extraction.
+    pub structured_output: bool,
+    /// Ask providers that return token logprobs for them, and order LLM candidates with equal
+    /// votes by the model's confidence in them (`candidate_confidence`).
+    pub logprob_confidence: bool,
+    /// Let the LLM call Mathlib lemma search and an SMT arithmetic check while answering
+    /// (`llm::tools::ToolRegistry::standard`; providers with `supports_tools`). Replies are then
+    /// free-form and not cached.
+    pub tools: bool,
+    /// Show the LLM this many solved goals most similar to this one (`fewshot`; 0: none).
+    pub few_shot: usize,
+    /// Append the solved goal and its proof to the repo's exemplar store (`fewshot`).
+    pub record_exemplars: bool,
+    /// Try the proofs recorded for α-equivalent goals in other declarations (`subproofs`) before
+    /// searching: they go first, and the LLM is asked only if they fail.
+    pub reuse_subproofs: bool,
+    /// Append the solved goal's closing script and its `have` sub-lemmas to the repo's
+    /// sub-proof store (`subproofs`).
+    pub record_subproofs: bool,
+    /// Append a record of the run to the repo's run history (`history`).
+    pub record_history: bool,
+    /// Use the run history's success rates per tactic family and model as ranking priors and to
+    /// order the fixed heuristics (`priors`).
+    pub history_priors: bool,
+    /// Repair this placeholder of the declaration (0-based, source order) instead of the first.
+    pub sorry_index: Option<usize>,
+    /// Each attempt is also sent here as soon as it is verified (for streaming front ends).
+    pub progress: Option<tokio::sync::mpsc::UnboundedSender<RepairAttempt>>,
+    /// Class of the error being repaired, when known from an earlier compile; picks the first
+    /// round's strategy (`strategy::strategy_for`).
+    pub error_class: Option<ErrorClass>,
+    /// Save the loop's state here after every round and resume from it (`checkpoint`); removed
+    /// once the declaration is repaired.
+    pub checkpoint: Option<PathBuf>,
+    /// Run Lean's library search (`library_search`: `exact?`, `apply?`, `rw?`) at the hole
+    /// before the first round and verify its closing suggestions first, as source "library".
+    /// Each search tactic costs one verification.
+    pub library_search: bool,
+    /// Verify only candidates from these sources (`RepairAttempt::source`); all when `None`.
+    /// The LLM is asked only if "llm" is among them (`ensemble` runs one strategy at a time).
+    pub sources: Option<Vec<String>>,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        Self {
+            max_rounds: 3,
+            candidates_per_round: 8,
+            max_verifications: 24,
+            verify_timeout: Duration::from_secs(120),
+            use_llm: false,
+            llm_timeout: Duration::from_secs(60),
+            goal_dump: true,
+            smt_timeout_ms: 2_000,
+            context_tokens: 3_000,
+            prefer_simple: true,
+            replay_failures: false,
+            stepping_stones: true,
+            renames: true,
+            mutations: true,
+            minimize: true,
+            lint_style: true,
+            reject_style_issues: false,
+            warm: false,
+            version_matrix: false,
+            from_line: None,
+            scope_errors_to_decl: false,
+            early_abort: true,
+            extra_context: Vec::new(),
+            lemmas: Vec::new(),
+            research_preset: None,
+            research_k: crate::research::rag::DEFAULT_K,
+            research_chunk_tokens: crate::research::rag::DEFAULT_CHUNK_TOKENS,
+            samples: 1,
+            sample_temperature: 0.8,
+            multi_turn: true,
+            conversation_tokens: 8_000,
+            structured_output: true,
+            logprob_confidence: true,
+            tools: false,
+            few_shot: 0,
+            record_exemplars: false,
+            reuse_subproofs: true,
+            record_subproofs: false,
+            record_history: false,
+            history_priors: false,
+            sorry_index: None,
+            progress: None,
+            error_class: None,
+            checkpoint: None,
+            library_search: false,
+            sources: None,
+        }
+    }
+}
+
+impl RepairOptions {
+    /// Override these options with the fields `p` sets (`[repair.profiles.<name>]`).
+    pub fn apply_profile(&mut self, p: &crate::config::RepairProfile) {
+        if let Some(n) = p.max_rounds {
+            self.max_rounds = n;
+        }
+        if let Some(n) = p.candidates_per_round {
+            self.candidates_per_round = n;
+        }
+        if let Some(n) = p.max_verifications {
+            self.max_verifications = n;
+        }
+        if let Some(s) = p.verify_timeout_s {
+            self.verify_timeout = Duration::from_secs(s);
+        }
+        if let Some(on) = p.use_llm {
+            self.use_llm = on;
+        }
+        if let Some(k) = p.few_shot {
+            self.few_shot = k;
+        }
+        if p.research.is_some() {
+            self.research_preset = p.research.clone();
+        }
+        if let Some(k) = p.research_k {
+            self.research_k = k;
+        }
+        if let Some(n) = p.research_chunk_tokens {
+            self.research_chunk_tokens = n;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairAttempt {
+    pub round: usize,
+    /// "heuristic", "goal", "calc", "stepping_stone", "rename", "reuse",
+    /// "mutation", "library", "strategy", or "llm".
+    pub source: String,
+    pub candidate: String,
+    pub ok: bool,
+    pub errors: usize,
+    pub first_error: Option<String>,
+    /// Class of the first error (see `diagnostics::ErrorClass`).
+    #[serde(default)]
+    pub error_class: Option<ErrorClass>,
+    /// With `samples > 1`: how many of the sampled replies proposed this candidate.
+    #[serde(default)]
+    pub votes: Option<usize>,
+    /// For `llm` candidates whose reply came with token logprobs: the model's confidence in
+    /// this candidate (`candidate_confidence`, in (0, 1]).
+    #[serde(default)]
+    pub confidence: Option<f64>,
+    /// How the candidate was ranked (`ranking`).
+    #[serde(default)]
+    pub score: Option<crate::ranking::CandidateScore>,
+    /// What each ranking signal contributed to `score` (`ranking::explain`).
+    #[serde(default)]
+    pub explanation: Option<crate::ranking::Explanation>,
+    /// For `llm` candidates: the model whose reply proposed it first.
+    #[serde(default)]
+    pub model: Option<String>,
+    pub elapsed_ms: u64,
+    /// Lean was killed at the candidate's first error (`RepairOptions::early_abort`).
+    #[serde(default)]
+    pub aborted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairOutcome {
+    pub file: String,
+    pub decl: String,
+    pub ok: bool,
+    pub rounds: usize,
+    pub verifications: usize,
+    /// "solved", "budget_exhausted", "no_new_candidates", or "max_rounds".
+    pub stop_reason: String,
+    /// SMT signal on the goal at the placeholder (`Some(true)`: LIA-entailed).
+    pub smt_entails: Option<bool>,
+    /// When the SMT check refutes the goal: a model and a Lean snippet showing it.
+    #[serde(default)]
+    pub counterexample: Option<crate::smt_lia::Counterexample>,
+    pub attempts: Vec<RepairAttempt>,
+    /// Candidates dropped before verification as variants of one already proposed or tried
+    /// (`goal_ast::candidate_key`).
+    #[serde(default)]
+    pub duplicates: usize,
+    /// Compiles cancelled because another candidate of the same batch succeeded first
+    /// (`parallel_verify`); not counted in `verifications`.
+    #[serde(default)]
+    pub cancelled_verifications: usize,
+    /// Candidates the safety gate turned away before compiling them (`safety`).
+    #[serde(default)]
+    pub safety_rejections: Vec<crate::safety::Rejection>,
+    /// `#print axioms` of the repaired declaration (`safety::check_axioms`).
+    #[serde(default)]
+    pub axioms: Option<AxiomsReport>,
+    pub solution: Option<String>,
+    /// Edit that turns the original text into the repaired text.
+    pub edit: Option<EditRecord>,
+    /// Complexity of the declaration's proof before and after the repair.
+    #[serde(default)]
+    pub complexity: Option<MetricsDelta>,
+    /// What the post-success shrinking pass removed (`solution` is already the minimized proof).
+    #[serde(default)]
+    pub minimized: Option<crate::minimize::MinimizeReport>,
+    /// Mathlib style issues in the solution's edit (`style::lint_edit`), when `lint_style` is on.
+    #[serde(default)]
+    pub style_issues: Vec<crate::style::StyleIssue>,
+    /// Per-version results for the solution, when `version_matrix` is on and versions are
+    /// configured.
+    #[serde(default)]
+    pub matrix: Option<crate::matrix::MatrixReport>,
+    /// Strategy chosen for each round (`None`: no error class known yet).
+    #[serde(default)]
+    pub strategies: Vec<Option<crate::strategy::RepairStrategy>>,
+    /// Revision of the `repair` prompt template the LLM was asked with (`prompts`).
+    #[serde(default)]
+    pub prompt: Option<crate::prompts::PromptVersion>,
+    /// What was cut from the last rendered prompt to fit the model's context window
+    /// (`prompt_budget`; `None`: nothing).
+    #[serde(default)]
+    pub prompt_budget: Option<crate::prompt_budget::BudgetReport>,
+    /// Tokens and estimated cost of this repair's LLM requests (`llm::cost`).
+    #[serde(default)]
+    pub llm_usage: crate::llm::cost::UsageTotals,
+    /// `[llm.routing]` tier asked in each LLM round (`llm::routing`).
+    #[serde(default)]
+    pub llm_tiers: Vec<String>,
+    /// Provider that served each LLM reply, with the ones it failed over from
+    /// (`llm::failover`), e.g. `openai (after anthropic: rate_limited)`.
+    #[serde(default)]
+    pub llm_providers: Vec<String>,
+    /// Tools the LLM called (`RepairOptions::tools`).
+    #[serde(default)]
+    pub llm_tool_calls: Vec<crate::llm::tools::ToolInvocation>,
+    /// Pretty-printed goal at the placeholder, when the goal dump succeeded.
+    #[serde(default)]
+    pub goal: Option<String>,
+    /// Strategies run by the `ensemble` controller, in order (empty for a single repair loop).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arms: Vec<crate::ensemble::Pull>,
+    /// The last rendered `repair` prompt (with `multi_turn`, the conversation's first turn; for
+    /// `history`).
+    #[serde(skip)]
+    pub llm_prompt: Option<crate::prompts::RenderedPrompt>,
+    #[serde(skip)]
+    pub patched_text: Option<String>,
+}
+
+fn strip_fences(s: &str) -> String {
+    let t = s.trim();
+    let Some(rest) = t.strip_prefix("```") else {
+        return t.to_string();
+    };
+    let rest = rest.split_once('\n').map(|(_, r)| r).unwrap_or("");
+    rest.trim_end()
+        .strip_suffix("```")
+        .unwrap_or(rest)
+        .trim()
+        .to_string()
+}
+
+/// Reply shape requested from providers that enforce a response schema
+/// (`RepairOptions::structured_output`).
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct CandidateList {
+    /// Lean proof terms or tactic blocks (starting with `by`) that replace the `sorry`, most
+    /// promising first.
+    pub candidates: Vec<String>,
+}
+
+/// Turn an LLM reply into candidates: a `CandidateList` object, a JSON string array, or the
+/// reply itself as one proof.
+pub fn candidates_from_llm_reply(content: &str) -> Vec<String> {
+    if let Some(list) = crate::json_extract::extract_first_json_value(content)
+        .filter(|v| v.is_object())
+        .and_then(|v| serde_json::from_value::<CandidateList>(v).ok())
+    {
+        return sanitize_candidates(list.candidates);
+    }
+    if let Some(xs) = crate::tree_search::parse_json_string_array(content) {
+        return sanitize_candidates(xs);
+    }
+    let one = strip_fences(content);
+    if one.is_empty() || one.contains("sorry") || one.contains("admit") {
+        return Vec::new();
+    }
+    vec![one]
+}
+
+/// Self-consistency voting over several replies to the same prompt: the distinct candidates
+/// (by `candidate_key`) with the number of replies that proposed each, most agreed-on first (ties
+/// in first-seen order).
+pub fn vote_candidates(replies: &[String]) -> Vec<(String, usize)> {
+    let mut out: Vec<(String, usize)> = Vec::new();
+    let mut index: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    for reply in replies {
+        let mut seen: HashSet<String> = HashSet::new();
+        for c in candidates_from_llm_reply(reply) {
+            let k = candidate_key(&c);
+            if !seen.insert(k.clone()) {
+                continue;
+            }
+            match index.get(&k) {
+                Some(&i) => out[i].1 += 1,
+                None => {
+                    index.insert(k, out.len());
+                    out.push((c, 1));
+                }
+            }
+        }
+    }
+    // Stable, so equal counts keep first-seen order.
+    out.sort_by_key(|c| std::cmp::Reverse(c.1));
+    out
+}
+
+/// Sequence-likelihood confidence of `candidate` in a reply generated as `logprobs`: the
+/// geometric-mean probability of the tokens that produced its text, or of all the reply's
+/// tokens when the text cannot be located (e.g. it was normalized). `None` without tokens.
+pub fn candidate_confidence(logprobs: &[TokenLogprob], candidate: &str) -> Option<f64> {
+    let text: String = logprobs.iter().map(|t| t.token.as_str()).collect();
+    // Inside a JSON reply the candidate appears escaped.
+    let escaped = serde_json::to_string(candidate.trim()).unwrap_or_default();
+    let span = [candidate.trim(), escaped.trim_matches('"')]
+        .into_iter()
+        .filter(|c| !c.is_empty())
+        .find_map(|c| text.find(c).map(|i| (i, i + c.len())));
+    let (mut pos, mut sum, mut n) = (0usize, 0.0f64, 0usize);
+    for t in logprobs {
+        let (start, end) = (pos, pos + t.token.len());
+        pos = end;
+        if span.is_none_or(|(s, e)| start < e && end > s) {
+            sum += t.logprob;
+            n += 1;
+        }
+    }
+    (n > 0).then(|| (sum / n as f64).exp())
+}
+
+/// `candidate_confidence` of each candidate (by `candidate_key`) in replies that came with
+/// token logprobs, the highest when several replies propose it.
+pub fn candidate_confidences(
+    replies: &[(String, Vec<TokenLogprob>)],
+) -> std::collections::HashMap<String, f64> {
+    let mut out: std::collections::HashMap<String, f64> = std::collections::HashMap::new();
+    for (reply, logprobs) in replies {
+        for c in candidates_from_llm_reply(reply) {
+            if let Some(p) = candidate_confidence(logprobs, &c) {
+                let e = out.entry(candidate_key(&c)).or_insert(p);
+                *e = e.max(p);
+            }
+        }
+    }
+    out
+}
+
+/// Metrics delta for `decl`'s proof between two versions of the file.
+fn proof_complexity(before: &str, after: &str, decl: &str) -> MetricsDelta {
+    let proof = |text: &str| {
+        crate::patching::resolve_target(text, &PatchTarget::DeclProof(decl.to_string()))
+            .map(|(s, e)| text[s..e].to_string())
+            .unwrap_or_default()
+    };
+    crate::metrics::compare(&proof(before), &proof(after))
+}
+
+/// True if Lean reported `declaration uses 'sorry'` at `decl_line_1`.
+pub fn decl_admitted_in_output(output: &str, decl_line_1: usize) -> bool {
+    output.lines().any(|l| {
+        let Some(idx) = l
+            .find(": warning: declaration uses 'sorry'")
+            .or_else(|| l.find(": warning: declaration uses 'admit'"))
+        else {
+            return false;
+        };
+        // `<path>:<line>:<col>`, parsed right-to-left (paths may contain `:`).
+        let mut it = l[..idx].rsplitn(3, ':');
+        let _col = it.next();
+        it.next().and_then(|s| s.trim().parse::<usize>().ok()) == Some(decl_line_1)
+    })
+}
+
+fn goal_pretty_and_smt(pp_dump: &Value, smt_timeout_ms: u64) -> (Option<String>, Option<bool>) {
+    let pretty = pp_dump
+        .get("goals")
+        .and_then(|g| g.get(0))
+        .and_then(|g| g.get("pretty"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let smt = crate::smt_lia::entails_from_pp_dump(pp_dump, smt_timeout_ms, 0)
+        .ok()
+        .flatten();
+    (pretty, smt)
+}
+
+/// Whether `d` is an error inside `decl_name` as it appears in `text`.
+fn error_in_decl(d: &crate::diagnostics::Diagnostic, text: &str, decl_name: &str) -> bool {
+    if d.severity != crate::diagnostics::Severity::Error {
+        return false;
+    }
+    let Ok((s, e)) = crate::patching::decl_byte_range(text, decl_name) else {
+        return true;
+    };
+    let first = text[..s].matches('\n').count() + 1;
+    let last = first + text[s..e].matches('\n').count();
+    (first..=last).contains(&d.line)
+}
+
+/// Run the repair loop for `decl_name` in `file_rel`.
+pub async fn repair_decl(
+    repo_root: &Path,
+    file_rel: &str,
+    decl_name: &str,
+    opts: &RepairOptions,
+) -> Result<RepairOutcome, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let abs = repo_root.join(file_rel);
+    let text = std::fs::read_to_string(&abs).map_err(|e| format!("read {}: {e}", abs.display()))?;
+    repair_decl_in_text(&repo_root, file_rel, &text, decl_name, opts).await
+}
+
+/// `provider`, or `provider (after a: kind, b: kind)` when the request failed over.
+fn served_by(r: &crate::llm::ChatCompletionResult) -> String {
+    if r.failover.is_empty() {
+        return r.provider.clone();
+    }
+    let failed: Vec<String> = r
+        .failover
+        .iter()
+        .map(|f| format!("{}: {}", f.provider, f.kind.as_str()))
+        .collect();
+    format!("{} (after {})", r.provider, failed.join(", "))
+}
+
+/// The goal dump at `hole_line` (mid-proof, with `from_line`: the goal left by the kept steps,
+/// at a `sorry` standing in for the rest). `None` when Lean could not produce one.
+async fn dump_goal(
+    repo_root: &Path,
+    file_rel: &str,
+    text: &str,
+    decl_name: &str,
+    hole_line: usize,
+    opts: &RepairOptions,
+) -> Result<Option<serde_json::Value>, String> {
+    let dump_text = match opts.from_line {
+        Some(line) => crate::patching::tail_goal_text(text, decl_name, line)?,
+        None => text.to_string(),
+    };
+    Ok(crate::goal_dump_in_text_at(
+        repo_root,
+        file_rel,
+        &dump_text,
+        opts.verify_timeout,
+        Some(hole_line),
+        None,
+    )
+    .await
+    .ok())
+}
+
+/// The `{{examples}}` block: the `k` most similar solved goals (`fewshot`; empty for `k = 0`).
+async fn few_shot_examples(repo_root: &Path, query: &str, k: usize) -> String {
+    if k == 0 {
+        return String::new();
+    }
+    let store = crate::fewshot::ExemplarStore::load(repo_root);
+    // A configured embeddings backend that fails falls back to the hashing embedder.
+    let hits = match crate::llm::embeddings::from_repo(repo_root) {
+        Ok(emb) if !emb.is_hashing() && !store.is_empty() => {
+            store.top_k_with(query, k, &emb).await.ok()
+        }
+        _ => None,
+    };
+    crate::fewshot::examples_prompt_block(&hits.unwrap_or_else(|| store.top_k(query, k)))
+}
+
+/// The `{{research}}` block: chunks of the profile's research preset most similar to `query`
+/// (`research::rag`; empty without a preset or when retrieval fails).
+async fn research_context(repo_root: &Path, query: &str, opts: &RepairOptions) -> String {
+    let Some(preset) = &opts.research_preset else {
+        return String::new();
+    };
+    crate::research::rag::context_for(
+        repo_root,
+        preset,
+        query,
+        opts.research_k,
+        opts.research_chunk_tokens,
+    )
+    .await
+    .unwrap_or_default()
+}
+
+/// Variables of a fresh `repair` prompt (before any `errors`).
+fn prompt_vars(
+    opts: &RepairOptions,
+    excerpt: &str,
+    goal: Option<&str>,
+    strategy: Option<crate::strategy::RepairStrategy>,
+    examples: &str,
+    research: &str,
+) -> crate::prompts::PromptVars {
+    crate::prompts::PromptVars::new()
+        .set("context", excerpt)
+        .set(
+            "from_line",
+            opts.from_line.map(|l| l.to_string()).unwrap_or_default(),
+        )
+        .set("goal", goal.unwrap_or_default())
+        .set(
+            "strategy",
+            strategy.map(|st| st.prompt_hint()).unwrap_or_default(),
+        )
+        .set(
+            "related",
+            opts.extra_context
+                .iter()
+                .map(|c| format!("{c}\n\n"))
+                .collect::<String>(),
+        )
+        .set(
+            "lemmas",
+            crate::untrusted::delimit("lemma search", &opts.lemmas.join("\n")),
+        )
+        .set("research", research)
+        .set("examples", examples)
+}
+
+/// `ChatOptions` of a repair request for a prompt rendered from `template`.
+fn chat_options(
+    opts: &RepairOptions,
+    template: Option<crate::prompts::PromptVersion>,
+) -> crate::llm::ChatOptions {
+    crate::llm::ChatOptions {
+        template,
+        schema: opts
+            .structured_output
+            .then(|| crate::llm::provider::ResponseSchema::of::<CandidateList>("candidates")),
+        logprobs: opts.logprob_confidence,
+        tools: opts
+            .tools
+            .then(|| std::sync::Arc::new(crate::llm::tools::ToolRegistry::standard())),
+        ..Default::default()
+    }
+}
+
+/// The prompt and chat options of the first LLM round of `repair_decl_in_text` for `decl_name`
+/// (the goal is dumped when `opts.goal_dump`). Answers to it sent ahead of time, e.g. through
+/// `llm::batch`, are what the repair loop finds in the response cache.
+pub async fn first_round_prompt(
+    repo_root: &Path,
+    file_rel: &str,
+    text: &str,
+    decl_name: &str,
+    opts: &RepairOptions,
+) -> Result<(crate::prompts::RenderedPrompt, crate::llm::ChatOptions), String> {
+    let target = match opts.sorry_index {
+        Some(index) => PatchTarget::DeclSorry {
+            decl: decl_name.to_string(),
+            index,
+        },
+        None => PatchTarget::DeclPlaceholder(decl_name.to_string()),
+    };
+    let (hole_start, _) = crate::patching::resolve_target(text, &target)?;
+    let hole_line = text[..hole_start].matches('\n').count() + 1;
+    let mut goal = None;
+    if opts.goal_dump {
+        if let Some(pp) = dump_goal(repo_root, file_rel, text, decl_name, hole_line, opts)
+            .await?
+            .and_then(|v| v.get("pp_dump").filter(|x| !x.is_null()).cloned())
+        {
+            goal = goal_pretty_and_smt(&pp, opts.smt_timeout_ms).0;
+        }
+    }
+    let excerpt = llm_excerpt(text, decl_name, opts.context_tokens)?;
+    let examples = few_shot_examples(
+        repo_root,
+        goal.as_deref().unwrap_or(&excerpt),
+        opts.few_shot,
+    )
+    .await;
+    let research = research_context(repo_root, goal.as_deref().unwrap_or(&excerpt), opts).await;
+    let template = crate::prompts::PromptTemplate::load(repo_root, "repair")?;
+    let strategy = opts.error_class.map(crate::strategy::strategy_for);
+    let (window, reserved) = crate::llm::window::window_for(None);
+    let mut vars = prompt_vars(
+        opts,
+        &excerpt,
+        goal.as_deref(),
+        strategy,
+        &examples,
+        &research,
+    );
+    let (prompt, _) = crate::prompt_budget::fit(&template, &mut vars, 0, window, reserved)?;
+    let chat = crate::llm::ChatOptions {
+        stable_prefix: stable_prefix(&template, &vars, &prompt.user),
+        ..chat_options(opts, Some(prompt.template.clone()))
+    };
+    Ok((prompt, chat))
+}
+
+/// Length of the part of `user` that every first-turn prompt for this declaration shares: what
+/// `template` renders from the context and goal alone (a prompt-cache breakpoint).
+fn stable_prefix(
+    template: &crate::prompts::PromptTemplate,
+    vars: &crate::prompts::PromptVars,
+    user: &str,
+) -> Option<usize> {
+    let base = crate::prompts::PromptVars::new()
+        .set("context", vars.get("context"))
+        .set("from_line", vars.get("from_line"))
+        .set("goal", vars.get("goal"));
+    let head = template.render(&base).ok()?.user;
+    let mut n = head
+        .bytes()
+        .zip(user.bytes())
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !user.is_char_boundary(n) {
+        n -= 1;
+    }
+    (n > 0).then_some(n)
+}
+
+/// The excerpt of `text` the LLM is shown for `decl_name`.
+fn llm_excerpt(text: &str, decl_name: &str, context_tokens: usize) -> Result<String, String> {
+    crate::prompt_context::minimal_context(text, decl_name, context_tokens)
+        .map(|c| c.render())
+        .or_else(|_| crate::extract_decl_block(text, decl_name))
+}
+
+/// `repair_decl` on `text` (a working copy of `file_rel`) instead of the file on disk.
+pub async fn repair_decl_in_text(
+    repo_root: &Path,
+    file_rel: &str,
+    text: &str,
+    decl_name: &str,
+    opts: &RepairOptions,
+) -> Result<RepairOutcome, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    crate::load_dotenv_smart(&repo_root);
+    let outcome = repair_loop(&repo_root, file_rel, text, decl_name, opts).await?;
+    if opts.record_history {
+        let context = llm_excerpt(text, decl_name, opts.context_tokens).unwrap_or_default();
+        let _ = crate::history::append(
+            &repo_root,
+            &crate::history::RunRecord::from_outcome(&outcome, &context),
+        );
+    }
+    Ok(outcome)
+}
+
+/// Compile `probe` (a patched, instrumented copy of the file) for `decl_name`. With
+/// `early_abort`, Lean is killed at the first error inside the declaration
+/// (`verify_lean_text_until`): the candidate has failed, and later declarations would only delay
+/// the next one.
+pub fn verify_candidate(
+    repo_root: &Path,
+    probe: String,
+    decl_name: &str,
+    opts: &RepairOptions,
+) -> impl std::future::Future<Output = Result<crate::VerifyResult, String>> + Send + 'static {
+    let lines = opts
+        .early_abort
+        .then(|| crate::verify::decl_lines(&probe, decl_name))
+        .flatten();
+    let (root, timeout) = (repo_root.to_path_buf(), opts.verify_timeout);
+    async move { crate::verify_lean_text_until(&root, &probe, timeout, lines).await }
+}
+
+/// How a verified candidate fared.
+struct Judged {
+    summary: crate::verify::CandidateVerification,
+    diags: Vec<crate::diagnostics::Diagnostic>,
+    /// Compiled without errors (inside the declaration, with `scope_errors_to_decl`), without
+    /// admitting the declaration, and without style issues when those are rejected.
+    ok: bool,
+    first_error: Option<String>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn judge(
+    vr: &crate::VerifyResult,
+    elapsed: Duration,
+    patched: &str,
+    edit: &EditRecord,
+    file_rel: &str,
+    decl_name: &str,
+    decl_line: usize,
+    opts: &RepairOptions,
+) -> Judged {
+    let s = crate::verify::summarize_build(
+        "",
+        file_rel,
+        None,
+        "",
+        (
+            vr.ok,
+            vr.timeout,
+            vr.returncode,
+            vr.stdout.clone(),
+            vr.stderr.clone(),
+        ),
+        elapsed,
+        &crate::verify::BuildVerifyOptions {
+            reject_sorry_warnings: false,
+            ..Default::default()
+        },
+    );
+    let merged = format!("{}\n{}", vr.stdout, vr.stderr);
+    let diags = crate::diagnostics::parse_diagnostics_from(&vr.stdout, &vr.stderr);
+    let clean = if opts.scope_errors_to_decl {
+        !vr.timeout && !diags.iter().any(|d| error_in_decl(d, patched, decl_name))
+    } else {
+        s.ok && s.errors == 0
+    };
+    let compiled = clean && !decl_admitted_in_output(&merged, decl_line);
+    let style_issues = if compiled && opts.reject_style_issues {
+        crate::style::lint_edit(patched, edit)
+    } else {
+        Vec::new()
+    };
+    let first_error = match style_issues.first() {
+        Some(i) => Some(format!("style ({}): {}", i.rule, i.message)),
+        None => s.first_error.clone(),
+    };
+    Judged {
+        summary: s,
+        diags,
+        ok: compiled && style_issues.is_empty(),
+        first_error,
+    }
+}
+
+async fn repair_loop(
+    repo_root: &Path,
+    file_rel: &str,
+    text: &str,
+    decl_name: &str,
+    opts: &RepairOptions,
+) -> Result<RepairOutcome, String> {
+    let repo_root = repo_root.to_path_buf();
+    let text = text.to_string();
+    let target = match (opts.from_line, opts.sorry_index) {
+        (Some(line), _) => PatchTarget::DeclTail {
+            decl: decl_name.to_string(),
+            line,
+        },
+        (None, Some(index)) => PatchTarget::DeclSorry {
+            decl: decl_name.to_string(),
+            index,
+        },
+        (None, None) => PatchTarget::DeclPlaceholder(decl_name.to_string()),
+    };
+    let caps = crate::toolchain::detect(&repo_root).capabilities;
+    let (hole_start, _) = crate::patching::resolve_target(&text, &target)?;
+    let hole_line = text[..hole_start].matches('\n').count() + 1;
+    let decl_span = crate::patching::decl_byte_range(&text, decl_name)?;
+    let decl_start = decl_span.0;
+    let decl_line = text[..decl_start].matches('\n').count() + 1;
+
+    let mut outcome = RepairOutcome {
+        file: file_rel.to_string(),
+        decl: decl_name.to_string(),
+        ok: false,
+        rounds: 0,
+        verifications: 0,
+        stop_reason: "max_rounds".to_string(),
+        smt_entails: None,
+        counterexample: None,
+        attempts: Vec::new(),
+        duplicates: 0,
+        cancelled_verifications: 0,
+        safety_rejections: Vec::new(),
+        axioms: None,
+        solution: None,
+        edit: None,
+        complexity: None,
+        minimized: None,
+        style_issues: Vec::new(),
+        matrix: None,
+        strategies: Vec::new(),
+        prompt: None,
+        prompt_budget: None,
+        llm_usage: Default::default(),
+        llm_tiers: Vec::new(),
+        llm_providers: Vec::new(),
+        llm_tool_calls: Vec::new(),
+        goal: None,
+        arms: Vec::new(),
+        llm_prompt: None,
+        patched_text: None,
+    };
+
+    let mut goal_pretty: Option<String> = None;
+    let mut smt_cands: Vec<(String, String)> = Vec::new();
+    if opts.goal_dump {
+        // Mid-proof: dump the goal left by the kept steps, at a `sorry` standing in for the rest.
+        if let Some(v) = dump_goal(&repo_root, file_rel, &text, decl_name, hole_line, opts).await? {
+            if let Some(pp) = v.get("pp_dump").filter(|x| !x.is_null()) {
+                (goal_pretty, outcome.smt_entails) = goal_pretty_and_smt(pp, opts.smt_timeout_ms);
+                if outcome.smt_entails == Some(false) {
+                    outcome.counterexample =
+                        crate::smt_lia::counterexample_from_pp_dump(pp, opts.smt_timeout_ms, 0, 0)
+                            .ok()
+                            .flatten();
+                }
+                if let Some(c) = crate::smt_lia::calc_from_pp_dump(pp, 4) {
+                    smt_cands.push(("calc".to_string(), c));
+                }
+                if opts.stepping_stones {
+                    let stones =
+                        crate::smt_lia::stepping_stones_from_pp_dump(pp, opts.smt_timeout_ms, 0, 3)
+                            .unwrap_or_default();
+                    smt_cands.extend(
+                        crate::smt_lia::stepping_stone_candidates(&stones)
+                            .into_iter()
+                            .map(|c| ("stepping_stone".to_string(), c)),
+                    );
+                }
+            }
+        }
+    }
+
+    outcome.goal = goal_pretty.clone();
+    let excerpt = llm_excerpt(&text, decl_name, opts.context_tokens)?;
+    let allowed = |source: &str| {
+        opts.sources
+            .as_ref()
+            .is_none_or(|s| s.iter().any(|x| x == source))
+    };
+    let template = (opts.use_llm && allowed("llm"))
+        .then(|| crate::prompts::PromptTemplate::load(&repo_root, "repair"))
+        .transpose()?;
+    let (examples, research) = match &template {
+        Some(_) => {
+            let query = goal_pretty.as_deref().unwrap_or(&excerpt);
+            (
+                few_shot_examples(&repo_root, query, opts.few_shot).await,
+                research_context(&repo_root, query, opts).await,
+            )
+        }
+        None => (String::new(), String::new()),
+    };
+    let mut tried: HashSet<u64> = HashSet::new();
+    let weights = crate::ranking::configured_weights(&repo_root);
+    let weights = crate::ranking::Weights {
+        length: if opts.prefer_simple {
+            weights.length
+        } else {
+            0.0
+        },
+        lint: if opts.lint_style { weights.lint } else { 0.0 },
+        ..weights
+    };
+    let mut rank_ctx = crate::ranking::RankContext {
+        smt_entails: outcome.smt_entails,
+        goal: goal_pretty.clone(),
+        priors: if opts.history_priors {
+            crate::priors::Priors::load(&repo_root)
+        } else {
+            Default::default()
+        },
+        ..Default::default()
+    };
+    let reranker = crate::rerank::configured(&repo_root)?;
+    let score_hook = crate::score_hook::configured(&repo_root)?;
+    let mut feedback: Vec<String> = Vec::new();
+    let mut conversation: Option<crate::conversation::RepairConversation> = None;
+    let mut router = template
+        .as_ref()
+        .and_then(|_| crate::llm::configured()?.routing)
+        .and_then(|r| {
+            let hard = crate::llm::routing::is_hard(&r, goal_pretty.as_deref(), opts.error_class);
+            crate::llm::routing::Router::new(&r, hard)
+        });
+    // LLM candidates that failed since the model was last asked.
+    let mut pending: Vec<crate::conversation::FailedCandidate> = Vec::new();
+    let mut rename_table = opts
+        .renames
+        .then(|| crate::renames::RenameTable::load(&repo_root));
+    let mut renamed: Vec<(String, String)> = Vec::new();
+    let mut mutants: Vec<(String, String)> = Vec::new();
+    let premises = opts
+        .mutations
+        .then(|| crate::premise::default_index_path(&repo_root))
+        .filter(|p| p.exists())
+        .and_then(|p| crate::premise::PremiseIndex::load(&p).ok());
+    let mut warm = if opts.warm {
+        crate::warm::WarmVerifier::start(
+            &repo_root,
+            file_rel,
+            &text,
+            decl_name,
+            opts.verify_timeout,
+        )
+        .await
+        .ok()
+    } else {
+        None
+    };
+
+    // The warm REPL checks one candidate at a time, so it verifies sequentially.
+    let parallel = crate::parallel_verify::settings(&repo_root).filter(|_| warm.is_none());
+    let safety = crate::safety::SafetyPolicy::configured(&repo_root);
+    let (span_start, span_end) = crate::patching::resolve_target(&text, &target)?;
+    let original_span = crate::strategy::span_script(&text, span_start, span_end);
+    // A new LLM conversation is started on resume, with the saved errors as feedback.
+    let resumed = match &opts.checkpoint {
+        Some(p) => crate::checkpoint::RepairCheckpoint::load(p, &text, decl_name)?,
+        None => None,
+    };
+    if let Some(c) = resumed {
+        tried.extend(c.tried);
+        feedback = c.feedback;
+        renamed = c.renamed;
+        mutants = c.mutants;
+        rank_ctx.unknown_names.extend(c.unknown_names);
+        if let Some(r) = router.as_mut() {
+            for _ in &c.outcome.llm_tiers {
+                r.round_failed();
+            }
+        }
+        outcome = c.outcome;
+    }
+    if opts.library_search && outcome.rounds == 0 {
+        let lopts = crate::library_search::LibrarySearchOptions {
+            timeout: opts.verify_timeout,
+            ..Default::default()
+        };
+        if let Ok(r) = crate::library_search::library_search_in_text(
+            &repo_root, file_rel, &text, &target, &lopts,
+        )
+        .await
+        {
+            outcome.verifications += r.passes.len();
+            renamed.extend(
+                crate::library_search::candidates_from_suggestions(&r.suggestions)
+                    .into_iter()
+                    .map(|c| ("library".to_string(), c)),
+            );
+        }
+    }
+    if let Some(g) = goal_pretty.as_ref().filter(|_| opts.reuse_subproofs) {
+        if outcome.rounds == 0 {
+            let store = crate::subproofs::SubproofStore::load(&repo_root);
+            renamed.extend(
+                store
+                    .lookup(g)
+                    .into_iter()
+                    .map(|s| s.proof.clone())
+                    .filter(|c| caps.supports_candidate(c))
+                    .take(4)
+                    .map(|c| ("reuse".to_string(), c)),
+            );
+        }
+    }
+    for round in outcome.rounds..opts.max_rounds {
+        outcome.rounds = round + 1;
+        let last_error = feedback.last().map(|s| s.as_str());
+        let last_failed = outcome.attempts.iter().rev().find(|a| !a.ok);
+        let strategy = last_failed
+            .and_then(|a| a.error_class)
+            .or(opts.error_class)
+            .map(crate::strategy::strategy_for);
+        outcome.strategies.push(strategy);
+        let previous = last_failed
+            .map(|a| a.candidate.as_str())
+            .unwrap_or(&original_span);
+        let seeds: Vec<(String, String)> = strategy
+            .map(|st| st.seed_candidates(Some(previous), opts.from_line.is_some()))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|c| caps.supports_candidate(c))
+            .filter(|c| tried.insert(candidate_hash(c)))
+            .take(4)
+            .map(|c| ("strategy".to_string(), c))
+            .collect();
+
+        let mut cands: Vec<(String, String)> = Vec::new();
+        let asked_llm = template.is_some() && renamed.is_empty();
+        if let Some(template) = template.as_ref().filter(|_| renamed.is_empty()) {
+            let follow_up = conversation
+                .as_ref()
+                .filter(|c| !c.is_empty() && !pending.is_empty())
+                .map(|c| {
+                    (
+                        c.system.clone(),
+                        crate::conversation::feedback_message(&pending),
+                        c.history(),
+                    )
+                });
+            pending.clear();
+            let mut stable = None;
+            let (system, user, history) = match follow_up {
+                Some(turn) => turn,
+                None => {
+                    let mut vars = prompt_vars(
+                        opts,
+                        &excerpt,
+                        goal_pretty.as_deref(),
+                        strategy,
+                        &examples,
+                        &research,
+                    );
+                    if !feedback.is_empty() {
+                        vars = vars.set(
+                            "errors",
+                            feedback
+                                .iter()
+                                .rev()
+                                .take(3)
+                                .map(|e| format!("- {e}\n"))
+                                .collect::<String>(),
+                        );
+                    }
+                    let (window, reserved) =
+                        crate::llm::window::window_for(router.as_ref().map(|r| r.current()));
+                    let (prompt, budget) =
+                        crate::prompt_budget::fit(template, &mut vars, 0, window, reserved)?;
+                    if budget.is_some() {
+                        outcome.prompt_budget = budget;
+                    }
+                    stable = stable_prefix(template, &vars, &prompt.user);
+                    outcome.llm_prompt = Some(prompt.clone());
+                    outcome.prompt = Some(prompt.template);
+                    conversation = opts.multi_turn.then(|| {
+                        crate::conversation::RepairConversation::new(
+                            &prompt.system,
+                            opts.conversation_tokens,
+                        )
+                    });
+                    (prompt.system, prompt.user, Vec::new())
+                }
+            };
+            let chat_opts = crate::llm::ChatOptions {
+                history,
+                tier: router.as_ref().map(|r| r.current()),
+                stable_prefix: stable,
+                ..chat_options(opts, outcome.prompt.clone())
+            };
+            if let Some(r) = &router {
+                outcome.llm_tiers.push(r.current_name().to_string());
+            }
+            let speculative = crate::llm::speculative::settings();
+            let results = if let Some(spec) = &speculative {
+                let out = crate::llm::speculative::speculative_completions(
+                    &system,
+                    &user,
+                    opts.llm_timeout,
+                    &chat_opts,
+                    spec,
+                    |reply| {
+                        candidates_from_llm_reply(reply)
+                            .into_iter()
+                            .filter(|c| caps.supports_candidate(c))
+                            .collect()
+                    },
+                    candidate_key,
+                )
+                .await;
+                outcome.llm_usage.cancelled_requests += out.cancelled as u64;
+                out.results
+            } else if opts.samples > 1 {
+                crate::llm::sample_completions(
+                    &system,
+                    &user,
+                    opts.llm_timeout,
+                    &chat_opts,
+                    opts.samples,
+                    opts.sample_temperature,
+                )
+                .await
+            } else {
+                vec![
+                    crate::llm::chat_completion_with(&system, &user, opts.llm_timeout, &chat_opts)
+                        .await,
+                ]
+            };
+            let mut replies: Vec<String> = Vec::new();
+            let mut scored: Vec<(String, Vec<TokenLogprob>)> = Vec::new();
+            for r in results {
+                match r {
+                    Ok(r) => {
+                        outcome.llm_usage.add(&r.usage);
+                        outcome.llm_providers.push(served_by(&r));
+                        outcome.llm_tool_calls.extend(r.tool_calls);
+                        for c in candidates_from_llm_reply(&r.content) {
+                            for key in
+                                [candidate_key(&c), candidate_key(&crate::style::autofix(&c))]
+                            {
+                                rank_ctx
+                                    .models
+                                    .entry(key)
+                                    .or_insert_with(|| r.model.clone());
+                            }
+                        }
+                        if let Some(lp) = r.logprobs.filter(|lp| !lp.is_empty()) {
+                            scored.push((r.content.clone(), lp));
+                        }
+                        replies.push(r.content);
+                    }
+                    Err(e) if e.starts_with(crate::llm::cost::BUDGET_EXHAUSTED) => {
+                        outcome.llm_usage.refused_requests += 1;
+                    }
+                    Err(_) => {}
+                }
+            }
+            let mut voted = vote_candidates(&replies);
+            let conf = candidate_confidences(&scored);
+            let conf_of = |c: &str| conf.get(&candidate_key(c)).copied();
+            // Equal votes: more confident first (candidates without a score last).
+            voted.sort_by(|a, b| {
+                b.1.cmp(&a.1).then_with(|| {
+                    let (x, y) = (conf_of(&a.0), conf_of(&b.0));
+                    y.unwrap_or(-1.0).total_cmp(&x.unwrap_or(-1.0))
+                })
+            });
+            if let Some(c) = conversation.as_mut().filter(|_| !replies.is_empty()) {
+                // Several samples are summarized as the candidate list they voted for.
+                let reply = match replies.as_slice() {
+                    [one] => one.clone(),
+                    _ => serde_json::to_string(
+                        &voted.iter().map(|(c, _)| c.as_str()).collect::<Vec<_>>(),
+                    )
+                    .unwrap_or_default(),
+                };
+                c.record(&user, &reply);
+            }
+            for (c, n) in voted {
+                if opts.samples > 1 || speculative.is_some() {
+                    let v = (n, replies.len());
+                    rank_ctx.votes.insert(candidate_key(&c), v);
+                    rank_ctx
+                        .votes
+                        .insert(candidate_key(&crate::style::autofix(&c)), v);
+                }
+                if let Some(p) = conf_of(&c) {
+                    rank_ctx.confidence.insert(candidate_key(&c), p);
+                    rank_ctx
+                        .confidence
+                        .insert(candidate_key(&crate::style::autofix(&c)), p);
+                }
+                cands.push(("llm".to_string(), c));
+            }
+        }
+        if let Some(g) = &goal_pretty {
+            cands.extend(
+                crate::derive_candidates_from_goal_pretty(g)
+                    .into_iter()
+                    .map(|c| ("goal".to_string(), c)),
+            );
+        }
+        cands.extend(smt_cands.iter().cloned());
+        cands.extend(
+            adapt_candidates_for_error(
+                &rank_ctx.priors.order_portfolio(default_det_candidates()),
+                last_error,
+            )
+            .into_iter()
+            .map(|c| ("heuristic".to_string(), c)),
+        );
+        if outcome.smt_entails == Some(true) && !cands.iter().any(|(_, c)| c.contains("omega")) {
+            cands.push(("goal".to_string(), "by\n  omega".to_string()));
+        }
+        let mut seen: HashSet<u64> = HashSet::new();
+        let pool: Vec<(String, String)> = cands
+            .into_iter()
+            .map(|(s, c)| {
+                let c = if opts.lint_style {
+                    crate::style::autofix(&c)
+                } else {
+                    c
+                };
+                (s, c)
+            })
+            .filter(|(s, c)| allowed(s) && caps.supports_candidate(c))
+            .collect();
+        let supported = pool.len();
+        let pool: Vec<(String, String)> = pool
+            .into_iter()
+            .filter(|(_, c)| {
+                let h = candidate_hash(c);
+                !tried.contains(&h) && seen.insert(h)
+            })
+            .collect();
+        outcome.duplicates += supported - pool.len();
+        let mut cands = crate::ranking::rank(pool, &rank_ctx, &weights);
+        if let Some(r) = &reranker {
+            crate::rerank::apply(&mut cands, &rank_ctx, r.as_ref(), &weights)?;
+        }
+        if let Some(h) = &score_hook {
+            crate::score_hook::apply(&mut cands, &rank_ctx, h.as_ref())?;
+        }
+        let mut cands = crate::ranking::select(cands, opts.candidates_per_round, &weights);
+        tried.extend(cands.iter().map(|r| candidate_hash(&r.candidate)));
+        // Mechanical renames of last round's failures and strategy seeds are not capped.
+        let renamed_now: Vec<(String, String)> = renamed
+            .drain(..)
+            .filter(|(_, c)| tried.insert(candidate_hash(c)))
+            .collect();
+        let mutants_now: Vec<(String, String)> = mutants
+            .drain(..)
+            .filter(|(_, c)| tried.insert(candidate_hash(c)))
+            .collect();
+        let mut extra = crate::ranking::rank(
+            renamed_now
+                .into_iter()
+                .chain(mutants_now)
+                .chain(seeds)
+                .filter(|(s, _)| allowed(s))
+                .collect(),
+            &rank_ctx,
+            &weights,
+        );
+        if let Some(r) = &reranker {
+            crate::rerank::apply(&mut extra, &rank_ctx, r.as_ref(), &weights)?;
+        }
+        if let Some(h) = &score_hook {
+            crate::score_hook::apply(&mut extra, &rank_ctx, h.as_ref())?;
+        }
+        cands.extend(extra);
+        crate::ranking::sort(&mut cands);
+        if cands.is_empty() {
+            outcome.stop_reason = "no_new_candidates".to_string();
+            break;
+        }
+
+        let mut replayed = false;
+        let mut queue: VecDeque<crate::ranking::Ranked> = cands.into();
+        loop {
+            // The next `width` candidates that apply; compiled at once with `parallel`.
+            let width = parallel.map_or(1, |p| p.k);
+            let mut batch: Vec<(crate::ranking::Ranked, String, EditRecord)> = Vec::new();
+            while batch.len() < width
+                && outcome.verifications + batch.len() < opts.max_verifications
+            {
+                let Some(r) = queue.pop_front() else {
+                    break;
+                };
+                let Ok((patched, edit)) = apply_patch(&text, &target, &r.candidate) else {
+                    continue;
+                };
+                let mut violations = crate::safety::check_candidate(&r.candidate, &safety);
+                violations.extend(crate::safety::check_edit(&text, &patched, &edit, decl_span));
+                if violations.is_empty() {
+                    batch.push((r, patched, edit));
+                } else {
+                    outcome.safety_rejections.push(crate::safety::Rejection {
+                        candidate: r.candidate,
+                        violations,
+                    });
+                }
+            }
+            if batch.is_empty() {
+                if !queue.is_empty() {
+                    outcome.stop_reason = "budget_exhausted".to_string();
+                    return Ok(outcome);
+                }
+                break;
+            }
+            let verified: Vec<Option<(crate::VerifyResult, Duration)>> = match parallel {
+                Some(p) if batch.len() > 1 => {
+                    let futures = batch
+                        .iter()
+                        .map(|(_, patched, _)| {
+                            let (probe, _) = crate::safety::instrument(patched, decl_name);
+                            let verify = verify_candidate(&repo_root, probe, decl_name, opts);
+                            async move {
+                                let t0 = Instant::now();
+                                verify.await.map(|vr| (vr, t0.elapsed()))
+                            }
+                        })
+                        .collect();
+                    let out = crate::parallel_verify::race(futures, p.keep_searching, |i, r| {
+                        r.as_ref().is_ok_and(|(vr, elapsed)| {
+                            let (_, patched, edit) = &batch[i];
+                            judge(
+                                vr, *elapsed, patched, edit, file_rel, decl_name, decl_line, opts,
+                            )
+                            .ok
+                        })
+                    })
+                    .await;
+                    outcome.cancelled_verifications += out.cancelled;
+                    out.results
+                        .into_iter()
+                        .map(Option::transpose)
+                        .collect::<Result<_, _>>()?
+                }
+                _ => {
+                    let t0 = Instant::now();
+                    let patched = &batch[0].1;
+                    let warm_vr = match warm.as_mut() {
+                        Some(w) => w.check_patched(patched).await.ok(),
+                        None => None,
+                    };
+                    // Warm checks only reject: a candidate they accept is confirmed by a full
+                    // compile.
+                    let vr = match warm_vr {
+                        Some(vr) if !vr.ok => vr,
+                        _ => {
+                            let (probe, _) = crate::safety::instrument(patched, decl_name);
+                            verify_candidate(&repo_root, probe, decl_name, opts).await?
+                        }
+                    };
+                    vec![Some((vr, t0.elapsed()))]
+                }
+            };
+
+            let mut solved: Vec<(String, String, EditRecord, Option<AxiomsReport>)> = Vec::new();
+            // Cancelled by the race: verified next unless the winner holds up.
+            let mut cancelled: Vec<crate::ranking::Ranked> = Vec::new();
+            for ((ranked, patched, edit), vr) in batch.into_iter().zip(verified) {
+                let Some((vr, elapsed)) = vr else {
+                    cancelled.push(ranked);
+                    continue;
+                };
+                let crate::ranking::Ranked {
+                    source,
+                    candidate: cand,
+                    score,
+                } = ranked;
+                outcome.verifications += 1;
+                let Judged {
+                    summary: s,
+                    diags,
+                    mut ok,
+                    mut first_error,
+                } = judge(
+                    &vr, elapsed, &patched, &edit, file_rel, decl_name, decl_line, opts,
+                );
+                let mut axioms = None;
+                if ok {
+                    let (_, line) = crate::safety::instrument(&patched, decl_name);
+                    let check = crate::safety::check_axioms(
+                        &repo_root,
+                        &text,
+                        &patched,
+                        decl_name,
+                        &format!("{}\n{}", vr.stdout, vr.stderr),
+                        line,
+                        &safety,
+                        opts.verify_timeout,
+                    )
+                    .await;
+                    outcome.verifications += check.checks;
+                    if let Some(v) = &check.violation {
+                        ok = false;
+                        first_error = Some(format!("safety: {}", v.message()));
+                    }
+                    axioms = check.report;
+                }
+                if !ok && source == "llm" && conversation.is_some() {
+                    pending.push(crate::conversation::FailedCandidate {
+                        candidate: cand.clone(),
+                        error: first_error.clone(),
+                        error_class: crate::diagnostics::first_error(&diags).map(|d| d.class),
+                        step: None,
+                    });
+                }
+                let model = (source == "llm")
+                    .then(|| rank_ctx.models.get(&candidate_key(&cand)).cloned())
+                    .flatten();
+                outcome.attempts.push(RepairAttempt {
+                    round,
+                    source,
+                    candidate: cand.clone(),
+                    ok,
+                    errors: s.errors,
+                    first_error: first_error.clone(),
+                    error_class: crate::diagnostics::first_error(&diags)
+                        .map(|d| d.class)
+                        .or(vr.timeout.then_some(ErrorClass::DeterministicTimeout)),
+                    votes: rank_ctx.votes.get(&candidate_key(&cand)).map(|v| v.0),
+                    confidence: rank_ctx.confidence.get(&candidate_key(&cand)).copied(),
+                    explanation: Some(crate::ranking::explain(&score, &weights)),
+                    model,
+                    score: Some(score),
+                    elapsed_ms: s.elapsed_ms,
+                    aborted: vr.aborted,
+                });
+                if let (Some(tx), Some(a)) = (&opts.progress, outcome.attempts.last()) {
+                    let _ = tx.send(a.clone());
+                }
+                if ok {
+                    solved.push((cand, patched, edit, axioms));
+                    continue;
+                }
+                rank_ctx
+                    .unknown_names
+                    .extend(diags.iter().filter_map(crate::renames::unknown_name));
+                if let Some(table) = rename_table.as_mut() {
+                    table.learn_from_diagnostics(&diags);
+                    if let Some(c) = crate::renames::rename_candidate(&cand, &diags, table) {
+                        if !tried.contains(&candidate_hash(&c)) {
+                            renamed.push(("rename".to_string(), c));
+                        }
+                    }
+                }
+                if opts.mutations {
+                    // Error lines within the candidate, which starts at the edit's first line.
+                    let span = edit.new_text.lines().count().max(1);
+                    let lines: Vec<usize> = diags
+                        .iter()
+                        .filter(|d| d.severity == crate::diagnostics::Severity::Error)
+                        .filter(|d| (edit.line..edit.line + span).contains(&d.line))
+                        .map(|d| d.line + 1 - edit.line)
+                        .collect();
+                    if let Some(i) = crate::mutate::failing_step(&cand, &lines) {
+                        mutants.extend(
+                            crate::mutate::mutate(&cand, i, premises.as_ref(), caps.omega)
+                                .into_iter()
+                                .filter(|m| !tried.contains(&candidate_hash(&m.candidate)))
+                                .map(|m| ("mutation".to_string(), m.candidate)),
+                        );
+                    }
+                }
+                if let Some(e) = first_error {
+                    if !feedback.contains(&e) {
+                        feedback.push(e);
+                    }
+                }
+                if opts.replay_failures
+                    && !replayed
+                    && outcome.verifications < opts.max_verifications
+                    && crate::replay::split_tactic_steps(&cand).len() > 1
+                {
+                    replayed = true;
+                    outcome.verifications += 1;
+                    let ropts = crate::replay::ReplayOptions {
+                        step_timeout: opts.verify_timeout,
+                        ..Default::default()
+                    };
+                    if let Ok(r) = crate::replay::replay_in_text(
+                        &repo_root, file_rel, &text, &target, &cand, &ropts,
+                    )
+                    .await
+                    {
+                        if let Some(f) = r.feedback() {
+                            if let Some(p) = pending.last_mut().filter(|p| p.candidate == cand) {
+                                p.step = Some(f.clone());
+                            }
+                            feedback.push(f);
+                        }
+                    }
+                }
+            }
+
+            // Several successes (`keep_searching`): the fewest steps, then the shortest text.
+            let Some((mut cand, mut patched, mut edit, axioms)) = solved
+                .into_iter()
+                .min_by_key(|(c, ..)| (crate::replay::split_tactic_steps(c).len(), c.trim().len()))
+            else {
+                // The race's winner failed the axioms check: its siblings are still untested.
+                for r in cancelled.into_iter().rev() {
+                    queue.push_front(r);
+                }
+                continue;
+            };
+            let remaining = opts.max_verifications.saturating_sub(outcome.verifications);
+            if opts.minimize && remaining > 0 {
+                let mopts = crate::minimize::MinimizeOptions {
+                    timeout: opts.verify_timeout,
+                    max_checks: remaining,
+                    verify_initial: false,
+                };
+                if let Ok(m) =
+                    crate::minimize::minimize_proof(&repo_root, &text, &target, &cand, &mopts).await
+                {
+                    outcome.verifications += m.checks;
+                    if m.minimized != cand {
+                        if let Ok((p, e)) = apply_patch(&text, &target, &m.minimized) {
+                            (cand, patched, edit) = (m.minimized.clone(), p, e);
+                        }
+                    }
+                    outcome.minimized = Some(m);
+                }
+            }
+            if opts.lint_style {
+                outcome.style_issues = crate::style::lint_edit(&patched, &edit);
+            }
+            let entries = crate::matrix::configured_entries(&repo_root);
+            if opts.version_matrix && !entries.is_empty() {
+                outcome.matrix = crate::matrix::verify_matrix(
+                    &repo_root,
+                    file_rel,
+                    &patched,
+                    &entries,
+                    opts.verify_timeout,
+                )
+                .await
+                .ok();
+            }
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            if let (true, Some(goal)) = (opts.record_exemplars, &goal_pretty) {
+                let ex = crate::fewshot::Exemplar {
+                    goal: goal.clone(),
+                    proof: cand.clone(),
+                    file: Some(file_rel.to_string()),
+                    decl: Some(decl_name.to_string()),
+                    added_at: now,
+                };
+                let _ = crate::fewshot::ExemplarStore::record(&repo_root, &ex);
+            }
+            if let (true, Some(goal)) = (opts.record_subproofs, &goal_pretty) {
+                let entries = crate::subproofs::entries_for(goal, &cand, file_rel, decl_name, now);
+                let _ = crate::subproofs::SubproofStore::record(&repo_root, &entries);
+            }
+            outcome.ok = true;
+            outcome.stop_reason = "solved".to_string();
+            outcome.axioms = axioms;
+            outcome.solution = Some(cand);
+            outcome.complexity = Some(proof_complexity(&text, &patched, decl_name));
+            outcome.edit = Some(edit);
+            outcome.patched_text = Some(patched);
+            if let Some(p) = &opts.checkpoint {
+                let _ = std::fs::remove_file(p);
+            }
+            return Ok(outcome);
+        }
+        if let Some(r) = router.as_mut().filter(|_| asked_llm) {
+            r.round_failed();
+        }
+        if let Some(p) = &opts.checkpoint {
+            let c = crate::checkpoint::RepairCheckpoint {
+                version: crate::checkpoint::CHECKPOINT_VERSION,
+                text_hash: crate::tree_search::hash_text(&text),
+                outcome: outcome.clone(),
+                tried: tried.iter().copied().collect(),
+                feedback: feedback.clone(),
+                renamed: renamed.clone(),
+                mutants: mutants.clone(),
+                unknown_names: rank_ctx.unknown_names.iter().cloned().collect(),
+            };
+            // A failed save loses progress, not the repair.
+            let _ = crate::checkpoint::save_json(p, &c);
+        }
+    }
+    Ok(outcome)
+}
+
+/// The attempts of `o` with their ranking breakdown, as a markdown table: one row per verified
+/// candidate, in verification order, with each signal's contribution to the total.
+pub fn ranking_markdown(o: &RepairOutcome) -> String {
+    let signals = [
+        "smt",
+        "validity",
+        "compile",
+        "length",
+        "lint",
+        "confidence",
+        "rerank",
+        "script",
+    ];
+    let mut out = format!(
+        "### {} ({})\n\n| round | source | candidate | ok | total | {} | diversity |\n|{}\n",
+        o.decl,
+        o.file,
+        signals.join(" | "),
+        "---|".repeat(signals.len() + 6)
+    );
+    for a in &o.attempts {
+        let cand: String = a
+            .candidate
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(60)
+            .collect();
+        let mut row = format!(
+            "| {} | {} | `{}` | {} |",
+            a.round,
+            a.source,
+            cand.replace('|', "\\|").replace('`', "'"),
+            if a.ok { "yes" } else { "no" }
+        );
+        match &a.explanation {
+            Some(e) => {
+                row.push_str(&format!(" {:.2} |", e.total));
+                for c in &e.contributions {
+                    row.push_str(&format!(" {:+.2} |", c.contribution));
+                }
+                row.push_str(&format!(" {:.2} |", e.diversity));
+            }
+            None => row.push_str(&" |".repeat(signals.len() + 2)),
+        }
+        out.push_str(&row);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn llm_reply_parsing_rejects_admitted_and_strips_fences() {
+        assert_eq!(
+            candidates_from_llm_reply("```lean\nby\n  simp\n```"),
+            vec!["by\n  simp".to_string()]
+        );
+        assert_eq!(
+            candidates_from_llm_reply("[\"omega\", \"simp\"]"),
+            vec!["omega".to_string(), "simp".to_string()]
+        );
+        assert_eq!(
+            candidates_from_llm_reply("{\"candidates\": [\"by ring\", \"by  ring\"]}"),
+            vec!["by ring".to_string()]
+        );
+        assert!(candidates_from_llm_reply("by\n  sorry").is_empty());
+    }
+
+    #[test]
+    fn votes_count_each_reply_once_and_order_by_agreement() {
+        let replies = vec![
+            "[\"simp\", \"omega\", \"simp\"]".to_string(),
+            "```lean\nomega\n```".to_string(),
+            "[\"ring\", \"omega \"]".to_string(),
+        ];
+        assert_eq!(
+            vote_candidates(&replies),
+            vec![
+                ("omega".to_string(), 3),
+                ("simp".to_string(), 1),
+                ("ring".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn confidence_is_the_likelihood_of_the_candidate_tokens() {
+        let lp = |token: &str, logprob: f64| TokenLogprob {
+            token: token.to_string(),
+            logprob,
+        };
+        // `{"candidates": ["by simp", "by omega"]}`, sure of `simp`, unsure of `omega`.
+        let reply = vec![
+            lp("{\"candidates\": [\"", -0.01),
+            lp("by", -0.05),
+            lp(" simp", -0.05),
+            lp("\", \"", -0.01),
+            lp("by", -0.7),
+            lp(" omega", -1.5),
+            lp("\"]}", -0.01),
+        ];
+        let text: String = reply.iter().map(|t| t.token.as_str()).collect();
+        let conf = candidate_confidences(&[(text, reply.clone())]);
+        let (simp, omega) = (conf["by simp"], conf["by omega"]);
+        assert!((simp - (-0.05f64).exp()).abs() < 1e-9);
+        assert!((omega - (-1.1f64).exp()).abs() < 1e-9);
+        // Not found in the reply: the whole reply's likelihood.
+        let all = candidate_confidence(&reply, "by ring").unwrap();
+        assert!(omega < all && all < simp);
+        assert_eq!(candidate_confidence(&[], "by simp"), None);
+    }
+
+    #[test]
+    fn admitted_decl_detected() {
+        let out = "Foo.lean:3:8: warning: declaration uses 'sorry'\n";
+        assert!(decl_admitted_in_output(out, 3));
+        assert!(!decl_admitted_in_output(out, 7));
+    }
+}
@@ -0,0 +1,391 @@
+//! Client for the community Lean REPL (`leanprover-community/repl`).
+//!
+//! Protocol: one JSON object per request on stdin, terminated by a blank line; each response is a
+//! JSON object terminated by a blank line. Requests used here:
+//! - `{"cmd": "...", "env": n}` elaborates commands, returns a new `env` plus `messages` and one
+//!   `sorries[i].proofState` per `sorry`;
+//! - `{"tactic": "...", "proofState": n}` runs a tactic, returns a new `proofState` and `goals`;
+//! - `{"pickleTo": path, "env"|"proofState": n}` / `{"unpickleEnvFrom"|"unpickleProofStateFrom":
+//!   path}` save and restore states as `.olean` files.
+//!
+//! Environments and proof states are immutable ids, so a search can branch from any state without
+//! re-elaborating the file: load imports once (`{"cmd": "import Mathlib"}`), pickle that env,
+//! then try tactics against proof states.
+//!
+//! The executable is `PROOFPATCH_REPL_BIN` if set, else the repo's own `repl` dependency
+//! (`.lake/packages/REPL/.lake/build/bin/repl`), else `lake exe repl`; it always runs under
+//! `lake env` in the repo root so imports resolve.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplPos {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplMessage {
+    pub severity: String,
+    #[serde(default)]
+    pub pos: ReplPos,
+    #[serde(default)]
+    pub end_pos: Option<ReplPos>,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplSorry {
+    #[serde(default)]
+    pub pos: ReplPos,
+    #[serde(default)]
+    pub end_pos: Option<ReplPos>,
+    pub goal: String,
+    #[serde(default)]
+    pub proof_state: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommandResponse {
+    #[serde(default)]
+    pub env: Option<u64>,
+    #[serde(default)]
+    pub messages: Vec<ReplMessage>,
+    #[serde(default)]
+    pub sorries: Vec<ReplSorry>,
+}
+
+impl CommandResponse {
+    pub fn errors(&self) -> Vec<&ReplMessage> {
+        self.messages
+            .iter()
+            .filter(|m| m.severity == "error")
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TacticResponse {
+    #[serde(default)]
+    pub proof_state: Option<u64>,
+    #[serde(default)]
+    pub goals: Vec<String>,
+    #[serde(default)]
+    pub messages: Vec<ReplMessage>,
+}
+
+impl TacticResponse {
+    /// No goals left and no errors.
+    pub fn solved(&self) -> bool {
+        self.goals.is_empty() && !self.messages.iter().any(|m| m.severity == "error")
+    }
+}
+
+/// Serialize one request in the REPL's framing (compact JSON + blank line).
+pub fn encode_request(req: &Value) -> String {
+    format!("{req}\n\n")
+}
+
+/// Turn a raw response into `Ok(value)` or the REPL's top-level error (`{"message": ...}`).
+pub fn check_response(v: Value) -> Result<Value, String> {
+    let is_error = v
+        .as_object()
+        .is_some_and(|o| o.len() == 1 && o.get("message").is_some_and(Value::is_string));
+    if is_error {
+        return Err(format!(
+            "repl error: {}",
+            v["message"].as_str().unwrap_or_default()
+        ));
+    }
+    Ok(v)
+}
+
+fn repl_program(repo_root: &Path) -> Vec<String> {
+    if let Ok(bin) = std::env::var("PROOFPATCH_REPL_BIN") {
+        if !bin.trim().is_empty() {
+            return vec!["env".into(), bin.trim().to_string()];
+        }
+    }
+    let vendored = repo_root.join(".lake/packages/REPL/.lake/build/bin/repl");
+    if vendored.is_file() {
+        return vec!["env".into(), vendored.display().to_string()];
+    }
+    vec!["exe".into(), "repl".into()]
+}
+
+/// One running REPL process.
+pub struct ReplSession {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    timeout: Duration,
+    /// Set after a timeout or I/O failure; the process is killed and further requests fail.
+    dead: bool,
+}
+
+impl ReplSession {
+    /// Start the REPL for `repo_root` (see the module docs for how the executable is found).
+    pub async fn start(repo_root: &Path, timeout: Duration) -> Result<Self, String> {
+        let repo_root = crate::find_lean_repo_root(repo_root)?;
+        let mut cmd = Command::new(crate::resolve_lake());
+        cmd.args(repl_program(&repo_root)).current_dir(&repo_root);
+        crate::maybe_extend_lean_path_for_lake_env(&mut cmd);
+        Self::spawn(cmd, timeout)
+    }
+
+    /// Wrap an arbitrary command that speaks the REPL protocol.
+    pub fn spawn(mut cmd: Command, timeout: Duration) -> Result<Self, String> {
+        cmd.stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true);
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| format!("failed to spawn repl: {e}"))?;
+        let stdin = child.stdin.take().ok_or("missing repl stdin")?;
+        let stdout = child.stdout.take().ok_or("missing repl stdout")?;
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            timeout,
+            dead: false,
+        })
+    }
+
+    /// Whether the process was killed (after a timeout or I/O failure).
+    pub fn is_closed(&self) -> bool {
+        self.dead
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    async fn read_response(&mut self) -> Result<Value, String> {
+        let mut buf = String::new();
+        loop {
+            let mut line = String::new();
+            let n = self
+                .stdout
+                .read_line(&mut line)
+                .await
+                .map_err(|e| format!("repl read failed: {e}"))?;
+            if n == 0 {
+                return Err("repl exited".to_string());
+            }
+            if line.trim().is_empty() {
+                if buf.trim().is_empty() {
+                    continue;
+                }
+                break;
+            }
+            buf.push_str(&line);
+        }
+        serde_json::from_str(&buf).map_err(|e| format!("invalid repl response: {e}: {buf}"))
+    }
+
+    /// Send one raw request and wait for its response (bounded by the session timeout).
+    pub async fn request(&mut self, req: Value) -> Result<Value, String> {
+        if self.dead {
+            return Err("repl session is closed".to_string());
+        }
+        let timeout = self.timeout;
+        let res = tokio::time::timeout(timeout, async {
+            self.stdin
+                .write_all(encode_request(&req).as_bytes())
+                .await
+                .map_err(|e| format!("repl write failed: {e}"))?;
+            self.stdin
+                .flush()
+                .await
+                .map_err(|e| format!("repl write failed: {e}"))?;
+            self.read_response().await
+        })
+        .await;
+        match res {
+            Ok(Ok(v)) => check_response(v),
+            Ok(Err(e)) => {
+                self.close().await;
+                Err(e)
+            }
+            Err(_) => {
+                // The REPL is single-threaded: a stuck request blocks all later ones.
+                self.close().await;
+                Err(format!(
+                    "repl request timed out after {}s",
+                    timeout.as_secs()
+                ))
+            }
+        }
+    }
+
+    /// Elaborate commands, optionally on top of environment `env`.
+    pub async fn command(
+        &mut self,
+        cmd: &str,
+        env: Option<u64>,
+    ) -> Result<CommandResponse, String> {
+        let mut req = json!({ "cmd": cmd });
+        if let Some(e) = env {
+            req["env"] = json!(e);
+        }
+        serde_json::from_value(self.request(req).await?).map_err(|e| format!("repl: {e}"))
+    }
+
+    /// Run `tactic` on proof state `proof_state`.
+    pub async fn tactic(
+        &mut self,
+        tactic: &str,
+        proof_state: u64,
+    ) -> Result<TacticResponse, String> {
+        let v = self
+            .request(json!({ "tactic": tactic, "proofState": proof_state }))
+            .await?;
+        serde_json::from_value(v).map_err(|e| format!("repl: {e}"))
+    }
+
+    /// Try each tactic independently on `proof_state` (a failed tactic does not stop the rest).
+    pub async fn try_tactics(
+        &mut self,
+        proof_state: u64,
+        tactics: &[String],
+    ) -> Vec<(String, Result<TacticResponse, String>)> {
+        let mut out = Vec::new();
+        for t in tactics {
+            let r = self.tactic(t, proof_state).await;
+            let stop = self.dead;
+            out.push((t.clone(), r));
+            if stop {
+                break;
+            }
+        }
+        out
+    }
+
+    pub async fn pickle_env(&mut self, env: u64, path: &Path) -> Result<(), String> {
+        self.request(json!({ "pickleTo": path.display().to_string(), "env": env }))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn unpickle_env(&mut self, path: &Path) -> Result<u64, String> {
+        let v = self
+            .request(json!({ "unpickleEnvFrom": path.display().to_string() }))
+            .await?;
+        v.get("env")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| format!("repl: unpickle returned no env: {v}"))
+    }
+
+    pub async fn pickle_proof_state(
+        &mut self,
+        proof_state: u64,
+        path: &Path,
+    ) -> Result<(), String> {
+        self.request(json!({ "pickleTo": path.display().to_string(), "proofState": proof_state }))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn unpickle_proof_state(&mut self, path: &Path) -> Result<u64, String> {
+        let v = self
+            .request(json!({ "unpickleProofStateFrom": path.display().to_string() }))
+            .await?;
+        v.get("proofState")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| format!("repl: unpickle returned no proofState: {v}"))
+    }
+
+    /// Environment with `imports` loaded, restored from `cache` when it exists (and pickled there
+    /// otherwise), so later sessions skip import loading.
+    pub async fn import_env(
+        &mut self,
+        imports: &[String],
+        cache: Option<&Path>,
+    ) -> Result<u64, String> {
+        if let Some(p) = cache.filter(|p| p.is_file()) {
+            if let Ok(env) = self.unpickle_env(p).await {
+                return Ok(env);
+            }
+        }
+        let header: String = imports.iter().map(|m| format!("import {m}\n")).collect();
+        let r = self.command(&header, None).await?;
+        if let Some(e) = r.errors().first() {
+            return Err(format!("repl: import failed: {}", e.data));
+        }
+        let env = r.env.ok_or("repl: no env returned for imports")?;
+        if let Some(p) = cache {
+            if let Some(dir) = p.parent() {
+                let _ = std::fs::create_dir_all(dir);
+            }
+            let _ = self.pickle_env(env, p).await;
+        }
+        Ok(env)
+    }
+
+    pub async fn close(&mut self) {
+        self.dead = true;
+        let _ = self.child.kill().await;
+    }
+}
+
+/// Default pickle location for an import set.
+pub fn default_env_cache(repo_root: &Path, imports: &[String]) -> PathBuf {
+    use sha2::{Digest, Sha256};
+    let h = hex::encode(Sha256::digest(imports.join("\n").as_bytes()));
+    repo_root
+        .join(".generated")
+        .join("proofpatch-repl")
+        .join(format!("env-{}.olean", &h[..16]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_responses_and_talks_to_a_fake_repl() {
+        let v: Value = serde_json::from_str(
+            r#"{"sorries": [{"proofState": 0, "pos": {"line": 1, "column": 22},
+                "endPos": {"line": 1, "column": 27}, "goal": "⊢ 1 + 1 = 2"}],
+                "messages": [{"severity": "warning", "pos": {"line": 1, "column": 8},
+                "endPos": null, "data": "declaration uses 'sorry'"}], "env": 1}"#,
+        )
+        .unwrap();
+        let r: CommandResponse = serde_json::from_value(v).unwrap();
+        assert_eq!(r.env, Some(1));
+        assert_eq!(r.sorries[0].proof_state, Some(0));
+        assert!(r.errors().is_empty());
+        assert!(check_response(json!({"message": "Unknown proof state."})).is_err());
+        assert_eq!(encode_request(&json!({"cmd": "x"})), "{\"cmd\":\"x\"}\n\n");
+
+        // A shell loop that answers every request with a solved tactic state.
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(
+            "while read -r req; do read -r _blank; \
+             printf '{\"proofState\": 1,\\n \"goals\": []}\\n\\n'; done",
+        );
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut s = ReplSession::spawn(cmd, Duration::from_secs(10)).unwrap();
+            let t = s.tactic("rfl", 0).await.unwrap();
+            assert!(t.solved() && t.proof_state == Some(1));
+            let rs = s.try_tactics(0, &["simp".into(), "omega".into()]).await;
+            assert_eq!(rs.len(), 2);
+            s.close().await;
+            assert!(s.tactic("rfl", 0).await.is_err());
+        });
+    }
+}
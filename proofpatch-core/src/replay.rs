@@ -0,0 +1,428 @@
+//! Step-by-step replay of a tactic script against the goal at a placeholder.
+//!
+//! Whole-file compilation only says "the proof failed, first error at line L". To see *which*
+//! step failed and what was left to prove, we split the script into top-level steps and interleave
+//! them with a tiny elaborated tactic, `proofpatch_step <i>`, that appends the current goals and a
+//! monotonic timestamp to a side file before step `i` runs. One compile of the instrumented file
+//! (through `verify_lean_text`, so `PROOFPATCH_VERIFY_BACKEND=lsp` reuses the warm server) then
+//! yields, per step: goals before/after, elapsed time, and the error attributed to its lines.
+//!
+//! The side file is written as elaboration proceeds, so it survives a compile that is killed on
+//! timeout: the step that started but never finished is the one that hung. The overall compile
+//! budget is `step_timeout * (steps + 1)`; steps that finish but exceed `step_timeout` are
+//! flagged. `ReplayOptions::step_heartbeats` additionally wraps each step in
+//! `set_option maxHeartbeats N in`, so runaway automation fails deterministically.
+
+use crate::diagnostics::{parse_diagnostics_from, ErrorClass, Severity};
+use crate::patching::{apply_patch, PatchTarget};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    pub step_timeout: Duration,
+    /// Wrap each step in `set_option maxHeartbeats N in` (Lean's default budget is 200000).
+    pub step_heartbeats: Option<u64>,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            step_timeout: Duration::from_secs(30),
+            step_heartbeats: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepResult {
+    pub index: usize,
+    pub tactic: String,
+    pub ok: bool,
+    /// Never reached (an earlier step failed).
+    pub skipped: bool,
+    pub timed_out: bool,
+    pub elapsed_ms: Option<u64>,
+    pub goals_before: Vec<String>,
+    pub goals_after: Option<Vec<String>>,
+    pub error: Option<String>,
+    pub error_class: Option<ErrorClass>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayReport {
+    pub file: String,
+    /// 1-based line of the placeholder that was replaced.
+    pub line: usize,
+    pub steps: Vec<StepResult>,
+    /// Every step ran and no goals remain.
+    pub completed: bool,
+    pub first_failure: Option<usize>,
+    /// Goals after the last step that ran.
+    pub remaining_goals: Vec<String>,
+    /// Errors not attributable to a step (e.g. `unsolved goals` at the `by`).
+    pub other_errors: Vec<String>,
+    pub timeout: bool,
+}
+
+impl ReplayReport {
+    /// One-line feedback for the repair loop.
+    pub fn feedback(&self) -> Option<String> {
+        if self.completed {
+            return None;
+        }
+        let goals = |g: &[String]| {
+            let s = g
+                .join(" | ")
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            s.chars().take(400).collect::<String>()
+        };
+        if let Some(i) = self.first_failure {
+            let s = &self.steps[i];
+            let why = if s.timed_out {
+                "timed out".to_string()
+            } else {
+                s.error.clone().unwrap_or_else(|| "failed".to_string())
+            };
+            return Some(format!(
+                "step {} `{}` {}; goals before it: {}",
+                i + 1,
+                s.tactic.lines().next().unwrap_or("").trim(),
+                why.lines().next().unwrap_or(""),
+                goals(&s.goals_before)
+            ));
+        }
+        Some(format!(
+            "all steps ran; unsolved goals: {}",
+            goals(&self.remaining_goals)
+        ))
+    }
+}
+
+/// Split a tactic script into top-level steps.
+///
+/// A leading `by` is dropped; a step is a line at the script's base indentation plus any more
+/// indented continuation lines (so `·` bullets and `calc` blocks stay whole). Top-level `;` is
+/// not split: `tac₁; tac₂` is treated as one step, like `<;>`.
+pub fn split_tactic_steps(script: &str) -> Vec<String> {
+    let s = script.trim();
+    let s = match s.strip_prefix("by") {
+        Some(r) if r.is_empty() || r.starts_with(char::is_whitespace) => r,
+        _ => s,
+    };
+    let lines: Vec<&str> = s
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.trim_start().starts_with("--"))
+        .collect();
+    let indent = |l: &str| l.len() - l.trim_start().len();
+    // The first line may be the remainder of `by tac`, so its indentation says nothing.
+    let base = lines.iter().skip(1).map(|l| indent(l)).min().unwrap_or(0);
+    let mut steps: Vec<Vec<String>> = Vec::new();
+    for (k, l) in lines.iter().enumerate() {
+        let ind = if k == 0 { base } else { indent(l) };
+        let body = l
+            .get(base.min(indent(l))..)
+            .unwrap_or(l)
+            .trim_end()
+            .to_string();
+        if ind <= base || steps.is_empty() {
+            steps.push(vec![body.trim_start().to_string()]);
+        } else {
+            steps.last_mut().unwrap().push(body);
+        }
+    }
+    steps.into_iter().map(|s| s.join("\n")).collect()
+}
+
+fn prelude(log_path: &Path) -> String {
+    let path = serde_json::to_string(&log_path.display().to_string())
+        .unwrap_or_else(|_| "\"replay.jsonl\"".to_string());
+    format!(
+        r#"
+import Lean
+
+open Lean Elab Tactic
+
+namespace ProofpatchReplay
+
+elab "proofpatch_step " n:num : tactic => do
+  let mut gs : Array Json := #[]
+  for g in (← getGoals) do
+    gs := gs.push (Json.str (← Meta.ppGoal g).pretty)
+  let t ← IO.monoMsNow
+  let h ← IO.FS.Handle.mk {path} IO.FS.Mode.append
+  h.putStrLn (Json.compress (Json.mkObj [("step", toJson n.getNat), ("t", toJson t), ("goals", Json.arr gs)]))
+  h.flush
+
+end ProofpatchReplay
+"#
+    )
+}
+
+/// Instrumented replacement text and each step's (first, last) line offset within it.
+fn instrument(
+    steps: &[String],
+    indent: &str,
+    by_prefix: bool,
+    heartbeats: Option<u64>,
+) -> (String, Vec<(usize, usize)>) {
+    let mut lines: Vec<String> = Vec::new();
+    let mut ranges = Vec::new();
+    if by_prefix {
+        lines.push("by".to_string());
+    }
+    let push = |lines: &mut Vec<String>, s: String| {
+        // The first line lands at the placeholder's column; the rest carry the indentation.
+        if lines.is_empty() {
+            lines.push(s);
+        } else {
+            lines.push(format!("{indent}{s}"));
+        }
+    };
+    for (i, step) in steps.iter().enumerate() {
+        push(&mut lines, format!("proofpatch_step {i}"));
+        let extra = if let Some(h) = heartbeats {
+            push(&mut lines, format!("set_option maxHeartbeats {h} in"));
+            "  "
+        } else {
+            ""
+        };
+        let first = lines.len();
+        for l in step.lines() {
+            push(&mut lines, format!("{extra}{l}"));
+        }
+        ranges.push((first, lines.len() - 1));
+    }
+    push(&mut lines, format!("proofpatch_step {}", steps.len()));
+    (lines.join("\n"), ranges)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct StepRecord {
+    step: usize,
+    t: u64,
+    goals: Vec<String>,
+}
+
+fn parse_step_log(text: &str) -> Vec<Option<StepRecord>> {
+    let mut first: Vec<Option<StepRecord>> = Vec::new();
+    for r in text
+        .lines()
+        .filter_map(|l| serde_json::from_str::<StepRecord>(l).ok())
+    {
+        let i = r.step;
+        if first.len() <= i {
+            first.resize(i + 1, None);
+        }
+        if first[i].is_none() {
+            first[i] = Some(r);
+        }
+    }
+    first
+}
+
+/// Build the report from the step log and the compiler output.
+///
+/// `ranges` are absolute 1-based line ranges of each step in the compiled file; `out` is
+/// `(stdout, stderr, timed_out)` of the compile.
+fn assemble(
+    file: &str,
+    line: usize,
+    steps: &[String],
+    ranges: &[(usize, usize)],
+    log: &[Option<StepRecord>],
+    out: (&str, &str, bool),
+    step_timeout: Duration,
+) -> ReplayReport {
+    let (stdout, stderr, timeout) = out;
+    let diags = parse_diagnostics_from(stdout, stderr);
+    let errors: Vec<_> = diags
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .collect();
+    let rec = |i: usize| log.get(i).and_then(|r| r.as_ref());
+    let mut results = Vec::new();
+    let mut first_failure = None;
+    for (i, tac) in steps.iter().enumerate() {
+        let (lo, hi) = ranges[i];
+        let err = errors.iter().find(|d| d.line >= lo && d.line <= hi);
+        let (before, after) = (rec(i), rec(i + 1));
+        let elapsed_ms = match (before, after) {
+            (Some(b), Some(a)) => Some(a.t.saturating_sub(b.t)),
+            _ => None,
+        };
+        let started = before.is_some();
+        let hung = started && after.is_none() && err.is_none() && timeout;
+        let slow = elapsed_ms.is_some_and(|ms| ms > step_timeout.as_millis() as u64);
+        let ok = started && after.is_some() && err.is_none() && !slow;
+        if !ok && started && first_failure.is_none() {
+            first_failure = Some(i);
+        }
+        results.push(StepResult {
+            index: i,
+            tactic: tac.clone(),
+            ok,
+            skipped: !started,
+            timed_out: hung
+                || slow
+                || err.is_some_and(|d| d.class == ErrorClass::DeterministicTimeout),
+            elapsed_ms,
+            goals_before: before.map(|r| r.goals.clone()).unwrap_or_default(),
+            goals_after: after.map(|r| r.goals.clone()),
+            error: err.map(|d| d.message.clone()),
+            error_class: err.map(|d| d.class),
+        });
+    }
+    let remaining_goals = log
+        .iter()
+        .rev()
+        .find_map(|r| r.as_ref())
+        .map(|r| r.goals.clone())
+        .unwrap_or_default();
+    let other_errors: Vec<String> = errors
+        .iter()
+        .filter(|d| !ranges.iter().any(|&(lo, hi)| d.line >= lo && d.line <= hi))
+        .map(|d| d.headline().to_string())
+        .collect();
+    let all_ran = rec(steps.len()).is_some();
+    ReplayReport {
+        file: file.to_string(),
+        line,
+        completed: all_ran
+            && first_failure.is_none()
+            && remaining_goals.is_empty()
+            && other_errors.is_empty(),
+        steps: results,
+        first_failure,
+        remaining_goals,
+        other_errors,
+        timeout,
+    }
+}
+
+/// Replay `script` at `target` in `text` (the current contents of `file_rel`).
+pub async fn replay_in_text(
+    repo_root: &Path,
+    file_rel: &str,
+    text: &str,
+    target: &PatchTarget,
+    script: &str,
+    opts: &ReplayOptions,
+) -> Result<ReplayReport, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let steps = split_tactic_steps(script);
+    if steps.is_empty() {
+        return Err("empty tactic script".to_string());
+    }
+    let (start, _) = crate::patching::resolve_target(text, target)?;
+    let line = text[..start].matches('\n').count() + 1;
+    let line_text = text.lines().nth(line - 1).unwrap_or("");
+    let line_start = start - text[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let tactic_ctx = crate::is_tactic_context_for_sorry(text, line, line_text);
+    let indent = if tactic_ctx {
+        " ".repeat(text[start - line_start..start].chars().count())
+    } else {
+        let ws: String = line_text
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect();
+        format!("{ws}  ")
+    };
+
+    let td = tempfile::tempdir().map_err(|e| format!("failed to create temp dir: {e}"))?;
+    let log_path = td.path().join("replay.jsonl");
+    let (replacement, rel_ranges) = instrument(&steps, &indent, !tactic_ctx, opts.step_heartbeats);
+    let (patched, rec) = apply_patch(text, target, &replacement)?;
+    let injected = crate::insert_after_imports(&patched, &prelude(&log_path));
+    let shift = injected.lines().count() - patched.lines().count();
+    let ranges: Vec<(usize, usize)> = rel_ranges
+        .iter()
+        .map(|&(a, b)| (rec.line + shift + a, rec.line + shift + b))
+        .collect();
+
+    let budget = opts.step_timeout * (steps.len() as u32 + 1);
+    let vr = crate::verify_lean_text(&repo_root, &injected, budget).await?;
+    let log = parse_step_log(&std::fs::read_to_string(&log_path).unwrap_or_default());
+    if log.iter().all(Option::is_none) && !vr.timeout {
+        let diags = parse_diagnostics_from(&vr.stdout, &vr.stderr);
+        let msg = crate::diagnostics::first_error(&diags)
+            .map(|d| d.headline().to_string())
+            .unwrap_or_else(|| "no step was reached".to_string());
+        return Err(format!("replay instrumentation failed: {msg}"));
+    }
+    Ok(assemble(
+        file_rel,
+        line,
+        &steps,
+        &ranges,
+        &log,
+        (&vr.stdout, &vr.stderr, vr.timeout),
+        opts.step_timeout,
+    ))
+}
+
+/// Replay `script` at `target` in `file_rel` (read from disk).
+pub async fn replay(
+    repo_root: &Path,
+    file_rel: &str,
+    target: &PatchTarget,
+    script: &str,
+    opts: &ReplayOptions,
+) -> Result<ReplayReport, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let abs = repo_root.join(file_rel);
+    let text = std::fs::read_to_string(&abs).map_err(|e| format!("read {}: {e}", abs.display()))?;
+    replay_in_text(&repo_root, file_rel, &text, target, script, opts).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_steps_and_attributes_failure() {
+        let steps =
+            split_tactic_steps("by\n  intro n\n  constructor\n  · simp\n    ring\n  · omega");
+        assert_eq!(
+            steps,
+            vec!["intro n", "constructor", "· simp\n  ring", "· omega"]
+        );
+        assert_eq!(split_tactic_steps("by simp"), vec!["simp"]);
+
+        let (text, ranges) = instrument(&steps[..2], "  ", true, None);
+        assert_eq!(
+            text,
+            "by\n  proofpatch_step 0\n  intro n\n  proofpatch_step 1\n  constructor\n  proofpatch_step 2"
+        );
+        assert_eq!(ranges, vec![(2, 2), (4, 4)]);
+
+        // Step 1 (`constructor`) fails at absolute line 14; step 0 ran.
+        let log = parse_step_log(
+            "{\"step\":0,\"t\":100,\"goals\":[\"⊢ ∀ n, P n\"]}\n{\"step\":1,\"t\":130,\"goals\":[\"n : ℕ\\n⊢ P n\"]}\n",
+        );
+        let r = assemble(
+            "A.lean",
+            7,
+            &steps[..2],
+            &[(12, 12), (14, 14)],
+            &log,
+            (
+                "A.lean:14:2: error: tactic 'constructor' failed, target is not an inductive datatype\n",
+                "",
+                false,
+            ),
+            Duration::from_secs(30),
+        );
+        assert!(r.steps[0].ok && r.steps[0].elapsed_ms == Some(30));
+        assert!(!r.steps[1].ok && !r.steps[1].skipped);
+        assert_eq!(r.first_failure, Some(1));
+        assert_eq!(r.remaining_goals, vec!["n : ℕ\n⊢ P n".to_string()]);
+        assert!(r
+            .feedback()
+            .unwrap()
+            .starts_with("step 2 `constructor` tactic"));
+    }
+}
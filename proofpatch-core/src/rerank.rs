@@ -0,0 +1,184 @@
+//! Learned reranking of repair candidates: a model trained on the project's past outcomes
+//! rescores candidates before they are verified.
+//!
+//! A `Reranker` maps one feature row per candidate (`features`) to a score, higher for
+//! candidates more likely to compile; `apply` stores it as the `rerank` signal of each
+//! `ranking::CandidateScore`, adds it to the total with `Weights::rerank`, and re-sorts. The
+//! repair loop does so for every round's candidates when `[ranking] rerank_model` names a model.
+//!
+//! The model is an ONNX graph run in-process through ONNX Runtime (`onnx`, cargo feature
+//! `onnx`): one float input of shape `[N, FEATURES]` and one float output of `N` scores (`[N]`
+//! or `[N, 1]`). `features` is the contract for training: the `ranking` signals, goal and
+//! candidate sizes, and hashed token counts, all stable across processes.
+
+use crate::ranking::{RankContext, Ranked, Weights};
+use std::path::Path;
+
+#[cfg(feature = "onnx")]
+pub mod onnx;
+
+/// Buckets for hashed candidate tokens (tactic and lemma names).
+pub const TACTIC_BUCKETS: usize = 32;
+/// Buckets for hashed goal tokens.
+pub const GOAL_BUCKETS: usize = 16;
+/// Length of a `features` row.
+pub const FEATURES: usize = 11 + TACTIC_BUCKETS + GOAL_BUCKETS;
+
+/// Scores candidates from their `features` rows (each `FEATURES` long), higher is better.
+pub trait Reranker: Send + Sync {
+    fn rescore(&self, rows: &[Vec<f32>]) -> Result<Vec<f64>, String>;
+}
+
+/// FNV-1a, so bucket assignments do not change between processes or releases.
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Token frequencies hashed into `n` buckets (summing to 1, or all 0 without tokens).
+fn hashed_bag(text: &str, n: usize) -> Vec<f32> {
+    let mut bag = vec![0f32; n];
+    let tokens: Vec<&str> = text
+        .split(|c: char| !(c.is_alphanumeric() || "_.'".contains(c)))
+        .filter(|t| !t.is_empty())
+        .collect();
+    for t in &tokens {
+        bag[(fnv1a(t) % n as u64) as usize] += 1.0 / tokens.len() as f32;
+    }
+    bag
+}
+
+fn ln1p(n: usize) -> f32 {
+    (n as f32).ln_1p()
+}
+
+/// The model input for one candidate, in order: the six `ranking` signals (smt, validity,
+/// compile, length, lint, confidence); ln(1 + goals), ln(1 + goal chars), and the SMT signal
+/// on the goal (1 entailed, −1 refuted, 0 unknown); ln(1 + candidate lines) and ln(1 +
+/// candidate chars); then `TACTIC_BUCKETS` candidate and `GOAL_BUCKETS` goal token frequencies.
+pub fn features(r: &Ranked, ctx: &RankContext) -> Vec<f32> {
+    let s = &r.score;
+    let goal = ctx.goal.as_deref().unwrap_or("");
+    let cand = crate::goal_ast::candidate_key(&r.candidate);
+    let mut x: Vec<f32> = [s.smt, s.validity, s.compile, s.length, s.lint, s.confidence]
+        .iter()
+        .map(|v| *v as f32)
+        .collect();
+    x.extend([
+        ln1p(
+            goal.lines()
+                .filter(|l| l.trim_start().starts_with('⊢'))
+                .count(),
+        ),
+        ln1p(goal.chars().count()),
+        match ctx.smt_entails {
+            Some(true) => 1.0,
+            Some(false) => -1.0,
+            None => 0.0,
+        },
+        ln1p(cand.lines().count()),
+        ln1p(cand.chars().count()),
+    ]);
+    x.extend(hashed_bag(&cand, TACTIC_BUCKETS));
+    x.extend(hashed_bag(goal, GOAL_BUCKETS));
+    x
+}
+
+/// Rescore `ranked` with `reranker` and re-sort it.
+pub fn apply(
+    ranked: &mut [Ranked],
+    ctx: &RankContext,
+    reranker: &dyn Reranker,
+    w: &Weights,
+) -> Result<(), String> {
+    if ranked.is_empty() || w.rerank == 0.0 {
+        return Ok(());
+    }
+    let rows: Vec<Vec<f32>> = ranked.iter().map(|r| features(r, ctx)).collect();
+    let scores = reranker.rescore(&rows)?;
+    if scores.len() != ranked.len() {
+        return Err(format!(
+            "rerank: {} scores for {} candidates",
+            scores.len(),
+            ranked.len()
+        ));
+    }
+    for (r, s) in ranked.iter_mut().zip(scores) {
+        r.score.rerank = s;
+        r.score.total += w.rerank * s;
+    }
+    crate::ranking::sort(ranked);
+    Ok(())
+}
+
+/// Load the ONNX model at `path`.
+pub fn load(path: &Path) -> Result<Box<dyn Reranker>, String> {
+    #[cfg(feature = "onnx")]
+    return onnx::OnnxReranker::load(path).map(|r| Box::new(r) as Box<dyn Reranker>);
+    #[cfg(not(feature = "onnx"))]
+    return Err(format!(
+        "rerank model {}: proofpatch was built without the `onnx` cargo feature",
+        path.display()
+    ));
+}
+
+/// The reranker named by the repo's `[ranking] rerank_model`, if any.
+pub fn configured(repo_root: &Path) -> Result<Option<Box<dyn Reranker>>, String> {
+    let model = crate::config::load_from_repo_root(repo_root)
+        .ok()
+        .flatten()
+        .and_then(|c| c.ranking.rerank_model)
+        .filter(|m| !m.trim().is_empty());
+    match model {
+        Some(m) => load(&repo_root.join(m)).map(Some),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Prefers candidates that mention `omega` (a stand-in for a trained model).
+    struct LikesOmega;
+
+    impl Reranker for LikesOmega {
+        fn rescore(&self, rows: &[Vec<f32>]) -> Result<Vec<f64>, String> {
+            let bucket = 11 + (fnv1a("omega") % TACTIC_BUCKETS as u64) as usize;
+            Ok(rows.iter().map(|x| 10.0 * x[bucket] as f64).collect())
+        }
+    }
+
+    #[test]
+    fn reranker_scores_enter_the_total() {
+        let ctx = RankContext {
+            goal: Some("n : ℕ\n⊢ n + 0 = n".to_string()),
+            ..Default::default()
+        };
+        let w = Weights::default();
+        let cands = vec![
+            ("llm".to_string(), "by\n  simp".to_string()),
+            ("heuristic".to_string(), "by\n  omega".to_string()),
+        ];
+        let mut ranked = crate::ranking::rank(cands, &ctx, &w);
+        assert_eq!(ranked[0].candidate, "by\n  simp");
+        let rows: Vec<_> = ranked.iter().map(|r| features(r, &ctx)).collect();
+        assert!(rows.iter().all(|x| x.len() == FEATURES));
+        assert_eq!(features(&ranked[0], &ctx), rows[0]);
+
+        apply(&mut ranked, &ctx, &LikesOmega, &w).unwrap();
+        assert_eq!(ranked[0].candidate, "by\n  omega");
+        assert_eq!(ranked[0].score.rerank, 5.0);
+        assert_eq!(ranked[1].score.rerank, 0.0);
+
+        let off = Weights { rerank: 0.0, ..w };
+        let mut again = crate::ranking::rank(
+            vec![("llm".to_string(), "by\n  simp".to_string())],
+            &ctx,
+            &off,
+        );
+        apply(&mut again, &ctx, &LikesOmega, &off).unwrap();
+        assert_eq!(again[0].score.rerank, 0.0);
+    }
+}
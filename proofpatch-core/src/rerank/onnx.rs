@@ -0,0 +1,151 @@
+//! `Reranker` on an ONNX model through ONNX Runtime (cargo feature `onnx`).
+//!
+//! The model is loaded once per path and shared; each `rescore` runs one batch on the CPU. The
+//! ONNX Runtime calls live in `onnx_shim.c`; `build.rs` compiles it against `ONNXRUNTIME_DIR`
+//! (or `ONNXRUNTIME_INCLUDE_DIR` / `ONNXRUNTIME_LIB_DIR`) and links `libonnxruntime`.
+
+use super::{Reranker, FEATURES};
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+#[repr(C)]
+struct PpOnnx {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    fn pp_onnx_load(
+        path: *const c_char,
+        n_features: *mut i64,
+        err: *mut *mut c_char,
+    ) -> *mut PpOnnx;
+    fn pp_onnx_run(
+        h: *mut PpOnnx,
+        x: *const f32,
+        n: i64,
+        d: i64,
+        out: *mut f32,
+        err: *mut *mut c_char,
+    ) -> i32;
+    fn pp_onnx_free(h: *mut PpOnnx);
+    fn pp_onnx_string_free(s: *mut c_char);
+}
+
+struct Session(*mut PpOnnx);
+
+// The handle is only used under the `Mutex` below; ONNX Runtime sessions may move between
+// threads.
+unsafe impl Send for Session {}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        unsafe { pp_onnx_free(self.0) }
+    }
+}
+
+type Loaded = Arc<Mutex<Session>>;
+
+fn loaded() -> &'static Mutex<HashMap<PathBuf, Loaded>> {
+    static MODELS: OnceLock<Mutex<HashMap<PathBuf, Loaded>>> = OnceLock::new();
+    MODELS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Take ownership of an error string from the shim.
+fn take_error(e: *mut c_char) -> String {
+    if e.is_null() {
+        return "unknown error".to_string();
+    }
+    let s = unsafe { CStr::from_ptr(e) }.to_string_lossy().to_string();
+    unsafe { pp_onnx_string_free(e) };
+    s
+}
+
+#[derive(Clone)]
+pub struct OnnxReranker {
+    pub path: PathBuf,
+    handle: Loaded,
+}
+
+impl OnnxReranker {
+    /// Load `path` (or reuse it if this process already has it loaded).
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.is_file() {
+            return Err(format!("rerank model not found: {}", path.display()));
+        }
+        let mut cache = loaded()
+            .lock()
+            .map_err(|_| "rerank model cache poisoned".to_string())?;
+        let handle = match cache.get(path) {
+            Some(h) => h.clone(),
+            None => {
+                let c = CString::new(path.display().to_string())
+                    .map_err(|e| format!("rerank model path: {e}"))?;
+                let mut n_features = 0i64;
+                let mut e: *mut c_char = std::ptr::null_mut();
+                let h = unsafe { pp_onnx_load(c.as_ptr(), &mut n_features, &mut e) };
+                if h.is_null() {
+                    return Err(format!(
+                        "ONNX Runtime could not load {}: {}",
+                        path.display(),
+                        take_error(e)
+                    ));
+                }
+                let h = Arc::new(Mutex::new(Session(h)));
+                // A dynamic width (-1) is checked when the model runs.
+                if n_features > 0 && n_features as usize != FEATURES {
+                    return Err(format!(
+                        "rerank model {} takes {n_features} features, proofpatch computes {FEATURES}",
+                        path.display()
+                    ));
+                }
+                cache.insert(path.to_path_buf(), h.clone());
+                h
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            handle,
+        })
+    }
+}
+
+impl Reranker for OnnxReranker {
+    fn rescore(&self, rows: &[Vec<f32>]) -> Result<Vec<f64>, String> {
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        if let Some(r) = rows.iter().find(|r| r.len() != FEATURES) {
+            return Err(format!(
+                "rerank: row of {} features, expected {FEATURES}",
+                r.len()
+            ));
+        }
+        let x: Vec<f32> = rows.concat();
+        let mut out = vec![0f32; rows.len()];
+        let session = self
+            .handle
+            .lock()
+            .map_err(|_| "rerank model lock poisoned".to_string())?;
+        let mut e: *mut c_char = std::ptr::null_mut();
+        let rc = unsafe {
+            pp_onnx_run(
+                session.0,
+                x.as_ptr(),
+                rows.len() as i64,
+                FEATURES as i64,
+                out.as_mut_ptr(),
+                &mut e,
+            )
+        };
+        if rc != 0 {
+            return Err(format!(
+                "rerank model {}: {}",
+                self.path.display(),
+                take_error(e)
+            ));
+        }
+        Ok(out.into_iter().map(f64::from).collect())
+    }
+}
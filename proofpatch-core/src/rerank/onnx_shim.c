@@ -0,0 +1,167 @@
+// Thin C layer over the ONNX Runtime C API for `rerank::onnx` (cargo feature `onnx`).
+//
+// The C API is a table of function pointers (`OrtApi`) with status objects for errors; keeping
+// it here means the Rust side only sees plain pointers, integers, and error strings.
+
+#include "onnxruntime_c_api.h"
+
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct pp_onnx {
+    OrtEnv *env;
+    OrtSession *session;
+    OrtMemoryInfo *mem;
+    char *input;
+    char *output;
+} pp_onnx;
+
+static const OrtApi *api(void) {
+    static const OrtApi *a = NULL;
+    if (!a) {
+        a = OrtGetApiBase()->GetApi(ORT_API_VERSION);
+    }
+    return a;
+}
+
+static char *dup_string(const char *s) {
+    size_t n = strlen(s);
+    char *out = malloc(n + 1);
+    if (out) {
+        memcpy(out, s, n + 1);
+    }
+    return out;
+}
+
+// Consume `st`, storing its message in `*err`; returns whether there was an error.
+static int failed(OrtStatus *st, char **err) {
+    if (!st) {
+        return 0;
+    }
+    if (err) {
+        *err = dup_string(api()->GetErrorMessage(st));
+    }
+    api()->ReleaseStatus(st);
+    return 1;
+}
+
+void pp_onnx_string_free(char *s) { free(s); }
+
+void pp_onnx_free(pp_onnx *h) {
+    if (!h) {
+        return;
+    }
+    const OrtApi *a = api();
+    if (h->mem) a->ReleaseMemoryInfo(h->mem);
+    if (h->session) a->ReleaseSession(h->session);
+    if (h->env) a->ReleaseEnv(h->env);
+    free(h->input);
+    free(h->output);
+    free(h);
+}
+
+// Copy the name of the first input (or output) of the session.
+static char *io_name(pp_onnx *h, int output, char **err) {
+    const OrtApi *a = api();
+    OrtAllocator *alloc = NULL;
+    char *name = NULL;
+    if (failed(a->GetAllocatorWithDefaultOptions(&alloc), err)) {
+        return NULL;
+    }
+    OrtStatus *st = output ? a->SessionGetOutputName(h->session, 0, alloc, &name)
+                           : a->SessionGetInputName(h->session, 0, alloc, &name);
+    if (failed(st, err)) {
+        return NULL;
+    }
+    char *out = dup_string(name);
+    a->AllocatorFree(alloc, name);
+    return out;
+}
+
+// Width of the first input (its last dimension; -1 when dynamic).
+static int64_t input_width(pp_onnx *h, char **err) {
+    const OrtApi *a = api();
+    OrtTypeInfo *ti = NULL;
+    const OrtTensorTypeAndShapeInfo *tti = NULL;
+    size_t nd = 0;
+    int64_t dims[8] = {0};
+    if (failed(a->SessionGetInputTypeInfo(h->session, 0, &ti), err)) {
+        return 0;
+    }
+    int64_t width = -1;
+    if (!failed(a->CastTypeInfoToTensorInfo(ti, &tti), err) && tti &&
+        !failed(a->GetDimensionsCount(tti, &nd), err) && nd >= 1 && nd <= 8 &&
+        !failed(a->GetDimensions(tti, dims, nd), err)) {
+        width = dims[nd - 1];
+    }
+    a->ReleaseTypeInfo(ti);
+    return width;
+}
+
+pp_onnx *pp_onnx_load(const char *path, int64_t *n_features, char **err) {
+    const OrtApi *a = api();
+    pp_onnx *h = calloc(1, sizeof *h);
+    OrtSessionOptions *so = NULL;
+    if (!h) {
+        *err = dup_string("out of memory");
+        return NULL;
+    }
+    if (failed(a->CreateEnv(ORT_LOGGING_LEVEL_WARNING, "proofpatch", &h->env), err) ||
+        failed(a->CreateSessionOptions(&so), err) ||
+        failed(a->SetIntraOpNumThreads(so, 1), err) ||
+        failed(a->CreateSession(h->env, path, so, &h->session), err) ||
+        failed(a->CreateCpuMemoryInfo(OrtArenaAllocator, OrtMemTypeDefault, &h->mem), err)) {
+        if (so) a->ReleaseSessionOptions(so);
+        pp_onnx_free(h);
+        return NULL;
+    }
+    a->ReleaseSessionOptions(so);
+    h->input = io_name(h, 0, err);
+    h->output = h->input ? io_name(h, 1, err) : NULL;
+    if (!h->output) {
+        pp_onnx_free(h);
+        return NULL;
+    }
+    *n_features = input_width(h, err);
+    return h;
+}
+
+// Run rows `x` (`n` x `d`, row-major) and write the `n` scores to `out`; 0 on success.
+int pp_onnx_run(pp_onnx *h, const float *x, int64_t n, int64_t d, float *out, char **err) {
+    const OrtApi *a = api();
+    int64_t shape[2] = {n, d};
+    OrtValue *input = NULL;
+    OrtValue *output = NULL;
+    OrtTensorTypeAndShapeInfo *info = NULL;
+    size_t count = 0;
+    float *scores = NULL;
+    int rc = -1;
+    if (failed(a->CreateTensorWithDataAsOrtValue(h->mem, (void *)x, (size_t)(n * d) * sizeof(float),
+                                                 shape, 2, ONNX_TENSOR_ELEMENT_DATA_TYPE_FLOAT,
+                                                 &input),
+               err)) {
+        return rc;
+    }
+    const char *in_names[1] = {h->input};
+    const char *out_names[1] = {h->output};
+    if (failed(a->Run(h->session, NULL, in_names, (const OrtValue *const *)&input, 1, out_names, 1,
+                      &output),
+               err) ||
+        failed(a->GetTensorTypeAndShape(output, &info), err) ||
+        failed(a->GetTensorShapeElementCount(info, &count), err) ||
+        failed(a->GetTensorMutableData(output, (void **)&scores), err)) {
+        goto done;
+    }
+    if ((int64_t)count != n) {
+        *err = dup_string("the model's output is not one score per row");
+        goto done;
+    }
+    memcpy(out, scores, (size_t)n * sizeof(float));
+    rc = 0;
+done:
+    if (info) a->ReleaseTensorTypeAndShapeInfo(info);
+    if (output) a->ReleaseValue(output);
+    a->ReleaseValue(input);
+    return rc;
+}
@@ -0,0 +1,529 @@
+//! The research runner: executes `[research.presets.<name>]` against search backends.
+//!
+//! A preset names the backends it fans out to (`backends`, resolved by `backend`). Each gets the
+//! preset's query concurrently and has `timeout_ms` to answer; a backend that errors or runs out
+//! of time is recorded in `ResearchRun::backends` and contributes nothing, the others still do.
+//! Results are normalized to `SearchResult` (title, url, snippet, source), filtered by the
+//! preset's word and regex filters (`filter`), and fused into one ranking (`fusion`: duplicates
+//! across backends merge, and results score by reciprocal rank fusion), up to `max_results`.
+//! `ResearchRun::notes` turns them into the `ResearchNotes` the summarizer (`research_summary`) and prompts consume.
+//! Answers are cached per backend, query and backend settings (`cache`).
+//! A finished run renders to Markdown and JSON reports stored with the run's artifacts (`report`).
+//! Repair prompts draw on a preset's results by retrieval against the goal (`rag`).
+//!
+//! Backends: `arxiv` (papers, with abstracts), `mathlib_docs` (declarations and docstrings), `zulip` (Lean Zulip
+//! threads), and the web engines in `web` (`searxng`, `brave`).
+
+use crate::config::{ResearchConfig, ResearchPresetResolved};
+use crate::{ResearchNotes, ResearchSource};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub mod arxiv;
+pub mod cache;
+pub mod filter;
+pub mod fusion;
+pub mod mathlib_docs;
+pub mod rag;
+pub mod report;
+pub mod web;
+pub mod zulip;
+
+/// One hit, normalized across backends.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    #[serde(default)]
+    pub snippet: String,
+    /// Name of the backend that returned it.
+    pub source: String,
+    /// 1-based position in that backend's answer.
+    pub rank: usize,
+    /// Reciprocal rank fusion score across backends (`fusion`), once fused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// Other backends that returned the same result.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub also_in: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchQuery {
+    pub text: String,
+    pub max_results: usize,
+}
+
+pub type SearchFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<SearchResult>, String>> + Send + 'a>>;
+
+pub trait SearchBackend: Send + Sync {
+    /// Stable name, as used in a preset's `backends`.
+    fn name(&self) -> &str;
+
+    /// At most `q.max_results` results, best first, with `source` and `rank` set.
+    fn search<'a>(&'a self, q: &'a SearchQuery) -> SearchFuture<'a>;
+
+    /// The settings that change this backend's answers to a query (endpoint, categories, …);
+    /// part of the cache key (`cache`).
+    fn filters(&self) -> String {
+        String::new()
+    }
+}
+
+/// Names `backend` accepts.
+pub const BACKENDS: &[&str] = &["arxiv", "mathlib_docs", "zulip", "searxng", "brave"];
+
+/// The backend called `name`, configured from `cfg`; `timeout` bounds its own requests.
+/// Relative paths in the config are against `repo_root`.
+pub fn backend(
+    name: &str,
+    cfg: &ResearchConfig,
+    repo_root: &Path,
+    timeout: Duration,
+) -> Result<Arc<dyn SearchBackend>, String> {
+    let b = &cfg.backends;
+    match name {
+        "arxiv" => Ok(Arc::new(arxiv::Arxiv::from_config(
+            b.arxiv.as_ref(),
+            timeout,
+        )?)),
+        "mathlib_docs" => Ok(Arc::new(mathlib_docs::MathlibDocs::from_config(
+            b.mathlib_docs.as_ref(),
+            repo_root,
+            timeout,
+        )?)),
+        "zulip" => Ok(Arc::new(zulip::Zulip::from_config(
+            b.zulip.as_ref(),
+            timeout,
+        ))),
+        "searxng" => Ok(Arc::new(web::Searxng::from_config(
+            b.searxng.as_ref(),
+            timeout,
+        )?)),
+        "brave" => Ok(Arc::new(web::Brave::from_config(
+            b.brave.as_ref(),
+            timeout,
+        )?)),
+        _ => Err(format!(
+            "unknown search backend: {name} (available: {})",
+            BACKENDS.join(", ")
+        )),
+    }
+}
+
+fn client(timeout: Duration) -> Result<reqwest::Client, String> {
+    let ua = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .user_agent(ua)
+        .build()
+        .map_err(|e| format!("reqwest client: {e}"))
+}
+
+/// `s` without HTML tags and common entities, whitespace collapsed.
+fn strip_tags(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_tag = false;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // `a < b` in a snippet is not a tag.
+            '<' if !in_tag
+                && chars
+                    .peek()
+                    .is_some_and(|n| n.is_ascii_alphabetic() || matches!(n, '/' | '!')) =>
+            {
+                in_tag = true
+            }
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    let out = out
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// How one backend fared in a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendRun {
+    pub backend: String,
+    /// Results it returned (before filters and `max_results` were applied to the run).
+    pub results: usize,
+    /// Of those, the ones the preset's filters dropped (`filter`).
+    #[serde(default)]
+    pub filtered: usize,
+    pub elapsed_ms: u64,
+    /// It did not answer within `timeout_ms`.
+    pub timed_out: bool,
+    /// The answer came from the cache (`cache`).
+    #[serde(default)]
+    pub cached: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResearchRun {
+    pub preset: String,
+    pub query: String,
+    pub results: Vec<SearchResult>,
+    pub backends: Vec<BackendRun>,
+    /// Cited LLM summary of `results`, when the preset asks for one (`llm_summary`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<crate::research_summary::ResearchSummary>,
+}
+
+impl ResearchRun {
+    /// The results as `ResearchNotes` sources, deduplicated by canonical URL.
+    pub fn notes(&self) -> ResearchNotes {
+        let mut seen = HashSet::new();
+        let mut sources = Vec::new();
+        for r in &self.results {
+            let canonical_url = crate::canonicalize_url(&r.url);
+            let key = canonical_url.clone().unwrap_or_else(|| r.url.clone());
+            if !seen.insert(key) {
+                continue;
+            }
+            sources.push(ResearchSource {
+                url: r.url.clone(),
+                canonical_url,
+                title: (!r.title.is_empty()).then(|| r.title.clone()),
+                snippet: (!r.snippet.is_empty()).then(|| r.snippet.clone()),
+                origin: Some(r.source.clone()),
+            });
+        }
+        ResearchNotes {
+            raw_urls: self.results.len(),
+            deduped_urls: sources.len(),
+            sources,
+            summary: self.summary.clone(),
+        }
+    }
+}
+
+/// Query `backends` concurrently with `preset`'s query, each within `timeout_ms`, and keep the
+/// results that pass its filters. With a `cache`, answers younger than its TTL are reused and
+/// fresh ones stored. `Err` only for a preset with an invalid filter.
+pub async fn run(
+    name: &str,
+    preset: &ResearchPresetResolved,
+    backends: &[Arc<dyn SearchBackend>],
+    cache: Option<&cache::SearchCache>,
+) -> Result<ResearchRun, String> {
+    let filter = filter::ResultFilter::from_preset(preset)?;
+    let query = SearchQuery {
+        text: preset.query.clone(),
+        max_results: preset.max_results,
+    };
+    let timeout = Duration::from_millis(preset.timeout_ms);
+    let mut set = tokio::task::JoinSet::new();
+    for (i, b) in backends.iter().enumerate() {
+        let (b, q, cache) = (Arc::clone(b), query.clone(), cache.cloned());
+        set.spawn(async move {
+            let start = Instant::now();
+            let key = cache::SearchCacheKey {
+                backend: b.name().to_string(),
+                filters: b.filters(),
+                query: q.text.clone(),
+                max_results: q.max_results,
+            };
+            let now = cache::now_s();
+            if let Some(hit) = cache.as_ref().and_then(|c| c.get(&key, now)) {
+                return (i, Ok(Ok(hit)), start.elapsed(), true);
+            }
+            let r = tokio::time::timeout(timeout, b.search(&q)).await;
+            if let (Some(c), Ok(Ok(rs))) = (&cache, &r) {
+                // Best-effort: a run without a writable cache still has its answers.
+                let _ = c.put(&key, rs, now);
+            }
+            (i, r, start.elapsed(), false)
+        });
+    }
+    let mut answers: Vec<Option<(Vec<SearchResult>, BackendRun)>> =
+        backends.iter().map(|_| None).collect();
+    while let Some(joined) = set.join_next().await {
+        let Ok((i, r, elapsed, cached)) = joined else {
+            continue;
+        };
+        let mut run = BackendRun {
+            backend: backends[i].name().to_string(),
+            results: 0,
+            elapsed_ms: elapsed.as_millis() as u64,
+            timed_out: false,
+            cached,
+            filtered: 0,
+            error: None,
+        };
+        let results = match r {
+            Ok(Ok(mut rs)) => {
+                rs.truncate(query.max_results);
+                run.results = rs.len();
+                rs.retain(|r| filter.keeps(r));
+                run.filtered = run.results - rs.len();
+                rs
+            }
+            Ok(Err(e)) => {
+                run.error = Some(e);
+                Vec::new()
+            }
+            Err(_) => {
+                run.timed_out = true;
+                run.error = Some(format!("timed out after {}ms", preset.timeout_ms));
+                Vec::new()
+            }
+        };
+        answers[i] = Some((results, run));
+    }
+    let mut out = ResearchRun {
+        preset: name.to_string(),
+        query: preset.query.clone(),
+        results: Vec::new(),
+        backends: Vec::new(),
+        summary: None,
+    };
+    let mut lists = Vec::new();
+    for (results, run) in answers.into_iter().flatten() {
+        lists.push(results);
+        out.backends.push(run);
+    }
+    out.results = fusion::fuse(lists);
+    out.results.truncate(preset.max_results);
+    Ok(out)
+}
+
+/// Resolve preset `name` in `cfg` (the config of `repo_root`), run it on its backends, and
+/// summarize the results when the preset asks for it (a failed summary leaves `summary` unset).
+/// Backend answers are cached under the repo when the preset allows it and `use_cache` is set.
+pub async fn run_preset(
+    repo_root: &Path,
+    cfg: &ResearchConfig,
+    name: &str,
+    use_cache: bool,
+) -> Result<ResearchRun, String> {
+    let preset = cfg.resolve_preset(name).ok_or_else(|| {
+        let mut names: Vec<&String> = cfg.presets.keys().collect();
+        names.sort();
+        let names: Vec<&str> = names.iter().map(|s| s.as_str()).collect();
+        format!("unknown preset: {name} (available: {})", names.join(", "))
+    })?;
+    let timeout = Duration::from_millis(preset.timeout_ms);
+    let backends = preset
+        .backends
+        .iter()
+        .map(|b| backend(b, cfg, repo_root, timeout))
+        .collect::<Result<Vec<_>, _>>()?;
+    let cache = (use_cache && preset.cache).then(|| cache::SearchCache {
+        dir: cache::default_dir(repo_root),
+        ttl: Duration::from_secs(preset.cache_ttl_s),
+    });
+    let mut out = run(name, &preset, &backends, cache.as_ref()).await?;
+    if preset.llm_summary && !out.results.is_empty() {
+        out.summary = crate::research_summary::summarize(
+            &preset.query,
+            &out.notes(),
+            preset.llm_chunk_tokens,
+            Duration::from_secs(preset.llm_timeout_s),
+        )
+        .await
+        .ok();
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResearchPreset;
+
+    struct Fixed {
+        name: &'static str,
+        delay_ms: u64,
+        urls: Vec<&'static str>,
+    }
+
+    impl SearchBackend for Fixed {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn search<'a>(&'a self, q: &'a SearchQuery) -> SearchFuture<'a> {
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(self.delay_ms)).await;
+                if self.urls.is_empty() {
+                    return Err("backend down".to_string());
+                }
+                Ok(self
+                    .urls
+                    .iter()
+                    .enumerate()
+                    .map(|(i, u)| SearchResult {
+                        title: format!("{} {}", q.text, i + 1),
+                        url: u.to_string(),
+                        snippet: String::new(),
+                        source: self.name.to_string(),
+                        rank: i + 1,
+                        ..Default::default()
+                    })
+                    .collect())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn fans_out_within_the_timeout() {
+        let mut cfg = ResearchConfig::default();
+        let mut p = ResearchPreset::new("ring lemma");
+        p.timeout_ms = Some(200);
+        p.max_results = Some(4);
+        cfg.presets.insert("demo".into(), p);
+        let preset = cfg.resolve_preset("demo").unwrap();
+        assert_eq!(preset.backends, ["arxiv"]);
+        assert!(backend("nope", &cfg, Path::new("."), Duration::from_secs(1)).is_err());
+
+        let backends: Vec<Arc<dyn SearchBackend>> = vec![
+            Arc::new(Fixed {
+                name: "slow",
+                delay_ms: 5_000,
+                urls: vec!["https://slow.example/1"],
+            }),
+            Arc::new(Fixed {
+                name: "a",
+                delay_ms: 10,
+                urls: vec!["https://arxiv.org/pdf/1234.5678.pdf", "https://a.example/2"],
+            }),
+            Arc::new(Fixed {
+                name: "down",
+                delay_ms: 0,
+                urls: vec![],
+            }),
+            Arc::new(Fixed {
+                name: "b",
+                delay_ms: 0,
+                urls: vec!["https://arxiv.org/abs/1234.5678", "https://b.example/2"],
+            }),
+        ];
+        let run = run("demo", &preset, &backends, None).await.unwrap();
+        let status: Vec<(&str, usize, bool, bool)> = run
+            .backends
+            .iter()
+            .map(|b| {
+                (
+                    b.backend.as_str(),
+                    b.results,
+                    b.timed_out,
+                    b.error.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            status,
+            [
+                ("slow", 0, true, true),
+                ("a", 2, false, false),
+                ("down", 0, false, true),
+                ("b", 2, false, false),
+            ]
+        );
+        let got: Vec<(&str, usize)> = run
+            .results
+            .iter()
+            .map(|r| (r.source.as_str(), r.rank))
+            .collect();
+        // The arXiv pdf and abs links are one result, which both backends rank first.
+        assert_eq!(got, [("a", 1), ("a", 2), ("b", 2)]);
+        assert_eq!(run.results[0].also_in, ["b"]);
+
+        let notes = run.notes();
+        assert_eq!((notes.raw_urls, notes.deduped_urls), (3, 3));
+        assert_eq!(notes.sources[0].origin.as_deref(), Some("a"));
+    }
+
+    struct Counting {
+        calls: std::sync::atomic::AtomicUsize,
+        filters: &'static str,
+    }
+
+    impl SearchBackend for Counting {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn filters(&self) -> String {
+            self.filters.to_string()
+        }
+
+        fn search<'a>(&'a self, q: &'a SearchQuery) -> SearchFuture<'a> {
+            let n = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Box::pin(async move {
+                Ok(vec![SearchResult {
+                    title: format!("{} #{n}", q.text),
+                    url: "https://c.example/1".into(),
+                    snippet: String::new(),
+                    source: "counting".into(),
+                    rank: 1,
+                    ..Default::default()
+                }])
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn caches_answers_per_backend_query_and_filters() {
+        let td = tempfile::tempdir().unwrap();
+        let mut cfg = ResearchConfig::default();
+        cfg.presets
+            .insert("demo".into(), ResearchPreset::new("ring lemma"));
+        cfg.presets
+            .insert("other".into(), ResearchPreset::new("field lemma"));
+        let (demo, other) = (
+            cfg.resolve_preset("demo").unwrap(),
+            cfg.resolve_preset("other").unwrap(),
+        );
+        assert!(demo.cache);
+        let counting = |filters| {
+            Arc::new(Counting {
+                calls: Default::default(),
+                filters,
+            })
+        };
+        let b = counting("");
+        let backends: Vec<Arc<dyn SearchBackend>> = vec![b.clone()];
+        let cache = cache::SearchCache {
+            dir: td.path().to_path_buf(),
+            ttl: Duration::from_secs(3600),
+        };
+
+        let first = run("demo", &demo, &backends, Some(&cache)).await.unwrap();
+        let again = run("demo", &demo, &backends, Some(&cache)).await.unwrap();
+        assert!(!first.backends[0].cached && again.backends[0].cached);
+        assert_eq!(again.results, first.results);
+        run("other", &other, &backends, Some(&cache)).await.unwrap();
+        assert_eq!(b.calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // Other filters, or an expired entry, miss.
+        let narrowed: Vec<Arc<dyn SearchBackend>> = vec![counting("cat:math.CO")];
+        let r = run("demo", &demo, &narrowed, Some(&cache)).await.unwrap();
+        assert!(!r.backends[0].cached);
+        let expired = cache::SearchCache {
+            ttl: Duration::ZERO,
+            ..cache.clone()
+        };
+        let r = run("demo", &demo, &backends, Some(&expired)).await.unwrap();
+        assert!(!r.backends[0].cached);
+        assert_eq!(b.calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+}
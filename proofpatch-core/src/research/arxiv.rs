@@ -0,0 +1,180 @@
+//! arXiv metadata search, for presets that want literature context.
+//!
+//! The query's words must each occur in the title or abstract (`[research.backends.arxiv]
+//! fields`), and with `categories` set the papers must be in one of them; both are compiled into
+//! one arXiv `search_query` (`search_query`). Snippets are the abstract, after the authors and
+//! year, so the summarizer has enough to cite.
+
+use super::{SearchBackend, SearchFuture, SearchQuery, SearchResult};
+use crate::arxiv::ArxivPaper;
+use crate::config::ArxivConfig;
+use std::time::Duration;
+
+/// Authors named in a snippet before "et al.".
+const MAX_AUTHORS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arxiv {
+    /// arXiv field prefixes each query word is searched in (`ti`, `abs`, or `all`).
+    pub fields: Vec<&'static str>,
+    pub categories: Vec<String>,
+    pub timeout: Duration,
+}
+
+impl Arxiv {
+    pub fn from_config(cfg: Option<&ArxivConfig>, timeout: Duration) -> Result<Self, String> {
+        let cfg = cfg.cloned().unwrap_or_default();
+        let fields = match cfg.fields.as_deref().unwrap_or("title_abstract") {
+            "title_abstract" => vec!["ti", "abs"],
+            "title" => vec!["ti"],
+            "abstract" => vec!["abs"],
+            "all" => vec!["all"],
+            other => {
+                return Err(format!(
+                "arxiv: unknown fields {other} (expected title_abstract, title, abstract, or all)"
+            ))
+            }
+        };
+        Ok(Self {
+            fields,
+            categories: cfg.categories,
+            timeout,
+        })
+    }
+
+    /// The arXiv `search_query` for `text`, e.g. `(ti:ring OR abs:ring) AND (cat:math.AC)`.
+    pub fn search_query(&self, text: &str) -> String {
+        let words: Vec<String> = text
+            .split_whitespace()
+            .map(|w| w.replace(['"', '(', ')', ':'], ""))
+            .filter(|w| !w.is_empty())
+            .collect();
+        let mut clauses: Vec<String> = words
+            .iter()
+            .map(|w| {
+                let alts: Vec<String> = self.fields.iter().map(|f| format!("{f}:{w}")).collect();
+                if alts.len() == 1 {
+                    alts[0].clone()
+                } else {
+                    format!("({})", alts.join(" OR "))
+                }
+            })
+            .collect();
+        if !self.categories.is_empty() {
+            let cats: Vec<String> = self.categories.iter().map(|c| format!("cat:{c}")).collect();
+            clauses.push(format!("({})", cats.join(" OR ")));
+        }
+        clauses.join(" AND ")
+    }
+}
+
+/// `Authors (year). Abstract`.
+pub fn snippet(p: &ArxivPaper) -> String {
+    let mut authors = p
+        .authors
+        .iter()
+        .take(MAX_AUTHORS)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if p.authors.len() > MAX_AUTHORS {
+        authors.push_str(" et al.");
+    }
+    let year = p
+        .published
+        .as_deref()
+        .and_then(|d| d.get(..4))
+        .map(|y| format!(" ({y})"))
+        .unwrap_or_default();
+    match (authors.is_empty(), p.abstract_text.is_empty()) {
+        (true, _) => p.abstract_text.clone(),
+        (false, true) => format!("{authors}{year}."),
+        (false, false) => format!("{authors}{year}. {}", p.abstract_text),
+    }
+}
+
+impl SearchBackend for Arxiv {
+    fn name(&self) -> &str {
+        "arxiv"
+    }
+
+    fn filters(&self) -> String {
+        format!(
+            "fields={} categories={}",
+            self.fields.join(","),
+            self.categories.join(",")
+        )
+    }
+
+    fn search<'a>(&'a self, q: &'a SearchQuery) -> SearchFuture<'a> {
+        Box::pin(async move {
+            let query = self.search_query(&q.text);
+            if query.is_empty() {
+                return Err("arxiv: empty query".to_string());
+            }
+            let papers =
+                crate::arxiv::arxiv_search_query(&query, q.max_results, self.timeout).await?;
+            Ok(papers
+                .iter()
+                .enumerate()
+                .map(|(i, p)| SearchResult {
+                    title: p.title.clone(),
+                    url: p.link.clone(),
+                    snippet: snippet(p),
+                    source: self.name().to_string(),
+                    rank: i + 1,
+                    ..Default::default()
+                })
+                .collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiles_queries_and_snippets() {
+        let a = Arxiv::from_config(None, Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            a.search_query("Ramsey \"bounds\""),
+            "(ti:Ramsey OR abs:Ramsey) AND (ti:bounds OR abs:bounds)"
+        );
+        let cfg = ArxivConfig {
+            categories: vec!["math.CO".into(), "cs.LO".into()],
+            fields: Some("title".into()),
+        };
+        let a = Arxiv::from_config(Some(&cfg), Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            a.search_query("Ramsey"),
+            "ti:Ramsey AND (cat:math.CO OR cat:cs.LO)"
+        );
+        assert!(Arxiv::from_config(
+            Some(&ArxivConfig {
+                fields: Some("body".into()),
+                ..Default::default()
+            }),
+            Duration::from_secs(1)
+        )
+        .is_err());
+
+        let xml = r#"<feed><entry>
+            <id>http://arxiv.org/abs/2101.00001v1</id>
+            <published>2021-01-01T00:00:00Z</published>
+            <title>Small Ramsey numbers</title>
+            <summary>We bound  R(5,5).</summary>
+            <author><name>A</name></author><author><name>B</name></author>
+            <author><name>C</name></author><author><name>D</name></author>
+            <arxiv:primary_category term="math.CO" scheme="http://arxiv.org/schemas/atom"/>
+            <category term="math.CO" scheme="http://arxiv.org/schemas/atom"/>
+            <category term="cs.DM" scheme="http://arxiv.org/schemas/atom"/>
+        </entry></feed>"#;
+        let papers = crate::arxiv::parse_arxiv_atom(xml, 5);
+        assert_eq!(papers[0].categories, ["math.CO", "cs.DM"]);
+        assert_eq!(
+            snippet(&papers[0]),
+            "A, B, C et al. (2021). We bound R(5,5)."
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Cache of search backend answers, so repeated runs and presets sharing a query do not hit
+//! external services (and API quotas) again.
+//!
+//! An entry is keyed on (backend, `SearchBackend::filters`, query, `max_results`) and stored as
+//! `<repo>/.generated/proofpatch-cache/research/<2 hex>/<digest>.json`. Entries older than the TTL
+//! (`[research.defaults] cache_ttl_s`, default a day) are ignored and overwritten. Only answers
+//! are cached; errors and timeouts are retried on the next run.
+
+use super::SearchResult;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 3600);
+
+pub fn default_dir(repo_root: &Path) -> PathBuf {
+    repo_root
+        .join(".generated")
+        .join("proofpatch-cache")
+        .join("research")
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchCacheKey {
+    pub backend: String,
+    /// `SearchBackend::filters`: the backend settings that change its answers.
+    pub filters: String,
+    pub query: String,
+    pub max_results: usize,
+}
+
+impl SearchCacheKey {
+    pub fn digest(&self) -> String {
+        use sha2::{Digest, Sha256};
+        let h = Sha256::digest(serde_json::to_string(self).unwrap_or_default().as_bytes());
+        format!("{h:x}")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedSearch {
+    pub key: SearchCacheKey,
+    /// Unix seconds.
+    pub created_at: u64,
+    pub results: Vec<SearchResult>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchCache {
+    pub dir: PathBuf,
+    pub ttl: Duration,
+}
+
+pub(super) fn now_s() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl SearchCache {
+    fn entry_path(&self, key: &SearchCacheKey) -> PathBuf {
+        let d = key.digest();
+        self.dir.join(&d[..2]).join(format!("{d}.json"))
+    }
+
+    /// The results cached for `key`, if younger than the TTL at `now` (unix seconds).
+    pub fn get(&self, key: &SearchCacheKey, now: u64) -> Option<Vec<SearchResult>> {
+        let txt = std::fs::read_to_string(self.entry_path(key)).ok()?;
+        let c: CachedSearch = serde_json::from_str(&txt).ok()?;
+        (c.key == *key && now.saturating_sub(c.created_at) < self.ttl.as_secs())
+            .then_some(c.results)
+    }
+
+    pub fn put(
+        &self,
+        key: &SearchCacheKey,
+        results: &[SearchResult],
+        now: u64,
+    ) -> Result<PathBuf, String> {
+        let p = self.entry_path(key);
+        let parent = p.parent().unwrap_or(&self.dir);
+        std::fs::create_dir_all(parent).map_err(|e| format!("mkdir {}: {e}", parent.display()))?;
+        let entry = CachedSearch {
+            key: key.clone(),
+            created_at: now,
+            results: results.to_vec(),
+        };
+        let data = serde_json::to_vec(&entry).map_err(|e| e.to_string())?;
+        // Write-then-rename, so a concurrent reader never sees half an entry.
+        let mut tmp = tempfile::NamedTempFile::new_in(parent).map_err(|e| e.to_string())?;
+        std::io::Write::write_all(&mut tmp, &data).map_err(|e| e.to_string())?;
+        tmp.persist(&p)
+            .map_err(|e| format!("write {}: {e}", p.display()))?;
+        Ok(p)
+    }
+}
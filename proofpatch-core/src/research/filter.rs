@@ -0,0 +1,116 @@
+//! Preset filters on search results, applied to each result's title and snippet before results
+//! are cut to `max_results` and summarized.
+//!
+//! Word filters are case-insensitive substring tests: `must_include_any` (one of them must
+//! occur), `must_include_all` (every one), and `must_exclude` (none may). Regex filters are
+//! case-insensitive too: a result must match one of `include_regex` (when set) and none of
+//! `exclude_regex`.
+
+use super::SearchResult;
+use crate::config::ResearchPresetResolved;
+use regex::{Regex, RegexBuilder};
+
+#[derive(Debug, Clone, Default)]
+pub struct ResultFilter {
+    any: Vec<String>,
+    all: Vec<String>,
+    exclude: Vec<String>,
+    include_re: Vec<Regex>,
+    exclude_re: Vec<Regex>,
+}
+
+fn lowered(words: &[String]) -> Vec<String> {
+    words
+        .iter()
+        .map(|w| w.trim().to_lowercase())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+fn compiled(patterns: &[String], field: &str) -> Result<Vec<Regex>, String> {
+    patterns
+        .iter()
+        .map(|p| {
+            RegexBuilder::new(p)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| format!("{field}: invalid pattern {p:?}: {e}"))
+        })
+        .collect()
+}
+
+impl ResultFilter {
+    pub fn from_preset(p: &ResearchPresetResolved) -> Result<Self, String> {
+        Ok(Self {
+            any: lowered(&p.must_include_any),
+            all: lowered(&p.must_include_all),
+            exclude: lowered(&p.must_exclude),
+            include_re: compiled(&p.include_regex, "include_regex")?,
+            exclude_re: compiled(&p.exclude_regex, "exclude_regex")?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.any.is_empty()
+            && self.all.is_empty()
+            && self.exclude.is_empty()
+            && self.include_re.is_empty()
+            && self.exclude_re.is_empty()
+    }
+
+    pub fn keeps(&self, r: &SearchResult) -> bool {
+        self.keeps_text(&r.title, &r.snippet)
+    }
+
+    /// `keeps` for a result with this title and snippet (e.g. an arXiv abstract).
+    pub fn keeps_text(&self, title: &str, snippet: &str) -> bool {
+        let text = format!("{title}\n{snippet}");
+        let hay = text.to_lowercase();
+        (self.any.is_empty() || self.any.iter().any(|w| hay.contains(w.as_str())))
+            && self.all.iter().all(|w| hay.contains(w.as_str()))
+            && !self.exclude.iter().any(|w| hay.contains(w.as_str()))
+            && (self.include_re.is_empty() || self.include_re.iter().any(|re| re.is_match(&text)))
+            && !self.exclude_re.iter().any(|re| re.is_match(&text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ResearchConfig, ResearchPreset};
+
+    #[test]
+    fn word_and_regex_filters() {
+        let mut p = ResearchPreset::new("Ramsey");
+        p.must_include_any = vec!["Ramsey".into(), "coloring".into()];
+        p.must_exclude = vec!["survey".into()];
+        p.exclude_regex = vec![r"\berratum\b".into()];
+        let mut cfg = ResearchConfig::default();
+        cfg.presets.insert("demo".into(), p.clone());
+        let f = ResultFilter::from_preset(&cfg.resolve_preset("demo").unwrap()).unwrap();
+        let r = |title: &str, snippet: &str| SearchResult {
+            title: title.into(),
+            url: "https://x.example".into(),
+            snippet: snippet.into(),
+            source: "t".into(),
+            rank: 1,
+            ..Default::default()
+        };
+        assert!(f.keeps(&r("Small ramsey numbers", "")));
+        assert!(f.keeps(&r("Bounds", "edge COLORING of graphs")));
+        assert!(!f.keeps(&r("Graph minors", "unrelated")));
+        assert!(!f.keeps(&r("Ramsey theory: a survey", "")));
+        assert!(!f.keeps(&r("Erratum: Ramsey numbers", "")));
+
+        p.include_regex = vec![r"R\(\d+, ?\d+\)".into()];
+        cfg.presets.insert("demo".into(), p.clone());
+        let f = ResultFilter::from_preset(&cfg.resolve_preset("demo").unwrap()).unwrap();
+        assert!(f.keeps(&r("Ramsey", "We bound r(5,5).")));
+        assert!(!f.keeps(&r("Ramsey", "Asymptotics.")));
+
+        p.include_regex = vec!["(".into()];
+        cfg.presets.insert("demo".into(), p);
+        let err = ResultFilter::from_preset(&cfg.resolve_preset("demo").unwrap()).unwrap_err();
+        assert!(err.starts_with("include_regex"));
+    }
+}
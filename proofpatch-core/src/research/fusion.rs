@@ -0,0 +1,214 @@
+//! Merging the answers of several backends into one ranking.
+//!
+//! Results are the same when their canonical URLs agree (`canonical_url`: scheme, `www.`,
+//! trailing slashes, tracking parameters and arXiv versions and pdf links don't count) or their
+//! snippets are near-duplicates (word-set Jaccard similarity of at least `NEAR_DUPLICATE`, e.g. a
+//! mirror of the same abstract). Each merged result scores by reciprocal rank fusion: the sum of
+//! `1 / (RRF_K + rank)` over the backends that returned it, so a result several backends agree
+//! on outranks every backend's second-best, and `max_results` takes the best of all sources.
+
+use super::SearchResult;
+use std::collections::{HashMap, HashSet};
+
+/// The usual RRF constant: it damps the difference between a backend's first few ranks.
+pub const RRF_K: f64 = 60.0;
+/// Jaccard similarity above which two snippets are one result.
+pub const NEAR_DUPLICATE: f64 = 0.8;
+/// Snippets shorter than this (in words) are never near-duplicates of anything.
+const MIN_WORDS: usize = 8;
+
+fn is_tracking_param(k: &str) -> bool {
+    k.starts_with("utm_") || matches!(k, "fbclid" | "gclid" | "ref" | "ref_src" | "source")
+}
+
+/// `https://arxiv.org/abs/<id>` for arXiv abs/pdf paths, without a version suffix.
+fn arxiv_abs(path: &str) -> Option<String> {
+    let rest = path
+        .strip_prefix("/abs/")
+        .or_else(|| path.strip_prefix("/pdf/"))?;
+    let id = rest.trim_end_matches(".pdf").trim_matches('/');
+    let id = match id.rfind('v') {
+        Some(i) if i > 0 && id[i + 1..].chars().all(|c| c.is_ascii_digit()) && i + 1 < id.len() => {
+            &id[..i]
+        }
+        _ => id,
+    };
+    (!id.is_empty()).then(|| format!("https://arxiv.org/abs/{id}"))
+}
+
+/// The URL results are compared by.
+pub fn canonical_url(url: &str) -> String {
+    let u = url.trim();
+    let Ok(parsed) = reqwest::Url::parse(u) else {
+        return u.trim_end_matches('/').to_string();
+    };
+    let host = parsed.host_str().unwrap_or("");
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    if host == "arxiv.org" || host == "export.arxiv.org" {
+        if let Some(abs) = arxiv_abs(parsed.path()) {
+            return abs;
+        }
+    }
+    let mut out = format!("https://{host}");
+    if let Some(port) = parsed.port() {
+        out.push_str(&format!(":{port}"));
+    }
+    out.push_str(parsed.path().trim_end_matches('/'));
+    let query: Vec<String> = parsed
+        .query_pairs()
+        .filter(|(k, _)| !is_tracking_param(k))
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect();
+    if !query.is_empty() {
+        out.push('?');
+        out.push_str(&query.join("&"));
+    }
+    // Fragments are kept: Zulip topics and doc anchors live there.
+    if let Some(f) = parsed.fragment().filter(|f| !f.is_empty()) {
+        out.push('#');
+        out.push_str(f);
+    }
+    out
+}
+
+fn words(s: &str) -> HashSet<String> {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Word-set Jaccard similarity of two snippets; 0 when either is shorter than `MIN_WORDS`.
+pub fn snippet_similarity(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    if a.len() < MIN_WORDS || b.len() < MIN_WORDS {
+        return 0.0;
+    }
+    let inter = a.intersection(b).count();
+    inter as f64 / (a.len() + b.len() - inter) as f64
+}
+
+struct Group {
+    best: SearchResult,
+    score: f64,
+    sources: Vec<String>,
+    words: HashSet<String>,
+}
+
+/// One ranking from the backends' answers (`lists`, in backend order, each best first).
+/// Results carry their fused `score` and the other backends that returned them (`also_in`);
+/// each is represented by its best-ranked copy.
+pub fn fuse(lists: Vec<Vec<SearchResult>>) -> Vec<SearchResult> {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut by_url: HashMap<String, usize> = HashMap::new();
+    for list in lists {
+        for r in list {
+            let key = canonical_url(&r.url);
+            let w = words(&r.snippet);
+            let found = by_url.get(&key).copied().or_else(|| {
+                groups
+                    .iter()
+                    .position(|g| snippet_similarity(&g.words, &w) >= NEAR_DUPLICATE)
+            });
+            let contribution = 1.0 / (RRF_K + r.rank as f64);
+            let Some(i) = found else {
+                by_url.insert(key, groups.len());
+                groups.push(Group {
+                    score: contribution,
+                    sources: vec![r.source.clone()],
+                    words: w,
+                    best: r,
+                });
+                continue;
+            };
+            by_url.insert(key, i);
+            let g = &mut groups[i];
+            // A backend counts once per result, at its best rank (it lists those first).
+            if !g.sources.contains(&r.source) {
+                g.score += contribution;
+                g.sources.push(r.source.clone());
+            }
+            if r.rank < g.best.rank {
+                let snippet = std::mem::take(&mut g.best.snippet);
+                g.best = SearchResult {
+                    snippet: if r.snippet.is_empty() {
+                        snippet
+                    } else {
+                        r.snippet
+                    },
+                    ..r
+                };
+            } else if g.best.snippet.is_empty() {
+                g.best.snippet = r.snippet;
+            }
+            if g.words.is_empty() {
+                g.words = words(&g.best.snippet);
+            }
+        }
+    }
+    let mut order: Vec<usize> = (0..groups.len()).collect();
+    // Stable: equal scores keep backend order.
+    order.sort_by(|&a, &b| groups[b].score.total_cmp(&groups[a].score));
+    let mut groups: Vec<Option<Group>> = groups.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|i| groups[i].take())
+        .map(|g| {
+            let mut r = g.best;
+            r.score = Some(g.score);
+            r.also_in = g.sources.into_iter().filter(|s| *s != r.source).collect();
+            r
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r(source: &str, rank: usize, url: &str, snippet: &str) -> SearchResult {
+        SearchResult {
+            title: format!("{source} {rank}"),
+            url: url.into(),
+            snippet: snippet.into(),
+            source: source.into(),
+            rank,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn canonicalizes_and_fuses() {
+        assert_eq!(
+            canonical_url("http://arxiv.org/pdf/2101.00001v2.pdf"),
+            "https://arxiv.org/abs/2101.00001"
+        );
+        assert_eq!(
+            canonical_url("https://www.Example.com/a/?utm_source=x&id=3"),
+            "https://example.com/a?id=3"
+        );
+        assert_eq!(
+            canonical_url("https://leanprover.zulipchat.com/#narrow/channel/1-general"),
+            "https://leanprover.zulipchat.com#narrow/channel/1-general"
+        );
+
+        let abs = "We prove that every two-coloring of the complete graph on many vertices has a monochromatic clique";
+        let fused = fuse(vec![
+            vec![
+                r("a", 1, "https://a.example/only-a", ""),
+                r("a", 2, "https://arxiv.org/abs/2101.00001v1", ""),
+            ],
+            vec![
+                r("b", 1, "https://arxiv.org/pdf/2101.00001", abs),
+                r("b", 2, "https://mirror.example/paper", &format!("{abs}.")),
+            ],
+            vec![r("c", 1, "https://c.example/x", "short")],
+        ]);
+        // The paper: from a (rank 2) and b (rank 1, which represents it), plus b's mirror.
+        let top = &fused[0];
+        assert_eq!((top.source.as_str(), top.rank), ("b", 1));
+        assert_eq!(top.also_in, ["a"]);
+        assert!((top.score.unwrap() - (1.0 / 62.0 + 1.0 / 61.0)).abs() < 1e-12);
+        let rest: Vec<&str> = fused[1..].iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(rest, ["https://a.example/only-a", "https://c.example/x"]);
+    }
+}
@@ -0,0 +1,284 @@
+//! Mathlib documentation search: declaration names and docstrings, linked into the mathlib4 docs.
+//!
+//! Two sources (`[research.backends.mathlib_docs] source`):
+//! - `leansearch` (default): the hosted semantic search (`lemma_search::leansearch_search`);
+//! - `local`: the declaration index doc-gen4 writes next to the docs it builds
+//!   (`declarations/declaration-data.bmp`, JSON despite the name). It is loaded once per backend
+//!   and searched by name and docstring tokens (`premise::premise_tokens`), name matches weighing
+//!   more; nothing leaves the machine.
+
+use super::{SearchBackend, SearchFuture, SearchQuery, SearchResult};
+use crate::config::MathlibDocsConfig;
+use crate::premise::premise_tokens;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+pub const DEFAULT_DOCS_URL: &str = "https://leanprover-community.github.io/mathlib4_docs";
+
+/// Characters of a docstring kept as the snippet.
+const SNIPPET_CHARS: usize = 400;
+
+/// Default `local` index location for a repo (`lake build Mathlib:docs`).
+pub fn default_index_path(repo_root: &Path) -> PathBuf {
+    repo_root
+        .join(".lake")
+        .join("build")
+        .join("doc")
+        .join("declarations")
+        .join("declaration-data.bmp")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocDecl {
+    pub name: String,
+    pub doc: String,
+    /// Relative to the docs root, e.g. `Mathlib/Logic/Basic.html#not_not`.
+    pub link: String,
+}
+
+#[derive(Deserialize)]
+struct DeclarationData {
+    declarations: HashMap<String, DeclEntry>,
+}
+
+#[derive(Deserialize)]
+struct DeclEntry {
+    #[serde(default)]
+    doc: String,
+    #[serde(default, rename = "docLink")]
+    doc_link: String,
+}
+
+/// Parse a doc-gen4 `declaration-data.bmp`.
+pub fn parse_declaration_data(json: &str) -> Result<Vec<DocDecl>, String> {
+    let data: DeclarationData =
+        serde_json::from_str(json).map_err(|e| format!("parse declaration data: {e}"))?;
+    let mut out: Vec<DocDecl> = data
+        .declarations
+        .into_iter()
+        .map(|(name, e)| DocDecl {
+            link: e.doc_link.trim_start_matches("./").to_string(),
+            doc: e.doc.trim().to_string(),
+            name,
+        })
+        .collect();
+    out.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(out)
+}
+
+/// The best `max` of `decls` for `query`: each query token scores 3 when it is a token of the
+/// name and 1 when the docstring mentions it; an exact name match comes first.
+pub fn search_local<'d>(decls: &'d [DocDecl], query: &str, max: usize) -> Vec<&'d DocDecl> {
+    let q: HashSet<String> = premise_tokens(query).into_iter().collect();
+    let q_name = query.trim();
+    let mut scored: Vec<(usize, &DocDecl)> = decls
+        .iter()
+        .filter_map(|d| {
+            let name: HashSet<String> = premise_tokens(&d.name).into_iter().collect();
+            let doc = d.doc.to_lowercase();
+            let mut score: usize = q
+                .iter()
+                .map(|t| 3 * usize::from(name.contains(t)) + usize::from(doc.contains(t.as_str())))
+                .sum();
+            if d.name == q_name {
+                score += 100;
+            }
+            (score > 0).then_some((score, d))
+        })
+        .collect();
+    scored.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then(a.1.name.len().cmp(&b.1.name.len()))
+            .then(a.1.name.cmp(&b.1.name))
+    });
+    scored.into_iter().take(max).map(|(_, d)| d).collect()
+}
+
+fn snippet(doc: &str) -> String {
+    let s = doc.split_whitespace().collect::<Vec<_>>().join(" ");
+    match s.char_indices().nth(SNIPPET_CHARS) {
+        Some((i, _)) => format!("{}…", &s[..i]),
+        None => s,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    LeanSearch,
+    Local(PathBuf),
+}
+
+pub struct MathlibDocs {
+    pub source: Source,
+    pub docs_url: String,
+    pub timeout: Duration,
+    index: OnceCell<Arc<Vec<DocDecl>>>,
+}
+
+impl MathlibDocs {
+    pub fn from_config(
+        cfg: Option<&MathlibDocsConfig>,
+        repo_root: &Path,
+        timeout: Duration,
+    ) -> Result<Self, String> {
+        let cfg = cfg.cloned().unwrap_or_default();
+        let source = match cfg.source.as_deref().unwrap_or("leansearch") {
+            "leansearch" => Source::LeanSearch,
+            "local" => Source::Local(
+                cfg.index_path
+                    .map(|p| repo_root.join(p))
+                    .unwrap_or_else(|| default_index_path(repo_root)),
+            ),
+            other => {
+                return Err(format!(
+                    "mathlib_docs: unknown source {other} (expected leansearch or local)"
+                ))
+            }
+        };
+        Ok(Self {
+            source,
+            docs_url: cfg
+                .docs_url
+                .map(|s| s.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| DEFAULT_DOCS_URL.to_string()),
+            timeout,
+            index: OnceCell::new(),
+        })
+    }
+
+    async fn index(&self, path: &Path) -> Result<Arc<Vec<DocDecl>>, String> {
+        let path = path.to_path_buf();
+        self.index
+            .get_or_try_init(|| async move {
+                tokio::task::spawn_blocking(move || {
+                    let txt = std::fs::read_to_string(&path).map_err(|e| {
+                        format!(
+                            "mathlib_docs: read {} (build the docs, or use source = \"leansearch\"): {e}",
+                            path.display()
+                        )
+                    })?;
+                    parse_declaration_data(&txt).map(Arc::new)
+                })
+                .await
+                .map_err(|e| format!("mathlib_docs: {e}"))?
+            })
+            .await
+            .cloned()
+    }
+
+    fn result(&self, rank: usize, name: &str, link: &str, snippet: String) -> SearchResult {
+        SearchResult {
+            title: name.to_string(),
+            url: format!("{}/{link}", self.docs_url),
+            snippet,
+            source: self.name().to_string(),
+            rank,
+            ..Default::default()
+        }
+    }
+}
+
+impl SearchBackend for MathlibDocs {
+    fn name(&self) -> &str {
+        "mathlib_docs"
+    }
+
+    fn filters(&self) -> String {
+        match &self.source {
+            Source::LeanSearch => format!("leansearch {}", self.docs_url),
+            Source::Local(p) => format!("local {} {}", p.display(), self.docs_url),
+        }
+    }
+
+    fn search<'a>(&'a self, q: &'a SearchQuery) -> SearchFuture<'a> {
+        Box::pin(async move {
+            match &self.source {
+                Source::Local(path) => {
+                    let decls = self.index(path).await?;
+                    Ok(search_local(&decls, &q.text, q.max_results)
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, d)| self.result(i + 1, &d.name, &d.link, snippet(&d.doc)))
+                        .collect())
+                }
+                Source::LeanSearch => {
+                    let hits = crate::lemma_search::leansearch_search(
+                        &q.text,
+                        q.max_results,
+                        self.timeout,
+                    )
+                    .await?;
+                    Ok(hits
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, h)| {
+                            let link = match &h.module {
+                                Some(m) => format!("{}.html#{}", m.replace('.', "/"), h.name),
+                                None => format!("find/?pattern={}#doc", h.name),
+                            };
+                            let text = h.doc.as_deref().unwrap_or(&h.type_signature);
+                            self.result(i + 1, &h.name, &link, snippet(text))
+                        })
+                        .collect())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn searches_a_local_declaration_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = r#"{"declarations": {
+            "Finset.sum_comm": {"doc": "Exchange the order of two finite sums.", "docLink": "./Mathlib/Algebra/BigOperators/Basic.html#Finset.sum_comm", "kind": "theorem"},
+            "Finset.card_le_card": {"doc": "", "docLink": "./Mathlib/Data/Finset/Card.html#Finset.card_le_card"},
+            "Nat.add_comm": {"doc": "Addition is commutative.", "docLink": "./Init/Core.html#Nat.add_comm"}
+        }, "instances": {}}"#;
+        let p = dir.path().join("declaration-data.bmp");
+        std::fs::write(&p, data).unwrap();
+
+        let cfg = MathlibDocsConfig {
+            source: Some("local".into()),
+            index_path: Some(p.display().to_string()),
+            docs_url: None,
+        };
+        let b = MathlibDocs::from_config(Some(&cfg), dir.path(), Duration::from_secs(5)).unwrap();
+        let q = SearchQuery {
+            text: "finite sums order".into(),
+            max_results: 5,
+        };
+        let got = b.search(&q).await.unwrap();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].title, "Finset.sum_comm");
+        assert_eq!(
+            got[0].url,
+            format!("{DEFAULT_DOCS_URL}/Mathlib/Algebra/BigOperators/Basic.html#Finset.sum_comm")
+        );
+
+        // Name tokens outweigh docstring mentions; an exact name wins outright.
+        let decls = b.index(&p).await.unwrap();
+        let names: Vec<&str> = search_local(&decls, "Finset card", 5)
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        assert_eq!(names, ["Finset.card_le_card", "Finset.sum_comm"]);
+        assert_eq!(
+            search_local(&decls, "Nat.add_comm", 1)[0].name,
+            "Nat.add_comm"
+        );
+
+        let bad = MathlibDocsConfig {
+            source: Some("web".into()),
+            ..Default::default()
+        };
+        assert!(MathlibDocs::from_config(Some(&bad), dir.path(), Duration::from_secs(1)).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Research results as repair context: retrieval over a preset's results for the goal at hand.
+//!
+//! A repair profile's `research` preset (`RepairOptions::research_preset`) names the results to
+//! draw on: the preset's latest stored report (`report::latest`), or, when it has none yet, a
+//! fresh run of the preset, whose backend answers come from the search cache while they are
+//! young. Results are split into chunks of about `chunk_tokens` estimated tokens at sentence
+//! boundaries (`chunk`), the chunks and the goal are embedded with the `[embeddings]` backend,
+//! and the `k` chunks most similar to the goal (`retrieve`) fill `{{research}}` in the `repair`
+//! prompt, fenced as untrusted (`prompt_block`).
+
+use super::SearchResult;
+use crate::prompt_context::estimate_tokens;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const DEFAULT_K: usize = 4;
+pub const DEFAULT_CHUNK_TOKENS: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chunk {
+    pub title: String,
+    pub url: String,
+    pub text: String,
+}
+
+impl Chunk {
+    /// What is embedded: the chunk with its result's title.
+    fn embed_text(&self) -> String {
+        format!("{}\n{}", self.title, self.text)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkHit {
+    pub score: f32,
+    #[serde(flatten)]
+    pub chunk: Chunk,
+}
+
+/// Sentences of `text`, each with its terminator.
+fn sentences(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    for w in text.split_whitespace() {
+        if !cur.is_empty() {
+            cur.push(' ');
+        }
+        cur.push_str(w);
+        if w.ends_with(['.', '?', '!']) {
+            out.push(std::mem::take(&mut cur));
+        }
+    }
+    if !cur.is_empty() {
+        out.push(cur);
+    }
+    out
+}
+
+/// The snippets of `results`, in chunks of at most about `max_tokens` each. Sentences are kept
+/// whole unless one alone is over the limit, which is then split between words.
+pub fn chunk(results: &[SearchResult], max_tokens: usize) -> Vec<Chunk> {
+    let max_tokens = max_tokens.max(1);
+    let mut out = Vec::new();
+    for r in results {
+        let mut pieces: Vec<String> = Vec::new();
+        for s in sentences(&r.snippet) {
+            if estimate_tokens(&s) <= max_tokens {
+                pieces.push(s);
+                continue;
+            }
+            let mut cur = String::new();
+            for w in s.split(' ') {
+                if !cur.is_empty() && estimate_tokens(&cur) + estimate_tokens(w) + 1 > max_tokens {
+                    pieces.push(std::mem::take(&mut cur));
+                }
+                if !cur.is_empty() {
+                    cur.push(' ');
+                }
+                cur.push_str(w);
+            }
+            pieces.push(cur);
+        }
+        let mut text = String::new();
+        for p in pieces {
+            if !text.is_empty() && estimate_tokens(&text) + estimate_tokens(&p) + 1 > max_tokens {
+                out.push(Chunk {
+                    title: r.title.clone(),
+                    url: r.url.clone(),
+                    text: std::mem::take(&mut text),
+                });
+            }
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(&p);
+        }
+        // A result without a snippet still has its title to match on.
+        out.push(Chunk {
+            title: r.title.clone(),
+            url: r.url.clone(),
+            text,
+        });
+    }
+    out
+}
+
+/// The `k` chunks most similar to `goal` (score > 0), best first.
+pub async fn retrieve(
+    chunks: &[Chunk],
+    goal: &str,
+    k: usize,
+    emb: &crate::llm::embeddings::Embeddings,
+) -> Result<Vec<ChunkHit>, String> {
+    if chunks.is_empty() || k == 0 {
+        return Ok(Vec::new());
+    }
+    let mut texts: Vec<String> = chunks.iter().map(Chunk::embed_text).collect();
+    texts.push(goal.to_string());
+    let mut vs = emb.embed_all(&texts).await?;
+    let q = vs.pop().unwrap_or_default();
+    let mut hits: Vec<ChunkHit> = chunks
+        .iter()
+        .zip(vs)
+        .map(|(c, v)| ChunkHit {
+            score: q.iter().zip(&v).map(|(a, b)| a * b).sum(),
+            chunk: c.clone(),
+        })
+        .filter(|h| h.score > 0.0)
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(k);
+    Ok(hits)
+}
+
+/// The `{{research}}` block: one entry per hit, best first, fenced as untrusted.
+pub fn prompt_block(hits: &[ChunkHit]) -> String {
+    let body = hits
+        .iter()
+        .map(|h| {
+            let text = h.chunk.text.trim();
+            if text.is_empty() {
+                format!("- {} ({})", h.chunk.title, h.chunk.url)
+            } else {
+                format!("- {} ({})\n  {text}", h.chunk.title, h.chunk.url)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    crate::untrusted::delimit("research", &body)
+}
+
+/// The results of `preset` to retrieve from: its latest stored report, else a run of it (with
+/// the search cache).
+pub async fn preset_results(repo_root: &Path, preset: &str) -> Result<Vec<SearchResult>, String> {
+    let cfg = crate::config::load_from_repo_root(repo_root)?.unwrap_or_default();
+    let runs_dir = crate::llm::transcripts::runs_dir(repo_root, &cfg.llm.transcripts);
+    if let Some(p) = super::report::latest(&runs_dir, preset) {
+        return Ok(super::report::load(&p)?.run.results);
+    }
+    Ok(super::run_preset(repo_root, &cfg.research, preset, true)
+        .await?
+        .results)
+}
+
+/// The `{{research}}` block for `goal` from the results of `preset` (empty when nothing is
+/// similar enough).
+pub async fn context_for(
+    repo_root: &Path,
+    preset: &str,
+    goal: &str,
+    k: usize,
+    chunk_tokens: usize,
+) -> Result<String, String> {
+    let results = preset_results(repo_root, preset).await?;
+    let chunks = chunk(&results, chunk_tokens);
+    let emb = crate::llm::embeddings::from_repo(repo_root)?;
+    let hits = match retrieve(&chunks, goal, k, &emb).await {
+        Ok(h) => h,
+        // A configured embeddings backend that fails falls back to the hashing embedder.
+        Err(_) if !emb.is_hashing() => {
+            retrieve(
+                &chunks,
+                goal,
+                k,
+                &crate::llm::embeddings::Embeddings::hashing(512),
+            )
+            .await?
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(prompt_block(&hits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::embeddings::Embeddings;
+
+    #[tokio::test]
+    async fn chunks_and_retrieves_for_the_goal() {
+        let r = |title: &str, url: &str, snippet: &str| SearchResult {
+            title: title.into(),
+            url: url.into(),
+            snippet: snippet.into(),
+            source: "t".into(),
+            rank: 1,
+            ..Default::default()
+        };
+        let results = [
+            r(
+                "Finite sums",
+                "https://a.example/sums",
+                "Exchanging the order of two finite sums over a product set. \
+                 The proof uses Finset.sum_comm and a bijection between the index sets.",
+            ),
+            r(
+                "Ring normalization",
+                "https://b.example/ring",
+                "The ring tactic normalizes commutative ring expressions.",
+            ),
+            r("Untitled note", "https://c.example/x", ""),
+        ];
+        let chunks = chunk(&results, 20);
+        let texts: Vec<(&str, &str)> = chunks
+            .iter()
+            .map(|c| (c.url.as_str(), c.text.as_str()))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                (
+                    "https://a.example/sums",
+                    "Exchanging the order of two finite sums over a product set."
+                ),
+                (
+                    "https://a.example/sums",
+                    "The proof uses Finset.sum_comm and a bijection between the index sets."
+                ),
+                (
+                    "https://b.example/ring",
+                    "The ring tactic normalizes commutative ring expressions."
+                ),
+                ("https://c.example/x", ""),
+            ]
+        );
+        assert!(chunks.iter().all(|c| estimate_tokens(&c.text) <= 20));
+
+        let hits = retrieve(
+            &chunks,
+            "⊢ commutative ring expressions normalize",
+            1,
+            &Embeddings::hashing(512),
+        )
+        .await
+        .unwrap();
+        assert_eq!(hits[0].chunk.url, "https://b.example/ring");
+        let block = prompt_block(&hits);
+        assert!(block.starts_with(
+            "<untrusted source=\"research\">\n- Ring normalization (https://b.example/ring)\n"
+        ));
+        assert!(prompt_block(&[]).is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+//! Reports of a research run: Markdown for people (`markdown`: the cited summary, the numbered
+//! sources it cites, and how each backend fared) and JSON for tools (`ResearchReport`).
+//!
+//! Both are stored with the run's other artifacts, as `research/<preset>.md` and
+//! `research/<preset>.json` in the run directory (`transcripts::artifacts_dir`: the transcripts'
+//! one, so a run's summary requests and its report sit together). `latest` finds a preset's most
+//! recent report across runs, so reports are addressable by preset name alone.
+
+use super::ResearchRun;
+use crate::ResearchSource;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Subdirectory of a run directory the reports go to.
+pub const DIR_NAME: &str = "research";
+
+/// Characters of a snippet quoted under its source.
+const SNIPPET_CHARS: usize = 300;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResearchReport {
+    /// Unix seconds.
+    pub created_at: u64,
+    #[serde(flatten)]
+    pub run: ResearchRun,
+    /// The deduplicated sources, numbered from 1 in this order; `summary.citations` refer to
+    /// them by number.
+    pub sources: Vec<ResearchSource>,
+}
+
+impl ResearchReport {
+    pub fn new(run: &ResearchRun, created_at: u64) -> Self {
+        Self {
+            created_at,
+            sources: run.notes().sources,
+            run: run.clone(),
+        }
+    }
+}
+
+/// Where the reports of `preset` go in `run_dir`: (Markdown, JSON).
+pub fn paths(run_dir: &Path, preset: &str) -> (PathBuf, PathBuf) {
+    let stem: String = preset
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let dir = run_dir.join(DIR_NAME);
+    (
+        dir.join(format!("{stem}.md")),
+        dir.join(format!("{stem}.json")),
+    )
+}
+
+fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn source_line(n: usize, s: &ResearchSource) -> String {
+    let title = one_line(s.title.as_deref().unwrap_or("(untitled)"));
+    let mut line = format!(
+        "{n}. [{}]({})",
+        title.replace('[', "\\[").replace(']', "\\]"),
+        s.url
+    );
+    if let Some(o) = &s.origin {
+        line.push_str(&format!(" ({o})"));
+    }
+    line.push('\n');
+    if let Some(snippet) = s.snippet.as_deref().map(one_line).filter(|x| !x.is_empty()) {
+        let quoted = match snippet.char_indices().nth(SNIPPET_CHARS) {
+            Some((i, _)) => format!("{}…", &snippet[..i]),
+            None => snippet,
+        };
+        line.push_str(&format!("   > {quoted}\n"));
+    }
+    line
+}
+
+/// The run as a Markdown report.
+pub fn markdown(run: &ResearchRun) -> String {
+    let sources = run.notes().sources;
+    let mut out = format!(
+        "# Research: {}\n\nQuery: `{}`\n",
+        run.preset,
+        run.query.replace('`', "'")
+    );
+    if let Some(s) = &run.summary {
+        out.push_str("\n## Summary\n\n");
+        out.push_str(s.text.trim());
+        out.push('\n');
+        if s.partial {
+            out.push_str(&format!(
+                "\n_Partial: {} of {} chunks summarized in time._\n",
+                s.chunks_summarized, s.chunks
+            ));
+        }
+        if !s.citations.is_empty() {
+            out.push_str("\nCited:");
+            for c in &s.citations {
+                out.push_str(&format!(" [{}]", c.n));
+            }
+            out.push('\n');
+        }
+    }
+    out.push_str("\n## Sources\n\n");
+    if sources.is_empty() {
+        out.push_str("No results.\n");
+    }
+    for (i, s) in sources.iter().enumerate() {
+        out.push_str(&source_line(i + 1, s));
+    }
+    out.push_str(
+        "\n## Backends\n\n| backend | results | filtered | ms | status |\n|---|---|---|---|---|\n",
+    );
+    for b in &run.backends {
+        let status = match (&b.error, b.cached) {
+            (Some(e), _) => one_line(e).replace('|', "\\|"),
+            (None, true) => "ok (cached)".to_string(),
+            (None, false) => "ok".to_string(),
+        };
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {status} |\n",
+            b.backend, b.results, b.filtered, b.elapsed_ms
+        ));
+    }
+    out
+}
+
+/// Write both reports of `run` into `run_dir`; returns their paths (Markdown, JSON).
+pub fn write(run_dir: &Path, run: &ResearchRun, now: u64) -> Result<(PathBuf, PathBuf), String> {
+    let (md, json) = paths(run_dir, &run.preset);
+    if let Some(dir) = md.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("mkdir {}: {e}", dir.display()))?;
+    }
+    std::fs::write(&md, markdown(run)).map_err(|e| format!("write {}: {e}", md.display()))?;
+    let data =
+        serde_json::to_string_pretty(&ResearchReport::new(run, now)).map_err(|e| e.to_string())?;
+    std::fs::write(&json, data).map_err(|e| format!("write {}: {e}", json.display()))?;
+    Ok((md, json))
+}
+
+/// The JSON report of `preset` in the newest run under `runs_dir` that has one.
+pub fn latest(runs_dir: &Path, preset: &str) -> Option<PathBuf> {
+    let mut runs: Vec<PathBuf> = std::fs::read_dir(runs_dir)
+        .ok()?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_dir())
+        .collect();
+    // Run names start with the creation time: newest first.
+    runs.sort();
+    runs.into_iter()
+        .rev()
+        .map(|r| paths(&r, preset).1)
+        .find(|p| p.is_file())
+}
+
+pub fn load(path: &Path) -> Result<ResearchReport, String> {
+    let txt = std::fs::read_to_string(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+    serde_json::from_str(&txt).map_err(|e| format!("parse {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::research::{BackendRun, SearchResult};
+    use crate::research_summary::{Citation, ResearchSummary};
+
+    #[test]
+    fn renders_and_finds_reports_by_preset() {
+        let result = |rank: usize, url: &str| SearchResult {
+            title: format!("Paper {rank}"),
+            url: url.into(),
+            snippet: "Bounds on R(5,5).".into(),
+            source: "arxiv".into(),
+            rank,
+            ..Default::default()
+        };
+        let run = ResearchRun {
+            preset: "ramsey".into(),
+            query: "Ramsey bounds".into(),
+            results: vec![
+                result(1, "https://arxiv.org/abs/2101.00001"),
+                result(2, "https://arxiv.org/abs/2101.00002"),
+            ],
+            backends: vec![
+                BackendRun {
+                    backend: "arxiv".into(),
+                    results: 2,
+                    filtered: 0,
+                    elapsed_ms: 12,
+                    timed_out: false,
+                    cached: true,
+                    error: None,
+                },
+                BackendRun {
+                    backend: "zulip".into(),
+                    results: 0,
+                    filtered: 0,
+                    elapsed_ms: 5,
+                    timed_out: false,
+                    cached: false,
+                    error: Some("zulip: http 500 |x|".into()),
+                },
+            ],
+            summary: Some(ResearchSummary {
+                text: "- R(5,5) is at most 46 [2].".into(),
+                citations: vec![Citation {
+                    n: 2,
+                    url: "https://arxiv.org/abs/2101.00002".into(),
+                    title: Some("Paper 2".into()),
+                }],
+                chunks: 1,
+                chunks_summarized: 1,
+                partial: false,
+                provider: None,
+                model: None,
+                usage: Default::default(),
+                elapsed_ms: 40,
+            }),
+        };
+        let md = markdown(&run);
+        assert!(md.starts_with("# Research: ramsey\n\nQuery: `Ramsey bounds`\n"));
+        assert!(md.contains("- R(5,5) is at most 46 [2].\n\nCited: [2]\n"));
+        assert!(md.contains(
+            "2. [Paper 2](https://arxiv.org/abs/2101.00002) (arxiv)\n   > Bounds on R(5,5).\n"
+        ));
+        assert!(md.contains("| arxiv | 2 | 0 | 12 | ok (cached) |"));
+        assert!(md.contains("| zulip | 0 | 0 | 5 | zulip: http 500 \\|x\\| |"));
+
+        let td = tempfile::tempdir().unwrap();
+        let (old, new) = (
+            td.path().join("1700000001-1"),
+            td.path().join("1700000002-1"),
+        );
+        write(&old, &run, 1).unwrap();
+        let (md_path, _) = write(&new, &run, 2).unwrap();
+        assert_eq!(md_path, new.join("research").join("ramsey.md"));
+        std::fs::create_dir_all(td.path().join("1700000003-1")).unwrap();
+
+        let found = latest(td.path(), "ramsey").unwrap();
+        assert_eq!(found, new.join("research").join("ramsey.json"));
+        let report = load(&found).unwrap();
+        assert_eq!(report.created_at, 2);
+        assert_eq!(report.run.preset, "ramsey");
+        assert_eq!(report.sources[1].url, "https://arxiv.org/abs/2101.00002");
+        assert!(latest(td.path(), "other").is_none());
+    }
+}
@@ -0,0 +1,223 @@
+//! Web search backends: SearxNG (self-hosted metasearch) and the Brave Search API.
+//!
+//! Both are configured under `[research.backends.<name>]`; the Brave token is read from the env
+//! var named there. Snippets come back with highlighting markup, which is stripped.
+
+use super::{client, strip_tags, SearchBackend, SearchFuture, SearchQuery, SearchResult};
+use crate::config::{BraveConfig, SearxngConfig};
+use serde_json::Value;
+use std::time::Duration;
+
+fn str_at<'v>(v: &'v Value, key: &str) -> &'v str {
+    v.get(key).and_then(Value::as_str).unwrap_or("").trim()
+}
+
+/// Results of a `{title, url, <snippet_key>}` array, ranked in order, skipping entries
+/// without a URL.
+fn results_of(items: Option<&Value>, snippet_key: &str, source: &str) -> Vec<SearchResult> {
+    items
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|it| !str_at(it, "url").is_empty())
+        .enumerate()
+        .map(|(i, it)| SearchResult {
+            title: strip_tags(str_at(it, "title")),
+            url: str_at(it, "url").to_string(),
+            snippet: strip_tags(str_at(it, snippet_key)),
+            source: source.to_string(),
+            rank: i + 1,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// `GET {base_url}/search?format=json`.
+#[derive(Debug, Clone)]
+pub struct Searxng {
+    pub base_url: String,
+    pub engines: Vec<String>,
+    pub language: Option<String>,
+    pub timeout: Duration,
+}
+
+impl Searxng {
+    pub fn from_config(cfg: Option<&SearxngConfig>, timeout: Duration) -> Result<Self, String> {
+        let cfg = cfg.cloned().unwrap_or_default();
+        let base_url = cfg
+            .base_url
+            .or_else(|| std::env::var("PROOFPATCH_SEARXNG_URL").ok())
+            .map(|s| s.trim().trim_end_matches('/').to_string())
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| {
+                "searxng: set [research.backends.searxng] base_url or PROOFPATCH_SEARXNG_URL"
+                    .to_string()
+            })?;
+        Ok(Self {
+            base_url,
+            engines: cfg.engines,
+            language: cfg.language,
+            timeout,
+        })
+    }
+}
+
+pub fn parse_searxng(v: &Value, max_results: usize) -> Vec<SearchResult> {
+    let mut out = results_of(v.get("results"), "content", "searxng");
+    out.truncate(max_results);
+    out
+}
+
+impl SearchBackend for Searxng {
+    fn name(&self) -> &str {
+        "searxng"
+    }
+
+    fn filters(&self) -> String {
+        format!(
+            "{} engines={} language={}",
+            self.base_url,
+            self.engines.join(","),
+            self.language.as_deref().unwrap_or("")
+        )
+    }
+
+    fn search<'a>(&'a self, q: &'a SearchQuery) -> SearchFuture<'a> {
+        Box::pin(async move {
+            let mut url = reqwest::Url::parse(&format!("{}/search", self.base_url))
+                .map_err(|e| format!("parse searxng url: {e}"))?;
+            url.query_pairs_mut()
+                .append_pair("q", &q.text)
+                .append_pair("format", "json");
+            if !self.engines.is_empty() {
+                url.query_pairs_mut()
+                    .append_pair("engines", &self.engines.join(","));
+            }
+            if let Some(l) = &self.language {
+                url.query_pairs_mut().append_pair("language", l);
+            }
+            let resp = client(self.timeout)?
+                .get(url)
+                .send()
+                .await
+                .map_err(|e| format!("searxng: {e}"))?;
+            let status = resp.status();
+            if !status.is_success() {
+                return Err(format!("searxng: http {status}"));
+            }
+            let v: Value = resp
+                .json()
+                .await
+                .map_err(|e| format!("searxng json: {e}"))?;
+            Ok(parse_searxng(&v, q.max_results))
+        })
+    }
+}
+
+/// `GET {base_url}/res/v1/web/search`, authenticated with `X-Subscription-Token`.
+#[derive(Debug, Clone)]
+pub struct Brave {
+    pub base_url: String,
+    pub api_key: String,
+    pub country: Option<String>,
+    pub timeout: Duration,
+}
+
+impl Brave {
+    pub fn from_config(cfg: Option<&BraveConfig>, timeout: Duration) -> Result<Self, String> {
+        let cfg = cfg.cloned().unwrap_or_default();
+        let key_env = cfg.api_key_env.as_deref().unwrap_or("BRAVE_API_KEY");
+        let api_key = std::env::var(key_env)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| format!("brave: {key_env} is not set"))?;
+        Ok(Self {
+            base_url: cfg
+                .base_url
+                .map(|s| s.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| "https://api.search.brave.com".to_string()),
+            api_key,
+            country: cfg.country,
+            timeout,
+        })
+    }
+}
+
+pub fn parse_brave(v: &Value, max_results: usize) -> Vec<SearchResult> {
+    let mut out = results_of(v.pointer("/web/results"), "description", "brave");
+    out.truncate(max_results);
+    out
+}
+
+impl SearchBackend for Brave {
+    fn name(&self) -> &str {
+        "brave"
+    }
+
+    fn filters(&self) -> String {
+        format!(
+            "{} country={}",
+            self.base_url,
+            self.country.as_deref().unwrap_or("")
+        )
+    }
+
+    fn search<'a>(&'a self, q: &'a SearchQuery) -> SearchFuture<'a> {
+        Box::pin(async move {
+            let mut url = reqwest::Url::parse(&format!("{}/res/v1/web/search", self.base_url))
+                .map_err(|e| format!("parse brave url: {e}"))?;
+            // The API returns at most 20 results per request.
+            url.query_pairs_mut()
+                .append_pair("q", &q.text)
+                .append_pair("count", &q.max_results.clamp(1, 20).to_string());
+            if let Some(c) = &self.country {
+                url.query_pairs_mut().append_pair("country", c);
+            }
+            let resp = client(self.timeout)?
+                .get(url)
+                .header("Accept", "application/json")
+                .header("X-Subscription-Token", &self.api_key)
+                .send()
+                .await
+                .map_err(|e| format!("brave: {e}"))?;
+            let status = resp.status();
+            if !status.is_success() {
+                return Err(format!("brave: http {status}"));
+            }
+            let v: Value = resp.json().await.map_err(|e| format!("brave json: {e}"))?;
+            Ok(parse_brave(&v, q.max_results))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_searxng_and_brave_answers() {
+        let sx = json!({"results": [
+            {"title": "Nat.succ_le_iff", "url": "https://a.example/1", "content": "a <b>lemma</b>", "engine": "ddg"},
+            {"title": "no url", "content": "skipped"},
+            {"title": "Two", "url": "https://a.example/2", "content": "a < b"},
+        ]});
+        let got = parse_searxng(&sx, 8);
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].snippet, "a lemma");
+        assert_eq!((got[1].rank, got[1].snippet.as_str()), (2, "a < b"));
+
+        let br = json!({"web": {"results": [
+            {"title": "<strong>Finset</strong> sum", "url": "https://b.example/1", "description": "x &amp; y"},
+            {"title": "B2", "url": "https://b.example/2", "description": "z"},
+        ]}});
+        let got = parse_brave(&br, 1);
+        assert_eq!(got.len(), 1);
+        assert_eq!(
+            (got[0].title.as_str(), got[0].snippet.as_str()),
+            ("Finset sum", "x & y")
+        );
+        assert!(parse_brave(&json!({"query": {}}), 5).is_empty());
+    }
+}
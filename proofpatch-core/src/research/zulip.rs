@@ -0,0 +1,246 @@
+//! Lean Zulip search (`leanprover.zulipchat.com`), one result per thread.
+//!
+//! Messages matching the query come from the Zulip search API (`GET /api/v1/messages` with a
+//! `search` narrow), newest first. With `ZULIP_EMAIL` and `ZULIP_API_KEY` (or the env vars named
+//! in `[research.backends.zulip]`) the request is authenticated; without, it is restricted to the
+//! web-public channels, which is where the Lean community's Q&A lives. Matches are grouped by
+//! channel and topic (`group_threads`): a thread with more matching messages ranks higher, ties
+//! going to the more recent one, and its snippet quotes the matching messages.
+
+use super::{client, strip_tags, SearchBackend, SearchFuture, SearchQuery, SearchResult};
+use crate::config::ZulipConfig;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub const DEFAULT_REALM: &str = "https://leanprover.zulipchat.com";
+
+/// Messages fetched per query (the API allows up to 5000; threads need a handful each).
+const MAX_MESSAGES: usize = 200;
+/// Characters of a message quoted in its thread's snippet.
+const QUOTE_CHARS: usize = 240;
+/// Messages quoted per thread.
+const QUOTES: usize = 3;
+
+/// Zulip's encoding of a narrow component in a URL fragment: percent-encoding with `.` for `%`.
+fn encode_hash_component(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'_'
+            | b'!'
+            | b'~'
+            | b'*'
+            | b'\''
+            | b'('
+            | b')' => out.push(b as char),
+            _ => out.push_str(&format!(".{b:02X}")),
+        }
+    }
+    out
+}
+
+/// Link to a topic: `{realm}/#narrow/channel/{id}-{channel}/topic/{topic}`.
+pub fn topic_url(realm: &str, stream_id: u64, stream: &str, topic: &str) -> String {
+    format!(
+        "{realm}/#narrow/channel/{stream_id}-{}/topic/{}",
+        encode_hash_component(stream),
+        encode_hash_component(topic)
+    )
+}
+
+fn quote(content: &str) -> String {
+    let s = strip_tags(content);
+    match s.char_indices().nth(QUOTE_CHARS) {
+        Some((i, _)) => format!("{}…", &s[..i]),
+        None => s,
+    }
+}
+
+/// Threads of the `messages` of a search answer, best first, at most `max`.
+pub fn group_threads(realm: &str, messages: &Value, max: usize) -> Vec<SearchResult> {
+    struct Thread {
+        stream_id: u64,
+        stream: String,
+        topic: String,
+        latest: u64,
+        quotes: Vec<String>,
+        hits: usize,
+    }
+    let mut threads: HashMap<(u64, String), Thread> = HashMap::new();
+    for m in messages.as_array().into_iter().flatten() {
+        let stream_id = m.get("stream_id").and_then(Value::as_u64);
+        let stream = m.get("display_recipient").and_then(Value::as_str);
+        let (Some(stream_id), Some(stream)) = (stream_id, stream) else {
+            // Direct messages: no thread to link to.
+            continue;
+        };
+        let topic = m.get("subject").and_then(Value::as_str).unwrap_or("");
+        let t = threads
+            .entry((stream_id, topic.to_string()))
+            .or_insert_with(|| Thread {
+                stream_id,
+                stream: stream.to_string(),
+                topic: topic.to_string(),
+                latest: 0,
+                quotes: Vec::new(),
+                hits: 0,
+            });
+        t.hits += 1;
+        t.latest = t
+            .latest
+            .max(m.get("timestamp").and_then(Value::as_u64).unwrap_or(0));
+        let content = m.get("content").and_then(Value::as_str).unwrap_or("");
+        if t.quotes.len() < QUOTES && !content.trim().is_empty() {
+            let who = m
+                .get("sender_full_name")
+                .and_then(Value::as_str)
+                .unwrap_or("?");
+            t.quotes.push(format!("{who}: {}", quote(content)));
+        }
+    }
+    let mut threads: Vec<Thread> = threads.into_values().collect();
+    threads.sort_by(|a, b| {
+        b.hits
+            .cmp(&a.hits)
+            .then(b.latest.cmp(&a.latest))
+            .then(a.topic.cmp(&b.topic))
+    });
+    threads
+        .into_iter()
+        .take(max)
+        .enumerate()
+        .map(|(i, t)| SearchResult {
+            title: format!("#{} > {}", t.stream, t.topic),
+            url: topic_url(realm, t.stream_id, &t.stream, &t.topic),
+            snippet: format!(
+                "{} matching message{}: {}",
+                t.hits,
+                if t.hits == 1 { "" } else { "s" },
+                t.quotes.join(" … ")
+            ),
+            source: "zulip".to_string(),
+            rank: i + 1,
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct Zulip {
+    pub realm: String,
+    /// Email and API key; `None` searches the web-public channels anonymously.
+    pub auth: Option<(String, String)>,
+    pub timeout: Duration,
+}
+
+impl Zulip {
+    pub fn from_config(cfg: Option<&ZulipConfig>, timeout: Duration) -> Self {
+        let cfg = cfg.cloned().unwrap_or_default();
+        let env = |name: Option<&str>, default: &str| {
+            std::env::var(name.unwrap_or(default))
+                .ok()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        let email = env(cfg.email_env.as_deref(), "ZULIP_EMAIL");
+        let key = env(cfg.api_key_env.as_deref(), "ZULIP_API_KEY");
+        Self {
+            realm: cfg
+                .realm
+                .map(|s| s.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| DEFAULT_REALM.to_string()),
+            auth: email.zip(key),
+            timeout,
+        }
+    }
+}
+
+impl SearchBackend for Zulip {
+    fn name(&self) -> &str {
+        "zulip"
+    }
+
+    fn filters(&self) -> String {
+        // Signed-in searches see more channels than anonymous ones.
+        let scope = if self.auth.is_some() {
+            "all"
+        } else {
+            "web-public"
+        };
+        format!("{} {scope}", self.realm)
+    }
+
+    fn search<'a>(&'a self, q: &'a SearchQuery) -> SearchFuture<'a> {
+        Box::pin(async move {
+            let mut narrow = vec![json!({"operator": "search", "operand": q.text})];
+            if self.auth.is_none() {
+                narrow.push(json!({"operator": "channels", "operand": "web-public"}));
+            }
+            let mut url = reqwest::Url::parse(&format!("{}/api/v1/messages", self.realm))
+                .map_err(|e| format!("parse zulip url: {e}"))?;
+            url.query_pairs_mut()
+                .append_pair("anchor", "newest")
+                .append_pair(
+                    "num_before",
+                    &(q.max_results * 20).clamp(20, MAX_MESSAGES).to_string(),
+                )
+                .append_pair("num_after", "0")
+                .append_pair("apply_markdown", "false")
+                .append_pair("narrow", &Value::Array(narrow).to_string());
+            let mut req = client(self.timeout)?.get(url);
+            if let Some((email, key)) = &self.auth {
+                req = req.basic_auth(email, Some(key));
+            }
+            let resp = req.send().await.map_err(|e| format!("zulip: {e}"))?;
+            let status = resp.status();
+            let v: Value = resp.json().await.map_err(|e| format!("zulip json: {e}"))?;
+            if !status.is_success() {
+                let msg = v.get("msg").and_then(Value::as_str).unwrap_or("");
+                return Err(format!("zulip: http {status} {msg}").trim().to_string());
+            }
+            Ok(group_threads(
+                &self.realm,
+                v.get("messages").unwrap_or(&Value::Null),
+                q.max_results,
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_messages_by_thread() {
+        let msgs = json!([
+            {"stream_id": 113488, "display_recipient": "general", "subject": "ring_nf vs ring",
+             "content": "use `ring_nf` at h", "sender_full_name": "A", "timestamp": 30},
+            {"stream_id": 113489, "display_recipient": "new members", "subject": "omega fails",
+             "content": "try <b>omega</b>", "sender_full_name": "B", "timestamp": 50},
+            {"stream_id": 113488, "display_recipient": "general", "subject": "ring_nf vs ring",
+             "content": "ring closes it", "sender_full_name": "C", "timestamp": 10},
+            {"type": "private", "display_recipient": [{"id": 1}], "subject": "", "content": "dm"}
+        ]);
+        let got = group_threads(DEFAULT_REALM, &msgs, 5);
+        let titles: Vec<&str> = got.iter().map(|r| r.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            ["#general > ring_nf vs ring", "#new members > omega fails"]
+        );
+        assert_eq!(
+            got[0].snippet,
+            "2 matching messages: A: use `ring_nf` at h … C: ring closes it"
+        );
+        assert_eq!(
+            got[1].url,
+            "https://leanprover.zulipchat.com/#narrow/channel/113489-new.20members/topic/omega.20fails"
+        );
+        assert_eq!(group_threads(DEFAULT_REALM, &msgs, 1).len(), 1);
+    }
+}
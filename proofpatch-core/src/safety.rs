@@ -0,0 +1,412 @@
+//! Safety gate: what a candidate may not do, however well it compiles.
+//!
+//! Before a candidate is compiled, `check_candidate` rejects text that proves nothing or
+//! changes what is being proved: `sorry` / `admit`, `axiom` declarations, `native_decide`
+//! (allowed with `[verify] allow_native_decide = true`), and commands that would end the proof
+//! and add declarations after it (`theorem`, `namespace`, `#eval`, attributes, …).
+//! `check_edit` rejects patches that touch the file outside the declaration being repaired.
+//! Comments and string literals are ignored.
+//!
+//! After a candidate compiles, `check_axioms` confirms it with `#print axioms` on the
+//! declaration: the command is added after the declaration in the compiled text
+//! (`instrument`), so the check usually costs nothing; when the verifier drops info messages,
+//! it is elaborated separately. Axioms beyond the standard three (and, when `native_decide` is
+//! allowed, the compiler axioms it needs) fail the check unless the original declaration already
+//! depended on them. `sorryAx` always fails it.
+//!
+//! The gate cannot be turned off; the repair loop and beam / MCTS confirmation run it.
+
+use crate::config::VerifyConfig;
+use crate::lean_query::{AxiomsReport, LeanQuery};
+use crate::patching::EditRecord;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// Axioms `native_decide` proofs depend on.
+pub const NATIVE_AXIOMS: &[&str] = &["Lean.ofReduceBool", "Lean.trustCompiler"];
+
+/// Commands that end a tactic block and start something new at the top level.
+const COMMANDS: &[&str] = &[
+    "theorem",
+    "lemma",
+    "def",
+    "abbrev",
+    "instance",
+    "example",
+    "axiom",
+    "structure",
+    "class",
+    "inductive",
+    "opaque",
+    "namespace",
+    "section",
+    "end",
+    "universe",
+    "variable",
+    "attribute",
+    "macro",
+    "macro_rules",
+    "syntax",
+    "elab",
+    "noncomputable",
+    "private",
+    "protected",
+    "unsafe",
+    "import",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SafetyPolicy {
+    pub allow_native_decide: bool,
+}
+
+impl SafetyPolicy {
+    pub fn from_config(c: &VerifyConfig) -> Self {
+        Self {
+            allow_native_decide: c.allow_native_decide.unwrap_or(false),
+        }
+    }
+
+    /// From the repo's `proofpatch.toml` (the defaults when absent).
+    pub fn configured(repo_root: &Path) -> Self {
+        crate::config::load_from_repo_root(repo_root)
+            .ok()
+            .flatten()
+            .map(|c| Self::from_config(&c.verify))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Violation {
+    Sorry,
+    Admit,
+    NativeDecide,
+    /// An `axiom` declaration in the candidate.
+    Axiom {
+        name: String,
+    },
+    /// A top-level command in the candidate, or a patch outside the declaration.
+    OutsideSpan {
+        detail: String,
+    },
+    /// Axioms the verified declaration depends on that it may not (`check_axioms`).
+    NewAxioms {
+        axioms: Vec<String>,
+    },
+    /// `#print axioms` produced nothing for the declaration.
+    AxiomsUnchecked,
+}
+
+impl Violation {
+    pub fn message(&self) -> String {
+        match self {
+            Self::Sorry => "uses `sorry`".to_string(),
+            Self::Admit => "uses `admit`".to_string(),
+            Self::NativeDecide => {
+                "uses `native_decide` (allow with `[verify] allow_native_decide`)".to_string()
+            }
+            Self::Axiom { name } => format!("declares axiom `{name}`"),
+            Self::OutsideSpan { detail } => format!("edits outside the target: {detail}"),
+            Self::NewAxioms { axioms } => format!("depends on new axioms: {}", axioms.join(", ")),
+            Self::AxiomsUnchecked => "`#print axioms` gave no result".to_string(),
+        }
+    }
+}
+
+/// A candidate the gate turned away before it was compiled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rejection {
+    pub candidate: String,
+    pub violations: Vec<Violation>,
+}
+
+/// `text` without comments and with string literals emptied, line structure kept.
+fn code_only(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut block = 0usize;
+    let mut in_str = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if !in_str && chars.peek() == Some(&'-') => {
+                chars.next();
+                block += 1;
+            }
+            '-' if block > 0 && chars.peek() == Some(&'/') => {
+                chars.next();
+                block -= 1;
+            }
+            '-' if block == 0 && !in_str && chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            '\n' => out.push('\n'),
+            _ if block > 0 => {}
+            '"' => {
+                in_str = !in_str;
+                out.push('"');
+            }
+            '\\' if in_str => {
+                chars.next();
+            }
+            _ if in_str => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn words(line: &str) -> impl Iterator<Item = &str> {
+    line.split(|c: char| !(c.is_alphanumeric() || "_.'!?".contains(c)))
+        .filter(|w| !w.is_empty())
+}
+
+/// What is wrong with `cand` under `policy` (empty: nothing).
+pub fn check_candidate(cand: &str, policy: &SafetyPolicy) -> Vec<Violation> {
+    let code = code_only(cand);
+    let mut out: Vec<Violation> = Vec::new();
+    let mut push = |v: Violation| {
+        if !out.contains(&v) {
+            out.push(v);
+        }
+    };
+    for (i, line) in code.lines().enumerate() {
+        let ws: Vec<&str> = words(line).collect();
+        for (j, w) in ws.iter().enumerate() {
+            match *w {
+                "sorry" => push(Violation::Sorry),
+                "admit" => push(Violation::Admit),
+                "native_decide" if !policy.allow_native_decide => push(Violation::NativeDecide),
+                "axiom" => push(Violation::Axiom {
+                    name: ws.get(j + 1).unwrap_or(&"").to_string(),
+                }),
+                _ => {}
+            }
+        }
+        // The first line may carry `by` or a term; later lines starting a command leave the proof.
+        let t = line.trim_start();
+        let first = words(t).next().unwrap_or("");
+        if i > 0 && (t.starts_with("@[") || t.starts_with('#') || COMMANDS.contains(&first)) {
+            push(Violation::OutsideSpan {
+                detail: format!("line {} starts a command: {}", i + 1, t.trim_end()),
+            });
+        }
+    }
+    out
+}
+
+/// `Some` when `edit` does not turn `original` into `patched` or reaches outside
+/// `allowed` (byte range of the declaration being repaired).
+pub fn check_edit(
+    original: &str,
+    patched: &str,
+    edit: &EditRecord,
+    allowed: (usize, usize),
+) -> Option<Violation> {
+    let end = edit.byte_start + edit.old_text.len();
+    if edit.byte_start < allowed.0 || end > allowed.1 {
+        return Some(Violation::OutsideSpan {
+            detail: format!(
+                "bytes {}..{end} outside {}..{}",
+                edit.byte_start, allowed.0, allowed.1
+            ),
+        });
+    }
+    match edit.apply(original) {
+        Ok(p) if p == patched => None,
+        _ => Some(Violation::OutsideSpan {
+            detail: "the patched text differs from the recorded edit".to_string(),
+        }),
+    }
+}
+
+/// `patched` with `#print axioms <decl_name>` after the declaration, and the command's 1-based
+/// line (`None`: the declaration was not found; `patched` is returned as is).
+pub fn instrument(patched: &str, decl_name: &str) -> (String, Option<usize>) {
+    let Ok((_, end)) = crate::patching::decl_byte_range(patched, decl_name) else {
+        return (patched.to_string(), None);
+    };
+    let line = patched[..end].matches('\n').count() + 3;
+    let text = format!(
+        "{}\n\n#print axioms {decl_name}{}",
+        &patched[..end],
+        &patched[end..]
+    );
+    (text, Some(line))
+}
+
+/// The `#print axioms` result at `line` of the compiler `output` of an `instrument`ed text.
+pub fn axioms_in_output(output: &str, decl_name: &str, line: usize) -> Option<AxiomsReport> {
+    let q = [LeanQuery::PrintAxioms(decl_name.to_string())];
+    crate::lean_query::parse_query_output(output, &q, &[line])
+        .pop()
+        .and_then(|r| r.axioms)
+}
+
+/// Elaborate `text` with `#print axioms` for `decl_name` (one extra compile).
+pub async fn audit(
+    repo_root: &Path,
+    text: &str,
+    decl_name: &str,
+    timeout: Duration,
+) -> Result<AxiomsReport, String> {
+    let (probe, line) = instrument(text, decl_name);
+    let line = line.ok_or_else(|| format!("declaration {decl_name} not found"))?;
+    let out = crate::lean_query::elaborate(repo_root, &probe, timeout).await?;
+    axioms_in_output(&out, decl_name, line)
+        .ok_or_else(|| format!("#print axioms {decl_name}: no output"))
+}
+
+/// Axioms in `report` outside the standard ones and what `policy` allows, `sorryAx` included.
+pub fn disallowed_axioms(report: &AxiomsReport, policy: &SafetyPolicy) -> Vec<String> {
+    let mut out: Vec<String> = report
+        .nonstandard
+        .iter()
+        .filter(|a| !(policy.allow_native_decide && NATIVE_AXIOMS.contains(&a.as_str())))
+        .cloned()
+        .collect();
+    if report.uses_sorry {
+        out.push("sorryAx".to_string());
+    }
+    out
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AxiomCheck {
+    /// What the patched declaration depends on (`None`: the audit gave no result).
+    pub report: Option<AxiomsReport>,
+    pub violation: Option<Violation>,
+    /// Extra compiles the check cost.
+    #[serde(skip)]
+    pub checks: usize,
+}
+
+/// Confirm that the declaration `decl_name` of `patched` (compiled `instrument`ed, with
+/// `output`, the probe at `line`) depends on no axiom it may not; axioms the declaration already
+/// depended on in `original` are kept.
+#[allow(clippy::too_many_arguments)]
+pub async fn check_axioms(
+    repo_root: &Path,
+    original: &str,
+    patched: &str,
+    decl_name: &str,
+    output: &str,
+    line: Option<usize>,
+    policy: &SafetyPolicy,
+    timeout: Duration,
+) -> AxiomCheck {
+    let mut checks = 0;
+    let mut report = line.and_then(|l| axioms_in_output(output, decl_name, l));
+    if report.is_none() {
+        checks += 1;
+        report = audit(repo_root, patched, decl_name, timeout).await.ok();
+    }
+    let Some(report) = report else {
+        return AxiomCheck {
+            report: None,
+            violation: Some(Violation::AxiomsUnchecked),
+            checks,
+        };
+    };
+    let mut new = disallowed_axioms(&report, policy);
+    if new.iter().any(|a| a != "sorryAx") {
+        checks += 1;
+        if let Ok(before) = audit(repo_root, original, decl_name, timeout).await {
+            new.retain(|a| a == "sorryAx" || !before.axioms.contains(a));
+        }
+    }
+    AxiomCheck {
+        report: Some(report),
+        violation: (!new.is_empty()).then_some(Violation::NewAxioms { axioms: new }),
+        checks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_placeholders_axioms_and_escaping_commands() {
+        let strict = SafetyPolicy::default();
+        assert!(check_candidate("by\n  simp [foo]\n  omega", &strict).is_empty());
+        // Comments and strings do not count.
+        assert!(check_candidate(
+            "by\n  -- no sorry here\n  /- admit -/ simp\n  trace \"sorry\"",
+            &strict
+        )
+        .is_empty());
+        assert_eq!(
+            check_candidate("by\n  constructor <;> sorry", &strict),
+            [Violation::Sorry]
+        );
+        assert_eq!(
+            check_candidate("by\n  native_decide", &strict),
+            [Violation::NativeDecide]
+        );
+        assert!(check_candidate(
+            "by\n  native_decide",
+            &SafetyPolicy {
+                allow_native_decide: true
+            }
+        )
+        .is_empty());
+        let v = check_candidate("by\n  exact cheat\n\naxiom cheat : False", &strict);
+        assert!(v.contains(&Violation::Axiom {
+            name: "cheat".to_string()
+        }));
+        assert!(matches!(v.last(), Some(Violation::OutsideSpan { .. })));
+        assert!(matches!(
+            check_candidate("by\n  simp\n@[simp] theorem x : True := trivial", &strict)[..],
+            [Violation::OutsideSpan { .. }]
+        ));
+    }
+
+    #[test]
+    fn edits_stay_inside_the_declaration() {
+        let original = "theorem a : True := by\n  sorry\n\ntheorem b : True := trivial\n";
+        let start = original.find("sorry").unwrap();
+        let edit = EditRecord {
+            byte_start: start,
+            old_text: "sorry".into(),
+            new_text: "trivial".into(),
+            line: 2,
+        };
+        let patched = edit.apply(original).unwrap();
+        let decl = crate::patching::decl_byte_range(original, "a").unwrap();
+        assert_eq!(check_edit(original, &patched, &edit, decl), None);
+        assert!(check_edit(original, &patched, &edit, (0, start)).is_some());
+        assert!(check_edit(original, original, &edit, decl).is_some());
+    }
+
+    #[test]
+    fn reads_the_probe_and_keeps_allowed_axioms() {
+        let patched = "namespace X\ntheorem a : True := by\n  trivial\nend X\n";
+        let (probe, line) = instrument(patched, "a");
+        assert_eq!(line, Some(5));
+        assert_eq!(probe.lines().nth(4), Some("#print axioms a"));
+        assert_eq!(probe.lines().nth(5), Some("end X"));
+
+        let out = "f.lean:5:0: info: 'X.a' depends on axioms: [propext, Lean.ofReduceBool]\n";
+        let r = axioms_in_output(out, "a", 5).unwrap();
+        assert_eq!(
+            disallowed_axioms(&r, &SafetyPolicy::default()),
+            ["Lean.ofReduceBool"]
+        );
+        assert!(disallowed_axioms(
+            &r,
+            &SafetyPolicy {
+                allow_native_decide: true
+            }
+        )
+        .is_empty());
+        assert!(axioms_in_output(out, "a", 4).is_none());
+    }
+}
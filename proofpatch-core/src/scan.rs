@@ -0,0 +1,353 @@
+//! Repo-wide placeholder scanner.
+//!
+//! Walks every `.lean` file under a repo root and reports `sorry`, `admit`, and `stop` tokens with
+//! exact byte spans and their enclosing declaration. The result is a work queue of repair targets
+//! in deterministic (file, offset) order, which is the entry point for batch repair runs.
+//!
+//! Unlike `locate_sorries_in_text` (one hit per line, bounded excerpts for prompting), this scanner
+//! reports every occurrence and is span-exact, so patches can be applied without re-searching.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Placeholder tokens that leave a proof admitted.
+pub const PLACEHOLDER_TOKENS: &[&str] = &["sorry", "admit", "stop"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaceholderHit {
+    pub token: String,
+    /// Byte offset of the token in the file text (inclusive).
+    pub byte_start: usize,
+    /// Byte offset just past the token.
+    pub byte_end: usize,
+    /// 1-based line.
+    pub line: usize,
+    /// 1-based column (Unicode scalar values).
+    pub col: usize,
+}
+
+/// One unit of repair work.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairTarget {
+    /// Stable id: `<file>:<line>:<col>`.
+    pub id: String,
+    pub file: String,
+    pub token: String,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub line: usize,
+    pub col: usize,
+    pub decl_kind: Option<String>,
+    pub decl_name: Option<String>,
+    pub decl_line: Option<usize>,
+    pub line_text: String,
+    /// Estimated difficulty of the enclosing declaration (`ScanOptions::by_difficulty`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<crate::difficulty::Difficulty>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanReport {
+    pub repo_root: String,
+    pub files_scanned: usize,
+    pub files_with_targets: usize,
+    pub counts: BTreeMap<String, usize>,
+    pub targets: Vec<RepairTarget>,
+    /// Files we could not read (path, error).
+    pub errors: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// Which tokens to report (default: all of `PLACEHOLDER_TOKENS`).
+    pub tokens: Vec<String>,
+    /// Only scan files whose repo-relative path starts with one of these prefixes.
+    pub include_prefixes: Vec<String>,
+    pub max_targets: usize,
+    /// Order targets so that a module's targets come before those of modules importing it
+    /// (`import_graph::ImportGraph::schedule_files`); otherwise files are in path order.
+    pub topological: bool,
+    /// Estimate each target's difficulty (`difficulty`) and put the easiest first, before
+    /// `max_targets` is applied; ties keep the file order.
+    pub by_difficulty: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            tokens: PLACEHOLDER_TOKENS.iter().map(|s| s.to_string()).collect(),
+            include_prefixes: Vec::new(),
+            max_targets: 10_000,
+            topological: false,
+            by_difficulty: false,
+        }
+    }
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'\'' || b == b'.' || b >= 0x80
+}
+
+/// Find placeholder tokens outside comments and string literals.
+///
+/// Best-effort lexer (not a Lean parser): handles `--` line comments, nested `/- -/` block
+/// comments (including doc comments), and `"..."` strings with escapes. Tokens must be whole
+/// identifiers, so `sorryAx`, `Foo.sorry`, and `h_stop` do not match.
+pub fn find_placeholders(text: &str, tokens: &[String]) -> Vec<PlaceholderHit> {
+    let bs = text.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0usize;
+    let mut line = 1usize;
+    let mut line_start = 0usize;
+    let mut block_depth = 0usize;
+    let mut in_string = false;
+    let mut in_line_comment = false;
+
+    while i < bs.len() {
+        let b = bs[i];
+        if b == b'\n' {
+            line += 1;
+            line_start = i + 1;
+            in_line_comment = false;
+            i += 1;
+            continue;
+        }
+        if in_line_comment {
+            i += 1;
+            continue;
+        }
+        if in_string {
+            if b == b'\\' {
+                i += 2;
+                continue;
+            }
+            if b == b'"' {
+                in_string = false;
+            }
+            i += 1;
+            continue;
+        }
+        let next = bs.get(i + 1).copied();
+        if block_depth > 0 {
+            if b == b'/' && next == Some(b'-') {
+                block_depth += 1;
+                i += 2;
+            } else if b == b'-' && next == Some(b'/') {
+                block_depth -= 1;
+                i += 2;
+            } else {
+                i += 1;
+            }
+            continue;
+        }
+        if b == b'-' && next == Some(b'-') {
+            in_line_comment = true;
+            i += 2;
+            continue;
+        }
+        if b == b'/' && next == Some(b'-') {
+            block_depth = 1;
+            i += 2;
+            continue;
+        }
+        if b == b'"' {
+            in_string = true;
+            i += 1;
+            continue;
+        }
+        if is_ident_byte(b) {
+            let start = i;
+            while i < bs.len() && is_ident_byte(bs[i]) {
+                i += 1;
+            }
+            let word = &text[start..i];
+            if let Some(tok) = tokens.iter().find(|t| t.as_str() == word) {
+                out.push(PlaceholderHit {
+                    token: tok.clone(),
+                    byte_start: start,
+                    byte_end: i,
+                    line,
+                    col: text[line_start..start].chars().count() + 1,
+                });
+            }
+            continue;
+        }
+        i += 1;
+    }
+    out
+}
+
+/// List repo-relative `.lean` files, skipping build output and dot-directories.
+pub fn list_lean_files(repo_root: &Path) -> Vec<String> {
+    fn walk(root: &Path, dir: &Path, out: &mut Vec<String>) {
+        let Ok(rd) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for ent in rd.flatten() {
+            let p = ent.path();
+            let name = ent.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || name == "lake-packages" || name == "build" {
+                continue;
+            }
+            let Ok(ft) = ent.file_type() else {
+                continue;
+            };
+            if ft.is_dir() {
+                walk(root, &p, out);
+            } else if ft.is_file() && name.ends_with(".lean") {
+                if let Ok(rel) = p.strip_prefix(root) {
+                    out.push(rel.to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+    }
+    let mut out = Vec::new();
+    walk(repo_root, repo_root, &mut out);
+    out.sort();
+    out
+}
+
+/// Scan one file's text into repair targets.
+pub fn scan_text(file_rel: &str, text: &str, tokens: &[String]) -> Vec<RepairTarget> {
+    let lines: Vec<&str> = text.lines().collect();
+    find_placeholders(text, tokens)
+        .into_iter()
+        .map(|h| {
+            let decl = crate::nearest_decl_header_in_text(text, h.line, 20_000);
+            RepairTarget {
+                id: format!("{file_rel}:{}:{}", h.line, h.col),
+                file: file_rel.to_string(),
+                token: h.token,
+                byte_start: h.byte_start,
+                byte_end: h.byte_end,
+                line: h.line,
+                col: h.col,
+                decl_kind: decl.as_ref().map(|d| d.kind.clone()),
+                decl_name: decl.as_ref().map(|d| d.name.clone()),
+                decl_line: decl.as_ref().map(|d| d.line),
+                line_text: lines.get(h.line - 1).unwrap_or(&"").to_string(),
+                difficulty: None,
+            }
+        })
+        .collect()
+}
+
+/// Walk the repo and build a work queue of repair targets.
+pub fn scan_repo(repo_root: &Path, opts: &ScanOptions) -> Result<ScanReport, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let mut files = list_lean_files(&repo_root);
+    if !opts.include_prefixes.is_empty() {
+        files.retain(|f| opts.include_prefixes.iter().any(|p| f.starts_with(p)));
+    }
+    if opts.topological {
+        files = crate::import_graph::ImportGraph::build(&repo_root)?.schedule_files(&files);
+    }
+    let mut report = ScanReport {
+        repo_root: repo_root.display().to_string(),
+        files_scanned: 0,
+        files_with_targets: 0,
+        counts: BTreeMap::new(),
+        targets: Vec::new(),
+        errors: Vec::new(),
+    };
+    let history = if opts.by_difficulty {
+        crate::history::load(&repo_root)
+    } else {
+        Vec::new()
+    };
+    let runs = crate::difficulty::runs_by_decl(&history);
+    for f in files {
+        let text = match std::fs::read_to_string(repo_root.join(&f)) {
+            Ok(t) => t,
+            Err(e) => {
+                report.errors.push((f, e.to_string()));
+                continue;
+            }
+        };
+        report.files_scanned += 1;
+        let ts = scan_text(&f, &text, &opts.tokens);
+        if !ts.is_empty() {
+            report.files_with_targets += 1;
+        }
+        for mut t in ts {
+            *report.counts.entry(t.token.clone()).or_insert(0) += 1;
+            if opts.by_difficulty {
+                t.difficulty = t
+                    .decl_name
+                    .as_deref()
+                    .and_then(|d| crate::difficulty::assess(&text, &f, d, &runs));
+                report.targets.push(t);
+            } else if report.targets.len() < opts.max_targets {
+                report.targets.push(t);
+            }
+        }
+    }
+    if opts.by_difficulty {
+        // Targets outside any declaration last.
+        report.targets.sort_by(|a, b| {
+            let score = |t: &RepairTarget| t.difficulty.as_ref().map_or(f64::INFINITY, |d| d.score);
+            score(a).total_cmp(&score(b))
+        });
+        report.targets.truncate(opts.max_targets);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_tokens() -> Vec<String> {
+        ScanOptions::default().tokens
+    }
+
+    #[test]
+    fn skips_comments_strings_and_identifier_parts() {
+        let text = "/- sorry /- nested sorry -/ still comment -/\n\
+                    theorem foo : True := by\n  -- admit\n  have h := \"sorry\"\n  exact sorryAx _\n\
+                    theorem bar : True := by\n  skip; sorry\n";
+        let hits = find_placeholders(text, &all_tokens());
+        assert_eq!(hits.len(), 1);
+        let h = &hits[0];
+        assert_eq!((h.line, h.col), (7, 9));
+        assert_eq!(&text[h.byte_start..h.byte_end], "sorry");
+    }
+
+    #[test]
+    fn reports_every_hit_with_enclosing_decl() {
+        let text = "theorem a : 1 = 1 := by\n  stop\n  admit\nlemma b (x : ℕ) : x = x := sorry\n";
+        let ts = scan_text("Foo/A.lean", text, &all_tokens());
+        let got: Vec<(&str, Option<&str>)> = ts
+            .iter()
+            .map(|t| (t.token.as_str(), t.decl_name.as_deref()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("stop", Some("a")),
+                ("admit", Some("a")),
+                ("sorry", Some("b"))
+            ]
+        );
+        assert_eq!(ts[2].id, "Foo/A.lean:4:28");
+    }
+
+    #[test]
+    fn anonymous_decls_are_addressed_by_line() {
+        let text = "theorem a : True := trivial\n\nexample (n : ℕ) : n = n := by\n  sorry\n\ninstance : Inhabited ℕ := sorry\n";
+        let ts = scan_text("Foo/A.lean", text, &all_tokens());
+        let names: Vec<Option<&str>> = ts.iter().map(|t| t.decl_name.as_deref()).collect();
+        assert_eq!(names, vec![Some("example@3"), Some("instance@6")]);
+
+        let target = crate::patching::PatchTarget::DeclPlaceholder("example@3".to_string());
+        let (out, _) = crate::patching::apply_patch(text, &target, "rfl").unwrap();
+        assert!(out.contains("n = n := by\n  rfl\n\ninstance"));
+        let (s, e) = crate::patching::decl_byte_range(text, "instance@6").unwrap();
+        assert_eq!(&text[s..e], "instance : Inhabited ℕ := sorry");
+        assert!(crate::patching::decl_byte_range(text, "example@1").is_err());
+        assert!(crate::extract_decl_block(text, "example@3")
+            .unwrap()
+            .starts_with("example (n : ℕ)"));
+    }
+}
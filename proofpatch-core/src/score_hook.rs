@@ -0,0 +1,250 @@
+//! User scoring hooks: repo-specific adjustments to candidate scores, in a script.
+//!
+//! A `ScoreHook` sees each ranked candidate and returns a number that is added to its total
+//! (`CandidateScore::script`), e.g. to penalize `nlinarith` in a repo where it always times out.
+//! The repair loop applies the hook configured with `[ranking] score_script` to every round's
+//! candidates after the reranker, then re-sorts them.
+//!
+//! Scripts are Rhai (cargo feature `scripting`) and define `adjust(c)`, where `c` is a map with
+//! the candidate's `source`, `candidate` text, `head` tactic, `goal` (empty when unknown),
+//! `smt_entails` (1, −1, or 0 when unknown), and the signals of its score (`smt`, `validity`,
+//! `compile`, `length`, `lint`, `confidence`, `rerank`, `total`). It returns the adjustment as
+//! a number (`()` counts as 0):
+//!
+//! ```text
+//! fn adjust(c) {
+//!     if c.head == "nlinarith" { -2.0 } else { 0.0 }
+//! }
+//! ```
+//!
+//! Scripts run with an operation limit (`MAX_OPERATIONS`) and without file or network access;
+//! a script error fails the repair, like a broken reranker model.
+
+use crate::ranking::{RankContext, Ranked};
+use std::path::Path;
+
+/// Operations a script may run per candidate.
+pub const MAX_OPERATIONS: u64 = 100_000;
+
+/// What a hook sees of a candidate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookInput {
+    pub source: String,
+    pub candidate: String,
+    pub head: String,
+    pub goal: String,
+    pub smt_entails: i64,
+    /// `(name, value)` of every signal, then `("total", …)`.
+    pub signals: Vec<(&'static str, f64)>,
+}
+
+impl HookInput {
+    pub fn new(r: &Ranked, ctx: &RankContext) -> Self {
+        let s = &r.score;
+        Self {
+            source: r.source.clone(),
+            candidate: r.candidate.clone(),
+            head: crate::priors::family(&r.candidate),
+            goal: ctx.goal.clone().unwrap_or_default(),
+            smt_entails: match ctx.smt_entails {
+                Some(true) => 1,
+                Some(false) => -1,
+                None => 0,
+            },
+            signals: vec![
+                ("smt", s.smt),
+                ("validity", s.validity),
+                ("compile", s.compile),
+                ("length", s.length),
+                ("lint", s.lint),
+                ("confidence", s.confidence),
+                ("rerank", s.rerank),
+                ("total", s.total),
+            ],
+        }
+    }
+}
+
+/// Adjusts a candidate's score; the result is added to its total.
+pub trait ScoreHook: Send + Sync {
+    fn adjust(&self, input: &HookInput) -> Result<f64, String>;
+}
+
+/// Add `hook`'s adjustment to each of `ranked` and re-sort it.
+pub fn apply(ranked: &mut [Ranked], ctx: &RankContext, hook: &dyn ScoreHook) -> Result<(), String> {
+    if ranked.is_empty() {
+        return Ok(());
+    }
+    for r in ranked.iter_mut() {
+        let d = hook.adjust(&HookInput::new(r, ctx))?;
+        if !d.is_finite() {
+            return Err(format!("score script: {d} for {:?}", r.candidate));
+        }
+        r.score.script = d;
+        r.score.total += d;
+    }
+    crate::ranking::sort(ranked);
+    Ok(())
+}
+
+#[cfg(feature = "scripting")]
+pub struct RhaiHook {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+}
+
+#[cfg(feature = "scripting")]
+impl RhaiHook {
+    pub fn compile(script: &str) -> Result<Self, String> {
+        let mut engine = rhai::Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile(script)
+            .map_err(|e| format!("score script: {e}"))?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == "adjust" && f.params.len() == 1)
+        {
+            return Err("score script: no `fn adjust(c)`".to_string());
+        }
+        Ok(Self { engine, ast })
+    }
+}
+
+#[cfg(feature = "scripting")]
+impl ScoreHook for RhaiHook {
+    fn adjust(&self, input: &HookInput) -> Result<f64, String> {
+        let mut c = rhai::Map::new();
+        c.insert("source".into(), input.source.clone().into());
+        c.insert("candidate".into(), input.candidate.clone().into());
+        c.insert("head".into(), input.head.clone().into());
+        c.insert("goal".into(), input.goal.clone().into());
+        c.insert("smt_entails".into(), input.smt_entails.into());
+        for (k, v) in &input.signals {
+            c.insert((*k).into(), (*v).into());
+        }
+        let out: rhai::Dynamic = self
+            .engine
+            .call_fn(&mut rhai::Scope::new(), &self.ast, "adjust", (c,))
+            .map_err(|e| format!("score script: {e}"))?;
+        if out.is_unit() {
+            Ok(0.0)
+        } else if let Ok(f) = out.as_float() {
+            Ok(f)
+        } else if let Ok(i) = out.as_int() {
+            Ok(i as f64)
+        } else {
+            Err(format!(
+                "score script: `adjust` returned {} (expected a number)",
+                out.type_name()
+            ))
+        }
+    }
+}
+
+/// Load the script at `path`.
+pub fn load(path: &Path) -> Result<Box<dyn ScoreHook>, String> {
+    #[cfg(feature = "scripting")]
+    {
+        let script = std::fs::read_to_string(path)
+            .map_err(|e| format!("score script {}: {e}", path.display()))?;
+        RhaiHook::compile(&script).map(|h| Box::new(h) as Box<dyn ScoreHook>)
+    }
+    #[cfg(not(feature = "scripting"))]
+    return Err(format!(
+        "score script {}: proofpatch was built without the `scripting` cargo feature",
+        path.display()
+    ));
+}
+
+/// The hook named by the repo's `[ranking] score_script`, if any.
+pub fn configured(repo_root: &Path) -> Result<Option<Box<dyn ScoreHook>>, String> {
+    let script = crate::config::load_from_repo_root(repo_root)
+        .ok()
+        .flatten()
+        .and_then(|c| c.ranking.score_script)
+        .filter(|s| !s.trim().is_empty());
+    match script {
+        Some(s) => load(&repo_root.join(s)).map(Some),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ranking::Weights;
+
+    struct PenalizeNlinarith;
+
+    impl ScoreHook for PenalizeNlinarith {
+        fn adjust(&self, input: &HookInput) -> Result<f64, String> {
+            Ok(if input.head == "nlinarith" {
+                -10.0
+            } else {
+                0.0
+            })
+        }
+    }
+
+    #[test]
+    fn adjustments_enter_the_total_and_the_order() {
+        let ctx = RankContext::default();
+        let w = Weights::default();
+        let mut ranked = crate::ranking::rank(
+            vec![
+                (
+                    "llm".to_string(),
+                    "by\n  nlinarith [sq_nonneg x]".to_string(),
+                ),
+                ("heuristic".to_string(), "by\n  positivity".to_string()),
+            ],
+            &ctx,
+            &w,
+        );
+        assert_eq!(ranked[0].source, "llm");
+        let before = ranked[0].score.total;
+        apply(&mut ranked, &ctx, &PenalizeNlinarith).unwrap();
+        assert_eq!(ranked[0].candidate, "by\n  positivity");
+        assert_eq!(ranked[1].score.script, -10.0);
+        assert_eq!(ranked[1].score.total, before - 10.0);
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn rhai_script_sees_the_candidate() {
+        let hook = RhaiHook::compile(
+            r#"fn adjust(c) {
+                if c.head == "nlinarith" { return -2.5; }
+                if c.source == "heuristic" && c.validity > 0.5 { 1 }
+            }"#,
+        )
+        .unwrap();
+        let ctx = RankContext::default();
+        let ranked = crate::ranking::rank(
+            vec![
+                ("llm".to_string(), "by\n  nlinarith".to_string()),
+                ("heuristic".to_string(), "by\n  simp".to_string()),
+                ("llm".to_string(), "by\n  simp".to_string()),
+            ],
+            &ctx,
+            &Weights::default(),
+        );
+        let got: Vec<f64> = ranked
+            .iter()
+            .map(|r| hook.adjust(&HookInput::new(r, &ctx)).unwrap())
+            .collect();
+        let want: Vec<f64> = ranked
+            .iter()
+            .map(|r| match (r.source.as_str(), r.candidate.as_str()) {
+                (_, "by\n  nlinarith") => -2.5,
+                ("heuristic", _) => 1.0,
+                _ => 0.0,
+            })
+            .collect();
+        assert_eq!(got, want);
+        assert!(RhaiHook::compile("fn other(c) { 0 }").is_err());
+        let looping = RhaiHook::compile("fn adjust(c) { loop {} }").unwrap();
+        assert!(looping.adjust(&HookInput::new(&ranked[0], &ctx)).is_err());
+    }
+}
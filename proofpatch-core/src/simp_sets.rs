@@ -0,0 +1,319 @@
+//! `simp only [...]` / `aesop (add ...)` candidates from retrieved lemmas, with pruning.
+//!
+//! Lemmas whose conclusion is an equation or iff (or that have no hypotheses) go into the simp
+//! set; the rest become `unsafe apply` rules for `aesop`. Lemmas sharing vocabulary with the goal
+//! are listed first.
+//!
+//! A long lemma list that closes the goal is brittle and slow, so `prune_lemmas` shrinks it: it
+//! re-verifies the invocation with chunks of the list removed (halves, then quarters, … down to
+//! single lemmas, the usual delta-debugging schedule) and keeps every removal that still
+//! compiles. Each check is one `verify_lean_text` call, bounded by `PruneOptions::max_checks`.
+
+use crate::diagnostics::{parse_diagnostics_from, Severity};
+use crate::lemma_search::LemmaHit;
+use crate::patching::{apply_patch, PatchTarget};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    /// Rewrite rule (`simp [h]`, `aesop (add simp h)`).
+    Simp,
+    /// Backward rule (`aesop (add unsafe 50% apply h)`).
+    Apply,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimpLemma {
+    pub name: String,
+    pub kind: RuleKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimpForm {
+    SimpOnly,
+    Simp,
+    SimpAll,
+    Aesop,
+}
+
+impl SimpForm {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().replace('_', "-").as_str() {
+            "simp-only" => Some(Self::SimpOnly),
+            "simp" => Some(Self::Simp),
+            "simp-all" => Some(Self::SimpAll),
+            "aesop" => Some(Self::Aesop),
+            _ => None,
+        }
+    }
+
+    /// The tactic line (no leading `by`). `simp` forms use every lemma as a rewrite rule.
+    pub fn render(&self, lemmas: &[SimpLemma]) -> String {
+        let all = lemmas
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        match self {
+            Self::SimpOnly => format!("simp only [{all}]"),
+            Self::Simp if lemmas.is_empty() => "simp".to_string(),
+            Self::Simp => format!("simp [{all}]"),
+            Self::SimpAll if lemmas.is_empty() => "simp_all".to_string(),
+            Self::SimpAll => format!("simp_all [{all}]"),
+            Self::Aesop if lemmas.is_empty() => "aesop".to_string(),
+            Self::Aesop => {
+                let rules: Vec<String> = lemmas
+                    .iter()
+                    .map(|l| match l.kind {
+                        RuleKind::Simp => format!("simp {}", l.name),
+                        RuleKind::Apply => format!("unsafe 50% apply {}", l.name),
+                    })
+                    .collect();
+                format!("aesop (add {})", rules.join(", "))
+            }
+        }
+    }
+}
+
+/// Classify a lemma by its statement (`∀ …, a = b` → simp; `p → q` or `(h : p) : q` → apply).
+pub fn classify(type_signature: &str) -> RuleKind {
+    let concl = type_signature.rsplit('→').next().unwrap_or("");
+    let has_hyps = type_signature.contains('→') || type_signature.contains("(h");
+    if concl.contains(" = ") || concl.contains(" ↔ ") || !has_hyps {
+        RuleKind::Simp
+    } else {
+        RuleKind::Apply
+    }
+}
+
+/// Deduplicated lemmas, those sharing the most vocabulary with `goal` first (stable otherwise).
+pub fn select_lemmas(goal: Option<&str>, hits: &[LemmaHit], max: usize) -> Vec<SimpLemma> {
+    let goal_toks: HashSet<String> = goal
+        .map(|g| crate::premise::premise_tokens(g).into_iter().collect())
+        .unwrap_or_default();
+    let mut seen = HashSet::new();
+    let mut scored: Vec<(usize, SimpLemma)> = hits
+        .iter()
+        .filter(|h| !h.name.trim().is_empty() && seen.insert(h.name.clone()))
+        .map(|h| {
+            let overlap =
+                crate::premise::premise_tokens(&format!("{} {}", h.name, h.type_signature))
+                    .into_iter()
+                    .filter(|t| goal_toks.contains(t))
+                    .collect::<HashSet<_>>()
+                    .len();
+            (
+                overlap,
+                SimpLemma {
+                    name: h.name.clone(),
+                    kind: classify(&h.type_signature),
+                },
+            )
+        })
+        .collect();
+    scored.sort_by_key(|s| std::cmp::Reverse(s.0));
+    scored.into_iter().take(max).map(|(_, l)| l).collect()
+}
+
+/// Candidate scripts (`by\n  …`): `simp only`, `simp`, `simp_all`, and `aesop (add …)`.
+pub fn simp_candidates(goal: Option<&str>, hits: &[LemmaHit], max_lemmas: usize) -> Vec<String> {
+    let lemmas = select_lemmas(goal, hits, max_lemmas);
+    if lemmas.is_empty() {
+        return Vec::new();
+    }
+    let out = [
+        SimpForm::SimpOnly,
+        SimpForm::Simp,
+        SimpForm::SimpAll,
+        SimpForm::Aesop,
+    ]
+    .iter()
+    .map(|f| format!("by\n  {}", f.render(&lemmas)))
+    .collect();
+    crate::tree_search::sanitize_candidates(out)
+}
+
+#[derive(Debug, Clone)]
+pub struct PruneOptions {
+    pub timeout: Duration,
+    /// Total `verify_lean_text` calls, including the initial check of the full list.
+    pub max_checks: usize,
+}
+
+impl Default for PruneOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(120),
+            max_checks: 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PruneReport {
+    pub form: SimpForm,
+    /// The full list closed the goal.
+    pub initial_ok: bool,
+    pub kept: Vec<SimpLemma>,
+    pub removed: Vec<SimpLemma>,
+    pub checks: usize,
+    /// `kept` has not been minimized all the way (budget ran out).
+    pub budget_exhausted: bool,
+    /// Tactic line for `kept` (only meaningful when `initial_ok`).
+    pub script: String,
+}
+
+/// Delta-debugging over `items`: remove chunks while `check` still passes.
+///
+/// `check` returns `None` when the budget is gone. Returns the kept items and whether the budget
+/// ran out before single-item granularity was finished.
+pub(crate) async fn ddmin<T: Clone, F, Fut>(items: Vec<T>, mut check: F) -> (Vec<T>, bool)
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: std::future::Future<Output = Option<bool>>,
+{
+    let mut keep = items;
+    let mut chunk = keep.len().div_ceil(2).max(1);
+    loop {
+        let mut i = 0usize;
+        while i < keep.len() && !keep.is_empty() {
+            let end = (i + chunk).min(keep.len());
+            let mut trial = keep[..i].to_vec();
+            trial.extend_from_slice(&keep[end..]);
+            match check(trial.clone()).await {
+                None => return (keep, true),
+                Some(true) => keep = trial,
+                Some(false) => i = end,
+            }
+        }
+        if chunk == 1 {
+            return (keep, false);
+        }
+        chunk = chunk.div_ceil(2);
+    }
+}
+
+/// Prune the lemma list of `form` at `target` in `text` (a file of the repo at `repo_root`).
+pub async fn prune_lemmas(
+    repo_root: &Path,
+    text: &str,
+    target: &PatchTarget,
+    form: SimpForm,
+    lemmas: &[SimpLemma],
+    opts: &PruneOptions,
+) -> Result<PruneReport, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let (start, _) = crate::patching::resolve_target(text, target)?;
+    let line = text[..start].matches('\n').count() + 1;
+    let line_text = text.lines().nth(line - 1).unwrap_or("");
+    let tactic_ctx = crate::is_tactic_context_for_sorry(text, line, line_text);
+
+    let checks = std::cell::Cell::new(0usize);
+    let run = |subset: Vec<SimpLemma>| {
+        let repo_root = repo_root.clone();
+        let checks = &checks;
+        async move {
+            if checks.get() >= opts.max_checks {
+                return None;
+            }
+            checks.set(checks.get() + 1);
+            let tac = form.render(&subset);
+            let replacement = if tactic_ctx { tac } else { format!("by {tac}") };
+            let (patched, rec) = apply_patch(text, target, &replacement).ok()?;
+            let vr = crate::verify_lean_text(&repo_root, &patched, opts.timeout)
+                .await
+                .ok()?;
+            let errors = parse_diagnostics_from(&vr.stdout, &vr.stderr)
+                .into_iter()
+                .filter(|d| d.severity == Severity::Error)
+                .count();
+            let merged = format!("{}\n{}", vr.stdout, vr.stderr);
+            // The edited line itself must not be admitted (a `sorry` elsewhere in the file is
+            // fine: it predates us).
+            let admitted = merged.lines().any(|l| {
+                l.contains(&format!(":{}:", rec.line)) && l.contains("declaration uses 'sorry'")
+            });
+            Some(vr.ok && errors == 0 && !admitted)
+        }
+    };
+
+    let initial_ok = run(lemmas.to_vec()).await.unwrap_or(false);
+    let (kept, budget_exhausted) = if initial_ok {
+        ddmin(lemmas.to_vec(), run).await
+    } else {
+        (lemmas.to_vec(), false)
+    };
+    let removed = lemmas
+        .iter()
+        .filter(|l| !kept.contains(l))
+        .cloned()
+        .collect();
+    Ok(PruneReport {
+        form,
+        initial_ok,
+        script: form.render(&kept),
+        kept,
+        removed,
+        checks: checks.get(),
+        budget_exhausted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(name: &str, ty: &str) -> LemmaHit {
+        LemmaHit {
+            name: name.to_string(),
+            type_signature: ty.to_string(),
+            module: None,
+            doc: None,
+            source: "test".to_string(),
+            score: None,
+        }
+    }
+
+    #[test]
+    fn renders_forms_and_orders_by_goal_overlap() {
+        let hits = vec![
+            hit(
+                "List.length_append",
+                "∀ (as bs : List α), (as ++ bs).length = as.length + bs.length",
+            ),
+            hit("Nat.succ_le_of_lt", "n < m → n.succ ≤ m"),
+            hit("Nat.add_comm", "∀ (n m : ℕ), n + m = m + n"),
+        ];
+        let ls = select_lemmas(Some("⊢ n + m ≤ m.succ + n"), &hits, 10);
+        assert_eq!(ls[0].name, "Nat.succ_le_of_lt");
+        assert_eq!(ls[0].kind, RuleKind::Apply);
+        assert_eq!(ls[2].name, "List.length_append");
+        assert_eq!(
+            SimpForm::Aesop.render(&ls[..2]),
+            "aesop (add unsafe 50% apply Nat.succ_le_of_lt, simp Nat.add_comm)"
+        );
+        let cands = simp_candidates(None, &hits[2..], 4);
+        assert_eq!(cands[0], "by\n  simp only [Nat.add_comm]");
+        assert_eq!(cands.len(), 4);
+    }
+
+    #[test]
+    fn ddmin_keeps_only_needed_items() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let items: Vec<u32> = (0..8).collect();
+        let calls = std::cell::Cell::new(0);
+        let (kept, exhausted) = rt.block_on(ddmin(items, |s: Vec<u32>| {
+            calls.set(calls.get() + 1);
+            async move { Some(s.contains(&2) && s.contains(&5)) }
+        }));
+        assert_eq!(kept, vec![2, 5]);
+        assert!(!exhausted && calls.get() < 16);
+    }
+}
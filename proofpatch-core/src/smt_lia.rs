@@ -7,7 +7,7 @@
 //! Soundness posture: this is a *heuristic signal* for ranking / candidate selection.
 //! It must never be used as a proof of a Lean goal without verification.
 
-use regex::Regex;
+use crate::goal_ast::{self, BinOp, Expr, UnOp};
 use serde_json::Value;
 use std::hash::{Hash, Hasher};
 use std::sync::OnceLock;
@@ -367,24 +367,21 @@ fn sanitize_name(s: &str) -> String {
     out
 }
 
-fn extract_decl_kind(hyp_text: &str) -> Option<(String, VarKind)> {
-    // Recognize tiny declaration shapes like:
+fn extract_decl_kinds(hyp_text: &str) -> Vec<(String, VarKind)> {
+    // Recognize declarations of integer-sorted locals:
     // - `n : ℕ` / `n : Nat`
-    // - `m : ℤ` / `m : Int`
-    let (name, ty) = hyp_text.split_once(':')?;
-    let name = name.trim();
-    let ty = ty.trim();
-    if name.is_empty() || ty.is_empty() {
-        return None;
-    }
-    let kind = if ty.contains('ℕ') || ty.contains("Nat") {
+    // - `a b : ℤ` / `m : Int`
+    let Ok(h) = goal_ast::parse_hyp(hyp_text) else {
+        return Vec::new();
+    };
+    let kind = if h.ty.is_nat_type() {
         VarKind::Nat
-    } else if ty.contains('ℤ') || ty.contains("Int") {
+    } else if h.ty.is_int_type() {
         VarKind::Int
     } else {
-        return None;
+        return Vec::new();
     };
-    Some((sanitize_name(name), kind))
+    h.names.iter().map(|n| (sanitize_name(n), kind)).collect()
 }
 
 #[derive(Debug, Clone)]
@@ -410,62 +407,306 @@ struct ParsedRel {
     rhs: LinearExpr,
 }
 
-fn parse_linear_expr_int(s: &str) -> Option<LinearExpr> {
-    // Small parser: sums/differences of identifiers and integer literals.
-    // Rejects obvious non-LIA operators.
-    let bad = ['*', '/', '^', '·', '↑', '∑', '∏'];
-    if s.chars().any(|c| bad.contains(&c)) {
-        return None;
+fn linear_scale(e: &mut LinearExpr, k: i64) {
+    e.c0 = e.c0.saturating_mul(k);
+    for c in e.coeffs.values_mut() {
+        *c = c.saturating_mul(k);
     }
-    let mut coeffs: std::collections::BTreeMap<String, i64> = std::collections::BTreeMap::new();
-    let mut c0: i64 = 0;
-    let mut i = 0usize;
-    let chars: Vec<char> = s.chars().collect();
-    let mut sign: i64 = 1;
-    while i < chars.len() {
-        let ch = chars[i];
-        if ch.is_whitespace() {
-            i += 1;
-            continue;
+}
+
+fn linear_add(a: &mut LinearExpr, b: LinearExpr) {
+    a.c0 = a.c0.saturating_add(b.c0);
+    for (v, c) in b.coeffs {
+        let e = a.coeffs.entry(v).or_insert(0);
+        *e = e.saturating_add(c);
+    }
+}
+
+/// Maps the spellings of a name seen in one goal to a single canonical one.
+///
+/// The pretty printer can show a local as `n` in one place and `Foo.n` in another (section
+/// variables, auto-bound names), and constants either fully qualified or relative to an open
+/// namespace. Without this, `sanitize_name` would turn each spelling into a distinct variable.
+#[derive(Debug, Clone, Default)]
+struct NameResolver {
+    locals: std::collections::BTreeSet<String>,
+    /// Longest first, so the most specific prefix is stripped.
+    namespaces: Vec<String>,
+}
+
+impl NameResolver {
+    /// Locals from the goal's hypothesis list; namespaces from an optional `open_namespaces`
+    /// array (added by `goal_dump_in_text_at`).
+    fn from_pp_dump(pp_dump: &Value) -> Self {
+        let mut locals = std::collections::BTreeSet::new();
+        let goal = pp_dump
+            .get("goals")
+            .and_then(|v| v.as_array())
+            .and_then(|a| a.first());
+        for h in goal
+            .and_then(|g| g.get("hyps"))
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+        {
+            if let Some((names, _)) = h
+                .get("text")
+                .and_then(|v| v.as_str())
+                .and_then(|t| t.split_once(':'))
+            {
+                locals.extend(names.split_whitespace().map(str::to_string));
+            }
         }
-        if ch == '+' {
-            sign = 1;
-            i += 1;
-            continue;
+        let mut namespaces: Vec<String> = pp_dump
+            .get("open_namespaces")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect();
+        namespaces.sort_by_key(|n| std::cmp::Reverse(n.len()));
+        Self { locals, namespaces }
+    }
+
+    fn resolve<'a>(&self, name: &'a str) -> std::borrow::Cow<'a, str> {
+        if self.locals.contains(name) || !name.contains('.') {
+            return name.into();
+        }
+        // `Foo.n` for a local `n`: only when the qualifier looks like a namespace (so projections
+        // such as `h.le` or `p.1` are left alone).
+        if let Some((qual, last)) = name.rsplit_once('.') {
+            let ns_like = qual
+                .split('.')
+                .all(|p| p.chars().next().is_some_and(char::is_uppercase))
+                || self.namespaces.iter().any(|n| n == qual);
+            if ns_like && self.locals.contains(last) {
+                return last.to_string().into();
+            }
         }
-        if ch == '-' {
-            sign = -1;
-            i += 1;
-            continue;
+        for ns in &self.namespaces {
+            if let Some(rest) = name
+                .strip_prefix(ns.as_str())
+                .and_then(|r| r.strip_prefix('.'))
+            {
+                if !rest.is_empty() && !self.locals.contains(rest) {
+                    return rest.to_string().into();
+                }
+            }
         }
-        if ch.is_ascii_digit() {
-            let mut j = i + 1;
-            while j < chars.len() && chars[j].is_ascii_digit() {
-                j += 1;
+        name.into()
+    }
+
+    /// Rewrite every identifier in `text`.
+    fn rewrite(&self, text: &str) -> String {
+        let is_id = |c: char| c.is_alphanumeric() || matches!(c, '_' | '.' | '\'' | '!' | '?');
+        let mut out = String::with_capacity(text.len());
+        let mut cur = String::new();
+        let flush = |cur: &mut String, out: &mut String| {
+            if !cur.is_empty() {
+                let id = cur.trim_end_matches('.');
+                out.push_str(&self.resolve(id));
+                out.push_str(&cur[id.len()..]);
+                cur.clear();
+            }
+        };
+        for c in text.chars() {
+            if is_id(c) {
+                cur.push(c);
+            } else {
+                flush(&mut cur, &mut out);
+                out.push(c);
             }
-            let lit: String = chars[i..j].iter().collect();
-            let v: i64 = lit.parse().ok()?;
-            c0 = c0.saturating_add(sign.saturating_mul(v));
-            i = j;
-            continue;
         }
-        if ch.is_alphanumeric() || ch == '_' || ch == '.' {
-            let mut j = i + 1;
-            while j < chars.len()
-                && (chars[j].is_alphanumeric() || chars[j] == '_' || chars[j] == '.')
-            {
-                j += 1;
+        flush(&mut cur, &mut out);
+        out
+    }
+}
+
+/// Copy of `pp_dump` with names in the first goal's `pretty` and hypotheses canonicalized (see
+/// `NameResolver`). A no-op for goals without qualified names.
+pub fn normalize_pp_dump_names(pp_dump: &Value) -> Value {
+    let r = NameResolver::from_pp_dump(pp_dump);
+    let mut out = pp_dump.clone();
+    let Some(goal) = out
+        .get_mut("goals")
+        .and_then(|v| v.as_array_mut())
+        .and_then(|a| a.first_mut())
+    else {
+        return out;
+    };
+    if let Some(p) = goal
+        .get("pretty")
+        .and_then(|v| v.as_str())
+        .map(|p| r.rewrite(p))
+    {
+        goal["pretty"] = Value::String(p);
+    }
+    if let Some(hyps) = goal.get_mut("hyps").and_then(|v| v.as_array_mut()) {
+        for h in hyps {
+            if let Some(t) = h.get("text").and_then(|v| v.as_str()).map(|t| r.rewrite(t)) {
+                h["text"] = Value::String(t);
             }
-            let raw: String = chars[i..j].iter().collect();
-            let name = sanitize_name(&raw);
-            *coeffs.entry(name.clone()).or_insert(0) =
-                coeffs.get(&name).copied().unwrap_or(0).saturating_add(sign);
-            i = j;
-            continue;
         }
-        return None;
     }
-    Some(LinearExpr { coeffs, c0 })
+    out
+}
+
+/// Prefix of the constants standing in for metavariables (see `linear_from_expr`).
+const METAVAR_PREFIX: &str = "mvar_";
+
+fn is_metavar(name: &str) -> bool {
+    name.starts_with('?')
+}
+
+fn metavar_const(text: &str) -> String {
+    format!(
+        "{METAVAR_PREFIX}{}",
+        sanitize_name(text.trim_start_matches('?'))
+    )
+}
+
+/// Metavariables (`?m.12`, `?a`) mentioned anywhere in the first goal of a `pp_dump`.
+pub fn metavars_in_pp_dump(pp_dump: &Value) -> Vec<String> {
+    let pretty = pp_dump
+        .get("goals")
+        .and_then(|v| v.as_array())
+        .and_then(|a| a.first())
+        .and_then(|g| g.get("pretty"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    scan_metavars(pretty).into_iter().collect()
+}
+
+/// Lexical scan (tolerates text the expression parser rejects): `?` + identifier.
+fn scan_metavars(text: &str) -> std::collections::BTreeSet<String> {
+    let mut out = std::collections::BTreeSet::new();
+    let mut rest = text;
+    while let Some(i) = rest.find('?') {
+        let tail = &rest[i + 1..];
+        let end = tail
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '\'')))
+            .unwrap_or(tail.len());
+        let id = tail[..end].trim_end_matches('.');
+        if id.chars().next().is_some_and(char::is_alphabetic) {
+            out.insert(format!("?{id}"));
+        }
+        rest = tail;
+    }
+    out
+}
+
+/// True if the goal's target mentions a metavariable.
+fn target_has_metavar(pp_dump: &Value) -> bool {
+    let pretty = pp_dump
+        .get("goals")
+        .and_then(|v| v.as_array())
+        .and_then(|a| a.first())
+        .and_then(|g| g.get("pretty"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    pretty
+        .lines()
+        .filter_map(|ln| ln.trim_start().strip_prefix("⊢"))
+        .any(|t| !scan_metavars(t).is_empty())
+}
+
+/// A `Some(false)` whose target mentions a metavariable is not a refutation: the counter-model
+/// picks a value for the metavariable that elaboration may never assign. (`Some(true)` is fine:
+/// it holds for every value.)
+fn discount_metavar_refutation(pp_dump: &Value, r: Option<bool>) -> Option<bool> {
+    if r == Some(false) && target_has_metavar(pp_dump) {
+        None
+    } else {
+        r
+    }
+}
+
+/// Entailment result together with how much to trust it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct EntailmentReport {
+    /// Raw solver answer (a `Some(false)` is kept here even with metavariables in the target).
+    pub entails: Option<bool>,
+    /// Metavariables abstracted as constants.
+    pub metavars: Vec<String>,
+    /// The goal had metavariables: the answer is about the abstraction, not the goal itself.
+    pub low_confidence: bool,
+}
+
+/// `entails_from_pp_dump_with_depth` with metavariable bookkeeping.
+pub fn entailment_report_from_pp_dump(
+    pp_dump: &Value,
+    timeout_ms: u64,
+    seed: u64,
+    depth: usize,
+) -> Result<EntailmentReport, String> {
+    let metavars = metavars_in_pp_dump(pp_dump);
+    Ok(EntailmentReport {
+        entails: entails_raw_with_depth(pp_dump, timeout_ms, seed, depth)?,
+        low_confidence: !metavars.is_empty(),
+        metavars,
+    })
+}
+
+fn linear_from_expr(e: &Expr) -> Option<LinearExpr> {
+    // Sums/differences of atoms and integer literals, scaled by literal constants.
+    // Anything else (non-linear products, division, unknown functions) is rejected.
+    let atom = |name: &str| LinearExpr {
+        coeffs: std::iter::once((sanitize_name(name), 1)).collect(),
+        c0: 0,
+    };
+    match e {
+        // Metavariables (`?m.12`, or applied: `?m.12 x`) stand for some fixed unknown term:
+        // abstract each distinct one as a fresh constant.
+        Expr::Ident(name) if is_metavar(name) => Some(atom(&metavar_const(&e.to_string()))),
+        Expr::App { func, .. } if func.ident().is_some_and(is_metavar) => {
+            Some(atom(&metavar_const(&e.to_string())))
+        }
+        Expr::Ident(name) => Some(atom(name)),
+        Expr::Num(_) => Some(LinearExpr {
+            coeffs: Default::default(),
+            c0: e.as_nat_lit()?,
+        }),
+        // Casts preserve value: `↑n` denotes the same integer as `n`.
+        Expr::Unary { op: UnOp::Coe, arg } => linear_from_expr(arg),
+        Expr::Unary { op: UnOp::Neg, arg } => {
+            let mut x = linear_from_expr(arg)?;
+            linear_scale(&mut x, -1);
+            Some(x)
+        }
+        Expr::Binary { op, lhs, rhs } => {
+            let mut l = linear_from_expr(lhs)?;
+            let mut r = linear_from_expr(rhs)?;
+            match op {
+                BinOp::Add => {
+                    linear_add(&mut l, r);
+                    Some(l)
+                }
+                BinOp::Sub => {
+                    linear_scale(&mut r, -1);
+                    linear_add(&mut l, r);
+                    Some(l)
+                }
+                BinOp::Mul if l.coeffs.is_empty() => {
+                    linear_scale(&mut r, l.c0);
+                    Some(r)
+                }
+                BinOp::Mul if r.coeffs.is_empty() => {
+                    linear_scale(&mut l, r.c0);
+                    Some(l)
+                }
+                _ => None,
+            }
+        }
+        Expr::App { func, args } if args.len() == 1 => match func.ident()? {
+            "Nat.succ" | "Int.succ" | "succ" => {
+                let mut x = linear_from_expr(&args[0])?;
+                x.c0 = x.c0.saturating_add(1);
+                Some(x)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -559,45 +800,22 @@ fn linear_expr_to_smt_sexp(e: &LinearExpr) -> smtkit::sexp::Sexp {
     }
 }
 
-fn normalize_expr_text(s: &str) -> String {
-    // Try to normalize a few common pretty-printed shapes into linear syntax.
-    // This is intentionally small; if it doesn't match, we fall back to "unknown".
-    let mut out = s.to_string();
-    // Nat.succ x  -> x + 1
-    if let Ok(re1) = Regex::new(r"\bNat\.succ\s+\(?([A-Za-z0-9_\.]+)\)?") {
-        out = re1.replace_all(&out, "$1 + 1").to_string();
-    }
-    // Int.succ x -> x + 1
-    if let Ok(re2) = Regex::new(r"\bInt\.succ\s+\(?([A-Za-z0-9_\.]+)\)?") {
-        out = re2.replace_all(&out, "$1 + 1").to_string();
-    }
-    // Drop parentheses which often wrap pretty-printed terms.
-    out = out
-        .chars()
-        .map(|c| if c == '(' || c == ')' { ' ' } else { c })
-        .collect();
-    out
-}
-
 fn parse_rel_constraint_int(s: &str) -> Option<ParsedRelConstraint> {
     let s = s.trim();
     let src = s.to_string();
-    let ops = ["<=", "≤", ">=", "≥", "<", ">", "="];
-    let (op, idx) = ops.iter().find_map(|op| s.find(op).map(|i| (*op, i)))?;
-    let (lhs, rhs0) = s.split_at(idx);
-    let rhs = rhs0.get(op.len()..)?;
-    let lhs_n = normalize_expr_text(lhs.trim());
-    let rhs_n = normalize_expr_text(rhs.trim());
-    let lhs_e = parse_linear_expr_int(lhs_n.trim())?;
-    let rhs_e = parse_linear_expr_int(rhs_n.trim())?;
+    let Expr::Binary { op, lhs, rhs } = goal_ast::parse_expr(s).ok()? else {
+        return None;
+    };
     let rel_op = match op {
-        "<=" | "≤" => RelOp::Le,
-        ">=" | "≥" => RelOp::Ge,
-        "<" => RelOp::Lt,
-        ">" => RelOp::Gt,
-        "=" => RelOp::Eq,
+        BinOp::Le => RelOp::Le,
+        BinOp::Ge => RelOp::Ge,
+        BinOp::Lt => RelOp::Lt,
+        BinOp::Gt => RelOp::Gt,
+        BinOp::Eq => RelOp::Eq,
         _ => return None,
     };
+    let lhs_e = linear_from_expr(&lhs)?;
+    let rhs_e = linear_from_expr(&rhs)?;
     use smtkit::smt2::t;
     let a = linear_expr_to_smt_sexp(&lhs_e);
     let b = linear_expr_to_smt_sexp(&rhs_e);
@@ -977,6 +1195,18 @@ pub fn entails_from_pp_dump_with_depth_reuse(
     depth: usize,
     reuse: &mut Option<ReusableSmtSession>,
 ) -> Result<Option<bool>, String> {
+    entails_raw_with_depth_reuse(pp_dump, timeout_ms, seed, depth, reuse)
+        .map(|r| discount_metavar_refutation(pp_dump, r))
+}
+
+fn entails_raw_with_depth_reuse(
+    pp_dump: &Value,
+    timeout_ms: u64,
+    seed: u64,
+    depth: usize,
+    reuse: &mut Option<ReusableSmtSession>,
+) -> Result<Option<bool>, String> {
+    let pp_dump = &normalize_pp_dump_names(pp_dump);
     // Parse same as the non-reuse path.
     let goal = pp_dump
         .get("goals")
@@ -1002,9 +1232,7 @@ pub fn entails_from_pp_dump_with_depth_reuse(
     if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
         for h in hyps {
             if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                if let Some((name, kind)) = extract_decl_kind(txt) {
-                    var_kinds.insert(name, kind);
-                }
+                var_kinds.extend(extract_decl_kinds(txt));
             }
         }
     }
@@ -1105,7 +1333,7 @@ pub fn entails_from_pp_dump_with_depth_reuse(
     }
 
     // Fallback: old behavior (spawn per call).
-    entails_from_pp_dump_with_depth(pp_dump, timeout_ms, seed, depth)
+    entails_raw_with_depth(pp_dump, timeout_ms, seed, depth)
 }
 
 /// Like `entails_from_pp_dump`, but optionally restricts which hypotheses are considered
@@ -1115,12 +1343,26 @@ pub fn entails_from_pp_dump_with_depth_reuse(
 /// - `0`: use all parseable LIA hypotheses (status quo)
 /// - `1`: only hypotheses that mention a target variable
 /// - `2+`: expand by shared variables up to `depth` passes
+///
+/// Metavariables are abstracted as constants; a refutation of a target that mentions one is
+/// reported as `None` (see `entailment_report_from_pp_dump` for the raw answer).
 pub fn entails_from_pp_dump_with_depth(
     pp_dump: &Value,
     timeout_ms: u64,
     seed: u64,
     depth: usize,
 ) -> Result<Option<bool>, String> {
+    entails_raw_with_depth(pp_dump, timeout_ms, seed, depth)
+        .map(|r| discount_metavar_refutation(pp_dump, r))
+}
+
+fn entails_raw_with_depth(
+    pp_dump: &Value,
+    timeout_ms: u64,
+    seed: u64,
+    depth: usize,
+) -> Result<Option<bool>, String> {
+    let pp_dump = &normalize_pp_dump_names(pp_dump);
     use smtkit::smt2::t;
 
     let goal = pp_dump
@@ -1147,9 +1389,7 @@ pub fn entails_from_pp_dump_with_depth(
     if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
         for h in hyps {
             if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                if let Some((name, kind)) = extract_decl_kind(txt) {
-                    var_kinds.insert(name, kind);
-                }
+                var_kinds.extend(extract_decl_kinds(txt));
             }
         }
     }
@@ -1261,6 +1501,7 @@ pub fn explain_fragment_from_pp_dump(
     depth: usize,
     max_hyps: usize,
 ) -> Option<Value> {
+    let pp_dump = &normalize_pp_dump_names(pp_dump);
     let goal = pp_dump
         .get("goals")
         .and_then(|v| v.as_array())
@@ -1334,6 +1575,7 @@ pub fn unsat_core_from_pp_dump(
     depth: usize,
     max_items: usize,
 ) -> Result<Option<Value>, String> {
+    let pp_dump = &normalize_pp_dump_names(pp_dump);
     use smtkit::smt2::t;
 
     fn sanitize_smt_sym(s: &str) -> Option<String> {
@@ -1462,9 +1704,7 @@ pub fn unsat_core_from_pp_dump(
     if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
         for h in hyps {
             if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                if let Some((name, kind)) = extract_decl_kind(txt) {
-                    var_kinds.insert(name, kind);
-                }
+                var_kinds.extend(extract_decl_kinds(txt));
             }
         }
     }
@@ -1566,6 +1806,642 @@ pub fn unsat_core_from_pp_dump(
     })))
 }
 
+/// A SAT model of `hyps ∧ ¬target`, with a Lean snippet that checks it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Counterexample {
+    /// Lean variable name → value.
+    pub assignment: std::collections::BTreeMap<String, i64>,
+    /// Hypotheses of the selected fragment (as printed in the goal).
+    pub hyps: Vec<String>,
+    pub target: String,
+    /// `example : <hyps> ∧ ¬(<target>) := by decide` with the values substituted; it compiles iff
+    /// the model really is a counterexample.
+    pub lean: String,
+}
+
+fn subst_idents(e: &Expr, values: &std::collections::BTreeMap<String, Expr>) -> Expr {
+    let go = |x: &Expr| Box::new(subst_idents(x, values));
+    match e {
+        Expr::Ident(n) => values.get(n).cloned().unwrap_or_else(|| e.clone()),
+        Expr::App { func, args } => Expr::App {
+            func: go(func),
+            args: args.iter().map(|a| subst_idents(a, values)).collect(),
+        },
+        Expr::Unary { op, arg } => Expr::Unary {
+            op: *op,
+            arg: go(arg),
+        },
+        Expr::Binary { op, lhs, rhs } => Expr::Binary {
+            op: op.clone(),
+            lhs: go(lhs),
+            rhs: go(rhs),
+        },
+        Expr::Ascription { expr, ty } => Expr::Ascription {
+            expr: go(expr),
+            ty: ty.clone(),
+        },
+        _ => e.clone(),
+    }
+}
+
+/// Render the checking snippet for `assignment` (keys are Lean names; `nat` lists the `ℕ` ones).
+fn counterexample_lean(
+    hyps: &[String],
+    target: &str,
+    assignment: &std::collections::BTreeMap<String, i64>,
+    nat: &std::collections::BTreeSet<String>,
+) -> Option<String> {
+    let values: std::collections::BTreeMap<String, Expr> = assignment
+        .iter()
+        .map(|(n, v)| {
+            let lit = Expr::Num(v.unsigned_abs().to_string());
+            let lit = if *v < 0 {
+                Expr::Unary {
+                    op: UnOp::Neg,
+                    arg: Box::new(lit),
+                }
+            } else {
+                lit
+            };
+            let ty = if nat.contains(n) { "ℕ" } else { "ℤ" };
+            (
+                n.clone(),
+                Expr::Ascription {
+                    expr: Box::new(lit),
+                    ty: Box::new(Expr::Ident(ty.to_string())),
+                },
+            )
+        })
+        .collect();
+    let mut conj = Expr::Unary {
+        op: UnOp::Not,
+        arg: Box::new(subst_idents(&goal_ast::parse_expr(target).ok()?, &values)),
+    };
+    for h in hyps.iter().rev() {
+        conj = Expr::Binary {
+            op: BinOp::And,
+            lhs: Box::new(subst_idents(&goal_ast::parse_expr(h).ok()?, &values)),
+            rhs: Box::new(conj),
+        };
+    }
+    let shown = assignment
+        .iter()
+        .map(|(n, v)| format!("{n} := {v}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(format!(
+        "-- counterexample: {shown}\nexample : {conj} := by decide\n"
+    ))
+}
+
+/// Counterexample for the first goal of a `pp_dump`: a model of the (depth-selected) linear
+/// hypotheses in which the target fails.
+///
+/// `Ok(None)` when the fragment does not parse, no solver is available, or the goal is entailed
+/// (or the solver gives up). Values come from the solver's `get-value`; the Lean snippet is the
+/// thing to trust (integer semantics here ignore `ℕ` truncated subtraction).
+pub fn counterexample_from_pp_dump(
+    pp_dump: &Value,
+    timeout_ms: u64,
+    seed: u64,
+    depth: usize,
+) -> Result<Option<Counterexample>, String> {
+    let pp_dump = &normalize_pp_dump_names(pp_dump);
+    use smtkit::smt2::t;
+    let Some(goal) = pp_dump
+        .get("goals")
+        .and_then(|v| v.as_array())
+        .and_then(|a| a.first())
+    else {
+        return Ok(None);
+    };
+    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
+    let target = pretty
+        .lines()
+        .find_map(|ln| {
+            ln.trim_start()
+                .strip_prefix("⊢")
+                .map(|r| r.trim().to_string())
+        })
+        .unwrap_or_default();
+    let Some(target_rel) = parse_rel_constraint_int(&target) else {
+        return Ok(None);
+    };
+    if target_has_metavar(pp_dump) {
+        return Ok(None);
+    }
+    let mut var_kinds: std::collections::BTreeMap<String, VarKind> =
+        std::collections::BTreeMap::new();
+    let mut hyp_rels: Vec<ParsedRelConstraint> = Vec::new();
+    for h in goal
+        .get("hyps")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let Some(txt) = h.get("text").and_then(|v| v.as_str()) else {
+            continue;
+        };
+        var_kinds.extend(extract_decl_kinds(txt));
+        let rhs = txt
+            .split_once(':')
+            .map(|(_, r)| r.trim())
+            .unwrap_or(txt.trim());
+        if let Some(r) = parse_rel_constraint_int(rhs) {
+            hyp_rels.push(r);
+        }
+    }
+    let hyp_rels = select_constraints_by_var_depth(&target_rel.vars, &hyp_rels, depth);
+
+    // SMT symbol → Lean name (the solver only sees `sanitize_name` output).
+    let mut lean_names: std::collections::BTreeMap<String, String> =
+        std::collections::BTreeMap::new();
+    for src in hyp_rels.iter().map(|r| &r.src).chain([&target_rel.src]) {
+        if let Ok(e) = goal_ast::parse_expr(src) {
+            e.walk(&mut |x| {
+                if let Expr::Ident(n) = x {
+                    lean_names.insert(sanitize_name(n), n.clone());
+                }
+            });
+        }
+    }
+    let mut used_vars: std::collections::BTreeSet<String> = target_rel.vars.clone();
+    for r in &hyp_rels {
+        used_vars.extend(r.vars.iter().cloned());
+    }
+    if used_vars.is_empty() {
+        return Ok(None);
+    }
+
+    let Ok((mut sess, _used)) = smtkit::session::spawn_auto() else {
+        return Ok(None);
+    };
+    sess.set_logic("QF_LIA").map_err(|e| e.to_string())?;
+    sess.set_print_success(false).map_err(|e| e.to_string())?;
+    sess.set_produce_models(true).map_err(|e| e.to_string())?;
+    sess.set_timeout_ms(timeout_ms).map_err(|e| e.to_string())?;
+    sess.set_random_seed(seed).map_err(|e| e.to_string())?;
+    for name in used_vars.iter() {
+        sess.declare_const(name, &smtkit::smt2::Sort::Int.to_smt2())
+            .map_err(|e| e.to_string())?;
+        if var_kinds.get(name) == Some(&VarKind::Nat) {
+            sess.assert_sexp(&t::ge(t::sym(name.clone()), t::int_lit(0)))
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    for r in &hyp_rels {
+        sess.assert_sexp(&r.sexp).map_err(|e| e.to_string())?;
+    }
+    sess.assert_sexp(&t::not(target_rel.sexp.clone()))
+        .map_err(|e| e.to_string())?;
+    if sess.check_sat().map_err(|e| e.to_string())? != smtkit::session::Status::Sat {
+        return Ok(None);
+    }
+    let syms: Vec<smtkit::sexp::Sexp> = used_vars.iter().map(|v| t::sym(v.clone())).collect();
+    let pairs = sess.get_value_pairs(&syms).map_err(|e| e.to_string())?;
+
+    let mut assignment = std::collections::BTreeMap::new();
+    let mut nat = std::collections::BTreeSet::new();
+    for (k, v) in pairs {
+        let smtkit::sexp::Sexp::Atom(sym) = k else {
+            continue;
+        };
+        // `5` or `(- 5)`.
+        let val = match v {
+            smtkit::sexp::Sexp::Atom(a) => a.parse::<i64>().ok(),
+            smtkit::sexp::Sexp::List(xs) => match xs.as_slice() {
+                [smtkit::sexp::Sexp::Atom(m), smtkit::sexp::Sexp::Atom(a)] if m == "-" => {
+                    a.parse::<i64>().ok().map(|x| -x)
+                }
+                _ => None,
+            },
+        };
+        let Some(val) = val else {
+            continue;
+        };
+        let name = lean_names.get(&sym).cloned().unwrap_or(sym.clone());
+        if var_kinds.get(&sym) == Some(&VarKind::Nat) {
+            nat.insert(name.clone());
+        }
+        assignment.insert(name, val);
+    }
+    let hyps: Vec<String> = hyp_rels.iter().map(|r| r.src.clone()).collect();
+    let Some(lean) = counterexample_lean(&hyps, &target, &assignment, &nat) else {
+        return Ok(None);
+    };
+    Ok(Some(Counterexample {
+        assignment,
+        hyps,
+        target,
+        lean,
+    }))
+}
+
+/// An intermediate linear fact to prove with `omega` before the main goal.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SteppingStone {
+    pub name: String,
+    /// Lean proposition, e.g. `a ≤ c + 1`.
+    pub statement: String,
+    /// Hypotheses it was chained from.
+    pub from: Vec<String>,
+    /// SMT check of `hyps ⊢ statement` (`None`: no solver / unknown).
+    pub entailed: Option<bool>,
+}
+
+impl SteppingStone {
+    pub fn have_line(&self) -> String {
+        format!("have {} : {} := by omega", self.name, self.statement)
+    }
+}
+
+/// `lo ≤ hi` (or `lo < hi`) oriented view of a hypothesis.
+#[derive(Debug, Clone)]
+struct ChainEdge {
+    lo: Expr,
+    hi: Expr,
+    lo_l: LinearExpr,
+    hi_l: LinearExpr,
+    strict: bool,
+    /// Both sides equal (only hypotheses of the form `a = b`, and chains of them).
+    eq: bool,
+    /// Proof term of `lo ≤ hi` / `lo < hi` / `lo = hi` for a single hypothesis (`h`, `h.symm`).
+    proof: String,
+    from: Vec<String>,
+}
+
+/// `a - b` if it is a constant.
+fn linear_const_diff(a: &LinearExpr, b: &LinearExpr) -> Option<i64> {
+    let vars: std::collections::BTreeSet<&String> =
+        a.coeffs.keys().chain(b.coeffs.keys()).collect();
+    vars.into_iter()
+        .all(|v| a.coeffs.get(v).copied().unwrap_or(0) == b.coeffs.get(v).copied().unwrap_or(0))
+        .then(|| a.c0.saturating_sub(b.c0))
+}
+
+fn plus_const(e: &Expr, k: i64) -> Expr {
+    Expr::Binary {
+        op: BinOp::Add,
+        lhs: Box::new(e.clone()),
+        rhs: Box::new(Expr::Num(k.to_string())),
+    }
+}
+
+fn chain_edges(name: &str, src: &str) -> Vec<ChainEdge> {
+    let Ok(Expr::Binary { op, lhs, rhs }) = goal_ast::parse_expr(src) else {
+        return Vec::new();
+    };
+    let (Some(l), Some(r)) = (linear_from_expr(&lhs), linear_from_expr(&rhs)) else {
+        return Vec::new();
+    };
+    let edge =
+        |lo: &Expr, hi: &Expr, lo_l: &LinearExpr, hi_l: &LinearExpr, strict: bool| ChainEdge {
+            lo: lo.clone(),
+            hi: hi.clone(),
+            lo_l: lo_l.clone(),
+            hi_l: hi_l.clone(),
+            strict,
+            eq: false,
+            proof: name.to_string(),
+            from: vec![name.to_string()],
+        };
+    match op {
+        BinOp::Le => vec![edge(&lhs, &rhs, &l, &r, false)],
+        BinOp::Lt => vec![edge(&lhs, &rhs, &l, &r, true)],
+        BinOp::Ge => vec![edge(&rhs, &lhs, &r, &l, false)],
+        BinOp::Gt => vec![edge(&rhs, &lhs, &r, &l, true)],
+        BinOp::Eq => {
+            let fwd = edge(&lhs, &rhs, &l, &r, false);
+            let bwd = edge(&rhs, &lhs, &r, &l, false);
+            vec![
+                ChainEdge { eq: true, ..fwd },
+                ChainEdge {
+                    eq: true,
+                    proof: format!("{name}.symm"),
+                    ..bwd
+                },
+            ]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// `lo1 ≤ hi1 = lo2 + k`, `lo2 ≤ hi2`  ⟹  `lo1 ≤ hi2 + k` (the constant moves to the left side
+/// when negative, to stay clear of `ℕ` subtraction).
+fn compose_edges(a: &ChainEdge, b: &ChainEdge) -> Option<ChainEdge> {
+    if a.from.iter().any(|h| b.from.contains(h)) {
+        return None;
+    }
+    let k = linear_const_diff(&a.hi_l, &b.lo_l)?;
+    let mut hi_l = b.hi_l.clone();
+    let mut lo_l = a.lo_l.clone();
+    let (lo, hi) = match k {
+        0 => (a.lo.clone(), b.hi.clone()),
+        k if k > 0 => {
+            hi_l.c0 = hi_l.c0.saturating_add(k);
+            (a.lo.clone(), plus_const(&b.hi, k))
+        }
+        k => {
+            lo_l.c0 = lo_l.c0.saturating_add(-k);
+            (plus_const(&a.lo, -k), b.hi.clone())
+        }
+    };
+    // Variable-free or self-comparisons carry no information.
+    if linear_const_diff(&hi_l, &lo_l).is_some() {
+        return None;
+    }
+    Some(ChainEdge {
+        lo,
+        hi,
+        lo_l,
+        hi_l,
+        strict: a.strict || b.strict,
+        eq: a.eq && b.eq && k == 0,
+        proof: String::new(),
+        from: a.from.iter().chain(&b.from).cloned().collect(),
+    })
+}
+
+/// Stepping-stone `have`s for the first goal of a `pp_dump`.
+///
+/// Linear hypotheses (restricted to the SMT unsat core when a solver can produce one) are chained
+/// by transitivity, up to two levels (`a ≤ b + 1`, `b ≤ c` ⟹ `a ≤ c + 1`). Facts that share a side
+/// with the goal come first; each is checked for entailment so a refuted fact is never proposed.
+/// At most `max` facts are returned.
+pub fn stepping_stones_from_pp_dump(
+    pp_dump: &Value,
+    timeout_ms: u64,
+    seed: u64,
+    max: usize,
+) -> Result<Vec<SteppingStone>, String> {
+    let pp_dump = &normalize_pp_dump_names(pp_dump);
+    let Some(goal) = pp_dump
+        .get("goals")
+        .and_then(|v| v.as_array())
+        .and_then(|a| a.first())
+    else {
+        return Ok(Vec::new());
+    };
+    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
+    let target = pretty
+        .lines()
+        .find_map(|ln| {
+            ln.trim_start()
+                .strip_prefix("⊢")
+                .map(|r| r.trim().to_string())
+        })
+        .unwrap_or_default();
+    let target_edges = chain_edges("⊢", &target);
+    if target_edges.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let core: std::collections::BTreeSet<String> =
+        unsat_core_from_pp_dump(pp_dump, timeout_ms, seed, 0, 64)
+            .ok()
+            .flatten()
+            .and_then(|c| c.get("core_items").and_then(|v| v.as_array()).cloned())
+            .into_iter()
+            .flatten()
+            .filter_map(|it| it.get("src").and_then(|v| v.as_str()).map(str::to_string))
+            .collect();
+
+    let mut taken: std::collections::BTreeSet<String> = std::collections::BTreeSet::new();
+    let mut all: Vec<(String, String)> = Vec::new();
+    for h in goal
+        .get("hyps")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let Some(txt) = h.get("text").and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let Some((names, ty)) = txt.split_once(':') else {
+            continue;
+        };
+        let name = names.split_whitespace().next().unwrap_or("").to_string();
+        taken.extend(names.split_whitespace().map(str::to_string));
+        if parse_rel_constraint_int(ty).is_some() {
+            all.push((name, ty.trim().to_string()));
+        }
+    }
+    let in_core: Vec<&(String, String)> = all.iter().filter(|(_, s)| core.contains(s)).collect();
+    let base: Vec<ChainEdge> = if in_core.len() >= 2 {
+        in_core
+    } else {
+        all.iter().collect()
+    }
+    .into_iter()
+    .flat_map(|(n, s)| chain_edges(n, s))
+    .collect();
+
+    let mut derived: Vec<ChainEdge> = Vec::new();
+    let mut frontier = base.clone();
+    for _ in 0..2 {
+        let mut next = Vec::new();
+        for a in &frontier {
+            for b in &base {
+                if let Some(e) = compose_edges(a, b) {
+                    next.push(e);
+                }
+            }
+        }
+        derived.extend(next.iter().cloned());
+        frontier = next;
+        if derived.len() > 256 {
+            break;
+        }
+    }
+
+    // Goal-sharing sides first, then shorter chains.
+    let goal_score = |e: &ChainEdge| {
+        target_edges
+            .iter()
+            .map(|t| {
+                usize::from(linear_const_diff(&e.lo_l, &t.lo_l).is_some())
+                    + usize::from(linear_const_diff(&e.hi_l, &t.hi_l).is_some())
+            })
+            .max()
+            .unwrap_or(0)
+    };
+    derived.sort_by_key(|e| (std::cmp::Reverse(goal_score(e)), e.from.len()));
+
+    let hyp_srcs: std::collections::BTreeSet<&str> = all.iter().map(|(_, s)| s.as_str()).collect();
+    let context: String = pretty
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('⊢'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut seen = std::collections::BTreeSet::new();
+    let mut out = Vec::new();
+    for e in derived {
+        if out.len() >= max {
+            break;
+        }
+        let statement = format!("{} {} {}", e.lo, if e.strict { "<" } else { "≤" }, e.hi);
+        if statement == target
+            || hyp_srcs.contains(statement.as_str())
+            || !seen.insert(statement.clone())
+        {
+            continue;
+        }
+        let probe = serde_json::json!({
+            "goals": [{
+                "pretty": format!("{context}\n⊢ {statement}"),
+                "hyps": goal.get("hyps").cloned().unwrap_or(Value::Null),
+            }]
+        });
+        let entailed = entails_from_pp_dump(&probe, timeout_ms, seed)
+            .ok()
+            .flatten();
+        if entailed == Some(false) {
+            continue;
+        }
+        let mut i = out.len() + 1;
+        while taken.contains(&format!("hs{i}")) {
+            i += 1;
+        }
+        let name = format!("hs{i}");
+        taken.insert(name.clone());
+        out.push(SteppingStone {
+            name,
+            statement,
+            from: e.from,
+            entailed,
+        });
+    }
+    Ok(out)
+}
+
+/// A readable `calc` proof of the first goal of a `pp_dump`, when its target follows from a chain
+/// of hypotheses (`h1 : a ≤ b`, `h2 : b < c` ⊢ `a < c`), at most `max_steps` long.
+///
+/// Chain links must match exactly up to linear normalization (`b + 1` links to `1 + b`); targets
+/// that only follow with offsets are left to `omega`. A non-strict target proved by a strict chain
+/// goes through `le_of_lt`. Returns a `by` block, or `None` if no chain of two or more steps exists.
+pub fn calc_from_pp_dump(pp_dump: &Value, max_steps: usize) -> Option<String> {
+    let pp_dump = &normalize_pp_dump_names(pp_dump);
+    let goal = pp_dump
+        .get("goals")
+        .and_then(|v| v.as_array())
+        .and_then(|a| a.first())?;
+    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
+    let target = pretty.lines().find_map(|ln| {
+        ln.trim_start()
+            .strip_prefix("⊢")
+            .map(|r| r.trim().to_string())
+    })?;
+    let Ok(Expr::Binary { op: target_op, .. }) = goal_ast::parse_expr(&target) else {
+        return None;
+    };
+    let goal_edge = chain_edges("⊢", &target).into_iter().next()?;
+    let edges: Vec<ChainEdge> = goal
+        .get("hyps")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|h| h.get("text").and_then(|v| v.as_str())?.split_once(':'))
+        .filter(|(names, _)| names.split_whitespace().count() == 1)
+        .flat_map(|(name, ty)| chain_edges(name.trim(), ty.trim()))
+        .collect();
+
+    // Iterative deepening: the shortest chain wins.
+    fn search<'a>(
+        edges: &'a [ChainEdge],
+        cur: &LinearExpr,
+        goal: &LinearExpr,
+        depth: usize,
+        path: &mut Vec<&'a ChainEdge>,
+    ) -> bool {
+        if depth == 0 {
+            return false;
+        }
+        for e in edges {
+            if linear_const_diff(&e.lo_l, cur) != Some(0) || path.iter().any(|p| p.from == e.from) {
+                continue;
+            }
+            path.push(e);
+            if (depth == 1 && linear_const_diff(&e.hi_l, goal) == Some(0))
+                || search(edges, &e.hi_l, goal, depth - 1, path)
+            {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+    let mut path: Vec<&ChainEdge> = Vec::new();
+    let found = (2..=max_steps.max(2))
+        .any(|n| search(&edges, &goal_edge.lo_l, &goal_edge.hi_l, n, &mut path));
+    if !found {
+        return None;
+    }
+
+    let strict = path.iter().any(|e| e.strict);
+    let all_eq = path.iter().all(|e| e.eq);
+    let needs_le_of_lt = match target_op {
+        BinOp::Eq if !all_eq => return None,
+        BinOp::Lt | BinOp::Gt if !strict => return None,
+        BinOp::Le | BinOp::Ge => strict,
+        BinOp::Eq | BinOp::Lt | BinOp::Gt => false,
+        _ => return None,
+    };
+    let rel = |e: &ChainEdge| {
+        if e.eq {
+            "="
+        } else if e.strict {
+            "<"
+        } else {
+            "≤"
+        }
+    };
+    let mut out = String::from("by");
+    if needs_le_of_lt {
+        out.push_str("\n  apply le_of_lt");
+    }
+    for (i, e) in path.iter().enumerate() {
+        // The goal's own spelling at both ends, the hypotheses' in between.
+        let hi = if i + 1 == path.len() {
+            goal_edge.hi.to_string()
+        } else {
+            e.hi.to_string()
+        };
+        if i == 0 {
+            out.push_str(&format!(
+                "\n  calc {} {} {hi} := {}",
+                goal_edge.lo,
+                rel(e),
+                e.proof
+            ));
+        } else {
+            out.push_str(&format!("\n    _ {} {hi} := {}", rel(e), e.proof));
+        }
+    }
+    Some(out)
+}
+
+/// Candidate scripts: the first one, two, and all stepping stones, each followed by `omega`, and
+/// all of them followed by `linarith`.
+pub fn stepping_stone_candidates(stones: &[SteppingStone]) -> Vec<String> {
+    if stones.is_empty() {
+        return Vec::new();
+    }
+    let script = |n: usize, closer: &str| {
+        let mut s = String::from("by");
+        for st in &stones[..n] {
+            s.push_str(&format!("\n  {}", st.have_line()));
+        }
+        s.push_str(&format!("\n  {closer}"));
+        s
+    };
+    let mut ns: Vec<usize> = vec![1, 2.min(stones.len()), stones.len()];
+    ns.dedup();
+    let mut out: Vec<String> = ns.into_iter().map(|n| script(n, "omega")).collect();
+    out.push(script(stones.len(), "linarith"));
+    out
+}
+
 /// Optional: capture an UNSAT proof object for the selected fragment (solver-dependent).
 ///
 /// This is intended for **debugging/provenance**, not as a proof checker:
@@ -1579,6 +2455,7 @@ pub fn unsat_proof_from_pp_dump(
     depth: usize,
     max_chars: usize,
 ) -> Result<Option<Value>, String> {
+    let pp_dump = &normalize_pp_dump_names(pp_dump);
     use smtkit::smt2::t;
 
     fn truncate_chars(s: &str, max: usize) -> String {
@@ -1654,9 +2531,7 @@ pub fn unsat_proof_from_pp_dump(
     if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
         for h in hyps {
             if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                if let Some((name, kind)) = extract_decl_kind(txt) {
-                    var_kinds.insert(name, kind);
-                }
+                var_kinds.extend(extract_decl_kinds(txt));
             }
         }
     }
@@ -1721,6 +2596,7 @@ pub fn smt2_script_from_pp_dump(
     seed: u64,
     depth: usize,
 ) -> Option<String> {
+    let pp_dump = &normalize_pp_dump_names(pp_dump);
     use smtkit::smt2::t;
 
     fn sanitize_smt_sym(s: &str) -> Option<String> {
@@ -1807,9 +2683,7 @@ pub fn smt2_script_from_pp_dump(
     if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
         for h in hyps {
             if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                if let Some((name, kind)) = extract_decl_kind(txt) {
-                    var_kinds.insert(name, kind);
-                }
+                var_kinds.extend(extract_decl_kinds(txt));
             }
         }
     }
@@ -1951,6 +2825,109 @@ mod tests {
         );
     }
 
+    #[test]
+    fn counterexample_snippet_substitutes_typed_values() {
+        let assignment = [("a".to_string(), 3), ("b".to_string(), -1)]
+            .into_iter()
+            .collect();
+        let nat = ["a".to_string()].into_iter().collect();
+        let lean =
+            counterexample_lean(&["a ≤ b + 5".to_string()], "a ≤ b", &assignment, &nat).unwrap();
+        assert_eq!(
+            lean,
+            "-- counterexample: a := 3, b := -1\nexample : (3 : ℕ) ≤ (-1 : ℤ) + 5 ∧ ¬(3 : ℕ) ≤ (-1 : ℤ) := by decide\n"
+        );
+    }
+
+    #[test]
+    fn calc_chains_hypotheses_from_goal_lhs_to_rhs() {
+        let pp = |hyps: &[&str], target: &str| {
+            serde_json::json!({
+                "goals": [{
+                    "pretty": format!("{}\n⊢ {target}", hyps.join("\n")),
+                    "hyps": hyps.iter().map(|h| serde_json::json!({ "text": h })).collect::<Vec<_>>(),
+                }]
+            })
+        };
+        let hyps = [
+            "a b c d : ℕ",
+            "h1 : a ≤ b + 1",
+            "h2 : c = b + 1",
+            "h3 : c < d",
+        ];
+        assert_eq!(
+            calc_from_pp_dump(&pp(&hyps, "a < d"), 4).unwrap(),
+            "by\n  calc a ≤ b + 1 := h1\n    _ = c := h2.symm\n    _ < d := h3"
+        );
+        assert_eq!(
+            calc_from_pp_dump(&pp(&hyps, "a ≤ c"), 4).unwrap(),
+            "by\n  calc a ≤ b + 1 := h1\n    _ = c := h2.symm"
+        );
+        assert!(calc_from_pp_dump(&pp(&hyps, "d ≥ a"), 4)
+            .unwrap()
+            .starts_with("by\n  apply le_of_lt\n  calc a ≤"));
+        assert_eq!(calc_from_pp_dump(&pp(&hyps, "b ≤ a"), 4), None);
+    }
+
+    #[test]
+    fn stepping_stones_chain_linear_hyps_toward_goal() {
+        let pp_dump = serde_json::json!({
+            "goals": [{
+                "pretty": "a b c : ℕ\nh1 : a ≤ b + 1\nh2 : b < c\nhx : 7 ≤ c\n⊢ a ≤ c",
+                "hyps": [
+                    { "text": "a b c : ℕ" },
+                    { "text": "h1 : a ≤ b + 1" },
+                    { "text": "h2 : b < c" },
+                    { "text": "hx : 7 ≤ c" }
+                ]
+            }]
+        });
+        let stones = stepping_stones_from_pp_dump(&pp_dump, 2_000, 0, 4).unwrap();
+        assert_eq!(stones[0].statement, "a < c + 1");
+        assert_eq!(stones[0].from, vec!["h1", "h2"]);
+        assert_eq!(stones[0].have_line(), "have hs1 : a < c + 1 := by omega");
+        let cands = stepping_stone_candidates(&stones[..1]);
+        assert_eq!(cands[0], "by\n  have hs1 : a < c + 1 := by omega\n  omega");
+    }
+
+    #[test]
+    fn qualified_locals_and_open_namespace_constants_are_unified() {
+        let pp_dump = serde_json::json!({
+            "open_namespaces": ["Foo", "Foo.Bar"],
+            "goals": [{
+                "pretty": "n : ℕ\nh : Foo.n ≤ Foo.Bar.bound\n⊢ n ≤ bound + h.le.1",
+                "hyps": [{ "text": "n : ℕ" }, { "text": "h : Foo.n ≤ Foo.Bar.bound" }]
+            }]
+        });
+        let out = normalize_pp_dump_names(&pp_dump);
+        assert_eq!(out["goals"][0]["hyps"][1]["text"], "h : n ≤ bound");
+        assert_eq!(
+            out["goals"][0]["pretty"],
+            "n : ℕ\nh : n ≤ bound\n⊢ n ≤ bound + h.le.1"
+        );
+    }
+
+    #[test]
+    fn metavariables_are_abstracted_and_discount_refutations() {
+        let r = parse_rel_constraint_int("n + ?m.5 x ≤ ?m.12 + 1").expect("parse");
+        assert_eq!(
+            r.vars.into_iter().collect::<Vec<_>>(),
+            vec!["mvar_m_12", "mvar_m_5_x", "n"]
+        );
+        let pp_dump = serde_json::json!({
+            "goals": [{
+                "pretty": "n : ℕ\nh : ?m.12 ≤ n\n⊢ n ≤ ?m.7",
+                "hyps": [{ "text": "n : ℕ" }, { "text": "h : ?m.12 ≤ n" }]
+            }]
+        });
+        assert_eq!(metavars_in_pp_dump(&pp_dump), vec!["?m.12", "?m.7"]);
+        assert_eq!(discount_metavar_refutation(&pp_dump, Some(false)), None);
+        assert_eq!(
+            discount_metavar_refutation(&pp_dump, Some(true)),
+            Some(true)
+        );
+    }
+
     #[test]
     fn smt_depth_selects_connected_constraints() {
         let mk = |s: &str| parse_rel_constraint_int(s).expect("parse");
@@ -1990,4 +2967,21 @@ mod tests {
         kinds.insert("c".to_string(), VarKind::Int);
         assert_eq!(idl_proves_entails(&target, &[h1, h2], &kinds), Some(true));
     }
+
+    #[test]
+    fn goal_ast_parses_shapes_the_string_splitter_missed() {
+        let r = parse_rel_constraint_int("Nat.succ (a + 1) ≤ 2 * ↑b - (c - 3)").expect("parse");
+        assert_eq!(r.rel.op, RelOp::Le);
+        assert_eq!(r.rel.lhs.c0, 2);
+        assert_eq!(r.rel.rhs.coeffs.get("b"), Some(&2));
+        assert_eq!(r.rel.rhs.coeffs.get("c"), Some(&-1));
+        assert_eq!(r.rel.rhs.c0, 3);
+        // Non-linear terms and unknown functions are rejected, not mangled.
+        assert!(parse_rel_constraint_int("a * b ≤ c").is_none());
+        assert!(parse_rel_constraint_int("f a ≤ b").is_none());
+
+        let kinds = extract_decl_kinds("a b : ℕ");
+        assert_eq!(kinds.len(), 2);
+        assert!(extract_decl_kinds("f : ℕ → ℕ").is_empty());
+    }
 }
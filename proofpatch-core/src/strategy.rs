@@ -0,0 +1,258 @@
+//! Which repair to try first, by kind of failure.
+//!
+//! `diagnostics::ErrorClass` buckets Lean errors; `strategy_for` maps each bucket to a
+//! `RepairStrategy`, and the strategy supplies class-specific seed candidates (tried ahead of the
+//! generic heuristics) and a hint for the LLM prompt. The repair loop picks the strategy from the
+//! error that is being repaired (`RepairOptions::error_class`) and then from the first error of
+//! each failed round, so a timeout is met with higher limits and leaner tactics, a type mismatch
+//! with casts and `convert`, unsolved goals with closing steps after the existing ones, and so on.
+
+use crate::diagnostics::ErrorClass;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairStrategy {
+    /// A lemma or constant no longer exists: rename (`renames`) or search for the replacement.
+    Rename,
+    /// Deterministic timeout / recursion limit: raise the limit, prefer targeted tactics.
+    RaiseLimits,
+    /// Type mismatch: bridge casts and definitional differences.
+    Convert,
+    /// Goals left open: keep the existing steps and close what remains.
+    CloseGoals,
+    /// Instance synthesis failed: make instances available.
+    Instances,
+    /// Universe mismatch: needs annotations; left to the LLM.
+    Universes,
+    /// The script does not parse (or uses an unknown tactic): regenerate with current syntax.
+    Syntax,
+    /// Anything else: the generic heuristics.
+    Automation,
+}
+
+/// The dispatch table.
+pub fn strategy_for(class: ErrorClass) -> RepairStrategy {
+    match class {
+        ErrorClass::UnknownIdentifier | ErrorClass::UnknownConstant => RepairStrategy::Rename,
+        ErrorClass::DeterministicTimeout | ErrorClass::MaxRecursion => RepairStrategy::RaiseLimits,
+        ErrorClass::TypeMismatch => RepairStrategy::Convert,
+        ErrorClass::UnsolvedGoals => RepairStrategy::CloseGoals,
+        ErrorClass::FailedToSynthesize => RepairStrategy::Instances,
+        ErrorClass::UniverseLevel => RepairStrategy::Universes,
+        ErrorClass::ParseError | ErrorClass::UnknownTactic => RepairStrategy::Syntax,
+        ErrorClass::NoProgress
+        | ErrorClass::TacticFailed
+        | ErrorClass::DeclarationUsesSorry
+        | ErrorClass::Linter
+        | ErrorClass::Other => RepairStrategy::Automation,
+    }
+}
+
+const CLOSERS: &[&str] = &["simp", "omega", "linarith", "aesop"];
+
+fn indent(block: &str, by: &str) -> String {
+    block
+        .lines()
+        .map(|l| {
+            if l.trim().is_empty() {
+                String::new()
+            } else {
+                format!("{by}{l}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn dedent(lines: &[&str]) -> Vec<String> {
+    let min = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|l| l.get(min..).unwrap_or("").trim_end().to_string())
+        .collect()
+}
+
+/// The text of `[start, end)` with continuation lines shifted left by the column of `start`, so
+/// a mid-proof span reads as a script of its own.
+pub fn span_script(text: &str, start: usize, end: usize) -> String {
+    let line_start = text[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let col = start - line_start;
+    text[start..end]
+        .lines()
+        .enumerate()
+        .map(|(i, l)| {
+            if i == 0 || l.len() - l.trim_start().len() < col {
+                l.to_string()
+            } else {
+                l[col..].to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The tactic script of a replacement, without a leading `by` (`None` for a term). Inside a
+/// tactic block (a mid-proof tail) the text is a script already.
+fn tactic_body(script: &str, in_tactic_block: bool) -> Option<String> {
+    let t = script.trim();
+    if let Some(rest) = t.strip_prefix("by") {
+        if rest.starts_with(char::is_whitespace) || rest.is_empty() {
+            let lines: Vec<&str> = rest.trim_start_matches([' ', '\t']).lines().collect();
+            let first = lines.first().map(|l| l.trim()).unwrap_or("");
+            let tail = dedent(&lines[lines.len().min(1)..]);
+            return Some(
+                std::iter::once(first.to_string())
+                    .chain(tail)
+                    .filter(|l| !l.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+        }
+    }
+    in_tactic_block.then(|| t.to_string())
+}
+
+impl RepairStrategy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Rename => "rename",
+            Self::RaiseLimits => "raise_limits",
+            Self::Convert => "convert",
+            Self::CloseGoals => "close_goals",
+            Self::Instances => "instances",
+            Self::Universes => "universes",
+            Self::Syntax => "syntax",
+            Self::Automation => "automation",
+        }
+    }
+
+    /// One sentence for the LLM prompt.
+    pub fn prompt_hint(self) -> &'static str {
+        match self {
+            Self::Rename => {
+                "A referenced name no longer exists; use its current Mathlib name or an equivalent lemma."
+            }
+            Self::RaiseLimits => {
+                "The previous proof ran out of heartbeats/recursion; prefer targeted lemmas (`rw`, `exact`, `simp only [...]`) over broad search."
+            }
+            Self::Convert => {
+                "The types differ (often casts, coercions, or argument order); consider `exact_mod_cast`, `push_cast`, `convert ... using 2`, or `simpa using`."
+            }
+            Self::CloseGoals => {
+                "The existing steps leave goals open; keep them and add the missing steps."
+            }
+            Self::Instances => {
+                "An instance could not be synthesized; consider `classical`, `haveI`/`letI`, or `inferInstance`."
+            }
+            Self::Universes => {
+                "Universe levels do not match; add explicit universe annotations or use `ULift`."
+            }
+            Self::Syntax => {
+                "The previous script does not parse in this Lean/Mathlib version; use current tactic syntax."
+            }
+            Self::Automation => "The previous tactics failed; try a different approach.",
+        }
+    }
+
+    /// Class-specific candidates. `previous` is what failed (the original proof text at the
+    /// target, or the last failed candidate); seeds that rework it are skipped when it is only a
+    /// placeholder. `in_tactic_block`: `previous` is a tactic script (mid-proof tail), not a term.
+    pub fn seed_candidates(self, previous: Option<&str>, in_tactic_block: bool) -> Vec<String> {
+        let previous = previous.filter(|p| !crate::scan::PLACEHOLDER_TOKENS.contains(&p.trim()));
+        let prev = previous
+            .and_then(|p| tactic_body(p, in_tactic_block))
+            .filter(|b| !b.trim().is_empty());
+        let term = previous
+            .map(str::trim)
+            .filter(|p| !in_tactic_block && tactic_body(p, false).is_none() && !p.contains('\n'));
+        let mut out: Vec<String> = Vec::new();
+        match self {
+            Self::RaiseLimits => {
+                if let Some(b) = &prev {
+                    out.push(format!(
+                        "by\n  set_option maxHeartbeats 800000 in\n{}",
+                        indent(b, "    ")
+                    ));
+                    out.push(format!(
+                        "by\n  set_option maxRecDepth 4000 in\n{}",
+                        indent(b, "    ")
+                    ));
+                }
+                out.push("by\n  (omega; done)".to_string());
+                out.push("by\n  (norm_num; done)".to_string());
+            }
+            Self::Convert => {
+                if let Some(t) = term {
+                    out.push(format!("by\n  exact_mod_cast ({t})"));
+                    out.push(format!("by\n  convert ({t}) using 2"));
+                    out.push(format!("by\n  simpa using ({t})"));
+                }
+                out.push("by\n  push_cast\n  ring".to_string());
+                out.push("by\n  (norm_cast; done)".to_string());
+            }
+            Self::CloseGoals => {
+                if let Some(b) = &prev {
+                    for c in CLOSERS {
+                        out.push(format!("by\n{}\n  all_goals {c}", indent(b, "  ")));
+                    }
+                }
+            }
+            Self::Instances => {
+                if let Some(b) = &prev {
+                    out.push(format!("by\n  classical\n{}", indent(b, "  ")));
+                }
+                out.push("by\n  classical\n  exact inferInstance".to_string());
+                out.push("by\n  classical\n  aesop".to_string());
+            }
+            Self::Rename | Self::Universes | Self::Syntax | Self::Automation => {}
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatches_by_class_and_reworks_the_failed_script() {
+        assert_eq!(
+            strategy_for(ErrorClass::classify(
+                "(deterministic) timeout at `whnf`, maximum number of heartbeats (200000)"
+            )),
+            RepairStrategy::RaiseLimits
+        );
+        assert_eq!(
+            strategy_for(ErrorClass::classify("unknown constant 'Nat.foo'")),
+            RepairStrategy::Rename
+        );
+        let seeds = RepairStrategy::CloseGoals
+            .seed_candidates(Some("by\n  intro x\n  cases x <;> simp"), false);
+        assert_eq!(
+            seeds[0],
+            "by\n  intro x\n  cases x <;> simp\n  all_goals simp"
+        );
+        let text = "theorem t : x = y := by\n  unfold f\n  simp [foo]\n    at h\n  ring\n";
+        let start = text.find("simp").unwrap();
+        let prev = span_script(text, start, text.len() - 1);
+        assert_eq!(prev, "simp [foo]\n  at h\nring");
+        let seeds = RepairStrategy::RaiseLimits.seed_candidates(Some(&prev), true);
+        assert_eq!(
+            seeds[0],
+            "by\n  set_option maxHeartbeats 800000 in\n    simp [foo]\n      at h\n    ring"
+        );
+        assert_eq!(
+            RepairStrategy::Convert.seed_candidates(Some("h.trans rfl"), false)[0],
+            "by\n  exact_mod_cast (h.trans rfl)"
+        );
+        assert!(RepairStrategy::CloseGoals
+            .seed_candidates(Some("sorry"), false)
+            .is_empty());
+    }
+}
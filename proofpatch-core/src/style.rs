@@ -0,0 +1,239 @@
+//! Mathlib style checks for generated proofs.
+//!
+//! A candidate that compiles can still be rejected in review or by mathlib's own linters. These
+//! checks are lexical and cheap (no elaboration), and cover what generated proofs most often get
+//! wrong:
+//! - `line_length`: lines over 100 characters (measured where the text will actually sit);
+//! - `semicolon`: tactics chained with `;` on one line (mathlib puts one tactic per line; `<;>` is
+//!   fine);
+//! - `nonterminal_simp`: a bare `simp`/`simp_all` followed by further tactics (it should be
+//!   `simp only [...]`, or `simpa`, so the proof does not break when the simp set changes);
+//! - `have_name`: `have` names that are not lower-case (`Hx`, Lean 3 style);
+//! - `bullet`: `.` instead of `·` for focusing, `λ` instead of `fun`, `$` instead of `<|`.
+//!
+//! `autofix` rewrites the issues that have a purely mechanical fix (the last group).
+
+use serde::{Deserialize, Serialize};
+
+pub const MAX_LINE_LEN: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StyleIssue {
+    /// `line_length`, `semicolon`, `nonterminal_simp`, `have_name`, or `bullet`.
+    pub rule: String,
+    /// 1-based line within the checked text.
+    pub line: usize,
+    pub message: String,
+}
+
+fn issue(rule: &str, line: usize, message: String) -> StyleIssue {
+    StyleIssue {
+        rule: rule.to_string(),
+        line,
+        message,
+    }
+}
+
+/// Strip a trailing `--` comment and string literals (so `;`/`$` inside them don't count).
+fn code_part(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut in_str = false;
+    let mut prev = '\0';
+    for ch in line.chars() {
+        if in_str {
+            if ch == '"' && prev != '\\' {
+                in_str = false;
+            }
+        } else if ch == '"' {
+            in_str = true;
+        } else if ch == '-' && prev == '-' {
+            out.pop();
+            break;
+        } else {
+            out.push(ch);
+        }
+        prev = ch;
+    }
+    out
+}
+
+/// A top-level `;` separating tactics (not `<;>`, not inside brackets).
+fn has_tactic_semicolon(code: &str) -> bool {
+    let chars: Vec<char> = code.chars().collect();
+    let mut depth = 0i32;
+    for (i, &c) in chars.iter().enumerate() {
+        match c {
+            '(' | '[' | '{' | '⟨' => depth += 1,
+            ')' | ']' | '}' | '⟩' => depth -= 1,
+            ';' if depth == 0 => {
+                let in_seq = i > 0 && chars[i - 1] == '<' && chars.get(i + 1) == Some(&'>');
+                if !in_seq {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+fn is_bare_simp(step: &str) -> bool {
+    let mut words = step.split_whitespace();
+    match words.next() {
+        Some("simp" | "simp_all") => words.next() != Some("only"),
+        _ => false,
+    }
+}
+
+/// Lint `text` (a candidate or a patched region). `first_col` is the column the first line
+/// starts at once patched in (later lines carry their own indentation).
+pub fn lint_text(text: &str, first_col: usize) -> Vec<StyleIssue> {
+    let mut out = Vec::new();
+    let lines: Vec<&str> = text.lines().collect();
+    for (i, l) in lines.iter().enumerate() {
+        let n = i + 1;
+        let width = l.trim_end().chars().count() + if i == 0 { first_col } else { 0 };
+        if width > MAX_LINE_LEN {
+            out.push(issue(
+                "line_length",
+                n,
+                format!("line is {width} characters (limit {MAX_LINE_LEN})"),
+            ));
+        }
+        let code = code_part(l);
+        let t = code.trim();
+        if has_tactic_semicolon(t) {
+            out.push(issue(
+                "semicolon",
+                n,
+                "put one tactic per line instead of chaining with `;`".to_string(),
+            ));
+        }
+        if t.starts_with(". ") || t == "." {
+            out.push(issue(
+                "bullet",
+                n,
+                "use `·` for focusing, not `.`".to_string(),
+            ));
+        }
+        if t.contains('λ') {
+            out.push(issue("bullet", n, "use `fun`, not `λ`".to_string()));
+        }
+        if t.split_whitespace().any(|w| w == "$") {
+            out.push(issue("bullet", n, "use `<|`, not `$`".to_string()));
+        }
+        for (k, w) in t.split_whitespace().enumerate() {
+            if w != "have" {
+                continue;
+            }
+            let name = t.split_whitespace().nth(k + 1).unwrap_or("");
+            if name.chars().next().is_some_and(char::is_uppercase) {
+                out.push(issue(
+                    "have_name",
+                    n,
+                    format!(
+                        "`have {name}`: hypothesis names are lower-case (`h{}`)",
+                        name.chars().skip(1).collect::<String>()
+                    ),
+                ));
+            }
+        }
+    }
+
+    // Non-terminal bare `simp`, per block: a step followed by another step at the same depth.
+    for (i, l) in lines.iter().enumerate() {
+        let code = code_part(l);
+        let t = code.trim().trim_start_matches(['·', '.']).trim();
+        let t = t.strip_prefix("by ").unwrap_or(t);
+        if !is_bare_simp(t) || t.contains("<;>") {
+            continue;
+        }
+        let indent = l.len() - l.trim_start().len();
+        let next = lines[i + 1..]
+            .iter()
+            .find(|x| !x.trim().is_empty() && !x.trim_start().starts_with("--"));
+        let followed = next.is_some_and(|x| {
+            let ind = x.len() - x.trim_start().len();
+            ind == indent && !x.trim_start().starts_with('·')
+        }) || has_tactic_semicolon(t);
+        if followed {
+            out.push(issue(
+                "nonterminal_simp",
+                i + 1,
+                "non-terminal `simp`: use `simp only [...]` (see `simp?`) or close the goal with `simpa`"
+                    .to_string(),
+            ));
+        }
+    }
+    out.sort_by_key(|x| x.line);
+    out
+}
+
+/// Lint a candidate proof on its own (`by` on the first line, or a tactic block).
+pub fn lint_candidate(cand: &str) -> Vec<StyleIssue> {
+    lint_text(cand, 0)
+}
+
+/// Lint the text an edit put into `patched` (`edit` from `patching::apply_patch`).
+pub fn lint_edit(patched: &str, edit: &crate::patching::EditRecord) -> Vec<StyleIssue> {
+    let line_start = patched[..edit.byte_start]
+        .rfind('\n')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let col = patched[line_start..edit.byte_start].chars().count();
+    let mut issues = lint_text(&edit.new_text, col);
+    for i in &mut issues {
+        i.line += edit.line - 1;
+    }
+    issues
+}
+
+/// Rewrite the issues with a mechanical fix: `.` bullets, `λ`, `$`.
+pub fn autofix(text: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|l| {
+            let indent = &l[..l.len() - l.trim_start().len()];
+            let rest = &l[indent.len()..];
+            let rest = match rest.strip_prefix(". ") {
+                Some(r) => format!("· {r}"),
+                None => rest.to_string(),
+            };
+            let code_len = code_part(&rest).len();
+            let (code, tail) = rest.split_at(code_len.min(rest.len()));
+            let code = code
+                .replace('λ', "fun")
+                .split(' ')
+                .map(|w| if w == "$" { "<|" } else { w })
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("{indent}{code}{tail}")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_mathlib_style_issues_and_fixes_mechanical_ones() {
+        let cand = "by\n  have Hx : 0 < n := by omega\n  simp at Hx; exact Hx\n  . simp\n  exact f $ λ x => x -- fine; really\n  rcases h with ⟨a, b⟩ <;> simp\n  simp";
+        let rules: Vec<(usize, String)> = lint_candidate(cand)
+            .into_iter()
+            .map(|i| (i.line, i.rule))
+            .collect();
+        assert!(rules.contains(&(2, "have_name".to_string())));
+        assert!(rules.contains(&(3, "semicolon".to_string())));
+        assert!(rules.contains(&(3, "nonterminal_simp".to_string())));
+        assert!(rules.contains(&(4, "bullet".to_string())));
+        assert_eq!(rules.iter().filter(|(l, _)| *l == 5).count(), 2);
+        assert!(!rules.iter().any(|(l, _)| *l == 6 || *l == 7));
+        assert!(lint_text(&"a".repeat(90), 12)[0].rule == "line_length");
+
+        let fixed = autofix(cand);
+        assert!(
+            fixed.contains("  · simp\n") && fixed.contains("exact f <| fun x => x -- fine; really")
+        );
+        assert!(autofix("by\n  simp only [foo]\n") == "by\n  simp only [foo]\n");
+    }
+}
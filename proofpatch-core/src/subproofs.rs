@@ -0,0 +1,397 @@
+//! Cross-goal sub-proof reuse: closing scripts and `have` sub-lemmas of solved goals, keyed by
+//! goal up to α-equivalence.
+//!
+//! A refactor tends to break many similar proofs the same way, so the goal one declaration left
+//! often reappears, renamed, in another. With `RepairOptions::record_subproofs`, a solved goal is
+//! appended to `.generated/proofpatch-subproofs/subproofs.jsonl` with its closing script, along
+//! with each `have name : T := proof` of the script (as the goal `T` in the same context). With
+//! `RepairOptions::reuse_subproofs`, the repair loop looks its goal up (`SubproofStore::lookup`)
+//! before searching and verifies the hits first, as source "reuse".
+//!
+//! `goal_key` is the binder-normalized goal (`Expr::normalize_binders`) with local names (the
+//! hypotheses, then the binders of `∀`, `∃`, `fun` and big operators) renamed in order of
+//! appearance, so goals that differ only in the names of locals share it. Hits on the whole goal
+//! come before hits on the target alone (`target_key`), whose context may differ.
+
+use crate::goal_ast::{Binder, Expr};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subproof {
+    /// "closing" (the script that closed a goal) or "have" (a sub-lemma proved inside one).
+    pub kind: String,
+    /// The goal, pretty-printed (`hyps…\n⊢ target`).
+    pub goal: String,
+    /// A tactic script (`by …`) proving `goal`.
+    pub proof: String,
+    #[serde(default)]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub decl: Option<String>,
+    /// Unix seconds.
+    #[serde(default)]
+    pub added_at: u64,
+}
+
+/// Renames locals in order of first binding: `x✝1`, `x✝2`, … Names in `free` are bound where
+/// they first occur.
+#[derive(Default)]
+struct Renamer {
+    scope: Vec<(String, String)>,
+    free: HashSet<String>,
+    next: usize,
+}
+
+impl Renamer {
+    /// Bind `name` to a fresh local and return it.
+    fn bind(&mut self, name: &str) -> String {
+        self.next += 1;
+        let fresh = format!("x✝{}", self.next);
+        self.scope.push((name.to_string(), fresh.clone()));
+        fresh
+    }
+
+    fn ident(&mut self, s: &str) -> String {
+        // `h.1`, `h.le`: projections of a local.
+        let (head, rest) = match s.find('.') {
+            Some(i) if i > 0 => s.split_at(i),
+            _ => (s, ""),
+        };
+        if self.free.remove(head) {
+            self.bind(head);
+        }
+        match self.scope.iter().rev().find(|(n, _)| n == head) {
+            Some((_, fresh)) => format!("{fresh}{rest}"),
+            None => s.to_string(),
+        }
+    }
+
+    fn binders(&mut self, binders: &[Binder]) -> Vec<Binder> {
+        binders
+            .iter()
+            .map(|b| {
+                let ty = b.ty.as_ref().map(|t| self.expr(t));
+                let domain = b.domain.as_ref().map(|d| self.expr(d));
+                Binder {
+                    names: b.names.iter().map(|n| self.bind(n)).collect(),
+                    ty,
+                    domain,
+                    info: b.info,
+                }
+            })
+            .collect()
+    }
+
+    fn boxed(&mut self, e: &Expr) -> Box<Expr> {
+        Box::new(self.expr(e))
+    }
+
+    fn expr(&mut self, e: &Expr) -> Expr {
+        match e {
+            Expr::Ident(s) => Expr::Ident(self.ident(s)),
+            Expr::Num(_) | Expr::Str(_) | Expr::Cdot => e.clone(),
+            Expr::App { func, args } => Expr::App {
+                func: self.boxed(func),
+                args: args.iter().map(|a| self.expr(a)).collect(),
+            },
+            Expr::Unary { op, arg } => Expr::Unary {
+                op: *op,
+                arg: self.boxed(arg),
+            },
+            Expr::Binary { op, lhs, rhs } => Expr::Binary {
+                op: op.clone(),
+                lhs: self.boxed(lhs),
+                rhs: self.boxed(rhs),
+            },
+            Expr::Arrow { dom, cod } => Expr::Arrow {
+                dom: self.boxed(dom),
+                cod: self.boxed(cod),
+            },
+            Expr::Binder {
+                kind,
+                binders,
+                body,
+            } => {
+                let depth = self.scope.len();
+                let binders = self.binders(binders);
+                let body = Box::new(self.expr(body));
+                self.scope.truncate(depth);
+                Expr::Binder {
+                    kind: kind.clone(),
+                    binders,
+                    body,
+                }
+            }
+            Expr::Tuple { anonymous, items } => Expr::Tuple {
+                anonymous: *anonymous,
+                items: items.iter().map(|i| self.expr(i)).collect(),
+            },
+            Expr::List(items) => Expr::List(items.iter().map(|i| self.expr(i)).collect()),
+            Expr::Ascription { expr, ty } => Expr::Ascription {
+                expr: self.boxed(expr),
+                ty: self.boxed(ty),
+            },
+        }
+    }
+}
+
+fn collapse(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Key of a pretty-printed goal up to α-equivalence (see the module docs). Goals we cannot parse
+/// fall back to their whitespace-collapsed text.
+pub fn goal_key(pretty: &str) -> String {
+    let Ok(g) = crate::goal_ast::parse_goal(pretty) else {
+        return collapse(pretty);
+    };
+    if !g.unparsed_hyps.is_empty() {
+        return collapse(pretty);
+    }
+    let mut r = Renamer::default();
+    let mut parts: Vec<String> = Vec::new();
+    for h in &g.hyps {
+        let ty = r.expr(&h.ty.normalize_binders());
+        let value = h.value.as_ref().map(|v| r.expr(&v.normalize_binders()));
+        let names: Vec<String> = h.names.iter().map(|n| r.bind(n)).collect();
+        match value {
+            Some(v) => parts.push(format!("{} : {ty} := {v}", names.join(" "))),
+            None => parts.push(format!("{} : {ty}", names.join(" "))),
+        }
+    }
+    parts.push(format!("⊢ {}", r.expr(&g.target.normalize_binders())));
+    parts.join("\n")
+}
+
+/// Key of the goal's target alone, its free locals renamed in order of appearance.
+pub fn target_key(pretty: &str) -> String {
+    let (_, target) = crate::lean_lsp::split_plain_goal(pretty);
+    let target = target.unwrap_or_else(|| pretty.to_string());
+    let Ok(e) = crate::goal_ast::parse_expr(&target) else {
+        return collapse(&target);
+    };
+    let e = e.normalize_binders();
+    // Free names that are hypotheses of the goal count as locals.
+    let mut r = Renamer {
+        free: crate::goal_ast::parse_goal(pretty)
+            .map(|g| g.hyps.into_iter().flat_map(|h| h.names).collect())
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    r.expr(&e).to_string()
+}
+
+/// The `have name : T := proof` steps of a script, as (T, proof as a `by` script). Multi-line
+/// proofs are the lines indented deeper than the `have`.
+pub fn have_steps(script: &str) -> Vec<(String, String)> {
+    let lines: Vec<&str> = script.lines().collect();
+    let indent = |l: &str| l.len() - l.trim_start().len();
+    let mut out = Vec::new();
+    for (i, l) in lines.iter().enumerate() {
+        let t = l.trim_start();
+        let Some(rest) = t.strip_prefix("have ") else {
+            continue;
+        };
+        let Some((head, proof)) = rest.split_once(":=") else {
+            continue;
+        };
+        let Some((_, stmt)) = head.split_once(" : ").or_else(|| head.split_once(": ")) else {
+            continue;
+        };
+        let mut body: Vec<String> = vec![proof.trim().to_string()];
+        body.extend(
+            lines[i + 1..]
+                .iter()
+                .take_while(|n| n.trim().is_empty() || indent(n) > indent(l))
+                .map(|n| n.trim().to_string())
+                .filter(|n| !n.is_empty()),
+        );
+        let proof = match body[0].strip_prefix("by") {
+            Some(first) if first.is_empty() || first.starts_with(char::is_whitespace) => {
+                let mut steps: Vec<String> = Vec::new();
+                if !first.trim().is_empty() {
+                    steps.push(first.trim().to_string());
+                }
+                steps.extend(body[1..].iter().cloned());
+                crate::beam::render_script(&steps)
+            }
+            _ => format!("by\n  exact {}", body.join(" ")),
+        };
+        if !stmt.trim().is_empty() && proof.lines().count() > 1 {
+            out.push((stmt.trim().to_string(), proof));
+        }
+    }
+    out
+}
+
+/// The entries to record for `goal` solved by `proof`: the closing script, and one entry per
+/// `have` in it (its statement as the target, in the goal's context).
+pub fn entries_for(goal: &str, proof: &str, file: &str, decl: &str, now: u64) -> Vec<Subproof> {
+    let (hyps, _) = crate::lean_lsp::split_plain_goal(goal);
+    let entry = |kind: &str, goal: String, proof: String| Subproof {
+        kind: kind.to_string(),
+        goal,
+        proof,
+        file: Some(file.to_string()),
+        decl: Some(decl.to_string()),
+        added_at: now,
+    };
+    let mut out = vec![entry("closing", goal.to_string(), proof.to_string())];
+    for (stmt, p) in have_steps(proof) {
+        let mut g = hyps.join("\n");
+        if !g.is_empty() {
+            g.push('\n');
+        }
+        g.push_str(&format!("⊢ {stmt}"));
+        out.push(entry("have", g, p));
+    }
+    out
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SubproofStore {
+    pub entries: Vec<Subproof>,
+    by_goal: HashMap<String, Vec<usize>>,
+    by_target: HashMap<String, Vec<usize>>,
+}
+
+fn proof_key(proof: &str) -> String {
+    crate::goal_ast::candidate_key(proof)
+}
+
+impl SubproofStore {
+    pub fn path(repo_root: &Path) -> PathBuf {
+        repo_root
+            .join(".generated")
+            .join("proofpatch-subproofs")
+            .join("subproofs.jsonl")
+    }
+
+    fn from_entries(entries: Vec<Subproof>) -> Self {
+        let mut s = Self::default();
+        let mut seen = HashSet::new();
+        for e in entries {
+            if !seen.insert((goal_key(&e.goal), proof_key(&e.proof))) {
+                continue;
+            }
+            let i = s.entries.len();
+            s.by_goal.entry(goal_key(&e.goal)).or_default().push(i);
+            s.by_target.entry(target_key(&e.goal)).or_default().push(i);
+            s.entries.push(e);
+        }
+        s
+    }
+
+    /// The repo's store (empty when there is none yet). Unparseable lines are skipped, and
+    /// repeated (goal, proof) pairs count once.
+    pub fn load(repo_root: &Path) -> Self {
+        let txt = std::fs::read_to_string(Self::path(repo_root)).unwrap_or_default();
+        Self::from_entries(
+            txt.lines()
+                .filter_map(|l| serde_json::from_str::<Subproof>(l).ok())
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Proofs recorded for goals α-equivalent to `goal`, then for goals with an α-equivalent
+    /// target (newest first within each), without repeats.
+    pub fn lookup(&self, goal: &str) -> Vec<&Subproof> {
+        let mut out: Vec<&Subproof> = Vec::new();
+        let mut seen = HashSet::new();
+        for idx in [
+            self.by_goal.get(&goal_key(goal)),
+            self.by_target.get(&target_key(goal)),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            for &i in idx.iter().rev() {
+                let e = &self.entries[i];
+                if seen.insert(proof_key(&e.proof)) {
+                    out.push(e);
+                }
+            }
+        }
+        out
+    }
+
+    /// Append the entries not already in the repo's store. Returns how many lines were written.
+    pub fn record(repo_root: &Path, entries: &[Subproof]) -> Result<usize, String> {
+        let store = Self::load(repo_root);
+        let mut have: HashSet<(String, String)> = store
+            .entries
+            .iter()
+            .map(|e| (goal_key(&e.goal), proof_key(&e.proof)))
+            .collect();
+        let new: Vec<&Subproof> = entries
+            .iter()
+            .filter(|e| !e.goal.trim().is_empty() && !e.proof.trim().is_empty())
+            .filter(|e| have.insert((goal_key(&e.goal), proof_key(&e.proof))))
+            .collect();
+        if new.is_empty() {
+            return Ok(0);
+        }
+        let p = Self::path(repo_root);
+        if let Some(dir) = p.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("mkdir {}: {e}", dir.display()))?;
+        }
+        let mut f = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&p)
+            .map_err(|e| format!("open {}: {e}", p.display()))?;
+        for e in &new {
+            let line = serde_json::to_string(e).map_err(|e| e.to_string())?;
+            writeln!(f, "{line}").map_err(|e| format!("write {}: {e}", p.display()))?;
+        }
+        Ok(new.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alpha_equivalent_goals_share_a_key() {
+        let a = "n m : ℕ\nh : n < m\n⊢ ∀ k, n + k < m + k";
+        let b = "a b : ℕ\nhab : a < b\n⊢ ∀ (j : ℕ), a + j < b + j";
+        let c = "a b : ℕ\nhab : b < a\n⊢ ∀ (j : ℕ), a + j < b + j";
+        assert_eq!(
+            goal_key(a),
+            goal_key("n m : ℕ\nh : n < m\n⊢ ∀ j, n + j < m + j")
+        );
+        assert_ne!(goal_key(a), goal_key(c));
+        assert_eq!(target_key(b), target_key(c));
+        // Globals keep their names.
+        assert_ne!(goal_key("x : ℕ\n⊢ f x = 0"), goal_key("x : ℕ\n⊢ g x = 0"));
+
+        let proof = "by\n  have hk : n + 1 ≤ m := by\n    omega\n  intro k\n  omega";
+        assert_eq!(
+            have_steps(proof),
+            vec![("n + 1 ≤ m".to_string(), "by\n  omega".to_string())]
+        );
+        let entries = entries_for(a, proof, "A.lean", "t", 1);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].goal, "n : ℕ\nm : ℕ\nh : n < m\n⊢ n + 1 ≤ m");
+
+        let store = SubproofStore::from_entries(entries);
+        let hits = store.lookup("x y : ℕ\nhxy : x < y\n⊢ ∀ i, x + i < y + i");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].kind, "closing");
+        let hits = store.lookup("p q : ℕ\nhpq : p < q\n⊢ p + 1 ≤ q");
+        assert_eq!(hits[0].proof, "by\n  omega");
+        assert!(store.lookup("⊢ True").is_empty());
+    }
+}
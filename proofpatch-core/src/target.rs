@@ -0,0 +1,166 @@
+//! One repair target, addressed as `<file>:<line>` or by declaration name (`proofpatch patch`).
+//!
+//! `Target::parse` reads the address: a `.lean` path with a line number after its last `:` is a
+//! location, anything else a declaration name. `resolve` turns it into the declaration to repair:
+//! a location names the declaration around the line (`nearest_decl_header_in_text`), the same
+//! ids `worklist` items carry, and the placeholder on that line when there is one (`sorry_index`,
+//! so a declaration's second `sorry` can be addressed); a name is looked up across the repo's
+//! `.lean` files and must be declared in exactly one of them.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Location { file: String, line: usize },
+    Decl(String),
+}
+
+impl Target {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return Err("empty target (expected <file>:<line> or a declaration name)".to_string());
+        }
+        if let Some((file, line)) = spec.rsplit_once(':') {
+            if file.ends_with(".lean") {
+                let line = line
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&l| l > 0)
+                    .ok_or_else(|| format!("bad line in target {spec} (expected <file>:<line>)"))?;
+                return Ok(Self::Location {
+                    file: file.trim_start_matches("./").to_string(),
+                    line,
+                });
+            }
+        }
+        Ok(Self::Decl(spec.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedTarget {
+    /// Repo-relative path.
+    pub file: String,
+    pub decl: String,
+    /// 1-based header line.
+    pub decl_line: usize,
+    /// The addressed line (`<file>:<line>` targets).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// The placeholder on `line`, 0-based among the declaration's placeholders (the repair's
+    /// `sorry_index`); `None`: the declaration's first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sorry_index: Option<usize>,
+}
+
+/// The declaration `target` addresses in the repo at `repo_root`.
+pub fn resolve(repo_root: &Path, target: &Target) -> Result<ResolvedTarget, String> {
+    match target {
+        Target::Location { file, line } => {
+            let abs = repo_root.join(file);
+            let text = std::fs::read_to_string(&abs)
+                .map_err(|e| format!("read {}: {e}", abs.display()))?;
+            let d = crate::nearest_decl_header_in_text(&text, *line, 20_000)
+                .ok_or_else(|| format!("no declaration at {file}:{line}"))?;
+            let sorry_index = crate::patching::decl_placeholders(&text, &d.name)
+                .unwrap_or_default()
+                .iter()
+                .position(|&(s, _)| text[..s].matches('\n').count() + 1 == *line);
+            Ok(ResolvedTarget {
+                file: file.clone(),
+                decl: d.name,
+                decl_line: d.line,
+                line: Some(*line),
+                sorry_index,
+            })
+        }
+        Target::Decl(name) => {
+            let mut found: Vec<ResolvedTarget> = Vec::new();
+            for f in crate::scan::list_lean_files(repo_root) {
+                let Ok(text) = std::fs::read_to_string(repo_root.join(&f)) else {
+                    continue;
+                };
+                if !text.contains(name.as_str()) {
+                    continue;
+                }
+                if let Ok((s, _)) = crate::patching::decl_byte_range(&text, name) {
+                    found.push(ResolvedTarget {
+                        decl_line: text[..s].matches('\n').count() + 1,
+                        file: f,
+                        decl: name.clone(),
+                        line: None,
+                        sorry_index: None,
+                    });
+                }
+            }
+            match found.len() {
+                0 => Err(format!(
+                    "declaration {name} not found under {}",
+                    repo_root.display()
+                )),
+                1 => Ok(found.remove(0)),
+                _ => {
+                    let at: Vec<String> = found
+                        .iter()
+                        .map(|t| format!("{}:{}", t.file, t.decl_line))
+                        .collect();
+                    Err(format!(
+                        "declaration {name} is ambiguous ({}); address it as <file>:<line>",
+                        at.join(", ")
+                    ))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_resolves_targets() {
+        assert_eq!(
+            Target::parse("./Foo/A.lean:12").unwrap(),
+            Target::Location {
+                file: "Foo/A.lean".into(),
+                line: 12
+            }
+        );
+        assert_eq!(
+            Target::parse("Nat.foo_le").unwrap(),
+            Target::Decl("Nat.foo_le".into())
+        );
+        assert!(Target::parse("Foo/A.lean:x").is_err());
+        assert!(Target::parse("Foo/A.lean:0").is_err());
+
+        let td = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(td.path().join("Foo")).unwrap();
+        std::fs::write(
+            td.path().join("Foo/A.lean"),
+            "theorem a : True := trivial\n\ntheorem b (n : ℕ) : n = n ∧ n = n := by\n  \
+             constructor\n  · sorry\n  · sorry\n",
+        )
+        .unwrap();
+        std::fs::write(td.path().join("Foo/B.lean"), "theorem a : 1 = 1 := rfl\n").unwrap();
+
+        let at = |spec: &str| resolve(td.path(), &Target::parse(spec).unwrap());
+        let t = at("Foo/A.lean:6").unwrap();
+        assert_eq!(
+            (t.file.as_str(), t.decl.as_str(), t.decl_line),
+            ("Foo/A.lean", "b", 3)
+        );
+        assert_eq!((t.line, t.sorry_index), (Some(6), Some(1)));
+        assert_eq!(at("Foo/A.lean:5").unwrap().sorry_index, Some(0));
+        assert_eq!(at("Foo/A.lean:4").unwrap().sorry_index, None);
+        let by_name = at("b").unwrap();
+        assert_eq!(
+            (by_name.decl_line, by_name.line, by_name.sorry_index),
+            (3, None, None)
+        );
+        assert!(at("a").unwrap_err().contains("Foo/A.lean:1, Foo/B.lean:1"));
+        assert!(at("c").is_err());
+    }
+}
@@ -0,0 +1,336 @@
+//! Lean toolchain / Mathlib version detection and capability flags.
+//!
+//! Reads `lean-toolchain`, `lakefile.lean` / `lakefile.toml`, and `lake-manifest.json` to learn
+//! which Lean and Mathlib versions a repo is pinned to, and derives `Capabilities` from the Lean
+//! version. Modules that emit tactics or parse pretty-printed output consult these flags instead
+//! of assuming the newest toolchain.
+//!
+//! Version thresholds are the first stable release that shipped the feature in Lean core. When
+//! the version cannot be determined (missing file, `nightly-…`, custom toolchain) every
+//! version-gated flag is `true`: new repos are the common case, and an unavailable tactic only
+//! costs one failed candidate. Mathlib-only tactics are gated on Mathlib being a dependency.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LeanVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// `true` for `-rcN` releases (ordered before the release itself).
+    pub rc: bool,
+}
+
+impl LeanVersion {
+    /// Parse `leanprover/lean4:v4.9.0-rc1`, `v4.9.0`, or `4.9.0`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let s = s.rsplit_once(':').map(|(_, v)| v).unwrap_or(s);
+        let s = s.strip_prefix('v').unwrap_or(s);
+        let (core, pre) = match s.split_once('-') {
+            Some((c, p)) => (c, Some(p)),
+            None => (s, None),
+        };
+        let mut it = core.split('.').map(|x| x.parse::<u32>());
+        let major = it.next()?.ok()?;
+        let minor = it.next()?.ok()?;
+        let patch = it.next().unwrap_or(Ok(0)).ok()?;
+        Some(Self {
+            major,
+            minor,
+            patch,
+            rc: pre.is_some_and(|p| p.starts_with("rc")),
+        })
+    }
+
+    /// At least `major.minor.0` (release candidates of that version count).
+    pub fn at_least(&self, major: u32, minor: u32) -> bool {
+        (self.major, self.minor) >= (major, minor)
+    }
+}
+
+/// Feature flags derived from the Lean version (and Mathlib presence).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// `omega` in Lean core (v4.7).
+    pub omega: bool,
+    /// `exact?` / `apply?` in Lean core (v4.8); older toolchains need Std/Mathlib.
+    pub exact_question: bool,
+    /// `rw?` (Mathlib, later core).
+    pub rw_question: bool,
+    /// `bv_decide` (v4.12).
+    pub bv_decide: bool,
+    /// `grind` (v4.22).
+    pub grind: bool,
+    /// Generalized field notation in the pretty printer (v4.8): goals show `n.succ` rather than
+    /// `Nat.succ n`.
+    pub pp_generalized_field_notation: bool,
+    /// `lakefile.toml` configurations (v4.10's Lake; earlier only `lakefile.lean`).
+    pub lakefile_toml: bool,
+    /// Mathlib is a dependency (tactics like `linarith`, `positivity`, `norm_num` exist).
+    pub mathlib: bool,
+}
+
+impl Capabilities {
+    pub fn for_version(v: Option<LeanVersion>, mathlib: bool) -> Self {
+        let at = |maj: u32, min: u32| v.is_none_or(|v| v.at_least(maj, min));
+        Self {
+            omega: at(4, 7),
+            exact_question: at(4, 8) || mathlib,
+            rw_question: mathlib,
+            bv_decide: at(4, 12),
+            grind: at(4, 22),
+            pp_generalized_field_notation: at(4, 8),
+            lakefile_toml: at(4, 10),
+            mathlib,
+        }
+    }
+
+    /// Whether every tactic a candidate script uses is available.
+    ///
+    /// Only checks tactics gated by a flag; anything else is assumed to exist.
+    pub fn supports_candidate(&self, candidate: &str) -> bool {
+        let uses = |t: &str| {
+            candidate
+                .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '?'))
+                .any(|w| w == t)
+        };
+        let mathlib_only = [
+            "linarith",
+            "nlinarith",
+            "positivity",
+            "norm_num",
+            "ring",
+            "ring_nf",
+            "aesop",
+            "field_simp",
+            "polyrith",
+        ];
+        !(uses("omega") && !self.omega
+            || (uses("exact?") || uses("apply?")) && !self.exact_question
+            || uses("rw?") && !self.rw_question
+            || uses("bv_decide") && !self.bv_decide
+            || uses("grind") && !self.grind
+            || !self.mathlib && mathlib_only.iter().any(|t| uses(t)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dependency {
+    pub name: String,
+    /// Requested revision (`@ "v4.9.0"` / `rev = "..."`), if pinned in the lakefile.
+    pub input_rev: Option<String>,
+    /// Resolved commit from `lake-manifest.json`.
+    pub rev: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolchainInfo {
+    /// Raw `lean-toolchain` contents (trimmed).
+    pub toolchain: Option<String>,
+    pub lean_version: Option<LeanVersion>,
+    /// `lakefile.lean` or `lakefile.toml`.
+    pub lakefile: Option<String>,
+    pub dependencies: Vec<Dependency>,
+    pub capabilities: Capabilities,
+}
+
+impl ToolchainInfo {
+    pub fn mathlib(&self) -> Option<&Dependency> {
+        self.dependencies.iter().find(|d| d.name == "mathlib")
+    }
+}
+
+fn unquote(s: &str) -> String {
+    s.trim().trim_matches('"').trim().to_string()
+}
+
+/// `require` names and pinned revisions from a `lakefile.lean`.
+///
+/// Handles `require mathlib from git "url" @ "rev"` and the newer
+/// `require "leanprover-community" / "mathlib" @ git "rev"`.
+pub fn parse_lakefile_lean(text: &str) -> Vec<Dependency> {
+    let mut out = Vec::new();
+    let mut lines = text.lines().peekable();
+    while let Some(ln) = lines.next() {
+        let Some(rest) = ln.trim_start().strip_prefix("require ") else {
+            continue;
+        };
+        // The `@ "rev"` part is sometimes on a continuation line.
+        let mut stmt = rest.to_string();
+        while let Some(next) = lines.peek() {
+            if next.starts_with([' ', '\t']) && !next.trim().is_empty() {
+                stmt.push(' ');
+                stmt.push_str(next.trim());
+                lines.next();
+            } else {
+                break;
+            }
+        }
+        let (head, rev) = match stmt.split_once('@') {
+            Some((h, r)) => (h.trim(), Some(r.trim())),
+            None => (stmt.trim(), None),
+        };
+        let name_part = head.split(" from ").next().unwrap_or(head);
+        let name = unquote(name_part.rsplit('/').next().unwrap_or(name_part))
+            .trim_start_matches('«')
+            .trim_end_matches('»')
+            .to_string();
+        let input_rev = rev
+            .map(|r| r.trim_start_matches("git").trim())
+            .and_then(|r| r.split('"').nth(1))
+            .map(|r| r.to_string());
+        if !name.is_empty() {
+            out.push(Dependency {
+                name,
+                input_rev,
+                rev: None,
+            });
+        }
+    }
+    out
+}
+
+/// `[[require]]` tables from a `lakefile.toml` (only the `name` / `rev` keys).
+pub fn parse_lakefile_toml(text: &str) -> Vec<Dependency> {
+    let mut out: Vec<Dependency> = Vec::new();
+    let mut in_require = false;
+    for ln in text.lines() {
+        let t = ln.trim();
+        if t.starts_with('[') {
+            in_require = t == "[[require]]";
+            if in_require {
+                out.push(Dependency {
+                    name: String::new(),
+                    input_rev: None,
+                    rev: None,
+                });
+            }
+            continue;
+        }
+        if !in_require {
+            continue;
+        }
+        let Some((k, v)) = t.split_once('=') else {
+            continue;
+        };
+        if let Some(d) = out.last_mut() {
+            match k.trim() {
+                "name" => d.name = unquote(v),
+                "rev" => d.input_rev = Some(unquote(v)),
+                _ => {}
+            }
+        }
+    }
+    out.retain(|d| !d.name.is_empty());
+    out
+}
+
+/// Fill resolved revisions from a `lake-manifest.json` (adds packages the lakefile didn't list,
+/// e.g. transitive dependencies).
+pub fn apply_manifest(deps: &mut Vec<Dependency>, manifest: &Value) {
+    let Some(pkgs) = manifest.get("packages").and_then(|p| p.as_array()) else {
+        return;
+    };
+    for p in pkgs {
+        let Some(name) = p.get("name").and_then(|n| n.as_str()) else {
+            continue;
+        };
+        let get = |k: &str| p.get(k).and_then(|v| v.as_str()).map(|s| s.to_string());
+        match deps.iter_mut().find(|d| d.name == name) {
+            Some(d) => {
+                d.rev = get("rev");
+                if d.input_rev.is_none() {
+                    d.input_rev = get("inputRev");
+                }
+            }
+            None => deps.push(Dependency {
+                name: name.to_string(),
+                input_rev: get("inputRev"),
+                rev: get("rev"),
+            }),
+        }
+    }
+}
+
+/// Detect toolchain information for a Lean repo root. Missing files are not errors.
+pub fn detect(repo_root: &Path) -> ToolchainInfo {
+    let read = |f: &str| std::fs::read_to_string(repo_root.join(f)).ok();
+    let toolchain = read("lean-toolchain")
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    let lean_version = toolchain.as_deref().and_then(LeanVersion::parse);
+
+    let (lakefile, mut dependencies) = if let Some(t) = read("lakefile.lean") {
+        (Some("lakefile.lean".to_string()), parse_lakefile_lean(&t))
+    } else if let Some(t) = read("lakefile.toml") {
+        (Some("lakefile.toml".to_string()), parse_lakefile_toml(&t))
+    } else {
+        (None, Vec::new())
+    };
+    if let Some(m) = read("lake-manifest.json").and_then(|s| serde_json::from_str(&s).ok()) {
+        apply_manifest(&mut dependencies, &m);
+    }
+    let mathlib = dependencies.iter().any(|d| d.name == "mathlib");
+    ToolchainInfo {
+        toolchain,
+        lean_version,
+        lakefile,
+        dependencies,
+        capabilities: Capabilities::for_version(lean_version, mathlib),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_versions_and_gates_capabilities() {
+        let v = LeanVersion::parse("leanprover/lean4:v4.6.0-rc1").unwrap();
+        assert_eq!((v.major, v.minor, v.patch, v.rc), (4, 6, 0, true));
+        assert!(LeanVersion::parse("leanprover/lean4:nightly-2024-03-01").is_none());
+
+        let old = Capabilities::for_version(Some(v), true);
+        assert!(!old.omega && old.exact_question && !old.pp_generalized_field_notation);
+        assert!(!old.supports_candidate("by\n  omega"));
+        assert!(old.supports_candidate("by\n  simp [Nat.omega_foo]"));
+
+        let core_only = Capabilities::for_version(LeanVersion::parse("v4.9.0"), false);
+        assert!(core_only.omega && !core_only.rw_question);
+        assert!(!core_only.supports_candidate("by\n  linarith"));
+        assert!(Capabilities::for_version(None, false).grind);
+    }
+
+    #[test]
+    fn parses_lakefiles_and_manifest() {
+        let lean = r#"
+import Lake
+open Lake DSL
+
+require mathlib from git
+  "https://github.com/leanprover-community/mathlib4.git" @ "v4.9.0"
+require "leanprover-community" / "batteries" @ git "main"
+"#;
+        let mut deps = parse_lakefile_lean(lean);
+        assert_eq!(deps[0].name, "mathlib");
+        assert_eq!(deps[0].input_rev.as_deref(), Some("v4.9.0"));
+        assert_eq!(deps[1].name, "batteries");
+        assert_eq!(deps[1].input_rev.as_deref(), Some("main"));
+
+        let manifest = serde_json::json!({"packages": [
+            {"name": "mathlib", "rev": "abc123", "inputRev": "v4.9.0"},
+            {"name": "aesop", "rev": "def456", "inputRev": "master"}
+        ]});
+        apply_manifest(&mut deps, &manifest);
+        assert_eq!(deps[0].rev.as_deref(), Some("abc123"));
+        assert_eq!(deps.len(), 3);
+
+        let toml = "name = \"Foo\"\n\n[[require]]\nname = \"mathlib\"\nscope = \"leanprover-community\"\nrev = \"v4.12.0\"\n\n[[lean_lib]]\nname = \"Foo\"\n";
+        let deps = parse_lakefile_toml(toml);
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].input_rev.as_deref(), Some("v4.12.0"));
+    }
+}
@@ -209,9 +209,8 @@ pub fn sanitize_candidates(mut xs: Vec<String>) -> Vec<String> {
     xs.retain(|s| !s.trim().is_empty());
     xs.truncate(24);
     xs.retain(|s| s.chars().count() <= 4_000);
-    // Deduplicate while preserving order.
-    let mut seen = std::collections::HashSet::new();
-    xs.retain(|s| seen.insert(s.clone()));
+    // Deduplicate while preserving order (up to `goal_ast::candidate_key` normalization).
+    crate::goal_ast::dedup_candidates(&mut xs);
     xs
 }
 
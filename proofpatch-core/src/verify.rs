@@ -0,0 +1,569 @@
+//! `lake build` verification of candidate patches.
+//!
+//! `verify_lean_text` checks a single buffer with `lake env lean`. That is fast, but it does not
+//! prove the patched module still builds as part of the project (downstream imports, `lake`
+//! options, module-level linters). This runner applies each candidate to a scratch working copy
+//! (`working_copy`: the repo's sources under `.generated/proofpatch-verify/worktree`, sharing its
+//! `.lake/packages`), runs `lake build <Module>` there (falling back to a full `lake build` when
+//! the module name cannot be derived; optionally also the in-repo modules that import it),
+//! records the outcome with timing, and restores the original file text. The user's checkout is
+//! never written, so an interrupted build cannot leave a candidate in it.
+//!
+//! Candidates are verified sequentially: builds share the working copy and must not race. Each
+//! `verify_candidates` call holds an exclusive lock on it (`lock_working_copy`), so concurrent runs
+//! on the same repo, in this process or others, wait their turn.
+//!
+//! With `early_abort`, build output is read as it streams and the build is killed as soon as an
+//! error inside the candidate's declaration (`CandidatePatch::decl`; the whole file when unset)
+//! is printed (`is_fatal_line`): the candidate has failed already, and the rest of the build
+//! (later declarations, dependents) would only delay the next one.
+
+use crate::{module_name_from_file_rel, parse_first_error_loc, resolve_lake, DiagnosticLoc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+/// One candidate: the full replacement text for one repo-relative file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CandidatePatch {
+    pub id: String,
+    pub file: String,
+    pub new_text: String,
+    /// The declaration the candidate repairs; scopes `early_abort` to its lines.
+    #[serde(default)]
+    pub decl: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CandidateVerification {
+    pub id: String,
+    pub file: String,
+    pub ok: bool,
+    pub timeout: bool,
+    pub returncode: Option<i32>,
+    /// The `lake build` targets we ran (space-separated module names, or empty for a
+    /// whole-project build).
+    pub target: String,
+    pub elapsed_ms: u64,
+    pub errors: usize,
+    pub sorry_warnings: usize,
+    pub first_error: Option<String>,
+    pub first_error_loc: Option<DiagnosticLoc>,
+    /// Bounded tail of merged stdout/stderr (for humans; not parsed downstream).
+    pub output_tail: String,
+    /// The build was killed at the candidate's first error (`early_abort`).
+    #[serde(default)]
+    pub aborted: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildVerifyOptions {
+    pub timeout: Duration,
+    /// Build only the affected module when its name can be derived (default: true).
+    pub scoped: bool,
+    /// With `scoped`, also rebuild in-repo modules that import the patched one
+    /// (`import_graph::ImportGraph::build_targets`), so downstream breakage is caught.
+    pub with_dependents: bool,
+    /// Stop after the first passing candidate.
+    pub stop_on_first_ok: bool,
+    /// Treat `declaration uses 'sorry'` warnings as failures (default: true).
+    pub reject_sorry_warnings: bool,
+    pub max_output_tail_chars: usize,
+    /// Kill the build at the first error inside the candidate's declaration (default: true).
+    pub early_abort: bool,
+}
+
+impl Default for BuildVerifyOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(600),
+            scoped: true,
+            with_dependents: false,
+            stop_on_first_ok: false,
+            reject_sorry_warnings: true,
+            max_output_tail_chars: 4_000,
+            early_abort: true,
+        }
+    }
+}
+
+/// Restores a working-copy file's original text on drop, so the next candidate (and a panic or
+/// early return) starts from the repo's text.
+struct RestoreGuard {
+    path: PathBuf,
+    original: String,
+}
+
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::write(&self.path, self.original.as_bytes());
+    }
+}
+
+fn tail_chars(s: &str, max: usize) -> String {
+    let n = s.chars().count();
+    if n <= max {
+        return s.to_string();
+    }
+    s.chars().skip(n - max).collect()
+}
+
+/// Run `lake build [target]` in `repo_root` with a timeout.
+///
+/// Returns `(ok, timeout, returncode, stdout, stderr)`.
+pub async fn lake_build(
+    repo_root: &Path,
+    target: Option<&str>,
+    timeout: Duration,
+) -> (bool, bool, Option<i32>, String, String) {
+    let targets: Vec<String> = target.map(|t| t.to_string()).into_iter().collect();
+    lake_build_targets(repo_root, &targets, timeout).await
+}
+
+/// Run `lake build <targets...>` (the whole project when `targets` is empty).
+pub async fn lake_build_targets(
+    repo_root: &Path,
+    targets: &[String],
+    timeout: Duration,
+) -> (bool, bool, Option<i32>, String, String) {
+    lake_build_targets_until(repo_root, targets, timeout, |_| false)
+        .await
+        .0
+}
+
+/// `lake_build_targets`, killing the build at the first output line `abort` accepts. The second
+/// value is that line.
+pub async fn lake_build_targets_until(
+    repo_root: &Path,
+    targets: &[String],
+    timeout: Duration,
+    abort: impl Fn(&str) -> bool,
+) -> ((bool, bool, Option<i32>, String, String), Option<String>) {
+    let mut cmd = Command::new(resolve_lake());
+    cmd.arg("build").args(targets).current_dir(repo_root);
+    run_until(cmd, timeout, abort).await
+}
+
+/// Run `cmd`, reading stdout and stderr line by line as they arrive, until it exits, `timeout`
+/// passes, or `abort` accepts a line (then the process is killed). Output that is not UTF-8 is
+/// decoded lossily.
+pub(crate) async fn run_until(
+    mut cmd: Command,
+    timeout: Duration,
+    abort: impl Fn(&str) -> bool,
+) -> ((bool, bool, Option<i32>, String, String), Option<String>) {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    cmd.stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = match cmd.spawn() {
+        Ok(c) => c,
+        Err(e) => {
+            let msg = format!("failed to execute: {e}");
+            return ((false, false, None, String::new(), msg), None);
+        }
+    };
+    let (Some(out), Some(err)) = (child.stdout.take(), child.stderr.take()) else {
+        return ((false, false, None, String::new(), String::new()), None);
+    };
+    let (mut out, mut err) = (BufReader::new(out), BufReader::new(err));
+    let (mut stdout, mut stderr) = (String::new(), String::new());
+    let mut aborted: Option<String> = None;
+    let run = async {
+        let (mut out_buf, mut err_buf) = (Vec::new(), Vec::new());
+        let (mut out_open, mut err_open) = (true, true);
+        while out_open || err_open {
+            // `read_until` is cancel safe: a partial line stays in its buffer.
+            let (n, from_out) = tokio::select! {
+                n = out.read_until(b'\n', &mut out_buf), if out_open => (n, true),
+                n = err.read_until(b'\n', &mut err_buf), if err_open => (n, false),
+            };
+            let (raw, text, open) = if from_out {
+                (&mut out_buf, &mut stdout, &mut out_open)
+            } else {
+                (&mut err_buf, &mut stderr, &mut err_open)
+            };
+            if !matches!(n, Ok(n) if n > 0) {
+                *open = false;
+                continue;
+            }
+            let line = String::from_utf8_lossy(raw);
+            let line = line.trim_end_matches(['\n', '\r']);
+            text.push_str(line);
+            text.push('\n');
+            if abort(line) {
+                aborted = Some(line.to_string());
+                return None;
+            }
+            raw.clear();
+        }
+        child.wait().await.ok()
+    };
+    let status = match tokio::time::timeout(timeout, run).await {
+        Ok(Some(status)) => status,
+        finished => {
+            let _ = child.kill().await;
+            return ((false, finished.is_err(), None, stdout, stderr), aborted);
+        }
+    };
+    (
+        (status.success(), false, status.code(), stdout, stderr),
+        None,
+    )
+}
+
+/// The 1-based line range of `decl` in `text`.
+pub fn decl_lines(text: &str, decl: &str) -> Option<(usize, usize)> {
+    let (s, e) = crate::patching::decl_byte_range(text, decl).ok()?;
+    let first = text[..s].matches('\n').count() + 1;
+    Some((first, first + text[s..e].matches('\n').count()))
+}
+
+/// Whether a build output line is an error inside `file` (repo-relative), within the 1-based
+/// line range `lines` when given.
+pub fn is_fatal_line(line: &str, file: &str, lines: Option<(usize, usize)>) -> bool {
+    crate::diagnostics::parse_diagnostics(line)
+        .first()
+        .is_some_and(|d| {
+            d.severity == crate::diagnostics::Severity::Error
+                && d.class != crate::diagnostics::ErrorClass::Linter
+                && Path::new(&d.file).ends_with(file)
+                && lines.is_none_or(|(a, b)| (a..=b).contains(&d.line))
+        })
+}
+
+/// Summarize one build into a `CandidateVerification`. `sorry_warnings` counts only the
+/// warnings inside `file` (repo-relative), within the 1-based line range `lines` when given:
+/// other declarations, dependencies and dependents may still be admitted.
+pub fn summarize_build(
+    id: &str,
+    file: &str,
+    lines: Option<(usize, usize)>,
+    target: &str,
+    build: (bool, bool, Option<i32>, String, String),
+    elapsed: Duration,
+    opts: &BuildVerifyOptions,
+) -> CandidateVerification {
+    let (ok, timeout, returncode, stdout, stderr) = build;
+    let diags = crate::diagnostics::parse_diagnostics_from(&stdout, &stderr);
+    let errors = diags
+        .iter()
+        .filter(|d| d.severity == crate::diagnostics::Severity::Error)
+        .count();
+    let sorry_warnings = diags
+        .iter()
+        .filter(|d| {
+            d.severity == crate::diagnostics::Severity::Warning
+                && d.message.contains("declaration uses 'sorry'")
+                && Path::new(&d.file).ends_with(file)
+                && lines.is_none_or(|(a, b)| (a..=b).contains(&d.line))
+        })
+        .count();
+    let first_error = crate::diagnostics::first_error(&diags)
+        .map(|d| format!("{}:{}:{}: error: {}", d.file, d.line, d.col, d.headline()));
+    let ok = ok && !(opts.reject_sorry_warnings && sorry_warnings > 0);
+    let merged = format!("{stdout}\n{stderr}");
+    CandidateVerification {
+        id: id.to_string(),
+        file: file.to_string(),
+        ok,
+        timeout,
+        returncode,
+        target: target.to_string(),
+        elapsed_ms: elapsed.as_millis() as u64,
+        errors,
+        sorry_warnings,
+        first_error,
+        first_error_loc: parse_first_error_loc(&stdout, &stderr),
+        output_tail: tail_chars(merged.trim(), opts.max_output_tail_chars),
+        aborted: false,
+    }
+}
+
+/// The scratch working copy of `repo_root` candidates are built in, with its sources mirrored
+/// from the repo (files and directories gone from the repo are removed). Its own build output is
+/// kept between calls; dependencies are shared through a link to the repo's `.lake/packages`.
+/// Hold `lock_working_copy` while using it.
+pub fn working_copy(repo_root: &Path) -> Result<PathBuf, String> {
+    let dir = repo_root
+        .join(".generated")
+        .join("proofpatch-verify")
+        .join("worktree");
+    crate::matrix::copy_sources(repo_root, &dir)?;
+    prune_stale_sources(repo_root, &dir)?;
+    let (packages, link) = (
+        repo_root.join(".lake").join("packages"),
+        dir.join(".lake").join("packages"),
+    );
+    if packages.is_dir() && std::fs::symlink_metadata(&link).is_err() {
+        std::fs::create_dir_all(dir.join(".lake"))
+            .map_err(|e| format!("mkdir {}: {e}", dir.display()))?;
+        #[cfg(unix)]
+        let linked = std::os::unix::fs::symlink(&packages, &link);
+        #[cfg(windows)]
+        let linked = std::os::windows::fs::symlink_dir(&packages, &link);
+        linked.map_err(|e| format!("link {}: {e}", link.display()))?;
+    }
+    Ok(dir)
+}
+
+/// Remove what `copy_sources` put in `to` that is no longer in `from` (deleted or renamed
+/// modules, lakefiles), so the working copy cannot import or build them.
+fn prune_stale_sources(from: &Path, to: &Path) -> Result<(), String> {
+    let rd = std::fs::read_dir(to).map_err(|e| format!("read {}: {e}", to.display()))?;
+    for ent in rd.flatten() {
+        let name = ent.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || name == "build" || name == "lake-packages" {
+            continue;
+        }
+        let (src, dst) = (from.join(&name), ent.path());
+        match ent.file_type() {
+            Ok(ft) if ft.is_dir() && src.is_dir() => prune_stale_sources(&src, &dst)?,
+            Ok(ft) if ft.is_dir() => std::fs::remove_dir_all(&dst)
+                .map_err(|e| format!("remove {}: {e}", dst.display()))?,
+            Ok(ft) if ft.is_file() && !src.is_file() => {
+                std::fs::remove_file(&dst).map_err(|e| format!("remove {}: {e}", dst.display()))?
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// An exclusive lock on `repo_root`'s working copy, released when the file is dropped: runs in
+/// other processes (CLI, MCP server) wait instead of overwriting each other's candidates.
+pub async fn lock_working_copy(repo_root: &Path) -> Result<std::fs::File, String> {
+    let dir = repo_root.join(".generated").join("proofpatch-verify");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("mkdir {}: {e}", dir.display()))?;
+    let path = dir.join("worktree.lock");
+    let f = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|e| format!("open {}: {e}", path.display()))?;
+    tokio::task::spawn_blocking(move || f.lock().map(|_| f))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("lock {}: {e}", path.display()))
+}
+
+/// Apply each candidate in turn to the working copy, build, record the result, and restore the
+/// original text.
+pub async fn verify_candidates(
+    repo_root: &Path,
+    candidates: &[CandidatePatch],
+    opts: &BuildVerifyOptions,
+) -> Result<Vec<CandidateVerification>, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let modules: Vec<String> = candidates
+        .iter()
+        .flat_map(|c| crate::mathlib_cache::mathlib_imports(&c.new_text))
+        .collect();
+    crate::mathlib_cache::ensure_mathlib_cache(&repo_root, &modules, opts.timeout).await;
+    let graph = if opts.scoped && opts.with_dependents {
+        crate::import_graph::ImportGraph::build(&repo_root).ok()
+    } else {
+        None
+    };
+    let _lock = lock_working_copy(&repo_root).await?;
+    let work = working_copy(&repo_root)?;
+    let mut out = Vec::new();
+    for c in candidates {
+        let abs = work.join(&c.file);
+        let original =
+            std::fs::read_to_string(&abs).map_err(|e| format!("read {}: {e}", abs.display()))?;
+        let targets: Vec<String> = match (opts.scoped, module_name_from_file_rel(&c.file)) {
+            (true, Some(m)) => match &graph {
+                Some(g) if g.modules.contains_key(&m) => g.build_targets(&[m]),
+                _ => vec![m],
+            },
+            _ => Vec::new(),
+        };
+        let target = targets.join(" ");
+
+        let _guard = RestoreGuard {
+            path: abs.clone(),
+            original,
+        };
+        std::fs::write(&abs, c.new_text.as_bytes())
+            .map_err(|e| format!("write {}: {e}", abs.display()))?;
+
+        let lines = c.decl.as_deref().and_then(|d| decl_lines(&c.new_text, d));
+        let t0 = Instant::now();
+        let (build, aborted) = lake_build_targets_until(&work, &targets, opts.timeout, |l| {
+            opts.early_abort && is_fatal_line(l, &c.file, lines)
+        })
+        .await;
+        let mut r = summarize_build(&c.id, &c.file, lines, &target, build, t0.elapsed(), opts);
+        r.aborted = aborted.is_some();
+        let stop = r.ok && opts.stop_on_first_ok;
+        out.push(r);
+        if stop {
+            break;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarize_build_counts_errors_and_rejects_sorry() {
+        let stdout = "✖ [3/4] Building Foo.Bar\nFoo/Bar.lean:3:2: error: unknown identifier 'x'\n";
+        let r = summarize_build(
+            "c0",
+            "Foo/Bar.lean",
+            None,
+            "Foo.Bar",
+            (false, false, Some(1), stdout.to_string(), String::new()),
+            Duration::from_millis(5),
+            &BuildVerifyOptions::default(),
+        );
+        assert!(!r.ok);
+        assert_eq!(r.errors, 1);
+        assert_eq!(r.first_error_loc.as_ref().map(|l| l.line), Some(3));
+
+        // Current lake: the severity comes first.
+        let stdout = "✖ [3/4] Building Foo.Bar\nerror: Foo/Bar.lean:3:2: unknown identifier 'x'\n\
+                      error: Foo/Bar.lean:5:2: unknown identifier 'y'\n";
+        let r = summarize_build(
+            "c0",
+            "Foo/Bar.lean",
+            None,
+            "Foo.Bar",
+            (false, false, Some(1), stdout.to_string(), String::new()),
+            Duration::from_millis(5),
+            &BuildVerifyOptions::default(),
+        );
+        assert_eq!(r.errors, 2);
+        assert_eq!(
+            r.first_error.as_deref(),
+            Some("Foo/Bar.lean:3:2: error: unknown identifier 'x'")
+        );
+
+        let warn = "Foo/Bar.lean:1:8: warning: declaration uses 'sorry'\n";
+        let r = summarize_build(
+            "c1",
+            "Foo/Bar.lean",
+            Some((1, 9)),
+            "Foo.Bar",
+            (true, false, Some(0), warn.to_string(), String::new()),
+            Duration::from_millis(5),
+            &BuildVerifyOptions::default(),
+        );
+        assert!(!r.ok);
+        assert_eq!(r.sorry_warnings, 1);
+
+        // Admitted declarations elsewhere (same file, other files) do not reject the candidate.
+        let others = "warning: Foo/Bar.lean:20:8: declaration uses 'sorry'\n\
+                      warning: Foo/Baz.lean:3:8: declaration uses 'sorry'\n";
+        let r = summarize_build(
+            "c2",
+            "Foo/Bar.lean",
+            Some((1, 9)),
+            "Foo.Bar Foo.Baz",
+            (true, false, Some(0), others.to_string(), String::new()),
+            Duration::from_millis(5),
+            &BuildVerifyOptions::default(),
+        );
+        assert!(r.ok);
+        assert_eq!(r.sorry_warnings, 0);
+    }
+
+    #[tokio::test]
+    async fn reads_non_utf8_output_and_times_out_a_silent_process() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("printf 'a\\377b\\n'; echo done");
+        let ((ok, _, _, stdout, _), _) = run_until(cmd, Duration::from_secs(20), |_| false).await;
+        assert!(ok);
+        assert_eq!(stdout, "a\u{FFFD}b\ndone\n");
+
+        // Output closed but the process still running: the wait is bounded too.
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("exec >/dev/null 2>&1; sleep 30");
+        let t0 = Instant::now();
+        let ((ok, timeout, ..), _) = run_until(cmd, Duration::from_millis(500), |_| false).await;
+        assert!(t0.elapsed() < Duration::from_secs(10));
+        assert!(!ok && timeout);
+    }
+
+    #[test]
+    fn working_copy_leaves_the_checkout_alone() {
+        let td = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(td.path().join("Foo")).unwrap();
+        std::fs::create_dir_all(td.path().join(".lake/packages/mathlib")).unwrap();
+        std::fs::write(
+            td.path().join("Foo/A.lean"),
+            "theorem a : True := trivial\n",
+        )
+        .unwrap();
+        std::fs::write(td.path().join("lakefile.toml"), "name = \"foo\"\n").unwrap();
+
+        let work = working_copy(td.path()).unwrap();
+        assert!(work.starts_with(td.path().join(".generated")));
+        assert!(work.join(".lake/packages/mathlib").is_dir());
+        std::fs::write(work.join("Foo/A.lean"), "theorem a : True := sorry\n").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(td.path().join("Foo/A.lean")).unwrap(),
+            "theorem a : True := trivial\n"
+        );
+        // Refreshed from the repo on the next call; modules removed from it are removed too.
+        std::fs::create_dir_all(work.join("Foo/Old")).unwrap();
+        std::fs::write(work.join("Foo/Old/C.lean"), "theorem c : True := trivial\n").unwrap();
+        std::fs::write(work.join("Foo/B.lean"), "theorem b : True := trivial\n").unwrap();
+        std::fs::create_dir_all(work.join(".lake/build")).unwrap();
+        let work = working_copy(td.path()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(work.join("Foo/A.lean")).unwrap(),
+            "theorem a : True := trivial\n"
+        );
+        assert!(!work.join("Foo/B.lean").exists());
+        assert!(!work.join("Foo/Old").exists());
+        assert!(work.join(".lake/build").is_dir());
+        assert!(work.join("lakefile.toml").is_file());
+    }
+
+    #[tokio::test]
+    async fn the_working_copy_is_locked_while_in_use() {
+        let td = tempfile::tempdir().unwrap();
+        let held = lock_working_copy(td.path()).await.unwrap();
+        let other =
+            std::fs::File::open(td.path().join(".generated/proofpatch-verify/worktree.lock"))
+                .unwrap();
+        assert!(other.try_lock().is_err());
+        drop(held);
+        assert!(other.try_lock().is_ok());
+    }
+
+    #[tokio::test]
+    async fn aborts_at_the_first_error_in_the_declaration() {
+        let err = "error: Foo/Bar.lean:7:2: unknown identifier 'x'";
+        assert!(is_fatal_line(err, "Foo/Bar.lean", Some((5, 9))));
+        assert!(!is_fatal_line(err, "Foo/Bar.lean", Some((1, 4))));
+        assert!(!is_fatal_line(err, "Foo/Baz.lean", None));
+        assert!(!is_fatal_line(
+            "warning: Foo/Bar.lean:7:2: declaration uses 'sorry'",
+            "Foo/Bar.lean",
+            None
+        ));
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(format!(
+            "echo '✔ [1/3] Built Foo.A'; echo \"{err}\"; sleep 30"
+        ));
+        let t0 = Instant::now();
+        let ((ok, timeout, _, stdout, _), aborted) = run_until(cmd, Duration::from_secs(20), |l| {
+            is_fatal_line(l, "Foo/Bar.lean", None)
+        })
+        .await;
+        assert!(t0.elapsed() < Duration::from_secs(10));
+        assert!(!ok && !timeout);
+        assert_eq!(aborted.as_deref(), Some(err));
+        assert!(stdout.starts_with("✔ [1/3] Built Foo.A\n"));
+    }
+}
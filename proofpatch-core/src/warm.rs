@@ -0,0 +1,178 @@
+//! Warm verification: check candidate declarations against a long-lived Lean process.
+//!
+//! `verify_lean_text` compiles the whole file from scratch, so every candidate pays for import
+//! loading and for elaborating everything before the declaration. `WarmVerifier` pays that once:
+//! it starts a `repl::ReplSession`, loads the file's imports (pickled per import set, see
+//! `repl::default_env_cache`), elaborates the text between the imports and the declaration, and
+//! keeps that environment. Each check then elaborates only the (patched) declaration on top of it.
+//!
+//! Results come back as a `VerifyResult` whose stdout holds the REPL's messages rendered the way
+//! `lake env lean` prints them (`file:line:col: severity: message`, lines relative to the original
+//! file), so `diagnostics::parse_diagnostics_from` and `repair::decl_admitted_in_output` work
+//! unchanged. Declarations after the target are not re-checked.
+
+use crate::repl::{CommandResponse, ReplSession};
+use crate::VerifyResult;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub struct WarmVerifier {
+    session: ReplSession,
+    /// Environment with imports and everything before the declaration.
+    env: u64,
+    repo_root: PathBuf,
+    file_rel: String,
+    /// `text[..decl_start]` of the file the verifier was started for.
+    prefix: String,
+    /// 1-based line of the declaration.
+    decl_line: usize,
+    decl_name: String,
+    /// Checks served so far.
+    pub checks: usize,
+}
+
+/// Byte offset just past the last `import` line of the header.
+fn header_end(text: &str) -> usize {
+    let mut off = 0usize;
+    let mut end = 0usize;
+    for line in text.split_inclusive('\n').take(200) {
+        off += line.len();
+        let t = line.trim_start();
+        if t.starts_with("import ") || t.trim_end() == "import" {
+            end = off;
+        }
+    }
+    end
+}
+
+/// Render REPL messages as `lake env lean` output, shifting lines by `line_offset`.
+pub fn render_messages(r: &CommandResponse, file_rel: &str, line_offset: usize) -> String {
+    let mut out = String::new();
+    for m in &r.messages {
+        out.push_str(&format!(
+            "{file_rel}:{}:{}: {}: {}\n",
+            m.pos.line + line_offset,
+            m.pos.column,
+            m.severity,
+            m.data
+        ));
+    }
+    out
+}
+
+impl WarmVerifier {
+    /// Start a REPL for `text` (the current contents of `file_rel`) and elaborate everything up to
+    /// `decl_name`.
+    pub async fn start(
+        repo_root: &Path,
+        file_rel: &str,
+        text: &str,
+        decl_name: &str,
+        timeout: Duration,
+    ) -> Result<Self, String> {
+        let repo_root = crate::find_lean_repo_root(repo_root)?;
+        let (decl_start, _) = crate::patching::decl_byte_range(text, decl_name)?;
+        let imports = crate::import_graph::parse_imports(text);
+        let mut session = ReplSession::start(&repo_root, timeout).await?;
+        let cache = crate::repl::default_env_cache(&repo_root, &imports);
+        let mut env = session.import_env(&imports, Some(&cache)).await?;
+
+        let body_start = header_end(&text[..decl_start]);
+        let between = &text[body_start..decl_start];
+        if !between.trim().is_empty() {
+            let r = session.command(between, Some(env)).await?;
+            if let Some(e) = r.errors().first() {
+                return Err(format!(
+                    "warm: text before {decl_name} does not elaborate: {}",
+                    e.data
+                ));
+            }
+            env = r.env.ok_or("warm: no env returned for the file prefix")?;
+        }
+        Ok(Self {
+            session,
+            env,
+            repo_root,
+            file_rel: file_rel.to_string(),
+            prefix: text[..decl_start].to_string(),
+            decl_line: text[..decl_start].matches('\n').count() + 1,
+            decl_name: decl_name.to_string(),
+            checks: 0,
+        })
+    }
+
+    /// Check the declaration as it appears in `patched` (which must share the original text up to
+    /// the declaration; otherwise `Err`, and the caller should fall back to a full compile).
+    pub async fn check_patched(&mut self, patched: &str) -> Result<VerifyResult, String> {
+        if !patched.starts_with(&self.prefix) {
+            return Err("warm: text before the declaration changed".to_string());
+        }
+        let (s, e) = crate::patching::decl_byte_range(patched, &self.decl_name)?;
+        if s != self.prefix.len() {
+            return Err("warm: declaration moved".to_string());
+        }
+        self.check_decl(&patched[s..e]).await
+    }
+
+    /// Elaborate one declaration's text on top of the warm environment.
+    pub async fn check_decl(&mut self, decl_text: &str) -> Result<VerifyResult, String> {
+        let r = self.elaborate(decl_text).await?;
+        let ok = r.errors().is_empty();
+        Ok(VerifyResult {
+            ok,
+            timeout: false,
+            returncode: Some(if ok { 0 } else { 1 }),
+            stdout: render_messages(&r, &self.file_rel, self.decl_line - 1),
+            stderr: String::new(),
+            cmd: vec!["repl".to_string()],
+            cwd: self.repo_root.display().to_string(),
+            tmp_file: None,
+            aborted: false,
+        })
+    }
+
+    /// The REPL session, e.g. to run tactics on proof states of `elaborate`d declarations.
+    pub fn session(&mut self) -> &mut ReplSession {
+        &mut self.session
+    }
+
+    /// Elaborate one declaration's text on top of the warm environment and return the REPL's
+    /// response (messages, and a proof state per `sorry`).
+    pub async fn elaborate(&mut self, decl_text: &str) -> Result<CommandResponse, String> {
+        self.checks += 1;
+        self.session.command(decl_text, Some(self.env)).await
+    }
+
+    /// 1-based line of the declaration in the original file.
+    pub fn decl_line(&self) -> usize {
+        self.decl_line
+    }
+
+    pub async fn close(&mut self) {
+        self.session.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_repl_messages_as_lean_output() {
+        let text =
+            "import Mathlib\nimport Foo.Bar -- x\n\nopen Nat\n\ntheorem t : True := by\n  sorry\n";
+        assert_eq!(
+            &text[header_end(text)..],
+            "\nopen Nat\n\ntheorem t : True := by\n  sorry\n"
+        );
+        let r: CommandResponse = serde_json::from_value(serde_json::json!({
+            "env": 3,
+            "messages": [{"severity": "warning", "pos": {"line": 1, "column": 8},
+                          "data": "declaration uses 'sorry'"}]
+        }))
+        .unwrap();
+        let out = render_messages(&r, "F.lean", 5);
+        assert_eq!(out, "F.lean:6:8: warning: declaration uses 'sorry'\n");
+        assert!(crate::repair::decl_admitted_in_output(&out, 6));
+    }
+}
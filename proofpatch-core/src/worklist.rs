@@ -0,0 +1,442 @@
+//! A repo's prioritized work list: its placeholders and, optionally, its build failures, with the
+//! cheap signals that tell which to take on first.
+//!
+//! Placeholders come from `scan::scan_repo`, one item per declaration (`placeholders` counts its
+//! tokens). With `build`, every scanned file is compiled and each declaration with errors
+//! (`file_repair::broken_decls`) becomes an item too, absorbing that declaration's placeholders.
+//! Each item gets the difficulty of its declaration (`difficulty::assess`, with the run history)
+//! and a statement-level SMT check (`statement_goal`): the binders as hypotheses and the type as
+//! the goal, so a statement that is LIA-entailed as written is marked cheap without running Lean.
+//!
+//! Items are ranked build failures first (a broken module hides what depends on it), then by
+//! difficulty score, easiest first; the order is otherwise stable (file order).
+
+use crate::diagnostics::{Diagnostic, ErrorClass};
+use crate::difficulty::Difficulty;
+use crate::history::RunRecord;
+use crate::scan::{RepairTarget, ScanOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::Duration;
+
+/// Characters of an item's detail shown in the table.
+const DETAIL_CHARS: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkKind {
+    /// A declaration with build errors.
+    Error,
+    /// A declaration admitted by `sorry`/`admit`/`stop`.
+    Placeholder,
+}
+
+impl WorkKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Placeholder => "placeholder",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkItem {
+    /// 1-based position in the ranked list.
+    pub rank: usize,
+    pub kind: WorkKind,
+    /// `<file>:<line>`, addressing the item for `proofpatch patch`.
+    pub target: String,
+    pub file: String,
+    /// 1-based line of the first error or placeholder.
+    pub line: usize,
+    pub decl_name: Option<String>,
+    pub decl_line: Option<usize>,
+    /// The first error's headline, or the first placeholder's line.
+    pub detail: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_class: Option<ErrorClass>,
+    /// Placeholder tokens in the declaration.
+    pub placeholders: usize,
+    /// Statement-level SMT check (`Some(true)`: LIA-entailed as stated).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smt_entails: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<Difficulty>,
+}
+
+impl WorkItem {
+    /// Difficulty score; items outside any declaration count as hardest.
+    pub fn score(&self) -> f64 {
+        self.difficulty.as_ref().map_or(f64::INFINITY, |d| d.score)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Worklist {
+    pub repo_root: String,
+    pub files_scanned: usize,
+    /// Files compiled (`WorklistOptions::build`).
+    pub files_built: usize,
+    /// Items by kind.
+    pub counts: BTreeMap<String, usize>,
+    pub items: Vec<WorkItem>,
+    /// Files we could not read or compile (path, error).
+    pub errors: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorklistOptions {
+    /// Which files and tokens to scan (`max_targets` and `by_difficulty` are ignored).
+    pub scan: ScanOptions,
+    /// Compile every scanned file and add its declarations with errors.
+    pub build: bool,
+    pub build_timeout: Duration,
+    /// Timeout of each statement-level SMT check; 0 skips them.
+    pub smt_timeout_ms: u64,
+    pub max_items: usize,
+}
+
+impl Default for WorklistOptions {
+    fn default() -> Self {
+        Self {
+            scan: ScanOptions::default(),
+            build: false,
+            build_timeout: Duration::from_secs(180),
+            smt_timeout_ms: 500,
+            max_items: 200,
+        }
+    }
+}
+
+/// A declaration statement as a plain goal: its explicit and implicit binders as hypotheses
+/// (instance binders are dropped) and its type as the target. `None` when it has no type.
+pub fn statement_goal(statement: &str) -> Option<String> {
+    let mut hyps: Vec<String> = Vec::new();
+    let mut depth = 0i32;
+    let mut group = String::new();
+    for (i, c) in statement.char_indices() {
+        match c {
+            '(' | '[' | '{' | '⦃' => {
+                if depth > 0 {
+                    group.push(c);
+                } else {
+                    group.clear();
+                }
+                depth += 1;
+            }
+            ')' | ']' | '}' | '⦄' => {
+                depth -= 1;
+                if depth > 0 {
+                    group.push(c);
+                } else if c != ']' {
+                    if let Some((names, ty)) = group.split_once(':') {
+                        hyps.push(format!("{} : {}", names.trim(), ty.trim()));
+                    }
+                }
+            }
+            ':' if depth == 0 => {
+                let target = statement[i + 1..].split_whitespace().collect::<Vec<_>>();
+                if target.is_empty() {
+                    return None;
+                }
+                hyps.push(format!("⊢ {}", target.join(" ")));
+                return Some(hyps.join("\n"));
+            }
+            _ if depth > 0 => group.push(c),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// SMT entailment of the statement of `decl_name` in `text` (`statement_goal`).
+fn statement_smt(text: &str, decl_name: &str, timeout_ms: u64) -> Option<bool> {
+    if timeout_ms == 0 {
+        return None;
+    }
+    let (s, e) = crate::patching::decl_byte_range(text, decl_name).ok()?;
+    let goal = statement_goal(crate::difficulty::statement(&text[s..e]))?;
+    let pp = crate::lean_lsp::pp_dump_from_plain_goals(&[goal]);
+    crate::smt_lia::entails_from_pp_dump(&pp, timeout_ms, 0)
+        .ok()
+        .flatten()
+}
+
+/// The items of one file: its declarations with errors in `diags` and its placeholder `targets`,
+/// unranked, in source order.
+pub fn file_items(
+    file_rel: &str,
+    text: &str,
+    diags: &[Diagnostic],
+    targets: &[RepairTarget],
+    runs: &HashMap<(String, String), Vec<&RunRecord>>,
+    smt_timeout_ms: u64,
+) -> Vec<WorkItem> {
+    let mut items: Vec<WorkItem> = crate::file_repair::broken_decls(text, diags, false)
+        .into_iter()
+        .map(|b| {
+            let line = b.first_error_line.unwrap_or(b.line);
+            WorkItem {
+                rank: 0,
+                kind: WorkKind::Error,
+                target: format!("{file_rel}:{line}"),
+                file: file_rel.to_string(),
+                line,
+                decl_name: Some(b.name),
+                decl_line: Some(b.line),
+                detail: b.first_error.unwrap_or_default(),
+                error_class: b.first_error_class,
+                placeholders: 0,
+                smt_entails: None,
+                difficulty: None,
+            }
+        })
+        .collect();
+    for t in targets {
+        let same_decl = |i: &WorkItem| t.decl_name.is_some() && i.decl_name == t.decl_name;
+        if let Some(i) = items.iter_mut().find(|i| same_decl(i)) {
+            i.placeholders += 1;
+            continue;
+        }
+        items.push(WorkItem {
+            rank: 0,
+            kind: WorkKind::Placeholder,
+            target: format!("{file_rel}:{}", t.line),
+            file: file_rel.to_string(),
+            line: t.line,
+            decl_name: t.decl_name.clone(),
+            decl_line: t.decl_line,
+            detail: t.line_text.trim().to_string(),
+            error_class: None,
+            placeholders: 1,
+            smt_entails: None,
+            difficulty: None,
+        });
+    }
+    for i in &mut items {
+        let Some(d) = i.decl_name.as_deref() else {
+            continue;
+        };
+        i.smt_entails = statement_smt(text, d, smt_timeout_ms);
+        i.difficulty = crate::difficulty::assess(text, file_rel, d, runs).map(|mut diff| {
+            // The history's own SMT outcome (on the recorded goal) wins over the statement's.
+            if diff.features.smt_entails.is_none() && i.smt_entails.is_some() {
+                diff.features.smt_entails = i.smt_entails;
+                diff.score = crate::difficulty::estimate(&diff.features);
+            }
+            diff
+        });
+    }
+    items.sort_by_key(|i| i.line);
+    items
+}
+
+/// Rank `items` (see the module docs), keep the first `max`, and number them.
+pub fn rank(mut items: Vec<WorkItem>, max: usize) -> Vec<WorkItem> {
+    items.sort_by(|a, b| a.kind.cmp(&b.kind).then(a.score().total_cmp(&b.score())));
+    items.truncate(max);
+    for (i, it) in items.iter_mut().enumerate() {
+        it.rank = i + 1;
+    }
+    items
+}
+
+/// Scan (and with `build`, compile) the repo into a ranked work list.
+pub async fn worklist(repo_root: &Path, opts: &WorklistOptions) -> Result<Worklist, String> {
+    let repo_root = crate::find_lean_repo_root(repo_root)?;
+    let scan = crate::scan::scan_repo(
+        &repo_root,
+        &ScanOptions {
+            max_targets: usize::MAX,
+            by_difficulty: false,
+            ..opts.scan.clone()
+        },
+    )?;
+    let mut targets: BTreeMap<&str, Vec<RepairTarget>> = BTreeMap::new();
+    for t in &scan.targets {
+        targets.entry(&t.file).or_default().push(t.clone());
+    }
+    let mut files: Vec<String> = if opts.build {
+        let mut fs = crate::scan::list_lean_files(&repo_root);
+        let prefixes = &opts.scan.include_prefixes;
+        if !prefixes.is_empty() {
+            fs.retain(|f| prefixes.iter().any(|p| f.starts_with(p)));
+        }
+        fs
+    } else {
+        targets.keys().map(|f| f.to_string()).collect()
+    };
+    if opts.scan.topological {
+        files = crate::import_graph::ImportGraph::build(&repo_root)?.schedule_files(&files);
+    }
+
+    let history = crate::history::load(&repo_root);
+    let runs = crate::difficulty::runs_by_decl(&history);
+    let mut out = Worklist {
+        repo_root: repo_root.display().to_string(),
+        files_scanned: scan.files_scanned,
+        files_built: 0,
+        counts: BTreeMap::new(),
+        items: Vec::new(),
+        errors: scan.errors.clone(),
+    };
+    let mut items = Vec::new();
+    for f in &files {
+        let text = match std::fs::read_to_string(repo_root.join(f)) {
+            Ok(t) => t,
+            Err(e) => {
+                out.errors.push((f.clone(), e.to_string()));
+                continue;
+            }
+        };
+        let diags = if opts.build {
+            match crate::verify_lean_file(&repo_root, f, opts.build_timeout).await {
+                Ok(vr) => {
+                    out.files_built += 1;
+                    crate::diagnostics::parse_diagnostics_from(&vr.stdout, &vr.stderr)
+                }
+                Err(e) => {
+                    out.errors.push((f.clone(), e));
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        let ts = targets.get(f.as_str()).map_or(&[][..], Vec::as_slice);
+        items.extend(file_items(f, &text, &diags, ts, &runs, opts.smt_timeout_ms));
+    }
+    for i in &items {
+        *out.counts.entry(i.kind.as_str().to_string()).or_insert(0) += 1;
+    }
+    out.items = rank(items, opts.max_items);
+    Ok(out)
+}
+
+fn one_line(s: &str, max: usize) -> String {
+    let s = s.split_whitespace().collect::<Vec<_>>().join(" ");
+    match s.char_indices().nth(max) {
+        Some((i, _)) => format!("{}…", &s[..i]),
+        None => s,
+    }
+}
+
+/// The work list as a plain-text table, one row per item in rank order.
+pub fn table(list: &Worklist) -> String {
+    let header = ["#", "kind", "score", "smt", "target", "decl", "detail"].map(String::from);
+    let mut rows: Vec<[String; 7]> = vec![header];
+    for i in &list.items {
+        rows.push([
+            i.rank.to_string(),
+            i.kind.as_str().to_string(),
+            i.difficulty
+                .as_ref()
+                .map_or("-".to_string(), |d| format!("{:.2}", d.score)),
+            match i.smt_entails {
+                Some(true) => "entailed",
+                Some(false) => "refuted",
+                None => "-",
+            }
+            .to_string(),
+            i.target.clone(),
+            i.decl_name.clone().unwrap_or_else(|| "-".to_string()),
+            one_line(&i.detail, DETAIL_CHARS),
+        ]);
+    }
+    let widths: Vec<usize> = (0..7)
+        .map(|c| rows.iter().map(|r| r[c].chars().count()).max().unwrap_or(0))
+        .collect();
+    let mut out = String::new();
+    for r in &rows {
+        let cells: Vec<String> = r
+            .iter()
+            .zip(&widths)
+            .map(|(s, &w)| format!("{s}{}", " ".repeat(w - s.chars().count())))
+            .collect();
+        out.push_str(cells.join("  ").trim_end());
+        out.push('\n');
+    }
+    let counts: Vec<String> = list
+        .counts
+        .iter()
+        .map(|(k, n)| format!("{n} {k}"))
+        .collect();
+    out.push_str(&format!(
+        "\n{} of {} items ({}); {} files scanned, {} built\n",
+        list.items.len(),
+        list.counts.values().sum::<usize>(),
+        if counts.is_empty() {
+            "none".to_string()
+        } else {
+            counts.join(", ")
+        },
+        list.files_scanned,
+        list.files_built
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_ranks_and_renders_items() {
+        assert_eq!(
+            statement_goal("theorem e (n : ℕ) [Fintype α] {h : n < 3} : n + 1 ≤ 3 ").as_deref(),
+            Some("n : ℕ\nh : n < 3\n⊢ n + 1 ≤ 3")
+        );
+        assert_eq!(statement_goal("instance foo "), None);
+
+        let text = "theorem a (n : ℕ) : n = n := by\n  sorry\n\n\
+                    theorem b {f : ℕ → ℝ} (hf : ∀ ε > 0, ∃ N, ∀ n ≥ N, |f n| < ε) :\n    \
+                    Tendsto f atTop (𝓝 0) := by\n  sorry\n\n\
+                    theorem c : 1 = 1 := by\n  exact foo\n  sorry\n";
+        let diags = crate::diagnostics::parse_diagnostics_from(
+            "F.lean:9:8: error: unknown identifier 'foo'\n",
+            "",
+        );
+        let targets = crate::scan::scan_text("F.lean", text, &ScanOptions::default().tokens);
+        let runs = HashMap::new();
+        let items = file_items("F.lean", text, &diags, &targets, &runs, 0);
+        let got: Vec<(WorkKind, &str, usize)> = items
+            .iter()
+            .map(|i| (i.kind, i.decl_name.as_deref().unwrap(), i.placeholders))
+            .collect();
+        assert_eq!(
+            got,
+            [
+                (WorkKind::Placeholder, "a", 1),
+                (WorkKind::Placeholder, "b", 1),
+                (WorkKind::Error, "c", 1)
+            ]
+        );
+        assert_eq!(items[2].target, "F.lean:9");
+        assert_eq!(items[2].error_class, Some(ErrorClass::UnknownIdentifier));
+
+        let ranked = rank(items, 10);
+        let order: Vec<(usize, &str)> = ranked
+            .iter()
+            .map(|i| (i.rank, i.decl_name.as_deref().unwrap()))
+            .collect();
+        assert_eq!(order, [(1, "c"), (2, "a"), (3, "b")]);
+        assert_eq!(rank(ranked.clone(), 1).len(), 1);
+
+        let list = Worklist {
+            repo_root: ".".into(),
+            files_scanned: 1,
+            files_built: 1,
+            counts: BTreeMap::from([("error".into(), 1), ("placeholder".into(), 2)]),
+            items: ranked,
+            errors: Vec::new(),
+        };
+        let t = table(&list);
+        assert!(t.starts_with("#  kind         score  smt  target    decl  detail\n"));
+        assert!(
+            t.contains("\n1  error        0.18   -    F.lean:9  c     unknown identifier 'foo'\n")
+        );
+        assert!(t.ends_with("\n3 of 3 items (1 error, 2 placeholder); 1 files scanned, 1 built\n"));
+    }
+}
@@ -48,3 +48,221 @@ hint_packs = ["demo"]
     let expected = vec!["demo".to_string()];
     assert_eq!(ts.hint_packs.as_deref(), Some(expected.as_slice()));
 }
+
+#[test]
+fn builder_merge_overrides_only_set_fields() {
+    let base_txt = r#"
+[research.defaults]
+max_results = 4
+timeout_ms = 1000
+
+[research.defaults.tree_search]
+goal_first_k = 5
+smt_depth = 2
+
+[research.presets.demo]
+query = "old query"
+"#;
+    let base: config::ProofpatchConfig = toml::from_str(base_txt).expect("toml parse");
+
+    let overlay = config::ProofpatchConfig::builder()
+        .timeout_ms(9000)
+        .tree_search(config::TreeSearchPolicy {
+            smt_depth: Some(3),
+            ..Default::default()
+        })
+        .preset("demo", config::ResearchPreset::new("new query"))
+        .enable_hint_pack("base")
+        .build();
+
+    let cfg = config::ProofpatchConfig::builder()
+        .merge(base)
+        .merge(overlay)
+        .build();
+    let p = cfg.research.resolve_preset("demo").expect("preset");
+    assert_eq!(p.query, "new query");
+    assert_eq!(p.max_results, 4);
+    assert_eq!(p.timeout_ms, 9000);
+    let ts = p.tree_search.expect("tree_search");
+    assert_eq!(ts.goal_first_k, Some(5));
+    assert_eq!(ts.smt_depth, Some(3));
+    assert_eq!(cfg.hints.defaults.enabled_packs, vec!["base".to_string()]);
+}
+
+#[test]
+fn repair_profile_references_a_research_preset() {
+    let txt = r#"
+[research.presets.ramsey]
+query = "Ramsey numbers"
+
+[repair.profiles.lit]
+use_llm = true
+max_verifications = 12
+research = "ramsey"
+research_k = 2
+"#;
+    let cfg: config::ProofpatchConfig = toml::from_str(txt).expect("toml parse");
+    let p = cfg.repair.profile("lit").expect("profile");
+    let mut opts = proofpatch_core::repair::RepairOptions::default();
+    opts.apply_profile(p);
+    assert!(opts.use_llm);
+    assert_eq!(opts.max_verifications, 12);
+    assert_eq!(opts.research_preset.as_deref(), Some("ramsey"));
+    assert_eq!(
+        (opts.research_k, opts.max_rounds),
+        (
+            2,
+            proofpatch_core::repair::RepairOptions::default().max_rounds
+        )
+    );
+    let err = cfg.repair.profile("nope").unwrap_err();
+    assert!(err.contains("available: lit"), "{err}");
+}
+
+#[test]
+fn default_repair_profile_applies_without_a_flag() {
+    let txt = r#"
+[repair]
+default_profile = "quick"
+
+[repair.profiles.quick]
+max_verifications = 4
+
+[repair.profiles.deep]
+max_verifications = 40
+"#;
+    let cfg: config::ProofpatchConfig = toml::from_str(txt).expect("toml parse");
+    let picked = |name: Option<&str>| {
+        cfg.repair
+            .selected(name)
+            .expect("profile")
+            .and_then(|p| p.max_verifications)
+    };
+    assert_eq!(picked(None), Some(4));
+    assert_eq!(picked(Some("deep")), Some(40));
+    assert!(config::RepairConfig::default()
+        .selected(None)
+        .unwrap()
+        .is_none());
+    assert!(cfg.repair.selected(Some("nope")).is_err());
+}
+
+#[test]
+fn embeddings_settings_merge_field_by_field() {
+    let base: config::ProofpatchConfig = toml::from_str(
+        r#"
+[embeddings]
+provider = "openai"
+model = "text-embedding-3-small"
+dimensions = 256
+"#,
+    )
+    .expect("toml parse");
+    let overlay: config::ProofpatchConfig = toml::from_str(
+        r#"
+[embeddings]
+model = "text-embedding-3-large"
+cache = false
+"#,
+    )
+    .expect("toml parse");
+
+    let cfg = config::ProofpatchConfig::builder()
+        .merge(base.clone())
+        .merge(config::ProofpatchConfig::default())
+        .build();
+    assert_eq!(cfg.embeddings, base.embeddings);
+
+    let cfg = config::ProofpatchConfig::builder()
+        .merge(base)
+        .merge(overlay)
+        .build();
+    assert_eq!(cfg.embeddings.provider.as_deref(), Some("openai"));
+    assert_eq!(
+        cfg.embeddings.model.as_deref(),
+        Some("text-embedding-3-large")
+    );
+    assert_eq!(cfg.embeddings.dimensions, Some(256));
+    assert_eq!(cfg.embeddings.cache, Some(false));
+}
+
+#[test]
+fn ranking_weights_merge_field_by_field() {
+    let user: config::ProofpatchConfig = toml::from_str(
+        r#"
+[ranking]
+smt = 5.0
+lint = 0.0
+score_script = "score.rhai"
+"#,
+    )
+    .expect("toml parse");
+    let project: config::ProofpatchConfig = toml::from_str(
+        r#"
+[ranking]
+lint = 0.5
+rerank_model = "models/rerank.onnx"
+"#,
+    )
+    .expect("toml parse");
+
+    let cfg = config::ProofpatchConfig::builder()
+        .merge(user)
+        .merge(project)
+        .build();
+    assert_eq!(cfg.ranking.smt, Some(5.0));
+    assert_eq!(cfg.ranking.lint, Some(0.5));
+    assert_eq!(cfg.ranking.validity, None);
+    assert_eq!(cfg.ranking.score_script.as_deref(), Some("score.rhai"));
+    assert_eq!(
+        cfg.ranking.rerank_model.as_deref(),
+        Some("models/rerank.onnx")
+    );
+}
+
+#[test]
+fn llm_settings_layer_without_a_provider() {
+    let project: config::ProofpatchConfig = toml::from_str(
+        r#"
+[llm]
+provider = "anthropic"
+model = "claude-sonnet"
+providers = ["anthropic", "ollama"]
+
+[llm.failover.ollama]
+model = "qwen2.5-coder:7b"
+
+[llm.transcripts]
+keep_runs = 5
+"#,
+    )
+    .expect("toml parse");
+    let user: config::ProofpatchConfig = toml::from_str(
+        r#"
+[llm]
+failover_cooldown_s = 300
+max_tokens = 2048
+
+[llm.limits.anthropic]
+max_concurrent = 2
+
+[llm.transcripts]
+redact = ["sk-[a-z0-9]+"]
+"#,
+    )
+    .expect("toml parse");
+
+    let cfg = config::ProofpatchConfig::builder()
+        .merge(project)
+        .merge(user)
+        .build();
+    assert_eq!(cfg.llm.provider.as_deref(), Some("anthropic"));
+    assert_eq!(cfg.llm.model.as_deref(), Some("claude-sonnet"));
+    assert_eq!(cfg.llm.providers, ["anthropic", "ollama"]);
+    assert_eq!(cfg.llm.failover_cooldown_s, Some(300));
+    assert_eq!(cfg.llm.max_tokens, Some(2048));
+    assert!(cfg.llm.failover.contains_key("ollama"));
+    assert_eq!(cfg.llm.limits["anthropic"].max_concurrent, Some(2));
+    assert_eq!(cfg.llm.transcripts.keep_runs, Some(5));
+    assert_eq!(cfg.llm.transcripts.redact, ["sk-[a-z0-9]+"]);
+}
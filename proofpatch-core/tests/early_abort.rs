@@ -0,0 +1,57 @@
+//! The repair loop kills Lean at a candidate's first error inside the declaration.
+//!
+//! `lake` is a stand-in script (`LAKE`) that reports an error in the repaired declaration and then
+//! hangs, as Lean would while elaborating the rest of a long file.
+
+use proofpatch_core::repair::{repair_decl_in_text, RepairOptions};
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+#[tokio::test]
+async fn repair_loop_aborts_a_candidate_at_its_first_error() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let td = tempfile::tempdir().unwrap();
+    let root = td.path();
+    std::fs::write(root.join("lean-toolchain"), "leanprover/lean4:v4.15.0\n").unwrap();
+    std::fs::write(root.join("lakefile.toml"), "name = \"foo\"\n").unwrap();
+    std::fs::create_dir_all(root.join(".lake/build/lib/lean")).unwrap();
+    let text = "theorem a : True := trivial\n\n\
+                theorem b (n : Nat) : n + 0 = n := by\n  sorry\n\n\
+                theorem c : True := trivial\n";
+    std::fs::write(root.join("Foo.lean"), text).unwrap();
+
+    let lake = root.join("fake-lake");
+    std::fs::write(
+        &lake,
+        "#!/bin/sh\n\
+         if [ \"$1\" = env ]; then echo \"$3:4:2: error: unsolved goals\"; sleep 30; fi\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&lake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::env::set_var("LAKE", &lake);
+    std::env::set_var("PROOFPATCH_VERIFY_BACKEND", "lake");
+
+    let opts = RepairOptions {
+        max_rounds: 1,
+        max_verifications: 1,
+        verify_timeout: Duration::from_secs(60),
+        goal_dump: false,
+        minimize: false,
+        reuse_subproofs: false,
+        ..Default::default()
+    };
+    let t0 = Instant::now();
+    let out = repair_decl_in_text(root, "Foo.lean", text, "b", &opts)
+        .await
+        .unwrap();
+    assert!(t0.elapsed() < Duration::from_secs(20), "{:?}", t0.elapsed());
+    assert!(!out.ok);
+    let a = &out.attempts[0];
+    assert!(a.aborted && !a.ok);
+    assert!(a
+        .first_error
+        .as_deref()
+        .unwrap_or("")
+        .contains("unsolved goals"));
+}
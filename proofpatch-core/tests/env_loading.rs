@@ -21,6 +21,9 @@ fn env_merge_from_cursor_mcp_json() {
     std::env::remove_var("OPENAI_API_KEY");
     std::env::remove_var("OPENROUTER_API_KEY");
     std::env::remove_var("GROQ_API_KEY");
+    std::env::remove_var("ANTHROPIC_API_KEY");
+    std::env::remove_var("GEMINI_API_KEY");
+    std::env::remove_var("AZURE_OPENAI_API_KEY");
     std::env::set_var("PROOFPATCH_MCP_JSON_PATH", &mcp_path);
     plc::load_cursor_mcp_env_if_present();
     assert_eq!(
@@ -46,6 +49,9 @@ fn dotenv_sibling_search_loads_key_when_repo_env_missing() {
     std::env::remove_var("OPENAI_API_KEY");
     std::env::remove_var("OPENROUTER_API_KEY");
     std::env::remove_var("GROQ_API_KEY");
+    std::env::remove_var("ANTHROPIC_API_KEY");
+    std::env::remove_var("GEMINI_API_KEY");
+    std::env::remove_var("AZURE_OPENAI_API_KEY");
     std::env::set_var("PROOFPATCH_DOTENV_SEARCH_ROOT", td.path());
     std::env::set_var("PROOFPATCH_DOTENV_SEARCH", "1");
 
@@ -70,6 +76,9 @@ fn dotenv_search_root_env_is_loaded_before_siblings() {
     std::env::remove_var("OPENAI_API_KEY");
     std::env::remove_var("OPENROUTER_API_KEY");
     std::env::remove_var("GROQ_API_KEY");
+    std::env::remove_var("ANTHROPIC_API_KEY");
+    std::env::remove_var("GEMINI_API_KEY");
+    std::env::remove_var("AZURE_OPENAI_API_KEY");
     std::env::set_var("PROOFPATCH_DOTENV_SEARCH_ROOT", td.path());
     std::env::set_var("PROOFPATCH_DOTENV_SEARCH", "1");
 
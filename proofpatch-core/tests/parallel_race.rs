@@ -0,0 +1,61 @@
+//! With `[verify] parallel`, a race winner that fails the axioms check does not lose the
+//! candidates cancelled in its favour.
+//!
+//! `lake` is a stand-in script (`LAKE`): every candidate compiles at once but its proof uses
+//! `Lean.ofReduceBool` (rejected by the default safety policy), except `omega`, which compiles
+//! cleanly after a second, so it is always cancelled when it races a faster candidate.
+
+use proofpatch_core::repair::{repair_decl_in_text, RepairOptions};
+use std::time::Duration;
+
+#[cfg(unix)]
+#[tokio::test]
+async fn siblings_of_a_rejected_race_winner_are_still_verified() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let td = tempfile::tempdir().unwrap();
+    let root = td.path();
+    std::fs::write(root.join("lean-toolchain"), "leanprover/lean4:v4.15.0\n").unwrap();
+    std::fs::write(root.join("lakefile.toml"), "name = \"foo\"\n").unwrap();
+    std::fs::write(root.join("proofpatch.toml"), "[verify]\nparallel = 2\n").unwrap();
+    std::fs::create_dir_all(root.join(".lake/build/lib/lean")).unwrap();
+    let text = "theorem b (n : Nat) : n + 0 = n := by\n  sorry\n";
+    std::fs::write(root.join("Foo.lean"), text).unwrap();
+
+    let lake = root.join("fake-lake");
+    std::fs::write(
+        &lake,
+        r#"#!/bin/sh
+[ "$1" = env ] || exit 0
+f="$3"
+line=$(grep -n '#print axioms' "$f" | cut -d: -f1)
+[ -n "$line" ] || line=1
+if grep -q sorry "$f"; then axioms="propext"
+elif grep -q omega "$f"; then sleep 1; axioms="propext"
+else axioms="propext, Lean.ofReduceBool"
+fi
+echo "$f:$line:0: info: 'b' depends on axioms: [$axioms]"
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&lake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::env::set_var("LAKE", &lake);
+    std::env::set_var("PROOFPATCH_VERIFY_BACKEND", "lake");
+
+    let opts = RepairOptions {
+        max_rounds: 1,
+        candidates_per_round: 32,
+        max_verifications: 64,
+        verify_timeout: Duration::from_secs(30),
+        goal_dump: false,
+        minimize: false,
+        reuse_subproofs: false,
+        ..Default::default()
+    };
+    let out = repair_decl_in_text(root, "Foo.lean", text, "b", &opts)
+        .await
+        .unwrap();
+    assert!(out.cancelled_verifications > 0);
+    assert!(out.ok, "{:?}", out.stop_reason);
+    assert!(out.solution.as_deref().unwrap_or("").contains("omega"));
+}
@@ -118,3 +118,85 @@ unrelated
         vec![["refine And.intro ?_ ?_", "· exact ha", "· exact hb"].join("\n")]
     );
 }
+
+#[test]
+fn span_patch_moves_multiline_script_off_inline_by_and_reverts() {
+    use plc::patching::{apply_patch, PatchTarget};
+    let src = "theorem t (n : Nat) : n + 0 = n := by sorry\n\ntheorem u : True := trivial\n";
+    let (out, rec) = apply_patch(
+        src,
+        &PatchTarget::DeclPlaceholder("t".to_string()),
+        "by\n  induction n\n  all_goals simp",
+    )
+    .unwrap();
+    assert_eq!(
+        out,
+        "theorem t (n : Nat) : n + 0 = n := by\n  induction n\n  all_goals simp\n\ntheorem u : True := trivial\n"
+    );
+    assert_eq!(rec.line, 1);
+    assert_eq!(rec.revert(&out).unwrap(), src);
+    assert_eq!(rec.apply(src).unwrap(), out);
+}
+
+#[test]
+fn span_patch_tactic_line_keeps_column_and_decl_proof_replaces_term() {
+    use plc::patching::{apply_patch, revert_all, PatchTarget};
+    let src =
+        "lemma a : 1 = 1 ∧ 2 = 2 := by\n  constructor\n  · sorry\n  · rfl\ndef d : Nat := 1 + 1\n";
+    let (s1, r1) = apply_patch(
+        src,
+        &PatchTarget::DeclPlaceholder("a".to_string()),
+        "have h : 1 = 1 := rfl\nexact h",
+    )
+    .unwrap();
+    assert!(s1.contains("  · have h : 1 = 1 := rfl\n    exact h\n  · rfl\n"));
+
+    let (s2, r2) = apply_patch(&s1, &PatchTarget::DeclProof("d".to_string()), "2").unwrap();
+    assert!(s2.ends_with("def d : Nat := 2\n"));
+    assert_eq!(revert_all(&s2, &[r1, r2]).unwrap(), src);
+}
+
+#[test]
+fn goal_refs_resolve_to_the_right_sorry() {
+    use plc::patching::{resolve_target, GoalRef, PatchTarget};
+    let src = "namespace Foo\n\ntheorem t (n : Nat) : n = n ∧ True := by\n  constructor\n  · sorry -- first\n  · sorry\n\nend Foo\n";
+    let second = src.rfind("sorry").unwrap();
+    let g = GoalRef::at_byte(src, second).unwrap();
+    assert_eq!(g.sorry_index, Some(1));
+    assert_eq!(g.resolve(src).unwrap(), (second, second + 5));
+
+    let pp = serde_json::json!({"kind": "pp_dump", "decl": "Foo.t", "sorry_index": 0});
+    let g = GoalRef::from_pp_dump(&pp).unwrap();
+    assert_eq!(g.resolve(src).unwrap().0, src.find("sorry").unwrap());
+    let pp = serde_json::json!({"kind": "pp_dump", "decl": "Foo.t"});
+    let (s, _) = GoalRef::from_pp_dump(&pp).unwrap().resolve(src).unwrap();
+    assert!(src[s..].starts_with("by\n  constructor"));
+    let bad = PatchTarget::DeclSorry {
+        decl: "t".to_string(),
+        index: 2,
+    };
+    assert!(resolve_target(src, &bad).unwrap_err().contains("2 `sorry`"));
+}
+
+#[test]
+fn tail_patch_keeps_prefix_and_stays_inside_focused_block() {
+    use plc::patching::{apply_patch, tail_goal_text, PatchTarget};
+    let src = "theorem t (a b : Nat) (h : a = b) : b = a ∧ True := by\n  constructor\n  · subst h\n    simp [foo]\n    rfl\n  · trivial\n";
+    let tail = PatchTarget::DeclTail {
+        decl: "t".to_string(),
+        line: 4,
+    };
+    let (out, rec) = apply_patch(src, &tail, "by\n  rfl").unwrap();
+    assert_eq!(
+        out,
+        "theorem t (a b : Nat) (h : a = b) : b = a ∧ True := by\n  constructor\n  · subst h\n    rfl\n  · trivial\n"
+    );
+    assert_eq!(rec.line, 4);
+    let goal = tail_goal_text(src, "t", 4).unwrap();
+    assert!(goal.contains("  · subst h\n    sorry\n  · trivial\n"));
+    let header = PatchTarget::DeclTail {
+        decl: "t".to_string(),
+        line: 1,
+    };
+    assert!(apply_patch(src, &header, "rfl").is_err());
+}